@@ -0,0 +1,238 @@
+// Criterion benchmarks of the paths every reading takes, run with
+// `cargo bench`, or `cargo bench -- <filter>` for the ones whose name matches
+// the filter. They need no hardware: scales read a `FakeVoltageSource` or a
+// `SimulatedScale`.
+//
+// Baseline, criterion's mean time per iteration from
+// `cargo bench --features binary-proto` on a shared x86-64 Linux container, so
+// only good for comparing one row with another. Each median includes copying
+// the weights into the buffer it sorts. The load cell medians took 2_959 and
+// 12_870 ns on the same container while each made a buffer per load cell and
+// sorted it in full. A median weight from a `FakeVoltageSource` is almost all
+// reads, so taking it with a `MedianSampler` is within noise of the simple way
+// there; what it saves is the allocation. The read_latency rows sleep 50 µs,
+// which the container stretches to about 100, in every read of a load cell;
+// with change events cached a weight reads none of them, so only the lock on
+// the snapshot is left. The postcard rows need the `binary-proto` feature.
+// WeightBatch holds 100 readings.
+//
+// | Benchmark                                | ns/iter |
+// |------------------------------------------|--------:|
+// | get_weight/fake_voltage_source           |     417 |
+// | get_weight/read_latency/direct           | 419_730 |
+// | get_weight/read_latency/change_events    |      81 |
+// | load_cell_medians/fake_voltage_source/5  |   2_443 |
+// | load_cell_medians/fake_voltage_source/21 |  10_683 |
+// | median_weight/throwaway/15               |   8_555 |
+// | median_weight/sampler/15                 |   8_467 |
+// | median_weight/throwaway/101              |  55_667 |
+// | median_weight/sampler/101                |  59_796 |
+// | get_weight/simulated_scale               |      66 |
+// | median/sort/5                            |      13 |
+// | median/select_nth/5                      |      12 |
+// | median/sort/21                           |     137 |
+// | median/select_nth/21                     |     105 |
+// | median/sort/101                          |     968 |
+// | median/select_nth/101                    |     286 |
+// | median/sort/1001                         |  12_374 |
+// | median/select_nth/1001                   |   1_823 |
+// | json/encode/GetMedianWeight              |      45 |
+// | json/decode/GetMedianWeight              |      71 |
+// | json/encode/WeightBatch                  |  13_167 |
+// | json/decode/WeightBatch                  |  22_075 |
+// | postcard/encode/GetMedianWeight          |      45 |
+// | postcard/decode/GetMedianWeight          |      16 |
+// | postcard/encode/WeightBatch              |   2_998 |
+// | postcard/decode/WeightBatch              |   1_043 |
+
+use std::hint::black_box;
+use std::time::{Duration, SystemTime};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use libra::testing::{SimulatedScale, SimulationConfig};
+use libra::{median, Grams, MedianGrams, Scale, ScaleCmd, ScaleResponse, StampedWeight};
+
+/// Times `op` under `name`.
+fn bench<T>(c: &mut Criterion, name: &str, mut op: impl FnMut() -> T) {
+    c.bench_function(name, |b| b.iter(|| black_box(op())));
+}
+
+/// Weights in no particular order, the same on every run.
+fn weights(n: usize) -> Vec<Grams> {
+    let mut state = 0x5eed_u64;
+    (0..n)
+        .map(|_| {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            Grams((z >> 11) as f64 / (1u64 << 53) as f64 * 1000.)
+        })
+        .collect()
+}
+
+/// The upper median, as [`median`] finds it, sorting every weight.
+fn sort_median(weights: &mut [Grams]) -> MedianGrams {
+    weights.sort_by(|a, b| a.0.total_cmp(&b.0));
+    MedianGrams(
+        weights
+            .get(weights.len() / 2)
+            .map_or(f64::NAN, |weight| weight.0),
+    )
+}
+
+fn bench_get_weight(c: &mut Criterion) {
+    #[cfg(feature = "hardware")]
+    {
+        use libra::calibration::Calibration;
+        use libra::scale::{ConnectedScale, NUMBER_OF_INPUTS};
+        use libra::testing::FakeVoltageSource;
+
+        let sources = FakeVoltageSource::bridge(716_000);
+        for (source, ratio) in sources.iter().zip([1e-4, 2e-4, 3e-4, 4e-4]) {
+            source.set_ratio(ratio);
+        }
+        let calibration = Calibration {
+            offset: -10.,
+            coefficients: [1e6; NUMBER_OF_INPUTS],
+        };
+        let scale = ConnectedScale::from_sources(716_000, calibration, sources);
+        bench(c, "get_weight/fake_voltage_source", || scale.get_weight());
+        for n in [5, 21] {
+            bench(
+                c,
+                &format!("load_cell_medians/fake_voltage_source/{n}"),
+                || scale.get_load_cell_medians(n, Duration::ZERO),
+            );
+        }
+
+        // Each read of a load cell slept for as long as one takes over USB.
+        let latency = Duration::from_micros(50);
+        let sources = FakeVoltageSource::bridge(716_000);
+        for source in &sources {
+            source.set_ratio(1e-4);
+            source.set_latency(latency);
+        }
+        let mut scale = ConnectedScale::from_sources(716_000, calibration, sources.clone());
+        bench(c, "get_weight/read_latency/direct", || scale.get_weight());
+        scale
+            .cache_change_events(Duration::from_secs(3600))
+            .unwrap();
+        for source in &sources {
+            source.set_ratio(1e-4);
+        }
+        bench(c, "get_weight/read_latency/change_events", || {
+            scale.get_weight()
+        });
+    }
+    let scale = SimulatedScale::new(SimulationConfig {
+        load: Grams(500.),
+        noise: 0.5,
+        ..SimulationConfig::default()
+    });
+    bench(c, "get_weight/simulated_scale", || {
+        Scale::get_weight(&scale)
+    });
+}
+
+/// A median weight taken the simple way, with a buffer made for it, and
+/// with a [`MedianSampler`](libra::sampler::MedianSampler) reusing one.
+#[cfg(feature = "hardware")]
+fn bench_median_weight(c: &mut Criterion) {
+    use libra::calibration::Calibration;
+    use libra::sampler::MedianSampler;
+    use libra::scale::{ConnectedScale, NUMBER_OF_INPUTS};
+    use libra::testing::FakeVoltageSource;
+
+    let calibration = Calibration {
+        offset: -10.,
+        coefficients: [1e6; NUMBER_OF_INPUTS],
+    };
+    let scale =
+        ConnectedScale::from_sources(716_000, calibration, FakeVoltageSource::bridge(716_000));
+    for n in [15, 101] {
+        bench(c, &format!("median_weight/throwaway/{n}"), || {
+            scale.get_median_weight(n, Duration::ZERO)
+        });
+        let mut sampler = MedianSampler::new(n, Duration::ZERO).unwrap();
+        bench(c, &format!("median_weight/sampler/{n}"), || {
+            sampler.measure(&scale)
+        });
+    }
+}
+
+fn bench_median(c: &mut Criterion) {
+    for n in [5, 21, 101, 1001] {
+        let unsorted = weights(n);
+        let mut buffer = unsorted.clone();
+        bench(c, &format!("median/sort/{n}"), || {
+            buffer.copy_from_slice(&unsorted);
+            sort_median(&mut buffer)
+        });
+        bench(c, &format!("median/select_nth/{n}"), || {
+            buffer.copy_from_slice(&unsorted);
+            median(&mut buffer)
+        });
+        buffer.copy_from_slice(&unsorted);
+        let sorted = sort_median(&mut buffer);
+        buffer.copy_from_slice(&unsorted);
+        assert_eq!(median(&mut buffer), sorted);
+    }
+}
+
+fn messages() -> (ScaleCmd, ScaleResponse) {
+    let cmd = ScaleCmd::GetMedianWeight { samples: 10 };
+    let batch = weights(100)
+        .into_iter()
+        .zip(0..)
+        .map(|(weight, sequence)| StampedWeight {
+            weight,
+            sequence,
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(sequence * 100),
+        })
+        .collect();
+    (cmd, ScaleResponse::WeightBatch(batch))
+}
+
+fn bench_json(c: &mut Criterion) {
+    let (cmd, response) = messages();
+    let cmd_json = serde_json::to_vec(&cmd).unwrap();
+    let response_json = serde_json::to_vec(&response).unwrap();
+    bench(c, "json/encode/GetMedianWeight", || {
+        serde_json::to_vec(&cmd).unwrap()
+    });
+    bench(c, "json/decode/GetMedianWeight", || {
+        serde_json::from_slice::<ScaleCmd>(&cmd_json).unwrap()
+    });
+    bench(c, "json/encode/WeightBatch", || {
+        serde_json::to_vec(&response).unwrap()
+    });
+    bench(c, "json/decode/WeightBatch", || {
+        serde_json::from_slice::<ScaleResponse>(&response_json).unwrap()
+    });
+}
+
+#[cfg(feature = "binary-proto")]
+fn bench_postcard(c: &mut Criterion) {
+    let (cmd, response) = messages();
+    let cmd_bytes = cmd.to_postcard();
+    let response_bytes = response.to_postcard();
+    bench(c, "postcard/encode/GetMedianWeight", || cmd.to_postcard());
+    bench(c, "postcard/decode/GetMedianWeight", || {
+        ScaleCmd::from_postcard(&cmd_bytes).unwrap()
+    });
+    bench(c, "postcard/encode/WeightBatch", || response.to_postcard());
+    bench(c, "postcard/decode/WeightBatch", || {
+        ScaleResponse::from_postcard(&response_bytes).unwrap()
+    });
+}
+
+#[cfg(feature = "hardware")]
+criterion_group!(scales, bench_get_weight, bench_median_weight);
+#[cfg(not(feature = "hardware"))]
+criterion_group!(scales, bench_get_weight);
+#[cfg(feature = "binary-proto")]
+criterion_group!(in_memory, bench_median, bench_json, bench_postcard);
+#[cfg(not(feature = "binary-proto"))]
+criterion_group!(in_memory, bench_median, bench_json);
+criterion_main!(scales, in_memory);
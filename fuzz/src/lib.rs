@@ -0,0 +1,55 @@
+// What each fuzz target checks, shared with the smoke run in
+// tests/fuzz_smoke.rs. Every check takes arbitrary bytes and only panics if
+// libra does: bad input must come back as an error.
+
+use libra::serial::{FrameDecoder, MAX_COMMAND_LEN};
+use libra::{Request, ScaleCmd, ScaleResponse};
+
+/// Feeds `data` to a command decoder configured as `serve_frames` configures
+/// it and to a response decoder with the full payload limit, split in two
+/// where the first byte says, and takes every frame they find.
+pub fn frame_decoder(data: &[u8]) {
+    let split = data
+        .first()
+        .map_or(0, |&byte| usize::from(byte) % (data.len() + 1));
+    let (first, second) = data.split_at(split);
+    let mut commands = FrameDecoder::<ScaleCmd>::with_max_payload(MAX_COMMAND_LEN);
+    let mut responses = FrameDecoder::<ScaleResponse>::new();
+    for piece in [first, second] {
+        commands.extend(piece);
+        // Every frame found, good or bad, consumes at least its start byte,
+        // so these end.
+        while commands.next_frame().is_some() {}
+        responses.extend(piece);
+        while responses.next_frame().is_some() {}
+    }
+}
+
+/// Decodes `data` as a JSON command, alone and in a request, as the
+/// JSON-lines server reads them.
+pub fn json_cmd(data: &[u8]) {
+    let _ = serde_json::from_slice::<ScaleCmd>(data);
+    let _ = serde_json::from_slice::<Request>(data);
+}
+
+/// Decodes `data` as a postcard command and as a postcard response.
+#[cfg(feature = "binary-proto")]
+pub fn postcard(data: &[u8]) {
+    let _ = ScaleCmd::from_postcard(data);
+    let _ = ScaleResponse::from_postcard(data);
+}
+
+/// Parses `data` as a mass given on the command line, and checks that what
+/// is accepted is a usable weight.
+#[cfg(feature = "cli")]
+pub fn parse_mass(data: &[u8]) {
+    let Ok(mass) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(grams) = libra::cli::parse_mass(mass) {
+        assert!(
+            grams.0 > 0. && grams.0.is_finite(),
+            "{mass:?} parsed to {grams:?}"
+        );
+    }
+}
@@ -0,0 +1,597 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::{oneshot, watch};
+use tokio::task::{self, JoinHandle};
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+
+use crate::cancel::CancelFlag;
+use crate::command::{execute, is_read, read};
+use crate::latest::LatestWeight;
+#[cfg(feature = "metrics")]
+use crate::metrics::ScaleMetrics;
+use crate::overflow::{OverflowPolicy, OverflowStats};
+use crate::queue::{
+    self, Lane, PriorityLane, PushError, Pushed, QueueReceiver, QueueSender, MAX_PRIORITY_STREAK,
+};
+use crate::watchdog::StaleChannel;
+use crate::{Scale, ScaleCmd, ScaleErrorInfo, ScaleErrorKind, ScaleResponse, StampedWeight};
+
+/// Commands that can be queued before the overflow policy applies.
+const COMMAND_QUEUE_DEPTH: usize = 32;
+
+/// What happens to the command being executed when shutdown is requested.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ShutdownPolicy {
+    /// Let it run to completion and deliver its response.
+    #[default]
+    FinishInFlight,
+    /// Cancel it between samples and answer it with
+    /// `ScaleResponse::ShuttingDown`.
+    CancelInFlight,
+}
+
+/// Options for [`spawn_scale_actor_with_config`].
+#[derive(Clone, Debug)]
+pub struct ActorConfig {
+    /// Commands that can be queued in each lane, that of
+    /// `ScaleHandle::send` and that of `ScaleHandle::send_priority`, before
+    /// `overflow_policy` applies.
+    pub queue_depth: usize,
+    /// What `ScaleHandle::send` does when the queue is full. Refused and
+    /// evicted commands are answered with `ScaleResponse::Error`. Defaults
+    /// to waiting for room indefinitely.
+    pub overflow_policy: OverflowPolicy,
+    /// When set, the actor reads the weight on its own at this interval and
+    /// publishes it to [`ScaleHandle::watch_weight`] receivers.
+    pub sample_interval: Option<Duration>,
+    pub shutdown_policy: ShutdownPolicy,
+    /// Where the actor reports its periodic samples, command results and
+    /// queue depth.
+    #[cfg(feature = "metrics")]
+    pub metrics: Option<ScaleMetrics>,
+}
+
+impl Default for ActorConfig {
+    fn default() -> Self {
+        Self {
+            queue_depth: COMMAND_QUEUE_DEPTH,
+            overflow_policy: OverflowPolicy::Block {
+                timeout: Duration::MAX,
+            },
+            sample_interval: None,
+            shutdown_policy: ShutdownPolicy::default(),
+            #[cfg(feature = "metrics")]
+            metrics: None,
+        }
+    }
+}
+
+struct Request {
+    cmd: ScaleCmd,
+    reply: oneshot::Sender<ScaleResponse>,
+    deadline: Option<Instant>,
+    lane: Lane,
+    /// The id of the `crate::Request` the command came in, for tracing.
+    correlation_id: Option<u64>,
+}
+
+impl Request {
+    fn is_expired(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+/// Cloneable sender side of a scale actor.
+#[derive(Clone)]
+pub struct ScaleHandle {
+    tx: QueueSender<Request>,
+    latest: watch::Receiver<Option<StampedWeight>>,
+    lockfree: LatestWeight,
+    stale: watch::Receiver<Option<StaleChannel>>,
+    data_interval: watch::Receiver<Option<Duration>>,
+    shutdown: CancellationToken,
+    stopped: watch::Receiver<bool>,
+    started: Instant,
+}
+
+impl ScaleHandle {
+    /// Queues `cmd` for the actor and waits for its reply.
+    ///
+    /// `ScaleCmd::Shutdown` does not queue: it is handled as
+    /// [`shutdown`](Self::shutdown) and answered with
+    /// `ScaleResponse::ShutdownAck` once the actor has stopped. Once the actor
+    /// has stopped, every other command is answered with
+    /// `ScaleResponse::Error`.
+    ///
+    /// A read queued directly behind an identical one is not run again: both
+    /// callers get the result of the single execution.
+    pub async fn send(&self, cmd: ScaleCmd) -> ScaleResponse {
+        self.send_request(Lane::Normal, cmd, None, None).await
+    }
+
+    /// Like [`send`](Self::send), for a command that came in a
+    /// [`Request`](crate::Request) with id `correlation_id`. The id is only
+    /// used to tag the command's `tracing` span.
+    pub async fn send_correlated(&self, cmd: ScaleCmd, correlation_id: u64) -> ScaleResponse {
+        self.send_request(Lane::Normal, cmd, None, Some(correlation_id))
+            .await
+    }
+
+    /// Like [`send`](Self::send), but `cmd` goes ahead of every normal
+    /// command still queued. A long median or batch already running pauses
+    /// between samples to let a read through, then carries on; a command that
+    /// changes the scale, such as `ScaleCmd::Tare`, waits for it to finish.
+    ///
+    /// Normal commands are never starved: after a few priority commands in a
+    /// row, one normal command runs.
+    pub async fn send_priority(&self, cmd: ScaleCmd) -> ScaleResponse {
+        self.send_request(Lane::Priority, cmd, None, None).await
+    }
+
+    /// Like [`send`](Self::send), but if the actor has not started on `cmd`
+    /// by `deadline` it is skipped and answered with
+    /// `ScaleResponse::Expired`. Useful for requests that are worthless once
+    /// stale, such as a display refresh.
+    pub async fn send_with_deadline(&self, cmd: ScaleCmd, deadline: Instant) -> ScaleResponse {
+        self.send_request(Lane::Normal, cmd, Some(deadline), None)
+            .await
+    }
+
+    async fn send_request(
+        &self,
+        lane: Lane,
+        cmd: ScaleCmd,
+        deadline: Option<Instant>,
+        correlation_id: Option<u64>,
+    ) -> ScaleResponse {
+        if let ScaleCmd::Shutdown = cmd {
+            self.shutdown().await;
+            return ScaleResponse::ShutdownAck;
+        }
+        let (reply, response) = oneshot::channel();
+        let request = Request {
+            cmd,
+            reply,
+            deadline,
+            lane,
+            correlation_id,
+        };
+        match self.tx.push(lane, request).await {
+            Ok(Pushed::Queued) => {}
+            Ok(Pushed::Evicted(oldest)) => {
+                self.overload("evicted");
+                let _ = oldest.reply.send(ScaleResponse::Error(ScaleErrorInfo::new(
+                    ScaleErrorKind::QueueFull,
+                    "Dropped from a full command queue",
+                )));
+            }
+            Err(PushError::Full(_)) => {
+                self.overload("refused");
+                return ScaleResponse::Error(ScaleErrorInfo::new(
+                    ScaleErrorKind::QueueFull,
+                    "Scale actor command queue is full",
+                ));
+            }
+            Err(PushError::Closed(_)) => {
+                return ScaleResponse::Error(ScaleErrorInfo::new(
+                    ScaleErrorKind::Stopped,
+                    "Scale actor has stopped",
+                ));
+            }
+        }
+        response.await.unwrap_or_else(|_| {
+            ScaleResponse::Error(ScaleErrorInfo::new(
+                ScaleErrorKind::Stopped,
+                "Scale actor dropped the command",
+            ))
+        })
+    }
+
+    /// Reports a full queue to `tracing`, if the feature is enabled.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn overload(&self, outcome: &'static str) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            name: "overload",
+            target: "libra",
+            outcome,
+            queue_depth = self.tx.stats().queued
+        );
+    }
+
+    /// Asks the actor to shut down and resolves once it has.
+    ///
+    /// The actor stops taking commands, deals with the in-flight command
+    /// according to its [`ShutdownPolicy`], answers everything still queued
+    /// with `ScaleResponse::ShuttingDown`, and closes the scale. Calling this
+    /// again, or after the actor has stopped, just waits for (or returns)
+    /// the same completion. Wrap it in `tokio::time::timeout` to bound the
+    /// wait.
+    pub async fn shutdown(&self) {
+        self.shutdown.cancel();
+        self.stopped().await;
+    }
+
+    /// Resolves once the actor has stopped, for whatever reason.
+    pub async fn stopped(&self) {
+        let mut stopped = self.stopped.clone();
+        // An error means the actor is gone, which is just as stopped.
+        let _ = stopped.wait_for(|stopped| *stopped).await;
+    }
+
+    /// The most recent weight from the actor's periodic sampling.
+    ///
+    /// Holds `None` until the first sample is taken, and never changes if the
+    /// actor was started without a `sample_interval`. Any number of receivers
+    /// can watch without sending commands to the actor.
+    pub fn watch_weight(&self) -> watch::Receiver<Option<StampedWeight>> {
+        self.latest.clone()
+    }
+
+    /// The same weights as [`watch_weight`](Self::watch_weight), in a cell
+    /// read without taking a lock, for readers on plain threads that poll
+    /// faster than they could wait on the receiver.
+    pub fn latest_lockfree(&self) -> LatestWeight {
+        self.lockfree.clone()
+    }
+
+    /// Warnings from the scale's [`Watchdog`](crate::watchdog::Watchdog),
+    /// checked after each periodic sample.
+    ///
+    /// Changes to `Some` when a load cell is caught frozen, or when a
+    /// different one is, and back to `None` once none are. The age is as of
+    /// the sample that tripped the watchdog. Never changes without a
+    /// `sample_interval`, or for a scale without a watchdog.
+    pub fn watch_watchdog(&self) -> watch::Receiver<Option<StaleChannel>> {
+        self.stale.clone()
+    }
+
+    /// How often the scale takes a reading, as it reported when the actor
+    /// started and after each `ScaleCmd::SetDataInterval`. `None` for a
+    /// scale without the setting, and until the actor has asked.
+    pub fn data_interval(&self) -> Option<Duration> {
+        *self.data_interval.borrow()
+    }
+
+    /// Commands waiting in the queue, and how many have been refused or
+    /// evicted by the overflow policy.
+    pub fn stats(&self) -> OverflowStats {
+        self.tx.stats()
+    }
+
+    /// How long ago the actor was spawned.
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+}
+
+/// Moves `scale` onto its own task and returns a handle for sending it
+/// `ScaleCmd`s.
+///
+/// Commands run one at a time, in the order they were received, on tokio's
+/// blocking pool so phidget calls never stall a runtime worker. A command
+/// that panics is answered with `ScaleResponse::InternalError` and the actor
+/// carries on with the same scale.
+///
+/// The actor never closes and reopens the scale itself, however often it
+/// panics or reports `ScaleError::is_disconnection`: a [`Scale`] cannot be
+/// reopened once closed, and an open phidget channel reattaches by itself
+/// when its bridge comes back, which closing it would stop. Replacing a
+/// scale that stays broken is left to its owner, as with
+/// [`ScaleManager::remove`](crate::manager::ScaleManager::remove) and `add`.
+///
+/// The task resolves to the (closed) scale once the actor shuts down, or to
+/// `None` if the runtime shut down first. See [`ScaleHandle::shutdown`] for
+/// the shutdown sequence; dropping every handle shuts the actor down the
+/// same way.
+pub fn spawn_scale_actor<S>(scale: S) -> (ScaleHandle, JoinHandle<Option<S>>)
+where
+    S: Scale + Send + 'static,
+{
+    spawn_scale_actor_with_config(scale, ActorConfig::default())
+}
+
+/// Like [`spawn_scale_actor`], with the options in `config`.
+///
+/// With periodic sampling enabled, queued commands always run before the next
+/// periodic read, so a busy command queue delays the published weight rather
+/// than the other way around.
+pub fn spawn_scale_actor_with_config<S>(
+    scale: S,
+    config: ActorConfig,
+) -> (ScaleHandle, JoinHandle<Option<S>>)
+where
+    S: Scale + Send + 'static,
+{
+    let (tx, rx) = queue::bounded(config.queue_depth, config.overflow_policy);
+    let (publish, latest) = watch::channel(None);
+    let lockfree = LatestWeight::new();
+    let (warn, stale) = watch::channel(None);
+    let (interval, data_interval) = watch::channel(None);
+    let (done, stopped) = watch::channel(false);
+    let shutdown = CancellationToken::new();
+    let actor = Actor {
+        rx,
+        publish,
+        lockfree: lockfree.clone(),
+        warn,
+        interval,
+        shutdown: shutdown.clone(),
+        observer: Observer::new(&config),
+        config,
+    };
+    let task = tokio::spawn(async move {
+        let scale = actor.run(scale).await;
+        done.send_replace(true);
+        scale
+    });
+    let handle = ScaleHandle {
+        tx,
+        latest,
+        lockfree,
+        stale,
+        data_interval,
+        shutdown,
+        stopped,
+        started: Instant::now(),
+    };
+    (handle, task)
+}
+
+struct Actor {
+    rx: QueueReceiver<Request>,
+    publish: watch::Sender<Option<StampedWeight>>,
+    lockfree: LatestWeight,
+    warn: watch::Sender<Option<StaleChannel>>,
+    interval: watch::Sender<Option<Duration>>,
+    shutdown: CancellationToken,
+    observer: Observer,
+    config: ActorConfig,
+}
+
+impl Actor {
+    async fn run<S>(mut self, mut scale: S) -> Option<S>
+    where
+        S: Scale + Send + 'static,
+    {
+        let mut ticker = self.config.sample_interval.map(|interval| {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            ticker
+        });
+        let mut sequence = 0;
+        let (returned, interval) = task::spawn_blocking(move || {
+            let interval = contain(|| scale.data_interval()).ok().and_then(Result::ok);
+            (scale, interval)
+        })
+        .await
+        .ok()?;
+        scale = returned;
+        self.interval.send_replace(interval);
+
+        loop {
+            let request = tokio::select! {
+                biased;
+                _ = self.shutdown.cancelled() => break,
+                request = self.rx.recv() => match request {
+                    Some(request) => request,
+                    None => break,
+                },
+                _ = next_tick(&mut ticker) => {
+                    let observer = self.observer.clone();
+                    let (returned, response, stale) = task::spawn_blocking(move || {
+                        let response = observer.run(|| {
+                            read(&scale, ScaleCmd::GetWeight, &CancelFlag::new(), &mut |_| {})
+                        });
+                        let stale = contain(|| scale.stale_channel()).ok().flatten();
+                        (scale, response, stale)
+                    })
+                    .await
+                    .ok()?;
+                    scale = returned;
+                    self.warn.send_if_modified(|current| {
+                        let changed = current.map(|c| c.channel) != stale.map(|s| s.channel);
+                        if changed {
+                            *current = stale;
+                            #[cfg(feature = "tracing")]
+                            if let Some(StaleChannel { channel, age }) = stale {
+                                tracing::warn!(
+                                    name: "watchdog_tripped",
+                                    target: "libra",
+                                    channel,
+                                    age_ms = age.as_millis() as u64
+                                );
+                            }
+                        }
+                        changed
+                    });
+                    if let ScaleResponse::Weight(weight) = response {
+                        let stamped = StampedWeight {
+                            weight,
+                            sequence,
+                            timestamp: SystemTime::now(),
+                        };
+                        self.lockfree.publish(stamped);
+                        self.publish.send_replace(Some(stamped));
+                        sequence += 1;
+                    }
+                    continue;
+                }
+            };
+
+            self.observer.queue_depth(self.rx.len());
+            if request.is_expired() {
+                let _ = request.reply.send(ScaleResponse::Expired);
+                continue;
+            }
+            let Request {
+                cmd,
+                reply,
+                lane,
+                correlation_id,
+                ..
+            } = request;
+            #[cfg(feature = "tracing")]
+            let span = tracing::debug_span!(
+                target: "libra",
+                "command",
+                command = cmd.name(),
+                correlation_id,
+                lane = lane.name()
+            );
+            #[cfg(not(feature = "tracing"))]
+            let _ = correlation_id;
+            let mut waiters = vec![reply];
+            if is_read(&cmd) {
+                while let Some(duplicate) = self.rx.try_recv_if(|next| next.cmd == cmd) {
+                    if duplicate.is_expired() {
+                        let _ = duplicate.reply.send(ScaleResponse::Expired);
+                    } else {
+                        waiters.push(duplicate.reply);
+                    }
+                }
+            }
+            let cancel = CancelFlag::new();
+            let mut in_flight = task::spawn_blocking({
+                let cancel = cancel.clone();
+                let priority = self.rx.priority_lane();
+                let observer = self.observer.clone();
+                move || {
+                    #[cfg(feature = "tracing")]
+                    let _span = span.entered();
+                    // Nothing jumps ahead of a priority command.
+                    let mut between_samples = |scale: &S| {
+                        if lane == Lane::Normal {
+                            serve_priority(scale, &priority, &observer);
+                        }
+                    };
+                    let response =
+                        observer.run(|| execute(&mut scale, cmd, &cancel, &mut between_samples));
+                    (scale, response)
+                }
+            });
+            let result = match self.config.shutdown_policy {
+                ShutdownPolicy::FinishInFlight => in_flight.await,
+                ShutdownPolicy::CancelInFlight => tokio::select! {
+                    result = &mut in_flight => result,
+                    _ = self.shutdown.cancelled() => {
+                        cancel.cancel();
+                        in_flight.await
+                    }
+                },
+            };
+            let (returned, response) = result.ok()?;
+            scale = returned;
+            let response = if cancel.is_cancelled() {
+                ScaleResponse::ShuttingDown
+            } else {
+                response
+            };
+            if let ScaleResponse::DataIntervalSet { interval_ms } = response {
+                self.interval
+                    .send_replace(Some(Duration::from_millis(interval_ms)));
+            }
+            for reply in waiters {
+                let _ = reply.send(response.clone());
+            }
+            self.observer.queue_depth(self.rx.len());
+        }
+
+        self.rx.close();
+        while let Some(pending) = self.rx.recv().await {
+            let _ = pending.reply.send(ScaleResponse::ShuttingDown);
+        }
+        task::spawn_blocking(move || {
+            let _ = contain(|| scale.close());
+            scale
+        })
+        .await
+        .ok()
+    }
+}
+
+/// Runs `f`, turning a panic into its message so one bad call cannot take
+/// the actor down with it.
+fn contain<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|panic| panic_message(&*panic))
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    let detail = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("no message");
+    format!("Scale panicked: {detail}")
+}
+
+/// Waits for the next periodic sample, or forever if sampling is off.
+async fn next_tick(ticker: &mut Option<Interval>) {
+    match ticker {
+        Some(ticker) => {
+            ticker.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Runs read commands that arrived on the priority lane while a long command
+/// is in progress, right there on the blocking thread. Takes a bounded number
+/// per call so the interrupted command keeps making progress. Commands that
+/// change the scale wait for the long command to finish.
+fn serve_priority<S: Scale>(scale: &S, priority: &PriorityLane<Request>, observer: &Observer) {
+    for _ in 0..MAX_PRIORITY_STREAK {
+        let Some(request) = priority.try_recv_if(|request| is_read(&request.cmd)) else {
+            return;
+        };
+        let response = if request.is_expired() {
+            ScaleResponse::Expired
+        } else {
+            observer.run(|| read(scale, request.cmd, &CancelFlag::new(), &mut |_| {}))
+        };
+        let _ = request.reply.send(response);
+    }
+}
+
+/// Runs scale calls for the actor and reports their outcome to
+/// `ActorConfig::metrics`, if the `metrics` feature is enabled.
+#[derive(Clone)]
+struct Observer {
+    #[cfg(feature = "metrics")]
+    metrics: Option<ScaleMetrics>,
+}
+
+impl Observer {
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    fn new(config: &ActorConfig) -> Self {
+        Self {
+            #[cfg(feature = "metrics")]
+            metrics: config.metrics.clone(),
+        }
+    }
+
+    /// Runs `f` under [`contain`], timing it for the metrics.
+    fn run(&self, f: impl FnOnce() -> ScaleResponse) -> ScaleResponse {
+        let started = std::time::Instant::now();
+        let response = contain(f).unwrap_or_else(ScaleResponse::InternalError);
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.record_response(&response, started.elapsed());
+        }
+        #[cfg(not(feature = "metrics"))]
+        let _ = started;
+        response
+    }
+
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    fn queue_depth(&self, depth: usize) {
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = &self.metrics {
+            metrics.set_queue_depth(depth);
+        }
+    }
+}
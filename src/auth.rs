@@ -0,0 +1,49 @@
+use std::fmt;
+use std::time::Duration;
+
+/// How long a server waits before answering and closing a connection that
+/// failed to authenticate, to slow down guessing.
+pub const DEFAULT_AUTH_FAILURE_DELAY: Duration = Duration::from_millis(500);
+
+/// A shared secret that clients must present before a server runs their
+/// commands.
+///
+/// Comparisons take the same time however much of a guess is right, and the
+/// token is left out of `Debug` output so it does not end up in logs.
+#[derive(Clone)]
+pub struct AuthToken(String);
+
+impl AuthToken {
+    pub fn new(token: impl Into<String>) -> Self {
+        Self(token.into())
+    }
+
+    /// Whether `candidate` is this token, in time that depends only on the
+    /// lengths of the two.
+    pub fn matches(&self, candidate: &str) -> bool {
+        let (expected, candidate) = (self.0.as_bytes(), candidate.as_bytes());
+        let mut difference = expected.len() ^ candidate.len();
+        for at in 0..expected.len().max(candidate.len()) {
+            let expected = expected.get(at).copied().unwrap_or(0);
+            let candidate = candidate.get(at).copied().unwrap_or(0);
+            difference |= usize::from(expected ^ candidate);
+        }
+        difference == 0
+    }
+}
+
+/// Compared with [`matches`](AuthToken::matches), so `==` takes the same
+/// time too.
+impl PartialEq for AuthToken {
+    fn eq(&self, other: &Self) -> bool {
+        self.matches(&other.0)
+    }
+}
+
+impl Eq for AuthToken {}
+
+impl fmt::Debug for AuthToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AuthToken(..)")
+    }
+}
@@ -0,0 +1,135 @@
+use std::fs::OpenOptions;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::Parser;
+use libra::cancel::CancelFlag;
+use libra::cli::{
+    connect, discover, run, transfer, watch, Cli, CliConfig, CliError, Command, Report, WatchStyle,
+    WatchView,
+};
+use libra::Scale;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = execute(&cli);
+    let code = match &result {
+        Ok(report) if cli.json => print(&report.to_json().to_string()),
+        Ok(report) => print(&report.to_string()),
+        Err(error) if cli.json => print(&error.to_json().to_string()).and(Err(error.exit_code())),
+        Err(error) => {
+            eprintln!("error: {error}");
+            Err(error.exit_code())
+        }
+    };
+    match code {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(code) => ExitCode::from(code as u8),
+    }
+}
+
+fn execute(cli: &Cli) -> Result<Report, CliError> {
+    // Needs no scale, so works on machines without one.
+    #[cfg(feature = "schema")]
+    if let Command::Schema { dir } = &cli.command {
+        return Ok(Report::Exported(libra::schema::export_schemas(dir)?));
+    }
+    // Looks for scales rather than connecting to one.
+    if let Command::Discover { timeout } = &cli.command {
+        return discover(*timeout);
+    }
+    let config = match &cli.config {
+        Some(path) => CliConfig::load(path)?,
+        None => CliConfig::default(),
+    };
+    // Works on the config file alone, so needs no scale either.
+    if let Command::Calibration { action } = &cli.command {
+        let report = transfer(action, &config)?;
+        save_calibration(cli, &config, &report)?;
+        return Ok(report);
+    }
+    let mut scale = connect(cli.serial.or(config.serial), config.calibration)?;
+    let report = match &cli.command {
+        Command::Watch { raw, log, interval } => {
+            let style = if cli.json {
+                WatchStyle::Json
+            } else if io::stdout().is_terminal() {
+                WatchStyle::Terminal
+            } else {
+                WatchStyle::Lines
+            };
+            let view = WatchView::new(style, *raw);
+            watch_until_interrupted(&scale, view, log.as_deref(), *interval)?
+        }
+        command => run(&mut scale, command, &mut confirm)?,
+    };
+    save_calibration(cli, &config, &report)?;
+    Ok(report)
+}
+
+/// Writes the calibration `report` left the scale with, if any, to the
+/// config file, if one was given.
+fn save_calibration(cli: &Cli, config: &CliConfig, report: &Report) -> Result<(), CliError> {
+    if let (Some(path), Some(calibration)) = (&cli.config, report.new_calibration()) {
+        let serial = cli.serial.or(config.serial);
+        let config = CliConfig {
+            serial,
+            calibration: Some(calibration),
+        };
+        config.save(path)?;
+        eprintln!("Saved the calibration to {}", path.display());
+    }
+    Ok(())
+}
+
+/// Watches `scale` until Ctrl-C, appending to the CSV file at `log` if given.
+fn watch_until_interrupted(
+    scale: &dyn Scale,
+    mut view: WatchView,
+    log: Option<&Path>,
+    interval: u64,
+) -> Result<Report, CliError> {
+    let mut log = match log {
+        Some(path) => {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            if file.metadata()?.len() == 0 {
+                writeln!(file, "{}", view.csv_header())?;
+            }
+            Some(file)
+        }
+        None => None,
+    };
+    let stop = CancelFlag::new();
+    let handler = stop.clone();
+    ctrlc::set_handler(move || handler.cancel()).map_err(io::Error::other)?;
+    let mut stdout = io::stdout().lock();
+    watch(
+        scale,
+        &mut view,
+        Duration::from_millis(interval),
+        &mut stdout,
+        log.as_mut().map(|file| file as &mut dyn Write),
+        &stop,
+    )
+}
+
+/// Prompts on stderr, so that stdout only holds the result, and waits for
+/// Enter.
+fn confirm(instruction: &str) -> io::Result<()> {
+    eprint!("{instruction} ");
+    io::stderr().flush()?;
+    let mut line = String::new();
+    match io::stdin().lock().read_line(&mut line)? {
+        0 => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "No operator at the terminal",
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn print(output: &str) -> Result<(), i32> {
+    writeln!(io::stdout(), "{output}").map_err(|_| libra::cli::EXIT_FAILURE)
+}
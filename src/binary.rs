@@ -0,0 +1,97 @@
+use std::io::{self, Read, Write};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{ScaleCmd, ScaleResponse};
+
+/// Largest message [`write_frame`] and [`read_frame`] carry.
+pub const MAX_MESSAGE_LEN: usize = u16::MAX as usize;
+
+/// Why a postcard message could not be decoded.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    #[error("Invalid postcard message: {0}")]
+    Postcard(#[from] postcard::Error),
+
+    #[error("{0} unexpected bytes after the message")]
+    TrailingBytes(usize),
+}
+
+impl From<DecodeError> for io::Error {
+    fn from(error: DecodeError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+impl ScaleCmd {
+    /// This command in postcard's compact binary encoding: variants are
+    /// numbered in declaration order and integers are varints, so most
+    /// commands take one to four bytes.
+    pub fn to_postcard(&self) -> Vec<u8> {
+        encode(self)
+    }
+
+    /// Decodes a command encoded by [`to_postcard`](Self::to_postcard).
+    pub fn from_postcard(bytes: &[u8]) -> Result<Self, DecodeError> {
+        decode(bytes)
+    }
+}
+
+impl ScaleResponse {
+    /// This response in postcard's compact binary encoding.
+    pub fn to_postcard(&self) -> Vec<u8> {
+        encode(self)
+    }
+
+    /// Decodes a response encoded by [`to_postcard`](Self::to_postcard).
+    pub fn from_postcard(bytes: &[u8]) -> Result<Self, DecodeError> {
+        decode(bytes)
+    }
+}
+
+fn encode<T: Serialize>(message: &T) -> Vec<u8> {
+    // Postcard only fails for unsized sequences and maps, and for running out
+    // of room; messages have neither and the buffer grows as needed.
+    postcard::to_allocvec(message).expect("commands and responses always serialize")
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, DecodeError> {
+    match postcard::take_from_bytes(bytes)? {
+        (message, []) => Ok(message),
+        (_, rest) => Err(DecodeError::TrailingBytes(rest.len())),
+    }
+}
+
+/// Writes `message` preceded by its length as a big-endian `u16`, so the
+/// reader knows where it ends on a stream. Fails with `InvalidInput` if
+/// `message` is longer than [`MAX_MESSAGE_LEN`].
+pub fn write_frame<W: Write>(writer: &mut W, message: &[u8]) -> io::Result<()> {
+    let len = u16::try_from(message.len()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Message of {} bytes exceeds the limit of {MAX_MESSAGE_LEN}",
+                message.len()
+            ),
+        )
+    })?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(message)?;
+    writer.flush()
+}
+
+/// Reads one message written by [`write_frame`]. Returns `None` if the
+/// stream ends cleanly before a frame starts; a stream that ends partway
+/// through a frame fails with `UnexpectedEof`.
+pub fn read_frame<R: Read>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 2];
+    match reader.read(&mut len[..1])? {
+        0 => return Ok(None),
+        _ => reader.read_exact(&mut len[1..])?,
+    }
+    let mut message = vec![0; usize::from(u16::from_be_bytes(len))];
+    reader.read_exact(&mut message)?;
+    Ok(Some(message))
+}
@@ -0,0 +1,104 @@
+use std::future::Future;
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::runtime::Handle;
+
+use crate::cancel::CancelFlag;
+use crate::scale::{ScaleError, DEFAULT_MEDIAN_SAMPLES};
+use crate::{AsyncScale, AsyncScaleError, Grams, MedianGrams, Scale};
+
+/// Per-call timeout used by [`BlockingScale::new`].
+pub const DEFAULT_BLOCKING_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Error, Debug)]
+pub enum BlockingScaleError {
+    #[error(
+        "BlockingScale called from inside a tokio runtime; \
+         await the AsyncScale directly or call from a plain thread"
+    )]
+    InsideRuntime,
+
+    #[error("Scale did not respond within {0:?}")]
+    Timeout(Duration),
+
+    #[error("{0}")]
+    Scale(#[source] AsyncScaleError),
+}
+
+/// Exposes an [`AsyncScale`] through the blocking [`Scale`] trait.
+///
+/// Each call drives the async scale to completion on `runtime`, giving up
+/// after the configured timeout. Blocking inside a runtime thread would stall
+/// (or deadlock) it, so calls made from async context fail immediately with
+/// [`BlockingScaleError::InsideRuntime`].
+pub struct BlockingScale<S> {
+    scale: S,
+    runtime: Handle,
+    timeout: Duration,
+}
+
+impl<S: AsyncScale> BlockingScale<S> {
+    pub fn new(scale: S, runtime: Handle) -> Self {
+        Self {
+            scale,
+            runtime,
+            timeout: DEFAULT_BLOCKING_TIMEOUT,
+        }
+    }
+
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.scale
+    }
+
+    pub fn into_inner(self) -> S {
+        self.scale
+    }
+
+    fn block_on<T>(
+        &self,
+        future: impl Future<Output = Result<T, AsyncScaleError>>,
+    ) -> Result<T, BlockingScaleError> {
+        if Handle::try_current().is_ok() {
+            return Err(BlockingScaleError::InsideRuntime);
+        }
+        self.runtime
+            .block_on(async { tokio::time::timeout(self.timeout, future).await })
+            .map_err(|_| BlockingScaleError::Timeout(self.timeout))?
+            .map_err(BlockingScaleError::Scale)
+    }
+}
+
+impl<S: AsyncScale> Scale for BlockingScale<S> {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.block_on(self.scale.get_weight())?)
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_median_weight_of(DEFAULT_MEDIAN_SAMPLES)
+    }
+
+    fn get_median_weight_of(
+        &self,
+        samples: usize,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.block_on(self.scale.get_median_weight(samples))?)
+    }
+
+    /// Checks `cancel` only before starting: the async scale takes the median
+    /// in one call.
+    fn get_median_weight_cancellable(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        if cancel.is_cancelled() {
+            return Err(Box::new(ScaleError::Cancelled { collected: 0 }));
+        }
+        self.get_median_weight_of(samples)
+    }
+}
@@ -0,0 +1,363 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::calibration::Calibration;
+use crate::cancel::CancelFlag;
+use crate::clock::Clock;
+use crate::scale::{ScaleError, NUMBER_OF_INPUTS};
+use crate::watchdog::StaleChannel;
+use crate::{Grams, MedianGrams, Scale, ScaleErrorInfo, ScaleErrorKind, ScaleStatus};
+
+/// Failures in a row after which a [`BreakerConfig::default`] opens.
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+/// How long a [`BreakerConfig::default`] stays open before probing.
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BreakerConfig {
+    /// Failures in a row that open the circuit. 0 is taken as 1.
+    pub failure_threshold: u32,
+    /// How long the circuit stays open before a call is let through to probe
+    /// the scale.
+    pub cooldown: Duration,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            cooldown: DEFAULT_COOLDOWN,
+        }
+    }
+}
+
+/// Where a [`CircuitBreaker`] stands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BreakerState {
+    /// Calls go through.
+    Closed,
+    /// Calls fail at once with `ScaleError::CircuitOpen`.
+    Open,
+    /// The cooldown is over and one call is probing the scale; the others
+    /// still fail at once.
+    HalfOpen,
+}
+
+struct Circuit {
+    state: BreakerState,
+    failures: u32,
+    /// When the circuit last opened, by both clocks: the monotonic one for
+    /// the cooldown, the wall clock for `ScaleError::CircuitOpen`.
+    opened: Option<(Instant, SystemTime)>,
+    last_error: Option<ScaleErrorInfo>,
+}
+
+type TransitionCallback = Box<dyn Fn(BreakerState, BreakerState) + Send + Sync>;
+
+/// Stops calls to a scale that keeps failing, so that callers fail fast
+/// instead of each waiting out the full timeout of a dead bridge.
+///
+/// After `failure_threshold` failures in a row the circuit opens, and calls
+/// fail at once with `ScaleError::CircuitOpen`. Once `cooldown` has passed,
+/// the next call probes the scale: if it succeeds the circuit closes again,
+/// and if it fails the circuit reopens for another cooldown.
+///
+/// Only errors that are transient or disconnections count as failures. A
+/// permanent error, such as an invalid argument, is an answer from a working
+/// scale, and counts as a success.
+pub struct CircuitBreaker {
+    config: BreakerConfig,
+    circuit: Mutex<Circuit>,
+    on_transition: Option<TransitionCallback>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: BreakerConfig) -> Self {
+        Self {
+            config,
+            circuit: Mutex::new(Circuit {
+                state: BreakerState::Closed,
+                failures: 0,
+                opened: None,
+                last_error: None,
+            }),
+            on_transition: None,
+        }
+    }
+
+    /// Calls `callback` with the old and new state on every change of state.
+    /// It runs on the thread that made the call causing the change, after
+    /// the breaker's lock has been released.
+    pub fn on_transition(
+        self,
+        callback: impl Fn(BreakerState, BreakerState) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            on_transition: Some(Box::new(callback)),
+            ..self
+        }
+    }
+
+    pub fn config(&self) -> &BreakerConfig {
+        &self.config
+    }
+
+    pub fn state(&self) -> BreakerState {
+        self.circuit().state
+    }
+
+    /// Failures counted since the last success.
+    pub fn consecutive_failures(&self) -> u32 {
+        self.circuit().failures
+    }
+
+    /// The last failure counted, even if the circuit has closed since.
+    pub fn last_error(&self) -> Option<ScaleErrorInfo> {
+        self.circuit().last_error.clone()
+    }
+
+    /// Closes the circuit and forgets the failures, as after reconnecting.
+    pub fn reset(&self) {
+        let transition = {
+            let mut circuit = self.circuit();
+            circuit.failures = 0;
+            circuit.opened = None;
+            Self::change(&mut circuit, BreakerState::Closed)
+        };
+        self.notify(transition);
+    }
+
+    /// Runs `op` unless the circuit is open, and counts its result.
+    pub fn call<T>(
+        &self,
+        op: impl FnOnce() -> Result<T, Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Result<T, Box<dyn std::error::Error + Send + Sync>> {
+        let transition = {
+            let mut circuit = self.circuit();
+            match (circuit.state, circuit.opened) {
+                (BreakerState::Closed, _) => None,
+                (BreakerState::Open, Some((opened, _)))
+                    if opened.elapsed() >= self.config.cooldown =>
+                {
+                    Self::change(&mut circuit, BreakerState::HalfOpen)
+                }
+                (_, opened) => {
+                    return Err(Box::new(ScaleError::CircuitOpen {
+                        since: opened.map_or_else(SystemTime::now, |(_, since)| since),
+                        last_error: circuit.last_error.clone().unwrap_or_else(|| {
+                            ScaleErrorInfo::new(ScaleErrorKind::Other, "no error recorded")
+                        }),
+                    }))
+                }
+            }
+        };
+        self.notify(transition);
+
+        let result = op();
+        let transition = {
+            let mut circuit = self.circuit();
+            match result
+                .as_ref()
+                .err()
+                .map(|error| (failure(&**error), error))
+            {
+                // Another breaker's open circuit says nothing about the scale.
+                Some((None, _)) => None,
+                Some((Some(true), error)) => {
+                    circuit.failures = circuit.failures.saturating_add(1);
+                    circuit.last_error = Some(ScaleErrorInfo::from_dyn(&**error));
+                    if circuit.state == BreakerState::HalfOpen
+                        || circuit.failures >= self.config.failure_threshold.max(1)
+                    {
+                        circuit.opened = Some((Instant::now(), SystemTime::now()));
+                        Self::change(&mut circuit, BreakerState::Open)
+                    } else {
+                        None
+                    }
+                }
+                _ => {
+                    circuit.failures = 0;
+                    circuit.opened = None;
+                    Self::change(&mut circuit, BreakerState::Closed)
+                }
+            }
+        };
+        self.notify(transition);
+        result
+    }
+
+    fn circuit(&self) -> MutexGuard<'_, Circuit> {
+        self.circuit.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn change(circuit: &mut Circuit, to: BreakerState) -> Option<(BreakerState, BreakerState)> {
+        let from = std::mem::replace(&mut circuit.state, to);
+        (from != to).then_some((from, to))
+    }
+
+    fn notify(&self, transition: Option<(BreakerState, BreakerState)>) {
+        if let (Some(callback), Some((from, to))) = (&self.on_transition, transition) {
+            callback(from, to);
+        }
+    }
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new(BreakerConfig::default())
+    }
+}
+
+/// Whether `error` counts as a failure of the scale, or `None` for an open
+/// circuit, which counts as nothing.
+fn failure(error: &(dyn std::error::Error + 'static)) -> Option<bool> {
+    let rebuilt;
+    let error = match error.downcast_ref::<ScaleError>() {
+        Some(error) => error,
+        None => {
+            rebuilt = ScaleError::from(ScaleErrorInfo::from_dyn(error));
+            &rebuilt
+        }
+    };
+    match error {
+        ScaleError::CircuitOpen { .. } => None,
+        error => Some(error.is_transient() || error.is_disconnection()),
+    }
+}
+
+/// Runs every call that reaches the hardware of a scale through a
+/// [`CircuitBreaker`].
+///
+/// Weight and raw reads, taring and zeroing go through the breaker; reading
+/// or setting the calibration, `status`, `stale_channel` and `close` do not,
+/// so a supervisor can still look at a scale whose circuit is open. A median
+/// is one call, however many samples it takes.
+pub struct CircuitBreakerScale<S> {
+    scale: S,
+    breaker: CircuitBreaker,
+}
+
+impl<S: Scale> CircuitBreakerScale<S> {
+    pub fn new(scale: S, breaker: CircuitBreaker) -> Self {
+        Self { scale, breaker }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.scale
+    }
+
+    pub fn into_inner(self) -> S {
+        self.scale
+    }
+
+    pub fn breaker(&self) -> &CircuitBreaker {
+        &self.breaker
+    }
+
+    pub fn state(&self) -> BreakerState {
+        self.breaker.state()
+    }
+}
+
+impl<S: Scale> Scale for CircuitBreakerScale<S> {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.breaker.call(|| self.scale.get_weight())
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.breaker.call(|| self.scale.get_median_weight())
+    }
+
+    fn get_median_weight_of(
+        &self,
+        samples: usize,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.breaker
+            .call(|| self.scale.get_median_weight_of(samples))
+    }
+
+    fn get_median_weight_cancellable(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.breaker
+            .call(|| self.scale.get_median_weight_cancellable(samples, cancel))
+    }
+
+    fn get_median_weight_yielding(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+        between_samples: &mut dyn FnMut(),
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.breaker.call(|| {
+            self.scale
+                .get_median_weight_yielding(samples, cancel, between_samples)
+        })
+    }
+
+    fn tare(&mut self, samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        let scale = &mut self.scale;
+        self.breaker.call(|| scale.tare(samples))
+    }
+
+    fn zero(
+        &mut self,
+        samples: usize,
+    ) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        let scale = &mut self.scale;
+        self.breaker.call(|| scale.zero(samples))
+    }
+
+    fn get_raw_readings(
+        &self,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        self.breaker.call(|| self.scale.get_raw_readings())
+    }
+
+    fn get_raw_medians(
+        &self,
+        samples: usize,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        self.breaker.call(|| self.scale.get_raw_medians(samples))
+    }
+
+    fn calibration(&self) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        self.scale.calibration()
+    }
+
+    fn set_calibration(
+        &mut self,
+        calibration: Calibration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.scale.set_calibration(calibration)
+    }
+
+    fn status(&mut self) -> Result<ScaleStatus, Box<dyn std::error::Error + Send + Sync>> {
+        self.scale.status()
+    }
+
+    fn data_interval(&mut self) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+        self.scale.data_interval()
+    }
+
+    fn set_data_interval(
+        &mut self,
+        interval: Duration,
+    ) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+        self.scale.set_data_interval(interval)
+    }
+
+    fn stale_channel(&self) -> Option<StaleChannel> {
+        self.scale.stale_channel()
+    }
+
+    fn clock(&self) -> Arc<dyn Clock> {
+        self.scale.clock()
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.scale.close()
+    }
+}
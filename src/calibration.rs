@@ -0,0 +1,717 @@
+use std::fmt;
+#[cfg(feature = "tokio")]
+use std::time::Duration;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+#[cfg(feature = "tokio")]
+use tokio::sync::{mpsc, oneshot};
+#[cfg(feature = "tokio")]
+use tokio_util::sync::CancellationToken;
+
+use crate::clock::utc_timestamp;
+use crate::scale::{ScaleError, NUMBER_OF_INPUTS};
+#[cfg(feature = "tokio")]
+use crate::{
+    clock::{Clock, SystemClock},
+    sampling::finite_readings,
+    shared::SharedScale,
+    stability::StabilityDetector,
+};
+use crate::{Grams, Scale};
+
+/// The constants that turn raw load cell readings into grams:
+/// `weight = readings · coefficients - offset`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Calibration {
+    pub offset: f64,
+    pub coefficients: [f64; NUMBER_OF_INPUTS],
+}
+
+impl Calibration {
+    /// Fails with `ScaleError::InvalidCoefficients` if the offset or a
+    /// coefficient is NaN or infinite, which would make every weight so.
+    pub fn validate(&self) -> Result<(), ScaleError> {
+        if self.offset.is_finite() && self.coefficients.iter().all(|c| c.is_finite()) {
+            Ok(())
+        } else {
+            Err(ScaleError::InvalidCoefficients)
+        }
+    }
+
+    /// The weight `readings` come to, before any tare is taken off.
+    pub fn weigh(&self, readings: &[f64; NUMBER_OF_INPUTS]) -> Grams {
+        let dot: f64 = readings
+            .iter()
+            .zip(&self.coefficients)
+            .map(|(reading, coefficient)| reading * coefficient)
+            .sum();
+        Grams(dot - self.offset)
+    }
+
+    /// The calibration as a code short enough to read out over the phone or
+    /// send in a text message, for [`from_compact_string`](Self::from_compact_string)
+    /// to read back exactly.
+    ///
+    /// The code is a version byte, the offset and coefficients as
+    /// little-endian `f64`s, and a CRC-32 of them, in Crockford's base32:
+    /// digits and capitals without I, L, O or U, in groups of six joined by
+    /// `-`, 83 characters in all.
+    pub fn to_compact_string(&self) -> String {
+        let mut bytes = Vec::with_capacity(COMPACT_BYTES);
+        bytes.push(COMPACT_VERSION);
+        for value in std::iter::once(self.offset).chain(self.coefficients) {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.extend(crc32(&bytes).to_le_bytes());
+
+        let digits: Vec<u8> = (0..COMPACT_DIGITS)
+            .map(|digit| {
+                let bit = digit * 5;
+                // The ten bits from the byte the digit starts in.
+                let pair = u16::from(bytes[bit / 8]) << 8
+                    | u16::from(bytes.get(bit / 8 + 1).copied().unwrap_or(0));
+                CROCKFORD[usize::from(pair >> (11 - bit % 8) & 0x1F)]
+            })
+            .collect();
+        digits
+            .chunks(COMPACT_GROUP)
+            .map(|group| String::from_utf8_lossy(group).into_owned())
+            .collect::<Vec<_>>()
+            .join("-")
+    }
+
+    /// Reads a code made by [`to_compact_string`](Self::to_compact_string).
+    ///
+    /// Case, spaces and `-` do not matter, and O, I and L read as 0, 1 and
+    /// 1, as they are easily mistaken for them. Fails, saying where, for a
+    /// character not of the code, a code cut short or too long, one of a
+    /// version not known, and one whose checksum does not match, such as
+    /// for a character read wrong.
+    pub fn from_compact_string(code: &str) -> Result<Self, CompactCodeError> {
+        let mut values = Vec::with_capacity(COMPACT_DIGITS);
+        for (position, character) in code.chars().enumerate() {
+            let value = match character.to_ascii_uppercase() {
+                '-' | ' ' => continue,
+                'O' => 0,
+                'I' | 'L' => 1,
+                upper => CROCKFORD
+                    .iter()
+                    .position(|digit| char::from(*digit) == upper)
+                    .ok_or(CompactCodeError::InvalidCharacter {
+                        character,
+                        position: position + 1,
+                    })?,
+            };
+            values.push(value as u8);
+        }
+        let mut bytes = vec![0u8; values.len() * 5 / 8];
+        for (digit, value) in values.iter().enumerate() {
+            for bit in 0..5 {
+                let at = digit * 5 + bit;
+                if value >> (4 - bit) & 1 == 1 && at / 8 < bytes.len() {
+                    bytes[at / 8] |= 0x80 >> (at % 8);
+                }
+            }
+        }
+        match bytes.first() {
+            Some(&COMPACT_VERSION) | None => {}
+            Some(&version) => return Err(CompactCodeError::UnknownVersion(version)),
+        }
+        if values.len() != COMPACT_DIGITS {
+            return Err(CompactCodeError::WrongLength {
+                found: values.len(),
+                expected: COMPACT_DIGITS,
+            });
+        }
+        let (body, checksum) = bytes.split_at(COMPACT_BYTES - 4);
+        let found = u32::from_le_bytes(checksum.try_into().expect("four bytes"));
+        let expected = crc32(body);
+        if found != expected {
+            return Err(CompactCodeError::ChecksumMismatch { expected, found });
+        }
+        let mut floats = body[1..]
+            .chunks(8)
+            .map(|value| f64::from_le_bytes(value.try_into().expect("eight bytes")));
+        let calibration = Calibration {
+            offset: floats.next().expect("an offset"),
+            coefficients: std::array::from_fn(|_| floats.next().expect("a coefficient")),
+        };
+        calibration
+            .validate()
+            .map_err(|_| CompactCodeError::NotFinite)?;
+        Ok(calibration)
+    }
+
+    /// Copies the calibration to `scale`, one of the same build as the scale
+    /// it was made on, and weighs `check_mass` on it, taking the median of
+    /// `samples` readings, to see whether it fits. The check mass should be
+    /// on the platform, which should have been zeroed or tared empty.
+    ///
+    /// If the weight is more than `tolerance` out, or cannot be read, the
+    /// scale's previous calibration is put back. Should putting it back fail
+    /// too, that is the error returned, as the scale is then left with a
+    /// calibration that was not checked.
+    pub fn transfer_check<S: Scale + ?Sized>(
+        &self,
+        scale: &mut S,
+        check_mass: Grams,
+        tolerance: Grams,
+        samples: usize,
+    ) -> Result<TransferResult, Box<dyn std::error::Error + Send + Sync>> {
+        let check = TransferCheck {
+            check_mass,
+            tolerance,
+            samples,
+        };
+        let previous = scale.calibration()?;
+        scale.set_calibration(*self)?;
+        let result = scale
+            .get_median_weight_of(samples)
+            .map(|measured| check.judge(Grams(measured.get())));
+        if !matches!(result, Ok(TransferResult { passed: true, .. })) {
+            scale.set_calibration(previous)?;
+        }
+        result
+    }
+}
+
+/// How to check a calibration copied to another scale: the median of
+/// `samples` readings of `check_mass` must come to within `tolerance` of it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransferCheck {
+    pub check_mass: Grams,
+    pub tolerance: Grams,
+    pub samples: usize,
+}
+
+impl TransferCheck {
+    /// The result of the check mass weighing `measured`.
+    pub fn judge(&self, measured: Grams) -> TransferResult {
+        let error = Grams(measured.get() - self.check_mass.get());
+        TransferResult {
+            check_mass: self.check_mass,
+            measured,
+            error,
+            passed: error.get().abs() <= self.tolerance.get(),
+        }
+    }
+}
+
+/// What [`Calibration::transfer_check`] weighed. A scale that failed has its
+/// previous calibration back.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransferResult {
+    pub check_mass: Grams,
+    pub measured: Grams,
+    /// `measured - check_mass`.
+    pub error: Grams,
+    pub passed: bool,
+}
+
+/// The version of the codes [`Calibration::to_compact_string`] makes.
+const COMPACT_VERSION: u8 = 1;
+/// A version byte, five `f64`s and a CRC-32.
+const COMPACT_BYTES: usize = 1 + 8 * (1 + NUMBER_OF_INPUTS) + 4;
+/// Base32 digits of the code, five bits each.
+const COMPACT_DIGITS: usize = (COMPACT_BYTES * 8).div_ceil(5);
+const COMPACT_GROUP: usize = 6;
+/// Crockford's base32 digits.
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Why [`Calibration::from_compact_string`] could not read a code.
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+pub enum CompactCodeError {
+    /// Positions count the characters of the code as given from 1, `-`
+    /// and spaces included.
+    #[error("{character:?} at position {position} is not a character of a calibration code")]
+    InvalidCharacter { character: char, position: usize },
+    /// Counting only the characters of the code, not `-` or spaces.
+    #[error(
+        "The calibration code has {found} characters, not {expected}; {}",
+        if .found < .expected { "is part of it missing?" } else { "was something added?" }
+    )]
+    WrongLength { found: usize, expected: usize },
+    #[error(
+        "The calibration code is of version {0}, and this version of libra reads version {current}",
+        current = COMPACT_VERSION
+    )]
+    UnknownVersion(u8),
+    #[error(
+        "The calibration code's checksum is {found:08X}, not {expected:08X}; \
+         a character was read or typed wrong"
+    )]
+    ChecksumMismatch { expected: u32, found: u32 },
+    /// The code is intact, but of an offset or coefficient that is NaN or
+    /// infinite.
+    #[error("The calibration code holds an offset or coefficient that is not a finite number")]
+    NotFinite,
+}
+
+/// CRC-32/ISO-HDLC, as in zip and PNG: polynomial 0x04C11DB7 reflected,
+/// initial value and final XOR 0xFFFFFFFF.
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            if crc & 1 == 0 {
+                crc >> 1
+            } else {
+                (crc >> 1) ^ 0xEDB8_8320
+            }
+        })
+    })
+}
+
+/// A [`Calibration`] with what an audit asks of it: when it was made, by
+/// whom, and against what.
+///
+/// Every field but the calibration is optional, and the calibration is
+/// flattened into the record, so that a file of a bare calibration reads as
+/// a record without metadata, and a record as a bare calibration. The
+/// flattening needs a self-describing format such as JSON.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationRecord {
+    #[serde(flatten)]
+    pub calibration: Calibration,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub performed_at: Option<SystemTime>,
+    /// Who made the calibration, such as a badge number.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operator: Option<String>,
+    /// The temperature and anything else about the conditions worth noting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// Each load measured for the calibration, in order, starting with the
+    /// empty platform.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reference_points: Vec<ReferencePoint>,
+    /// The platform calibrated and the bridge it was read through.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<ScaleIdentity>,
+}
+
+/// The platform a calibration is of, and the bridge it was read through.
+///
+/// A calibration belongs to the load cells of its platform, not to the
+/// bridge: it still holds once a bridge is swapped for another, which
+/// `CalibrationStore::adopt_new_bridge` records, but a new platform needs a
+/// calibration of its own.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ScaleIdentity {
+    /// A name for the platform that stays with it, such as an asset tag.
+    pub platform_id: String,
+    /// The serial number of the phidget bridge.
+    pub bridge_serial: i32,
+}
+
+/// A load measured for a calibration, and how far off the weight read under
+/// it was, in grams, before and after.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReferencePoint {
+    /// Zero for the empty platform.
+    pub mass: Grams,
+    /// The median voltage ratio of each load cell under the load.
+    pub readings: [f64; NUMBER_OF_INPUTS],
+    /// By the calibration it replaced, if known; see
+    /// [`CalibrationRecord::with_previous`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub residual_before: Option<f64>,
+    /// By the calibration made.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub residual_after: Option<f64>,
+}
+
+impl ReferencePoint {
+    /// The point of `mass` read as `readings`, with its residual under
+    /// `calibration`.
+    pub fn new(mass: Grams, readings: [f64; NUMBER_OF_INPUTS], calibration: &Calibration) -> Self {
+        Self {
+            mass,
+            readings,
+            residual_before: None,
+            residual_after: Some(calibration.weigh(&readings).0 - mass.0),
+        }
+    }
+}
+
+impl From<Calibration> for CalibrationRecord {
+    fn from(calibration: Calibration) -> Self {
+        Self {
+            calibration,
+            performed_at: None,
+            operator: None,
+            notes: None,
+            reference_points: Vec::new(),
+            identity: None,
+        }
+    }
+}
+
+impl CalibrationRecord {
+    /// Fills in the residual of each reference point under `previous`, the
+    /// calibration this one replaces.
+    pub fn with_previous(mut self, previous: &Calibration) -> Self {
+        for point in &mut self.reference_points {
+            point.residual_before = Some(previous.weigh(&point.readings).0 - point.mass.0);
+        }
+        self
+    }
+
+    pub fn with_operator(self, operator: impl Into<String>) -> Self {
+        Self {
+            operator: Some(operator.into()),
+            ..self
+        }
+    }
+
+    pub fn with_notes(self, notes: impl Into<String>) -> Self {
+        Self {
+            notes: Some(notes.into()),
+            ..self
+        }
+    }
+
+    pub fn with_identity(self, identity: ScaleIdentity) -> Self {
+        Self {
+            identity: Some(identity),
+            ..self
+        }
+    }
+
+    /// The reference masses placed on the platform, leaving out the empty
+    /// platform.
+    pub fn reference_masses(&self) -> Vec<Grams> {
+        self.reference_points
+            .iter()
+            .map(|point| point.mass)
+            .filter(|mass| mass.0 != 0.)
+            .collect()
+    }
+
+    /// The record as text for a calibration certificate.
+    pub fn summary(&self) -> CalibrationSummary<'_> {
+        CalibrationSummary(self)
+    }
+}
+
+/// The [`CalibrationRecord::summary`] of a record.
+#[derive(Clone, Copy, Debug)]
+pub struct CalibrationSummary<'a>(&'a CalibrationRecord);
+
+impl fmt::Display for CalibrationSummary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let record = self.0;
+        let or_unknown = |value: Option<&str>| value.unwrap_or("not recorded").to_string();
+        writeln!(f, "Calibration certificate")?;
+        writeln!(
+            f,
+            "Performed: {}",
+            or_unknown(record.performed_at.map(utc_timestamp).as_deref())
+        )?;
+        writeln!(f, "Operator: {}", or_unknown(record.operator.as_deref()))?;
+        if let Some(identity) = &record.identity {
+            writeln!(
+                f,
+                "Platform: {} on bridge {}",
+                identity.platform_id, identity.bridge_serial
+            )?;
+        }
+        writeln!(f, "Notes: {}", or_unknown(record.notes.as_deref()))?;
+        writeln!(f, "Offset: {}", record.calibration.offset)?;
+        write!(f, "Coefficients: {:?}", record.calibration.coefficients)?;
+        if record.reference_points.is_empty() {
+            return write!(f, "\nReference points: not recorded");
+        }
+        write!(
+            f,
+            "\n{:>12}  {:>12}  {:>12}",
+            "Reference", "Before", "After"
+        )?;
+        let residual = |residual: Option<f64>| match residual {
+            Some(residual) => format!("{residual:+.3} g"),
+            None => "-".into(),
+        };
+        for point in &record.reference_points {
+            write!(
+                f,
+                "\n{:>12}  {:>12}  {:>12}",
+                format!("{:.1} g", point.mass.0),
+                residual(point.residual_before),
+                residual(point.residual_after)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A scale that can report the voltage ratio of each load cell, as needed to
+/// calibrate it.
+pub trait RawScale {
+    fn get_raw_readings(&self) -> Result<[f64; NUMBER_OF_INPUTS], ScaleError>;
+}
+
+/// Median of each load cell across `readings`.
+#[cfg(feature = "tokio")]
+fn cell_medians(readings: &[[f64; NUMBER_OF_INPUTS]]) -> [f64; NUMBER_OF_INPUTS] {
+    std::array::from_fn(|cell| {
+        let mut values: Vec<f64> = readings.iter().map(|reading| reading[cell]).collect();
+        let middle = values.len() / 2;
+        *values.select_nth_unstable_by(middle, f64::total_cmp).1
+    })
+}
+
+/// Fits one gain shared by every load cell to the zero reading and the
+/// readings under each reference mass, by least squares.
+#[cfg(any(feature = "tokio", feature = "cli"))]
+pub(crate) fn fit(
+    zero: [f64; NUMBER_OF_INPUTS],
+    spans: &[(f64, [f64; NUMBER_OF_INPUTS])],
+) -> Result<Calibration, ScaleError> {
+    let zero: f64 = zero.iter().sum();
+    let (numerator, denominator) = spans.iter().fold((0., 0.), |(n, d), (mass, cells)| {
+        let delta = cells.iter().sum::<f64>() - zero;
+        (n + mass * delta, d + delta * delta)
+    });
+    let gain = numerator / denominator;
+    if !gain.is_finite() || gain == 0. {
+        return Err(ScaleError::InvalidCoefficients);
+    }
+    Ok(Calibration {
+        offset: gain * zero,
+        coefficients: [gain; NUMBER_OF_INPUTS],
+    })
+}
+
+/// How to run [`calibrate`].
+#[cfg(feature = "tokio")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CalibrationPlan {
+    /// Readings, per load cell, whose median gives the empty-platform zero.
+    pub zero_samples: usize,
+    /// Masses placed on the platform in turn for the span steps.
+    pub reference_masses: Vec<Grams>,
+    /// Readings, per load cell, whose median is taken under each reference mass.
+    pub span_samples: usize,
+    /// Spacing between readings, while settling and while measuring.
+    pub sample_interval: Duration,
+    /// Consecutive readings that must agree before a step is measured.
+    pub settle_window: usize,
+    /// Largest spread, in summed voltage ratio, allowed across the settle
+    /// window.
+    pub settle_tolerance: f64,
+    /// How long to wait for the platform to settle before giving up with
+    /// `ScaleError::NotSettled`.
+    pub settle_timeout: Duration,
+}
+
+/// A step of [`calibrate`] that the operator has to set up.
+#[cfg(feature = "tokio")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CalibrationStep {
+    /// Empty the platform for the zero reading.
+    ClearPlatform,
+    /// Put this reference mass, and nothing else, on the platform.
+    PlaceMass(Grams),
+}
+
+/// Completed by the operator once a [`CalibrationStep`] is set up. Dropping it
+/// without confirming cancels the calibration.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct OperatorReady(oneshot::Sender<()>);
+
+#[cfg(feature = "tokio")]
+impl OperatorReady {
+    pub fn confirm(self) {
+        let _ = self.0.send(());
+    }
+}
+
+/// Progress events sent by [`calibrate`], in order, for each step.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub enum CalibrationProgress {
+    /// Calibration is paused until `ready` is confirmed.
+    AwaitingOperator {
+        step: CalibrationStep,
+        ready: OperatorReady,
+    },
+    /// Waiting for the readings to stop moving.
+    Settling { step: CalibrationStep },
+    /// Reading `sample` of `samples` has been taken.
+    Measuring {
+        step: CalibrationStep,
+        sample: usize,
+        samples: usize,
+    },
+}
+
+/// Calibrates `scale` by walking the operator through `plan`: a zero reading
+/// on the empty platform, then a span reading under each reference mass. Each
+/// step waits for the operator, waits for the platform to settle, then
+/// measures, reporting all of it on `progress`.
+///
+/// All load cells are given the same gain, fitted across the reference
+/// masses. Returns `ScaleError::Cancelled` as soon as `cancel` is cancelled or
+/// the operator's [`OperatorReady`] is dropped, at any step. The scale itself
+/// is left unchanged; apply the calibration with
+/// [`ConnectedScale::set_calibration`](crate::scale::ConnectedScale::set_calibration).
+///
+/// The record returned has the time the calibration finished, and each
+/// point measured with its residual under the new calibration. The operator,
+/// notes and residuals under the calibration replaced are for the caller to
+/// fill in, as with [`CalibrationRecord::with_previous`].
+#[cfg(feature = "tokio")]
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "calibrate",
+        target = "libra",
+        level = "info",
+        skip_all,
+        fields(reference_masses = plan.reference_masses.len())
+    )
+)]
+pub async fn calibrate<S>(
+    scale: &SharedScale<S>,
+    plan: CalibrationPlan,
+    progress: mpsc::Sender<CalibrationProgress>,
+    cancel: &CancellationToken,
+) -> Result<CalibrationRecord, ScaleError>
+where
+    S: RawScale + Send + 'static,
+{
+    calibrate_with_clock(scale, plan, progress, cancel, &SystemClock).await
+}
+
+/// Like [`calibrate`], spacing the readings and timing the settling by
+/// `clock`.
+#[cfg(feature = "tokio")]
+pub async fn calibrate_with_clock<S>(
+    scale: &SharedScale<S>,
+    plan: CalibrationPlan,
+    progress: mpsc::Sender<CalibrationProgress>,
+    cancel: &CancellationToken,
+    clock: &dyn Clock,
+) -> Result<CalibrationRecord, ScaleError>
+where
+    S: RawScale + Send + 'static,
+{
+    let run = Calibrator {
+        scale,
+        plan: &plan,
+        progress,
+        clock,
+    };
+    tokio::select! {
+        _ = cancel.cancelled() => Err(ScaleError::Cancelled { collected: 0 }),
+        result = run.run() => result,
+    }
+}
+
+#[cfg(feature = "tokio")]
+struct Calibrator<'a, S> {
+    scale: &'a SharedScale<S>,
+    plan: &'a CalibrationPlan,
+    progress: mpsc::Sender<CalibrationProgress>,
+    clock: &'a dyn Clock,
+}
+
+#[cfg(feature = "tokio")]
+impl<S: RawScale + Send + 'static> Calibrator<'_, S> {
+    async fn run(&self) -> Result<CalibrationRecord, ScaleError> {
+        let zero = self
+            .step(CalibrationStep::ClearPlatform, self.plan.zero_samples)
+            .await?;
+        let mut spans = Vec::with_capacity(self.plan.reference_masses.len());
+        for &mass in &self.plan.reference_masses {
+            let cells = self
+                .step(CalibrationStep::PlaceMass(mass), self.plan.span_samples)
+                .await?;
+            spans.push((mass.get(), cells));
+        }
+        let calibration = fit(zero, &spans)?;
+        let points = std::iter::once((0., zero)).chain(spans);
+        Ok(CalibrationRecord {
+            performed_at: Some(SystemTime::now()),
+            reference_points: points
+                .map(|(mass, cells)| ReferencePoint::new(Grams(mass), cells, &calibration))
+                .collect(),
+            ..calibration.into()
+        })
+    }
+
+    async fn step(
+        &self,
+        step: CalibrationStep,
+        samples: usize,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], ScaleError> {
+        let (ready, confirmed) = oneshot::channel();
+        let ready = OperatorReady(ready);
+        if self
+            .progress
+            .send(CalibrationProgress::AwaitingOperator { step, ready })
+            .await
+            .is_err()
+            || confirmed.await.is_err()
+        {
+            return Err(ScaleError::Cancelled { collected: 0 });
+        }
+
+        self.report(CalibrationProgress::Settling { step }).await;
+        self.settle().await?;
+
+        let mut readings = Vec::with_capacity(samples);
+        for sample in 1..=samples.max(1) {
+            self.clock.sleep_async(self.plan.sample_interval).await;
+            readings.push(self.read().await?);
+            self.report(CalibrationProgress::Measuring {
+                step,
+                sample,
+                samples,
+            })
+            .await;
+        }
+        Ok(cell_medians(&readings))
+    }
+
+    /// Waits until `settle_window` consecutive readings agree. The timeout is
+    /// kept by the clock, and by tokio's too in case a read hangs.
+    async fn settle(&self) -> Result<(), ScaleError> {
+        let mut stability =
+            StabilityDetector::new(self.plan.settle_window, self.plan.settle_tolerance);
+        let deadline = self.clock.now() + self.plan.settle_timeout;
+        let waiting = async {
+            loop {
+                self.clock.sleep_async(self.plan.sample_interval).await;
+                if stability.push(self.read().await?.iter().sum::<f64>()) {
+                    return Ok(());
+                }
+                if self.clock.now() >= deadline {
+                    return Err(ScaleError::NotSettled(self.plan.settle_timeout));
+                }
+            }
+        };
+        tokio::time::timeout(self.plan.settle_timeout, waiting)
+            .await
+            .unwrap_or(Err(ScaleError::NotSettled(self.plan.settle_timeout)))
+    }
+
+    async fn read(&self) -> Result<[f64; NUMBER_OF_INPUTS], ScaleError> {
+        let scale = self.scale.clone();
+        match tokio::task::spawn_blocking(move || scale.lock().get_raw_readings()).await {
+            Ok(result) => result.and_then(finite_readings),
+            Err(e) => match e.try_into_panic() {
+                Ok(panic) => std::panic::resume_unwind(panic),
+                Err(_) => Err(ScaleError::Cancelled { collected: 0 }),
+            },
+        }
+    }
+
+    /// Sends a progress event. Nobody listening is no reason to stop.
+    async fn report(&self, event: CalibrationProgress) {
+        let _ = self.progress.send(event).await;
+    }
+}
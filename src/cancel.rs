@@ -0,0 +1,52 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::clock::{Clock, SystemClock};
+
+/// Longest stretch a cancellable wait sleeps before checking the flag again.
+const CANCEL_POLL_PERIOD: Duration = Duration::from_millis(5);
+
+/// A cloneable flag for aborting long-running blocking operations.
+///
+/// Clones share the same flag, so one can be handed to the thread doing the
+/// work and another kept by whoever decides to stop it. Operations check it
+/// between samples and while waiting for the next sample, and return
+/// `ScaleError::Cancelled` once it is set.
+#[derive(Clone, Debug, Default)]
+pub struct CancelFlag(Arc<AtomicBool>);
+
+impl CancelFlag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// Sleeps for `duration` unless cancelled first. Returns `false` if the
+    /// flag was set before the time was up.
+    pub(crate) fn sleep(&self, duration: Duration) -> bool {
+        self.sleep_on(&SystemClock, duration)
+    }
+
+    /// Like [`sleep`](Self::sleep), by `clock`.
+    pub(crate) fn sleep_on(&self, clock: &dyn Clock, duration: Duration) -> bool {
+        let deadline = clock.now() + duration;
+        loop {
+            if self.is_cancelled() {
+                return false;
+            }
+            let remaining = deadline.saturating_duration_since(clock.now());
+            if remaining.is_zero() {
+                return true;
+            }
+            clock.sleep(remaining.min(CANCEL_POLL_PERIOD));
+        }
+    }
+}
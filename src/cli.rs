@@ -0,0 +1,678 @@
+use std::fmt::{self, Write as _};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use clap::{Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::calibration::{fit, Calibration};
+use crate::cancel::CancelFlag;
+use crate::discovery::DiscoveredScale;
+use crate::sampling::collect_median;
+use crate::scale::{
+    ConnectedScale, DisconnectedScale, DEFAULT_MEDIAN_SAMPLES, NUMBER_OF_INPUTS, TIMEOUT,
+};
+use crate::stability::StabilityDetector;
+use crate::{Grams, MedianGrams, Scale, ScaleErrorInfo, ScaleStatus};
+
+/// Exit code for bad arguments or a bad config file.
+pub const EXIT_USAGE: i32 = 2;
+/// Exit code for an error from the scale: a Phidget error, a detached load
+/// cell, or something the scale does not support.
+pub const EXIT_SCALE: i32 = 3;
+/// Exit code for anything else, such as failing to write the output.
+pub const EXIT_FAILURE: i32 = 1;
+
+/// Reads and commissions a Phidget load cell scale.
+#[derive(Parser, Debug, Clone, PartialEq)]
+#[command(name = "libra", version)]
+pub struct Cli {
+    /// Serial number of the Phidget bridge. Defaults to the one in the
+    /// config file, then to the first bridge found.
+    #[arg(long, global = true)]
+    pub serial: Option<i32>,
+    /// JSON file holding the serial number and calibration. `zero` and
+    /// `calibrate` write their new calibration back to it.
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Print results and errors as JSON.
+    #[arg(long, global = true)]
+    pub json: bool,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Take one reading.
+    Weight,
+    /// Take the median of several readings.
+    Median {
+        #[arg(long, default_value_t = DEFAULT_MEDIAN_SAMPLES)]
+        samples: usize,
+        /// Milliseconds between readings.
+        #[arg(long, value_name = "MS", default_value_t = 100)]
+        interval: u64,
+    },
+    /// Tare off whatever is on the platform.
+    Tare {
+        #[arg(long, default_value_t = DEFAULT_MEDIAN_SAMPLES)]
+        samples: usize,
+    },
+    /// Re-zero the calibration on the empty platform.
+    Zero {
+        #[arg(long, default_value_t = DEFAULT_MEDIAN_SAMPLES)]
+        samples: usize,
+    },
+    /// Print the voltage ratio of each load cell.
+    Raw,
+    /// Calibrate from the empty platform and one reference mass, prompting
+    /// for each.
+    Calibrate {
+        /// The reference mass, such as `500g` or `2kg`.
+        #[arg(long, value_parser = parse_mass)]
+        mass: Grams,
+        /// Readings per load cell at each step.
+        #[arg(long, default_value_t = DEFAULT_MEDIAN_SAMPLES)]
+        samples: usize,
+    },
+    /// Print what the scale reports about itself.
+    Info,
+    /// Keep printing the weight until interrupted, then summarise.
+    Watch {
+        /// Also show the voltage ratio of each load cell.
+        #[arg(long)]
+        raw: bool,
+        /// Append every reading to this CSV file too.
+        #[arg(long, value_name = "FILE")]
+        log: Option<PathBuf>,
+        /// Milliseconds between readings.
+        #[arg(long, value_name = "MS", default_value_t = 100)]
+        interval: u64,
+    },
+    /// List the bridges attached that look like scales. Needs no scale.
+    Discover {
+        /// Milliseconds to wait for the phidget library to report them.
+        #[arg(long, value_name = "MS", default_value_t = 1000)]
+        timeout: u64,
+    },
+    /// Move a calibration between machines as a short code, to read out or
+    /// send as text. Needs no scale.
+    Calibration {
+        #[command(subcommand)]
+        action: CalibrationAction,
+    },
+    /// Write the JSON Schema of each protocol message to a directory.
+    #[cfg(feature = "schema")]
+    Schema {
+        #[arg(value_name = "DIR")]
+        dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq)]
+pub enum CalibrationAction {
+    /// Print the calibration of the config file as a code.
+    Export,
+    /// Read a code made by `export`, and write its calibration to the
+    /// config file, if one is given.
+    Import {
+        /// The code, with or without its dashes.
+        #[arg(value_name = "CODE")]
+        code: String,
+    },
+}
+
+/// Parses a mass in grams, with an optional `g` or `kg` suffix.
+pub fn parse_mass(mass: &str) -> Result<Grams, String> {
+    let mass = mass.trim();
+    let (number, scale) = if let Some(number) = mass.strip_suffix("kg") {
+        (number, 1000.)
+    } else {
+        (mass.strip_suffix('g').unwrap_or(mass), 1.)
+    };
+    // Scaled before checking, so that a kilogram count too large for grams
+    // is not let through as infinity.
+    match number.trim().parse::<f64>().map(|number| number * scale) {
+        Ok(grams) if grams > 0. && grams.is_finite() => Ok(Grams(grams)),
+        _ => Err(format!("{mass:?} is not a positive mass such as 500g")),
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CliError {
+    /// The arguments or the config file cannot be used.
+    #[error("{0}")]
+    Usage(String),
+
+    #[error("{0}")]
+    Scale(Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("{0}")]
+    Io(#[from] io::Error),
+}
+
+impl CliError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Usage(_) => EXIT_USAGE,
+            CliError::Scale(_) => EXIT_SCALE,
+            CliError::Io(_) => EXIT_FAILURE,
+        }
+    }
+
+    /// `{"error": ..}`, where `..` is a [`ScaleErrorInfo`] for scale errors
+    /// and `{"message": ..}` otherwise.
+    pub fn to_json(&self) -> Value {
+        match self {
+            CliError::Scale(error) => json!({ "error": ScaleErrorInfo::from_dyn(&**error) }),
+            error => json!({ "error": { "message": error.to_string() } }),
+        }
+    }
+}
+
+/// What the command line tool knows about a scale between runs.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CliConfig {
+    #[serde(default)]
+    pub serial: Option<i32>,
+    #[serde(default)]
+    pub calibration: Option<Calibration>,
+}
+
+impl CliConfig {
+    /// Reads the config at `path`. A file that does not exist yet is an
+    /// empty config, so that `calibrate` can create it.
+    pub fn load(path: &Path) -> Result<Self, CliError> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text)
+                .map_err(|e| CliError::Usage(format!("Invalid config {}: {e}", path.display()))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(CliError::Usage(format!(
+                "Cannot read config {}: {e}",
+                path.display()
+            ))),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), CliError> {
+        let mut text = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        text.push('\n');
+        Ok(std::fs::write(path, text)?)
+    }
+}
+
+/// Connects to the bridge with serial number `serial`, or the first one
+/// found, and applies `calibration`.
+pub fn connect(
+    serial: Option<i32>,
+    calibration: Option<Calibration>,
+) -> Result<ConnectedScale, CliError> {
+    let calibration = calibration.unwrap_or(Calibration {
+        offset: 0.,
+        coefficients: [0.; NUMBER_OF_INPUTS],
+    });
+    let scale = match serial {
+        Some(serial) => DisconnectedScale::new(serial).connect(
+            calibration.offset,
+            calibration.coefficients,
+            TIMEOUT,
+        ),
+        None => ConnectedScale::without_id(TIMEOUT).map(|mut scale| {
+            scale.set_calibration(calibration);
+            scale
+        }),
+    };
+    scale.map_err(|e| CliError::Scale(e.into()))
+}
+
+/// The result of a [`Command`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Report {
+    Weight(Grams),
+    Median {
+        weight: MedianGrams,
+        samples: usize,
+    },
+    Tared(Grams),
+    Zeroed(Calibration),
+    Raw([f64; NUMBER_OF_INPUTS]),
+    Calibrated(Calibration),
+    Info(ScaleStatus),
+    Watched(WatchSummary),
+    /// The bridges found by `discover`.
+    Discovered(Vec<DiscoveredScale>),
+    /// The code of a calibration, from `calibration export`.
+    CalibrationCode(String),
+    /// The calibration of a code, from `calibration import`.
+    Imported(Calibration),
+    /// The schema files written.
+    #[cfg(feature = "schema")]
+    Exported(Vec<PathBuf>),
+}
+
+impl Report {
+    /// The calibration the command left the scale with, if it changed it.
+    pub fn new_calibration(&self) -> Option<Calibration> {
+        match self {
+            Report::Zeroed(calibration)
+            | Report::Calibrated(calibration)
+            | Report::Imported(calibration) => Some(*calibration),
+            _ => None,
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        match self {
+            Report::Weight(weight) => json!({ "weight": weight }),
+            Report::Median { weight, samples } => json!({ "weight": weight, "samples": samples }),
+            Report::Tared(tare) => json!({ "tare": tare }),
+            Report::Zeroed(calibration)
+            | Report::Calibrated(calibration)
+            | Report::Imported(calibration) => {
+                json!({ "calibration": calibration })
+            }
+            Report::Raw(ratios) => json!({ "ratios": ratios }),
+            Report::Discovered(scales) => {
+                let scales: Vec<_> = scales
+                    .iter()
+                    .map(|scale| {
+                        json!({
+                            "serial": scale.serial,
+                            "label": scale.label,
+                            "hub_port": scale.hub_port,
+                            "channels": scale.channel_count(),
+                        })
+                    })
+                    .collect();
+                json!({ "scales": scales })
+            }
+            Report::CalibrationCode(code) => json!({ "code": code }),
+            Report::Info(status) => json!(status),
+            Report::Watched(summary) => json!({
+                "samples": summary.samples,
+                "min": summary.min,
+                "max": summary.max,
+                "last": summary.last,
+            }),
+            #[cfg(feature = "schema")]
+            Report::Exported(paths) => json!({ "files": paths }),
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Report::Weight(Grams(weight)) => write!(f, "{weight:.1} g"),
+            Report::Median {
+                weight: MedianGrams(weight),
+                samples,
+            } => write!(f, "{weight:.1} g (median of {samples})"),
+            Report::Tared(Grams(tare)) => write!(f, "Tared {tare:.1} g"),
+            Report::Zeroed(calibration)
+            | Report::Calibrated(calibration)
+            | Report::Imported(calibration) => write_calibration(f, calibration),
+            Report::Discovered(scales) if scales.is_empty() => {
+                write!(f, "No scales found")
+            }
+            Report::Discovered(scales) => {
+                write!(
+                    f,
+                    "{:<8}  {:<8}  {:<8}  Label",
+                    "Serial", "Hub port", "Channels"
+                )?;
+                for scale in scales {
+                    let hub_port = scale
+                        .hub_port
+                        .map_or_else(|| "-".into(), |port| port.to_string());
+                    write!(
+                        f,
+                        "\n{:<8}  {hub_port:<8}  {:<8}  {}",
+                        scale.serial,
+                        scale.channel_count(),
+                        scale.label.as_deref().unwrap_or("-")
+                    )?;
+                }
+                Ok(())
+            }
+            Report::CalibrationCode(code) => f.write_str(code),
+            Report::Raw(ratios) => {
+                for (cell, ratio) in ratios.iter().enumerate() {
+                    if cell > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "Load cell {cell}: {ratio:.9}")?;
+                }
+                Ok(())
+            }
+            Report::Info(status) => {
+                writeln!(f, "Phidget: {}", status.phidget_id)?;
+                writeln!(
+                    f,
+                    "Attached: {}",
+                    if status.attached { "yes" } else { "no" }
+                )?;
+                writeln!(f, "Tare: {:.1} g", status.tare.get())?;
+                write_calibration(f, &status.calibration)
+            }
+            Report::Watched(summary) => match (summary.min, summary.max, summary.last) {
+                (Some(Grams(min)), Some(Grams(max)), Some(Grams(last))) => write!(
+                    f,
+                    "{} samples, last {last:.1} g, min {min:.1} g, max {max:.1} g",
+                    summary.samples
+                ),
+                _ => write!(f, "No samples"),
+            },
+            #[cfg(feature = "schema")]
+            Report::Exported(paths) => {
+                for (at, path) in paths.iter().enumerate() {
+                    if at > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "Wrote {}", path.display())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn write_calibration(f: &mut fmt::Formatter<'_>, calibration: &Calibration) -> fmt::Result {
+    writeln!(f, "Offset: {}", calibration.offset)?;
+    write!(f, "Coefficients: {:?}", calibration.coefficients)
+}
+
+/// Runs `command` against `scale`. `calibrate` hands each instruction for
+/// the operator to `confirm`, which returns once it has been carried out;
+/// an error from it stops the calibration.
+pub fn run<S: Scale + ?Sized>(
+    scale: &mut S,
+    command: &Command,
+    confirm: &mut dyn FnMut(&str) -> io::Result<()>,
+) -> Result<Report, CliError> {
+    let samples = match command {
+        Command::Median { samples, .. }
+        | Command::Tare { samples }
+        | Command::Zero { samples }
+        | Command::Calibrate { samples, .. } => *samples,
+        _ => 1,
+    };
+    if samples == 0 {
+        return Err(CliError::Usage("--samples must be at least 1".into()));
+    }
+    let report = match *command {
+        Command::Weight => Report::Weight(scale.get_weight().map_err(CliError::Scale)?),
+        Command::Median { samples, interval } => {
+            let interval = Duration::from_millis(interval);
+            let weight =
+                collect_median(samples, interval, &CancelFlag::new(), || scale.get_weight())
+                    .map_err(CliError::Scale)?;
+            Report::Median { weight, samples }
+        }
+        Command::Tare { samples } => Report::Tared(scale.tare(samples).map_err(CliError::Scale)?),
+        Command::Zero { samples } => Report::Zeroed(scale.zero(samples).map_err(CliError::Scale)?),
+        Command::Raw => Report::Raw(scale.get_raw_readings().map_err(CliError::Scale)?),
+        Command::Calibrate { mass, samples } => {
+            confirm("Clear the platform, then press Enter.")?;
+            let zero = scale.get_raw_medians(samples).map_err(CliError::Scale)?;
+            confirm(&format!(
+                "Place {} g on the platform, then press Enter.",
+                mass.get()
+            ))?;
+            let span = scale.get_raw_medians(samples).map_err(CliError::Scale)?;
+            let calibration =
+                fit(zero, &[(mass.get(), span)]).map_err(|e| CliError::Scale(e.into()))?;
+            scale
+                .set_calibration(calibration)
+                .map_err(CliError::Scale)?;
+            Report::Calibrated(calibration)
+        }
+        Command::Info => Report::Info(scale.status().map_err(CliError::Scale)?),
+        #[cfg(feature = "schema")]
+        Command::Schema { ref dir } => Report::Exported(crate::schema::export_schemas(dir)?),
+        Command::Watch { .. } => {
+            return Err(CliError::Usage(
+                "watch runs until interrupted and has to be started with cli::watch".into(),
+            ))
+        }
+        Command::Discover { .. } => {
+            return Err(CliError::Usage(
+                "discover looks for scales rather than using one, with cli::discover".into(),
+            ))
+        }
+        Command::Calibration { .. } => {
+            return Err(CliError::Usage(
+                "calibration export and import work on the config file, with cli::transfer".into(),
+            ))
+        }
+    };
+    Ok(report)
+}
+
+/// Lists the bridges attached that look like scales, waiting `timeout` ms
+/// for the phidget library to report them.
+pub fn discover(timeout: u64) -> Result<Report, CliError> {
+    crate::discovery::discover_scales(Duration::from_millis(timeout))
+        .map(Report::Discovered)
+        .map_err(|error| CliError::Scale(error.into()))
+}
+
+/// Runs `action` on the calibration of `config`, without a scale.
+/// Exporting fails with a usage error if the config has no calibration,
+/// and importing if the code cannot be read, saying why.
+pub fn transfer(action: &CalibrationAction, config: &CliConfig) -> Result<Report, CliError> {
+    match action {
+        CalibrationAction::Export => {
+            let calibration = config.calibration.ok_or_else(|| {
+                CliError::Usage(
+                    "No calibration to export; give the --config file of a calibrated scale".into(),
+                )
+            })?;
+            Ok(Report::CalibrationCode(calibration.to_compact_string()))
+        }
+        CalibrationAction::Import { code } => Calibration::from_compact_string(code)
+            .map(Report::Imported)
+            .map_err(|error| CliError::Usage(error.to_string())),
+    }
+}
+
+/// How [`WatchView`] draws each reading.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchStyle {
+    /// Rewrites one line in place, for a terminal.
+    Terminal,
+    /// One line per reading, for when stdout is a pipe or a file.
+    Lines,
+    /// One JSON object per line.
+    Json,
+}
+
+/// One reading taken by [`watch`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WatchSample {
+    /// Time since the watch started.
+    pub elapsed: Duration,
+    pub weight: Grams,
+    /// The voltage ratio of each load cell, when watching with `--raw`.
+    pub ratios: Option<[f64; NUMBER_OF_INPUTS]>,
+}
+
+/// What a watch saw, for the summary printed when it stops.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WatchSummary {
+    pub samples: usize,
+    pub min: Option<Grams>,
+    pub max: Option<Grams>,
+    pub last: Option<Grams>,
+}
+
+/// Turns the readings of a watch into output, keeping track of the stability
+/// of the weight and of its range since the start.
+#[derive(Clone, Debug)]
+pub struct WatchView {
+    style: WatchStyle,
+    raw: bool,
+    stability: StabilityDetector,
+    summary: WatchSummary,
+    last: Option<(WatchSample, bool)>,
+}
+
+impl WatchView {
+    /// A view in `style`, showing load cell ratios too if `raw` is set.
+    pub fn new(style: WatchStyle, raw: bool) -> Self {
+        Self {
+            style,
+            raw,
+            stability: StabilityDetector::default(),
+            summary: WatchSummary::default(),
+            last: None,
+        }
+    }
+
+    pub fn raw(&self) -> bool {
+        self.raw
+    }
+
+    /// Records `sample` and returns the text to write for it.
+    pub fn push(&mut self, sample: WatchSample) -> String {
+        let stable = self.stability.push(sample.weight.get());
+        let weight = sample.weight.get();
+        self.summary = WatchSummary {
+            samples: self.summary.samples + 1,
+            min: Some(Grams(
+                self.summary.min.map_or(weight, |min| min.get().min(weight)),
+            )),
+            max: Some(Grams(
+                self.summary.max.map_or(weight, |max| max.get().max(weight)),
+            )),
+            last: Some(sample.weight),
+        };
+        self.last = Some((sample, stable));
+        self.render(&sample, stable)
+    }
+
+    fn render(&self, sample: &WatchSample, stable: bool) -> String {
+        let min = self.summary.min.map_or(0., |min| min.get());
+        let max = self.summary.max.map_or(0., |max| max.get());
+        let mut line = match self.style {
+            WatchStyle::Json => {
+                let mut json = json!({
+                    "elapsed": sample.elapsed.as_secs_f64(),
+                    "weight": sample.weight,
+                    "stable": stable,
+                    "min": min,
+                    "max": max,
+                });
+                if let Some(ratios) = sample.ratios {
+                    json["ratios"] = json!(ratios);
+                }
+                return format!("{json}\n");
+            }
+            // Returns to the start of the line and clears what was there.
+            WatchStyle::Terminal => "\r\x1b[K".to_owned(),
+            WatchStyle::Lines => format!("{:>8.3} s  ", sample.elapsed.as_secs_f64()),
+        };
+        let marker = if stable { "stable" } else { "moving" };
+        let _ = write!(
+            line,
+            "{:>10.1} g  {marker}  min {min:.1} g  max {max:.1} g",
+            sample.weight.get()
+        );
+        for ratio in sample.ratios.iter().flatten() {
+            let _ = write!(line, "  {ratio:>13.9}");
+        }
+        if self.style == WatchStyle::Lines {
+            line.push('\n');
+        }
+        line
+    }
+
+    /// The text that ends the output, so that whatever is printed after the
+    /// watch starts on a line of its own.
+    pub fn finish(&self) -> &'static str {
+        match (self.style, &self.last) {
+            (WatchStyle::Terminal, Some(_)) => "\n",
+            _ => "",
+        }
+    }
+
+    /// The first line of a CSV log of this watch.
+    pub fn csv_header(&self) -> String {
+        let mut header = "elapsed_s,weight_g,stable".to_owned();
+        if self.raw {
+            for cell in 0..NUMBER_OF_INPUTS {
+                let _ = write!(header, ",ratio_{cell}");
+            }
+        }
+        header
+    }
+
+    /// The CSV log line for the last sample pushed, if there is one.
+    pub fn csv_row(&self) -> Option<String> {
+        let (sample, stable) = self.last?;
+        let mut row = format!(
+            "{:.3},{},{stable}",
+            sample.elapsed.as_secs_f64(),
+            sample.weight.get()
+        );
+        for ratio in sample.ratios.iter().flatten() {
+            let _ = write!(row, ",{ratio}");
+        }
+        Some(row)
+    }
+
+    pub fn summary(&self) -> WatchSummary {
+        self.summary
+    }
+}
+
+/// Reads `scale` every `interval` until `stop` is set, writing each reading
+/// to `out` as `view` draws it and, when there is a `log`, a CSV line to it.
+/// Stops early on the first error from the scale.
+pub fn watch<S: Scale + ?Sized>(
+    scale: &S,
+    view: &mut WatchView,
+    interval: Duration,
+    out: &mut dyn Write,
+    mut log: Option<&mut dyn Write>,
+    stop: &CancelFlag,
+) -> Result<Report, CliError> {
+    let start = Instant::now();
+    let raw = view.raw();
+    let read = || -> Result<WatchSample, CliError> {
+        let weight = scale.get_weight().map_err(CliError::Scale)?;
+        let ratios = if raw {
+            Some(scale.get_raw_readings().map_err(CliError::Scale)?)
+        } else {
+            None
+        };
+        Ok(WatchSample {
+            elapsed: start.elapsed(),
+            weight,
+            ratios,
+        })
+    };
+    let result = loop {
+        if stop.is_cancelled() {
+            break Ok(());
+        }
+        let sample = match read() {
+            Ok(sample) => sample,
+            Err(error) => break Err(error),
+        };
+        out.write_all(view.push(sample).as_bytes())?;
+        out.flush()?;
+        if let (Some(log), Some(row)) = (log.as_mut(), view.csv_row()) {
+            writeln!(log, "{row}")?;
+        }
+        if !stop.sleep(interval) {
+            break Ok(());
+        }
+    };
+    out.write_all(view.finish().as_bytes())?;
+    result.map(|()| Report::Watched(view.summary()))
+}
@@ -0,0 +1,286 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::retry::RetryPolicy;
+use crate::{ScaleCmd, ScaleResponse};
+
+/// How long a [`ScaleClient`] waits to connect again after failing to.
+pub const DEFAULT_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Commands a [`ScaleClient`] holds while it has no connection to send
+/// them on.
+const QUEUE_DEPTH: usize = 32;
+
+/// Options for [`ScaleClient`].
+#[derive(Clone, Debug)]
+pub struct ClientConfig {
+    /// A connection that nothing, not even a heartbeat, has arrived on for
+    /// this long is stale: it is closed and replaced, and its outstanding
+    /// commands fail with `TimedOut`. Set it to a few of the server's
+    /// `heartbeat_interval`s; `None` trusts a connection until it closes.
+    pub stale_after: Option<Duration>,
+    /// Waits between attempts to connect, which go on for as long as the
+    /// client lives. Commands waiting to be sent fail once `max_attempts`
+    /// attempts in a row have failed, and on each failure after that.
+    /// Defaults to failing them at once and trying again every
+    /// [`DEFAULT_RECONNECT_DELAY`].
+    pub reconnect: RetryPolicy,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            stale_after: None,
+            reconnect: RetryPolicy::fixed(1, DEFAULT_RECONNECT_DELAY),
+        }
+    }
+}
+
+/// A client for the JSON-lines protocol of [`serve_tcp`](crate::net::serve_tcp)
+/// that notices when the server goes quiet.
+///
+/// The client connects as soon as it is created and keeps a connection open
+/// on a task of its own, reconnecting whenever it is lost or goes stale, so a
+/// pulled cable is noticed without waiting on a command. Commands are sent
+/// bare and answered in order; heartbeats are counted as traffic and skipped.
+/// A command sent while there is no connection waits for the next one, or
+/// fails if that cannot be made.
+///
+/// Dropping the client closes its connection.
+pub struct ScaleClient {
+    requests: mpsc::Sender<Pending>,
+    shared: Arc<Shared>,
+    task: JoinHandle<()>,
+}
+
+struct Pending {
+    line: Vec<u8>,
+    reply: oneshot::Sender<io::Result<ScaleResponse>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    last_heard: Mutex<Option<Instant>>,
+    on_stale: Mutex<Option<Box<dyn Fn() + Send>>>,
+}
+
+impl Shared {
+    fn heard(&self) {
+        *self
+            .last_heard
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(Instant::now());
+    }
+
+    fn last_heard(&self) -> Option<Instant> {
+        *self
+            .last_heard
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn stale(&self) {
+        let on_stale = self.on_stale.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(on_stale) = &*on_stale {
+            on_stale();
+        }
+    }
+}
+
+/// Why a connection ended.
+enum Ended {
+    /// The client was dropped.
+    Dropped,
+    Lost,
+    Stale,
+}
+
+impl ScaleClient {
+    /// A client for the server at `addr`. Must be called within a tokio
+    /// runtime.
+    pub fn tcp<A>(addr: A, config: ClientConfig) -> Self
+    where
+        A: ToSocketAddrs + Clone + Send + 'static,
+    {
+        Self::new(move || TcpStream::connect(addr.clone()), config)
+    }
+
+    /// A client that reaches the server through whatever `connect` opens,
+    /// which is called for every connection. Must be called within a tokio
+    /// runtime.
+    pub fn new<C, F, T>(connect: C, config: ClientConfig) -> Self
+    where
+        C: FnMut() -> F + Send + 'static,
+        F: Future<Output = io::Result<T>> + Send + 'static,
+        T: AsyncRead + AsyncWrite + Send + 'static,
+    {
+        let (requests, queued) = mpsc::channel(QUEUE_DEPTH);
+        let shared = Arc::new(Shared::default());
+        let task = tokio::spawn(run(connect, config, queued, shared.clone()));
+        Self {
+            requests,
+            shared,
+            task,
+        }
+    }
+
+    /// Sends `cmd` and waits for the server's response.
+    pub async fn request(&self, cmd: &ScaleCmd) -> io::Result<ScaleResponse> {
+        let mut line = serde_json::to_vec(cmd).map_err(io::Error::other)?;
+        line.push(b'\n');
+        let (reply, response) = oneshot::channel();
+        let closed = || io::Error::new(io::ErrorKind::BrokenPipe, "Client has stopped");
+        self.requests
+            .send(Pending { line, reply })
+            .await
+            .map_err(|_| closed())?;
+        response.await.map_err(|_| closed())?
+    }
+
+    /// When anything last arrived from the server, or `None` if the client
+    /// has never been connected. Connecting counts.
+    pub fn last_heard(&self) -> Option<Instant> {
+        self.shared.last_heard()
+    }
+
+    /// Calls `callback` each time a connection goes stale, just before it is
+    /// replaced, instead of any callback set before. Only used when
+    /// [`ClientConfig::stale_after`] is set.
+    pub fn on_stale(&self, callback: impl Fn() + Send + 'static) {
+        *self
+            .shared
+            .on_stale
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(Box::new(callback));
+    }
+}
+
+impl Drop for ScaleClient {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn run<C, F, T>(
+    mut connect: C,
+    config: ClientConfig,
+    mut requests: mpsc::Receiver<Pending>,
+    shared: Arc<Shared>,
+) where
+    C: FnMut() -> F,
+    F: Future<Output = io::Result<T>>,
+    T: AsyncRead + AsyncWrite,
+{
+    let mut failures = 0;
+    loop {
+        let connecting = connect();
+        #[cfg(feature = "tracing")]
+        let connecting = tracing::Instrument::instrument(
+            connecting,
+            tracing::info_span!(target: "libra", "reconnect", attempt = failures + 1),
+        );
+        let stream = match connecting.await {
+            Ok(stream) => stream,
+            Err(e) => {
+                failures += 1;
+                let delay = config.reconnect.jittered_backoff(failures);
+                #[cfg(feature = "tracing")]
+                tracing::warn!(
+                    name: "reconnect_failed",
+                    target: "libra",
+                    attempt = failures,
+                    error = %e,
+                    delay_ms = delay.as_millis() as u64
+                );
+                if !config.reconnect.allows_retry_after(failures) {
+                    // Fail what is waiting now rather than hold it
+                    // indefinitely.
+                    while let Ok(pending) = requests.try_recv() {
+                        let _ = pending
+                            .reply
+                            .send(Err(io::Error::new(e.kind(), e.to_string())));
+                    }
+                }
+                tokio::time::sleep(delay).await;
+                continue;
+            }
+        };
+        failures = 0;
+        shared.heard();
+        match serve(stream, &config, &mut requests, &shared).await {
+            Ended::Dropped => return,
+            Ended::Stale => shared.stale(),
+            Ended::Lost => {}
+        }
+    }
+}
+
+/// Sends commands on `stream` and hands out its responses until it is lost,
+/// goes stale or the client is dropped.
+async fn serve<T>(
+    stream: T,
+    config: &ClientConfig,
+    requests: &mut mpsc::Receiver<Pending>,
+    shared: &Shared,
+) -> Ended
+where
+    T: AsyncRead + AsyncWrite,
+{
+    let (read, mut write) = tokio::io::split(stream);
+    let mut lines = BufReader::new(read).lines();
+    let mut waiting = VecDeque::<oneshot::Sender<io::Result<ScaleResponse>>>::new();
+    let ended = loop {
+        let stale = async {
+            match (config.stale_after, shared.last_heard()) {
+                (Some(window), Some(heard)) => tokio::time::sleep_until(heard + window).await,
+                _ => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            pending = requests.recv() => {
+                let Some(pending) = pending else {
+                    break Ended::Dropped;
+                };
+                match write.write_all(&pending.line).await {
+                    Ok(()) => waiting.push_back(pending.reply),
+                    Err(e) => {
+                        let _ = pending.reply.send(Err(e));
+                        break Ended::Lost;
+                    }
+                }
+            }
+            line = lines.next_line() => {
+                let Ok(Some(line)) = line else {
+                    break Ended::Lost;
+                };
+                shared.heard();
+                let response = match serde_json::from_str(&line) {
+                    Ok(ScaleResponse::Heartbeat { .. }) => continue,
+                    Ok(response) => Ok(response),
+                    Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+                };
+                if let Some(reply) = waiting.pop_front() {
+                    let _ = reply.send(response);
+                }
+            }
+            () = stale => break Ended::Stale,
+        }
+    };
+    for reply in waiting {
+        let error = match ended {
+            Ended::Stale => io::Error::new(io::ErrorKind::TimedOut, "Server went quiet"),
+            _ => io::Error::new(io::ErrorKind::ConnectionAborted, "Connection was lost"),
+        };
+        let _ = reply.send(Err(error));
+    }
+    ended
+}
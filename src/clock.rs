@@ -0,0 +1,69 @@
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "tokio")]
+use crate::BoxFuture;
+
+/// Where the sampling, settling and retry code tells the time and waits: the
+/// real clock with [`SystemClock`], which is what every scale uses unless
+/// given another, or a clock a test moves on by hand, such as
+/// [`ManualClock`](crate::testing::ManualClock).
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Blocks the thread for `duration`.
+    fn sleep(&self, duration: Duration);
+
+    /// Waits `duration` without blocking the thread. The default is a
+    /// `tokio::time::sleep`.
+    #[cfg(feature = "tokio")]
+    fn sleep_async(&self, duration: Duration) -> BoxFuture<'_, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// The real clock: `Instant::now` and `thread::sleep`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// The year, month and day of the `days`th day after 1970-01-01.
+pub(crate) fn civil_date(days: u64) -> (u64, u64, u64) {
+    // Howard Hinnant's days_from_civil inverse, with eras starting in March.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// `timestamp` as `2026-10-14 09:30:00 UTC`, to the second. Times before
+/// the Unix epoch read as the epoch.
+pub(crate) fn utc_timestamp(timestamp: SystemTime) -> String {
+    let seconds = timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (year, month, day) = civil_date(seconds / 86_400);
+    let time = seconds % 86_400;
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
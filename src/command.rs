@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+use crate::cancel::CancelFlag;
+use crate::sampling::{collect_batch_on, finite, finite_readings};
+use crate::scale::{check_interval, ScaleError, DEFAULT_MAX_DURATION};
+use crate::{Grams, Scale, ScaleCmd, ScaleErrorInfo, ScaleResponse, MAX_BATCH_COUNT};
+
+/// Commands with no side effects, which can share one execution when queued
+/// back to back and can interrupt a long read.
+#[cfg(feature = "tokio")]
+pub(crate) fn is_read(cmd: &ScaleCmd) -> bool {
+    matches!(
+        cmd,
+        ScaleCmd::GetWeight
+            | ScaleCmd::GetMedianWeight { .. }
+            | ScaleCmd::GetWeightBatch { .. }
+            | ScaleCmd::GetRawReadings
+            | ScaleCmd::GetRawMedians { .. }
+            | ScaleCmd::GetCalibration
+    )
+}
+
+/// Runs `cmd`, calling `between_samples` between the samples of long reads.
+pub(crate) fn execute<S: Scale>(
+    scale: &mut S,
+    cmd: ScaleCmd,
+    cancel: &CancelFlag,
+    between_samples: &mut dyn FnMut(&S),
+) -> ScaleResponse {
+    let result = match cmd {
+        ScaleCmd::Tare { samples } => scale.tare(samples).map(ScaleResponse::Tared),
+        ScaleCmd::Zero { samples } => scale.zero(samples).map(ScaleResponse::Zeroed),
+        ScaleCmd::SetCalibration(calibration) => calibration
+            .validate()
+            .map_err(Into::into)
+            .and_then(|()| scale.set_calibration(calibration))
+            .map(|()| ScaleResponse::CalibrationSet),
+        ScaleCmd::GetStatus => scale.status().map(ScaleResponse::Status),
+        ScaleCmd::Shutdown => Ok(ScaleResponse::ShutdownAck),
+        ScaleCmd::Hello { .. } => Ok(ScaleResponse::hello_ack()),
+        // Transports with a token check it before commands reach the scale.
+        ScaleCmd::Auth { .. } => Ok(ScaleResponse::Authenticated),
+        // Servers answer for their scales; a scale alone is in no list.
+        ScaleCmd::ListScales => Err(ScaleError::Unsupported("Listing scales").into()),
+        ScaleCmd::SetDataInterval { interval_ms } => scale
+            .set_data_interval(Duration::from_millis(interval_ms))
+            .map(|interval| ScaleResponse::DataIntervalSet {
+                interval_ms: interval.as_millis() as u64,
+            }),
+        cmd => return read(scale, cmd, cancel, between_samples),
+    };
+    result.unwrap_or_else(|e| ScaleResponse::Error(ScaleErrorInfo::from_dyn(&*e)))
+}
+
+/// Runs a command that `is_read`, which needs only shared access to the
+/// scale.
+pub(crate) fn read<S: Scale>(
+    scale: &S,
+    cmd: ScaleCmd,
+    cancel: &CancelFlag,
+    between_samples: &mut dyn FnMut(&S),
+) -> ScaleResponse {
+    let result = match cmd {
+        ScaleCmd::GetWeight => scale.get_weight().map(ScaleResponse::Weight),
+        ScaleCmd::GetMedianWeight { samples } => scale
+            .get_median_weight_yielding(samples, cancel, &mut || between_samples(scale))
+            .map(ScaleResponse::MedianWeight),
+        ScaleCmd::GetWeightBatch { count, .. } if count > MAX_BATCH_COUNT => {
+            Err(ScaleError::BatchTooLarge {
+                count,
+                max: MAX_BATCH_COUNT,
+            }
+            .into())
+        }
+        ScaleCmd::GetWeightBatch { count, interval_ms } => {
+            let interval = Duration::from_millis(interval_ms);
+            check_interval(interval, DEFAULT_MAX_DURATION)
+                .map_err(Into::into)
+                .and_then(|()| {
+                    collect_batch_on(&*scale.clock(), count, interval, cancel, || {
+                        between_samples(scale);
+                        scale.get_weight()
+                    })
+                })
+                .map(ScaleResponse::WeightBatch)
+        }
+        ScaleCmd::GetRawReadings => scale.get_raw_readings().map(ScaleResponse::RawReadings),
+        ScaleCmd::GetRawMedians { samples } => scale
+            .get_raw_medians(samples)
+            .map(ScaleResponse::RawMedians),
+        ScaleCmd::GetCalibration => scale.calibration().map(ScaleResponse::Calibration),
+        cmd => return ScaleResponse::InternalError(format!("{cmd:?} is not a read")),
+    };
+    result
+        .and_then(|response| Ok(finite_response(response)?))
+        .unwrap_or_else(|e| ScaleResponse::Error(ScaleErrorInfo::from_dyn(&*e)))
+}
+
+/// Fails with `ScaleError::NonFinite` for a reading that is NaN or infinite,
+/// which a scale may pass on from its hardware and JSON cannot carry.
+fn finite_response(response: ScaleResponse) -> Result<ScaleResponse, ScaleError> {
+    match response {
+        ScaleResponse::Weight(weight) => finite(weight).map(ScaleResponse::Weight),
+        ScaleResponse::MedianWeight(median) => {
+            finite(Grams(median.0)).map(|_| ScaleResponse::MedianWeight(median))
+        }
+        ScaleResponse::RawReadings(readings) => {
+            finite_readings(readings).map(ScaleResponse::RawReadings)
+        }
+        ScaleResponse::RawMedians(medians) => {
+            finite_readings(medians).map(ScaleResponse::RawMedians)
+        }
+        response => Ok(response),
+    }
+}
@@ -0,0 +1,197 @@
+use std::error::Error;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use crate::cancel::CancelFlag;
+use crate::sampling::collect_median;
+use crate::scale::{ScaleError, DEFAULT_MEDIAN_SAMPLES, DEFAULT_SAMPLE_INTERVAL};
+use crate::{Grams, MedianGrams, Scale, ScaleErrorInfo};
+
+struct Member<S> {
+    /// Locked so that members can be read from threads of their own, as
+    /// `ConnectedScale` is not `Sync`.
+    scale: Mutex<S>,
+    /// 1 for a member that adds its weight, -1 for one that takes it away.
+    sign: f64,
+}
+
+impl<S> Member<S> {
+    fn scale(&self) -> MutexGuard<'_, S> {
+        self.scale.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A scale whose weight is worked out from the weights of other scales:
+/// the sum of platforms under one load with [`sum`](Self::sum), or what one
+/// platform weighs over another with [`difference`](Self::difference).
+///
+/// Each weight reads every member at the same time, one scoped thread per
+/// member, and fails with `ScaleError::MemberFailed`, numbering the member
+/// from 0, if any member does. A median takes every sample across all the
+/// members at once, rather than a median of each, so the members are
+/// weighed together at each point of the window.
+///
+/// The tare is held here rather than in the members: `Scale::tare` takes
+/// the median of the composite weight as a tare to subtract from it, and
+/// leaves each member's own tare alone. Members that cannot be tared can so
+/// still make up a composite that can.
+pub struct CompositeScale<S> {
+    members: Vec<Member<S>>,
+    tare: f64,
+    sample_interval: Duration,
+}
+
+impl<S: Scale + Send> CompositeScale<S> {
+    /// The sum of `scales`. Fails with `ScaleError::InvalidArgument` for no
+    /// scales.
+    pub fn sum(scales: Vec<S>) -> Result<Self, ScaleError> {
+        if scales.is_empty() {
+            return Err(ScaleError::InvalidArgument(
+                "A composite scale needs at least one member".into(),
+            ));
+        }
+        Ok(Self::new(scales.into_iter().map(|scale| (scale, 1.))))
+    }
+
+    /// What `a` weighs over `b`, members 0 and 1.
+    pub fn difference(a: S, b: S) -> Self {
+        Self::new([(a, 1.), (b, -1.)])
+    }
+
+    fn new(members: impl IntoIterator<Item = (S, f64)>) -> Self {
+        Self {
+            members: members
+                .into_iter()
+                .map(|(scale, sign)| Member {
+                    scale: Mutex::new(scale),
+                    sign,
+                })
+                .collect(),
+            tare: 0.,
+            sample_interval: DEFAULT_SAMPLE_INTERVAL,
+        }
+    }
+
+    /// Spacing between the samples of a median or a tare.
+    pub fn with_sample_interval(self, sample_interval: Duration) -> Self {
+        Self {
+            sample_interval,
+            ..self
+        }
+    }
+
+    pub fn member_count(&self) -> usize {
+        self.members.len()
+    }
+
+    /// The members, in the order given.
+    pub fn into_members(self) -> Vec<S> {
+        self.members
+            .into_iter()
+            .map(|member| {
+                member
+                    .scale
+                    .into_inner()
+                    .unwrap_or_else(PoisonError::into_inner)
+            })
+            .collect()
+    }
+
+    pub fn tare_weight(&self) -> Grams {
+        Grams(self.tare)
+    }
+
+    pub fn clear_tare(&mut self) {
+        self.tare = 0.;
+    }
+
+    /// The composite weight before the tare, from every member read at once.
+    fn gross(&self) -> Result<f64, ScaleError> {
+        let readings: Vec<_> = thread::scope(|scope| {
+            let reads: Vec<_> = self
+                .members
+                .iter()
+                .map(|member| scope.spawn(move || member.scale().get_weight()))
+                .collect();
+            reads
+                .into_iter()
+                .map(|read| {
+                    read.join()
+                        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                })
+                .collect()
+        });
+        let mut gross = 0.;
+        for (index, (reading, member)) in readings.into_iter().zip(&self.members).enumerate() {
+            gross += member.sign * reading.map_err(|error| member_failed(index, error))?.0;
+        }
+        Ok(gross)
+    }
+}
+
+/// `error` from the member numbered `member`, as a `ScaleError` if it is not
+/// one already.
+fn member_failed(member: usize, error: Box<dyn Error + Send + Sync>) -> ScaleError {
+    let error = error
+        .downcast::<ScaleError>()
+        .unwrap_or_else(|error| Box::new(ScaleError::from(ScaleErrorInfo::from_dyn(&*error))));
+    ScaleError::MemberFailed { member, error }
+}
+
+impl<S: Scale + Send> Scale for CompositeScale<S> {
+    fn get_weight(&self) -> Result<Grams, Box<dyn Error + Send + Sync>> {
+        Ok(Grams(self.gross()? - self.tare))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn Error + Send + Sync>> {
+        self.get_median_weight_of(DEFAULT_MEDIAN_SAMPLES)
+    }
+
+    fn get_median_weight_cancellable(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+    ) -> Result<MedianGrams, Box<dyn Error + Send + Sync>> {
+        collect_median(samples, self.sample_interval, cancel, || self.get_weight())
+    }
+
+    fn get_median_weight_yielding(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+        between_samples: &mut dyn FnMut(),
+    ) -> Result<MedianGrams, Box<dyn Error + Send + Sync>> {
+        collect_median(samples, self.sample_interval, cancel, || {
+            between_samples();
+            self.get_weight()
+        })
+    }
+
+    /// Takes the median composite weight, ignoring any current tare, as the
+    /// new tare. The members are not tared.
+    fn tare(&mut self, samples: usize) -> Result<Grams, Box<dyn Error + Send + Sync>> {
+        let gross = collect_median(samples, self.sample_interval, &CancelFlag::new(), || {
+            self.gross().map(Grams)
+        })?;
+        self.tare = gross.get();
+        Ok(Grams(self.tare))
+    }
+
+    /// Closes every member, even after one fails to close, and fails as the
+    /// first that did.
+    fn close(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut result = Ok(());
+        for (index, member) in self.members.iter_mut().enumerate() {
+            let closed = member
+                .scale
+                .get_mut()
+                .unwrap_or_else(PoisonError::into_inner)
+                .close();
+            if let (Ok(()), Err(error)) = (&result, closed) {
+                result = Err(member_failed(index, error).into());
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,1478 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "hardware")]
+use phidget::{devices::VoltageRatioInput, Phidget, ReturnCode};
+use serde::de::{self, DeserializeOwned, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use thiserror::Error;
+use toml::de::{DeTable, DeValue};
+use toml::Spanned;
+
+use crate::calibration::Calibration;
+#[cfg(feature = "hardware")]
+use crate::registry::SerialClaim;
+#[cfg(feature = "hardware")]
+use crate::scale::{open_channels_on_port, ConnectedScale, ScaleError};
+use crate::scale::{
+    DEFAULT_MAX_DURATION, DEFAULT_MEDIAN_SAMPLES, DEFAULT_SAMPLE_INTERVAL, NUMBER_OF_INPUTS,
+};
+#[cfg(feature = "watch")]
+use crate::shared::SharedScale;
+#[cfg(feature = "hardware")]
+use crate::source::VoltageSource;
+#[cfg(feature = "hardware")]
+use crate::Grams;
+
+/// How often a [`ScaleConfig::default`] has the bridge take a reading: as
+/// fast as a phidget bridge goes.
+pub const DEFAULT_DATA_INTERVAL_MS: u64 = 8;
+/// How long a [`ScaleConfig::default`] waits for each channel to open, the
+/// phidget library's own default.
+pub const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 1000;
+
+/// How much a phidget bridge channel amplifies its load cell before
+/// reading it. A higher gain resolves lighter loads over a narrower range;
+/// a bridge starts at [`Gain128`](Self::Gain128). Written in a config file
+/// as the factor, such as `128`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "u32", into = "u32")]
+pub enum BridgeGain {
+    Gain1,
+    Gain2,
+    Gain4,
+    Gain8,
+    Gain16,
+    Gain32,
+    Gain64,
+    Gain128,
+}
+
+impl BridgeGain {
+    pub const ALL: [BridgeGain; 8] = [
+        BridgeGain::Gain1,
+        BridgeGain::Gain2,
+        BridgeGain::Gain4,
+        BridgeGain::Gain8,
+        BridgeGain::Gain16,
+        BridgeGain::Gain32,
+        BridgeGain::Gain64,
+        BridgeGain::Gain128,
+    ];
+
+    /// The factor of the gain, such as 128 for [`Gain128`](Self::Gain128).
+    pub fn factor(self) -> u32 {
+        match self {
+            BridgeGain::Gain1 => 1,
+            BridgeGain::Gain2 => 2,
+            BridgeGain::Gain4 => 4,
+            BridgeGain::Gain8 => 8,
+            BridgeGain::Gain16 => 16,
+            BridgeGain::Gain32 => 32,
+            BridgeGain::Gain64 => 64,
+            BridgeGain::Gain128 => 128,
+        }
+    }
+}
+
+impl TryFrom<u32> for BridgeGain {
+    type Error = String;
+
+    fn try_from(factor: u32) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|gain| gain.factor() == factor)
+            .ok_or_else(|| {
+                format!("{factor} is not a bridge gain, which is 1, 2, 4, 8, 16, 32, 64 or 128")
+            })
+    }
+}
+
+impl From<BridgeGain> for u32 {
+    fn from(gain: BridgeGain) -> Self {
+        gain.factor()
+    }
+}
+
+impl fmt::Display for BridgeGain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.factor())
+    }
+}
+
+/// Reads [`ScaleConfig::bridge_gain`]: one gain for every channel, such as
+/// `128`, or one for each, such as `[128, 128, 64, 64]`.
+fn gains<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<[BridgeGain; NUMBER_OF_INPUTS]>, D::Error> {
+    struct Gains;
+
+    impl<'de> Visitor<'de> for Gains {
+        type Value = Option<[BridgeGain; NUMBER_OF_INPUTS]>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "a bridge gain, or an array of {NUMBER_OF_INPUTS}")
+        }
+
+        fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(
+            self,
+            deserializer: D,
+        ) -> Result<Self::Value, D::Error> {
+            deserializer.deserialize_any(self)
+        }
+
+        fn visit_i64<E: de::Error>(self, factor: i64) -> Result<Self::Value, E> {
+            let factor = u32::try_from(factor)
+                .map_err(|_| E::custom(format!("{factor} is not a bridge gain")))?;
+            let gain = BridgeGain::try_from(factor).map_err(E::custom)?;
+            Ok(Some([gain; NUMBER_OF_INPUTS]))
+        }
+
+        fn visit_u64<E: de::Error>(self, factor: u64) -> Result<Self::Value, E> {
+            self.visit_i64(i64::try_from(factor).unwrap_or(i64::MAX))
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+            Deserialize::deserialize(de::value::SeqAccessDeserializer::new(seq)).map(Some)
+        }
+    }
+
+    deserializer.deserialize_option(Gains)
+}
+
+/// How a scale takes the medians asked of it without saying how, such as
+/// those of `Scale::get_median_weight`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplingDefaults {
+    pub median_samples: usize,
+    /// Time between the samples of a median. Zero takes them back to back.
+    pub sample_interval_ms: u64,
+}
+
+impl SamplingDefaults {
+    pub fn sample_interval(&self) -> Duration {
+        Duration::from_millis(self.sample_interval_ms)
+    }
+}
+
+impl Default for SamplingDefaults {
+    fn default() -> Self {
+        Self {
+            median_samples: DEFAULT_MEDIAN_SAMPLES,
+            sample_interval_ms: DEFAULT_SAMPLE_INTERVAL.as_millis() as u64,
+        }
+    }
+}
+
+/// Everything it takes to connect to a scale and set it up, as one value
+/// that can be read from a file, such as with
+/// [`from_toml_file`](Self::from_toml_file), and overridden from the
+/// environment. Missing fields take their defaults, and fields not known are
+/// ignored.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScaleConfig {
+    /// Serial number of the phidget bridge, or `None` for the first found.
+    pub serial: Option<i32>,
+    /// A name for the scale in logs and reports. Not sent to the phidget.
+    pub label: Option<String>,
+    /// The VINT hub port the bridge is on, or `None` for any.
+    pub hub_port: Option<i32>,
+    /// Zero offset and span of each load cell. Zero by default, which reads
+    /// nothing until the scale is calibrated.
+    pub calibration: Calibration,
+    /// How often the bridge takes a reading of each load cell.
+    pub data_interval_ms: u64,
+    /// The gain of each channel, in channel order, or `None` to leave the
+    /// bridge's own. Read from one gain for every channel, or from one for
+    /// each.
+    #[serde(deserialize_with = "gains")]
+    pub bridge_gain: Option<[BridgeGain; NUMBER_OF_INPUTS]>,
+    /// How long to wait for each channel to open.
+    pub connect_timeout_ms: u64,
+    /// The heaviest load, in grams, the scale reads; see
+    /// `ConnectedScale::set_capacity`.
+    pub capacity_g: Option<f64>,
+    pub sampling: SamplingDefaults,
+}
+
+impl Default for ScaleConfig {
+    fn default() -> Self {
+        Self {
+            serial: None,
+            label: None,
+            hub_port: None,
+            calibration: Calibration {
+                offset: 0.,
+                coefficients: [0.; NUMBER_OF_INPUTS],
+            },
+            data_interval_ms: DEFAULT_DATA_INTERVAL_MS,
+            bridge_gain: None,
+            connect_timeout_ms: DEFAULT_CONNECT_TIMEOUT_MS,
+            capacity_g: None,
+            sampling: SamplingDefaults::default(),
+        }
+    }
+}
+
+/// One thing wrong with a [`ScaleConfig`]: the key of the field, as in a
+/// config file, and what is wrong with its value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigProblem {
+    pub key: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.message)
+    }
+}
+
+/// Everything wrong with a [`ScaleConfig`], in field order.
+#[derive(Error, Clone, Debug, PartialEq, Eq)]
+#[error("Invalid scale config: {}", problem_list(.problems))]
+pub struct ConfigError {
+    pub problems: Vec<ConfigProblem>,
+}
+
+fn problem_list(problems: &[ConfigProblem]) -> String {
+    problems
+        .iter()
+        .map(ConfigProblem::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl ScaleConfig {
+    /// Checks every field, without connecting, and lists all the problems
+    /// found rather than stopping at the first.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        let mut problem = |key: &str, message: String| {
+            problems.push(ConfigProblem {
+                key: key.into(),
+                message,
+            })
+        };
+        if let Some(serial) = self.serial.filter(|serial| *serial <= 0) {
+            problem("serial", format!("{serial} is not a serial number"));
+        }
+        if self
+            .label
+            .as_ref()
+            .is_some_and(|label| label.trim().is_empty())
+        {
+            problem("label", "must not be blank".into());
+        }
+        if let Some(hub_port) = self.hub_port.filter(|port| *port < 0) {
+            problem("hub_port", format!("{hub_port} is not a hub port"));
+        }
+        if !self.calibration.offset.is_finite() {
+            problem("calibration.offset", "must be a finite number".into());
+        }
+        for (i, coefficient) in self.calibration.coefficients.iter().enumerate() {
+            if !coefficient.is_finite() {
+                problem(
+                    &format!("calibration.coefficients[{i}]"),
+                    "must be a finite number".into(),
+                );
+            }
+        }
+        if self.data_interval_ms == 0 {
+            problem("data_interval_ms", "must be at least 1".into());
+        }
+        if let Some(message) = over_limit(self.data_interval_ms) {
+            problem("data_interval_ms", message);
+        }
+        if self.connect_timeout_ms == 0 {
+            problem("connect_timeout_ms", "must be at least 1".into());
+        }
+        if let Some(message) = over_limit(self.connect_timeout_ms) {
+            problem("connect_timeout_ms", message);
+        }
+        if let Some(capacity) = self.capacity_g {
+            if !(capacity.is_finite() && capacity > 0.) {
+                problem(
+                    "capacity_g",
+                    format!("{capacity} is not a positive number of grams"),
+                );
+            }
+        }
+        if self.sampling.median_samples == 0 {
+            problem("sampling.median_samples", "must be at least 1".into());
+        }
+        if let Some(message) = over_limit(self.sampling.sample_interval_ms) {
+            problem("sampling.sample_interval_ms", message);
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError { problems })
+        }
+    }
+}
+
+/// Every key of a config file, in field order, as overridden from the
+/// environment.
+const KEYS: [&str; 11] = [
+    "serial",
+    "label",
+    "hub_port",
+    "data_interval_ms",
+    "bridge_gain",
+    "connect_timeout_ms",
+    "capacity_g",
+    "calibration.offset",
+    "calibration.coefficients",
+    "sampling.median_samples",
+    "sampling.sample_interval_ms",
+];
+
+/// A key of a config file that [`ScaleConfig::from_toml`] does not know, and
+/// so ignored: most often a typo, or a key of a newer version. A table not
+/// known is one warning, for its name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigWarning {
+    pub line: usize,
+    pub key: String,
+}
+
+impl fmt::Display for ConfigWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: unknown key `{}` ignored", self.line, self.key)
+    }
+}
+
+/// Why a [`ScaleConfig`] could not be loaded from a file or the environment.
+#[derive(Error, Debug)]
+pub enum ConfigFileError {
+    #[error("Cannot read config {}: {source}", .path.display())]
+    Read { path: PathBuf, source: io::Error },
+    /// The text is not the TOML of a config, or a value is of the wrong
+    /// type. Lines and columns count from 1.
+    #[error("{}line {line}, column {column}: {message}", in_file(.path))]
+    Parse {
+        path: Option<PathBuf>,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// An environment variable could not be read as the value of its key.
+    #[error("{variable}: {message}")]
+    Env { variable: String, message: String },
+    /// The config loaded does not [`validate`](ScaleConfig::validate). Each
+    /// problem says where its value was set, by line or by variable.
+    #[error("{}{error}", in_file(.path))]
+    Invalid {
+        path: Option<PathBuf>,
+        error: ConfigError,
+    },
+}
+
+fn in_file(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map(|path| format!("{}: ", path.display()))
+        .unwrap_or_default()
+}
+
+impl ScaleConfig {
+    /// Reads a config from TOML, with the keys of the fields at the top and
+    /// those of [`calibration`](Self::calibration) and
+    /// [`sampling`](Self::sampling) in tables of those names, as in
+    /// [`example_toml`](Self::example_toml). Keys left out take their
+    /// defaults, and keys and tables not known are ignored, each with a
+    /// warning.
+    ///
+    /// Fails with [`ConfigFileError::Parse`] at the line and column of the
+    /// first mistake, and with [`ConfigFileError::Invalid`], listing every
+    /// problem, if the config read does not [`validate`](Self::validate).
+    pub fn from_toml(toml: &str) -> Result<(Self, Vec<ConfigWarning>), ConfigFileError> {
+        Self::parse_toml(toml, None)
+    }
+
+    /// Like [`from_toml`](Self::from_toml), reading the file at `path`,
+    /// which every error names.
+    pub fn from_toml_file(
+        path: impl AsRef<Path>,
+    ) -> Result<(Self, Vec<ConfigWarning>), ConfigFileError> {
+        let path = path.as_ref();
+        let toml = std::fs::read_to_string(path).map_err(|source| ConfigFileError::Read {
+            path: path.into(),
+            source,
+        })?;
+        Self::parse_toml(&toml, Some(path))
+    }
+
+    fn parse_toml(
+        toml: &str,
+        path: Option<&Path>,
+    ) -> Result<(Self, Vec<ConfigWarning>), ConfigFileError> {
+        let parse_error = |error| parse_error(path, error);
+        let document = Document::parse(toml).map_err(parse_error)?;
+        let (config, ignored) = document.deserialize::<Self>().map_err(parse_error)?;
+        config
+            .validate()
+            .map_err(|error| ConfigFileError::Invalid {
+                path: path.map(Into::into),
+                error: set_by(error, |key| document.origin(key)),
+            })?;
+        Ok((config, document.warnings(ignored)))
+    }
+
+    /// The default config from [`with_env`](Self::with_env).
+    pub fn from_env(prefix: &str) -> Result<Self, ConfigFileError> {
+        Self::default().with_env(prefix)
+    }
+
+    /// This config with each field overridden by the environment variable,
+    /// if set, named after its key in upper case, with `_` for `.`, after
+    /// `prefix` and `_`: for a prefix of `LIBRA`, `LIBRA_SERIAL`,
+    /// `LIBRA_CALIBRATION_OFFSET`, `LIBRA_SAMPLING_MEDIAN_SAMPLES` and so on.
+    ///
+    /// Values are written as in a config file, except that a label needs no
+    /// quotes and the coefficients and gains no brackets, as in
+    /// `LIBRA_CALIBRATION_COEFFICIENTS=1.5,1.5,1.5,1.5`. An empty value unsets
+    /// an optional field: `LIBRA_SERIAL=` connects to the first bridge found
+    /// whatever the file says. Fails with [`ConfigFileError::Env`] naming
+    /// the variable of a value that cannot be read, and with
+    /// [`ConfigFileError::Invalid`] if the config overridden does not
+    /// [`validate`](Self::validate).
+    pub fn with_env(self, prefix: &str) -> Result<Self, ConfigFileError> {
+        let vars = KEYS.into_iter().filter_map(|key| {
+            let variable = variable(prefix, key);
+            let value = std::env::var_os(&variable)?;
+            Some((variable, value.to_string_lossy().into_owned()))
+        });
+        self.with_vars(prefix, vars)
+    }
+
+    /// Like [`with_env`](Self::with_env), taking the variables from `vars`
+    /// rather than the environment.
+    pub fn with_vars(
+        mut self,
+        prefix: &str,
+        vars: impl IntoIterator<Item = (impl Into<String>, impl Into<String>)>,
+    ) -> Result<Self, ConfigFileError> {
+        let vars: HashMap<String, String> = vars
+            .into_iter()
+            .map(|(name, value)| (name.into(), value.into()))
+            .collect();
+        let mut set_from = Vec::new();
+        for key in KEYS {
+            let variable = variable(prefix, key);
+            let Some(value) = vars.get(&variable).map(|value| value.trim()) else {
+                continue;
+            };
+            let value = if value.is_empty() {
+                unset(&mut self, key)
+            } else {
+                match key {
+                    "label" => Ok(toml::Value::String(value.into())),
+                    "calibration.coefficients" if !value.starts_with('[') => {
+                        format!("[{value}]").parse()
+                    }
+                    "bridge_gain" if value.contains(',') && !value.starts_with('[') => {
+                        format!("[{value}]").parse()
+                    }
+                    _ => value.parse(),
+                }
+                .map_err(|error: toml::de::Error| error.message().to_string())
+                .and_then(|value| set(&mut self, key, value))
+            };
+            value.map_err(|message| ConfigFileError::Env {
+                variable: variable.clone(),
+                message,
+            })?;
+            set_from.push((key, variable));
+        }
+
+        self.validate().map_err(|error| ConfigFileError::Invalid {
+            path: None,
+            error: set_by(error, |key| {
+                let (_, variable) = set_from.iter().find(|(set, _)| *set == key)?;
+                Some(format!("from {variable}"))
+            }),
+        })?;
+        Ok(self)
+    }
+
+    /// A config file of the defaults, with every key and what it is for,
+    /// for a new machine to start from. The optional keys are commented out.
+    pub fn example_toml() -> String {
+        let config = Self::default();
+        let coefficients = config
+            .calibration
+            .coefficients
+            .map(|coefficient| format!("{coefficient:?}"))
+            .join(", ");
+        format!(
+            "# Every key may be left out for its default, and be overridden by an\n\
+             # environment variable such as LIBRA_SERIAL or LIBRA_SAMPLING_MEDIAN_SAMPLES.\n\
+             \n\
+             # Serial number of the phidget bridge. Left out, the first found.\n\
+             # serial = 716000\n\
+             # A name for the scale in logs and reports.\n\
+             # label = \"Pass 1\"\n\
+             # The VINT hub port the bridge is on. Left out, any.\n\
+             # hub_port = 0\n\
+             # How often the bridge takes a reading of each load cell.\n\
+             data_interval_ms = {}\n\
+             # The gain of every channel, or of each in channel order, as in\n\
+             # [128, 128, 64, 64]. Left out, the bridge's own.\n\
+             # bridge_gain = 128\n\
+             # How long to wait for each channel to open.\n\
+             connect_timeout_ms = {}\n\
+             # The heaviest load the scale reads; heavier ones are refused.\n\
+             # capacity_g = 5000.0\n\
+             \n\
+             # weight = readings · coefficients - offset, in grams. All zero\n\
+             # reads nothing until the scale is calibrated.\n\
+             [calibration]\n\
+             offset = {:?}\n\
+             coefficients = [{coefficients}]\n\
+             \n\
+             # How medians are taken when the caller does not say.\n\
+             [sampling]\n\
+             median_samples = {}\n\
+             # Time between the samples of a median; 0 takes them back to back.\n\
+             sample_interval_ms = {}\n",
+            config.data_interval_ms,
+            config.connect_timeout_ms,
+            config.calibration.offset,
+            config.sampling.median_samples,
+            config.sampling.sample_interval_ms,
+        )
+    }
+
+    /// The config as a file that [`from_toml`](Self::from_toml) reads back
+    /// as it is, such as to keep the settings of a scale tuned by hand, from
+    /// `ConnectedScale::current_config`. Optional fields not set are left
+    /// out.
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("every field of a config is TOML")
+    }
+}
+
+/// A mistake at a line and column of TOML, both from 1.
+struct TomlError {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl TomlError {
+    fn new(toml: &str, error: &toml::de::Error) -> Self {
+        let (line, column) = line_column(toml, error.span().map_or(0, |span| span.start));
+        Self {
+            line,
+            column,
+            message: error.message().to_string(),
+        }
+    }
+}
+
+fn parse_error(path: Option<&Path>, error: TomlError) -> ConfigFileError {
+    ConfigFileError::Parse {
+        path: path.map(Into::into),
+        line: error.line,
+        column: error.column,
+        message: error.message,
+    }
+}
+
+/// The line and column, both from 1, of byte `at` of `text`.
+pub(crate) fn line_column(text: &str, at: usize) -> (usize, usize) {
+    let before = text.get(..at).unwrap_or(text);
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// A TOML file, and the line and column each of its keys is at.
+struct Document<'a> {
+    toml: &'a str,
+    root: Spanned<DeTable<'a>>,
+    /// By dotted key, such as `sampling.median_samples`, with a table's
+    /// at its name.
+    keys: HashMap<String, (usize, usize)>,
+}
+
+impl<'a> Document<'a> {
+    fn parse(toml: &'a str) -> Result<Self, TomlError> {
+        let root = DeTable::parse(toml).map_err(|error| TomlError::new(toml, &error))?;
+        let mut keys = HashMap::new();
+        index_keys(toml, "", root.get_ref(), &mut keys);
+        Ok(Self { toml, root, keys })
+    }
+
+    /// The file read into a `T`, and the dotted key of each key it has no
+    /// field for.
+    fn deserialize<T: DeserializeOwned>(&self) -> Result<(T, Vec<String>), TomlError> {
+        let mut ignored = Vec::new();
+        let deserializer = toml::Deserializer::from(self.root.clone());
+        let value = serde_ignored::deserialize(deserializer, |key| ignored.push(key.to_string()))
+            .map_err(|error| TomlError::new(self.toml, &error))?;
+        Ok((value, ignored))
+    }
+
+    /// The line and column of `key`, if it is in the file.
+    fn position(&self, key: &str) -> Option<(usize, usize)> {
+        self.keys.get(key).copied()
+    }
+
+    /// Where `key` was set, if it was.
+    fn origin(&self, key: &str) -> Option<String> {
+        let (line, _) = self.position(key)?;
+        Some(format!("line {line}"))
+    }
+
+    /// A warning for each of the keys `ignored`, in file order.
+    fn warnings(&self, ignored: Vec<String>) -> Vec<ConfigWarning> {
+        let mut warnings: Vec<_> = ignored
+            .into_iter()
+            .map(|key| ConfigWarning {
+                line: self.position(&key).map_or(0, |(line, _)| line),
+                key,
+            })
+            .collect();
+        warnings.sort_by_key(|warning| warning.line);
+        warnings
+    }
+
+    /// A mistake at `key`, which must be in the file.
+    fn error(&self, key: &str, message: String) -> TomlError {
+        let (line, column) = self.position(key).unwrap_or((1, 1));
+        TomlError {
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+/// Adds where each key of `table`, and of the tables in it, is to `keys`,
+/// after `prefix`.
+fn index_keys(
+    toml: &str,
+    prefix: &str,
+    table: &DeTable,
+    keys: &mut HashMap<String, (usize, usize)>,
+) {
+    for (key, value) in table.iter() {
+        let key_of = match prefix {
+            "" => key.get_ref().to_string(),
+            prefix => format!("{prefix}.{}", key.get_ref()),
+        };
+        if let DeValue::Table(table) = value.get_ref() {
+            index_keys(toml, &key_of, table, keys);
+        }
+        keys.insert(key_of, line_column(toml, key.span().start));
+    }
+}
+
+/// The environment variable of `key`, after `prefix`.
+fn variable(prefix: &str, key: &str) -> String {
+    format!("{prefix}_{}", key.replace('.', "_").to_uppercase())
+}
+
+/// Sets the field of `key` to `value`, or says what is wrong with it.
+fn set(config: &mut ScaleConfig, key: &str, value: toml::Value) -> Result<(), String> {
+    // `sampling.median_samples` is `{ sampling = { median_samples = value } }`.
+    let set = key.rsplit('.').fold(value, |value, part| {
+        toml::Value::Table(toml::Table::from_iter([(part.to_string(), value)]))
+    });
+    let current = toml::Value::try_from(&*config).map_err(|error| error.to_string())?;
+    *config = merge(current, set)
+        .try_into()
+        .map_err(|error: toml::de::Error| error.message().to_string())?;
+    Ok(())
+}
+
+/// `value` with the keys of `over` in place of its own, table by table.
+fn merge(value: toml::Value, over: toml::Value) -> toml::Value {
+    match (value, over) {
+        (toml::Value::Table(mut table), toml::Value::Table(over)) => {
+            for (key, over) in over {
+                let value = match table.remove(&key) {
+                    Some(value) => merge(value, over),
+                    None => over,
+                };
+                table.insert(key, value);
+            }
+            toml::Value::Table(table)
+        }
+        (_, over) => over,
+    }
+}
+
+/// Sets the field of `key` to `None`, if it is optional.
+fn unset(config: &mut ScaleConfig, key: &str) -> Result<(), String> {
+    match key {
+        "serial" => config.serial = None,
+        "label" => config.label = None,
+        "hub_port" => config.hub_port = None,
+        "bridge_gain" => config.bridge_gain = None,
+        "capacity_g" => config.capacity_g = None,
+        _ => return Err(format!("`{key}` must be set, and cannot be empty")),
+    }
+    Ok(())
+}
+
+/// `error` with each problem saying where the value of its key was set, by
+/// `origin`.
+fn set_by(mut error: ConfigError, origin: impl Fn(&str) -> Option<String>) -> ConfigError {
+    for problem in &mut error.problems {
+        // `calibration.coefficients[1]` is set by `calibration.coefficients`.
+        let key = problem.key.split('[').next().unwrap_or_default();
+        if let Some(origin) = origin(key) {
+            problem.message = format!("{} ({origin})", problem.message);
+        }
+    }
+    error
+}
+
+/// Why `ms` milliseconds is too long for a timeout or interval, if it is.
+fn over_limit(ms: u64) -> Option<String> {
+    (Duration::from_millis(ms) > DEFAULT_MAX_DURATION).then(|| {
+        format!("{ms} ms is over the limit of {DEFAULT_MAX_DURATION:?}; is it in the wrong unit?")
+    })
+}
+
+/// `gains` as a TOML array, such as `[128, 128, 64, 64]`.
+#[cfg(feature = "hardware")]
+fn gain_list(gains: &[BridgeGain]) -> String {
+    let gains: Vec<_> = gains.iter().map(BridgeGain::to_string).collect();
+    format!("[{}]", gains.join(", "))
+}
+
+/// A setting of a [`ScaleConfig`] that a channel of the phidget did not
+/// take as asked when connecting, as read back once set: most often a data
+/// interval the bridge clamped to the range it supports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SettingMismatch {
+    /// The key of the field, as in a config file.
+    pub key: &'static str,
+    pub channel: usize,
+    pub requested: String,
+    pub applied: String,
+}
+
+impl fmt::Display for SettingMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of Load Cell {}: asked for {}, set to {}",
+            self.key, self.channel, self.requested, self.applied
+        )
+    }
+}
+
+/// Reports `mismatch` to `tracing`, if the feature is enabled.
+#[cfg(feature = "hardware")]
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+fn setting_mismatch(mismatch: &SettingMismatch) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        name: "setting_mismatch",
+        target: "libra",
+        key = mismatch.key,
+        channel = mismatch.channel,
+        requested = %mismatch.requested,
+        applied = %mismatch.applied
+    );
+}
+
+/// A field changed by [`ConnectedScale::apply_config_update`], with its
+/// value before and after.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigChange {
+    /// The key of the field, as in a config file.
+    pub key: &'static str,
+    pub from: String,
+    pub to: String,
+}
+
+impl fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.key, self.from, self.to)
+    }
+}
+
+/// What [`ConnectedScale::apply_config_update`] changed, in field order.
+/// Empty when the scale already matched the config.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AppliedChanges {
+    pub changes: Vec<ConfigChange>,
+}
+
+impl AppliedChanges {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Whether the field of `key` was changed.
+    pub fn changed(&self, key: &str) -> bool {
+        self.changes.iter().any(|change| change.key == key)
+    }
+
+    #[cfg(feature = "hardware")]
+    fn push(&mut self, key: &'static str, from: impl fmt::Display, to: impl fmt::Display) {
+        self.changes.push(ConfigChange {
+            key,
+            from: from.to_string(),
+            to: to.to_string(),
+        });
+    }
+}
+
+impl fmt::Display for AppliedChanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.changes.is_empty() {
+            return f.write_str("no changes");
+        }
+        let changes: Vec<_> = self.changes.iter().map(ConfigChange::to_string).collect();
+        f.write_str(&changes.join("; "))
+    }
+}
+
+/// `value`, or `none`.
+#[cfg(feature = "hardware")]
+fn or_none(value: Option<impl fmt::Display>) -> String {
+    value.map_or_else(|| "none".into(), |value| value.to_string())
+}
+
+/// Watches a config file for changes by polling when it was last written,
+/// for reloading it into a running scale with
+/// [`ConnectedScale::apply_config_update`]. With the `watch` feature, a
+/// `ConfigReloader` is told of each write instead.
+#[derive(Clone, Debug)]
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    /// Watches the file at `path`. The first [`poll`](Self::poll) reads it.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            modified: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the file as [`ScaleConfig::from_toml_file`] does if it has been
+    /// written since the last poll, and returns `None` if not.
+    ///
+    /// A file that fails to load is not read again until it is next
+    /// written, so each broken version is reported once.
+    pub fn poll(&mut self) -> Result<Option<(ScaleConfig, Vec<ConfigWarning>)>, ConfigFileError> {
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|source| ConfigFileError::Read {
+                path: self.path.clone(),
+                source,
+            })?;
+        if self.modified == Some(modified) {
+            return Ok(None);
+        }
+        self.modified = Some(modified);
+        ScaleConfig::from_toml_file(&self.path).map(Some)
+    }
+}
+
+/// Why a [`ConfigReloader`] could not reload its file.
+#[cfg(feature = "watch")]
+#[derive(Error, Debug)]
+pub enum ReloadError {
+    #[error("Cannot watch config: {0}")]
+    Watch(#[from] notify::Error),
+    #[error(transparent)]
+    File(#[from] ConfigFileError),
+    /// The new version could not be applied to the scale.
+    #[error(transparent)]
+    Apply(#[from] ScaleError),
+}
+
+/// Reloads a config file into a running scale with
+/// [`ConnectedScale::apply_config_update`] each time it is written, as the
+/// operating system reports it, rather than by polling a [`ConfigWatcher`].
+///
+/// The directory of the file is watched, so that a file replaced by renaming
+/// another over it, as editors save, is still seen. Where the system reports
+/// files closed after writing, a file written in place is read once it is
+/// closed, not while it is half written, though writing it again before the
+/// last write is read may still be seen half done; renaming over it never is.
+/// A version the same as the last one read is not applied again. Watching
+/// stops when the reloader is dropped.
+#[cfg(feature = "watch")]
+pub struct ConfigReloader {
+    _watcher: notify::RecommendedWatcher,
+}
+
+#[cfg(feature = "watch")]
+impl ConfigReloader {
+    /// Watches the file at `path` for `scale`, calling `on_reload`, from the
+    /// watching thread, with what each new version changed and the warnings
+    /// reading it, or why it could not be applied. The version there now is
+    /// taken as already applied.
+    pub fn new<V: VoltageSource + Send + 'static>(
+        path: impl Into<PathBuf>,
+        scale: SharedScale<ConnectedScale<V>>,
+        mut on_reload: impl FnMut(Result<(AppliedChanges, Vec<ConfigWarning>), ReloadError>)
+            + Send
+            + 'static,
+    ) -> Result<Self, notify::Error> {
+        use notify::{RecursiveMode, Watcher};
+
+        let path = path.into();
+        let mut last = std::fs::read_to_string(&path).ok();
+        let name = path.file_name().map(ToOwned::to_owned);
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_owned(),
+            _ => PathBuf::from("."),
+        };
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(error) => return on_reload(Err(error.into())),
+                };
+                if !written(&event.kind)
+                    || !event
+                        .paths
+                        .iter()
+                        .any(|path| path.file_name() == name.as_deref())
+                {
+                    return;
+                }
+                let toml = match std::fs::read_to_string(&path) {
+                    Ok(toml) => toml,
+                    Err(source) => {
+                        let path = path.clone();
+                        return on_reload(Err(ConfigFileError::Read { path, source }.into()));
+                    }
+                };
+                if last.as_ref() == Some(&toml) {
+                    return;
+                }
+                let reload = ScaleConfig::parse_toml(&toml, Some(&path))
+                    .map_err(ReloadError::from)
+                    .and_then(|(config, warnings)| {
+                        let changes = scale.lock().apply_config_update(&config)?;
+                        Ok((changes, warnings))
+                    });
+                last = Some(toml);
+                on_reload(reload);
+            })?;
+        watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+        Ok(Self { _watcher: watcher })
+    }
+}
+
+/// Whether `kind` is a file written in full: closed after writing where the
+/// system reports it, and created or modified where not, or renamed.
+#[cfg(feature = "watch")]
+fn written(kind: &notify::EventKind) -> bool {
+    use notify::event::{AccessKind, AccessMode, ModifyKind};
+    use notify::EventKind;
+
+    match kind {
+        EventKind::Modify(ModifyKind::Name(_)) => true,
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+        EventKind::Create(_) | EventKind::Modify(_) => {
+            !cfg!(any(target_os = "linux", target_os = "android"))
+        }
+        _ => false,
+    }
+}
+
+/// The fields the scales of a [`FleetConfig`] take when they do not give
+/// their own. A scale's serial number and label are always its own.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScaleConfigDefaults {
+    pub hub_port: Option<i32>,
+    pub calibration: Option<Calibration>,
+    pub data_interval_ms: Option<u64>,
+    pub bridge_gain: Option<[BridgeGain; NUMBER_OF_INPUTS]>,
+    pub connect_timeout_ms: Option<u64>,
+    pub capacity_g: Option<f64>,
+    pub sampling: Option<SamplingDefaults>,
+}
+
+impl ScaleConfigDefaults {
+    /// [`ScaleConfig::default`] with these fields in place of its own: the
+    /// config of a scale that gives none of its own.
+    pub fn config(&self) -> ScaleConfig {
+        let config = ScaleConfig::default();
+        ScaleConfig {
+            hub_port: self.hub_port.or(config.hub_port),
+            calibration: self.calibration.unwrap_or(config.calibration),
+            data_interval_ms: self.data_interval_ms.unwrap_or(config.data_interval_ms),
+            bridge_gain: self.bridge_gain.or(config.bridge_gain),
+            connect_timeout_ms: self.connect_timeout_ms.unwrap_or(config.connect_timeout_ms),
+            capacity_g: self.capacity_g.or(config.capacity_g),
+            sampling: self.sampling.unwrap_or(config.sampling),
+            ..config
+        }
+    }
+
+    /// The fields of `config` that `set` says were set, by key.
+    fn from_keys(config: &ScaleConfig, set: impl Fn(&str) -> bool) -> Self {
+        Self {
+            hub_port: config.hub_port.filter(|_| set("hub_port")),
+            calibration: set("calibration").then_some(config.calibration),
+            data_interval_ms: set("data_interval_ms").then_some(config.data_interval_ms),
+            bridge_gain: config.bridge_gain.filter(|_| set("bridge_gain")),
+            connect_timeout_ms: set("connect_timeout_ms").then_some(config.connect_timeout_ms),
+            capacity_g: config.capacity_g.filter(|_| set("capacity_g")),
+            sampling: set("sampling").then_some(config.sampling),
+        }
+    }
+}
+
+/// Several scales run together, each by name, such as the hoppers and check
+/// weigher of one machine.
+///
+/// In a file, as read by [`from_toml`](Self::from_toml), each scale is a
+/// table `[scales.<name>]` of the keys of a [`ScaleConfig`] file, with its
+/// calibration and sampling in `[scales.<name>.calibration]` and
+/// `[scales.<name>.sampling]`. Keys given in `[defaults]`, and its
+/// `[defaults.calibration]` and `[defaults.sampling]`, are taken by every
+/// scale that does not give its own:
+///
+/// ```toml
+/// [defaults]
+/// data_interval_ms = 16
+///
+/// [defaults.sampling]
+/// median_samples = 5
+///
+/// [scales.hopper_a]
+/// serial = 716000
+///
+/// [scales.catch_weigher]
+/// serial = 716002
+/// data_interval_ms = 8
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FleetConfig {
+    /// The config of each scale, with the defaults already taken.
+    pub scales: BTreeMap<String, ScaleConfig>,
+    /// What the defaults were, if given.
+    pub defaults: Option<ScaleConfigDefaults>,
+}
+
+/// The layout of a fleet's config file, with a `T` for the defaults and
+/// each scale.
+#[derive(Deserialize)]
+struct FleetFile<T> {
+    #[serde(default)]
+    defaults: Option<T>,
+    #[serde(default)]
+    scales: BTreeMap<String, T>,
+}
+
+impl FleetConfig {
+    /// Reads a fleet's config from TOML, laid out as described on
+    /// [`FleetConfig`], with whatever the defaults do not give taking the
+    /// defaults of a [`ScaleConfig`]. The other rules, and the errors, are
+    /// those of [`ScaleConfig::from_toml`], with each problem's key being
+    /// its key in the file, such as `scales.hopper_a.capacity_g`. Setting a
+    /// serial number or label in `[defaults]` is a mistake.
+    pub fn from_toml(toml: &str) -> Result<(Self, Vec<ConfigWarning>), ConfigFileError> {
+        Self::parse_toml(toml, None)
+    }
+
+    /// Like [`from_toml`](Self::from_toml), reading the file at `path`,
+    /// which every error names.
+    pub fn from_toml_file(
+        path: impl AsRef<Path>,
+    ) -> Result<(Self, Vec<ConfigWarning>), ConfigFileError> {
+        let path = path.as_ref();
+        let toml = std::fs::read_to_string(path).map_err(|source| ConfigFileError::Read {
+            path: path.into(),
+            source,
+        })?;
+        Self::parse_toml(&toml, Some(path))
+    }
+
+    /// Checks every scale, as [`ScaleConfig::validate`] does, and that no
+    /// two share a serial number or label. Each problem's key is the key in
+    /// a file, such as `scales.hopper_a.serial`.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        for (index, (name, config)) in self.scales.iter().enumerate() {
+            if let Err(error) = config.validate() {
+                problems.extend(error.problems.into_iter().map(|problem| ConfigProblem {
+                    key: format!("scales.{name}.{}", problem.key),
+                    ..problem
+                }));
+            }
+            let earlier = || self.scales.iter().take(index);
+            if let Some(serial) = config.serial {
+                if let Some((other, _)) = earlier().find(|(_, other)| other.serial == Some(serial))
+                {
+                    problems.push(ConfigProblem {
+                        key: format!("scales.{name}.serial"),
+                        message: format!("{serial} is also the serial of `{other}`"),
+                    });
+                }
+            }
+            if let Some(label) = &config.label {
+                if let Some((other, _)) =
+                    earlier().find(|(_, other)| other.label.as_ref() == Some(label))
+                {
+                    problems.push(ConfigProblem {
+                        key: format!("scales.{name}.label"),
+                        message: format!("{label:?} is also the label of `{other}`"),
+                    });
+                }
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError { problems })
+        }
+    }
+
+    fn parse_toml(
+        toml: &str,
+        path: Option<&Path>,
+    ) -> Result<(Self, Vec<ConfigWarning>), ConfigFileError> {
+        let parse_error = |error| parse_error(path, error);
+        let document = Document::parse(toml).map_err(parse_error)?;
+        for key in ["serial", "label"] {
+            if document.position(&format!("defaults.{key}")).is_some() {
+                return Err(parse_error(document.error(
+                    &format!("defaults.{key}"),
+                    format!("`{key}` cannot be a default; give it to each scale"),
+                )));
+            }
+        }
+        // Read as configs first, for the mistakes and the keys not known...
+        let (file, ignored) = document
+            .deserialize::<FleetFile<ScaleConfig>>()
+            .map_err(parse_error)?;
+        for name in file.scales.keys() {
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                return Err(parse_error(document.error(
+                    &format!("scales.{name}"),
+                    format!("{name:?} is not a scale name"),
+                )));
+            }
+        }
+        // ...then as tables, for each scale to take the defaults' keys it
+        // does not give.
+        let (tables, _) = document
+            .deserialize::<FleetFile<toml::Table>>()
+            .map_err(parse_error)?;
+        let defaults = tables.defaults.unwrap_or_default();
+        let mut scales = BTreeMap::new();
+        for (name, table) in tables.scales {
+            let merged = merge(
+                toml::Value::Table(defaults.clone()),
+                toml::Value::Table(table),
+            );
+            let config = merged.try_into().map_err(|error: toml::de::Error| {
+                parse_error(document.error(&format!("scales.{name}"), error.message().into()))
+            })?;
+            scales.insert(name, config);
+        }
+
+        let fleet = FleetConfig {
+            scales,
+            defaults: file.defaults.map(|config| {
+                ScaleConfigDefaults::from_keys(&config, |key| {
+                    document.position(&format!("defaults.{key}")).is_some()
+                })
+            }),
+        };
+        fleet.validate().map_err(|error| ConfigFileError::Invalid {
+            path: path.map(Into::into),
+            error: set_by(error, |key| {
+                let (name, key) = key.strip_prefix("scales.")?.split_once('.')?;
+                document
+                    .origin(&format!("scales.{name}.{key}"))
+                    .or_else(|| document.origin(&format!("defaults.{key}")))
+            }),
+        })?;
+        Ok((fleet, document.warnings(ignored)))
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl ScaleConfig {
+    /// Connects to the phidget bridge this config describes and sets it up:
+    /// calibration, bridge gains, data interval, capacity and sampling
+    /// defaults. The gains and data interval are set on the channels as soon
+    /// as they are open, before anything is read, and the data interval is
+    /// then read back: a channel that clamped it to the range it supports is
+    /// listed in the scale's
+    /// [`connect_mismatches`](ConnectedScale::connect_mismatches).
+    ///
+    /// Fails with `ScaleError::InvalidArgument`, listing every problem,
+    /// without opening anything if the config does not
+    /// [`validate`](Self::validate). As with
+    /// [`DisconnectedScale::connect`](crate::scale::DisconnectedScale::connect),
+    /// the phidget is claimed in the [`registry`](crate::registry), and
+    /// the channels are closed again if anything after opening them fails.
+    pub fn connect(&self) -> Result<ConnectedScale, ScaleError> {
+        self.connect_with(VoltageRatioInput::new)
+    }
+
+    /// Like [`connect`](Self::connect), opening the channels on handles
+    /// made by `new`, so that the sequence can be run against stand-ins for
+    /// the hardware.
+    pub fn connect_with<P: Phidget + VoltageSource>(
+        &self,
+        new: impl FnMut() -> P,
+    ) -> Result<ConnectedScale<P>, ScaleError> {
+        self.validate()
+            .map_err(|error| ScaleError::InvalidArgument(error.to_string()))?;
+        let timeout = Duration::from_millis(self.connect_timeout_ms);
+        let mut claim = self.serial.map(SerialClaim::claim).transpose()?;
+        let mut vins = open_channels_on_port(self.serial, self.hub_port, timeout, new)?;
+        let serial = match self.serial {
+            Some(serial) => serial,
+            None => Phidget::serial_number(&mut vins[0])
+                .map_err(|return_code| ScaleError::phidget_error(return_code, 0))?,
+        };
+
+        let mut scale = ConnectedScale::from_sources(serial, self.calibration, vins);
+        let interval = Duration::from_millis(self.data_interval_ms);
+        let set_up = (|| {
+            if claim.is_none() {
+                claim = Some(SerialClaim::claim(serial)?);
+            }
+            if let Some(gains) = self.bridge_gain {
+                scale.set_bridge_gains(gains)?;
+            }
+            scale.set_data_intervals(interval)?;
+            scale.get_data_intervals()
+        })();
+        let intervals = match set_up {
+            Ok(intervals) => intervals,
+            Err(error) => {
+                let _ = scale.close();
+                return Err(error);
+            }
+        };
+        let mismatches: Vec<_> = intervals
+            .iter()
+            .enumerate()
+            .filter(|(_, applied)| **applied != interval)
+            .map(|(channel, applied)| SettingMismatch {
+                key: "data_interval_ms",
+                channel,
+                requested: self.data_interval_ms.to_string(),
+                applied: applied.as_millis().to_string(),
+            })
+            .collect();
+        mismatches.iter().for_each(setting_mismatch);
+        scale.set_connect_mismatches(mismatches);
+        scale.set_claim(claim);
+        scale.set_hub_port(self.hub_port);
+        scale.set_capacity(self.capacity_g.map(Grams));
+        scale.set_sampling(self.sampling);
+        Ok(scale)
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl<V: VoltageSource> ConnectedScale<V> {
+    /// Brings the running scale in line with `config` without reconnecting:
+    /// the calibration, bridge gains, data interval, capacity and sampling
+    /// defaults are changed where they differ, and the changes returned.
+    /// Gains left out of the config are left as they are.
+    ///
+    /// Fails with `ScaleError::InvalidArgument`, changing nothing, if the
+    /// config does not [`validate`](ScaleConfig::validate), or names a
+    /// serial number or hub port other than the scale's, listing each one:
+    /// those take a reconnect. `label` and `connect_timeout_ms` do not
+    /// affect a connected scale, and are not compared. The gains and data
+    /// interval are set first, and if setting either fails nothing after it
+    /// is changed.
+    pub fn apply_config_update(
+        &mut self,
+        config: &ScaleConfig,
+    ) -> Result<AppliedChanges, ScaleError> {
+        config
+            .validate()
+            .map_err(|error| ScaleError::InvalidArgument(error.to_string()))?;
+        let mut fixed = Vec::new();
+        if let Some(serial) = config
+            .serial
+            .filter(|serial| *serial != self.get_phidget_id())
+        {
+            fixed.push(format!("serial ({} to {serial})", self.get_phidget_id()));
+        }
+        if config.hub_port.is_some() && config.hub_port != self.hub_port() {
+            fixed.push(format!(
+                "hub_port ({} to {})",
+                or_none(self.hub_port()),
+                or_none(config.hub_port)
+            ));
+        }
+        if !fixed.is_empty() {
+            return Err(ScaleError::InvalidArgument(format!(
+                "Cannot change {} without reconnecting the scale",
+                fixed.join(", ")
+            )));
+        }
+
+        let mut applied = AppliedChanges::default();
+        if let Some(gains) = config.bridge_gain {
+            let current = self.get_bridge_gains()?;
+            if current != gains {
+                self.set_bridge_gains(gains)?;
+                applied.push("bridge_gain", gain_list(&current), gain_list(&gains));
+            }
+        }
+        let interval = Duration::from_millis(config.data_interval_ms);
+        let intervals = self.get_data_intervals()?;
+        if intervals.iter().any(|current| *current != interval) {
+            self.set_data_intervals(interval)?;
+            applied.push(
+                "data_interval_ms",
+                intervals[0].as_millis(),
+                config.data_interval_ms,
+            );
+        }
+        let calibration = self.calibration();
+        if calibration.offset != config.calibration.offset {
+            applied.push(
+                "calibration.offset",
+                calibration.offset,
+                config.calibration.offset,
+            );
+        }
+        if calibration.coefficients != config.calibration.coefficients {
+            applied.push(
+                "calibration.coefficients",
+                format!("{:?}", calibration.coefficients),
+                format!("{:?}", config.calibration.coefficients),
+            );
+        }
+        self.set_calibration(config.calibration);
+        let capacity = self.capacity().map(|capacity| capacity.0);
+        if capacity != config.capacity_g {
+            applied.push("capacity_g", or_none(capacity), or_none(config.capacity_g));
+            self.set_capacity(config.capacity_g.map(Grams));
+        }
+        let sampling = self.sampling();
+        if sampling.median_samples != config.sampling.median_samples {
+            applied.push(
+                "sampling.median_samples",
+                sampling.median_samples,
+                config.sampling.median_samples,
+            );
+        }
+        if sampling.sample_interval_ms != config.sampling.sample_interval_ms {
+            applied.push(
+                "sampling.sample_interval_ms",
+                sampling.sample_interval_ms,
+                config.sampling.sample_interval_ms,
+            );
+        }
+        self.set_sampling(config.sampling);
+        Ok(applied)
+    }
+
+    /// The config of the scale as it is set up now, such as to write out
+    /// with [`ScaleConfig::to_toml`] once tuned by hand: the data interval
+    /// and bridge gains read back from the phidget, Load Cell 0's interval
+    /// for all, and the rest as the scale keeps it. The gains are `None` for
+    /// sources without them. The label and connect timeout, which a
+    /// connected scale does not keep, are the defaults.
+    pub fn current_config(&mut self) -> Result<ScaleConfig, ScaleError> {
+        let interval = self.get_data_intervals()?[0];
+        let bridge_gain = match self.get_bridge_gains() {
+            Ok(gains) => gains.try_into().ok(),
+            Err(ScaleError::PhidgetError(error))
+                if matches!(error.return_code(), ReturnCode::Unsupported) =>
+            {
+                None
+            }
+            Err(error) => return Err(error),
+        };
+        Ok(ScaleConfig {
+            serial: Some(self.get_phidget_id()),
+            hub_port: self.hub_port(),
+            calibration: self.calibration(),
+            data_interval_ms: interval.as_millis() as u64,
+            bridge_gain,
+            capacity_g: self.capacity().map(|capacity| capacity.0),
+            sampling: self.sampling(),
+            ..ScaleConfig::default()
+        })
+    }
+}
+
+/// The scales of a [`FleetConfig`] that connected, by name, and why the
+/// others did not.
+#[cfg(feature = "hardware")]
+pub struct FleetConnection<V = VoltageRatioInput> {
+    pub scales: BTreeMap<String, ConnectedScale<V>>,
+    pub failures: BTreeMap<String, ScaleError>,
+}
+
+#[cfg(feature = "hardware")]
+impl<V> FleetConnection<V> {
+    /// Whether every scale connected.
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl FleetConfig {
+    /// Connects every scale, as [`ScaleConfig::connect`] does, in name
+    /// order. A scale that fails does not stop the others: it is listed
+    /// among the failures, and the rest are connected and set up.
+    pub fn connect_all(&self) -> FleetConnection {
+        self.connect_all_with(VoltageRatioInput::new)
+    }
+
+    /// Like [`connect_all`](Self::connect_all), opening the channels on
+    /// handles made by `new`, as [`ScaleConfig::connect_with`] does.
+    pub fn connect_all_with<P: Phidget + VoltageSource>(
+        &self,
+        mut new: impl FnMut() -> P,
+    ) -> FleetConnection<P> {
+        let mut connection = FleetConnection {
+            scales: BTreeMap::new(),
+            failures: BTreeMap::new(),
+        };
+        for (name, config) in &self.scales {
+            match config.connect_with(&mut new) {
+                Ok(scale) => {
+                    connection.scales.insert(name.clone(), scale);
+                }
+                Err(error) => {
+                    connection.failures.insert(name.clone(), error);
+                }
+            }
+        }
+        connection
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+use crate::{Reply, Request, ScaleCmd, ScaleResponse};
+
+/// How long [`Correlator::new`] waits for a reply before giving up on it.
+pub const DEFAULT_REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CorrelationError {
+    #[error("No reply to request {id} within {timeout:?}")]
+    TimedOut { id: u64, timeout: Duration },
+
+    #[error("Request {id} was abandoned before it was answered")]
+    Abandoned { id: u64 },
+}
+
+/// Matches [`Reply`]s to the [`Request`]s they answer, for transports that
+/// do not keep replies in order, such as MQTT or a pipelined connection.
+///
+/// [`start`](Self::start) wraps a command in a `Request` with a fresh id to
+/// be sent however the transport sends, and returns a [`PendingReply`] that
+/// resolves when whatever reads the transport hands the matching `Reply` to
+/// [`resolve`](Self::resolve). Clones share the same requests, so the sending
+/// and receiving sides can each hold one.
+///
+/// Replies that match nothing pending, because their request timed out, was
+/// already answered or was never sent, are dropped: `resolve` gives them back
+/// so they can be logged, and [`stray_replies`](Self::stray_replies) counts
+/// them.
+#[derive(Clone)]
+pub struct Correlator {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    next_id: AtomicU64,
+    stray_replies: AtomicU64,
+    pending: Mutex<HashMap<u64, oneshot::Sender<ScaleResponse>>>,
+    timeout: Duration,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, HashMap<u64, oneshot::Sender<ScaleResponse>>> {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Correlator {
+    pub fn new() -> Self {
+        Self::with_timeout(DEFAULT_REPLY_TIMEOUT)
+    }
+
+    /// A correlator whose requests time out after `timeout`.
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                next_id: AtomicU64::new(0),
+                stray_replies: AtomicU64::new(0),
+                pending: Mutex::new(HashMap::new()),
+                timeout,
+            }),
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.shared.timeout
+    }
+
+    /// Registers `cmd` under an id no pending request is using.
+    pub fn start(&self, cmd: ScaleCmd) -> (Request, PendingReply) {
+        let (reply, receiver) = oneshot::channel();
+        let mut pending = self.shared.lock();
+        let id = loop {
+            let id = self.shared.next_id.fetch_add(1, Ordering::Relaxed);
+            // Only after wrapping around, past a request that never finished.
+            if !pending.contains_key(&id) {
+                break id;
+            }
+        };
+        pending.insert(id, reply);
+        drop(pending);
+        let pending = PendingReply {
+            id,
+            receiver,
+            deadline: Box::pin(tokio::time::sleep(self.shared.timeout)),
+            shared: Arc::clone(&self.shared),
+        };
+        (
+            Request {
+                id,
+                scale: None,
+                cmd,
+            },
+            pending,
+        )
+    }
+
+    /// Completes the request `reply` answers. Returns the reply back if no
+    /// request is waiting for it.
+    pub fn resolve(&self, reply: Reply) -> Result<(), Reply> {
+        let waiting = self.shared.lock().remove(&reply.id);
+        match waiting {
+            // A caller that stopped waiting has already left the map, so a
+            // closed receiver here lost a race with it and is stray too.
+            Some(waiting) => waiting.send(reply.response).map_err(|response| Reply {
+                id: reply.id,
+                response,
+            }),
+            None => Err(reply),
+        }
+        .inspect_err(|_| {
+            self.shared.stray_replies.fetch_add(1, Ordering::Relaxed);
+        })
+    }
+
+    /// Fails every pending request with [`CorrelationError::Abandoned`], for
+    /// when the transport is lost and their replies will never come.
+    pub fn abandon_all(&self) {
+        self.shared.lock().clear();
+    }
+
+    /// Requests waiting for a reply.
+    pub fn pending(&self) -> usize {
+        self.shared.lock().len()
+    }
+
+    /// Replies that have been dropped because nothing was waiting for them.
+    pub fn stray_replies(&self) -> u64 {
+        self.shared.stray_replies.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for Correlator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The reply to a request from [`Correlator::start`], or
+/// [`CorrelationError::TimedOut`] once the correlator's timeout has passed
+/// since the request was started. Dropping it gives up on the request.
+pub struct PendingReply {
+    id: u64,
+    receiver: oneshot::Receiver<ScaleResponse>,
+    deadline: Pin<Box<tokio::time::Sleep>>,
+    shared: Arc<Shared>,
+}
+
+impl PendingReply {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Takes this request out of the correlator so a late reply is stray.
+    fn forget(&mut self) {
+        self.receiver.close();
+        // Leaves a newer request alone if the id has been reused since.
+        let mut pending = self.shared.lock();
+        if pending.get(&self.id).is_some_and(|reply| reply.is_closed()) {
+            pending.remove(&self.id);
+        }
+    }
+}
+
+impl Future for PendingReply {
+    type Output = Result<ScaleResponse, CorrelationError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let id = self.id;
+        if let Poll::Ready(response) = Pin::new(&mut self.receiver).poll(cx) {
+            return Poll::Ready(response.map_err(|_| CorrelationError::Abandoned { id }));
+        }
+        if self.deadline.as_mut().poll(cx).is_ready() {
+            self.forget();
+            return Poll::Ready(Err(CorrelationError::TimedOut {
+                id,
+                timeout: self.shared.timeout,
+            }));
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for PendingReply {
+    fn drop(&mut self) {
+        self.forget();
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::BTreeMap;
+use std::ffi::{c_void, CStr};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use phidget::{ffi, ChannelClass, ReturnCode};
+
+use crate::scale::{
+    check_timeout, DisconnectedScale, ScaleError, DEFAULT_MAX_DURATION, NUMBER_OF_INPUTS,
+};
+
+/// One channel the phidget library reports as attached, with what
+/// [`group_channels`] needs to know of its device.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttachedChannel {
+    /// Serial number of the device, or of the hub of a VINT device.
+    pub serial: i32,
+    /// The label written to the device, if any.
+    pub label: Option<String>,
+    /// The VINT hub port of a device on a hub; `None` for one on USB.
+    pub hub_port: Option<i32>,
+    /// Whether the channel is a hub port itself, in one of its port
+    /// modes, rather than a device on it.
+    pub is_hub_port_device: bool,
+    pub class: ChannelClass,
+    pub channel: i32,
+}
+
+/// Where the channels attached come from: the phidget manager with
+/// [`PhidgetManager`], or a stand-in for testing.
+pub trait ChannelManager {
+    /// Every channel attached, as reported within `timeout` of asking.
+    fn attached_channels(&mut self, timeout: Duration) -> Result<Vec<AttachedChannel>, ScaleError>;
+}
+
+/// A bridge found by [`discover_scales`], with enough voltage ratio
+/// channels for a scale.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiscoveredScale {
+    pub serial: i32,
+    /// The label written to the bridge, if any.
+    pub label: Option<String>,
+    /// The VINT hub port of a bridge on a hub; `None` for one on USB.
+    pub hub_port: Option<i32>,
+    /// The numbers of the voltage ratio channels seen, in order.
+    pub channels: Vec<i32>,
+}
+
+impl DiscoveredScale {
+    pub fn channel_count(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// The scale on this bridge, to connect with
+    /// [`DisconnectedScale::connect`].
+    pub fn into_disconnected(self) -> DisconnectedScale {
+        let scale = DisconnectedScale::new(self.serial);
+        match self.hub_port {
+            Some(hub_port) => scale.on_hub_port(hub_port),
+            None => scale,
+        }
+    }
+}
+
+/// Groups `channels` by the device they are on, keeping the devices with
+/// at least [`NUMBER_OF_INPUTS`] voltage ratio channels, ordered by serial
+/// number then hub port. Hub ports in voltage ratio mode are left out,
+/// having one channel each. A channel reported twice counts once.
+pub fn group_channels(channels: impl IntoIterator<Item = AttachedChannel>) -> Vec<DiscoveredScale> {
+    let mut tracker = DeviceTracker::new();
+    for channel in channels {
+        tracker.apply(DeviceEvent::Attached(channel));
+    }
+    tracker
+        .devices
+        .into_values()
+        .filter(|device| device.channel_count() >= NUMBER_OF_INPUTS)
+        .collect()
+}
+
+/// A channel attached or detached, as the phidget manager reports them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceEvent {
+    Attached(AttachedChannel),
+    Detached(AttachedChannel),
+}
+
+/// A bridge that has become a scale, or stopped being one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceChange {
+    /// The bridge has [`NUMBER_OF_INPUTS`] voltage ratio channels attached.
+    Available(DiscoveredScale),
+    /// A channel of a bridge that was available has been detached.
+    Lost { serial: i32, hub_port: Option<i32> },
+}
+
+/// Follows [`DeviceEvent`]s to tell when a bridge has enough channels
+/// attached to be a scale, and when it no longer has, with the channels
+/// counted as [`group_channels`] counts them.
+#[derive(Debug, Default)]
+pub struct DeviceTracker {
+    devices: BTreeMap<(i32, Option<i32>), DiscoveredScale>,
+}
+
+impl DeviceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes in `event`, and returns the change it makes, if any. A
+    /// channel attached twice, or detached without having been attached,
+    /// changes nothing.
+    pub fn apply(&mut self, event: DeviceEvent) -> Option<DeviceChange> {
+        let (DeviceEvent::Attached(channel) | DeviceEvent::Detached(channel)) = &event;
+        if channel.class != ChannelClass::VoltageRatioInput || channel.is_hub_port_device {
+            return None;
+        }
+        let key = (channel.serial, channel.hub_port);
+        match event {
+            DeviceEvent::Attached(channel) => {
+                let device = self.devices.entry(key).or_insert_with(|| DiscoveredScale {
+                    serial: channel.serial,
+                    label: None,
+                    hub_port: channel.hub_port,
+                    channels: Vec::new(),
+                });
+                if device.label.is_none() {
+                    device.label = channel.label;
+                }
+                let Err(at) = device.channels.binary_search(&channel.channel) else {
+                    return None;
+                };
+                device.channels.insert(at, channel.channel);
+                (device.channel_count() == NUMBER_OF_INPUTS)
+                    .then(|| DeviceChange::Available(device.clone()))
+            }
+            DeviceEvent::Detached(channel) => {
+                let device = self.devices.get_mut(&key)?;
+                let at = device.channels.binary_search(&channel.channel).ok()?;
+                device.channels.remove(at);
+                let lost = device.channel_count() == NUMBER_OF_INPUTS - 1;
+                if device.channels.is_empty() {
+                    self.devices.remove(&key);
+                }
+                lost.then_some(DeviceChange::Lost {
+                    serial: key.0,
+                    hub_port: key.1,
+                })
+            }
+        }
+    }
+}
+
+/// Lists the bridges attached that look like scales, as reported by the
+/// phidget manager within `timeout`, for finding a serial number without
+/// reading it off the device.
+///
+/// Fails with `ScaleError::InvalidArgument` for a `timeout` that is zero or
+/// over [`DEFAULT_MAX_DURATION`], and with a `ScaleError::PhidgetError` of
+/// Load Cell 0 if the manager cannot be opened.
+pub fn discover_scales(timeout: Duration) -> Result<Vec<DiscoveredScale>, ScaleError> {
+    discover_scales_with(&mut PhidgetManager, timeout)
+}
+
+/// Like [`discover_scales`], asking `manager` for the channels attached.
+pub fn discover_scales_with(
+    manager: &mut impl ChannelManager,
+    timeout: Duration,
+) -> Result<Vec<DiscoveredScale>, ScaleError> {
+    check_timeout(timeout, DEFAULT_MAX_DURATION)?;
+    Ok(group_channels(manager.attached_channels(timeout)?))
+}
+
+/// The phidget library's manager, which reports every channel attached as
+/// soon as it is opened, and those attached later as they are.
+///
+/// The phidget crate has no manager, so this calls the library itself.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PhidgetManager;
+
+impl PhidgetManager {
+    /// Opens a manager that calls `on_event` for every channel attached,
+    /// those already attached first, and for every one detached, until the
+    /// [`DeviceWatch`] returned is dropped. `on_event` is called on the
+    /// phidget library's own thread.
+    ///
+    /// Fails with a `ScaleError::PhidgetError` of Load Cell 0 if the manager
+    /// cannot be opened.
+    pub fn watch(
+        on_event: impl Fn(DeviceEvent) + Send + Sync + 'static,
+    ) -> Result<DeviceWatch, ScaleError> {
+        let failed = |code| ScaleError::phidget_error(ReturnCode::from(code), 0);
+        let on_event: Box<EventHandler> = Box::new(Box::new(on_event));
+        let mut manager = ptr::null_mut();
+        unsafe {
+            let code = ffi::PhidgetManager_create(&mut manager);
+            if code != 0 {
+                return Err(failed(code));
+            }
+            let context = &*on_event as *const EventHandler as *mut c_void;
+            let mut code =
+                ffi::PhidgetManager_setOnAttachHandler(manager, Some(on_attach), context);
+            if code == 0 {
+                code = ffi::PhidgetManager_setOnDetachHandler(manager, Some(on_detach), context);
+            }
+            if code == 0 {
+                code = ffi::PhidgetManager_open(manager);
+            }
+            if code != 0 {
+                ffi::PhidgetManager_delete(&mut manager);
+                return Err(failed(code));
+            }
+        }
+        Ok(DeviceWatch {
+            manager,
+            _on_event: on_event,
+        })
+    }
+}
+
+impl ChannelManager for PhidgetManager {
+    fn attached_channels(&mut self, timeout: Duration) -> Result<Vec<AttachedChannel>, ScaleError> {
+        let attached: Arc<Mutex<Vec<AttachedChannel>>> = Arc::default();
+        let watch = PhidgetManager::watch({
+            let attached = Arc::clone(&attached);
+            move |event| {
+                if let DeviceEvent::Attached(channel) = event {
+                    attached
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .push(channel);
+                }
+            }
+        })?;
+        std::thread::sleep(timeout);
+        drop(watch);
+        let attached = attached.lock().unwrap_or_else(PoisonError::into_inner);
+        Ok(attached.clone())
+    }
+}
+
+type EventHandler = Box<dyn Fn(DeviceEvent) + Send + Sync>;
+
+/// An open phidget manager from [`PhidgetManager::watch`], closed when
+/// dropped.
+pub struct DeviceWatch {
+    manager: ffi::PhidgetManagerHandle,
+    /// The context of the manager's handlers, boxed so that it stays put.
+    _on_event: Box<EventHandler>,
+}
+
+// The phidget library's handles can be used from any thread.
+unsafe impl Send for DeviceWatch {}
+
+impl Drop for DeviceWatch {
+    fn drop(&mut self) {
+        // Once closed, the manager calls the handlers no more, so
+        // `_on_event` is only dropped after the last call.
+        unsafe {
+            ffi::PhidgetManager_close(self.manager);
+            ffi::PhidgetManager_delete(&mut self.manager);
+        }
+    }
+}
+
+unsafe extern "C" fn on_attach(
+    _manager: ffi::PhidgetManagerHandle,
+    context: *mut c_void,
+    phid: ffi::PhidgetHandle,
+) {
+    notify(context, phid, DeviceEvent::Attached);
+}
+
+unsafe extern "C" fn on_detach(
+    _manager: ffi::PhidgetManagerHandle,
+    context: *mut c_void,
+    phid: ffi::PhidgetHandle,
+) {
+    notify(context, phid, DeviceEvent::Detached);
+}
+
+/// Passes the channel `phid`, as `event`, to the [`EventHandler`] at
+/// `context`. Channels whose class, number or serial number cannot be read
+/// are skipped, and a handler that panics is not let unwind into the
+/// library.
+unsafe fn notify(
+    context: *mut c_void,
+    phid: ffi::PhidgetHandle,
+    event: fn(AttachedChannel) -> DeviceEvent,
+) {
+    let on_event = &*(context as *const EventHandler);
+    if let Some(channel) = attached_channel(phid) {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| on_event(event(channel))));
+    }
+}
+
+unsafe fn attached_channel(phid: ffi::PhidgetHandle) -> Option<AttachedChannel> {
+    let mut class = 0;
+    let mut channel = 0;
+    let mut serial = 0;
+    if ffi::Phidget_getChannelClass(phid, &mut class) != 0
+        || ffi::Phidget_getChannel(phid, &mut channel) != 0
+        || ffi::Phidget_getDeviceSerialNumber(phid, &mut serial) != 0
+    {
+        return None;
+    }
+    let mut label: *const c_char = ptr::null();
+    let label = (ffi::Phidget_getDeviceLabel(phid, &mut label) == 0 && !label.is_null())
+        .then(|| CStr::from_ptr(label).to_string_lossy().into_owned())
+        .filter(|label| !label.is_empty());
+    let mut hub = ptr::null_mut();
+    let mut hub_port = 0;
+    let hub_port = (ffi::Phidget_getHub(phid, &mut hub) == 0
+        && !hub.is_null()
+        && ffi::Phidget_getHubPort(phid, &mut hub_port) == 0)
+        .then_some(hub_port);
+    let mut is_hub_port_device = 0;
+    ffi::Phidget_getIsHubPortDevice(phid, &mut is_hub_port_device);
+    Some(AttachedChannel {
+        serial,
+        label,
+        hub_port,
+        is_hub_port_device: is_hub_port_device != 0,
+        class: ChannelClass::try_from(class).ok()?,
+        channel,
+    })
+}
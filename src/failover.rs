@@ -0,0 +1,516 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::calibration::Calibration;
+use crate::cancel::CancelFlag;
+use crate::clock::Clock;
+use crate::scale::{ScaleError, NUMBER_OF_INPUTS};
+use crate::watchdog::StaleChannel;
+use crate::{Grams, MedianGrams, Scale, ScaleErrorInfo, ScaleErrorKind, ScaleStatus};
+
+/// Failures in a row of the primary after which a
+/// [`FailoverPolicy::default`] fails over.
+pub const DEFAULT_FAILOVER_THRESHOLD: u32 = 3;
+/// How long a [`FailoverPolicy::default`] waits between probes of the
+/// primary while on the backup.
+pub const DEFAULT_PROBE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What counts as the primary of a [`FailoverScale`] being unhealthy, and
+/// how often to see if it has recovered.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FailoverPolicy {
+    /// Failures in a row that fail over. 0 is taken as 1.
+    pub failure_threshold: u32,
+    /// Fail over at the first `ScaleError::CircuitOpen`, as from a primary
+    /// behind a [`CircuitBreakerScale`](crate::breaker::CircuitBreakerScale),
+    /// rather than counting it as one failure.
+    pub on_circuit_open: bool,
+    /// Fail over as soon as a watchdog catches a load cell of the primary
+    /// frozen: at a `ScaleError::StaleData`, or at a read that succeeds
+    /// while `Scale::stale_channel` has one.
+    pub on_stale: bool,
+    /// How long after failing over, or after a probe that failed, the
+    /// primary is probed again.
+    pub probe_interval: Duration,
+}
+
+impl Default for FailoverPolicy {
+    fn default() -> Self {
+        Self {
+            failure_threshold: DEFAULT_FAILOVER_THRESHOLD,
+            on_circuit_open: true,
+            on_stale: true,
+            probe_interval: DEFAULT_PROBE_INTERVAL,
+        }
+    }
+}
+
+/// One of the two scales of a [`FailoverScale`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FailoverRole {
+    Primary,
+    Backup,
+}
+
+impl fmt::Display for FailoverRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FailoverRole::Primary => "primary",
+            FailoverRole::Backup => "backup",
+        })
+    }
+}
+
+/// Why a [`FailoverScale`] switched scales.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SwitchReason {
+    /// The primary failed `failures` calls in a row, the last with
+    /// `last_error`.
+    Failures {
+        failures: u32,
+        last_error: ScaleErrorInfo,
+    },
+    /// The primary's circuit is open.
+    CircuitOpen,
+    /// A load cell of the primary has frozen.
+    Stale(StaleChannel),
+    /// A probe of the primary succeeded.
+    Recovered,
+}
+
+/// A switch of a [`FailoverScale`] from one scale to the other.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Switchover {
+    pub from: FailoverRole,
+    pub to: FailoverRole,
+    pub reason: SwitchReason,
+}
+
+struct Failover {
+    active: FailoverRole,
+    failures: u32,
+    /// When the primary is next probed, while on the backup.
+    next_probe: Option<Instant>,
+}
+
+/// What a call to the primary said of its health.
+enum Health {
+    Healthy,
+    Failing(ScaleErrorInfo),
+    Unhealthy(SwitchReason),
+}
+
+type SwitchCallback = Box<dyn Fn(&Switchover) + Send + Sync>;
+
+/// Reads from a primary scale, and from a backup while the primary is
+/// unhealthy, as the [`FailoverPolicy`] defines it, so a station with
+/// redundant platforms keeps weighing through the loss of one.
+///
+/// Reads go to the active scale. A read that makes the primary unhealthy is
+/// made again on the backup, which becomes the active scale; up to then,
+/// the primary's failures are returned as they are. Only errors that are
+/// transient or disconnections count as failures, as for a
+/// [`CircuitBreaker`](crate::breaker::CircuitBreaker). While on the backup,
+/// the first read after each `probe_interval` probes the primary with a
+/// weight read first, and fails back to it if the probe succeeds; a timer
+/// can also call [`probe`](Self::probe), and the scale actor's periodic
+/// sampling keeps reads coming. A read the backup fails is tried on the
+/// primary at once, and fails with `ScaleError::BothScalesFailed` if the
+/// primary fails it too.
+///
+/// Taring, zeroing and setting the data interval are done to both scales,
+/// the primary first, and fail with `ScaleError::FailoverMemberFailed` as
+/// soon as either fails, even when the other has already been changed: a
+/// backup tared apart from its primary would weigh differently after a
+/// switch. Each platform has its own calibration, so
+/// [`set_calibration`](Scale::set_calibration) is unsupported; set it on
+/// [`primary_mut`](Self::primary_mut) and [`backup_mut`](Self::backup_mut).
+/// The calibration, status, data interval and stale channel are the active
+/// scale's.
+pub struct FailoverScale<S> {
+    primary: S,
+    backup: S,
+    policy: FailoverPolicy,
+    failover: Mutex<Failover>,
+    on_switch: Option<SwitchCallback>,
+}
+
+impl<S: Scale> FailoverScale<S> {
+    pub fn new(primary: S, backup: S, policy: FailoverPolicy) -> Self {
+        Self {
+            primary,
+            backup,
+            policy,
+            failover: Mutex::new(Failover {
+                active: FailoverRole::Primary,
+                failures: 0,
+                next_probe: None,
+            }),
+            on_switch: None,
+        }
+    }
+
+    /// Calls `callback` on every switch of scales. It runs on the thread
+    /// that made the call causing the switch, outside the failover's lock.
+    pub fn on_switch(self, callback: impl Fn(&Switchover) + Send + Sync + 'static) -> Self {
+        Self {
+            on_switch: Some(Box::new(callback)),
+            ..self
+        }
+    }
+
+    pub fn policy(&self) -> &FailoverPolicy {
+        &self.policy
+    }
+
+    /// The scale reads come from.
+    pub fn active(&self) -> FailoverRole {
+        self.failover().active
+    }
+
+    pub fn primary(&self) -> &S {
+        &self.primary
+    }
+
+    pub fn backup(&self) -> &S {
+        &self.backup
+    }
+
+    pub fn primary_mut(&mut self) -> &mut S {
+        &mut self.primary
+    }
+
+    pub fn backup_mut(&mut self) -> &mut S {
+        &mut self.backup
+    }
+
+    /// The primary and the backup.
+    pub fn into_inner(self) -> (S, S) {
+        (self.primary, self.backup)
+    }
+
+    /// While on the backup, reads the weight of the primary, fails back to
+    /// it if the read succeeds, and puts the next probe off otherwise.
+    /// Returns whether the primary is active.
+    pub fn probe(&self) -> bool {
+        if self.active() == FailoverRole::Primary {
+            return true;
+        }
+        let result = self.primary.get_weight();
+        let recovered = result.is_ok() && matches!(self.health(&result), Health::Healthy);
+        let switch = {
+            let mut failover = self.failover();
+            if recovered {
+                failover.failures = 0;
+                failover.next_probe = None;
+                Self::switch(
+                    &mut failover,
+                    FailoverRole::Primary,
+                    SwitchReason::Recovered,
+                )
+            } else {
+                failover.next_probe = Some(Instant::now() + self.policy.probe_interval);
+                None
+            }
+        };
+        self.notify(switch);
+        recovered
+    }
+
+    fn failover(&self) -> MutexGuard<'_, Failover> {
+        self.failover.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Runs `read` on the active scale, switching as the policy says.
+    fn read<T>(
+        &self,
+        mut read: impl FnMut(&S) -> Result<T, Box<dyn Error + Send + Sync>>,
+    ) -> Result<T, Box<dyn Error + Send + Sync>> {
+        let due = {
+            let failover = self.failover();
+            failover
+                .next_probe
+                .is_some_and(|next_probe| Instant::now() >= next_probe)
+        };
+        if due {
+            self.probe();
+        }
+        if self.active() == FailoverRole::Backup {
+            return self.read_backup(&mut read, None);
+        }
+
+        let result = read(&self.primary);
+        let health = self.health(&result);
+        let switch = {
+            let mut failover = self.failover();
+            let reason = match health {
+                Health::Healthy => {
+                    failover.failures = 0;
+                    None
+                }
+                Health::Failing(last_error) => {
+                    failover.failures = failover.failures.saturating_add(1);
+                    (failover.failures >= self.policy.failure_threshold.max(1)).then(|| {
+                        SwitchReason::Failures {
+                            failures: failover.failures,
+                            last_error,
+                        }
+                    })
+                }
+                Health::Unhealthy(reason) => Some(reason),
+            };
+            let Some(reason) = reason else {
+                return result;
+            };
+            failover.failures = 0;
+            failover.next_probe = Some(Instant::now() + self.policy.probe_interval);
+            Self::switch(&mut failover, FailoverRole::Backup, reason)
+        };
+        self.notify(switch);
+        self.read_backup(&mut read, result.err())
+    }
+
+    /// Runs `read` on the backup, then on the primary if the backup fails.
+    /// `primary` is the error the primary has just failed with, if it has.
+    fn read_backup<T>(
+        &self,
+        read: &mut impl FnMut(&S) -> Result<T, Box<dyn Error + Send + Sync>>,
+        primary: Option<Box<dyn Error + Send + Sync>>,
+    ) -> Result<T, Box<dyn Error + Send + Sync>> {
+        let backup = match read(&self.backup) {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+        let primary = match primary {
+            Some(error) => error,
+            None => {
+                let result = read(&self.primary);
+                if result.is_ok() && matches!(self.health(&result), Health::Healthy) {
+                    let switch = {
+                        let mut failover = self.failover();
+                        failover.next_probe = None;
+                        Self::switch(
+                            &mut failover,
+                            FailoverRole::Primary,
+                            SwitchReason::Recovered,
+                        )
+                    };
+                    self.notify(switch);
+                    return result;
+                }
+                match result {
+                    Ok(value) => return Ok(value),
+                    Err(error) => error,
+                }
+            }
+        };
+        Err(Box::new(ScaleError::BothScalesFailed {
+            primary: Box::new(scale_error(primary)),
+            backup: Box::new(scale_error(backup)),
+        }))
+    }
+
+    /// What `result`, of a call to the primary, says of its health.
+    fn health<T>(&self, result: &Result<T, Box<dyn Error + Send + Sync>>) -> Health {
+        let error = match result {
+            Ok(_) => {
+                return match self.primary.stale_channel() {
+                    Some(stale) if self.policy.on_stale => {
+                        Health::Unhealthy(SwitchReason::Stale(stale))
+                    }
+                    _ => Health::Healthy,
+                }
+            }
+            Err(error) => error,
+        };
+        let rebuilt;
+        let error = match error.downcast_ref::<ScaleError>() {
+            Some(error) => error,
+            None => {
+                rebuilt = ScaleError::from(ScaleErrorInfo::from_dyn(&**error));
+                &rebuilt
+            }
+        };
+        match *error {
+            _ if error.kind() == ScaleErrorKind::CircuitOpen && self.policy.on_circuit_open => {
+                Health::Unhealthy(SwitchReason::CircuitOpen)
+            }
+            ScaleError::StaleData { channel, age } if self.policy.on_stale => {
+                Health::Unhealthy(SwitchReason::Stale(StaleChannel { channel, age }))
+            }
+            _ if error.is_transient() || error.is_disconnection() => {
+                Health::Failing(ScaleErrorInfo::from(error))
+            }
+            // A permanent error is an answer from a working scale.
+            _ => Health::Healthy,
+        }
+    }
+
+    fn switch(
+        failover: &mut Failover,
+        to: FailoverRole,
+        reason: SwitchReason,
+    ) -> Option<Switchover> {
+        let from = std::mem::replace(&mut failover.active, to);
+        (from != to).then_some(Switchover { from, to, reason })
+    }
+
+    fn notify(&self, switch: Option<Switchover>) {
+        if let (Some(callback), Some(switch)) = (&self.on_switch, switch) {
+            callback(&switch);
+        }
+    }
+
+    /// Runs `op` on the primary, then on the backup, failing as the first
+    /// to fail, and returns the active scale's answer.
+    fn both<T>(
+        &mut self,
+        mut op: impl FnMut(&mut S) -> Result<T, Box<dyn Error + Send + Sync>>,
+    ) -> Result<T, Box<dyn Error + Send + Sync>> {
+        let failed = |role| {
+            move |error| -> Box<dyn Error + Send + Sync> {
+                Box::new(ScaleError::FailoverMemberFailed {
+                    role,
+                    error: Box::new(scale_error(error)),
+                })
+            }
+        };
+        let primary = op(&mut self.primary).map_err(failed(FailoverRole::Primary))?;
+        let backup = op(&mut self.backup).map_err(failed(FailoverRole::Backup))?;
+        Ok(match self.active() {
+            FailoverRole::Primary => primary,
+            FailoverRole::Backup => backup,
+        })
+    }
+
+    fn active_mut(&mut self) -> &mut S {
+        match self.active() {
+            FailoverRole::Primary => &mut self.primary,
+            FailoverRole::Backup => &mut self.backup,
+        }
+    }
+
+    fn active_ref(&self) -> &S {
+        match self.active() {
+            FailoverRole::Primary => &self.primary,
+            FailoverRole::Backup => &self.backup,
+        }
+    }
+}
+
+/// `error` as a `ScaleError`, rebuilt from its description if it is not
+/// one already.
+fn scale_error(error: Box<dyn Error + Send + Sync>) -> ScaleError {
+    error
+        .downcast::<ScaleError>()
+        .map(|error| *error)
+        .unwrap_or_else(|error| ScaleError::from(ScaleErrorInfo::from_dyn(&*error)))
+}
+
+impl<S: Scale> Scale for FailoverScale<S> {
+    fn get_weight(&self) -> Result<Grams, Box<dyn Error + Send + Sync>> {
+        self.read(|scale| scale.get_weight())
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn Error + Send + Sync>> {
+        self.read(|scale| scale.get_median_weight())
+    }
+
+    fn get_median_weight_of(
+        &self,
+        samples: usize,
+    ) -> Result<MedianGrams, Box<dyn Error + Send + Sync>> {
+        self.read(|scale| scale.get_median_weight_of(samples))
+    }
+
+    fn get_median_weight_cancellable(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+    ) -> Result<MedianGrams, Box<dyn Error + Send + Sync>> {
+        self.read(|scale| scale.get_median_weight_cancellable(samples, cancel))
+    }
+
+    fn get_median_weight_yielding(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+        between_samples: &mut dyn FnMut(),
+    ) -> Result<MedianGrams, Box<dyn Error + Send + Sync>> {
+        self.read(|scale| scale.get_median_weight_yielding(samples, cancel, between_samples))
+    }
+
+    fn tare(&mut self, samples: usize) -> Result<Grams, Box<dyn Error + Send + Sync>> {
+        self.both(|scale| scale.tare(samples))
+    }
+
+    fn zero(&mut self, samples: usize) -> Result<Calibration, Box<dyn Error + Send + Sync>> {
+        self.both(|scale| scale.zero(samples))
+    }
+
+    fn get_raw_readings(&self) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn Error + Send + Sync>> {
+        self.read(|scale| scale.get_raw_readings())
+    }
+
+    fn get_raw_medians(
+        &self,
+        samples: usize,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn Error + Send + Sync>> {
+        self.read(|scale| scale.get_raw_medians(samples))
+    }
+
+    fn calibration(&self) -> Result<Calibration, Box<dyn Error + Send + Sync>> {
+        self.active_ref().calibration()
+    }
+
+    fn set_calibration(
+        &mut self,
+        _calibration: Calibration,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Err(ScaleError::Unsupported("Setting one calibration on both scales of a failover").into())
+    }
+
+    fn status(&mut self) -> Result<ScaleStatus, Box<dyn Error + Send + Sync>> {
+        self.active_mut().status()
+    }
+
+    fn data_interval(&mut self) -> Result<Duration, Box<dyn Error + Send + Sync>> {
+        self.active_mut().data_interval()
+    }
+
+    fn set_data_interval(
+        &mut self,
+        interval: Duration,
+    ) -> Result<Duration, Box<dyn Error + Send + Sync>> {
+        self.both(|scale| scale.set_data_interval(interval))
+    }
+
+    fn stale_channel(&self) -> Option<StaleChannel> {
+        self.active_ref().stale_channel()
+    }
+
+    fn clock(&self) -> Arc<dyn Clock> {
+        self.active_ref().clock()
+    }
+
+    /// Closes both scales, even after the primary fails to close, and fails
+    /// as the first that did.
+    fn close(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let primary = self.primary.close();
+        let backup = self.backup.close();
+        for (role, closed) in [
+            (FailoverRole::Primary, primary),
+            (FailoverRole::Backup, backup),
+        ] {
+            if let Err(error) = closed {
+                return Err(Box::new(ScaleError::FailoverMemberFailed {
+                    role,
+                    error: Box::new(scale_error(error)),
+                }));
+            }
+        }
+        Ok(())
+    }
+}
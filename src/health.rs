@@ -0,0 +1,561 @@
+use std::error::Error;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::sampling::finite_readings;
+use crate::scale::{
+    check_interval, ScaleError, DEFAULT_MAX_DURATION, DEFAULT_SAMPLE_INTERVAL, NUMBER_OF_INPUTS,
+};
+use crate::watchdog::StaleChannel;
+use crate::{Scale, ScaleErrorInfo, ScaleErrorKind, ScaleState, StampedWeight};
+
+/// Noise, as the standard deviation of a load cell's voltage ratio, above
+/// which a [`HealthConfig::default`] calls a scale degraded.
+pub const DEFAULT_NOISE_DEGRADED: f64 = 5e-7;
+/// Noise above which a [`HealthConfig::default`] calls a scale unusable.
+pub const DEFAULT_NOISE_UNUSABLE: f64 = 5e-6;
+/// Calibration age past which a [`HealthConfig::default`] calls a scale
+/// degraded.
+pub const DEFAULT_CALIBRATION_DEGRADED: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// What a scale does about a calibration older than its
+/// [`CalibrationPolicy`] allows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum ExpiryAction {
+    /// Weights are read as usual, but health reports are degraded and a
+    /// `calibration_expired` event is emitted.
+    Warn,
+    /// Weights fail with `ScaleError::CalibrationExpired` and health reports
+    /// are unusable. Raw readings still work, so the scale can be
+    /// recalibrated.
+    Block,
+}
+
+/// How long a calibration is good for, and what happens once it is not.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub struct CalibrationPolicy {
+    pub max_age: Duration,
+    pub action: ExpiryAction,
+}
+
+/// Where a calibration stands under a [`CalibrationPolicy`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum CalibrationExpiry {
+    /// Good for `remaining` more.
+    Valid { remaining: Duration },
+    /// Made `age` ago, `overdue` past the policy's limit.
+    Expired { age: Duration, overdue: Duration },
+    /// Dated `ahead` of the system clock, so either the clock or the date is
+    /// wrong and the age is unknown.
+    FutureDated { ahead: Duration },
+}
+
+impl CalibrationPolicy {
+    pub fn new(max_age: Duration, action: ExpiryAction) -> Self {
+        Self { max_age, action }
+    }
+
+    /// Where a calibration made at `calibrated_at` stands at `now`.
+    pub fn expiry(&self, calibrated_at: SystemTime, now: SystemTime) -> CalibrationExpiry {
+        match now.duration_since(calibrated_at) {
+            Ok(age) => match age.checked_sub(self.max_age) {
+                Some(overdue) if !overdue.is_zero() => CalibrationExpiry::Expired { age, overdue },
+                _ => CalibrationExpiry::Valid {
+                    remaining: self.max_age - age,
+                },
+            },
+            Err(error) => CalibrationExpiry::FutureDated {
+                ahead: error.duration(),
+            },
+        }
+    }
+}
+
+impl CalibrationExpiry {
+    /// Whole days until the calibration expires, rounded down, so negative
+    /// once it has: -1 during its first day expired. `None` when future
+    /// dated.
+    pub fn days_left(&self) -> Option<i64> {
+        match self {
+            CalibrationExpiry::Valid { remaining } => Some((remaining.as_secs() / DAY) as i64),
+            CalibrationExpiry::Expired { overdue, .. } => {
+                let secs = overdue.as_secs() + u64::from(overdue.subsec_nanos() > 0);
+                Some(-(secs.div_ceil(DAY) as i64))
+            }
+            CalibrationExpiry::FutureDated { .. } => None,
+        }
+    }
+}
+
+/// The thresholds a [`HealthReport`] is judged by, and how its samples are
+/// taken.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HealthConfig {
+    /// Spacing between the raw reads the noise is estimated from.
+    pub sample_interval: Duration,
+    /// Noise, as the standard deviation of a load cell's voltage ratio, above
+    /// which the scale is degraded.
+    pub noise_degraded: f64,
+    /// Noise above which the scale is unusable.
+    pub noise_unusable: f64,
+    /// Calibration age past which the scale is degraded. `None` for no limit.
+    pub calibration_degraded: Option<Duration>,
+    /// Calibration age past which the scale is unusable. `None` for no limit.
+    pub calibration_unusable: Option<Duration>,
+    /// When the calibration expires, and what that does to the status. With
+    /// `None`, a `ConnectedScale` uses its own, if it has one.
+    pub calibration_policy: Option<CalibrationPolicy>,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            sample_interval: DEFAULT_SAMPLE_INTERVAL,
+            noise_degraded: DEFAULT_NOISE_DEGRADED,
+            noise_unusable: DEFAULT_NOISE_UNUSABLE,
+            calibration_degraded: Some(DEFAULT_CALIBRATION_DEGRADED),
+            calibration_unusable: None,
+            calibration_policy: None,
+        }
+    }
+}
+
+/// Whether a scale is fit for production, from best to worst.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum Status {
+    Ok,
+    /// Weights can still be trusted, but something needs looking at soon.
+    Degraded,
+    /// Weights cannot be trusted.
+    Unusable,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Status::Ok => "ok",
+            Status::Degraded => "degraded",
+            Status::Unusable => "unusable",
+        })
+    }
+}
+
+/// One load cell's part of a [`HealthReport`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ChannelHealth {
+    pub channel: usize,
+    /// False when a read failed because the load cell is detached.
+    pub attached: bool,
+    /// Standard deviation of the voltage ratio over the samples, or `None`
+    /// when fewer than two reads succeeded.
+    pub noise: Option<f64>,
+}
+
+/// Everything that says whether a scale is fit for production right now, and
+/// the [`Status`] it adds up to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct HealthReport {
+    pub status: Status,
+    /// Why the status is not `Ok`, one line each.
+    pub reasons: Vec<String>,
+    pub channels: [ChannelHealth; NUMBER_OF_INPUTS],
+    /// Raw reads taken, and how many of them failed.
+    pub reads: usize,
+    pub failed_reads: usize,
+    /// A load cell whose readings have frozen, from the scale's watchdog.
+    pub stale: Option<StaleChannel>,
+    /// Time since the calibration was made, when the scale knows it. Zero
+    /// when it is dated in the future.
+    pub calibration_age: Option<Duration>,
+    /// Where the calibration stands under the policy judged by, if any, and
+    /// the scale knows when it was made.
+    pub calibration_expiry: Option<CalibrationExpiry>,
+    /// Whole days until the calibration expires, negative once it has; see
+    /// [`CalibrationExpiry::days_left`].
+    pub calibration_expires_in_days: Option<i64>,
+    /// Whether the calibration is dated later than the system clock, so its
+    /// age cannot be trusted.
+    pub calibration_future_dated: bool,
+    /// The last error seen, during the check or before it.
+    pub last_error: Option<ScaleErrorInfo>,
+}
+
+impl HealthReport {
+    /// Sets the status and reasons from the rest of the report.
+    fn judge(&mut self, config: &HealthConfig) {
+        let mut found = Vec::new();
+        if self.failed_reads == self.reads {
+            found.push((Status::Unusable, "Every raw read failed".to_string()));
+        } else if self.failed_reads > 0 {
+            found.push((
+                Status::Degraded,
+                format!("{} of {} raw reads failed", self.failed_reads, self.reads),
+            ));
+        }
+        for channel in &self.channels {
+            let n = channel.channel;
+            if !channel.attached {
+                found.push((Status::Unusable, format!("Load Cell {n} is detached")));
+            }
+            match channel.noise {
+                Some(noise) if noise > config.noise_unusable => found.push((
+                    Status::Unusable,
+                    format!("Load Cell {n} is too noisy ({noise:.3e})"),
+                )),
+                Some(noise) if noise > config.noise_degraded => found.push((
+                    Status::Degraded,
+                    format!("Load Cell {n} is noisy ({noise:.3e})"),
+                )),
+                _ => {}
+            }
+        }
+        if let Some(StaleChannel { channel, age }) = self.stale {
+            found.push((
+                Status::Unusable,
+                format!("Load Cell {channel} has not changed in {age:?}"),
+            ));
+        }
+        if let Some(age) = self.calibration_age {
+            if config.calibration_unusable.is_some_and(|limit| age > limit) {
+                found.push((Status::Unusable, format!("Calibration is {age:?} old")));
+            } else if config.calibration_degraded.is_some_and(|limit| age > limit) {
+                found.push((Status::Degraded, format!("Calibration is {age:?} old")));
+            }
+        }
+        if self.calibration_future_dated {
+            found.push((
+                Status::Degraded,
+                "Calibration is dated in the future; check the clock".to_string(),
+            ));
+        }
+        if let (Some(CalibrationExpiry::Expired { overdue, .. }), Some(policy)) =
+            (self.calibration_expiry, config.calibration_policy)
+        {
+            let status = match policy.action {
+                ExpiryAction::Warn => Status::Degraded,
+                ExpiryAction::Block => Status::Unusable,
+            };
+            found.push((status, format!("Calibration expired {overdue:?} ago")));
+        }
+        self.status = found
+            .iter()
+            .map(|(status, _)| *status)
+            .max()
+            .unwrap_or(Status::Ok);
+        self.reasons = found.into_iter().map(|(_, reason)| reason).collect();
+    }
+}
+
+impl fmt::Display for HealthReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Status: {}", self.status)?;
+        for reason in &self.reasons {
+            writeln!(f, "  - {reason}")?;
+        }
+        writeln!(
+            f,
+            "Reads: {} of {} succeeded",
+            self.reads - self.failed_reads,
+            self.reads
+        )?;
+        for channel in &self.channels {
+            write!(f, "Load Cell {}: ", channel.channel)?;
+            match (channel.attached, channel.noise) {
+                (false, _) => writeln!(f, "detached")?,
+                (true, Some(noise)) => writeln!(f, "attached, noise {noise:.3e}")?,
+                (true, None) => writeln!(f, "attached, noise unknown")?,
+            }
+        }
+        match self.stale {
+            Some(StaleChannel { channel, age }) => {
+                writeln!(f, "Stale data: Load Cell {channel}, unchanged for {age:?}")?
+            }
+            None => writeln!(f, "Stale data: none")?,
+        }
+        match self.calibration_age {
+            Some(_) if self.calibration_future_dated => {
+                writeln!(f, "Calibration age: unknown, dated in the future")?
+            }
+            Some(age) => writeln!(f, "Calibration age: {age:?}")?,
+            None => writeln!(f, "Calibration age: unknown")?,
+        }
+        match self.calibration_expires_in_days {
+            Some(days) if days < 0 => writeln!(f, "Calibration expired: {} days ago", -days)?,
+            Some(days) => writeln!(f, "Calibration expires: in {days} days")?,
+            None => {}
+        }
+        match &self.last_error {
+            Some(error) => write!(f, "Last error: {error}"),
+            None => write!(f, "Last error: none"),
+        }
+    }
+}
+
+/// Every scale of a `ScaleManager` at a glance, as its
+/// `fleet_status` finds them, ordered by name.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct FleetStatus {
+    /// The worst status of any scale, `Ok` for a fleet without scales. A
+    /// scale that could not be checked counts as `Unusable`.
+    pub status: Status,
+    pub scales: Vec<ScaleHealth>,
+}
+
+/// One scale's part of a [`FleetStatus`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ScaleHealth {
+    pub name: String,
+    pub state: ScaleState,
+    /// The scale's health, judged from raw reads through its actor, or why
+    /// it could not be: a `Timeout` for a scale that did not answer within
+    /// the deadline.
+    pub health: Result<HealthReport, ScaleErrorInfo>,
+    /// The latest weight of the actor's periodic sampling, if it has one.
+    pub last_weight: Option<StampedWeight>,
+    /// How old `last_weight` was when the status was taken.
+    pub last_weight_age: Option<Duration>,
+    /// Times the scale's bridge has been reattached since it was added.
+    pub reconnects: u64,
+    /// Whole days until the calibration expires, negative once it has, as
+    /// in `ScaleStatus`.
+    pub calibration_expires_in_days: Option<i64>,
+}
+
+impl ScaleHealth {
+    /// The scale's status, `Unusable` if it could not be checked.
+    pub fn status(&self) -> Status {
+        self.health
+            .as_ref()
+            .map_or(Status::Unusable, |report| report.status)
+    }
+}
+
+impl FleetStatus {
+    /// The status of `scales`, in the order given.
+    pub fn new(scales: Vec<ScaleHealth>) -> Self {
+        let status = scales
+            .iter()
+            .map(ScaleHealth::status)
+            .max()
+            .unwrap_or(Status::Ok);
+        Self { status, scales }
+    }
+}
+
+impl fmt::Display for FleetStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .scales
+            .iter()
+            .map(|scale| scale.name.chars().count())
+            .chain(["SCALE".len()])
+            .max()
+            .unwrap_or_default();
+        write!(f, "Fleet: {} ({} scales)", self.status, self.scales.len())?;
+        write!(
+            f,
+            "\n{:width$}  {:<12}  {:<11}  {:<22}  {:>10}  CALIBRATION",
+            "SCALE", "STATE", "HEALTH", "LAST WEIGHT", "RECONNECTS"
+        )?;
+        for scale in &self.scales {
+            let state = match scale.state {
+                ScaleState::Connected => "connected",
+                ScaleState::Disconnected => "disconnected",
+            };
+            let health = match &scale.health {
+                Ok(report) => report.status.to_string(),
+                Err(error) if error.kind == ScaleErrorKind::Timeout => "timeout".into(),
+                Err(_) => "error".into(),
+            };
+            let last_weight = match (scale.last_weight, scale.last_weight_age) {
+                (Some(weight), Some(age)) => {
+                    format!("{:.1} g, {:.1} s ago", weight.weight.0, age.as_secs_f64())
+                }
+                (Some(weight), None) => format!("{:.1} g", weight.weight.0),
+                (None, _) => "none".into(),
+            };
+            let calibration = match scale.calibration_expires_in_days {
+                Some(days) if days < 0 => format!("expired {} days ago", -days),
+                Some(days) => format!("expires in {days} days"),
+                None => "-".into(),
+            };
+            write!(
+                f,
+                "\n{:width$}  {state:<12}  {health:<11}  {last_weight:<22}  {:>10}  {calibration}",
+                scale.name, scale.reconnects
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Takes `samples` raw reads of `scale`, `config.sample_interval` apart, and
+/// reports on its health.
+///
+/// A read that fails, or reads NaN or infinity, does not end the check: a
+/// load cell it shows to be detached is reported so, and the error becomes
+/// the report's last error.
+/// `calibrated_at` is when the calibration in use was made, and `last_error`
+/// the last error seen before the check, if known; the calibration's expiry
+/// is judged by `config.calibration_policy`. Fails only if `samples`
+/// is below 2, or `config.sample_interval` is over [`DEFAULT_MAX_DURATION`].
+pub fn check<S: Scale + ?Sized>(
+    scale: &S,
+    samples: usize,
+    calibrated_at: Option<SystemTime>,
+    last_error: Option<ScaleErrorInfo>,
+    config: &HealthConfig,
+) -> Result<HealthReport, ScaleError> {
+    if samples < 2 {
+        return Err(ScaleError::InvalidArgument(format!(
+            "samples must be at least 2 to estimate noise, got {samples}"
+        )));
+    }
+    check_interval(config.sample_interval, DEFAULT_MAX_DURATION)?;
+    let reads = (0..samples).map(|sample| {
+        if sample > 0 {
+            thread::sleep(config.sample_interval);
+        }
+        scale.get_raw_readings()
+    });
+    Ok(assess(
+        reads,
+        scale.stale_channel(),
+        calibrated_at,
+        last_error,
+        config,
+    ))
+}
+
+/// The report on a scale whose raw reads came to `reads`, as [`check`]
+/// makes it.
+pub(crate) fn assess(
+    reads: impl IntoIterator<Item = Result<[f64; NUMBER_OF_INPUTS], Box<dyn Error + Send + Sync>>>,
+    stale: Option<StaleChannel>,
+    calibrated_at: Option<SystemTime>,
+    mut last_error: Option<ScaleErrorInfo>,
+    config: &HealthConfig,
+) -> HealthReport {
+    let mut readings: [Vec<f64>; NUMBER_OF_INPUTS] = Default::default();
+    let mut attached = [true; NUMBER_OF_INPUTS];
+    let mut failed_reads = 0;
+    let mut samples = 0;
+    for read in reads {
+        samples += 1;
+        match read.and_then(|ratios| Ok(finite_readings(ratios)?)) {
+            Ok(ratios) => {
+                for (channel, ratio) in readings.iter_mut().zip(ratios) {
+                    channel.push(ratio);
+                }
+            }
+            Err(error) => {
+                failed_reads += 1;
+                for channel in detached_channels(&*error) {
+                    attached[channel] = false;
+                }
+                last_error = Some(ScaleErrorInfo::from_dyn(&*error));
+            }
+        }
+    }
+    let channels = std::array::from_fn(|channel| ChannelHealth {
+        channel,
+        attached: attached[channel],
+        noise: standard_deviation(&readings[channel]),
+    });
+    let now = SystemTime::now();
+    let calibration_age = calibrated_at.map(|at| now.duration_since(at).unwrap_or_default());
+    let calibration_expiry = calibrated_at
+        .zip(config.calibration_policy)
+        .map(|(at, policy)| policy.expiry(at, now));
+    let mut report = HealthReport {
+        status: Status::Ok,
+        reasons: Vec::new(),
+        channels,
+        reads: samples,
+        failed_reads,
+        stale,
+        calibration_age,
+        calibration_expiry,
+        calibration_expires_in_days: calibration_expiry.and_then(|expiry| expiry.days_left()),
+        calibration_future_dated: calibrated_at.is_some_and(|at| at > now),
+        last_error,
+    };
+    report.judge(config);
+    report
+}
+
+/// Reports a calibration found `age` old, past its policy's limit, to
+/// `tracing`, if the feature is enabled.
+#[cfg(feature = "hardware")]
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn calibration_expired(age: Duration, action: ExpiryAction) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        name: "calibration_expired",
+        target: "libra",
+        age_ms = age.as_millis() as u64,
+        action = ?action
+    );
+}
+
+/// Reports a calibration dated `ahead` of the system clock to `tracing`, if
+/// the feature is enabled.
+#[cfg(feature = "hardware")]
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn calibration_future_dated(ahead: Duration) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        name: "calibration_future_dated",
+        target: "libra",
+        ahead_ms = ahead.as_millis() as u64
+    );
+}
+
+/// The load cells `error` says are detached: those of its disconnections, or
+/// every one for a disconnection of the whole scale.
+fn detached_channels(error: &(dyn std::error::Error + 'static)) -> Vec<usize> {
+    let remote;
+    let error = match error.downcast_ref::<ScaleError>() {
+        Some(error) => error,
+        None => {
+            remote = ScaleError::from(ScaleErrorInfo::from_dyn(error));
+            &remote
+        }
+    };
+    let failures = match error {
+        ScaleError::MultipleChannels(failures) => failures
+            .iter()
+            .map(|(channel, error)| (Some(*channel), error))
+            .collect(),
+        error => vec![(ScaleErrorInfo::from(error).load_cell, error)],
+    };
+    failures
+        .into_iter()
+        .filter(|(_, error)| error.is_disconnection())
+        .flat_map(|(channel, _)| match channel {
+            Some(channel) if channel < NUMBER_OF_INPUTS => channel..channel + 1,
+            _ => 0..NUMBER_OF_INPUTS,
+        })
+        .collect()
+}
+
+fn standard_deviation(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.);
+    Some(variance.sqrt())
+}
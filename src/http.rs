@@ -0,0 +1,478 @@
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::Bytes;
+use axum::extract::rejection::QueryRejection;
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, Request, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::sync::watch;
+use tokio::time::MissedTickBehavior;
+
+use crate::actor::ScaleHandle;
+use crate::auth::{AuthToken, DEFAULT_AUTH_FAILURE_DELAY};
+use crate::health::Status;
+use crate::manager::{FleetStatusSource, ScaleManager};
+use crate::scale::DEFAULT_MEDIAN_SAMPLES;
+use crate::{Grams, MedianGrams, Scale, ScaleCmd, ScaleErrorInfo, ScaleErrorKind, ScaleResponse};
+use crate::{StampedWeight, MAX_BATCH_COUNT};
+
+/// How long a request waits for the actor before it is answered with 504.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Largest `samples` accepted by the median and tare routes.
+pub const MAX_SAMPLES: usize = MAX_BATCH_COUNT;
+/// Weights per second pushed by `/weight/stream` when the client asks for no
+/// particular rate.
+pub const DEFAULT_STREAM_RATE: u32 = 5;
+/// Fastest `rate` a client of `/weight/stream` is served at.
+pub const DEFAULT_MAX_STREAM_RATE: u32 = 50;
+/// How often `/weight/stream` pings its clients.
+pub const DEFAULT_STREAM_KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// Options for [`serve_http_with_config`] and [`router`].
+#[derive(Clone, Debug)]
+pub struct HttpConfig {
+    /// Time allowed for the actor to answer, queueing included. Long medians
+    /// need a longer timeout.
+    pub request_timeout: Duration,
+    /// Higher `rate`s asked of `/weight/stream` are served at this rate.
+    pub max_stream_rate: u32,
+    /// Interval between pings on `/weight/stream`. A client that has not
+    /// answered one by the time the next is due, or that leaves a message
+    /// unsent for this long, is disconnected.
+    pub stream_keepalive: Duration,
+    /// Token every request, the WebSocket upgrade included, must carry as
+    /// `Authorization: Bearer <token>`. Requests without it are answered
+    /// with 401 after `auth_failure_delay`, and their connection is closed.
+    pub auth: Option<AuthToken>,
+    pub auth_failure_delay: Duration,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            max_stream_rate: DEFAULT_MAX_STREAM_RATE,
+            stream_keepalive: DEFAULT_STREAM_KEEPALIVE,
+            auth: None,
+            auth_failure_delay: DEFAULT_AUTH_FAILURE_DELAY,
+        }
+    }
+}
+
+/// Serves a small JSON API for the actor behind `handle` on `addr`, until
+/// the actor stops:
+///
+/// - `GET /weight`: the latest `StampedWeight` from the actor's periodic
+///   sampling. Answers 503 until the first sample, so the actor should be
+///   started with a `sample_interval`.
+/// - `GET /weight/stream?rate=5`: a WebSocket that pushes each new
+///   `StampedWeight` as a JSON text message, at most `rate` times a second.
+///   Closed with code 1001 when the actor stops.
+/// - `GET /weight/median?samples=10`: `{"weight": .., "samples": ..}`.
+/// - `POST /tare?samples=10`: `{"tare": ..}`, the weight tared off.
+/// - `GET /health`: `{"status": "ok"}`, with `"attached"` when the scale
+///   reports it. A scale with a detached channel answers 503 with
+///   `"status": "disconnected"`.
+///
+/// `samples` defaults to `DEFAULT_MEDIAN_SAMPLES` and must be between 1 and
+/// [`MAX_SAMPLES`]. Failures are answered with a JSON [`ScaleErrorInfo`]
+/// and a status that depends on its kind: 400 for bad parameters, 503 when
+/// the scale is disconnected, busy or stopped, and 504 when it does not
+/// answer in time. With an [`HttpConfig::auth`] token, requests without it
+/// are answered with 401.
+///
+/// Once the actor stops, the listener is closed and open connections are
+/// allowed to finish their current request.
+pub fn serve_http(
+    handle: ScaleHandle,
+    addr: impl ToSocketAddrs,
+) -> impl Future<Output = io::Result<()>> {
+    serve_http_with_config(handle, addr, HttpConfig::default())
+}
+
+/// Like [`serve_http`], with the options in `config`.
+pub async fn serve_http_with_config(
+    handle: ScaleHandle,
+    addr: impl ToSocketAddrs,
+    config: HttpConfig,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    serve_http_listener(handle, listener, config).await
+}
+
+/// Like [`serve_http_with_config`], on a listener that is already bound.
+pub async fn serve_http_listener(
+    handle: ScaleHandle,
+    listener: TcpListener,
+    config: HttpConfig,
+) -> io::Result<()> {
+    let stopped = handle.clone();
+    axum::serve(listener, router(handle, config))
+        .with_graceful_shutdown(async move { stopped.stopped().await })
+        .await
+}
+
+/// The routes of [`serve_http`], for mounting in a larger application.
+pub fn router(handle: ScaleHandle, config: HttpConfig) -> Router {
+    let server = Server { handle, config };
+    Router::new()
+        .route("/weight", get(weight))
+        .route("/weight/median", get(median_weight))
+        .route("/weight/stream", get(weight_stream))
+        .route("/tare", post(tare))
+        .route("/health", get(health))
+        .route_layer(middleware::from_fn_with_state(
+            server.config.clone(),
+            authorize,
+        ))
+        .with_state(server)
+}
+
+/// Serves the health of every scale of `manager` on a listener that is
+/// already bound, until the future is dropped:
+///
+/// - `GET /health`: the manager's
+///   [`fleet_status`](ScaleManager::fleet_status) as a JSON
+///   [`FleetStatus`](crate::health::FleetStatus). Answers 503 when it is
+///   `Unusable`, as it is when any scale is, or could not be checked in
+///   time.
+///
+/// Requests without the [`HttpConfig::auth`] token, if there is one, are
+/// answered with 401.
+pub fn serve_http_fleet<S: Scale + Send + 'static>(
+    manager: &ScaleManager<S>,
+    listener: TcpListener,
+    config: HttpConfig,
+) -> impl Future<Output = io::Result<()>> + Send + 'static {
+    let router = fleet_router(manager, config);
+    async move { axum::serve(listener, router).await }
+}
+
+/// The routes of [`serve_http_fleet`], for mounting in a larger
+/// application.
+pub fn fleet_router<S: Scale + Send + 'static>(
+    manager: &ScaleManager<S>,
+    config: HttpConfig,
+) -> Router {
+    Router::new()
+        .route("/health", get(fleet_health))
+        .route_layer(middleware::from_fn_with_state(config, authorize))
+        .with_state(manager.status_source())
+}
+
+/// Passes on requests carrying the configured token, if there is one.
+async fn authorize(State(config): State<HttpConfig>, request: Request, next: Next) -> Response {
+    let Some(token) = &config.auth else {
+        return next.run(request).await;
+    };
+    let message = match bearer(request.headers()) {
+        Some(candidate) if token.matches(candidate) => return next.run(request).await,
+        Some(_) => "Wrong token",
+        None => "Send the server's token as Authorization: Bearer <token>",
+    };
+    tokio::time::sleep(config.auth_failure_delay).await;
+    let error = HttpError::from(ScaleErrorInfo::new(ScaleErrorKind::Unauthorized, message));
+    (
+        [
+            (header::WWW_AUTHENTICATE, "Bearer"),
+            (header::CONNECTION, "close"),
+        ],
+        error,
+    )
+        .into_response()
+}
+
+/// The token of an `Authorization: Bearer` header.
+fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+#[derive(Clone)]
+struct Server {
+    handle: ScaleHandle,
+    config: HttpConfig,
+}
+
+impl Server {
+    /// Sends `cmd` to the actor, turning anything but a result into an
+    /// error response.
+    async fn send(&self, cmd: ScaleCmd) -> Result<ScaleResponse, HttpError> {
+        let timeout = self.config.request_timeout;
+        let Ok(response) = tokio::time::timeout(timeout, self.handle.send(cmd)).await else {
+            return Err(HttpError::timeout(timeout));
+        };
+        match response {
+            ScaleResponse::Error(info) => Err(HttpError::from(info)),
+            ScaleResponse::InternalError(message) => Err(HttpError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ScaleErrorInfo::new(ScaleErrorKind::Other, message),
+            )),
+            ScaleResponse::ShuttingDown => Err(HttpError::from(ScaleErrorInfo::new(
+                ScaleErrorKind::Stopped,
+                "Scale is shutting down",
+            ))),
+            ScaleResponse::Expired => Err(HttpError::timeout(timeout)),
+            ScaleResponse::Unsupported { command } => Err(HttpError::from(ScaleErrorInfo::new(
+                ScaleErrorKind::Unsupported,
+                format!("{command} is not supported"),
+            ))),
+            response => Ok(response),
+        }
+    }
+}
+
+/// An error response: `status`, with the error as the JSON body.
+struct HttpError(StatusCode, ScaleErrorInfo);
+
+impl HttpError {
+    fn timeout(timeout: Duration) -> Self {
+        Self::from(ScaleErrorInfo::new(
+            ScaleErrorKind::Timeout,
+            format!("Scale did not answer within {timeout:?}"),
+        ))
+    }
+
+    fn bad_parameter(message: impl Into<String>) -> Self {
+        Self::from(ScaleErrorInfo::new(ScaleErrorKind::InvalidCommand, message))
+    }
+
+    fn unexpected(response: ScaleResponse) -> Self {
+        Self(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ScaleErrorInfo::new(
+                ScaleErrorKind::Other,
+                format!("Unexpected response {response:?}"),
+            ),
+        )
+    }
+}
+
+impl From<ScaleErrorInfo> for HttpError {
+    fn from(info: ScaleErrorInfo) -> Self {
+        let status = match info.kind {
+            ScaleErrorKind::InvalidCommand
+            | ScaleErrorKind::BatchTooLarge
+            | ScaleErrorKind::InvalidCoefficients
+            | ScaleErrorKind::InvalidArgument => StatusCode::BAD_REQUEST,
+            ScaleErrorKind::Phidget
+            | ScaleErrorKind::Busy
+            | ScaleErrorKind::Cancelled
+            | ScaleErrorKind::QueueFull
+            | ScaleErrorKind::Stopped
+            | ScaleErrorKind::Disconnected
+            | ScaleErrorKind::StaleData
+            | ScaleErrorKind::CircuitOpen
+            | ScaleErrorKind::NonFinite
+            | ScaleErrorKind::OverCapacity
+            | ScaleErrorKind::CalibrationExpired => StatusCode::SERVICE_UNAVAILABLE,
+            ScaleErrorKind::NotSettled | ScaleErrorKind::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ScaleErrorKind::Unsupported => StatusCode::NOT_IMPLEMENTED,
+            ScaleErrorKind::Unauthorized => StatusCode::UNAUTHORIZED,
+            ScaleErrorKind::AlreadyConnected => StatusCode::CONFLICT,
+            ScaleErrorKind::InvalidPhidgetId
+            | ScaleErrorKind::WrongDevice
+            | ScaleErrorKind::Io
+            | ScaleErrorKind::Overflow
+            | ScaleErrorKind::Other => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self(status, info)
+    }
+}
+
+impl IntoResponse for HttpError {
+    fn into_response(self) -> Response {
+        (self.0, Json(self.1)).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct SamplesQuery {
+    samples: Option<usize>,
+}
+
+impl SamplesQuery {
+    fn samples(query: Result<Query<Self>, QueryRejection>) -> Result<usize, HttpError> {
+        let Query(query) = query.map_err(|e| HttpError::bad_parameter(e.body_text()))?;
+        match query.samples.unwrap_or(DEFAULT_MEDIAN_SAMPLES) {
+            samples @ 1..=MAX_SAMPLES => Ok(samples),
+            samples => Err(HttpError::bad_parameter(format!(
+                "samples must be between 1 and {MAX_SAMPLES}, not {samples}"
+            ))),
+        }
+    }
+}
+
+async fn weight(State(server): State<Server>) -> Result<Json<StampedWeight>, HttpError> {
+    let latest = server.handle.watch_weight();
+    // The last sample outlives the actor; don't serve it as current.
+    if latest.has_changed().is_err() {
+        return Err(HttpError::from(ScaleErrorInfo::new(
+            ScaleErrorKind::Stopped,
+            "Scale actor has stopped",
+        )));
+    }
+    let latest = *latest.borrow();
+    latest.map(Json).ok_or_else(|| {
+        HttpError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            ScaleErrorInfo::new(ScaleErrorKind::Other, "No weight has been sampled yet"),
+        )
+    })
+}
+
+#[derive(Deserialize)]
+struct StreamQuery {
+    rate: Option<u32>,
+}
+
+async fn weight_stream(
+    State(server): State<Server>,
+    query: Result<Query<StreamQuery>, QueryRejection>,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, HttpError> {
+    let Query(query) = query.map_err(|e| HttpError::bad_parameter(e.body_text()))?;
+    let rate = match query.rate.unwrap_or(DEFAULT_STREAM_RATE) {
+        0 => return Err(HttpError::bad_parameter("rate must be at least 1")),
+        rate => rate.min(server.config.max_stream_rate.max(1)),
+    };
+    let latest = server.handle.watch_weight();
+    let keepalive = server.config.stream_keepalive;
+    Ok(upgrade.on_upgrade(move |socket| stream_weights(socket, latest, rate, keepalive)))
+}
+
+/// Pushes samples from `latest` to `socket` until either side goes away.
+async fn stream_weights(
+    mut socket: WebSocket,
+    mut latest: watch::Receiver<Option<StampedWeight>>,
+    rate: u32,
+    keepalive: Duration,
+) {
+    let mut ticks = tokio::time::interval(Duration::from_secs(1) / rate);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut pings = tokio::time::interval_at(tokio::time::Instant::now() + keepalive, keepalive);
+    let mut answered = true;
+    // The sample already taken is news to this client.
+    latest.mark_changed();
+    loop {
+        let message = tokio::select! {
+            _ = ticks.tick() => match latest.has_changed() {
+                Err(_) => Message::Close(Some(CloseFrame {
+                    code: close_code::AWAY,
+                    reason: "Scale actor has stopped".into(),
+                })),
+                Ok(false) => continue,
+                Ok(true) => match *latest.borrow_and_update() {
+                    Some(sample) => match serde_json::to_string(&sample) {
+                        Ok(json) => Message::Text(json.into()),
+                        Err(_) => continue,
+                    },
+                    None => continue,
+                },
+            },
+            _ = pings.tick() => {
+                if !answered {
+                    return;
+                }
+                answered = false;
+                Message::Ping(Bytes::new())
+            }
+            received = socket.recv() => match received {
+                Some(Ok(Message::Pong(_))) => {
+                    answered = true;
+                    continue;
+                }
+                // Pings from the client are answered by `socket` itself.
+                Some(Ok(Message::Text(_) | Message::Binary(_) | Message::Ping(_))) => continue,
+                Some(Ok(Message::Close(_)) | Err(_)) | None => return,
+            },
+        };
+        let closing = matches!(message, Message::Close(_));
+        // A client that stops reading fills the socket's buffers; drop it
+        // rather than queue samples for it.
+        match tokio::time::timeout(keepalive, socket.send(message)).await {
+            Ok(Ok(())) if !closing => {}
+            _ => return,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct MedianBody {
+    weight: MedianGrams,
+    samples: usize,
+}
+
+async fn median_weight(
+    State(server): State<Server>,
+    query: Result<Query<SamplesQuery>, QueryRejection>,
+) -> Result<Json<MedianBody>, HttpError> {
+    let samples = SamplesQuery::samples(query)?;
+    match server.send(ScaleCmd::GetMedianWeight { samples }).await? {
+        ScaleResponse::MedianWeight(weight) => Ok(Json(MedianBody { weight, samples })),
+        response => Err(HttpError::unexpected(response)),
+    }
+}
+
+#[derive(Serialize)]
+struct TareBody {
+    tare: Grams,
+}
+
+async fn tare(
+    State(server): State<Server>,
+    query: Result<Query<SamplesQuery>, QueryRejection>,
+) -> Result<Json<TareBody>, HttpError> {
+    let samples = SamplesQuery::samples(query)?;
+    match server.send(ScaleCmd::Tare { samples }).await? {
+        ScaleResponse::Tared(tare) => Ok(Json(TareBody { tare })),
+        response => Err(HttpError::unexpected(response)),
+    }
+}
+
+#[derive(Serialize)]
+struct HealthBody {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attached: Option<bool>,
+}
+
+async fn health(State(server): State<Server>) -> Result<Response, HttpError> {
+    let attached = match server.send(ScaleCmd::GetStatus).await {
+        Ok(ScaleResponse::Status(status)) => Some(status.attached),
+        Ok(response) => return Err(HttpError::unexpected(response)),
+        // The actor answered, which is all a scale without a status can show.
+        Err(HttpError(_, info)) if info.kind == ScaleErrorKind::Unsupported => None,
+        Err(e) => return Err(e),
+    };
+    let (status, body) = match attached {
+        Some(false) => (StatusCode::SERVICE_UNAVAILABLE, "disconnected"),
+        _ => (StatusCode::OK, "ok"),
+    };
+    let body = HealthBody {
+        status: body,
+        attached,
+    };
+    Ok((status, Json(body)).into_response())
+}
+
+async fn fleet_health(State(source): State<Arc<dyn FleetStatusSource>>) -> Response {
+    let fleet = source.fleet_status().await;
+    let status = match fleet.status {
+        Status::Unusable => StatusCode::SERVICE_UNAVAILABLE,
+        Status::Ok | Status::Degraded => StatusCode::OK,
+    };
+    (status, Json(fleet)).into_response()
+}
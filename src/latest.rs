@@ -0,0 +1,110 @@
+use std::hint;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::{Grams, StampedWeight};
+
+/// The latest weight a sampler published, for readers that must never wait
+/// on the scale, such as a UI redrawing many times a second while another
+/// thread holds the scale for a long median.
+///
+/// Readers take no lock: a read copies the weight, its sequence number and
+/// its timestamp, and copies them again only if a publish was writing them
+/// at the same time, which takes a few stores. Each read is one whole
+/// update, never parts of two, and the newest one finished when it read, so
+/// at most one update old. Timestamps before the Unix epoch read as the
+/// epoch.
+///
+/// Clones share the same cell, so one can be handed to whatever publishes
+/// and others to any number of readers.
+#[derive(Clone, Debug, Default)]
+pub struct LatestWeight {
+    cell: Arc<Cell>,
+}
+
+/// A sequence lock over the words of a `StampedWeight`.
+#[derive(Debug, Default)]
+struct Cell {
+    /// Twice the updates finished, plus one while one is being written.
+    version: AtomicU64,
+    weight: AtomicU64,
+    sequence: AtomicU64,
+    /// Since the Unix epoch.
+    nanos: AtomicU64,
+}
+
+impl LatestWeight {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the weight readers see. Publishes from several threads take
+    /// turns.
+    pub fn publish(&self, stamped: StampedWeight) {
+        let cell = &*self.cell;
+        let mut version = cell.version.load(Ordering::Relaxed);
+        loop {
+            if version % 2 == 1 {
+                hint::spin_loop();
+                version = cell.version.load(Ordering::Relaxed);
+                continue;
+            }
+            match cell.version.compare_exchange_weak(
+                version,
+                version + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => version = current,
+            }
+        }
+        // A reader that sees any word written below sees the version moved
+        // on when it checks again.
+        fence(Ordering::Release);
+        let nanos = stamped
+            .timestamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| {
+                u64::try_from(since.as_nanos()).unwrap_or(u64::MAX)
+            });
+        cell.weight
+            .store(stamped.weight.0.to_bits(), Ordering::Relaxed);
+        cell.sequence.store(stamped.sequence, Ordering::Relaxed);
+        cell.nanos.store(nanos, Ordering::Relaxed);
+        cell.version.store(version + 2, Ordering::Release);
+    }
+
+    /// The latest weight published, or `None` before the first.
+    pub fn get(&self) -> Option<StampedWeight> {
+        let cell = &*self.cell;
+        loop {
+            let before = cell.version.load(Ordering::Acquire);
+            if before == 0 {
+                return None;
+            }
+            if before % 2 == 1 {
+                hint::spin_loop();
+                continue;
+            }
+            let weight = cell.weight.load(Ordering::Relaxed);
+            let sequence = cell.sequence.load(Ordering::Relaxed);
+            let nanos = cell.nanos.load(Ordering::Relaxed);
+            // The words were read before the version is checked again.
+            fence(Ordering::Acquire);
+            if cell.version.load(Ordering::Relaxed) == before {
+                return Some(StampedWeight {
+                    weight: Grams(f64::from_bits(weight)),
+                    sequence,
+                    timestamp: SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos),
+                });
+            }
+        }
+    }
+
+    /// Weights published so far.
+    pub fn updates(&self) -> u64 {
+        self.cell.version.load(Ordering::Acquire) / 2
+    }
+}
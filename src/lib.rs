@@ -1,8 +1,91 @@
 use serde::{Deserialize, Serialize};
 use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::calibration::Calibration;
+use crate::cancel::CancelFlag;
+use crate::clock::{Clock, SystemClock};
+use crate::health::FleetStatus;
+use crate::scale::{harder_to_recover, PhidgetError, ScaleError, NUMBER_OF_INPUTS};
+use crate::watchdog::StaleChannel;
+#[cfg(feature = "tokio")]
+pub mod actor;
+#[cfg(any(feature = "net", feature = "http"))]
+pub mod auth;
+#[cfg(feature = "binary-proto")]
+pub mod binary;
+#[cfg(feature = "tokio")]
+pub mod blocking;
+pub mod breaker;
+pub mod calibration;
+pub mod cancel;
+#[cfg(feature = "cli")]
+pub mod cli;
+#[cfg(feature = "net")]
+pub mod client;
+pub mod clock;
+mod command;
+pub mod composite;
+pub mod config;
+#[cfg(feature = "tokio")]
+pub mod correlation;
+#[cfg(feature = "hardware")]
+pub mod discovery;
+pub mod failover;
+pub mod health;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod latest;
+#[cfg(feature = "logger")]
+pub mod logger;
+#[cfg(feature = "tokio")]
+pub mod manager;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod multi;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod overflow;
+#[cfg(feature = "tokio")]
+mod queue;
+#[cfg(feature = "recording")]
+pub mod recording;
+pub mod registry;
+pub mod retry;
+pub mod sampler;
+mod sampling;
 pub mod scale;
+#[cfg(feature = "schema")]
+pub mod schema;
+pub mod scoped;
+pub mod serial;
+pub mod shared;
+#[cfg(feature = "hardware")]
+pub mod snapshot;
+pub mod soak;
+#[cfg(feature = "hardware")]
+pub mod source;
+pub mod stability;
+#[cfg(feature = "store")]
+pub mod store;
+#[cfg(feature = "tokio")]
+pub mod stream;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(feature = "tokio")]
+pub mod timeout;
+#[cfg(feature = "tracing")]
+pub mod trace;
+#[cfg(all(feature = "net", unix))]
+pub mod unix;
+pub mod watchdog;
 
-#[derive(PartialEq, PartialOrd, Debug)]
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct MedianGrams(pub f64);
 impl MedianGrams {
     pub fn get(&self) -> f64 {
@@ -10,7 +93,8 @@ impl MedianGrams {
     }
 }
 
-#[derive(PartialEq, PartialOrd, Debug)]
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Grams(pub f64);
 impl Grams {
     pub fn get(&self) -> f64 {
@@ -18,28 +102,715 @@ impl Grams {
     }
 }
 
+/// A weight reading tagged with when it was taken.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct StampedWeight {
+    pub weight: Grams,
+    /// Position of this reading in its source's sequence of readings.
+    pub sequence: u64,
+    pub timestamp: SystemTime,
+}
+
+/// The voltage ratio of each load cell, tagged with when they were read.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct StampedReadings {
+    pub ratios: [f64; NUMBER_OF_INPUTS],
+    /// Position of these readings in their source's sequence of readings.
+    pub sequence: u64,
+    pub timestamp: SystemTime,
+}
+
+/// The upper median of `weights`, the middle one were they sorted. They are
+/// reordered in place, moving only as many as it takes to find it rather
+/// than sorting them all. NaNs order to the ends, and an empty slice has a
+/// NaN median.
+///
+/// Of an even number of weights, such as the [`DEFAULT_MEDIAN_SAMPLES`]
+/// of a default median, this is the upper of the middle two rather than
+/// their mean, so that a median is always a weight the scale read.
+///
+/// [`DEFAULT_MEDIAN_SAMPLES`]: crate::scale::DEFAULT_MEDIAN_SAMPLES
 pub fn median(weights: &mut [Grams]) -> MedianGrams {
-    weights.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    if weights.is_empty() {
+        return MedianGrams(f64::NAN);
+    }
     let middle = weights.len() / 2;
-    MedianGrams(weights[middle].0)
+    let (_, weight, _) = weights.select_nth_unstable_by(middle, |a, b| a.0.total_cmp(&b.0));
+    MedianGrams(weight.0)
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Largest `count` accepted by `ScaleCmd::GetWeightBatch`.
+pub const MAX_BATCH_COUNT: usize = 1000;
+
+/// Version of the command protocol spoken by this crate, exchanged by
+/// `ScaleCmd::Hello`. Raised whenever commands or responses change in a way
+/// an older peer cannot decode.
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// A request for a scale, as sent to the scale actor or over the wire.
+///
+/// New commands may be added, so matches need a wildcard arm.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
 pub enum ScaleCmd {
     GetWeight,
-    GetMedianWeight { samples: usize },
+    GetMedianWeight {
+        samples: usize,
+    },
+    /// `count` readings taken `interval_ms` apart, answered all at once with
+    /// `ScaleResponse::WeightBatch`. At most [`MAX_BATCH_COUNT`], and at most
+    /// [`DEFAULT_MAX_DURATION`](scale::DEFAULT_MAX_DURATION) apart; 0 takes
+    /// them back to back.
+    GetWeightBatch {
+        count: usize,
+        interval_ms: u64,
+    },
     Shutdown,
+    /// Take the median of `samples` readings as the weight of a container,
+    /// to be subtracted from later readings. The calibration is unchanged.
+    Tare {
+        samples: usize,
+    },
+    /// Take the median of `samples` readings on the empty platform as the new
+    /// zero, correcting the calibration offset and clearing any tare.
+    Zero {
+        samples: usize,
+    },
+    GetRawReadings,
+    /// Median voltage ratio of each load cell across `samples` readings.
+    GetRawMedians {
+        samples: usize,
+    },
+    SetCalibration(Calibration),
+    GetCalibration,
+    /// Answered with `ScaleResponse::Status`, or with
+    /// `ScaleResponse::FleetStatus` by a multi-scale server when it names
+    /// no scale and the server has other than one.
+    GetStatus,
+    /// Opens a conversation with a server, answered with
+    /// `ScaleResponse::HelloAck`. Optional: servers answer every command
+    /// without it.
+    Hello {
+        client_version: u32,
+    },
+    /// Proves the client knows the server's shared secret, answered with
+    /// `ScaleResponse::Authenticated`. Servers configured with a token take
+    /// nothing but `Hello` and `Auth` until it has been sent.
+    Auth {
+        token: String,
+    },
+    /// Asks a server for the scales it answers for, answered with
+    /// `ScaleResponse::Scales`.
+    ListScales,
+    /// Has the scale take a reading every `interval_ms`, answered with
+    /// `ScaleResponse::DataIntervalSet`.
+    SetDataInterval {
+        interval_ms: u64,
+    },
+}
+
+impl ScaleCmd {
+    /// The [`name`](Self::name) of every command this version of the crate
+    /// understands, as listed in `ScaleResponse::HelloAck`.
+    pub const NAMES: &'static [&'static str] = &[
+        "GetWeight",
+        "GetMedianWeight",
+        "GetWeightBatch",
+        "Shutdown",
+        "Tare",
+        "Zero",
+        "GetRawReadings",
+        "GetRawMedians",
+        "SetCalibration",
+        "GetCalibration",
+        "GetStatus",
+        "Hello",
+        "Auth",
+        "ListScales",
+        "SetDataInterval",
+    ];
+
+    /// The response that answers this command when it succeeds. Any command
+    /// may instead be answered by one of the outcomes listed under
+    /// [`ScaleResponse::answers`].
+    pub fn expects(&self) -> ResponseKind {
+        match self {
+            ScaleCmd::GetWeight => ResponseKind::Weight,
+            ScaleCmd::GetMedianWeight { .. } => ResponseKind::MedianWeight,
+            ScaleCmd::GetWeightBatch { .. } => ResponseKind::WeightBatch,
+            ScaleCmd::Shutdown => ResponseKind::ShutdownAck,
+            ScaleCmd::Tare { .. } => ResponseKind::Tared,
+            ScaleCmd::Zero { .. } => ResponseKind::Zeroed,
+            ScaleCmd::GetRawReadings => ResponseKind::RawReadings,
+            ScaleCmd::GetRawMedians { .. } => ResponseKind::RawMedians,
+            ScaleCmd::SetCalibration(_) => ResponseKind::CalibrationSet,
+            ScaleCmd::GetCalibration => ResponseKind::Calibration,
+            ScaleCmd::GetStatus => ResponseKind::Status,
+            ScaleCmd::Hello { .. } => ResponseKind::HelloAck,
+            ScaleCmd::Auth { .. } => ResponseKind::Authenticated,
+            ScaleCmd::ListScales => ResponseKind::Scales,
+            ScaleCmd::SetDataInterval { .. } => ResponseKind::DataIntervalSet,
+        }
+    }
+
+    /// The command's variant name, as it appears on the wire.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScaleCmd::GetWeight => "GetWeight",
+            ScaleCmd::GetMedianWeight { .. } => "GetMedianWeight",
+            ScaleCmd::GetWeightBatch { .. } => "GetWeightBatch",
+            ScaleCmd::Shutdown => "Shutdown",
+            ScaleCmd::Tare { .. } => "Tare",
+            ScaleCmd::Zero { .. } => "Zero",
+            ScaleCmd::GetRawReadings => "GetRawReadings",
+            ScaleCmd::GetRawMedians { .. } => "GetRawMedians",
+            ScaleCmd::SetCalibration(_) => "SetCalibration",
+            ScaleCmd::GetCalibration => "GetCalibration",
+            ScaleCmd::GetStatus => "GetStatus",
+            ScaleCmd::Hello { .. } => "Hello",
+            ScaleCmd::Auth { .. } => "Auth",
+            ScaleCmd::ListScales => "ListScales",
+            ScaleCmd::SetDataInterval { .. } => "SetDataInterval",
+        }
+    }
+}
+
+/// The successful [`ScaleResponse`] variants, without their payloads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ResponseKind {
+    Weight,
+    MedianWeight,
+    WeightBatch,
+    RawReadings,
+    ShutdownAck,
+    Tared,
+    Zeroed,
+    RawMedians,
+    CalibrationSet,
+    Calibration,
+    Status,
+    HelloAck,
+    Authenticated,
+    Scales,
+    DataIntervalSet,
+    FleetStatus,
+}
+
+/// Reply to a [`ScaleCmd`].
+///
+/// This is a wire format: variants serialize externally tagged, as
+/// `{"Weight":12.5}` or `"ShutdownAck"`, and existing variants must keep their
+/// names and payloads. New variants may be added, so matches need a wildcard
+/// arm.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[non_exhaustive]
+pub enum ScaleResponse {
+    Weight(Grams),
+    MedianWeight(MedianGrams),
+    /// Readings in the order taken, numbered from zero.
+    WeightBatch(Vec<StampedWeight>),
+    /// Voltage ratio of each load cell.
+    RawReadings([f64; NUMBER_OF_INPUTS]),
+    Error(ScaleErrorInfo),
+    /// The scale panicked while running the command. The actor keeps going.
+    InternalError(String),
+    ShutdownAck,
+    /// The command was not run because the scale is shutting down.
+    ShuttingDown,
+    /// The command's deadline passed before the scale got to it.
+    Expired,
+    /// The tare now subtracted from readings.
+    Tared(Grams),
+    /// The calibration after zeroing.
+    Zeroed(Calibration),
+    /// Median voltage ratio of each load cell.
+    RawMedians([f64; NUMBER_OF_INPUTS]),
+    CalibrationSet,
+    Calibration(Calibration),
+    Status(ScaleStatus),
+    /// The server's side of `ScaleCmd::Hello`. A client should only send the
+    /// commands listed in `supported_commands`, by [`ScaleCmd::name`].
+    HelloAck {
+        server_version: u32,
+        supported_commands: Vec<String>,
+    },
+    /// The command is well formed but unknown to this server, which is older
+    /// than the client that sent it. `command` is the name it was sent under.
+    Unsupported {
+        command: String,
+    },
+    /// The token sent with `ScaleCmd::Auth` was accepted.
+    Authenticated,
+    /// Sent unprompted on a quiet connection by servers configured to, so a
+    /// client can tell an idle server from a lost one. Answers no command.
+    Heartbeat {
+        /// How long the server's scale actor has been running.
+        uptime: Duration,
+        /// How old the actor's latest periodic reading is, if it has one.
+        last_reading_age: Option<Duration>,
+    },
+    /// The scales the server answers for, ordered by name.
+    Scales(Vec<ListedScale>),
+    /// The command named a scale the server does not answer for, or named
+    /// none on a server with several. `available` are those it does.
+    UnknownScale {
+        name: Option<String>,
+        available: Vec<String>,
+    },
+    /// The data interval the scale took, which its hardware may have
+    /// rounded from the one asked for.
+    DataIntervalSet {
+        interval_ms: u64,
+    },
+    /// Every scale of a multi-scale server, answering a `ScaleCmd::GetStatus`
+    /// that names none.
+    FleetStatus(FleetStatus),
+}
+
+impl ScaleResponse {
+    /// Which successful response this is, or `None` for the outcomes that can
+    /// answer any command: `Error`, `InternalError`, `ShuttingDown`,
+    /// `Expired`, `Unsupported` and `UnknownScale`. `Heartbeat`, which answers none, is
+    /// `None` too.
+    pub fn kind(&self) -> Option<ResponseKind> {
+        match self {
+            ScaleResponse::Weight(_) => Some(ResponseKind::Weight),
+            ScaleResponse::MedianWeight(_) => Some(ResponseKind::MedianWeight),
+            ScaleResponse::WeightBatch(_) => Some(ResponseKind::WeightBatch),
+            ScaleResponse::RawReadings(_) => Some(ResponseKind::RawReadings),
+            ScaleResponse::ShutdownAck => Some(ResponseKind::ShutdownAck),
+            ScaleResponse::Tared(_) => Some(ResponseKind::Tared),
+            ScaleResponse::Zeroed(_) => Some(ResponseKind::Zeroed),
+            ScaleResponse::RawMedians(_) => Some(ResponseKind::RawMedians),
+            ScaleResponse::CalibrationSet => Some(ResponseKind::CalibrationSet),
+            ScaleResponse::Calibration(_) => Some(ResponseKind::Calibration),
+            ScaleResponse::Status(_) => Some(ResponseKind::Status),
+            ScaleResponse::HelloAck { .. } => Some(ResponseKind::HelloAck),
+            ScaleResponse::Authenticated => Some(ResponseKind::Authenticated),
+            ScaleResponse::Scales(_) => Some(ResponseKind::Scales),
+            ScaleResponse::DataIntervalSet { .. } => Some(ResponseKind::DataIntervalSet),
+            ScaleResponse::FleetStatus(_) => Some(ResponseKind::FleetStatus),
+            ScaleResponse::Error(_)
+            | ScaleResponse::InternalError(_)
+            | ScaleResponse::ShuttingDown
+            | ScaleResponse::Expired
+            | ScaleResponse::Unsupported { .. }
+            | ScaleResponse::UnknownScale { .. }
+            | ScaleResponse::Heartbeat { .. } => None,
+        }
+    }
+
+    /// Whether this is a valid reply to `cmd`.
+    pub fn answers(&self, cmd: &ScaleCmd) -> bool {
+        !matches!(self, ScaleResponse::Heartbeat { .. })
+            && self.kind().is_none_or(|kind| {
+                kind == cmd.expects()
+                    || (kind == ResponseKind::FleetStatus && *cmd == ScaleCmd::GetStatus)
+            })
+    }
+
+    /// This server's answer to `ScaleCmd::Hello`.
+    pub fn hello_ack() -> Self {
+        ScaleResponse::HelloAck {
+            server_version: PROTOCOL_VERSION,
+            supported_commands: ScaleCmd::NAMES
+                .iter()
+                .map(|&name| name.to_owned())
+                .collect(),
+        }
+    }
 }
 
+/// A command sent with an `id` chosen by the client, for transports where
+/// replies can arrive out of order. The server answers it with a [`Reply`]
+/// carrying the same `id`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Request {
+    pub id: u64,
+    /// The scale of a multi-scale server to send `cmd` to, by name. May be
+    /// left out on a server with only one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<String>,
+    pub cmd: ScaleCmd,
+}
+
+/// The answer to the [`Request`] with the same `id`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Reply {
+    pub id: u64,
+    pub response: ScaleResponse,
+}
+
+/// A scale's state, as answered to `ScaleCmd::GetStatus`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ScaleStatus {
+    pub phidget_id: i32,
+    /// Whether every load cell channel is attached.
+    pub attached: bool,
+    pub calibration: Calibration,
+    pub tare: Grams,
+    /// Whole days until the calibration expires under the scale's policy,
+    /// negative once it has. `None` without a policy, or when the scale does
+    /// not know when its calibration was made, or it is dated in the future.
+    #[serde(default)]
+    pub calibration_expires_in_days: Option<i64>,
+}
+
+/// Whether a scale's bridge is attached, as far as the `HotPlugWatcher` of
+/// its `ScaleManager` has seen. Scales are `Connected` unless a watcher says
+/// otherwise.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum ScaleState {
+    #[default]
+    Connected,
+    /// The bridge was detached. The scale keeps its actor, whose reads fail
+    /// until the bridge is attached again and the phidget library reattaches
+    /// the channels it still has open.
+    Disconnected,
+}
+
+/// A scale as answered to `ScaleCmd::ListScales`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ListedScale {
+    pub name: String,
+    pub state: ScaleState,
+    /// How often the scale takes a reading, if it has a setting for it.
+    pub data_interval_ms: Option<u64>,
+}
+
+/// What went wrong, in a form that can cross the wire.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub enum ScaleErrorKind {
+    InvalidCoefficients,
+    InvalidPhidgetId,
+    Phidget,
+    Io,
+    Busy,
+    Cancelled,
+    BatchTooLarge,
+    NotSettled,
+    Overflow,
+    /// The actor's command queue was full and the command was refused or
+    /// evicted.
+    QueueFull,
+    /// The actor had stopped, or stopped before answering.
+    Stopped,
+    Unsupported,
+    /// A command that could not be decoded.
+    InvalidCommand,
+    /// The client has not authenticated, or sent the wrong token.
+    Unauthorized,
+    /// An error from a `Scale` implementation that is not a `ScaleError`.
+    Other,
+    Timeout,
+    Disconnected,
+    InvalidArgument,
+    /// The phidget is not the 4-channel bridge a scale needs.
+    WrongDevice,
+    /// A load cell's readings have stopped changing.
+    StaleData,
+    /// A circuit breaker is failing calls without trying the scale.
+    CircuitOpen,
+    /// A reading came back as NaN or infinite.
+    NonFinite,
+    /// The phidget is already in use by another scale in the same process.
+    AlreadyConnected,
+    /// A reading was over the scale's configured capacity.
+    OverCapacity,
+    /// The calibration is older than the scale's policy allows weights to be
+    /// read with.
+    CalibrationExpired,
+}
+
+/// Serializable description of an error, carried by `ScaleResponse::Error`.
+///
+/// Converts back into a `ScaleError` for clients that want to handle remote
+/// errors like local ones.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ScaleErrorInfo {
+    pub kind: ScaleErrorKind,
+    /// The load cell a `Phidget`, `Disconnected` or `WrongDevice` error came
+    /// from.
+    #[serde(default)]
+    pub load_cell: Option<usize>,
+    /// The phidget22 return code of a `Phidget` error.
+    #[serde(default)]
+    pub return_code: Option<i32>,
+    pub message: String,
+}
+
+impl ScaleErrorInfo {
+    pub fn new(kind: ScaleErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            load_cell: None,
+            return_code: None,
+            message: message.into(),
+        }
+    }
+
+    /// Describes an error returned through the `Scale` trait, keeping the
+    /// kind if it is a `ScaleError` or an `io::Error`.
+    pub fn from_dyn(error: &(dyn std::error::Error + 'static)) -> Self {
+        match error.downcast_ref::<ScaleError>() {
+            Some(error) => error.into(),
+            None if error.is::<std::io::Error>() => {
+                Self::new(ScaleErrorKind::Io, error.to_string())
+            }
+            None => Self::new(ScaleErrorKind::Other, error.to_string()),
+        }
+    }
+}
+
+impl From<&ScaleError> for ScaleErrorInfo {
+    fn from(error: &ScaleError) -> Self {
+        match error {
+            ScaleError::Remote(info) => info.clone(),
+            ScaleError::PhidgetError(phidget) => Self {
+                load_cell: Some(phidget.load_cell()),
+                return_code: Some(phidget.code() as i32),
+                ..Self::new(error.kind(), error.to_string())
+            },
+            ScaleError::Disconnected { channel } | ScaleError::NonFinite { channel } => Self {
+                load_cell: *channel,
+                ..Self::new(error.kind(), error.to_string())
+            },
+            ScaleError::OpenRolledBack { error: inner, .. }
+            | ScaleError::MemberFailed { error: inner, .. }
+            | ScaleError::FailoverMemberFailed { error: inner, .. } => Self {
+                message: error.to_string(),
+                ..Self::from(&**inner)
+            },
+            ScaleError::BothScalesFailed { primary, backup } => Self {
+                message: error.to_string(),
+                ..Self::from(harder_to_recover(primary, backup))
+            },
+            ScaleError::WrongDevice { channel, .. } | ScaleError::StaleData { channel, .. } => {
+                Self {
+                    load_cell: Some(*channel),
+                    ..Self::new(error.kind(), error.to_string())
+                }
+            }
+            error => Self::new(error.kind(), error.to_string()),
+        }
+    }
+}
+
+/// Rebuilds the `ScaleError` that `info` describes, where that is possible
+/// from what crossed the wire, and wraps it in `ScaleError::Remote`
+/// otherwise. Either way the error has the same `ScaleError::kind`.
+impl From<ScaleErrorInfo> for ScaleError {
+    fn from(info: ScaleErrorInfo) -> Self {
+        match (info.kind, info.load_cell, info.return_code) {
+            (ScaleErrorKind::InvalidCoefficients, ..) => ScaleError::InvalidCoefficients,
+            (ScaleErrorKind::InvalidPhidgetId, ..) => ScaleError::InvalidPhidgetId,
+            (ScaleErrorKind::Phidget, Some(load_cell), Some(return_code)) if return_code >= 0 => {
+                ScaleError::PhidgetError(PhidgetError::from_code(return_code as u32, load_cell))
+            }
+            (ScaleErrorKind::Busy, ..) => ScaleError::Busy,
+            (ScaleErrorKind::Disconnected, channel, _) => ScaleError::Disconnected { channel },
+            (ScaleErrorKind::NonFinite, channel, _) => ScaleError::NonFinite { channel },
+            _ => ScaleError::Remote(info),
+        }
+    }
+}
+
+impl std::fmt::Display for ScaleErrorInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ScaleErrorInfo {}
+
+/// Error type of the async scale traits, the same as the `Scale` trait's.
+/// `Send + Sync` so results can be passed between tasks and threads.
+pub type AsyncScaleError = Box<dyn std::error::Error + Send + Sync>;
+
 pub trait AsyncScale {
-    fn get_weight(&self) -> impl Future<Output = Result<f64, Box<dyn std::error::Error>>>;
+    fn get_weight(&self) -> impl Future<Output = Result<Grams, AsyncScaleError>> + Send;
+    fn get_median_weight(
+        &self,
+        samples: usize,
+    ) -> impl Future<Output = Result<MedianGrams, AsyncScaleError>> + Send;
+}
+
+/// A boxed future as returned by [`DynAsyncScale`].
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Object-safe form of [`AsyncScale`], for holding different kinds of scale
+/// behind `Box<dyn DynAsyncScale + Send>`.
+///
+/// Every `AsyncScale` implements this automatically; the only cost is boxing
+/// the returned future.
+pub trait DynAsyncScale {
+    fn get_weight(&self) -> BoxFuture<'_, Result<Grams, AsyncScaleError>>;
     fn get_median_weight(
         &self,
         samples: usize,
-    ) -> impl Future<Output = Result<f64, Box<dyn std::error::Error>>>;
+    ) -> BoxFuture<'_, Result<MedianGrams, AsyncScaleError>>;
+}
+
+impl<T: AsyncScale> DynAsyncScale for T {
+    fn get_weight(&self) -> BoxFuture<'_, Result<Grams, AsyncScaleError>> {
+        Box::pin(AsyncScale::get_weight(self))
+    }
+
+    fn get_median_weight(
+        &self,
+        samples: usize,
+    ) -> BoxFuture<'_, Result<MedianGrams, AsyncScaleError>> {
+        Box::pin(AsyncScale::get_median_weight(self, samples))
+    }
 }
 
+/// A scale the blocking way. Errors are `Send + Sync`, so results can be
+/// handed between threads and tasks; most are [`ScaleError`]s.
 pub trait Scale {
-    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error>>;
-    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error>>;
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>>;
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Median of `samples` readings, as requested by `ScaleCmd::GetMedianWeight`.
+    fn get_median_weight_of(
+        &self,
+        samples: usize,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_median_weight_cancellable(samples, &CancelFlag::new())
+    }
+
+    /// Median of `samples` readings that stops early with
+    /// `ScaleError::Cancelled` once `cancel` is set.
+    fn get_median_weight_cancellable(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        sampling::collect_median(samples, Duration::ZERO, cancel, || self.get_weight())
+    }
+
+    /// Like [`get_median_weight_cancellable`](Self::get_median_weight_cancellable),
+    /// calling `between_samples` before each sample so the caller can slip
+    /// other work in. The scale actor uses this to let priority commands
+    /// interrupt a long median.
+    ///
+    /// The default ignores `between_samples` and takes the median in one go;
+    /// scales with slow medians should override it.
+    fn get_median_weight_yielding(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+        between_samples: &mut dyn FnMut(),
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        let _ = between_samples;
+        self.get_median_weight_cancellable(samples, cancel)
+    }
+
+    /// Reads the weight, handing the voltage ratios it was weighed from to
+    /// `observe` first; an error from `observe` fails the read. The default
+    /// takes the ratios in a read of their own.
+    fn get_weight_observed(
+        &self,
+        observe: &mut dyn FnMut(&[f64; NUMBER_OF_INPUTS]) -> Result<(), ScaleError>,
+    ) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        observe(&self.get_raw_readings()?)?;
+        self.get_weight()
+    }
+
+    /// Takes the median of `samples` readings as a tare to subtract from
+    /// later readings, and returns it. The default is unsupported.
+    fn tare(&mut self, samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        let _ = samples;
+        Err(ScaleError::Unsupported("Taring").into())
+    }
+
+    /// Takes the median of `samples` readings as the new zero and returns the
+    /// corrected calibration. The default is unsupported.
+    fn zero(
+        &mut self,
+        samples: usize,
+    ) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        let _ = samples;
+        Err(ScaleError::Unsupported("Zeroing").into())
+    }
+
+    /// Voltage ratio of each load cell. The default is unsupported.
+    fn get_raw_readings(
+        &self,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        Err(ScaleError::Unsupported("Raw readings").into())
+    }
+
+    /// Median voltage ratio of each load cell across `samples` readings. The
+    /// default is unsupported.
+    fn get_raw_medians(
+        &self,
+        samples: usize,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        let _ = samples;
+        Err(ScaleError::Unsupported("Raw readings").into())
+    }
+
+    /// The default is unsupported.
+    fn calibration(&self) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        Err(ScaleError::Unsupported("Calibration").into())
+    }
+
+    /// The default is unsupported.
+    fn set_calibration(
+        &mut self,
+        calibration: Calibration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let _ = calibration;
+        Err(ScaleError::Unsupported("Calibration").into())
+    }
+
+    /// The default is unsupported.
+    fn status(&mut self) -> Result<ScaleStatus, Box<dyn std::error::Error + Send + Sync>> {
+        Err(ScaleError::Unsupported("Status").into())
+    }
+
+    /// How often the scale takes a reading. The default is unsupported.
+    fn data_interval(&mut self) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+        Err(ScaleError::Unsupported("Data intervals").into())
+    }
+
+    /// Has the scale take a reading every `interval`, and returns the
+    /// interval it took, which hardware may round. The default is
+    /// unsupported.
+    fn set_data_interval(
+        &mut self,
+        interval: Duration,
+    ) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+        let _ = interval;
+        Err(ScaleError::Unsupported("Data intervals").into())
+    }
+
+    /// The load cell a [`Watchdog`](crate::watchdog::Watchdog) on this scale
+    /// has caught frozen, if any. The default has no watchdog.
+    fn stale_channel(&self) -> Option<StaleChannel> {
+        None
+    }
+
+    /// The clock the scale waits by, such as between the readings of a
+    /// `ScaleCmd::GetWeightBatch`. The default is the system's.
+    fn clock(&self) -> Arc<dyn Clock> {
+        Arc::new(SystemClock)
+    }
+
+    /// Releases the underlying hardware. The scale should not be read
+    /// afterwards.
+    fn close(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Ok(())
+    }
 }
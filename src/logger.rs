@@ -0,0 +1,308 @@
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::json;
+
+use crate::clock::civil_date;
+use crate::scale::{ScaleError, NUMBER_OF_INPUTS};
+use crate::StampedWeight;
+
+/// How long a [`WeightLogger`] keeps lines buffered before writing them out.
+pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+/// When a [`WeightLogger`] moves on to a new file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    /// Keep appending to one file.
+    #[default]
+    Never,
+    /// Start a new file before one would grow past this many bytes.
+    Size(u64),
+    /// Start a new file for each UTC day, going by the samples' timestamps.
+    Daily,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// `timestamp,sequence,grams,ratio_0,..,ratio_3` with a header line.
+    Csv,
+    /// One JSON object per line.
+    JsonLines,
+}
+
+/// One line of a weight log.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LogRecord {
+    pub sample: StampedWeight,
+    /// The voltage ratio of each load cell, if the sampler read them.
+    pub ratios: Option<[f64; NUMBER_OF_INPUTS]>,
+}
+
+impl From<StampedWeight> for LogRecord {
+    fn from(sample: StampedWeight) -> Self {
+        Self {
+            sample,
+            ratios: None,
+        }
+    }
+}
+
+/// Writes weight samples to CSV or JSON-lines files.
+///
+/// The path given to [`csv`](Self::csv) or [`jsonl`](Self::jsonl) is a
+/// pattern: `{date}` is replaced with the UTC date of the samples in the file
+/// and `{index}` with a counter that tells apart files rotated on the same
+/// day. A pattern without `{index}` gets `.1`, `.2` and so on appended once
+/// it needs one. Existing files are appended to when the logger starts, but
+/// rotation always moves on to a file that does not exist yet.
+///
+/// Lines are buffered and written out every flush interval, and when the
+/// logger finishes. Timestamps are seconds since the Unix epoch.
+pub struct WeightLogger {
+    pattern: String,
+    format: LogFormat,
+    rotation: Rotation,
+    flush_interval: Duration,
+    file: Option<LogFile>,
+    last_flush: Instant,
+}
+
+struct LogFile {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    day: u64,
+    index: u32,
+    written: u64,
+}
+
+impl WeightLogger {
+    pub fn csv(path: impl AsRef<Path>) -> Self {
+        Self::new(path.as_ref(), LogFormat::Csv)
+    }
+
+    pub fn jsonl(path: impl AsRef<Path>) -> Self {
+        Self::new(path.as_ref(), LogFormat::JsonLines)
+    }
+
+    fn new(path: &Path, format: LogFormat) -> Self {
+        Self {
+            pattern: path.to_string_lossy().into_owned(),
+            format,
+            rotation: Rotation::Never,
+            flush_interval: DEFAULT_FLUSH_INTERVAL,
+            file: None,
+            last_flush: Instant::now(),
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_flush_interval(mut self, flush_interval: Duration) -> Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+
+    pub fn format(&self) -> LogFormat {
+        self.format
+    }
+
+    /// The file being written, once the first sample has been logged.
+    pub fn current_path(&self) -> Option<&Path> {
+        self.file.as_ref().map(|file| file.path.as_path())
+    }
+
+    /// Writes `record`, rotating first if the record is due a new file.
+    pub fn log(&mut self, record: &LogRecord) -> io::Result<()> {
+        let day = seconds(record.sample.timestamp).0 / SECONDS_PER_DAY;
+        let line = self.line(record);
+        let rotate = self.file.as_ref().is_some_and(|file| match self.rotation {
+            Rotation::Never => false,
+            Rotation::Size(limit) => file.written > 0 && file.written + line.len() as u64 > limit,
+            Rotation::Daily => file.day != day,
+        });
+        if rotate {
+            self.rotate(day)?;
+        }
+        if self.file.is_none() {
+            self.file = Some(self.open(day, 0)?);
+        }
+        if let Some(file) = &mut self.file {
+            file.writer.write_all(line.as_bytes())?;
+            file.written += line.len() as u64;
+        }
+        if self.last_flush.elapsed() >= self.flush_interval {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes out any buffered lines.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.last_flush = Instant::now();
+        match &mut self.file {
+            Some(file) => file.writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Flushes and closes the current file.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.flush()
+    }
+
+    /// Logs every sample from `samples`, such as the receiver of a
+    /// [`ScopedSampler`](crate::scoped::ScopedSampler), on a thread of its
+    /// own until the sampler stops. Read errors are skipped.
+    ///
+    /// An error writing the log ends the thread and drops `samples`, which
+    /// stops the sampler too; [`LoggerHandle::join`] returns it.
+    pub fn attach<T>(mut self, samples: Receiver<Result<T, ScaleError>>) -> LoggerHandle
+    where
+        T: Into<LogRecord> + Send + 'static,
+    {
+        let thread = thread::spawn(move || {
+            loop {
+                match samples.recv_timeout(self.flush_interval) {
+                    Ok(Ok(sample)) => self.log(&sample.into())?,
+                    Ok(Err(_)) => {}
+                    // Nothing new, so write out what was buffered before it.
+                    Err(RecvTimeoutError::Timeout) => self.flush()?,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            self.finish()
+        });
+        LoggerHandle { thread }
+    }
+
+    fn line(&self, record: &LogRecord) -> String {
+        let (secs, nanos) = seconds(record.sample.timestamp);
+        match self.format {
+            LogFormat::Csv => {
+                let mut line = format!(
+                    "{secs}.{nanos:09},{},{}",
+                    record.sample.sequence,
+                    record.sample.weight.get()
+                );
+                match record.ratios {
+                    Some(ratios) => {
+                        for ratio in ratios {
+                            let _ = write!(line, ",{ratio}");
+                        }
+                    }
+                    None => line.push_str(&",".repeat(NUMBER_OF_INPUTS)),
+                }
+                line.push('\n');
+                line
+            }
+            LogFormat::JsonLines => {
+                let mut json = json!({
+                    "timestamp": secs as f64 + f64::from(nanos) / 1e9,
+                    "sequence": record.sample.sequence,
+                    "grams": record.sample.weight,
+                });
+                if let Some(ratios) = record.ratios {
+                    json["ratios"] = json!(ratios);
+                }
+                format!("{json}\n")
+            }
+        }
+    }
+
+    fn header(&self) -> Option<String> {
+        match self.format {
+            LogFormat::Csv => {
+                let mut header = "timestamp,sequence,grams".to_owned();
+                for cell in 0..NUMBER_OF_INPUTS {
+                    let _ = write!(header, ",ratio_{cell}");
+                }
+                header.push('\n');
+                Some(header)
+            }
+            LogFormat::JsonLines => None,
+        }
+    }
+
+    fn path(&self, day: u64, index: u32) -> PathBuf {
+        let (year, month, date) = civil_date(day);
+        let mut path = self
+            .pattern
+            .replace("{date}", &format!("{year:04}-{month:02}-{date:02}"))
+            .replace("{index}", &index.to_string());
+        if !self.pattern.contains("{index}") && index > 0 {
+            let _ = write!(path, ".{index}");
+        }
+        PathBuf::from(path)
+    }
+
+    fn open(&self, day: u64, index: u32) -> io::Result<LogFile> {
+        let path = self.path(day, index);
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let mut written = file.metadata()?.len();
+        let mut writer = BufWriter::new(file);
+        if let (0, Some(header)) = (written, self.header()) {
+            writer.write_all(header.as_bytes())?;
+            written = header.len() as u64;
+        }
+        Ok(LogFile {
+            writer,
+            path,
+            day,
+            index,
+            written,
+        })
+    }
+
+    fn rotate(&mut self, day: u64) -> io::Result<()> {
+        let Some(mut old) = self.file.take() else {
+            return Ok(());
+        };
+        old.writer.flush()?;
+        let mut index = if old.day == day { old.index + 1 } else { 0 };
+        loop {
+            let path = self.path(day, index);
+            if path != old.path && !path.exists() {
+                break;
+            }
+            index += 1;
+        }
+        self.file = Some(self.open(day, index)?);
+        Ok(())
+    }
+}
+
+/// The thread behind [`WeightLogger::attach`].
+pub struct LoggerHandle {
+    thread: JoinHandle<io::Result<()>>,
+}
+
+impl LoggerHandle {
+    /// Whether the logging thread has exited.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Waits for the sampler to stop and the log to be flushed. A panic on
+    /// the logging thread is passed on to the caller.
+    pub fn join(self) -> io::Result<()> {
+        self.thread
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+}
+
+/// Whole seconds and nanoseconds since the Unix epoch, or zero before it.
+fn seconds(timestamp: SystemTime) -> (u64, u32) {
+    let since = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+    (since.as_secs(), since.subsec_nanos())
+}
@@ -0,0 +1,1169 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use thiserror::Error;
+use tokio::sync::broadcast;
+#[cfg(feature = "hardware")]
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+
+use crate::actor::{spawn_scale_actor_with_config, ActorConfig, ScaleHandle};
+use crate::calibration::{Calibration, TransferCheck, TransferResult};
+#[cfg(feature = "hardware")]
+use crate::config::{FleetConfig, FleetConnection, ScaleConfig, ScaleConfigDefaults};
+#[cfg(feature = "hardware")]
+use crate::discovery::{
+    DeviceChange, DeviceEvent, DeviceTracker, DeviceWatch, DiscoveredScale, PhidgetManager,
+};
+use crate::health::{self, FleetStatus, HealthConfig, HealthReport, ScaleHealth};
+#[cfg(feature = "net")]
+use crate::net::ScaleDirectory;
+#[cfg(feature = "hardware")]
+use crate::scale::ConnectedScale;
+use crate::scale::ScaleError;
+#[cfg(feature = "hardware")]
+use crate::source::VoltageSource;
+#[cfg(all(feature = "hardware", feature = "store"))]
+use crate::store::{AssignmentError, AssignmentStore};
+use crate::watchdog::StaleChannel;
+#[cfg(any(feature = "net", feature = "http"))]
+use crate::BoxFuture;
+use crate::{
+    Grams, ListedScale, Scale, ScaleCmd, ScaleErrorInfo, ScaleErrorKind, ScaleResponse, ScaleState,
+    ScaleStatus, StampedWeight,
+};
+
+/// Events buffered for each [`ScaleManager::events`] receiver before the
+/// oldest are dropped.
+pub const EVENT_CAPACITY: usize = 256;
+/// How long [`ScaleManager::fleet_status`] waits for the slowest scale.
+pub const DEFAULT_FLEET_STATUS_DEADLINE: Duration = Duration::from_secs(2);
+/// Raw reads [`ScaleManager::fleet_status`] judges each scale's health by.
+pub const DEFAULT_FLEET_HEALTH_SAMPLES: usize = 5;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ManagerError {
+    #[error("No scale is named {name:?}")]
+    UnknownScale { name: String },
+
+    #[error("A scale is already named {name:?}")]
+    NameTaken { name: String },
+
+    #[error("The scale manager has shut down")]
+    ShutDown,
+
+    #[error("Scale {name:?} failed: {error}")]
+    ScaleFailed { name: String, error: ScaleErrorInfo },
+
+    /// The scale was assigned its bridge, but the assignment could not be
+    /// written to the manager's `AssignmentStore`, so it is lost on restart.
+    #[error("Scale {name:?} was assigned, but the assignment was not saved: {message}")]
+    AssignmentNotSaved { name: String, message: String },
+}
+
+/// Something that happened to one of a [`ScaleManager`]'s scales.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScaleEvent {
+    /// A weight from the actor's periodic sampling, as published to
+    /// [`ScaleHandle::watch_weight`].
+    Weight(StampedWeight),
+    /// A change of [`ScaleHandle::watch_watchdog`].
+    Watchdog(Option<StaleChannel>),
+    /// The actor has stopped and the scale is closed. Nothing follows.
+    Stopped,
+}
+
+/// A [`ScaleEvent`] with the name of the scale it happened to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NamedEvent {
+    pub name: String,
+    pub event: ScaleEvent,
+}
+
+/// What [`ScaleManager::events`] receivers hear of.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FleetEvent {
+    Scale(NamedEvent),
+    /// A bridge with the serial number was attached with enough channels
+    /// for a scale. Sent by a [`HotPlugWatcher`].
+    ScaleAvailable(i32),
+    /// The bridge of the scale was detached, and it is
+    /// `ScaleState::Disconnected`. Sent by a [`HotPlugWatcher`].
+    ScaleLost(String),
+    /// The scale was connected as its bridge was attached, or was back to
+    /// `ScaleState::Connected` with its bridge reattached. Sent by a
+    /// [`HotPlugWatcher`].
+    ScaleConnected(String),
+    /// The bridge of a scale of the fleet was attached, but connecting the
+    /// scale failed with `error`. It is tried again the next time the
+    /// bridge is attached. Sent by a [`HotPlugWatcher`].
+    ConnectFailed {
+        name: String,
+        error: ScaleErrorInfo,
+    },
+    /// A bridge was attached that no scale of the fleet binds to and no
+    /// name is assigned. It waits, listed by
+    /// [`ScaleManager::unassigned`], until one is with
+    /// [`ScaleManager::assign`]. Sent by a [`HotPlugWatcher`] connecting a
+    /// fleet.
+    ScaleUnassigned {
+        serial: i32,
+        hub_port: Option<i32>,
+    },
+    /// A bridge bound to the scale `name` was attached while the manager
+    /// had a scale of that name on another bridge. The bridge is not
+    /// connected, and waits as an unassigned one does. Sent by a
+    /// [`HotPlugWatcher`] connecting a fleet.
+    BindingConflict {
+        name: String,
+        serial: i32,
+    },
+}
+
+/// How [`ScaleManager::fleet_status_with`] checks the scales.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FleetStatusConfig {
+    /// How long to wait for the scales, all checked at once. Scales that
+    /// have not finished by then are reported with a `Timeout` error.
+    pub deadline: Duration,
+    /// Raw reads of each scale, `health.sample_interval` apart, that its
+    /// health is judged by. Noise takes at least 2 to estimate.
+    pub samples: usize,
+    pub health: HealthConfig,
+}
+
+impl Default for FleetStatusConfig {
+    fn default() -> Self {
+        Self {
+            deadline: DEFAULT_FLEET_STATUS_DEADLINE,
+            samples: DEFAULT_FLEET_HEALTH_SAMPLES,
+            health: HealthConfig::default(),
+        }
+    }
+}
+
+struct Managed<S> {
+    handle: ScaleHandle,
+    task: JoinHandle<Option<S>>,
+    /// The serial number and hub port of the scale's bridge, if known, for
+    /// a [`HotPlugWatcher`] to tell when it is detached. A hub port of
+    /// `None` matches any.
+    #[cfg_attr(not(feature = "hardware"), allow(dead_code))]
+    device: Option<(i32, Option<i32>)>,
+    state: ScaleState,
+    /// Times a [`HotPlugWatcher`] has seen the bridge reattached.
+    reconnects: u64,
+}
+
+impl<S> Managed<S> {
+    fn listing(&self, name: &str) -> ListedScale {
+        ListedScale {
+            name: name.into(),
+            state: self.state,
+            data_interval_ms: self
+                .handle
+                .data_interval()
+                .map(|interval| interval.as_millis() as u64),
+        }
+    }
+}
+
+struct Scales<S> {
+    by_name: BTreeMap<String, Managed<S>>,
+    shut_down: bool,
+    #[cfg(feature = "hardware")]
+    bindings: Bindings,
+}
+
+/// What a [`HotPlugWatcher`] tells which scale a bridge is by, besides the
+/// fleet's config.
+#[cfg(feature = "hardware")]
+#[derive(Default)]
+struct Bindings {
+    /// The serial number of the bridge assigned to each name with
+    /// [`ScaleManager::assign`].
+    assigned: BTreeMap<String, i32>,
+    /// Bridges attached that are waiting for a name, by serial number and
+    /// hub port.
+    unassigned: BTreeMap<(i32, Option<i32>), DiscoveredScale>,
+    /// Where assignments are saved, if anywhere.
+    #[cfg(feature = "store")]
+    store: Option<AssignmentStore>,
+}
+
+/// What a [`ScaleManager`] shares with its [`HotPlugWatcher`]s.
+struct Shared<S> {
+    scales: Mutex<Scales<S>>,
+    config: ActorConfig,
+    events: broadcast::Sender<FleetEvent>,
+    /// Sent to each time a name is assigned, for the watchers to connect
+    /// the bridges that were waiting for one.
+    #[cfg(feature = "hardware")]
+    assigned: watch::Sender<()>,
+}
+
+impl<S> Shared<S> {
+    fn scales(&self) -> MutexGuard<'_, Scales<S>> {
+        self.scales.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    #[cfg_attr(not(feature = "hardware"), allow(dead_code))]
+    fn send(&self, event: FleetEvent) {
+        // No receivers is not a failure.
+        let _ = self.events.send(event);
+    }
+}
+
+impl<S> Shared<S> {
+    async fn fleet_status(&self, config: &FleetStatusConfig) -> FleetStatus {
+        let scales: Vec<_> = self
+            .scales()
+            .by_name
+            .iter()
+            .map(|(name, managed)| {
+                let (state, reconnects) = (managed.state, managed.reconnects);
+                (name.clone(), state, reconnects, managed.handle.clone())
+            })
+            .collect();
+        let deadline = tokio::time::Instant::now() + config.deadline;
+        let tasks: Vec<_> = scales
+            .into_iter()
+            .map(|(name, state, reconnects, handle)| {
+                let config = config.clone();
+                let check = {
+                    let handle = handle.clone();
+                    async move {
+                        tokio::time::timeout_at(deadline, check_health(&handle, &config)).await
+                    }
+                };
+                (name, state, reconnects, handle, tokio::spawn(check))
+            })
+            .collect();
+        let mut scales = Vec::with_capacity(tasks.len());
+        for (name, state, reconnects, handle, task) in tasks {
+            let checked = match task.await {
+                Ok(Ok(checked)) => checked,
+                Ok(Err(_)) => Err(ScaleErrorInfo::new(
+                    ScaleErrorKind::Timeout,
+                    format!("No answer within {:?}", config.deadline),
+                )),
+                Err(e) => Err(ScaleErrorInfo::new(
+                    ScaleErrorKind::Other,
+                    format!("Status task failed: {e}"),
+                )),
+            };
+            let (health, calibration_expires_in_days) = match checked {
+                Ok((report, days)) => (Ok(report), days),
+                Err(error) => (Err(error), None),
+            };
+            let last_weight = *handle.watch_weight().borrow();
+            scales.push(ScaleHealth {
+                name,
+                state,
+                health,
+                last_weight,
+                last_weight_age: last_weight
+                    .and_then(|weight| SystemTime::now().duration_since(weight.timestamp).ok()),
+                reconnects,
+                calibration_expires_in_days,
+            });
+        }
+        FleetStatus::new(scales)
+    }
+}
+
+/// Where the servers answering for a [`ScaleManager`] get its
+/// [`FleetStatus`].
+#[cfg(any(feature = "net", feature = "http"))]
+pub(crate) trait FleetStatusSource: Send + Sync {
+    /// [`ScaleManager::fleet_status`].
+    fn fleet_status(&self) -> BoxFuture<'_, FleetStatus>;
+}
+
+#[cfg(any(feature = "net", feature = "http"))]
+impl<S: Send> FleetStatusSource for Shared<S> {
+    fn fleet_status(&self) -> BoxFuture<'_, FleetStatus> {
+        Box::pin(async { Shared::fleet_status(self, &FleetStatusConfig::default()).await })
+    }
+}
+
+#[cfg(feature = "net")]
+impl<S: Send> ScaleDirectory for Shared<S> {
+    fn get(&self, name: &str) -> Option<ScaleHandle> {
+        let scales = self.scales();
+        scales
+            .by_name
+            .get(name)
+            .map(|managed| managed.handle.clone())
+    }
+
+    fn scales(&self) -> Vec<(ListedScale, ScaleHandle)> {
+        let scales = Shared::scales(self);
+        scales
+            .by_name
+            .iter()
+            .map(|(name, managed)| (managed.listing(name), managed.handle.clone()))
+            .collect()
+    }
+}
+
+impl<S: Scale + Send + 'static> Shared<S> {
+    fn insert(
+        &self,
+        name: String,
+        scale: S,
+        device: Option<(i32, Option<i32>)>,
+    ) -> Result<ScaleHandle, ManagerError> {
+        let mut scales = self.scales();
+        if scales.shut_down {
+            return Err(ManagerError::ShutDown);
+        }
+        if scales.by_name.contains_key(&name) {
+            return Err(ManagerError::NameTaken { name });
+        }
+        let (handle, task) = spawn_scale_actor_with_config(scale, self.config.clone());
+        forward_events(name.clone(), &handle, self.events.clone());
+        scales.by_name.insert(
+            name,
+            Managed {
+                handle: handle.clone(),
+                task,
+                device,
+                state: ScaleState::Connected,
+                reconnects: 0,
+            },
+        );
+        Ok(handle)
+    }
+}
+
+/// Scales addressed by name, each on an actor of its own, so that commands
+/// to different scales run at the same time and a scale that fails or
+/// panics leaves the others alone.
+///
+/// Must be used within a tokio runtime, which the actors are spawned on.
+/// Dropping the manager, and any [`HotPlugWatcher`] of it, drops its
+/// handles, which shuts each actor down once no other handle to it is left;
+/// [`shutdown`](Self::shutdown) shuts them all down and waits until they
+/// have.
+pub struct ScaleManager<S> {
+    shared: Arc<Shared<S>>,
+}
+
+impl<S: Scale + Send + 'static> Default for ScaleManager<S> {
+    fn default() -> Self {
+        Self::new(ActorConfig::default())
+    }
+}
+
+impl<S: Scale + Send + 'static> ScaleManager<S> {
+    /// A manager without scales, which starts the actors of those added
+    /// with `config`.
+    pub fn new(config: ActorConfig) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                scales: Mutex::new(Scales {
+                    by_name: BTreeMap::new(),
+                    shut_down: false,
+                    #[cfg(feature = "hardware")]
+                    bindings: Bindings::default(),
+                }),
+                config,
+                events: broadcast::channel(EVENT_CAPACITY).0,
+                #[cfg(feature = "hardware")]
+                assigned: watch::Sender::new(()),
+            }),
+        }
+    }
+
+    /// Starts an actor for `scale` under `name`, and returns its handle.
+    ///
+    /// Fails with `ManagerError::NameTaken`, leaving `scale` to be dropped,
+    /// if a scale already goes by `name`.
+    pub fn add(&self, name: impl Into<String>, scale: S) -> Result<ScaleHandle, ManagerError> {
+        self.shared.insert(name.into(), scale, None)
+    }
+
+    /// Shuts down the actor of the scale `name` and gives the closed scale
+    /// back, or `None` if the runtime shut down first.
+    pub async fn remove(&self, name: &str) -> Result<Option<S>, ManagerError> {
+        let managed = self
+            .shared
+            .scales()
+            .by_name
+            .remove(name)
+            .ok_or_else(|| ManagerError::UnknownScale { name: name.into() })?;
+        Ok(stop(managed).await)
+    }
+
+    /// The handle of the scale `name`.
+    pub fn get(&self, name: &str) -> Result<ScaleHandle, ManagerError> {
+        self.shared
+            .scales()
+            .by_name
+            .get(name)
+            .map(|managed| managed.handle.clone())
+            .ok_or_else(|| ManagerError::UnknownScale { name: name.into() })
+    }
+
+    pub fn state(&self, name: &str) -> Result<ScaleState, ManagerError> {
+        self.shared
+            .scales()
+            .by_name
+            .get(name)
+            .map(|managed| managed.state)
+            .ok_or_else(|| ManagerError::UnknownScale { name: name.into() })
+    }
+
+    /// The names of the scales, in order.
+    pub fn names(&self) -> Vec<String> {
+        self.shared.scales().by_name.keys().cloned().collect()
+    }
+
+    /// Every scale with its state, ordered by name, as answered to
+    /// `ScaleCmd::ListScales`.
+    pub fn list(&self) -> Vec<ListedScale> {
+        self.shared
+            .scales()
+            .by_name
+            .iter()
+            .map(|(name, managed)| managed.listing(name))
+            .collect()
+    }
+
+    /// The scales for a server to answer for, as they are when asked.
+    #[cfg(feature = "net")]
+    pub(crate) fn directory(&self) -> Arc<dyn ScaleDirectory> {
+        self.shared.clone()
+    }
+
+    /// Has the scale `name` take a reading every `interval`, in whole
+    /// milliseconds, and returns the interval it took. The other scales
+    /// keep theirs.
+    ///
+    /// Fails with `ManagerError::ScaleFailed` if the scale turns the
+    /// interval down, as a scale without the setting does.
+    pub async fn set_data_interval(
+        &self,
+        name: &str,
+        interval: Duration,
+    ) -> Result<Duration, ManagerError> {
+        let cmd = ScaleCmd::SetDataInterval {
+            interval_ms: interval.as_millis() as u64,
+        };
+        match self.get(name)?.send(cmd).await {
+            ScaleResponse::DataIntervalSet { interval_ms } => {
+                Ok(Duration::from_millis(interval_ms))
+            }
+            response => Err(ManagerError::ScaleFailed {
+                name: name.into(),
+                error: failure(response),
+            }),
+        }
+    }
+
+    /// Sends `cmd` to every scale at once and gathers the responses by name.
+    pub async fn send_all(&self, cmd: ScaleCmd) -> BTreeMap<String, ScaleResponse> {
+        self.send_all_within(cmd, None).await
+    }
+
+    /// The status of every scale, asked of all at once. A scale that does
+    /// not answer within `timeout` has a `Timeout` error in the map, and
+    /// holds up none of the others.
+    pub async fn status_all(
+        &self,
+        timeout: Duration,
+    ) -> BTreeMap<String, Result<ScaleStatus, ScaleErrorInfo>> {
+        answers(
+            self.send_all_within(ScaleCmd::GetStatus, Some(timeout))
+                .await,
+            |response| match response {
+                ScaleResponse::Status(status) => Ok(status),
+                response => Err(response),
+            },
+        )
+    }
+
+    /// Every scale at a glance, for a dashboard: its state, its health from
+    /// [`DEFAULT_FLEET_HEALTH_SAMPLES`] raw reads, its latest weight and how
+    /// old it is, how often it has been reconnected and when its calibration
+    /// expires. See [`fleet_status_with`](Self::fleet_status_with).
+    pub async fn fleet_status(&self) -> FleetStatus {
+        self.fleet_status_with(&FleetStatusConfig::default()).await
+    }
+
+    /// The [`fleet_status`](Self::fleet_status), checked as `config` says.
+    ///
+    /// The scales are checked all at once, and the status is ready within
+    /// `config.deadline` however many there are. A scale that has not
+    /// answered by then, or that cannot take raw reads, has the error in
+    /// place of its health, with everything else the manager knows of it.
+    pub async fn fleet_status_with(&self, config: &FleetStatusConfig) -> FleetStatus {
+        self.shared.fleet_status(config).await
+    }
+
+    /// Where a server gets the fleet's status.
+    #[cfg(feature = "http")]
+    pub(crate) fn status_source(&self) -> Arc<dyn FleetStatusSource> {
+        self.shared.clone()
+    }
+
+    /// [`send_all`](Self::send_all), answering for each scale that takes
+    /// longer than `timeout` with a `Timeout` error.
+    async fn send_all_within(
+        &self,
+        cmd: ScaleCmd,
+        timeout: Option<Duration>,
+    ) -> BTreeMap<String, ScaleResponse> {
+        let handles: Vec<_> = self
+            .shared
+            .scales()
+            .by_name
+            .iter()
+            .map(|(name, managed)| (name.clone(), managed.handle.clone()))
+            .collect();
+        let tasks: Vec<_> = handles
+            .into_iter()
+            .map(|(name, handle)| {
+                let cmd = cmd.clone();
+                let answer = async move {
+                    let Some(timeout) = timeout else {
+                        return handle.send(cmd).await;
+                    };
+                    tokio::time::timeout(timeout, handle.send(cmd))
+                        .await
+                        .unwrap_or_else(|_| {
+                            ScaleResponse::Error(ScaleErrorInfo::new(
+                                ScaleErrorKind::Timeout,
+                                format!("No answer within {timeout:?}"),
+                            ))
+                        })
+                };
+                (name, tokio::spawn(answer))
+            })
+            .collect();
+        let mut responses = BTreeMap::new();
+        for (name, task) in tasks {
+            let response = task.await.unwrap_or_else(|e| {
+                ScaleResponse::InternalError(format!("Command task failed: {e}"))
+            });
+            responses.insert(name, response);
+        }
+        responses
+    }
+
+    /// Reads every scale at once. A scale that fails has its error in the
+    /// map, among the weights of the others.
+    pub async fn read_all(&self) -> BTreeMap<String, Result<Grams, ScaleErrorInfo>> {
+        answers(
+            self.send_all(ScaleCmd::GetWeight).await,
+            |response| match response {
+                ScaleResponse::Weight(weight) => Ok(weight),
+                response => Err(response),
+            },
+        )
+    }
+
+    /// Tares every scale at once with the median of `samples` readings,
+    /// giving the tare each now subtracts.
+    pub async fn tare_all(
+        &self,
+        samples: usize,
+    ) -> BTreeMap<String, Result<Grams, ScaleErrorInfo>> {
+        answers(
+            self.send_all(ScaleCmd::Tare { samples }).await,
+            |response| match response {
+                ScaleResponse::Tared(tare) => Ok(tare),
+                response => Err(response),
+            },
+        )
+    }
+
+    /// Copies `calibration` to the scales `names` at once, checking each
+    /// with [`Calibration::transfer_check`]'s weighing of `check`, and gives
+    /// what each weighed by name. Scales that fail the check, or cannot be
+    /// weighed, have their previous calibration put back.
+    ///
+    /// A name without a scale has `ManagerError::UnknownScale` in the map,
+    /// and a scale that fails, or cannot have its calibration put back,
+    /// `ManagerError::ScaleFailed`.
+    pub async fn apply_calibration_to(
+        &self,
+        names: impl IntoIterator<Item = impl Into<String>>,
+        calibration: Calibration,
+        check: TransferCheck,
+    ) -> BTreeMap<String, Result<TransferResult, ManagerError>> {
+        let tasks: Vec<_> = names
+            .into_iter()
+            .map(Into::into)
+            .map(|name: String| {
+                let handle = self.get(&name);
+                let failed = name.clone();
+                let transfer = async move {
+                    transfer(&handle?, calibration, check)
+                        .await
+                        .map_err(|error| ManagerError::ScaleFailed {
+                            name: failed,
+                            error,
+                        })
+                };
+                (name, tokio::spawn(transfer))
+            })
+            .collect();
+        let mut results = BTreeMap::new();
+        for (name, task) in tasks {
+            let result = task.await.unwrap_or_else(|e| {
+                Err(ManagerError::ScaleFailed {
+                    name: name.clone(),
+                    error: ScaleErrorInfo::new(
+                        ScaleErrorKind::Other,
+                        format!("Command task failed: {e}"),
+                    ),
+                })
+            });
+            results.insert(name, result);
+        }
+        results
+    }
+
+    /// The events of every scale, tagged with its name, and of any
+    /// [`HotPlugWatcher`], from now on.
+    ///
+    /// Each scale's events arrive in order, ending with
+    /// `ScaleEvent::Stopped`; those of different scales interleave. A
+    /// receiver that falls more than [`EVENT_CAPACITY`] events behind loses
+    /// the oldest, as `broadcast` receivers do. Once the manager has shut
+    /// down, every scale has stopped and every watcher is gone, the receiver
+    /// is closed.
+    pub fn events(&self) -> broadcast::Receiver<FleetEvent> {
+        self.shared.events.subscribe()
+    }
+
+    /// Shuts every actor down at once, as [`ScaleHandle::shutdown`] does,
+    /// and returns the closed scales by name once all of them have stopped.
+    /// A scale is `None` if the runtime shut down before it was closed.
+    /// Scales that a [`HotPlugWatcher`] connects from then on are refused.
+    pub async fn shutdown(self) -> BTreeMap<String, Option<S>> {
+        let by_name = {
+            let mut scales = self.shared.scales();
+            scales.shut_down = true;
+            std::mem::take(&mut scales.by_name)
+        };
+        let tasks: Vec<_> = by_name
+            .into_iter()
+            .map(|(name, managed)| (name, tokio::spawn(stop(managed))))
+            .collect();
+        let mut scales = BTreeMap::new();
+        for (name, task) in tasks {
+            scales.insert(name, task.await.ok().flatten());
+        }
+        scales
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl<V: VoltageSource + Send + 'static> ScaleManager<ConnectedScale<V>> {
+    /// A manager of the scales that connected in `connection`, by the names
+    /// in the fleet, with the failures of those that did not.
+    pub fn from_fleet(
+        connection: FleetConnection<V>,
+        config: ActorConfig,
+    ) -> (Self, BTreeMap<String, ScaleError>) {
+        let manager = Self::new(config);
+        for (name, scale) in connection.scales {
+            let device = (scale.get_phidget_id(), scale.hub_port());
+            // The names are the keys of a map, so none is taken.
+            let _ = manager.shared.insert(name, scale, Some(device));
+        }
+        (manager, connection.failures)
+    }
+
+    /// Assigns the scale `name` the bridge with serial number `serial`,
+    /// ahead of any bridge the fleet's config binds it to, for
+    /// [`HotPlugWatcher`]s connecting a fleet. A name the bridge was
+    /// assigned before loses it. If the bridge is waiting for a name, it
+    /// is connected under this one; a scale already connected keeps its
+    /// bridge until it is lost.
+    ///
+    /// With an [`AssignmentStore`](crate::store::AssignmentStore) from
+    /// [`persist_assignments`](Self::persist_assignments), the assignments
+    /// are saved to it, and fail with `ManagerError::AssignmentNotSaved`,
+    /// still made, if they cannot be. Fails with `ManagerError::ShutDown`
+    /// once the manager has shut down.
+    pub fn assign(&self, name: impl Into<String>, serial: i32) -> Result<(), ManagerError> {
+        let name = name.into();
+        let mut scales = self.shared.scales();
+        if scales.shut_down {
+            return Err(ManagerError::ShutDown);
+        }
+        let bindings = &mut scales.bindings;
+        bindings.assigned.retain(|_, assigned| *assigned != serial);
+        bindings.assigned.insert(name.clone(), serial);
+        #[cfg(feature = "store")]
+        let saved =
+            match &bindings.store {
+                Some(store) => store.save(&bindings.assigned).map_err(|error| {
+                    ManagerError::AssignmentNotSaved {
+                        name,
+                        message: error.to_string(),
+                    }
+                }),
+                None => Ok(()),
+            };
+        #[cfg(not(feature = "store"))]
+        let saved = Ok(());
+        drop(scales);
+        self.shared.assigned.send_replace(());
+        saved
+    }
+
+    /// The serial number of the bridge assigned to each name with
+    /// [`assign`](Self::assign).
+    pub fn assignments(&self) -> BTreeMap<String, i32> {
+        self.shared.scales().bindings.assigned.clone()
+    }
+
+    /// The bridges attached that are waiting for a name to be
+    /// [`assign`](Self::assign)ed, by serial number then hub port.
+    pub fn unassigned(&self) -> Vec<DiscoveredScale> {
+        let scales = self.shared.scales();
+        scales.bindings.unassigned.values().cloned().collect()
+    }
+
+    /// Replaces the manager's assignments with those saved in `store`, and
+    /// saves each made from then on to it, so that they outlast the
+    /// process. Bridges waiting for a name are bound again.
+    ///
+    /// Fails with the `AssignmentError` of reading the store, leaving the
+    /// assignments as they were.
+    #[cfg(feature = "store")]
+    pub fn persist_assignments(&self, store: AssignmentStore) -> Result<(), AssignmentError> {
+        let saved = store.load()?;
+        {
+            let mut scales = self.shared.scales();
+            scales.bindings.assigned = saved;
+            scales.bindings.store = Some(store);
+        }
+        self.shared.assigned.send_replace(());
+        Ok(())
+    }
+
+    /// Starts a [`HotPlugWatcher`] on the channels attached and detached in
+    /// `devices`, connecting the scales of `auto_connect` with `connect`.
+    pub fn watch_devices_with(
+        &self,
+        mut devices: mpsc::UnboundedReceiver<DeviceEvent>,
+        auto_connect: AutoConnect,
+        connect: impl Fn(&ScaleConfig) -> Result<ConnectedScale<V>, ScaleError> + Send + Sync + 'static,
+    ) -> HotPlugWatcher {
+        let shared = Arc::clone(&self.shared);
+        let connect = Arc::new(connect);
+        let mut assigned = shared.assigned.subscribe();
+        let task = tokio::spawn(async move {
+            let mut tracker = DeviceTracker::new();
+            loop {
+                let to_connect = tokio::select! {
+                    event = devices.recv() => {
+                        let Some(event) = event else {
+                            break;
+                        };
+                        let Some(change) = tracker.apply(event) else {
+                            continue;
+                        };
+                        shared.device_changed(&change, &auto_connect)
+                    }
+                    Ok(()) = assigned.changed() => shared.assignments_changed(&auto_connect),
+                };
+                for (name, config) in to_connect {
+                    let bridge = config.serial.map(|serial| (serial, config.hub_port));
+                    let connect = Arc::clone(&connect);
+                    let connected = tokio::task::spawn_blocking(move || connect(&config))
+                        .await
+                        .unwrap_or_else(|e| {
+                            Err(ScaleError::from(ScaleErrorInfo::new(
+                                ScaleErrorKind::Other,
+                                format!("Connecting panicked: {e}"),
+                            )))
+                        });
+                    let event = match connected {
+                        Ok(scale) => {
+                            match shared.insert(name.clone(), scale, bridge) {
+                                Ok(_) => FleetEvent::ScaleConnected(name),
+                                // Added since, or shut down.
+                                Err(_) => continue,
+                            }
+                        }
+                        Err(error) => FleetEvent::ConnectFailed {
+                            name,
+                            error: ScaleErrorInfo::from(&error),
+                        },
+                    };
+                    shared.send(event);
+                }
+            }
+        });
+        HotPlugWatcher {
+            task,
+            _devices: None,
+        }
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl ScaleManager<ConnectedScale> {
+    /// Starts a [`HotPlugWatcher`] on the phidget manager's attach and
+    /// detach events, connecting the scales of `auto_connect` with
+    /// [`ScaleConfig::connect`].
+    ///
+    /// Fails with a `ScaleError::PhidgetError` of Load Cell 0 if the phidget
+    /// manager cannot be opened.
+    pub fn watch_devices(&self, auto_connect: AutoConnect) -> Result<HotPlugWatcher, ScaleError> {
+        let (send, devices) = mpsc::unbounded_channel();
+        let watch = PhidgetManager::watch(move |event| {
+            // The watcher stopped, so there is nobody to tell.
+            let _ = send.send(event);
+        })?;
+        let mut watcher = self.watch_devices_with(devices, auto_connect, ScaleConfig::connect);
+        watcher._devices = Some(watch);
+        Ok(watcher)
+    }
+}
+
+/// Which scales a [`HotPlugWatcher`] connects as their bridges are
+/// attached.
+#[cfg(feature = "hardware")]
+#[derive(Clone, Debug, Default)]
+pub enum AutoConnect {
+    /// None: the watcher reports bridges attached and scales lost.
+    #[default]
+    Off,
+    /// The scales of the fleet, each under its name and with the
+    /// calibration in its config, when its bridge is attached.
+    ///
+    /// A name binds to the bridge assigned to it with
+    /// [`ScaleManager::assign`], if any, or else to the one with the serial
+    /// number in its config, on its hub port if it has one, or for a scale
+    /// with a label but no serial number, to the one with that label
+    /// written to it. A name assigned a bridge that is not in the fleet
+    /// takes the fleet's defaults. A bridge no name binds to is
+    /// `FleetEvent::ScaleUnassigned`, and one bound to a name the manager
+    /// already has on another bridge is a `FleetEvent::BindingConflict`;
+    /// neither is connected.
+    Fleet(FleetConfig),
+}
+
+#[cfg(feature = "hardware")]
+impl<S> Shared<S> {
+    /// Marks the scales on the bridge of `change` connected or lost, sends
+    /// the events that go with it, and returns the scales of `auto_connect`
+    /// to connect.
+    fn device_changed(
+        &self,
+        change: &DeviceChange,
+        auto_connect: &AutoConnect,
+    ) -> Vec<(String, ScaleConfig)> {
+        let on_bridge = |device: Option<(i32, Option<i32>)>, serial: i32, hub_port: Option<i32>| {
+            device.is_some_and(|(device_serial, device_port)| {
+                device_serial == serial && (device_port.is_none() || device_port == hub_port)
+            })
+        };
+        let mut events = Vec::new();
+        let mut to_connect = Vec::new();
+        {
+            let mut scales = self.scales();
+            match change {
+                DeviceChange::Available(device) => {
+                    events.push(FleetEvent::ScaleAvailable(device.serial));
+                    let mut known = false;
+                    for (name, managed) in &mut scales.by_name {
+                        if on_bridge(managed.device, device.serial, device.hub_port) {
+                            known = true;
+                            if managed.state == ScaleState::Disconnected {
+                                managed.state = ScaleState::Connected;
+                                managed.reconnects += 1;
+                                events.push(FleetEvent::ScaleConnected(name.clone()));
+                            }
+                        }
+                    }
+                    if let (false, AutoConnect::Fleet(fleet)) = (known, auto_connect) {
+                        to_connect.extend(scales.bind(device, fleet, &mut events));
+                    }
+                }
+                DeviceChange::Lost { serial, hub_port } => {
+                    scales.bindings.unassigned.remove(&(*serial, *hub_port));
+                    for (name, managed) in &mut scales.by_name {
+                        if managed.state == ScaleState::Connected
+                            && on_bridge(managed.device, *serial, *hub_port)
+                        {
+                            managed.state = ScaleState::Disconnected;
+                            events.push(FleetEvent::ScaleLost(name.clone()));
+                        }
+                    }
+                }
+            }
+        }
+        for event in events {
+            self.send(event);
+        }
+        to_connect
+    }
+
+    /// Binds the bridges waiting for a name that have since been assigned
+    /// one, sends the events that go with it, and returns the scales of
+    /// `auto_connect` to connect.
+    fn assignments_changed(&self, auto_connect: &AutoConnect) -> Vec<(String, ScaleConfig)> {
+        let AutoConnect::Fleet(fleet) = auto_connect else {
+            return Vec::new();
+        };
+        let mut events = Vec::new();
+        let to_connect = {
+            let mut scales = self.scales();
+            let bindings = &scales.bindings;
+            let waiting: Vec<_> = bindings
+                .unassigned
+                .values()
+                .filter(|device| {
+                    bindings
+                        .assigned
+                        .values()
+                        .any(|&serial| serial == device.serial)
+                })
+                .cloned()
+                .collect();
+            waiting
+                .iter()
+                .filter_map(|device| scales.bind(device, fleet, &mut events))
+                .collect()
+        };
+        for event in events {
+            self.send(event);
+        }
+        to_connect
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl<S> Scales<S> {
+    /// The name `device` binds to in `fleet`, as `AutoConnect::Fleet`
+    /// describes, with its config on that bridge, unless the name is taken
+    /// or there is none, when the bridge is left waiting and the event
+    /// saying why is pushed to `events`.
+    fn bind(
+        &mut self,
+        device: &DiscoveredScale,
+        fleet: &FleetConfig,
+        events: &mut Vec<FleetEvent>,
+    ) -> Option<(String, ScaleConfig)> {
+        let assigned = &self.bindings.assigned;
+        let bound = match assigned.iter().find(|(_, &serial)| serial == device.serial) {
+            Some((name, _)) => Some((
+                name.clone(),
+                fleet.scales.get(name).cloned().unwrap_or_else(|| {
+                    fleet
+                        .defaults
+                        .as_ref()
+                        .map(ScaleConfigDefaults::config)
+                        .unwrap_or_default()
+                }),
+            )),
+            None => fleet
+                .scales
+                .iter()
+                .filter(|(name, _)| !assigned.contains_key(*name))
+                .find(|(_, config)| match config.serial {
+                    Some(serial) => {
+                        serial == device.serial
+                            && (config.hub_port.is_none() || config.hub_port == device.hub_port)
+                    }
+                    None => config.label.is_some() && config.label == device.label,
+                })
+                .map(|(name, config)| (name.clone(), config.clone())),
+        };
+        let bridge = (device.serial, device.hub_port);
+        match bound {
+            None => {
+                self.bindings.unassigned.insert(bridge, device.clone());
+                events.push(FleetEvent::ScaleUnassigned {
+                    serial: device.serial,
+                    hub_port: device.hub_port,
+                });
+                None
+            }
+            Some((name, _)) if self.by_name.contains_key(&name) => {
+                self.bindings.unassigned.insert(bridge, device.clone());
+                events.push(FleetEvent::BindingConflict {
+                    name,
+                    serial: device.serial,
+                });
+                None
+            }
+            Some((name, config)) => {
+                self.bindings.unassigned.remove(&bridge);
+                Some((
+                    name,
+                    ScaleConfig {
+                        serial: Some(device.serial),
+                        hub_port: device.hub_port,
+                        ..config
+                    },
+                ))
+            }
+        }
+    }
+}
+
+/// Follows the bridges attached and detached for a [`ScaleManager`],
+/// sending `FleetEvent::ScaleAvailable` for each bridge with enough channels
+/// for a scale and `FleetEvent::ScaleLost` for each scale whose bridge is
+/// detached, and connecting the scales of its [`AutoConnect`] as their
+/// bridges are attached. Stops when dropped.
+///
+/// Only scales from [`ScaleManager::from_fleet`] or connected by a watcher
+/// are known by their bridge, so only they are ever lost.
+#[cfg(feature = "hardware")]
+pub struct HotPlugWatcher {
+    task: JoinHandle<()>,
+    /// The phidget manager feeding the watcher, if it is the library's.
+    _devices: Option<DeviceWatch>,
+}
+
+#[cfg(feature = "hardware")]
+impl Drop for HotPlugWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn stop<S>(managed: Managed<S>) -> Option<S> {
+    managed.handle.shutdown().await;
+    managed.task.await.ok().flatten()
+}
+
+/// The result `answer` finds in each response, with the responses that
+/// carry none turned into the error they stand for.
+fn answers<T>(
+    responses: BTreeMap<String, ScaleResponse>,
+    answer: impl Fn(ScaleResponse) -> Result<T, ScaleResponse>,
+) -> BTreeMap<String, Result<T, ScaleErrorInfo>> {
+    responses
+        .into_iter()
+        .map(|(name, response)| (name, answer(response).map_err(failure)))
+        .collect()
+}
+
+/// The health of the scale behind `handle`, from `config.samples` raw reads
+/// through its actor, with the days until its calibration expires if its
+/// status says. Fails with the error of a scale without raw readings.
+async fn check_health(
+    handle: &ScaleHandle,
+    config: &FleetStatusConfig,
+) -> Result<(HealthReport, Option<i64>), ScaleErrorInfo> {
+    let calibration_expires_in_days = match handle.send(ScaleCmd::GetStatus).await {
+        ScaleResponse::Status(status) => status.calibration_expires_in_days,
+        _ => None,
+    };
+    let mut reads = Vec::with_capacity(config.samples);
+    for sample in 0..config.samples {
+        if sample > 0 {
+            tokio::time::sleep(config.health.sample_interval).await;
+        }
+        reads.push(match handle.send(ScaleCmd::GetRawReadings).await {
+            ScaleResponse::RawReadings(ratios) => Ok(ratios),
+            response => Err(failure(response)),
+        });
+    }
+    if let Some(Err(error)) = reads.first() {
+        if error.kind == ScaleErrorKind::Unsupported {
+            return Err(error.clone());
+        }
+    }
+    let reads = reads.into_iter().map(|read| {
+        read.map_err(|error| -> Box<dyn std::error::Error + Send + Sync> {
+            Box::new(ScaleError::from(error))
+        })
+    });
+    let stale = *handle.watch_watchdog().borrow();
+    let report = health::assess(reads, stale, None, None, &config.health);
+    Ok((report, calibration_expires_in_days))
+}
+
+/// [`Calibration::transfer_check`] through the scale's actor.
+async fn transfer(
+    handle: &ScaleHandle,
+    calibration: Calibration,
+    check: TransferCheck,
+) -> Result<TransferResult, ScaleErrorInfo> {
+    let set = |calibration| async move {
+        match handle.send(ScaleCmd::SetCalibration(calibration)).await {
+            ScaleResponse::CalibrationSet => Ok(()),
+            response => Err(failure(response)),
+        }
+    };
+    let previous = match handle.send(ScaleCmd::GetCalibration).await {
+        ScaleResponse::Calibration(previous) => previous,
+        response => return Err(failure(response)),
+    };
+    set(calibration).await?;
+    let cmd = ScaleCmd::GetMedianWeight {
+        samples: check.samples,
+    };
+    let result = match handle.send(cmd).await {
+        ScaleResponse::MedianWeight(measured) => Ok(check.judge(Grams(measured.get()))),
+        response => Err(failure(response)),
+    };
+    if !matches!(result, Ok(TransferResult { passed: true, .. })) {
+        set(previous).await?;
+    }
+    result
+}
+
+fn failure(response: ScaleResponse) -> ScaleErrorInfo {
+    match response {
+        ScaleResponse::Error(info) => info,
+        ScaleResponse::InternalError(message) => {
+            ScaleErrorInfo::new(ScaleErrorKind::Other, message)
+        }
+        ScaleResponse::ShuttingDown => {
+            ScaleErrorInfo::new(ScaleErrorKind::Stopped, "Scale is shutting down")
+        }
+        response => ScaleErrorInfo::new(
+            ScaleErrorKind::Other,
+            format!("Unexpected response {response:?}"),
+        ),
+    }
+}
+
+/// Passes on the weights and watchdog warnings `handle` publishes as events
+/// of the scale `name`, then `ScaleEvent::Stopped` once its actor is gone.
+/// Holds no handle, so it does not keep the actor running.
+fn forward_events(name: String, handle: &ScaleHandle, events: broadcast::Sender<FleetEvent>) {
+    let mut weight = handle.watch_weight();
+    let mut watchdog = handle.watch_watchdog();
+    tokio::spawn(async move {
+        let send = |event| {
+            // No receivers is not a failure.
+            let _ = events.send(FleetEvent::Scale(NamedEvent {
+                name: name.clone(),
+                event,
+            }));
+        };
+        loop {
+            tokio::select! {
+                changed = weight.changed() => match changed {
+                    Ok(()) => {
+                        if let Some(stamped) = *weight.borrow_and_update() {
+                            send(ScaleEvent::Weight(stamped));
+                        }
+                    }
+                    Err(_) => break,
+                },
+                changed = watchdog.changed() => match changed {
+                    Ok(()) => send(ScaleEvent::Watchdog(*watchdog.borrow_and_update())),
+                    Err(_) => break,
+                },
+            }
+        }
+        send(ScaleEvent::Stopped);
+    });
+}
@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::{ScaleErrorKind, ScaleResponse};
+
+/// Upper bounds of the read latency histogram buckets, in seconds.
+pub const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1., 2.5, 5., 10.];
+
+/// Largest request head [`serve_metrics`] reads before answering.
+const MAX_REQUEST_LEN: usize = 8 * 1024;
+
+#[derive(Debug, Default)]
+struct State {
+    weight: Option<f64>,
+    queue_depth: usize,
+    reads: u64,
+    errors: BTreeMap<&'static str, u64>,
+    reconnects: u64,
+    /// Reads that fell in each bucket, not yet cumulative.
+    latency_buckets: [u64; LATENCY_BUCKETS.len()],
+    latency_sum: f64,
+}
+
+/// Scale health and readings, rendered in the Prometheus text format by
+/// [`exposition`](Self::exposition):
+///
+/// | Metric | Type | Meaning |
+/// |---|---|---|
+/// | `libra_weight_grams` | gauge | Most recent weight read, by a periodic sample or a weight command. Absent until the first read. |
+/// | `libra_queue_depth` | gauge | Commands waiting in the actor's queue. |
+/// | `libra_reads_total` | counter | Successful weight reads: periodic samples, single reads, medians and batches. |
+/// | `libra_errors_total{kind}` | counter | Failed samples and commands. `kind` is the snake_case `ScaleErrorKind`, or `internal` for a panic. |
+/// | `libra_reconnects_total` | counter | Times the scale was reattached after losing a channel. |
+/// | `libra_read_duration_seconds` | histogram | Time taken by each successful weight read. |
+///
+/// These names and labels are stable; new metrics may be added.
+///
+/// Clones share the same counters, so one can be handed to the actor through
+/// `ActorConfig::metrics` and another kept for [`exposition`](Self::exposition).
+#[derive(Clone, Debug, Default)]
+pub struct ScaleMetrics {
+    state: Arc<Mutex<State>>,
+}
+
+impl ScaleMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Counts a reattachment of the scale, for code that recovers a lost
+    /// phidget channel.
+    pub fn record_reconnect(&self) {
+        self.state().reconnects += 1;
+    }
+
+    /// Records the outcome of a sample or command that took `elapsed`.
+    pub(crate) fn record_response(&self, response: &ScaleResponse, elapsed: Duration) {
+        let mut state = self.state();
+        let weight = match response {
+            ScaleResponse::Weight(weight) => Some(weight.0),
+            ScaleResponse::MedianWeight(median) => Some(median.0),
+            ScaleResponse::WeightBatch(batch) => batch.last().map(|stamped| stamped.weight.0),
+            ScaleResponse::Error(info) => {
+                *state.errors.entry(kind_label(info.kind)).or_default() += 1;
+                return;
+            }
+            ScaleResponse::InternalError(_) => {
+                *state.errors.entry("internal").or_default() += 1;
+                return;
+            }
+            _ => return,
+        };
+        state.weight = weight.or(state.weight);
+        state.reads += 1;
+        let seconds = elapsed.as_secs_f64();
+        state.latency_sum += seconds;
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|&le| seconds <= le) {
+            state.latency_buckets[bucket] += 1;
+        }
+    }
+
+    pub(crate) fn set_queue_depth(&self, depth: usize) {
+        self.state().queue_depth = depth;
+    }
+
+    /// Every metric in the Prometheus text exposition format, version 0.0.4.
+    pub fn exposition(&self) -> String {
+        let state = self.state();
+        let mut out = String::new();
+        header(
+            &mut out,
+            "libra_weight_grams",
+            "gauge",
+            "Most recent weight read from the scale.",
+        );
+        if let Some(weight) = state.weight {
+            let _ = writeln!(out, "libra_weight_grams {weight}");
+        }
+        header(
+            &mut out,
+            "libra_queue_depth",
+            "gauge",
+            "Commands waiting in the actor's queue.",
+        );
+        let _ = writeln!(out, "libra_queue_depth {}", state.queue_depth);
+        header(
+            &mut out,
+            "libra_reads_total",
+            "counter",
+            "Successful weight reads.",
+        );
+        let _ = writeln!(out, "libra_reads_total {}", state.reads);
+        header(
+            &mut out,
+            "libra_errors_total",
+            "counter",
+            "Failed samples and commands by error kind.",
+        );
+        for (kind, count) in &state.errors {
+            let _ = writeln!(out, "libra_errors_total{{kind=\"{kind}\"}} {count}");
+        }
+        header(
+            &mut out,
+            "libra_reconnects_total",
+            "counter",
+            "Times the scale was reattached.",
+        );
+        let _ = writeln!(out, "libra_reconnects_total {}", state.reconnects);
+        header(
+            &mut out,
+            "libra_read_duration_seconds",
+            "histogram",
+            "Time taken by each successful weight read.",
+        );
+        let mut cumulative = 0;
+        for (le, count) in LATENCY_BUCKETS.iter().zip(state.latency_buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "libra_read_duration_seconds_bucket{{le=\"{le}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "libra_read_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            state.reads
+        );
+        let _ = writeln!(out, "libra_read_duration_seconds_sum {}", state.latency_sum);
+        let _ = writeln!(out, "libra_read_duration_seconds_count {}", state.reads);
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn kind_label(kind: ScaleErrorKind) -> &'static str {
+    match kind {
+        ScaleErrorKind::InvalidCoefficients => "invalid_coefficients",
+        ScaleErrorKind::InvalidPhidgetId => "invalid_phidget_id",
+        ScaleErrorKind::Phidget => "phidget",
+        ScaleErrorKind::Io => "io",
+        ScaleErrorKind::Busy => "busy",
+        ScaleErrorKind::Cancelled => "cancelled",
+        ScaleErrorKind::BatchTooLarge => "batch_too_large",
+        ScaleErrorKind::NotSettled => "not_settled",
+        ScaleErrorKind::Overflow => "overflow",
+        ScaleErrorKind::QueueFull => "queue_full",
+        ScaleErrorKind::Stopped => "stopped",
+        ScaleErrorKind::Unsupported => "unsupported",
+        ScaleErrorKind::InvalidCommand => "invalid_command",
+        ScaleErrorKind::Unauthorized => "unauthorized",
+        ScaleErrorKind::Other => "other",
+        ScaleErrorKind::Timeout => "timeout",
+        ScaleErrorKind::Disconnected => "disconnected",
+        ScaleErrorKind::InvalidArgument => "invalid_argument",
+        ScaleErrorKind::WrongDevice => "wrong_device",
+        ScaleErrorKind::StaleData => "stale_data",
+        ScaleErrorKind::CircuitOpen => "circuit_open",
+        ScaleErrorKind::NonFinite => "non_finite",
+        ScaleErrorKind::AlreadyConnected => "already_connected",
+        ScaleErrorKind::OverCapacity => "over_capacity",
+        ScaleErrorKind::CalibrationExpired => "calibration_expired",
+    }
+}
+
+/// Serves [`ScaleMetrics::exposition`] over HTTP at `GET /metrics` on
+/// `addr`, for Prometheus to scrape. Every other request gets a 404. Runs
+/// until its future is dropped; fails only if `addr` cannot be bound.
+pub async fn serve_metrics(metrics: ScaleMetrics, addr: impl ToSocketAddrs) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    serve_metrics_listener(metrics, listener).await;
+    Ok(())
+}
+
+/// Like [`serve_metrics`], on a listener that is already bound.
+pub async fn serve_metrics_listener(metrics: ScaleMetrics, listener: TcpListener) {
+    loop {
+        // A failed accept only loses that one scrape.
+        if let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(answer_scrape(metrics.clone(), stream));
+        }
+    }
+}
+
+async fn answer_scrape(metrics: ScaleMetrics, mut stream: TcpStream) -> io::Result<()> {
+    let mut head = Vec::new();
+    let mut buffer = [0; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 || head.len() > MAX_REQUEST_LEN {
+            return Ok(());
+        }
+        head.extend_from_slice(&buffer[..read]);
+    }
+    let response = if head.starts_with(b"GET /metrics ") {
+        let body = metrics.exposition();
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_owned()
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
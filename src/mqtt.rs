@@ -0,0 +1,240 @@
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, Event, EventLoop, LastWill, MqttOptions, Outgoing, Packet, QoS};
+use thiserror::Error;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::actor::ScaleHandle;
+use crate::retry::RetryPolicy;
+use crate::StampedWeight;
+
+/// Retained on the status topic while the publisher is connected.
+pub const STATUS_ONLINE: &str = "online";
+/// Retained on the status topic after a clean stop, and registered as the
+/// last will so the broker publishes it if the process dies.
+pub const STATUS_OFFLINE: &str = "offline";
+
+/// Messages waiting for the broker before further ones are dropped.
+const CLIENT_QUEUE_CAPACITY: usize = 16;
+/// How long a stopping publisher keeps trying to deliver its offline status.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// MQTT quality of service.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Qos {
+    #[default]
+    AtMostOnce,
+    AtLeastOnce,
+    ExactlyOnce,
+}
+
+impl From<Qos> for QoS {
+    fn from(qos: Qos) -> Self {
+        match qos {
+            Qos::AtMostOnce => QoS::AtMostOnce,
+            Qos::AtLeastOnce => QoS::AtLeastOnce,
+            Qos::ExactlyOnce => QoS::ExactlyOnce,
+        }
+    }
+}
+
+/// Where and how [`publish_weights`] publishes.
+#[derive(Clone, Debug)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    /// Weights go to `{topic_prefix}/weight` and the online status to
+    /// `{topic_prefix}/status`.
+    pub topic_prefix: String,
+    /// Weights are published at most this often. Readings in between are
+    /// skipped, so the newest reading is always the one sent.
+    pub publish_interval: Duration,
+    pub qos: Qos,
+    pub keep_alive: Duration,
+    /// Waits between attempts to reach the broker, which go on for as long
+    /// as the publisher runs, so `max_attempts` is not used. Defaults to
+    /// starting at 100 ms and doubling after each failure, up to 30 s.
+    pub reconnect: RetryPolicy,
+}
+
+impl MqttConfig {
+    /// Publishes once a second at QoS 0 to the broker at `host:port`.
+    pub fn new(
+        host: impl Into<String>,
+        port: u16,
+        client_id: impl Into<String>,
+        topic_prefix: impl Into<String>,
+    ) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            client_id: client_id.into(),
+            topic_prefix: topic_prefix.into(),
+            publish_interval: Duration::from_secs(1),
+            qos: Qos::default(),
+            keep_alive: Duration::from_secs(30),
+            reconnect: RetryPolicy {
+                max_attempts: u32::MAX,
+                initial_backoff: Duration::from_millis(100),
+                max_backoff: Duration::from_secs(30),
+                multiplier: 2.,
+                jitter: 0.,
+            },
+        }
+    }
+
+    pub fn weight_topic(&self) -> String {
+        format!("{}/weight", self.topic_prefix)
+    }
+
+    pub fn status_topic(&self) -> String {
+        format!("{}/status", self.topic_prefix)
+    }
+
+    /// Connection options for the broker, with [`STATUS_OFFLINE`] as the
+    /// retained last will on the status topic.
+    pub fn mqtt_options(&self) -> MqttOptions {
+        let mut options = MqttOptions::new(&self.client_id, &self.host, self.port);
+        options.set_keep_alive(self.keep_alive);
+        options.set_last_will(LastWill::new(
+            self.status_topic(),
+            STATUS_OFFLINE,
+            self.qos.into(),
+            true,
+        ));
+        options
+    }
+}
+
+/// A message that could not be queued for the broker and was dropped.
+#[derive(Error, Debug)]
+#[error("MQTT message to {topic} was dropped")]
+pub struct MqttError {
+    pub topic: String,
+}
+
+/// The sending half of an MQTT client.
+pub trait MqttPublisher {
+    /// Queues a message for the broker without waiting for it.
+    fn publish(
+        &self,
+        topic: &str,
+        qos: Qos,
+        retain: bool,
+        payload: Vec<u8>,
+    ) -> Result<(), MqttError>;
+}
+
+impl MqttPublisher for AsyncClient {
+    fn publish(
+        &self,
+        topic: &str,
+        qos: Qos,
+        retain: bool,
+        payload: Vec<u8>,
+    ) -> Result<(), MqttError> {
+        self.try_publish(topic, qos.into(), retain, payload)
+            .map_err(|_| MqttError {
+                topic: topic.to_owned(),
+            })
+    }
+}
+
+/// Publishes the weights sampled by the actor behind `handle` to the broker
+/// in `config`, as JSON `StampedWeight`s.
+///
+/// Publishing never holds up the scale: while the broker is unreachable,
+/// readings are dropped and the connection is retried in the background with
+/// a growing delay. Each successful connection retains [`STATUS_ONLINE`] on
+/// the status topic. The task ends when the actor stops, after retaining
+/// [`STATUS_OFFLINE`] and disconnecting.
+///
+/// The actor must have been started with a `sample_interval`, or there is
+/// nothing to publish.
+pub fn publish_weights(handle: &ScaleHandle, config: MqttConfig) -> JoinHandle<()> {
+    let weights = handle.watch_weight();
+    tokio::spawn(async move {
+        let (client, mut eventloop) =
+            AsyncClient::new(config.mqtt_options(), CLIENT_QUEUE_CAPACITY);
+        tokio::select! {
+            () = drive(&mut eventloop, &client, &config) => {}
+            () = publish_weights_with(weights, &client, &config) => {
+                let _ = client.try_disconnect();
+            }
+        }
+        let _ = tokio::time::timeout(FLUSH_TIMEOUT, drive_until_disconnected(&mut eventloop)).await;
+    })
+}
+
+/// The publishing half of [`publish_weights`], sending through any
+/// [`MqttPublisher`]. Resolves once `weights` has no sender left.
+pub async fn publish_weights_with<P: MqttPublisher>(
+    mut weights: watch::Receiver<Option<StampedWeight>>,
+    publisher: &P,
+    config: &MqttConfig,
+) {
+    let topic = config.weight_topic();
+    while weights.changed().await.is_ok() {
+        let Some(weight) = *weights.borrow_and_update() else {
+            continue;
+        };
+        // Plain numbers and a timestamp from the epoch serialize whatever
+        // their values; a NaN weight becomes null.
+        let payload = serde_json::to_vec(&weight).expect("a StampedWeight always serializes");
+        // A dropped reading is soon replaced by a newer one.
+        let _ = publisher.publish(&topic, config.qos, false, payload);
+        tokio::time::sleep(config.publish_interval).await;
+    }
+    let _ = publisher.publish(
+        &config.status_topic(),
+        config.qos,
+        true,
+        STATUS_OFFLINE.into(),
+    );
+}
+
+/// Runs the client's event loop, announcing the online status after each
+/// connection and backing off while the broker cannot be reached. Never
+/// returns.
+async fn drive(eventloop: &mut EventLoop, client: &AsyncClient, config: &MqttConfig) {
+    let mut failures = 0;
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                failures = 0;
+                let _ = MqttPublisher::publish(
+                    client,
+                    &config.status_topic(),
+                    config.qos,
+                    true,
+                    STATUS_ONLINE.into(),
+                );
+            }
+            Ok(_) => {}
+            Err(_e) => {
+                failures += 1;
+                let delay = config.reconnect.jittered_backoff(failures);
+                #[cfg(feature = "tracing")]
+                tracing::warn!(
+                    name: "reconnect_failed",
+                    target: "libra",
+                    attempt = failures,
+                    error = %_e,
+                    delay_ms = delay.as_millis() as u64
+                );
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+async fn drive_until_disconnected(eventloop: &mut EventLoop) {
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Outgoing(Outgoing::Disconnect)) | Err(_) => return,
+            Ok(_) => {}
+        }
+    }
+}
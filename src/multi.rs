@@ -0,0 +1,102 @@
+use std::thread;
+#[cfg(feature = "hardware")]
+use std::time::Duration;
+
+#[cfg(feature = "hardware")]
+use crate::scale::{ConnectedScale, ScaleError};
+#[cfg(feature = "hardware")]
+use crate::source::VoltageSource;
+#[cfg(feature = "tokio")]
+use crate::{shared::SharedScale, AsyncScale, AsyncScaleError, Scale};
+#[cfg(any(feature = "hardware", feature = "tokio"))]
+use crate::{Grams, MedianGrams};
+
+/// Runs `op` against every scale at the same time, one scoped thread per
+/// scale, and returns the results in the same order as `scales`.
+///
+/// Scales are taken by `&mut` because `ConnectedScale` is not `Sync`: each
+/// thread gets exclusive use of its own scale.
+pub fn run_concurrently<S, T, F>(scales: &mut [S], op: F) -> Vec<T>
+where
+    S: Send,
+    T: Send,
+    F: Fn(&mut S) -> T + Sync,
+{
+    let op = &op;
+    thread::scope(|scope| {
+        let handles: Vec<_> = scales
+            .iter_mut()
+            .map(|scale| scope.spawn(move || op(scale)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    })
+}
+
+/// Reads every scale at once. A failure on one scale does not stop the
+/// others; each result lines up with its scale in `scales`.
+#[cfg(feature = "hardware")]
+pub fn read_all<V: VoltageSource + Send>(
+    scales: &mut [ConnectedScale<V>],
+) -> Vec<Result<Grams, ScaleError>> {
+    run_concurrently(scales, |scale| scale.get_weight())
+}
+
+/// Takes a median from every scale at once, so the sampling windows overlap
+/// instead of running back to back.
+#[cfg(feature = "hardware")]
+pub fn read_all_medians<V: VoltageSource + Send>(
+    scales: &mut [ConnectedScale<V>],
+    samples: usize,
+    interval: Duration,
+) -> Vec<Result<MedianGrams, ScaleError>> {
+    run_concurrently(scales, |scale| scale.get_median_weight(samples, interval))
+}
+
+/// Async twin of [`read_all`]: every read runs on the blocking pool at the
+/// same time and the results keep input order.
+#[cfg(feature = "tokio")]
+pub async fn read_all_async<S>(scales: &[SharedScale<S>]) -> Vec<Result<Grams, AsyncScaleError>>
+where
+    S: Scale + Send + 'static,
+{
+    let tasks: Vec<_> = scales
+        .iter()
+        .cloned()
+        .map(|scale| tokio::spawn(async move { AsyncScale::get_weight(&scale).await }))
+        .collect();
+    let mut results = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        results.push(task.await.unwrap_or_else(|e| Err(e.into())));
+    }
+    results
+}
+
+/// Async twin of [`read_all_medians`].
+#[cfg(feature = "tokio")]
+pub async fn read_all_medians_async<S>(
+    scales: &[SharedScale<S>],
+    samples: usize,
+) -> Vec<Result<MedianGrams, AsyncScaleError>>
+where
+    S: Scale + Send + 'static,
+{
+    let tasks: Vec<_> = scales
+        .iter()
+        .cloned()
+        .map(|scale| {
+            tokio::spawn(async move { AsyncScale::get_median_weight(&scale, samples).await })
+        })
+        .collect();
+    let mut results = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        results.push(task.await.unwrap_or_else(|e| Err(e.into())));
+    }
+    results
+}
@@ -0,0 +1,536 @@
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader,
+};
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, Interval, MissedTickBehavior};
+use tokio_util::sync::CancellationToken;
+
+use crate::actor::ScaleHandle;
+use crate::auth::{AuthToken, DEFAULT_AUTH_FAILURE_DELAY};
+use crate::manager::{FleetStatusSource, ScaleManager};
+use crate::{
+    ListedScale, Reply, Scale, ScaleCmd, ScaleErrorInfo, ScaleErrorKind, ScaleResponse, ScaleState,
+};
+
+/// Commands from one connection that may be executing or waiting for their
+/// turn to be answered.
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 8;
+/// How long a connection may go without sending a command.
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+/// The longest line a connection may send, in bytes, line ending left out.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 64 * 1024;
+/// The name a server of one scale lists it under, and answers to besides
+/// no name at all.
+pub const DEFAULT_SCALE_NAME: &str = "scale";
+
+/// Options for [`serve_tcp_with_config`] and [`serve_listener`].
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    /// Commands a connection can have outstanding. Once reached, the
+    /// connection is not read again until the oldest is answered.
+    pub max_in_flight: usize,
+    /// A connection that sends no command for this long stops being read and
+    /// is closed once its outstanding commands are answered.
+    pub idle_timeout: Duration,
+    /// Token a connection must send in a `ScaleCmd::Auth` before any command
+    /// but `Hello`. A connection that sends another command first, or the
+    /// wrong token, is answered with an `Unauthorized` error after
+    /// `auth_failure_delay` and closed.
+    pub auth: Option<AuthToken>,
+    pub auth_failure_delay: Duration,
+    /// How long a connection may go without being sent anything before it
+    /// is sent a `ScaleResponse::Heartbeat`, or `None` for no heartbeats.
+    /// Off by default, since clients that predate heartbeats would take one
+    /// for the answer to their next command.
+    pub heartbeat_interval: Option<Duration>,
+    /// The longest line a connection may send, in bytes. A longer one is
+    /// answered with an `InvalidCommand` error and the connection closed.
+    pub max_line_length: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            auth: None,
+            auth_failure_delay: DEFAULT_AUTH_FAILURE_DELAY,
+            heartbeat_interval: None,
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+        }
+    }
+}
+
+/// Serves the scale command protocol on `addr` until the actor behind
+/// `handle` stops.
+///
+/// Clients send one JSON [`ScaleCmd`] per line and get one JSON
+/// [`ScaleResponse`] per line back, in the order the commands were sent. A
+/// line that is not a valid command is answered with a `ScaleResponse::Error`
+/// of kind `InvalidCommand`, and the connection stays open. A line longer
+/// than [`ServerConfig::max_line_length`] is answered the same way, but the
+/// connection is closed.
+///
+/// A line can also hold a [`Request`](crate::Request), which is answered
+/// with a [`Reply`] carrying its id, so clients that pipeline commands can
+/// match replies with a [`Correlator`](crate::correlation::Correlator). Bare
+/// commands and requests can be mixed on one connection.
+///
+/// The scale is listed by `ScaleCmd::ListScales` as [`DEFAULT_SCALE_NAME`].
+/// A request that names another scale is answered with
+/// `ScaleResponse::UnknownScale`; see [`serve_fleet`] for serving several.
+///
+/// With a [`ServerConfig::heartbeat_interval`], a `ScaleResponse::Heartbeat`
+/// line is sent whenever a connection has been sent nothing for that long,
+/// including while a slow command runs. Heartbeats are always bare, come only
+/// between whole lines and answer no command.
+///
+/// The server holds a clone of `handle`, so it keeps the actor running;
+/// shut the actor down to stop the server. Open connections are closed when
+/// the server stops or its future is dropped, once their outstanding commands
+/// are answered. Fails only if `addr` cannot be bound.
+pub fn serve_tcp(
+    handle: ScaleHandle,
+    addr: impl ToSocketAddrs,
+) -> impl Future<Output = io::Result<()>> {
+    serve_tcp_with_config(handle, addr, ServerConfig::default())
+}
+
+/// Like [`serve_tcp`], with the options in `config`.
+pub async fn serve_tcp_with_config(
+    handle: ScaleHandle,
+    addr: impl ToSocketAddrs,
+    config: ServerConfig,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    serve_listener(handle, listener, config).await;
+    Ok(())
+}
+
+/// Like [`serve_tcp_with_config`], on a listener that is already bound, for
+/// example to port 0 to let the system pick one.
+pub async fn serve_listener(handle: ScaleHandle, listener: TcpListener, config: ServerConfig) {
+    serve_fleet_listener(Fleet::One(handle), listener, config).await;
+}
+
+/// Like [`serve_listener`], answering for every scale of `manager`, those
+/// added later included, until the future is dropped.
+///
+/// A [`Request`](crate::Request) names the scale its command is for in its
+/// `scale` field, and a line of `{"scale":"flour","cmd":"GetWeight"}` does
+/// the same for a bare command. A command that names no scale goes to the
+/// only one, so clients of a single-scale server keep working while the
+/// manager has just one. One that names a scale the manager does not have,
+/// or none while it has several, is answered with
+/// `ScaleResponse::UnknownScale`.
+///
+/// `ScaleCmd::ListScales` and `ScaleCmd::Hello` are answered by the server
+/// itself, whatever scale they name, and so is a `ScaleCmd::GetStatus` that
+/// names none while the manager has other than one scale, with the
+/// manager's [`fleet_status`](ScaleManager::fleet_status). Heartbeats carry the uptime of the
+/// longest-running actor and the age of the latest reading of any.
+pub fn serve_fleet<S: Scale + Send + 'static>(
+    manager: &ScaleManager<S>,
+    listener: TcpListener,
+    config: ServerConfig,
+) -> impl Future<Output = ()> + Send + 'static {
+    serve_fleet_listener(Fleet::Many(manager.directory()), listener, config)
+}
+
+async fn serve_fleet_listener(fleet: Fleet, listener: TcpListener, config: ServerConfig) {
+    let stop = CancellationToken::new();
+    let _close_connections = stop.clone().drop_guard();
+    loop {
+        tokio::select! {
+            () = fleet.stopped() => return,
+            accepted = listener.accept() => {
+                // A failed accept only loses that one connection.
+                if let Ok((stream, _)) = accepted {
+                    tokio::spawn(serve_connection(
+                        fleet.clone(),
+                        stream,
+                        config.clone(),
+                        stop.clone(),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// The scales a server answers for.
+#[derive(Clone)]
+pub(crate) enum Fleet {
+    /// One scale, under [`DEFAULT_SCALE_NAME`].
+    One(ScaleHandle),
+    /// The scales of a [`ScaleManager`].
+    Many(Arc<dyn ScaleDirectory>),
+}
+
+/// A [`ScaleManager`]'s scales, as seen by the servers answering for them.
+pub(crate) trait ScaleDirectory: FleetStatusSource {
+    fn get(&self, name: &str) -> Option<ScaleHandle>;
+    /// Every scale, ordered by name, with its handle.
+    fn scales(&self) -> Vec<(ListedScale, ScaleHandle)>;
+}
+
+impl Fleet {
+    /// Resolves once a server of the fleet should stop: when its one
+    /// actor does, or never for a manager's.
+    pub(crate) async fn stopped(&self) {
+        match self {
+            Fleet::One(handle) => handle.stopped().await,
+            Fleet::Many(_) => std::future::pending().await,
+        }
+    }
+
+    fn list(&self) -> Vec<ListedScale> {
+        match self {
+            Fleet::One(handle) => vec![ListedScale {
+                name: DEFAULT_SCALE_NAME.into(),
+                state: ScaleState::Connected,
+                data_interval_ms: handle
+                    .data_interval()
+                    .map(|interval| interval.as_millis() as u64),
+            }],
+            Fleet::Many(directory) => directory
+                .scales()
+                .into_iter()
+                .map(|(listed, _)| listed)
+                .collect(),
+        }
+    }
+
+    fn handles(&self) -> Vec<ScaleHandle> {
+        match self {
+            Fleet::One(handle) => vec![handle.clone()],
+            Fleet::Many(directory) => directory
+                .scales()
+                .into_iter()
+                .map(|(_, handle)| handle)
+                .collect(),
+        }
+    }
+
+    /// The handle of the scale `name`, or of the only scale for no name,
+    /// or the answer for a command that cannot be routed.
+    fn route(&self, name: Option<&str>) -> Result<ScaleHandle, ScaleResponse> {
+        let unknown = |available| ScaleResponse::UnknownScale {
+            name: name.map(Into::into),
+            available,
+        };
+        match self {
+            Fleet::One(handle) => match name {
+                None | Some(DEFAULT_SCALE_NAME) => Ok(handle.clone()),
+                Some(_) => Err(unknown(vec![DEFAULT_SCALE_NAME.into()])),
+            },
+            Fleet::Many(directory) => {
+                if let Some(handle) = name.and_then(|name| directory.get(name)) {
+                    return Ok(handle);
+                }
+                let mut scales = directory.scales();
+                if name.is_none() && scales.len() == 1 {
+                    return Ok(scales.remove(0).1);
+                }
+                Err(unknown(
+                    scales.into_iter().map(|(listed, _)| listed.name).collect(),
+                ))
+            }
+        }
+    }
+
+    /// Runs `cmd` on the scale `name`, or answers it for the fleet.
+    async fn send(&self, name: Option<&str>, cmd: ScaleCmd, id: Option<u64>) -> ScaleResponse {
+        match (self, &cmd) {
+            (_, ScaleCmd::ListScales) => return ScaleResponse::Scales(self.list()),
+            (Fleet::Many(_), ScaleCmd::Hello { .. }) => return ScaleResponse::hello_ack(),
+            (Fleet::Many(directory), ScaleCmd::GetStatus)
+                if name.is_none() && directory.scales().len() != 1 =>
+            {
+                return ScaleResponse::FleetStatus(directory.fleet_status().await)
+            }
+            _ => {}
+        }
+        match (self.route(name), id) {
+            (Ok(handle), Some(id)) => handle.send_correlated(cmd, id).await,
+            (Ok(handle), None) => handle.send(cmd).await,
+            (Err(response), _) => response,
+        }
+    }
+}
+
+/// Answers JSON-lines commands from `stream` until the client closes it,
+/// goes idle, stops reading responses, or `stop` is cancelled.
+pub(crate) async fn serve_connection<T>(
+    fleet: Fleet,
+    stream: T,
+    config: ServerConfig,
+    stop: CancellationToken,
+) where
+    T: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (read, mut write) = tokio::io::split(stream);
+    // Replies in command order. The channel's capacity is the in-flight limit.
+    let (pending, mut replies) = mpsc::channel::<JoinHandle<Answer>>(config.max_in_flight.max(1));
+    let mut heartbeats = config.heartbeat_interval.map(|period| {
+        let mut heartbeats = tokio::time::interval_at(Instant::now() + period, period);
+        heartbeats.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        heartbeats
+    });
+    let scales = fleet.clone();
+
+    let reader = async move {
+        let mut read = BufReader::new(read);
+        let mut authenticated = config.auth.is_none();
+        loop {
+            let line = tokio::select! {
+                line = tokio::time::timeout(
+                    config.idle_timeout,
+                    read_line(&mut read, config.max_line_length),
+                ) => line,
+                // The writer gave up, so nobody would see the answers.
+                _ = pending.closed() => break,
+                _ = stop.cancelled() => break,
+            };
+            let line = match line {
+                Ok(Ok(Line::Read(line))) => line,
+                Ok(Ok(Line::TooLong)) => {
+                    let response = ScaleResponse::Error(ScaleErrorInfo::new(
+                        ScaleErrorKind::InvalidCommand,
+                        format!("Lines are limited to {} bytes", config.max_line_length),
+                    ));
+                    let reply = tokio::spawn(async move { Answer::Bare(response) });
+                    let _ = pending.send(reply).await;
+                    break;
+                }
+                // Idle, closed by the client, or unreadable.
+                _ => break,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (id, scale, cmd) = decode(&line);
+            let refusal = match (&config.auth, &cmd) {
+                (Some(token), Ok(ScaleCmd::Auth { token: candidate })) => {
+                    authenticated = token.matches(candidate);
+                    (!authenticated).then_some("Wrong token")
+                }
+                (Some(_), Ok(ScaleCmd::Hello { .. })) => None,
+                (Some(_), _) if !authenticated => {
+                    Some("Send Auth with the server's token before any command")
+                }
+                _ => None,
+            };
+            let fleet = fleet.clone();
+            let delay = config.auth_failure_delay;
+            let reply = tokio::spawn(async move {
+                let response = match (refusal, cmd) {
+                    (Some(message), _) => {
+                        tokio::time::sleep(delay).await;
+                        ScaleResponse::Error(ScaleErrorInfo::new(
+                            ScaleErrorKind::Unauthorized,
+                            message,
+                        ))
+                    }
+                    // Checked above; there is nothing for the scale to do.
+                    (None, Ok(ScaleCmd::Auth { .. })) => ScaleResponse::Authenticated,
+                    (None, Ok(cmd)) => fleet.send(scale.as_deref(), cmd, id).await,
+                    (None, Err(response)) => response,
+                };
+                match id {
+                    Some(id) => Answer::Reply(Reply { id, response }),
+                    None => Answer::Bare(response),
+                }
+            });
+            if pending.send(reply).await.is_err() || refusal.is_some() {
+                break;
+            }
+        }
+    };
+
+    let writer = async move {
+        loop {
+            let mut reply = tokio::select! {
+                reply = replies.recv() => match reply {
+                    Some(reply) => reply,
+                    None => break,
+                },
+                () = heartbeat_due(&mut heartbeats) => {
+                    write_line(&mut write, &heartbeat(&scales)).await?;
+                    continue;
+                }
+            };
+            let answer = loop {
+                tokio::select! {
+                    answer = &mut reply => break answer,
+                    () = heartbeat_due(&mut heartbeats) => {
+                        write_line(&mut write, &heartbeat(&scales)).await?;
+                    }
+                }
+            };
+            let answer = answer.unwrap_or_else(|e| {
+                Answer::Bare(ScaleResponse::InternalError(format!(
+                    "Command task failed: {e}"
+                )))
+            });
+            write_line(&mut write, &answer).await?;
+            if let Some(heartbeats) = &mut heartbeats {
+                heartbeats.reset();
+            }
+        }
+        write.shutdown().await
+    };
+
+    let ((), _) = tokio::join!(reader, writer);
+}
+
+async fn write_line<W>(write: &mut W, message: &impl Serialize) -> io::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut line = serde_json::to_vec(message).map_err(io::Error::other)?;
+    line.push(b'\n');
+    write.write_all(&line).await
+}
+
+/// Resolves when the next heartbeat is due, or never without heartbeats.
+async fn heartbeat_due(heartbeats: &mut Option<Interval>) {
+    match heartbeats {
+        Some(heartbeats) => {
+            heartbeats.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+fn heartbeat(fleet: &Fleet) -> ScaleResponse {
+    let handles = fleet.handles();
+    ScaleResponse::Heartbeat {
+        uptime: handles
+            .iter()
+            .map(ScaleHandle::uptime)
+            .max()
+            .unwrap_or_default(),
+        last_reading_age: handles
+            .iter()
+            .filter_map(|handle| {
+                let latest = *handle.watch_weight().borrow();
+                latest.map(|sample| {
+                    SystemTime::now()
+                        .duration_since(sample.timestamp)
+                        .unwrap_or_default()
+                })
+            })
+            .min(),
+    }
+}
+
+/// A line's answer, shaped the way its command was sent.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Answer {
+    Bare(ScaleResponse),
+    Reply(Reply),
+}
+
+/// What [`read_line`] read.
+pub(crate) enum Line {
+    /// A line, without its line ending.
+    Read(String),
+    /// A line longer than allowed, of which some has been read.
+    TooLong,
+    Closed,
+}
+
+/// The next line of `reader`, if no longer than `max` bytes, reading no
+/// more than one byte past that.
+pub(crate) async fn read_line<R>(reader: &mut R, max: usize) -> io::Result<Line>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut line = Vec::new();
+    let limit = u64::try_from(max).unwrap_or(u64::MAX).saturating_add(1);
+    if reader.take(limit).read_until(b'\n', &mut line).await? == 0 {
+        return Ok(Line::Closed);
+    }
+    end_line(line, max)
+}
+
+/// `line`, as read up to a byte past `max`, without its line ending.
+pub(crate) fn end_line(mut line: Vec<u8>, max: usize) -> io::Result<Line> {
+    if line.last() == Some(&b'\n') {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    } else if line.len() > max {
+        return Ok(Line::TooLong);
+    }
+    String::from_utf8(line)
+        .map(Line::Read)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/// The command on `line`, the id to answer it under if it came in a
+/// [`Request`], and the scale it names, if any. A line that is not a valid
+/// command gets its answer instead.
+fn decode(line: &str) -> (Option<u64>, Option<String>, Result<ScaleCmd, ScaleResponse>) {
+    /// A [`Request`] whose command is decoded separately, so that even an
+    /// invalid one is answered under its id. Without an id, it is a bare
+    /// command sent to the scale named.
+    #[derive(Deserialize)]
+    struct Envelope {
+        id: Option<u64>,
+        scale: Option<String>,
+        cmd: Value,
+    }
+
+    let error = match serde_json::from_str::<ScaleCmd>(line) {
+        Ok(cmd) => return (None, None, Ok(cmd)),
+        Err(e) => e,
+    };
+    match serde_json::from_str::<Envelope>(line) {
+        Ok(Envelope { id, scale, cmd }) if id.is_some() || scale.is_some() => {
+            match ScaleCmd::deserialize(&cmd) {
+                Ok(cmd) => (id, scale, Ok(cmd)),
+                Err(e) => (id, scale, Err(undecodable(Some(cmd), e))),
+            }
+        }
+        _ => (
+            None,
+            None,
+            Err(undecodable(serde_json::from_str(line).ok(), error)),
+        ),
+    }
+}
+
+/// The answer to a command that does not decode: `Unsupported` if it names
+/// a command this server does not know, as in `"Frobnicate"` or
+/// `{"Frobnicate":{}}`, or an `InvalidCommand` error otherwise.
+fn undecodable(value: Option<Value>, error: serde_json::Error) -> ScaleResponse {
+    let name = match value {
+        Some(Value::String(name)) => Some(name),
+        Some(Value::Object(fields)) if fields.len() == 1 => {
+            fields.into_iter().next().map(|(name, _)| name)
+        }
+        _ => None,
+    };
+    match name {
+        Some(command) if !ScaleCmd::NAMES.contains(&command.as_str()) => {
+            ScaleResponse::Unsupported { command }
+        }
+        _ => ScaleResponse::Error(ScaleErrorInfo::new(
+            ScaleErrorKind::InvalidCommand,
+            format!("Invalid command: {error}"),
+        )),
+    }
+}
@@ -0,0 +1,27 @@
+use std::time::Duration;
+
+/// What a bounded buffer does with a new item when it is already full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Discard the oldest buffered item to make room. Consumers always see
+    /// the most recent data.
+    DropOldest,
+    /// Discard the new item. Consumers see an unbroken run of older data.
+    DropNewest,
+    /// Make the producer wait up to `timeout` for room, then discard the new
+    /// item. Buffers fed by a sampling thread cap the wait at one sample
+    /// interval so hardware reads are never held up for longer than that.
+    Block { timeout: Duration },
+    /// Refuse the new item and report the overflow as an error.
+    Error,
+}
+
+/// Counters for a bounded buffer, as returned by the `stats` accessor on the
+/// handle that owns it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OverflowStats {
+    /// Items currently waiting in the buffer.
+    pub queued: usize,
+    /// Items discarded or refused so far because the buffer was full.
+    pub dropped: u64,
+}
@@ -0,0 +1,325 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use tokio::sync::Notify;
+
+use crate::overflow::{OverflowPolicy, OverflowStats};
+
+/// Priority items [`QueueReceiver::recv`] hands out in a row while normal
+/// items are waiting, before letting one normal item through.
+pub(crate) const MAX_PRIORITY_STREAK: usize = 4;
+
+/// Which of the queue's two lanes an item goes into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Lane {
+    Normal,
+    Priority,
+}
+
+impl Lane {
+    #[cfg(feature = "tracing")]
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Lane::Normal => "normal",
+            Lane::Priority => "priority",
+        }
+    }
+}
+
+/// Why [`QueueSender::push`] did not queue an item.
+pub(crate) enum PushError<T> {
+    /// The receiver has closed the queue.
+    Closed(T),
+    /// The queue was full and the policy refused the item.
+    Full(T),
+}
+
+/// Result of a successful [`QueueSender::push`].
+pub(crate) enum Pushed<T> {
+    Queued,
+    /// Queued, evicting this older item under `OverflowPolicy::DropOldest`.
+    Evicted(T),
+}
+
+struct State<T> {
+    normal: VecDeque<T>,
+    priority: VecDeque<T>,
+    /// Priority items received back to back while normal items waited.
+    priority_streak: usize,
+    closed: bool,
+}
+
+impl<T> State<T> {
+    fn lane(&mut self, lane: Lane) -> &mut VecDeque<T> {
+        match lane {
+            Lane::Normal => &mut self.normal,
+            Lane::Priority => &mut self.priority,
+        }
+    }
+
+    /// The next item, and the lane it was in.
+    fn pop(&mut self) -> Option<(Lane, T)> {
+        let starved = self.priority_streak >= MAX_PRIORITY_STREAK && !self.normal.is_empty();
+        if !starved {
+            if let Some(item) = self.priority.pop_front() {
+                if !self.normal.is_empty() {
+                    self.priority_streak += 1;
+                }
+                return Some((Lane::Priority, item));
+            }
+        }
+        self.priority_streak = 0;
+        self.normal.pop_front().map(|item| (Lane::Normal, item))
+    }
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    /// Capacity of each lane.
+    capacity: usize,
+    policy: OverflowPolicy,
+    /// Signalled when an item is queued or the queue closes.
+    ready: Notify,
+    /// Signalled when an item is taken from the normal lane or the queue
+    /// closes. Each lane has its own, so a slot freed in one never wakes a
+    /// sender waiting on the other in place of one waiting on it.
+    normal_space: Notify,
+    /// As `normal_space`, for the priority lane.
+    priority_space: Notify,
+    senders: AtomicUsize,
+    dropped: AtomicU64,
+}
+
+impl<T> Shared<T> {
+    fn state(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn space(&self, lane: Lane) -> &Notify {
+        match lane {
+            Lane::Normal => &self.normal_space,
+            Lane::Priority => &self.priority_space,
+        }
+    }
+
+    fn close(&self) {
+        self.state().closed = true;
+        self.ready.notify_one();
+        self.normal_space.notify_waiters();
+        self.priority_space.notify_waiters();
+    }
+
+    fn taken(&self, lane: Lane, item: Option<T>) -> Option<T> {
+        if item.is_some() {
+            self.space(lane).notify_one();
+        }
+        item
+    }
+}
+
+/// A bounded multi-producer, single-consumer queue whose behavior when full is
+/// set by an [`OverflowPolicy`]. `tokio::sync::mpsc` can only make senders
+/// wait; this one can also evict or refuse.
+///
+/// Items go into a normal or a priority lane, each holding up to `capacity`.
+/// Priority items are received first, but never more than
+/// [`MAX_PRIORITY_STREAK`] in a row while normal items wait.
+pub(crate) fn bounded<T>(
+    capacity: usize,
+    policy: OverflowPolicy,
+) -> (QueueSender<T>, QueueReceiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            normal: VecDeque::new(),
+            priority: VecDeque::new(),
+            priority_streak: 0,
+            closed: false,
+        }),
+        capacity: capacity.max(1),
+        policy,
+        ready: Notify::new(),
+        normal_space: Notify::new(),
+        priority_space: Notify::new(),
+        senders: AtomicUsize::new(1),
+        dropped: AtomicU64::new(0),
+    });
+    (
+        QueueSender {
+            shared: Arc::clone(&shared),
+        },
+        QueueReceiver { shared },
+    )
+}
+
+/// Sending side of [`bounded`]. The queue closes when the last sender drops.
+pub(crate) struct QueueSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueSender<T> {
+    pub(crate) async fn push(&self, lane: Lane, item: T) -> Result<Pushed<T>, PushError<T>> {
+        let shared = &self.shared;
+        let deadline = match shared.policy {
+            OverflowPolicy::Block { timeout } => tokio::time::Instant::now().checked_add(timeout),
+            _ => None,
+        };
+        loop {
+            // Register before checking so a slot freed in between still wakes us.
+            let space = shared.space(lane).notified();
+            {
+                let mut state = shared.state();
+                if state.closed {
+                    return Err(PushError::Closed(item));
+                }
+                let items = state.lane(lane);
+                if items.len() < shared.capacity {
+                    items.push_back(item);
+                    drop(state);
+                    shared.ready.notify_one();
+                    return Ok(Pushed::Queued);
+                }
+                match shared.policy {
+                    OverflowPolicy::DropOldest => {
+                        let evicted = items.pop_front();
+                        items.push_back(item);
+                        drop(state);
+                        shared.dropped.fetch_add(1, Ordering::Relaxed);
+                        shared.ready.notify_one();
+                        return Ok(evicted.map_or(Pushed::Queued, Pushed::Evicted));
+                    }
+                    OverflowPolicy::DropNewest | OverflowPolicy::Error => {
+                        shared.dropped.fetch_add(1, Ordering::Relaxed);
+                        return Err(PushError::Full(item));
+                    }
+                    OverflowPolicy::Block { .. } => {}
+                }
+            }
+            match deadline {
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, space).await.is_err() {
+                        shared.dropped.fetch_add(1, Ordering::Relaxed);
+                        return Err(PushError::Full(item));
+                    }
+                }
+                // The timeout is too long to represent, so wait indefinitely.
+                None => space.await,
+            }
+        }
+    }
+
+    pub(crate) fn stats(&self) -> OverflowStats {
+        let state = self.shared.state();
+        OverflowStats {
+            queued: state.normal.len() + state.priority.len(),
+            dropped: self.shared.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<T> Clone for QueueSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for QueueSender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.close();
+        }
+    }
+}
+
+/// Receiving side of [`bounded`].
+pub(crate) struct QueueReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueReceiver<T> {
+    /// Waits for the next item. Resolves to `None` once the queue is closed
+    /// and empty. Cancel safe: an item is only taken when this resolves.
+    pub(crate) async fn recv(&mut self) -> Option<T> {
+        loop {
+            {
+                let mut state = self.shared.state();
+                if let Some((lane, item)) = state.pop() {
+                    drop(state);
+                    return self.shared.taken(lane, Some(item));
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            // Single consumer: `notify_one` stores a permit if we are not yet
+            // waiting, so an item queued in between is not missed.
+            self.shared.ready.notified().await;
+        }
+    }
+
+    /// Takes the next normal item without waiting, but only if `pred`
+    /// accepts it.
+    pub(crate) fn try_recv_if(&mut self, pred: impl FnOnce(&T) -> bool) -> Option<T> {
+        let mut state = self.shared.state();
+        if !pred(state.normal.front()?) {
+            return None;
+        }
+        let item = state.normal.pop_front();
+        drop(state);
+        self.shared.taken(Lane::Normal, item)
+    }
+
+    /// A handle for taking priority items from another thread while the
+    /// receiver is busy.
+    pub(crate) fn priority_lane(&self) -> PriorityLane<T> {
+        PriorityLane {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    /// Items waiting in both lanes.
+    pub(crate) fn len(&self) -> usize {
+        let state = self.shared.state();
+        state.normal.len() + state.priority.len()
+    }
+
+    /// Stops accepting items. Already queued items can still be received.
+    pub(crate) fn close(&mut self) {
+        self.shared.close();
+    }
+}
+
+impl<T> Drop for QueueReceiver<T> {
+    /// Closes the queue and drops whatever is left in it, so senders find out
+    /// immediately instead of queueing for a receiver that is gone.
+    fn drop(&mut self) {
+        self.shared.close();
+        let mut state = self.shared.state();
+        let _unsent = (
+            std::mem::take(&mut state.normal),
+            std::mem::take(&mut state.priority),
+        );
+    }
+}
+
+/// See [`QueueReceiver::priority_lane`].
+pub(crate) struct PriorityLane<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> PriorityLane<T> {
+    /// Takes the next priority item without waiting, but only if `pred`
+    /// accepts it.
+    pub(crate) fn try_recv_if(&self, pred: impl FnOnce(&T) -> bool) -> Option<T> {
+        let mut state = self.shared.state();
+        if !pred(state.priority.front()?) {
+            return None;
+        }
+        let item = state.priority.pop_front();
+        drop(state);
+        self.shared.taken(Lane::Priority, item)
+    }
+}
@@ -0,0 +1,464 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::OnceLock;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::calibration::Calibration;
+use crate::cancel::CancelFlag;
+use crate::sampling::collect_median;
+use crate::scale::{ScaleError, DEFAULT_MEDIAN_SAMPLES, NUMBER_OF_INPUTS};
+use crate::{Grams, MedianGrams, Scale, ScaleStatus, StampedReadings};
+
+/// Version written in the header of new recordings. Recordings with a newer
+/// version are refused.
+pub const RECORDING_VERSION: u32 = 1;
+
+/// How long an attached [`Recorder`] keeps snapshots buffered while no new
+/// ones arrive.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Error, Debug)]
+pub enum RecordingError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+
+    #[error("Line {line} of the recording is invalid: {message}")]
+    Invalid { line: usize, message: String },
+
+    #[error("The recording does not start with a header")]
+    MissingHeader,
+
+    #[error("Recording version {0} is newer than this libra understands")]
+    UnsupportedVersion(u32),
+
+    /// A [`ReplayScale`] was read after its last snapshot.
+    #[error("The recording ended after {snapshots} snapshots")]
+    Ended { snapshots: usize },
+}
+
+/// The setup of the scale a recording was taken from.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordingHeader {
+    pub version: u32,
+    pub phidget_id: Option<i32>,
+    pub calibration: Calibration,
+    /// The tare taken off every recorded weight.
+    pub tare: Grams,
+    /// When the recording started. Snapshots are timed from here.
+    pub started: SystemTime,
+}
+
+impl RecordingHeader {
+    /// A header for a recording starting now of a scale calibrated with
+    /// `calibration` and not tared.
+    pub fn new(calibration: Calibration) -> Self {
+        Self {
+            version: RECORDING_VERSION,
+            phidget_id: None,
+            calibration,
+            tare: Grams(0.),
+            started: SystemTime::now(),
+        }
+    }
+
+    /// A header for a recording starting now of the scale `status` describes.
+    pub fn from_status(status: &ScaleStatus) -> Self {
+        Self {
+            phidget_id: Some(status.phidget_id),
+            tare: status.tare,
+            ..Self::new(status.calibration)
+        }
+    }
+
+    /// The weight `ratios` come to on the recorded scale.
+    pub fn weigh(&self, ratios: &[f64; NUMBER_OF_INPUTS]) -> Grams {
+        Grams(self.calibration.weigh(ratios).get() - self.tare.get())
+    }
+}
+
+/// The load cells at one moment of a recording.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Time since [`RecordingHeader::started`].
+    pub at: Duration,
+    pub ratios: [f64; NUMBER_OF_INPUTS],
+    /// The weight the ratios came to, as the recorded scale would have read.
+    pub weight: Grams,
+}
+
+/// One line of a recording file.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Entry {
+    Header(RecordingHeader),
+    Snapshot(Snapshot),
+}
+
+/// Everything a scale did over a session: a JSON-lines file of [`Entry`]s,
+/// the header first and then a snapshot for each reading.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recording {
+    pub header: RecordingHeader,
+    pub snapshots: Vec<Snapshot>,
+}
+
+impl Recording {
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, RecordingError> {
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let entry = |line: usize, text: Option<io::Result<String>>| match text {
+            Some(text) => serde_json::from_str::<Entry>(&text?)
+                .map(Some)
+                .map_err(|e| RecordingError::Invalid {
+                    line,
+                    message: e.to_string(),
+                }),
+            None => Ok(None),
+        };
+        let header = match entry(1, lines.next())? {
+            Some(Entry::Header(header)) => header,
+            _ => return Err(RecordingError::MissingHeader),
+        };
+        if header.version > RECORDING_VERSION {
+            return Err(RecordingError::UnsupportedVersion(header.version));
+        }
+        let mut snapshots = Vec::new();
+        for line in 2.. {
+            match entry(line, lines.next())? {
+                Some(Entry::Snapshot(snapshot)) => snapshots.push(snapshot),
+                Some(Entry::Header(_)) => {
+                    return Err(RecordingError::Invalid {
+                        line,
+                        message: "a second header".into(),
+                    })
+                }
+                None => break,
+            }
+        }
+        Ok(Self { header, snapshots })
+    }
+
+    /// Reads a log of raw load cell readings from a CSV file, as a recording
+    /// of a scale calibrated with `calibration` and not tared.
+    ///
+    /// Each row is a timestamp in seconds followed by the voltage ratio of
+    /// every load cell in order:
+    ///
+    /// ```text
+    /// seconds,ratio0,ratio1,ratio2,ratio3
+    /// 1700000000.000,0.1001,0.19997,0.050007,0.15
+    /// 1700000000.008,0.1002,0.19994,0.050014,0.15
+    /// ```
+    ///
+    /// The timestamps may count from any origin, but must not go backwards;
+    /// snapshots are timed from the first row, and the recording is taken to
+    /// have started that many seconds after the Unix epoch. A first row that
+    /// does not start with a number is a column header and skipped, as are
+    /// blank lines and lines starting with `#`.
+    pub fn read_csv(
+        path: impl AsRef<Path>,
+        calibration: Calibration,
+    ) -> Result<Self, RecordingError> {
+        let mut header = RecordingHeader {
+            started: UNIX_EPOCH,
+            ..RecordingHeader::new(calibration)
+        };
+        let mut rows = 0;
+        let mut first = None;
+        let mut last = 0.;
+        let mut snapshots = Vec::new();
+        for (index, text) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let text = text?;
+            let line = index + 1;
+            let text = text.trim();
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+            rows += 1;
+            let invalid = |message: String| RecordingError::Invalid { line, message };
+            let columns: Vec<&str> = text.split(',').map(str::trim).collect();
+            let seconds = match columns[0].parse::<f64>() {
+                Ok(seconds) => seconds,
+                Err(_) if rows == 1 => continue,
+                Err(_) => return Err(invalid(format!("{:?} is not a timestamp", columns[0]))),
+            };
+            if columns.len() != NUMBER_OF_INPUTS + 1 {
+                return Err(invalid(format!(
+                    "expected a timestamp and {NUMBER_OF_INPUTS} ratios, got {} columns",
+                    columns.len()
+                )));
+            }
+            if !seconds.is_finite() || seconds < 0. {
+                return Err(invalid(format!("{seconds} is not a timestamp")));
+            }
+            if seconds < last {
+                return Err(invalid(format!(
+                    "the timestamp {seconds} is before the one above it, {last}"
+                )));
+            }
+            last = seconds;
+            let mut ratios = [0.; NUMBER_OF_INPUTS];
+            for (channel, (ratio, column)) in ratios.iter_mut().zip(&columns[1..]).enumerate() {
+                *ratio = column.parse().map_err(|_| {
+                    invalid(format!("{column:?} is not a ratio for Load Cell {channel}"))
+                })?;
+            }
+            let first = *first.get_or_insert_with(|| {
+                header.started = UNIX_EPOCH + Duration::from_secs_f64(seconds);
+                seconds
+            });
+            snapshots.push(Snapshot {
+                at: Duration::from_secs_f64(seconds - first),
+                ratios,
+                weight: header.weigh(&ratios),
+            });
+        }
+        Ok(Self { header, snapshots })
+    }
+}
+
+/// Writes a [`Recording`] as the readings come in.
+pub struct Recorder {
+    writer: BufWriter<File>,
+    header: RecordingHeader,
+}
+
+impl Recorder {
+    /// Starts a recording at `path`, replacing any file there.
+    pub fn create(path: impl AsRef<Path>, header: RecordingHeader) -> io::Result<Self> {
+        let mut recorder = Self {
+            writer: BufWriter::new(File::create(path)?),
+            header,
+        };
+        recorder.write(&Entry::Header(header))?;
+        Ok(recorder)
+    }
+
+    pub fn header(&self) -> &RecordingHeader {
+        &self.header
+    }
+
+    /// Records `readings` along with the weight they come to, and returns
+    /// the snapshot written.
+    pub fn record(&mut self, readings: &StampedReadings) -> io::Result<Snapshot> {
+        let snapshot = Snapshot {
+            at: readings
+                .timestamp
+                .duration_since(self.header.started)
+                .unwrap_or_default(),
+            ratios: readings.ratios,
+            weight: self.header.weigh(&readings.ratios),
+        };
+        self.write(&Entry::Snapshot(snapshot))?;
+        Ok(snapshot)
+    }
+
+    /// Writes out any buffered snapshots.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.flush()
+    }
+
+    /// Records everything from `samples`, such as the receiver of
+    /// [`ScopedSampler::run_raw`](crate::scoped::ScopedSampler::run_raw), to
+    /// `path` on a thread of its own until the sampler stops. Read errors are
+    /// skipped.
+    ///
+    /// An error writing the recording ends the thread and drops `samples`,
+    /// which stops the sampler too; [`RecorderHandle::join`] returns it.
+    pub fn attach(
+        samples: Receiver<Result<StampedReadings, ScaleError>>,
+        path: impl AsRef<Path>,
+        header: RecordingHeader,
+    ) -> io::Result<RecorderHandle> {
+        let mut recorder = Self::create(path, header)?;
+        let thread = thread::spawn(move || {
+            loop {
+                match samples.recv_timeout(FLUSH_INTERVAL) {
+                    Ok(Ok(readings)) => {
+                        recorder.record(&readings)?;
+                    }
+                    Ok(Err(_)) => {}
+                    Err(RecvTimeoutError::Timeout) => recorder.flush()?,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            recorder.finish()
+        });
+        Ok(RecorderHandle { thread })
+    }
+
+    fn write(&mut self, entry: &Entry) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, entry)?;
+        self.writer.write_all(b"\n")
+    }
+}
+
+/// The thread behind [`Recorder::attach`].
+pub struct RecorderHandle {
+    thread: JoinHandle<io::Result<()>>,
+}
+
+impl RecorderHandle {
+    /// Whether the recording thread has exited.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Waits for the sampler to stop and the recording to be flushed. A
+    /// panic on the recording thread is passed on to the caller.
+    pub fn join(self) -> io::Result<()> {
+        self.thread
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+}
+
+/// A [`Scale`] that plays back a [`Recording`].
+///
+/// Every read, whether of the weight or of the raw readings, takes the next
+/// snapshot, waiting until it is due at the recorded timing divided by the
+/// [`speed`](Self::speed). The clock starts with the first read. Weights are
+/// worked out from the recorded ratios with the scale's current calibration
+/// and tare, which start as the recorded ones, so an untouched replay reads
+/// exactly the weights that were recorded. Once the snapshots run out, reads
+/// fail with [`RecordingError::Ended`].
+pub struct ReplayScale {
+    recording: Recording,
+    calibration: Calibration,
+    tare: f64,
+    speed: f64,
+    position: AtomicUsize,
+    started: OnceLock<Instant>,
+}
+
+impl ReplayScale {
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            calibration: recording.header.calibration,
+            tare: recording.header.tare.get(),
+            recording,
+            speed: 1.,
+            position: AtomicUsize::new(0),
+            started: OnceLock::new(),
+        }
+    }
+
+    pub fn from_recording(path: impl AsRef<Path>) -> Result<Self, RecordingError> {
+        Recording::read(path).map(Self::new)
+    }
+
+    /// Plays back a CSV log of raw readings, as read by
+    /// [`Recording::read_csv`].
+    pub fn from_csv(
+        path: impl AsRef<Path>,
+        calibration: Calibration,
+    ) -> Result<Self, RecordingError> {
+        Recording::read_csv(path, calibration).map(Self::new)
+    }
+
+    /// Plays back `speed` times faster than recorded. `f64::INFINITY` plays
+    /// back as fast as the snapshots are read.
+    pub fn speed(self, speed: f64) -> Self {
+        Self { speed, ..self }
+    }
+
+    /// Plays back without waiting for the recorded timing.
+    pub fn as_fast_as_possible(self) -> Self {
+        self.speed(f64::INFINITY)
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    /// Snapshots not yet read.
+    pub fn remaining(&self) -> usize {
+        let position = self.position.load(Ordering::Relaxed);
+        self.recording.snapshots.len().saturating_sub(position)
+    }
+
+    fn next(&self) -> Result<Snapshot, RecordingError> {
+        let snapshots = &self.recording.snapshots;
+        let position = self.position.fetch_add(1, Ordering::Relaxed);
+        let snapshot = *snapshots.get(position).ok_or(RecordingError::Ended {
+            snapshots: snapshots.len(),
+        })?;
+        let started = *self.started.get_or_init(Instant::now);
+        let since_first = snapshot.at.saturating_sub(snapshots[0].at);
+        let due = Duration::try_from_secs_f64(since_first.as_secs_f64() / self.speed)
+            .unwrap_or(Duration::ZERO);
+        thread::sleep(due.saturating_sub(started.elapsed()));
+        Ok(snapshot)
+    }
+
+    fn gross(&self, samples: usize) -> Result<f64, Box<dyn std::error::Error + Send + Sync>> {
+        let median = collect_median(samples, Duration::ZERO, &CancelFlag::new(), || {
+            self.get_weight()
+        })?;
+        Ok(median.get() + self.tare)
+    }
+}
+
+impl Scale for ReplayScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        let snapshot = self.next()?;
+        Ok(Grams(
+            self.calibration.weigh(&snapshot.ratios).get() - self.tare,
+        ))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_median_weight_of(DEFAULT_MEDIAN_SAMPLES)
+    }
+
+    fn tare(&mut self, samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.tare = self.gross(samples)?;
+        Ok(Grams(self.tare))
+    }
+
+    fn zero(
+        &mut self,
+        samples: usize,
+    ) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        self.calibration.offset += self.gross(samples)?;
+        self.tare = 0.;
+        Ok(self.calibration)
+    }
+
+    fn get_raw_readings(
+        &self,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.next()?.ratios)
+    }
+
+    fn calibration(&self) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.calibration)
+    }
+
+    fn set_calibration(
+        &mut self,
+        calibration: Calibration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.calibration = calibration;
+        Ok(())
+    }
+
+    fn status(&mut self) -> Result<ScaleStatus, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ScaleStatus {
+            phidget_id: self.recording.header.phidget_id.unwrap_or_default(),
+            attached: self.remaining() > 0,
+            calibration: self.calibration,
+            tare: Grams(self.tare),
+            calibration_expires_in_days: None,
+        })
+    }
+}
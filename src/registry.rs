@@ -0,0 +1,50 @@
+use std::collections::BTreeSet;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::scale::ScaleError;
+
+/// Serial numbers of the phidgets claimed by a scale in this process.
+static CLAIMED: Mutex<BTreeSet<i32>> = Mutex::new(BTreeSet::new());
+
+fn claimed() -> MutexGuard<'static, BTreeSet<i32>> {
+    CLAIMED.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// This process's claim on the phidget with a serial number, so that two
+/// scales are not connected to the same bridge by mistake and left to
+/// interleave their readings. Released when dropped.
+///
+/// [`DisconnectedScale::connect`](crate::scale::DisconnectedScale::connect)
+/// takes one before opening any channel, unless told to share the phidget
+/// with [`DisconnectedScale::shared`](crate::scale::DisconnectedScale::shared),
+/// and the connected scale holds it until it is closed or dropped.
+#[derive(Debug)]
+pub struct SerialClaim {
+    serial: i32,
+}
+
+impl SerialClaim {
+    /// Fails with `ScaleError::AlreadyConnected` if `serial` is claimed.
+    pub fn claim(serial: i32) -> Result<Self, ScaleError> {
+        if claimed().insert(serial) {
+            Ok(Self { serial })
+        } else {
+            Err(ScaleError::AlreadyConnected { serial })
+        }
+    }
+
+    pub fn serial(&self) -> i32 {
+        self.serial
+    }
+}
+
+impl Drop for SerialClaim {
+    fn drop(&mut self) {
+        claimed().remove(&self.serial);
+    }
+}
+
+/// Whether a scale in this process has claimed the phidget with `serial`.
+pub fn is_claimed(serial: i32) -> bool {
+    claimed().contains(&serial)
+}
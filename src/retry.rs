@@ -0,0 +1,203 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::io;
+use std::time::Duration;
+
+use crate::clock::{Clock, SystemClock};
+use crate::scale::ScaleError;
+use crate::ScaleErrorInfo;
+
+/// How often, and how patiently, to try something that can fail for a
+/// while.
+///
+/// The wait before retry `n` is `initial_backoff * multiplier^(n - 1)`, at
+/// most `max_backoff`, less a random share of up to `jitter` of it so that
+/// clients failing together do not retry together. [`execute`](Self::execute)
+/// only retries errors that are [`Transient`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Calls made in all, counting the first. 0 is taken as 1.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// What each wait is multiplied by to get the next one.
+    pub multiplier: f64,
+    /// Share of each wait, from 0 to 1, that may be taken off at random.
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// A single attempt, with no retries.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            multiplier: 1.,
+            jitter: 0.,
+        }
+    }
+
+    /// Three attempts, 100 ms and then 200 ms apart, give or take 10%. Suits
+    /// errors such as a busy channel or a sample that timed out.
+    pub fn default_transient() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+            multiplier: 2.,
+            jitter: 0.1,
+        }
+    }
+
+    /// The same wait before every retry.
+    pub fn fixed(max_attempts: u32, backoff: Duration) -> Self {
+        Self {
+            max_attempts,
+            initial_backoff: backoff,
+            max_backoff: backoff,
+            multiplier: 1.,
+            jitter: 0.,
+        }
+    }
+
+    /// The wait before retry `retry`, counting from 1, without jitter.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exponent = i32::try_from(retry.saturating_sub(1)).unwrap_or(i32::MAX);
+        let backoff = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+        if backoff.is_nan() || backoff >= self.max_backoff.as_secs_f64() {
+            return self.max_backoff;
+        }
+        Duration::from_secs_f64(backoff.max(0.))
+    }
+
+    /// [`backoff`](Self::backoff) less its random share of jitter.
+    pub fn jittered_backoff(&self, retry: u32) -> Duration {
+        let backoff = self.backoff(retry);
+        let jitter = self.jitter.clamp(0., 1.);
+        if jitter == 0. {
+            return backoff;
+        }
+        let random = RandomState::new().hash_one(retry) as f64 / u64::MAX as f64;
+        backoff.mul_f64(1. - jitter * random)
+    }
+
+    /// Whether the call numbered `attempt`, counting from 1, may be followed
+    /// by another.
+    pub fn allows_retry_after(&self, attempt: u32) -> bool {
+        attempt < self.max_attempts.max(1)
+    }
+
+    /// Calls `op` with the attempt number, counting from 1, until it
+    /// succeeds, fails with an error that is not transient, or has been
+    /// called `max_attempts` times. Sleeps the thread between attempts;
+    /// returns the last error.
+    pub fn execute<T, E: Transient>(&self, op: impl FnMut(u32) -> Result<T, E>) -> Result<T, E> {
+        self.execute_with_clock(&SystemClock, op)
+    }
+
+    /// Like [`execute`](Self::execute), waiting between attempts by `clock`.
+    pub fn execute_with_clock<T, E: Transient>(
+        &self,
+        clock: &dyn Clock,
+        op: impl FnMut(u32) -> Result<T, E>,
+    ) -> Result<T, E> {
+        self.execute_with_sleep(|backoff| clock.sleep(backoff), op)
+    }
+
+    /// Like [`execute`](Self::execute), waiting between attempts with `sleep`
+    /// instead, which lets a test stand in a clock of its own.
+    pub fn execute_with_sleep<T, E: Transient>(
+        &self,
+        mut sleep: impl FnMut(Duration),
+        mut op: impl FnMut(u32) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let mut attempt = 1;
+        loop {
+            match op(attempt) {
+                Err(error) if error.is_transient() && self.allows_retry_after(attempt) => {
+                    sleep(self.jittered_backoff(attempt));
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Like [`execute`](Self::execute), for async calls. Waits with
+    /// `tokio::time::sleep`, so a paused test clock drives it.
+    #[cfg(feature = "tokio")]
+    pub async fn execute_async<T, E, F>(&self, op: impl FnMut(u32) -> F) -> Result<T, E>
+    where
+        E: Transient,
+        F: std::future::Future<Output = Result<T, E>>,
+    {
+        self.execute_async_with_clock(&SystemClock, op).await
+    }
+
+    /// Like [`execute_async`](Self::execute_async), waiting between attempts
+    /// by `clock`.
+    #[cfg(feature = "tokio")]
+    pub async fn execute_async_with_clock<T, E, F>(
+        &self,
+        clock: &dyn Clock,
+        mut op: impl FnMut(u32) -> F,
+    ) -> Result<T, E>
+    where
+        E: Transient,
+        F: std::future::Future<Output = Result<T, E>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match op(attempt).await {
+                Err(error) if error.is_transient() && self.allows_retry_after(attempt) => {
+                    clock.sleep_async(self.jittered_backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// An error that [`RetryPolicy::execute`] can tell is worth retrying.
+pub trait Transient {
+    fn is_transient(&self) -> bool;
+}
+
+/// See [`ScaleError::is_transient`].
+impl Transient for ScaleError {
+    fn is_transient(&self) -> bool {
+        ScaleError::is_transient(self)
+    }
+}
+
+/// Transient when it would be as a `ScaleError::IoError`.
+impl Transient for io::Error {
+    fn is_transient(&self) -> bool {
+        ScaleError::IoError(self.kind().into()).is_transient()
+    }
+}
+
+/// Transient when it would be as the `ScaleError` it converts back into.
+impl Transient for ScaleErrorInfo {
+    fn is_transient(&self) -> bool {
+        ScaleError::from(self.clone()).is_transient()
+    }
+}
+
+/// Looks inside for a `ScaleError`, `io::Error` or `ScaleErrorInfo`, as the
+/// `Scale` trait returns; anything else is not transient.
+impl Transient for Box<dyn std::error::Error + Send + Sync> {
+    fn is_transient(&self) -> bool {
+        if let Some(error) = self.downcast_ref::<ScaleError>() {
+            error.is_transient()
+        } else if let Some(error) = self.downcast_ref::<io::Error>() {
+            error.is_transient()
+        } else if let Some(info) = self.downcast_ref::<ScaleErrorInfo>() {
+            info.is_transient()
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::cancel::CancelFlag;
+use crate::clock::{Clock, SystemClock};
+use crate::sampling::{check_samples, collect_median_in};
+use crate::scale::{check_interval, ScaleError, DEFAULT_MAX_DURATION};
+use crate::{Grams, MedianGrams, Scale, ScaleErrorInfo};
+
+/// Takes medians of a set number of weights, `interval` apart, over and over
+/// into the same buffer, for a loop that weighs several times a second.
+///
+/// A median from `ConnectedScale::get_median_weight` makes a buffer for its
+/// weights; a sampler makes its buffer once. Each median is the one
+/// `get_median_weight` takes of the same weights, paced the same way.
+pub struct MedianSampler {
+    samples: usize,
+    interval: Duration,
+    weights: Vec<Grams>,
+    clock: Arc<dyn Clock>,
+    /// Never set: what [`measure`](Self::measure) waits on.
+    never: CancelFlag,
+}
+
+impl MedianSampler {
+    /// Fails with `ScaleError::InvalidArgument` for no samples, or for an
+    /// `interval` over [`DEFAULT_MAX_DURATION`].
+    pub fn new(samples: usize, interval: Duration) -> Result<Self, ScaleError> {
+        check_samples(samples)?;
+        check_interval(interval, DEFAULT_MAX_DURATION)?;
+        Ok(Self {
+            samples,
+            interval,
+            weights: Vec::with_capacity(samples),
+            clock: Arc::new(SystemClock),
+            never: CancelFlag::new(),
+        })
+    }
+
+    /// Waits between samples on `clock` rather than the real clock.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// The median of the sampler's number of weights of `scale`. Fails with
+    /// the first read that does, as a `ScaleError` rebuilt from its
+    /// description if it is not one already, or with `ScaleError::NonFinite`
+    /// for a weight that is not finite.
+    pub fn measure<S: Scale + ?Sized>(&mut self, scale: &S) -> Result<MedianGrams, ScaleError> {
+        let never = self.never.clone();
+        self.measure_cancellable(scale, &never)
+    }
+
+    /// Like [`measure`](Self::measure), but gives up with
+    /// `ScaleError::Cancelled` as soon as `cancel` is set.
+    pub fn measure_cancellable<S: Scale + ?Sized>(
+        &mut self,
+        scale: &S,
+        cancel: &CancelFlag,
+    ) -> Result<MedianGrams, ScaleError> {
+        collect_median_in(
+            &*self.clock,
+            &mut self.weights,
+            self.samples,
+            self.interval,
+            cancel,
+            || scale.get_weight().map_err(scale_error),
+        )
+    }
+}
+
+/// `error` as a `ScaleError`, rebuilt from its description if it is not
+/// one already.
+fn scale_error(error: Box<dyn Error + Send + Sync>) -> ScaleError {
+    error
+        .downcast::<ScaleError>()
+        .map(|error| *error)
+        .unwrap_or_else(|error| ScaleError::from(ScaleErrorInfo::from_dyn(&*error)))
+}
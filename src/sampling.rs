@@ -0,0 +1,242 @@
+use std::fmt::Display;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::cancel::CancelFlag;
+use crate::clock::{Clock, SystemClock};
+use crate::scale::{ScaleError, NUMBER_OF_INPUTS};
+use crate::{median, Grams, MedianGrams, StampedWeight};
+
+/// Fails with `ScaleError::InvalidArgument` for a median of no samples.
+pub(crate) fn check_samples(samples: usize) -> Result<(), ScaleError> {
+    if samples == 0 {
+        return Err(ScaleError::InvalidArgument(
+            "samples must be at least 1".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Fails with `ScaleError::NonFinite` for a NaN or infinite weight.
+pub(crate) fn finite(weight: Grams) -> Result<Grams, ScaleError> {
+    if weight.0.is_finite() {
+        Ok(weight)
+    } else {
+        Err(ScaleError::NonFinite { channel: None })
+    }
+}
+
+/// Fails with `ScaleError::NonFinite` for the first load cell whose ratio is
+/// NaN or infinite.
+pub(crate) fn finite_readings(
+    readings: [f64; NUMBER_OF_INPUTS],
+) -> Result<[f64; NUMBER_OF_INPUTS], ScaleError> {
+    match readings.iter().position(|reading| !reading.is_finite()) {
+        Some(channel) => Err(ScaleError::NonFinite {
+            channel: Some(channel),
+        }),
+        None => Ok(readings),
+    }
+}
+
+/// Pacing and bookkeeping for a median read, shared by the blocking and async
+/// paths so both wait the same way between samples.
+///
+/// Each sample is taken once `interval` has passed since the previous one
+/// finished, so a slow read pushes the following samples back rather than
+/// causing a burst of catch-up reads.
+pub(crate) struct MedianCollector<'a> {
+    samples: usize,
+    interval: Duration,
+    weights: Vec<Grams>,
+    clock: &'a dyn Clock,
+    last_read: Instant,
+}
+
+#[cfg(feature = "tokio")]
+impl MedianCollector<'static> {
+    pub(crate) fn new(samples: usize, interval: Duration) -> Self {
+        Self::with_clock(samples, interval, &SystemClock)
+    }
+}
+
+impl<'a> MedianCollector<'a> {
+    pub(crate) fn with_clock(samples: usize, interval: Duration, clock: &'a dyn Clock) -> Self {
+        Self::with_buffer(samples, interval, clock, Vec::new())
+    }
+
+    /// Like [`with_clock`](Self::with_clock), collecting into `weights`,
+    /// emptied first, whose room is kept.
+    pub(crate) fn with_buffer(
+        samples: usize,
+        interval: Duration,
+        clock: &'a dyn Clock,
+        mut weights: Vec<Grams>,
+    ) -> Self {
+        weights.clear();
+        weights.reserve(samples);
+        Self {
+            samples,
+            interval,
+            weights,
+            clock,
+            last_read: clock.now(),
+        }
+    }
+
+    /// How long to wait before the next sample, or `None` once enough have
+    /// been collected.
+    pub(crate) fn next_delay(&self) -> Option<Duration> {
+        if self.weights.len() >= self.samples {
+            return None;
+        }
+        Some(
+            self.interval
+                .saturating_sub(self.clock.now().saturating_duration_since(self.last_read)),
+        )
+    }
+
+    pub(crate) fn collected(&self) -> usize {
+        self.weights.len()
+    }
+
+    pub(crate) fn push(&mut self, weight: Grams) {
+        self.weights.push(weight);
+        self.last_read = self.clock.now();
+    }
+
+    pub(crate) fn finish(mut self) -> MedianGrams {
+        median(self.weights.as_mut_slice())
+    }
+
+    /// The median, and the buffer to collect the next median into.
+    pub(crate) fn finish_reusing(mut self) -> (MedianGrams, Vec<Grams>) {
+        let median = median(&mut self.weights);
+        (median, self.weights)
+    }
+
+    /// The buffer collected into, for the next median.
+    pub(crate) fn into_weights(self) -> Vec<Grams> {
+        self.weights
+    }
+}
+
+/// Blocking median read: waits out each delay on `cancel`, then takes a
+/// sample with `read`. Fails for no samples, or for a sample that is not a
+/// finite weight.
+pub(crate) fn collect_median<E: From<ScaleError> + Display>(
+    samples: usize,
+    interval: Duration,
+    cancel: &CancelFlag,
+    read: impl FnMut() -> Result<Grams, E>,
+) -> Result<MedianGrams, E> {
+    collect_median_on(&SystemClock, samples, interval, cancel, read)
+}
+
+/// Like [`collect_median`], waiting by `clock`.
+pub(crate) fn collect_median_on<E: From<ScaleError> + Display>(
+    clock: &dyn Clock,
+    samples: usize,
+    interval: Duration,
+    cancel: &CancelFlag,
+    read: impl FnMut() -> Result<Grams, E>,
+) -> Result<MedianGrams, E> {
+    let mut collector = MedianCollector::with_clock(samples, interval, clock);
+    collect(&mut collector, cancel, read)?;
+    Ok(collector.finish())
+}
+
+/// Like [`collect_median_on`], collecting into `weights`, which keeps its
+/// room for the next median whether this one succeeds or not.
+pub(crate) fn collect_median_in<E: From<ScaleError> + Display>(
+    clock: &dyn Clock,
+    weights: &mut Vec<Grams>,
+    samples: usize,
+    interval: Duration,
+    cancel: &CancelFlag,
+    read: impl FnMut() -> Result<Grams, E>,
+) -> Result<MedianGrams, E> {
+    let buffer = std::mem::take(weights);
+    let mut collector = MedianCollector::with_buffer(samples, interval, clock, buffer);
+    match collect(&mut collector, cancel, read) {
+        Ok(()) => {
+            let (median, buffer) = collector.finish_reusing();
+            *weights = buffer;
+            Ok(median)
+        }
+        Err(error) => {
+            *weights = collector.into_weights();
+            Err(error)
+        }
+    }
+}
+
+/// Takes the samples of `collector` with `read`, waiting out each delay on
+/// `cancel`.
+fn collect<E: From<ScaleError> + Display>(
+    collector: &mut MedianCollector<'_>,
+    cancel: &CancelFlag,
+    mut read: impl FnMut() -> Result<Grams, E>,
+) -> Result<(), E> {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::debug_span!(
+        target: "libra",
+        "get_median_weight",
+        samples = collector.samples,
+        interval_ms = collector.interval.as_millis() as u64
+    )
+    .entered();
+    check_samples(collector.samples)?;
+    while let Some(delay) = collector.next_delay() {
+        if !cancel.sleep_on(collector.clock, delay) {
+            return Err(ScaleError::Cancelled {
+                collected: collector.collected(),
+            }
+            .into());
+        }
+        let sample = collector.collected();
+        let weight = read().and_then(|weight| Ok(finite(weight)?));
+        collector.push(weight.inspect_err(|e| sample_failed(sample, e))?);
+    }
+    Ok(())
+}
+
+/// Blocking batch read: `count` stamped readings `interval` apart by
+/// `clock`, the first taken straight away. Fails for a reading that is not a
+/// finite weight.
+pub(crate) fn collect_batch_on<E: From<ScaleError> + Display>(
+    clock: &dyn Clock,
+    count: usize,
+    interval: Duration,
+    cancel: &CancelFlag,
+    mut read: impl FnMut() -> Result<Grams, E>,
+) -> Result<Vec<StampedWeight>, E> {
+    let mut batch = Vec::with_capacity(count);
+    for sequence in 0..count as u64 {
+        let delay = if sequence == 0 {
+            Duration::ZERO
+        } else {
+            interval
+        };
+        if !cancel.sleep_on(clock, delay) {
+            return Err(ScaleError::Cancelled {
+                collected: batch.len(),
+            }
+            .into());
+        }
+        let weight = read().and_then(|weight| Ok(finite(weight)?));
+        batch.push(StampedWeight {
+            weight: weight.inspect_err(|e| sample_failed(batch.len(), e))?,
+            sequence,
+            timestamp: SystemTime::now(),
+        });
+    }
+    Ok(batch)
+}
+
+/// Reports the failure of sample number `sample` of a median or batch to
+/// `tracing`, if the feature is enabled.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn sample_failed(sample: usize, error: &impl Display) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(name: "sample_failed", target: "libra", sample, error = %error);
+}
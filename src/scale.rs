@@ -1,242 +1,1736 @@
+#[cfg(feature = "hardware")]
 use phidget::ReturnCode;
+#[cfg(feature = "hardware")]
 use phidget::{devices::VoltageRatioInput, Phidget};
-use std::time::Duration;
-use std::{array, time};
+#[cfg(feature = "hardware")]
+use std::array;
+#[cfg(feature = "hardware")]
+use std::cell::{Cell, RefCell};
+use std::io;
+#[cfg(feature = "hardware")]
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use thiserror::Error;
 
-use crate::{median, Grams, MedianGrams};
-const NUMBER_OF_INPUTS: usize = 4;
+#[cfg(feature = "hardware")]
+use crate::calibration::{Calibration, RawScale, ScaleIdentity};
+#[cfg(feature = "hardware")]
+use crate::cancel::CancelFlag;
+#[cfg(feature = "hardware")]
+use crate::clock::{Clock, SystemClock};
+#[cfg(feature = "hardware")]
+use crate::config::{BridgeGain, SamplingDefaults, SettingMismatch};
+use crate::failover::FailoverRole;
+#[cfg(feature = "hardware")]
+use crate::health::{
+    self, CalibrationExpiry, CalibrationPolicy, ExpiryAction, HealthConfig, HealthReport,
+};
+#[cfg(feature = "hardware")]
+use crate::registry::SerialClaim;
+#[cfg(feature = "hardware")]
+use crate::sampling::{check_samples, collect_median_on, finite};
+#[cfg(feature = "hardware")]
+use crate::snapshot::{RatioSnapshot, ReadCounters};
+#[cfg(feature = "hardware")]
+use crate::source::VoltageSource;
+#[cfg(feature = "hardware")]
+use crate::{Grams, MedianGrams, Scale, ScaleStatus};
+use crate::{ScaleErrorInfo, ScaleErrorKind};
+/// Load cells on a scale, one per phidget channel.
+pub const NUMBER_OF_INPUTS: usize = 4;
+#[cfg(feature = "hardware")]
 pub const TIMEOUT: Duration = phidget::TIMEOUT_DEFAULT;
+/// Sample count used by the `Scale` trait's median read.
+pub const DEFAULT_MEDIAN_SAMPLES: usize = 10;
+/// Sample spacing used by the `Scale` trait's median read.
+pub const DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+/// Longest timeout or sampling interval accepted unless configured
+/// otherwise. Anything longer is most likely in the wrong unit, such as
+/// milliseconds passed as seconds.
+pub const DEFAULT_MAX_DURATION: Duration = Duration::from_secs(60 * 60);
 
-fn dot_product(a: &[f64], b: &[f64]) -> f64 {
-    a.iter().zip(b.iter()).map(|(a, b)| a * b).sum::<f64>()
+/// The phidget22 return codes [`PhidgetError::kind`] tells apart, as
+/// numbers, so that errors can be classified without the phidget crate.
+mod code {
+    pub const TIMEOUT: u32 = 3;
+    pub const BUSY: u32 = 9;
+    pub const INVALID: u32 = 13;
+    pub const INVALID_ARG: u32 = 21;
+    pub const AGAIN: u32 = 22;
+    pub const CONN_REF: u32 = 35;
+    pub const NO_DEV: u32 = 40;
+    pub const PIPE: u32 = 41;
+    pub const NET_UNAVAIL: u32 = 45;
+    pub const CONN_RESET: u32 = 46;
+    pub const HOST_UNREACH: u32 = 48;
+    pub const UNKNOWN_VAL: u32 = 51;
+    pub const NOT_ATTACHED: u32 = 52;
+    pub const CLOSED: u32 = 56;
+    pub const KEEP_ALIVE: u32 = 58;
+    pub const UNKNOWN_VAL_HIGH: u32 = 60;
+    pub const UNKNOWN_VAL_LOW: u32 = 61;
 }
 
 #[derive(Debug, Clone)]
 pub struct PhidgetError {
-    return_code: ReturnCode,
+    code: u32,
     load_cell: usize,
 }
 impl PhidgetError {
+    #[cfg(feature = "hardware")]
     pub fn new(return_code: ReturnCode, load_cell: usize) -> Self {
-        Self {
-            return_code,
-            load_cell,
+        Self::from_code(return_code as u32, load_cell)
+    }
+
+    /// An error with the phidget22 return code numbered `code`, for building
+    /// one without the phidget crate, such as from a [`ScaleErrorInfo`].
+    pub fn from_code(code: u32, load_cell: usize) -> Self {
+        Self { code, load_cell }
+    }
+
+    /// Codes the phidget crate does not know are `ReturnCode::Unexpected`.
+    #[cfg(feature = "hardware")]
+    pub fn return_code(&self) -> ReturnCode {
+        ReturnCode::from(self.code)
+    }
+
+    /// The phidget22 return code, as a number.
+    pub fn code(&self) -> u32 {
+        self.code
+    }
+
+    pub fn load_cell(&self) -> usize {
+        self.load_cell
+    }
+
+    /// What went wrong, for branching on without the phidget crate.
+    pub fn kind(&self) -> PhidgetErrorKind {
+        match self.code {
+            code::TIMEOUT => PhidgetErrorKind::Timeout,
+            code::NOT_ATTACHED | code::NO_DEV | code::CLOSED => PhidgetErrorKind::NotAttached,
+            code::INVALID | code::INVALID_ARG => PhidgetErrorKind::InvalidArgument,
+            code::BUSY | code::AGAIN => PhidgetErrorKind::Busy,
+            code::UNKNOWN_VAL | code::UNKNOWN_VAL_HIGH | code::UNKNOWN_VAL_LOW => {
+                PhidgetErrorKind::UnknownValue
+            }
+            code::CONN_REF
+            | code::CONN_RESET
+            | code::NET_UNAVAIL
+            | code::HOST_UNREACH
+            | code::PIPE
+            | code::KEEP_ALIVE => PhidgetErrorKind::Connection,
+            _ => PhidgetErrorKind::Unknown,
         }
     }
 }
+
+impl std::error::Error for PhidgetError {}
+
+/// The broad class of a [`PhidgetError`]'s return code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PhidgetErrorKind {
+    /// The channel did not answer in time.
+    Timeout,
+    /// The channel is unplugged, or was closed.
+    NotAttached,
+    /// The phidget library turned down a value it was given.
+    InvalidArgument,
+    /// The device is busy with something else; trying again may work.
+    Busy,
+    /// The channel has no reading yet, or its reading is out of range.
+    UnknownValue,
+    /// The connection to a networked phidget failed.
+    Connection,
+    Unknown,
+}
 impl std::fmt::Display for PhidgetError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        #[cfg(feature = "hardware")]
+        let description = self.return_code();
+        #[cfg(not(feature = "hardware"))]
+        let description = format_args!("return code {}", self.code);
         write!(
             f,
-            "Phidget error at Load Cell {}: {}",
-            self.load_cell, self.return_code
+            "Phidget error at Load Cell {}: {description}",
+            self.load_cell
         )
     }
 }
 
-#[derive(Error, Debug, Clone)]
+/// An error from a scale.
+///
+/// Each variant's docs say how [`is_transient`](Self::is_transient) and
+/// [`is_disconnection`](Self::is_disconnection) classify it. Errors that are
+/// neither are permanent: trying again will fail the same way.
+///
+/// New variants may be added without a major release, so matches need a
+/// wildcard arm. Matching on [`kind`](Self::kind) or on the classification
+/// methods instead keeps working as variants are added.
+#[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum ScaleError {
+    /// Permanent.
     #[error("Invalid coefficients")]
     InvalidCoefficients,
 
+    /// Permanent.
     #[error("Invalid Phidget ID")]
     InvalidPhidgetId,
 
+    /// The phidget has no `channel`, or reports fewer than
+    /// [`NUMBER_OF_INPUTS`] channels, most likely because it is not a
+    /// 4-channel bridge. `channels` is how many it reports, when known.
+    /// Permanent.
+    #[error(
+        "Phidget has no Load Cell {channel}{}; check that it is a {NUMBER_OF_INPUTS}-channel bridge",
+        .channels.map_or(String::new(), |channels| format!(" (it reports {channels} channels)"))
+    )]
+    WrongDevice {
+        channel: usize,
+        channels: Option<u32>,
+    },
+
+    /// Transient for timeouts, busy channels and unknown values; a
+    /// disconnection when the channel is detached or its network connection
+    /// failed. See [`PhidgetError::kind`].
     #[error("{0}")]
     PhidgetError(PhidgetError),
 
-    #[error("IO Error")]
-    IoError,
+    /// Transient for timeouts and interruptions; a disconnection when the
+    /// connection was refused, reset or closed.
+    #[error("IO error: {0}")]
+    IoError(#[from] io::Error),
+
+    /// Transient.
+    #[error("Scale is busy")]
+    Busy,
+
+    /// Permanent, since the caller asked for it.
+    #[error("Cancelled after {collected} samples")]
+    Cancelled { collected: usize },
+
+    /// Permanent.
+    #[error("Batch of {count} readings exceeds the limit of {max}")]
+    BatchTooLarge { count: usize, max: usize },
+
+    /// Transient: the platform may have settled by the next try.
+    #[error("Platform did not settle within {0:?}")]
+    NotSettled(Duration),
+
+    /// Transient: the samples that did not fit are lost, but reading can go
+    /// on.
+    #[error("Buffer of {capacity} samples overflowed")]
+    Overflow { capacity: usize },
+
+    /// Permanent.
+    #[error("{0} is not supported by this scale")]
+    Unsupported(&'static str),
+
+    /// A read ran out of time, after taking `collected` samples. Transient.
+    #[error("Timed out after {elapsed:?} with {collected} samples")]
+    Timeout { elapsed: Duration, collected: usize },
+
+    /// The scale, or the load cell on `channel` when only one is affected,
+    /// is detached. A disconnection; match on this rather than on a
+    /// `PhidgetError` of kind `NotAttached` where only attachment matters.
+    #[error(
+        "Scale is disconnected{}",
+        .channel.map_or(String::new(), |channel| format!(" at Load Cell {channel}"))
+    )]
+    Disconnected { channel: Option<usize> },
+
+    /// An argument was out of range before anything was asked of the scale.
+    /// Permanent.
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    /// The reading of the load cell on `channel` has not changed in `age`,
+    /// longer than a strict [`Watchdog`](crate::watchdog::Watchdog) allows.
+    /// A disconnection: a bridge whose stream has frozen needs reconnecting.
+    #[error("Load Cell {channel} has not changed in {age:?}")]
+    StaleData { channel: usize, age: Duration },
+
+    /// `error` stopped the channels from opening, and the `closed` channels
+    /// that had opened before it were closed again. Has the kind and
+    /// classification of `error`.
+    #[error("{error}; closed the {closed} channels opened before it")]
+    OpenRolledBack {
+        closed: usize,
+        #[source]
+        error: Box<ScaleError>,
+    },
+
+    /// The member numbered `member`, from 0, of a
+    /// [`CompositeScale`](crate::composite::CompositeScale) failed with
+    /// `error`. Has the kind and classification of `error`.
+    #[error("Member {member} of the composite scale failed: {error}")]
+    MemberFailed {
+        member: usize,
+        #[source]
+        error: Box<ScaleError>,
+    },
+
+    /// The `role` scale of a
+    /// [`FailoverScale`](crate::failover::FailoverScale) failed with `error`
+    /// at something done to both scales. Has the kind and classification of
+    /// `error`.
+    #[error("The {role} scale of the failover failed: {error}")]
+    FailoverMemberFailed {
+        role: FailoverRole,
+        #[source]
+        error: Box<ScaleError>,
+    },
+
+    /// Both scales of a [`FailoverScale`](crate::failover::FailoverScale)
+    /// failed the same read. Has the kind and classification of the harder
+    /// of the two errors to recover from, the primary's on a tie.
+    #[error("Both scales of the failover failed: primary: {primary}; backup: {backup}")]
+    BothScalesFailed {
+        primary: Box<ScaleError>,
+        backup: Box<ScaleError>,
+    },
+
+    /// Reading failed on more than one load cell: each failing channel with
+    /// its error, in channel order. Has the kind and classification of the
+    /// hardest of them to recover from, so it is only transient when every
+    /// failure is.
+    #[error("Load Cells {} failed: {}", channel_list(.0), error_list(.0))]
+    MultipleChannels(Vec<(usize, ScaleError)>),
+
+    /// A reading, of the load cell on `channel` or of the weight when it is
+    /// `None`, came back as NaN or infinite. Transient: a glitch on the
+    /// bridge, which the next reading may not repeat.
+    #[error(
+        "Reading is not a finite number{}",
+        .channel.map_or(String::new(), |channel| format!(" at Load Cell {channel}"))
+    )]
+    NonFinite { channel: Option<usize> },
+
+    /// A [`CircuitBreakerScale`](crate::breaker::CircuitBreakerScale) has
+    /// seen too many failures in a row, the last being `last_error`, and has
+    /// failed every call without trying the scale since `since`. Transient:
+    /// once the cooldown is over, a call is let through to probe the scale.
+    #[error("Circuit open after repeated failures: {last_error}")]
+    CircuitOpen {
+        since: SystemTime,
+        last_error: ScaleErrorInfo,
+    },
+
+    /// Another scale in this process has already connected to the phidget
+    /// with serial number `serial`; see [`SerialClaim`]. Permanent.
+    #[error("Phidget {serial} is already connected to another scale")]
+    AlreadyConnected { serial: i32 },
+
+    /// A reading of `weight` grams, before the tare, is over the `capacity`
+    /// the scale was set up with, so the load cells may be out of their
+    /// linear range. Transient: the reading is good again once the load
+    /// comes off.
+    #[error("Load of {weight:.1} g is over the capacity of {capacity} g")]
+    OverCapacity { weight: f64, capacity: f64 },
+
+    /// The calibration was made `age` ago, longer than the scale's
+    /// [`CalibrationPolicy`](crate::health::CalibrationPolicy) allows, and
+    /// the policy blocks weights until it is recalibrated. Permanent.
+    #[error("Calibration expired {} days after it was made; recalibrate the scale", .age.as_secs() / 86_400)]
+    CalibrationExpired { age: Duration },
+
+    /// An error reported by a scale across the network that has no closer
+    /// match here, or whose details did not survive the trip.
+    ///
+    /// Transient when the remote scale was busy, its queue was full, or it
+    /// hit an I/O, settling, overflow, timeout, non-finite reading or
+    /// over-capacity error or an open circuit; a disconnection when it has stopped, is disconnected or has stale data.
+    #[error("{0}")]
+    Remote(ScaleErrorInfo),
 }
 impl ScaleError {
+    /// The kind this error has in a `ScaleErrorInfo`.
+    pub fn kind(&self) -> ScaleErrorKind {
+        match self {
+            ScaleError::InvalidCoefficients => ScaleErrorKind::InvalidCoefficients,
+            ScaleError::InvalidPhidgetId => ScaleErrorKind::InvalidPhidgetId,
+            ScaleError::WrongDevice { .. } => ScaleErrorKind::WrongDevice,
+            ScaleError::PhidgetError(_) => ScaleErrorKind::Phidget,
+            ScaleError::IoError(_) => ScaleErrorKind::Io,
+            ScaleError::Busy => ScaleErrorKind::Busy,
+            ScaleError::Cancelled { .. } => ScaleErrorKind::Cancelled,
+            ScaleError::BatchTooLarge { .. } => ScaleErrorKind::BatchTooLarge,
+            ScaleError::NotSettled(_) => ScaleErrorKind::NotSettled,
+            ScaleError::Overflow { .. } => ScaleErrorKind::Overflow,
+            ScaleError::Unsupported(_) => ScaleErrorKind::Unsupported,
+            ScaleError::Timeout { .. } => ScaleErrorKind::Timeout,
+            ScaleError::Disconnected { .. } => ScaleErrorKind::Disconnected,
+            ScaleError::InvalidArgument(_) => ScaleErrorKind::InvalidArgument,
+            ScaleError::StaleData { .. } => ScaleErrorKind::StaleData,
+            ScaleError::CircuitOpen { .. } => ScaleErrorKind::CircuitOpen,
+            ScaleError::NonFinite { .. } => ScaleErrorKind::NonFinite,
+            ScaleError::AlreadyConnected { .. } => ScaleErrorKind::AlreadyConnected,
+            ScaleError::OverCapacity { .. } => ScaleErrorKind::OverCapacity,
+            ScaleError::CalibrationExpired { .. } => ScaleErrorKind::CalibrationExpired,
+            ScaleError::OpenRolledBack { error, .. }
+            | ScaleError::MemberFailed { error, .. }
+            | ScaleError::FailoverMemberFailed { error, .. } => error.kind(),
+            ScaleError::BothScalesFailed { primary, backup } => {
+                harder_to_recover(primary, backup).kind()
+            }
+            ScaleError::MultipleChannels(failures) => {
+                worst_failure(failures).map_or(ScaleErrorKind::Other, ScaleError::kind)
+            }
+            ScaleError::Remote(info) => info.kind,
+        }
+    }
+
+    #[cfg(feature = "hardware")]
     pub fn phidget_error(return_code: ReturnCode, load_cell: usize) -> Self {
         ScaleError::PhidgetError(PhidgetError::new(return_code, load_cell))
     }
+
+    /// Whether the same call may succeed if it is simply made again.
+    pub fn is_transient(&self) -> bool {
+        self.recovery() == Recovery::Retry
+    }
+
+    /// Whether the scale, or the connection to it, is gone, so nothing will
+    /// succeed until it is reconnected.
+    pub fn is_disconnection(&self) -> bool {
+        self.recovery() == Recovery::Reconnect
+    }
+
+    fn recovery(&self) -> Recovery {
+        match self {
+            ScaleError::InvalidCoefficients
+            | ScaleError::InvalidPhidgetId
+            | ScaleError::WrongDevice { .. }
+            | ScaleError::Cancelled { .. }
+            | ScaleError::BatchTooLarge { .. }
+            | ScaleError::Unsupported(_)
+            | ScaleError::InvalidArgument(_)
+            | ScaleError::AlreadyConnected { .. }
+            | ScaleError::CalibrationExpired { .. } => Recovery::None,
+            ScaleError::Busy
+            | ScaleError::NotSettled(_)
+            | ScaleError::Overflow { .. }
+            | ScaleError::Timeout { .. }
+            | ScaleError::CircuitOpen { .. }
+            | ScaleError::NonFinite { .. }
+            | ScaleError::OverCapacity { .. } => Recovery::Retry,
+            ScaleError::Disconnected { .. } | ScaleError::StaleData { .. } => Recovery::Reconnect,
+            ScaleError::OpenRolledBack { error, .. }
+            | ScaleError::MemberFailed { error, .. }
+            | ScaleError::FailoverMemberFailed { error, .. } => error.recovery(),
+            ScaleError::BothScalesFailed { primary, backup } => {
+                harder_to_recover(primary, backup).recovery()
+            }
+            ScaleError::MultipleChannels(failures) => {
+                worst_failure(failures).map_or(Recovery::None, ScaleError::recovery)
+            }
+            ScaleError::PhidgetError(error) => match error.kind() {
+                PhidgetErrorKind::Timeout
+                | PhidgetErrorKind::Busy
+                | PhidgetErrorKind::UnknownValue => Recovery::Retry,
+                PhidgetErrorKind::NotAttached | PhidgetErrorKind::Connection => Recovery::Reconnect,
+                PhidgetErrorKind::InvalidArgument | PhidgetErrorKind::Unknown => Recovery::None,
+            },
+            ScaleError::IoError(error) => match error.kind() {
+                io::ErrorKind::TimedOut
+                | io::ErrorKind::Interrupted
+                | io::ErrorKind::WouldBlock => Recovery::Retry,
+                io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::NotConnected
+                | io::ErrorKind::BrokenPipe
+                | io::ErrorKind::UnexpectedEof => Recovery::Reconnect,
+                _ => Recovery::None,
+            },
+            ScaleError::Remote(info) => match info.kind {
+                ScaleErrorKind::Busy
+                | ScaleErrorKind::QueueFull
+                | ScaleErrorKind::Io
+                | ScaleErrorKind::NotSettled
+                | ScaleErrorKind::Overflow
+                | ScaleErrorKind::Timeout
+                | ScaleErrorKind::CircuitOpen
+                | ScaleErrorKind::NonFinite
+                | ScaleErrorKind::OverCapacity => Recovery::Retry,
+                ScaleErrorKind::Stopped
+                | ScaleErrorKind::Disconnected
+                | ScaleErrorKind::StaleData => Recovery::Reconnect,
+                _ => Recovery::None,
+            },
+        }
+    }
+}
+
+/// The first of `failures` that is hardest to recover from.
+fn worst_failure(failures: &[(usize, ScaleError)]) -> Option<&ScaleError> {
+    failures
+        .iter()
+        .map(|(_, error)| error)
+        .min_by_key(|error| std::cmp::Reverse(error.recovery()))
+}
+
+/// Whichever of `a` and `b` is harder to recover from, `a` on a tie.
+pub(crate) fn harder_to_recover<'a>(a: &'a ScaleError, b: &'a ScaleError) -> &'a ScaleError {
+    if b.recovery() > a.recovery() {
+        b
+    } else {
+        a
+    }
+}
+
+/// "1 and 3", or "0, 1 and 3".
+fn channel_list(failures: &[(usize, ScaleError)]) -> String {
+    let channels: Vec<String> = failures
+        .iter()
+        .map(|(channel, _)| channel.to_string())
+        .collect();
+    match channels.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} and {last}", rest.join(", ")),
+        _ => channels.concat(),
+    }
+}
+
+fn error_list(failures: &[(usize, ScaleError)]) -> String {
+    failures
+        .iter()
+        .map(|(_, error)| error.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Combines a reading of every load cell, as from
+/// [`ConnectedScale::get_raw_readings_all`], into one result. A single
+/// failure is returned as it is; more than one as
+/// `ScaleError::MultipleChannels`, so every failing channel is reported at
+/// once.
+pub fn all_channels(
+    results: [Result<f64, ScaleError>; NUMBER_OF_INPUTS],
+) -> Result<[f64; NUMBER_OF_INPUTS], ScaleError> {
+    let mut readings = [0.; NUMBER_OF_INPUTS];
+    let mut failures = Vec::new();
+    for (channel, result) in results.into_iter().enumerate() {
+        match result {
+            Ok(reading) => readings[channel] = reading,
+            Err(error) => failures.push((channel, error)),
+        }
+    }
+    match failures.len() {
+        0 => Ok(readings),
+        1 => Err(failures.remove(0).1),
+        _ => Err(ScaleError::MultipleChannels(failures)),
+    }
+}
+
+/// Fails with `ScaleError::InvalidArgument` for a zero `timeout`, which no
+/// open can meet, or one over `max`.
+pub fn check_timeout(timeout: Duration, max: Duration) -> Result<(), ScaleError> {
+    if timeout.is_zero() {
+        return Err(ScaleError::InvalidArgument(
+            "timeout must be more than zero".into(),
+        ));
+    }
+    check_duration("timeout", timeout, max)
+}
+
+/// Fails with `ScaleError::InvalidArgument` for an `interval` over `max`.
+/// Zero is allowed, and means as fast as the scale answers.
+pub fn check_interval(interval: Duration, max: Duration) -> Result<(), ScaleError> {
+    check_duration("interval", interval, max)
 }
 
+/// The data interval to ask of a bridge sampled `sampling` apart: the
+/// longest at or below it in whole milliseconds, the unit phidgets take, so
+/// that every sample is a new reading without the bridge reading any faster
+/// than that. It is kept from `min` to `max` where the bridge has them, so
+/// sampling faster than the bridge reads gets the shortest interval there
+/// is. Back to back sampling gets 1 ms, or `min`.
+pub fn data_interval_for(
+    sampling: Duration,
+    min: Option<Duration>,
+    max: Option<Duration>,
+) -> Duration {
+    let millis = u64::try_from(sampling.as_millis()).unwrap_or(u64::MAX);
+    let mut interval = Duration::from_millis(millis.max(1));
+    if let Some(max) = max {
+        interval = interval.min(max);
+    }
+    if let Some(min) = min {
+        interval = interval.max(min);
+    }
+    interval
+}
+
+fn check_duration(what: &str, duration: Duration, max: Duration) -> Result<(), ScaleError> {
+    if duration > max {
+        return Err(ScaleError::InvalidArgument(format!(
+            "{what} of {duration:?} is over the limit of {max:?}; is it in the wrong unit?"
+        )));
+    }
+    Ok(())
+}
+
+/// What it takes for a failed call to succeed, from easiest to hardest.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Recovery {
+    Retry,
+    Reconnect,
+    None,
+}
+
+#[cfg(feature = "hardware")]
 pub struct DisconnectedScale {
     phidget_id: i32,
+    hub_port: Option<i32>,
+    max_duration: Duration,
+    shared: bool,
 }
 
+#[cfg(feature = "hardware")]
 impl DisconnectedScale {
     pub fn new(phidget_id: i32) -> Self {
-        Self { phidget_id }
+        Self {
+            phidget_id,
+            hub_port: None,
+            max_duration: DEFAULT_MAX_DURATION,
+            shared: false,
+        }
+    }
+
+    /// Opens the channels on the VINT hub port `hub_port`, for a bridge on
+    /// a hub, rather than on any port.
+    pub fn on_hub_port(self, hub_port: i32) -> Self {
+        Self {
+            hub_port: Some(hub_port),
+            ..self
+        }
+    }
+
+    pub fn phidget_id(&self) -> i32 {
+        self.phidget_id
+    }
+
+    pub fn hub_port(&self) -> Option<i32> {
+        self.hub_port
+    }
+
+    /// Connects even if another scale in this process has the phidget, and
+    /// without claiming it, for when sharing one bridge is deliberate.
+    pub fn shared(self) -> Self {
+        Self {
+            shared: true,
+            ..self
+        }
+    }
+
+    /// Sets the longest timeout or sampling interval accepted, by `connect`
+    /// and then by the connected scale. [`DEFAULT_MAX_DURATION`] otherwise.
+    pub fn with_max_duration(self, max_duration: Duration) -> Self {
+        Self {
+            max_duration,
+            ..self
+        }
     }
 
+    /// Fails without opening anything: with `ScaleError::InvalidArgument`
+    /// for a `timeout` that is zero or over the maximum, and with
+    /// `ScaleError::AlreadyConnected` if another scale in this process has
+    /// the phidget and this one is not [`shared`](Self::shared).
     pub fn connect(
         self,
         offset: f64,
         coefficients: [f64; NUMBER_OF_INPUTS],
         timeout: Duration,
     ) -> Result<ConnectedScale, ScaleError> {
-        let vins_result: Result<Vec<VoltageRatioInput>, ScaleError> = (0..NUMBER_OF_INPUTS)
-            .map(|i| {
-                let mut vin = VoltageRatioInput::new();
-                vin.set_serial_number(self.phidget_id)
-                    .map_err(|_| ScaleError::InvalidPhidgetId)?;
-                vin.set_channel(i as i32).unwrap();
-                vin.open_wait(timeout)
-                    .map_err(|return_code| ScaleError::phidget_error(return_code, i))?;
-                Ok(vin)
-            })
-            .collect();
+        check_timeout(timeout, self.max_duration)?;
+        let claim = (!self.shared)
+            .then(|| SerialClaim::claim(self.phidget_id))
+            .transpose()?;
+        let vins = open_channels_on_port(
+            Some(self.phidget_id),
+            self.hub_port,
+            timeout,
+            VoltageRatioInput::new,
+        )?;
+        let mut scale = ConnectedScale::new(self.phidget_id, offset, coefficients, vins);
+        scale.hub_port = self.hub_port;
+        scale.max_duration = self.max_duration;
+        scale.claim = claim;
+        Ok(scale)
+    }
+}
 
-        let vins_vec = vins_result?;
-        let vins = match vins_vec.try_into() {
-            Ok(arr) => arr,
-            Err(_) => unreachable!("We know the size is correct"),
+/// Opens channels `0..NUMBER_OF_INPUTS` of the phidget with serial number
+/// `phidget_id`, or of any phidget, on handles made by `new`, waiting up to
+/// `timeout` for each.
+///
+/// Once the first channel is open, the device must report at least
+/// [`NUMBER_OF_INPUTS`] channels of its class; a device that does not, or
+/// that turns down a channel number, fails with
+/// [`ScaleError::WrongDevice`]. Generic over the handle so the sequence can
+/// be run against stand-ins for the hardware.
+///
+/// If a channel fails to open, the ones opened before it are closed again so
+/// that the next attempt does not find them busy, and the error is wrapped in
+/// [`ScaleError::OpenRolledBack`].
+#[cfg(feature = "hardware")]
+pub fn open_channels<P: Phidget>(
+    phidget_id: Option<i32>,
+    timeout: Duration,
+    new: impl FnMut() -> P,
+) -> Result<[P; NUMBER_OF_INPUTS], ScaleError> {
+    open_channels_on_port(phidget_id, None, timeout, new)
+}
+
+/// Like [`open_channels`], on the VINT hub port `hub_port` if given.
+#[cfg(feature = "hardware")]
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(
+        name = "connect",
+        target = "libra",
+        level = "info",
+        skip_all,
+        fields(phidget_id, hub_port, timeout_ms = timeout.as_millis() as u64),
+        err(Display)
+    )
+)]
+pub(crate) fn open_channels_on_port<P: Phidget>(
+    phidget_id: Option<i32>,
+    hub_port: Option<i32>,
+    timeout: Duration,
+    mut new: impl FnMut() -> P,
+) -> Result<[P; NUMBER_OF_INPUTS], ScaleError> {
+    let mut opened = Vec::with_capacity(NUMBER_OF_INPUTS);
+    for channel in 0..NUMBER_OF_INPUTS {
+        let mut vin = new();
+        if let Err(error) = open_channel(&mut vin, phidget_id, hub_port, channel, timeout) {
+            return Err(roll_back(opened, error));
+        }
+        opened.push(vin);
+        if channel == 0 {
+            if let Err(error) = check_channel_count(&mut opened[0]) {
+                return Err(roll_back(opened, error));
+            }
+        }
+    }
+    opened.try_into().map_err(|opened: Vec<P>| {
+        let error = ScaleError::WrongDevice {
+            channel: opened.len(),
+            channels: None,
         };
+        roll_back(opened, error)
+    })
+}
 
-        Ok(ConnectedScale::new(
-            self.phidget_id,
-            offset,
-            coefficients,
-            vins,
-        ))
+#[cfg(feature = "hardware")]
+fn open_channel(
+    vin: &mut impl Phidget,
+    phidget_id: Option<i32>,
+    hub_port: Option<i32>,
+    channel: usize,
+    timeout: Duration,
+) -> Result<(), ScaleError> {
+    if let Some(phidget_id) = phidget_id {
+        vin.set_serial_number(phidget_id)
+            .map_err(|_| ScaleError::InvalidPhidgetId)?;
+    }
+    if let Some(hub_port) = hub_port {
+        vin.set_hub_port(hub_port)
+            .map_err(|return_code| ScaleError::phidget_error(return_code, channel))?;
+    }
+    vin.set_channel(channel as i32)
+        .map_err(|_| ScaleError::WrongDevice {
+            channel,
+            channels: None,
+        })?;
+    vin.open_wait(timeout)
+        .map_err(|return_code| ScaleError::phidget_error(return_code, channel))
+}
+
+/// Closes the channels in `opened` after `error`, ignoring failures to close:
+/// a handle that stays open is closed again when it is dropped. The handle
+/// that failed is not among them; dropping it closes it if need be.
+#[cfg(feature = "hardware")]
+fn roll_back<P: Phidget>(opened: Vec<P>, error: ScaleError) -> ScaleError {
+    if opened.is_empty() {
+        return error;
+    }
+    let closed = opened.len();
+    for mut vin in opened {
+        let _ = vin.close();
     }
+    ScaleError::OpenRolledBack {
+        closed,
+        error: Box::new(error),
+    }
+}
+
+/// Fails unless the device `vin` is open on has a channel of its class for
+/// every load cell.
+#[cfg(feature = "hardware")]
+fn check_channel_count(vin: &mut impl Phidget) -> Result<(), ScaleError> {
+    let channels = vin
+        .channel_class()
+        .and_then(|class| vin.device_channel_count(class))
+        .map_err(|return_code| ScaleError::phidget_error(return_code, 0))?;
+    if channels as usize >= NUMBER_OF_INPUTS {
+        return Ok(());
+    }
+    Err(ScaleError::WrongDevice {
+        channel: channels as usize,
+        channels: Some(channels),
+    })
 }
 
-pub struct ConnectedScale {
+/// A scale with all four load cell channels open.
+///
+/// `ConnectedScale` is `Send`, so it can be handed to another thread, but not
+/// `Sync`: the phidget handles must not be used from two threads at once. Wrap
+/// it in a [`SharedScale`](crate::shared::SharedScale) to share it.
+///
+/// The channels are phidget `VoltageRatioInput`s unless the scale was made
+/// with [`from_sources`](Self::from_sources) from some other
+/// [`VoltageSource`], such as a
+/// [`FakeVoltageSource`](crate::testing::FakeVoltageSource).
+#[cfg(feature = "hardware")]
+pub struct ConnectedScale<V = VoltageRatioInput> {
     phidget_id: i32,
+    /// The VINT hub port the channels were opened on, if one was asked for.
+    hub_port: Option<i32>,
     offset: f64,
     coefficients: [f64; NUMBER_OF_INPUTS],
-    vins: [VoltageRatioInput; NUMBER_OF_INPUTS],
+    /// Subtracted from every weight, after the calibration.
+    tare: f64,
+    calibrated_at: Option<SystemTime>,
+    calibration_policy: Option<CalibrationPolicy>,
+    /// Whether the calibration's expiry or future date has been reported to
+    /// `tracing` since the policy or calibration time last changed.
+    expiry_reported: Cell<bool>,
+    last_error: RefCell<Option<ScaleErrorInfo>>,
+    /// Longest sampling interval accepted.
+    max_duration: Duration,
+    /// `None` once closed, or when connected as shared.
+    claim: Option<SerialClaim>,
+    /// Paces the samples of medians.
+    clock: Arc<dyn Clock>,
+    /// Heaviest gross load read without `ScaleError::OverCapacity`.
+    capacity: Option<f64>,
+    /// Medians asked for through the `Scale` trait, which has no arguments
+    /// for them.
+    sampling: SamplingDefaults,
+    /// Settings the phidget did not take as asked when connected.
+    mismatches: Vec<SettingMismatch>,
+    /// The platform the load cells are of, for the identity saved with a
+    /// calibration.
+    platform_id: Option<String>,
+    /// The samples of each load cell that its median is taken of, kept
+    /// from one median to the next so that their room is only made once.
+    cell_samples: RefCell<[Vec<f64>; NUMBER_OF_INPUTS]>,
+    /// The ratios change events reported, once
+    /// [`cache_change_events`](Self::cache_change_events) is called.
+    snapshot: Option<RatioSnapshot>,
+    reads: ReadCounters,
+    /// The longest data interval of the channels when last set or asked,
+    /// which load cell medians are paced by at the least.
+    known_data_interval: Cell<Option<Duration>>,
+    vins: [V; NUMBER_OF_INPUTS],
 }
 
+#[cfg(feature = "hardware")]
 impl ConnectedScale {
+    /// Fails with `ScaleError::InvalidArgument`, without opening anything,
+    /// for a `timeout` that is zero or over [`DEFAULT_MAX_DURATION`]. The
+    /// serial number is only known once the channels are open, so if another
+    /// scale in this process has that phidget they are closed again and it
+    /// fails with `ScaleError::AlreadyConnected`.
+    pub fn without_id(timeout: Duration) -> Result<Self, ScaleError> {
+        check_timeout(timeout, DEFAULT_MAX_DURATION)?;
+        let mut vins = open_channels(None, timeout, VoltageRatioInput::new)?;
+
+        let vin = 0;
+        let sn = Phidget::serial_number(&mut vins[vin])
+            .map_err(|return_code| ScaleError::phidget_error(return_code, vin))?;
+
+        let mut scale = Self::new(sn, 0., [0.; NUMBER_OF_INPUTS], vins);
+        match SerialClaim::claim(sn) {
+            Ok(claim) => scale.claim = Some(claim),
+            Err(error) => {
+                let _ = scale.close();
+                return Err(error);
+            }
+        }
+        Ok(scale)
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl<V: VoltageSource> ConnectedScale<V> {
     fn new(
         phidget_id: i32,
         offset: f64,
         coefficients: [f64; NUMBER_OF_INPUTS],
-        vins: [VoltageRatioInput; NUMBER_OF_INPUTS],
+        vins: [V; NUMBER_OF_INPUTS],
     ) -> Self {
         Self {
             phidget_id,
+            hub_port: None,
             offset,
             coefficients,
+            tare: 0.,
+            calibrated_at: None,
+            calibration_policy: None,
+            expiry_reported: Cell::new(false),
+            last_error: RefCell::new(None),
+            max_duration: DEFAULT_MAX_DURATION,
+            claim: None,
+            clock: Arc::new(SystemClock),
+            capacity: None,
+            sampling: SamplingDefaults::default(),
+            mismatches: Vec::new(),
+            platform_id: None,
+            cell_samples: RefCell::default(),
+            snapshot: None,
+            reads: ReadCounters::default(),
+            known_data_interval: Cell::new(None),
             vins,
         }
     }
 
-    pub fn without_id(timeout: Duration) -> Result<Self, ScaleError> {
-        let vins_result: Result<Vec<VoltageRatioInput>, ScaleError> = (0..NUMBER_OF_INPUTS)
-            .map(|i| {
-                let mut vin = VoltageRatioInput::new();
-                vin.set_channel(i as i32).unwrap(); //This is ok because its impossible for i to exceed the number of channels
-                vin.open_wait(timeout)
-                    .map_err(|return_code| ScaleError::phidget_error(return_code, i))?;
-                Ok(vin)
-            })
-            .collect();
-
-        let vins_vec = vins_result?;
-        let mut vins: [VoltageRatioInput; NUMBER_OF_INPUTS] = match vins_vec.try_into() {
-            Ok(arr) => arr,
-            Err(_) => unreachable!("We know the size is correct"),
-        };
-
-        let vin = 0;
-        let sn = Phidget::serial_number(&mut vins[vin])
-            .map_err(|return_code| ScaleError::phidget_error(return_code, vin))?;
-
-        Ok(Self::new(sn, 0., [0.; NUMBER_OF_INPUTS], vins))
+    /// A scale reading the load cells through `sources`, one per channel in
+    /// channel order, with the phidget serial number `phidget_id`. Nothing is
+    /// claimed in the [`registry`](crate::registry).
+    pub fn from_sources(
+        phidget_id: i32,
+        calibration: Calibration,
+        sources: [V; NUMBER_OF_INPUTS],
+    ) -> Self {
+        Self::new(
+            phidget_id,
+            calibration.offset,
+            calibration.coefficients,
+            sources,
+        )
     }
 
     pub fn set_data_intervals(&mut self, interval: Duration) -> Result<(), ScaleError> {
+        self.known_data_interval.set(None);
         self.vins.iter_mut().enumerate().try_for_each(|(i, vin)| {
             vin.set_data_interval(interval)
                 .map_err(|return_code| ScaleError::phidget_error(return_code, i))
-        })
+        })?;
+        self.known_data_interval.set(Some(interval));
+        Ok(())
     }
 
     pub fn get_data_intervals(&mut self) -> Result<Vec<Duration>, ScaleError> {
-        self.vins
+        let intervals = self
+            .vins
             .iter_mut()
             .enumerate()
             .map(|(i, vin)| {
                 vin.data_interval()
                     .map_err(|e| ScaleError::phidget_error(e, i))
             })
-            .collect()
+            .collect::<Result<Vec<_>, _>>()?;
+        self.known_data_interval
+            .set(intervals.iter().copied().max());
+        Ok(intervals)
     }
 
-    pub fn update_coefficients(self, coefficients: [f64; 4]) -> Self {
-        Self {
-            phidget_id: self.phidget_id,
-            offset: self.offset,
-            coefficients,
-            vins: self.vins,
+    /// The longest data interval of the channels, which is how often a
+    /// reading of every load cell is new.
+    pub fn data_interval(&mut self) -> Result<Duration, ScaleError> {
+        Ok(self
+            .get_data_intervals()?
+            .into_iter()
+            .max()
+            .unwrap_or_default())
+    }
+
+    /// Sets the data interval of every channel, and returns the one
+    /// [`data_interval`](Self::data_interval) then reports. Fails with
+    /// `ScaleError::InvalidArgument` for a zero `interval`.
+    pub fn set_data_interval(&mut self, interval: Duration) -> Result<Duration, ScaleError> {
+        if interval.is_zero() {
+            return Err(ScaleError::InvalidArgument(
+                "A data interval must be at least 1 ms".into(),
+            ));
         }
+        self.set_data_intervals(interval)?;
+        self.data_interval()
     }
 
-    pub fn update_offset(self, offset: f64) -> Self {
-        Self {
-            phidget_id: self.phidget_id,
-            offset,
-            coefficients: self.coefficients,
-            vins: self.vins,
+    /// The shortest and longest data intervals every channel takes, each
+    /// `None` if a channel cannot say.
+    pub fn data_interval_bounds(
+        &mut self,
+    ) -> Result<(Option<Duration>, Option<Duration>), ScaleError> {
+        let (mut min, mut max) = (Some(Duration::ZERO), Some(Duration::MAX));
+        for (i, vin) in self.vins.iter_mut().enumerate() {
+            let bound = |result: phidget::Result<Duration>| match result {
+                Ok(bound) => Ok(Some(bound)),
+                Err(ReturnCode::Unsupported) => Ok(None),
+                Err(return_code) => Err(ScaleError::phidget_error(return_code, i)),
+            };
+            let (shortest, longest) = (
+                bound(vin.min_data_interval())?,
+                bound(vin.max_data_interval())?,
+            );
+            min = min.zip(shortest).map(|(min, shortest)| min.max(shortest));
+            max = max.zip(longest).map(|(max, longest)| max.min(longest));
         }
+        Ok((min, max))
+    }
+
+    /// Sets the data interval of every channel to the one
+    /// [`data_interval_for`] picks for samples `sampling` apart, within the
+    /// [`data_interval_bounds`](Self::data_interval_bounds). The scale is
+    /// read through the returned guard, which puts back each channel's data
+    /// interval from before when dropped. If a channel does not take the new
+    /// interval, the ones from before are put back and the error returned.
+    pub fn tune_data_interval_for(
+        &mut self,
+        sampling: Duration,
+    ) -> Result<TunedDataInterval<'_, V>, ScaleError> {
+        let previous = self.get_data_intervals()?;
+        let (min, max) = self.data_interval_bounds()?;
+        let interval = match self.set_data_interval(data_interval_for(sampling, min, max)) {
+            Ok(interval) => interval,
+            Err(error) => {
+                let _ = self.restore_data_intervals(&previous);
+                return Err(error);
+            }
+        };
+        Ok(TunedDataInterval {
+            scale: self,
+            previous,
+            interval,
+            restored: false,
+        })
     }
 
-    pub fn get_raw_readings(&self) -> Result<Vec<f64>, ScaleError> {
+    fn restore_data_intervals(&mut self, intervals: &[Duration]) -> Result<(), ScaleError> {
+        self.known_data_interval.set(None);
         self.vins
-            .iter()
+            .iter_mut()
+            .zip(intervals)
+            .enumerate()
+            .try_for_each(|(i, (vin, &interval))| {
+                vin.set_data_interval(interval)
+                    .map_err(|return_code| ScaleError::phidget_error(return_code, i))
+            })?;
+        self.known_data_interval
+            .set(intervals.iter().copied().max());
+        Ok(())
+    }
+
+    /// Sets the gain of every channel, each to its own of `gains`, in
+    /// channel order.
+    pub fn set_bridge_gains(
+        &mut self,
+        gains: [BridgeGain; NUMBER_OF_INPUTS],
+    ) -> Result<(), ScaleError> {
+        self.vins
+            .iter_mut()
+            .zip(gains)
+            .enumerate()
+            .try_for_each(|(i, (vin, gain))| {
+                vin.set_bridge_gain(gain)
+                    .map_err(|return_code| ScaleError::phidget_error(return_code, i))
+            })
+    }
+
+    pub fn get_bridge_gains(&mut self) -> Result<Vec<BridgeGain>, ScaleError> {
+        self.vins
+            .iter_mut()
             .enumerate()
             .map(|(i, vin)| {
-                vin.voltage_ratio()
+                vin.bridge_gain()
                     .map_err(|e| ScaleError::phidget_error(e, i))
             })
             .collect()
     }
+
+    /// The settings of the config connected with that the phidget did not
+    /// take as asked, such as a data interval it clamped to the range it
+    /// supports, as read back once they were set. Empty for a scale not
+    /// connected with [`ScaleConfig::connect`](crate::config::ScaleConfig::connect).
+    pub fn connect_mismatches(&self) -> &[SettingMismatch] {
+        &self.mismatches
+    }
+
+    pub(crate) fn set_connect_mismatches(&mut self, mismatches: Vec<SettingMismatch>) {
+        self.mismatches = mismatches;
+    }
+
+    /// The longest sampling interval accepted; see
+    /// [`DisconnectedScale::with_max_duration`].
+    pub fn max_duration(&self) -> Duration {
+        self.max_duration
+    }
+
+    pub fn set_max_duration(&mut self, max_duration: Duration) {
+        self.max_duration = max_duration;
+    }
+
+    /// Paces the samples of medians by `clock` rather than the real clock.
+    /// Change events already cached keep the clock they were cached with.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Keeps the ratio each load cell's change events report, so that a
+    /// weight or load cell median is read from them, without asking any load
+    /// cell, while every one has reported within `max_age`. Otherwise, or
+    /// once any has not, each load cell is asked as before. A median of
+    /// weights taken faster than the phidget's data interval weighs the same
+    /// ratios more than once.
+    ///
+    /// Replaces any change event callback set on the channels. Fails with
+    /// `ScaleError::InvalidArgument` for a zero `max_age`, which nothing
+    /// could meet.
+    pub fn cache_change_events(&mut self, max_age: Duration) -> Result<(), ScaleError> {
+        if max_age.is_zero() {
+            return Err(ScaleError::InvalidArgument(
+                "the age of cached change events must be more than zero".into(),
+            ));
+        }
+        let snapshot = RatioSnapshot::new(Arc::clone(&self.clock), max_age);
+        for (i, vin) in self.vins.iter_mut().enumerate() {
+            vin.on_ratio_change(snapshot.recorder(i))
+                .map_err(|return_code| ScaleError::phidget_error(return_code, i))?;
+        }
+        self.snapshot = Some(snapshot);
+        Ok(())
+    }
+
+    /// The oldest cached change events are read from, if they are cached;
+    /// see [`cache_change_events`](Self::cache_change_events).
+    pub fn change_event_max_age(&self) -> Option<Duration> {
+        self.snapshot.as_ref().map(RatioSnapshot::max_age)
+    }
+
+    /// How many readings came from cached change events and how many asked
+    /// each load cell. The handle keeps counting as the scale reads.
+    pub fn read_counters(&self) -> ReadCounters {
+        self.reads.clone()
+    }
+
+    /// The heaviest load the scale reads, if limited; see
+    /// [`set_capacity`](Self::set_capacity).
+    pub fn capacity(&self) -> Option<Grams> {
+        self.capacity.map(Grams)
+    }
+
+    /// Fails readings of more than `capacity` on the platform, tare
+    /// included, with `ScaleError::OverCapacity`. `None`, the default, reads
+    /// any load.
+    pub fn set_capacity(&mut self, capacity: Option<Grams>) {
+        self.capacity = capacity.map(|capacity| capacity.0);
+    }
+
+    /// How medians taken through the [`Scale`] trait are sampled.
+    pub fn sampling(&self) -> SamplingDefaults {
+        self.sampling
+    }
+
+    pub fn set_sampling(&mut self, sampling: SamplingDefaults) {
+        self.sampling = sampling;
+    }
+
+    pub(crate) fn set_claim(&mut self, claim: Option<SerialClaim>) {
+        self.claim = claim;
+    }
+
+    /// The VINT hub port the channels were opened on, when connected by a
+    /// [`ScaleConfig`](crate::config::ScaleConfig) that named one.
+    pub fn hub_port(&self) -> Option<i32> {
+        self.hub_port
+    }
+
+    pub(crate) fn set_hub_port(&mut self, hub_port: Option<i32>) {
+        self.hub_port = hub_port;
+    }
+
+    /// The platform the load cells are of, if it has been named.
+    pub fn platform_id(&self) -> Option<&str> {
+        self.platform_id.as_deref()
+    }
+
+    pub fn set_platform_id(&mut self, platform_id: Option<String>) {
+        self.platform_id = platform_id;
+    }
+
+    /// The platform, if named, and the bridge it is read through.
+    pub fn identity(&self) -> Option<ScaleIdentity> {
+        Some(ScaleIdentity {
+            platform_id: self.platform_id.clone()?,
+            bridge_serial: self.phidget_id,
+        })
+    }
+
+    pub fn update_coefficients(self, coefficients: [f64; 4]) -> Self {
+        Self {
+            coefficients,
+            ..self
+        }
+    }
+
+    pub fn update_offset(self, offset: f64) -> Self {
+        Self { offset, ..self }
+    }
+
+    pub fn calibration(&self) -> Calibration {
+        Calibration {
+            offset: self.offset,
+            coefficients: self.coefficients,
+        }
+    }
+
+    /// Replaces the calibration. The tare is kept.
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.offset = calibration.offset;
+        self.coefficients = calibration.coefficients;
+    }
+
+    /// When the calibration in use was made, if known. Not changed by
+    /// [`set_calibration`](Self::set_calibration) or [`zero`](Self::zero).
+    pub fn calibrated_at(&self) -> Option<SystemTime> {
+        self.calibrated_at
+    }
+
+    pub fn set_calibrated_at(&mut self, calibrated_at: Option<SystemTime>) {
+        self.calibrated_at = calibrated_at;
+        self.expiry_reported.set(false);
+    }
+
+    /// Judges the calibration by its age since
+    /// [`calibrated_at`](Self::calibrated_at). Once it is older than
+    /// `max_age`, `ExpiryAction::Warn` degrades health reports and emits a
+    /// `calibration_expired` event, and `ExpiryAction::Block` makes them
+    /// unusable and fails weights with `ScaleError::CalibrationExpired`.
+    ///
+    /// A calibration of unknown age never expires. One dated in the future
+    /// is not blocked, but degrades health reports and emits a
+    /// `calibration_future_dated` event.
+    pub fn set_calibration_policy(&mut self, max_age: Duration, action: ExpiryAction) {
+        self.calibration_policy = Some(CalibrationPolicy::new(max_age, action));
+        self.expiry_reported.set(false);
+    }
+
+    pub fn calibration_policy(&self) -> Option<CalibrationPolicy> {
+        self.calibration_policy
+    }
+
+    /// Lets the calibration be used however old it is.
+    pub fn clear_calibration_policy(&mut self) {
+        self.calibration_policy = None;
+    }
+
+    /// Where the calibration stands under the scale's policy, if it has one
+    /// and knows when the calibration was made.
+    pub fn calibration_expiry(&self) -> Option<CalibrationExpiry> {
+        let policy = self.calibration_policy?;
+        Some(policy.expiry(self.calibrated_at?, SystemTime::now()))
+    }
+
+    /// Fails if the policy blocks an expired calibration, reporting an
+    /// expired or future dated one to `tracing` the first time.
+    fn check_calibration(&self) -> Result<(), ScaleError> {
+        let (Some(policy), Some(expiry)) = (self.calibration_policy, self.calibration_expiry())
+        else {
+            return Ok(());
+        };
+        match expiry {
+            CalibrationExpiry::Valid { .. } => Ok(()),
+            CalibrationExpiry::Expired { age, .. } => {
+                if !self.expiry_reported.replace(true) {
+                    health::calibration_expired(age, policy.action);
+                }
+                match policy.action {
+                    ExpiryAction::Warn => Ok(()),
+                    ExpiryAction::Block => Err(ScaleError::CalibrationExpired { age }),
+                }
+            }
+            CalibrationExpiry::FutureDated { ahead } => {
+                if !self.expiry_reported.replace(true) {
+                    health::calibration_future_dated(ahead);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The last error from reading a load cell.
+    pub fn last_error(&self) -> Option<ScaleErrorInfo> {
+        self.last_error.borrow().clone()
+    }
+
+    /// Whether the scale is fit for production right now, judged by
+    /// [`HealthConfig::default`] from `samples` raw reads. See
+    /// [`health::check`].
+    pub fn health(&self, samples: usize) -> Result<HealthReport, ScaleError> {
+        self.health_with(samples, &HealthConfig::default())
+    }
+
+    /// Like [`health`](Self::health), judged by `config`, or by the scale's
+    /// calibration policy where `config` has none.
+    pub fn health_with(
+        &self,
+        samples: usize,
+        config: &HealthConfig,
+    ) -> Result<HealthReport, ScaleError> {
+        let config = HealthConfig {
+            calibration_policy: config.calibration_policy.or(self.calibration_policy),
+            ..config.clone()
+        };
+        health::check(
+            self,
+            samples,
+            self.calibrated_at,
+            self.last_error(),
+            &config,
+        )
+    }
+
+    pub fn tare_weight(&self) -> Grams {
+        Grams(self.tare)
+    }
+
+    pub fn clear_tare(&mut self) {
+        self.tare = 0.;
+    }
+
+    /// Takes the median weight, ignoring any current tare, as the new tare,
+    /// so the scale reads zero with the container on it. Returns the tare.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "tare",
+            target = "libra",
+            level = "info",
+            skip_all,
+            fields(samples, interval_ms = interval.as_millis() as u64)
+        )
+    )]
+    pub fn tare(&mut self, samples: usize, interval: Duration) -> Result<Grams, ScaleError> {
+        let gross = self.get_median_weight(samples, interval)?.get() + self.tare;
+        self.tare = gross;
+        Ok(Grams(gross))
+    }
+
+    /// Corrects the calibration offset so the empty platform reads zero, and
+    /// clears the tare. Returns the corrected calibration.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "zero",
+            target = "libra",
+            level = "info",
+            skip_all,
+            fields(samples, interval_ms = interval.as_millis() as u64)
+        )
+    )]
+    pub fn zero(&mut self, samples: usize, interval: Duration) -> Result<Calibration, ScaleError> {
+        let gross = self.get_median_weight(samples, interval)?.get() + self.tare;
+        self.offset += gross;
+        self.tare = 0.;
+        Ok(self.calibration())
+    }
+
+    /// Whether every load cell channel is attached, along with the
+    /// calibration and tare in use.
+    pub fn status(&mut self) -> Result<ScaleStatus, ScaleError> {
+        let mut attached = true;
+        for (i, vin) in self.vins.iter_mut().enumerate() {
+            attached &= vin
+                .is_attached()
+                .map_err(|return_code| ScaleError::phidget_error(return_code, i))?;
+        }
+        Ok(ScaleStatus {
+            phidget_id: self.phidget_id,
+            attached,
+            calibration: self.calibration(),
+            tare: self.tare_weight(),
+            calibration_expires_in_days: self
+                .calibration_expiry()
+                .and_then(|expiry| expiry.days_left()),
+        })
+    }
+
+    pub fn get_raw_readings(&self) -> Result<[f64; NUMBER_OF_INPUTS], ScaleError> {
+        RawScale::get_raw_readings(self)
+    }
+
+    /// Reads every load cell, carrying on past any that fail, and returns
+    /// each one's result. See [`all_channels`] for combining them.
+    pub fn get_raw_readings_all(&self) -> [Result<f64, ScaleError>; NUMBER_OF_INPUTS] {
+        array::from_fn(|input| self.get_input_reading(input))
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "get_weight", target = "libra", level = "debug", skip_all)
+    )]
     pub fn get_weight(&self) -> Result<Grams, ScaleError> {
-        let readings = self.get_raw_readings()?;
-        Ok(Grams(
-            dot_product(readings.as_slice(), self.coefficients.as_slice()) - self.offset,
-        ))
+        self.check_calibration()?;
+        self.weigh(&RawScale::get_raw_readings(self)?)
+    }
+
+    fn weigh(&self, readings: &[f64; NUMBER_OF_INPUTS]) -> Result<Grams, ScaleError> {
+        let gross = finite(Grams(self.calibration().weigh(readings).get()))?.0;
+        match self.capacity {
+            Some(capacity) if gross > capacity => Err(ScaleError::OverCapacity {
+                weight: gross,
+                capacity,
+            }),
+            _ => Ok(Grams(gross - self.tare)),
+        }
     }
 
+    /// The median of `samples` weights, `interval` apart. An interval of zero
+    /// takes them back to back, as fast as the load cells answer, so they
+    /// may all come from the same few updates of the bridge. An interval
+    /// over [`max_duration`](Self::max_duration) fails with
+    /// `ScaleError::InvalidArgument`.
     pub fn get_median_weight(
         &self,
         samples: usize,
         interval: Duration,
     ) -> Result<MedianGrams, ScaleError> {
-        let mut weights = Vec::with_capacity(samples);
-        let mut init_time = time::Instant::now();
-        while weights.len() < samples {
-            let current_time = time::Instant::now();
-            let time_delta = current_time - init_time;
-            if time_delta > interval {
-                let weight = self.get_weight()?;
-                weights.push(weight);
-                init_time = time::Instant::now();
-            }
-        }
-        Ok(median(weights.as_mut_slice()))
+        self.get_median_weight_cancellable(samples, interval, &CancelFlag::new())
+    }
+
+    /// Like [`get_median_weight`](Self::get_median_weight), with the data
+    /// interval tuned for samples `interval` apart while the median is taken,
+    /// as [`tune_data_interval_for`](Self::tune_data_interval_for) does, and
+    /// put back after. Fails with the median's error before any from putting
+    /// the data interval back.
+    pub fn get_median_weight_tuned(
+        &mut self,
+        samples: usize,
+        interval: Duration,
+    ) -> Result<TunedMedian, ScaleError> {
+        check_samples(samples)?;
+        check_interval(interval, self.max_duration)?;
+        let tuned = self.tune_data_interval_for(interval)?;
+        let data_interval = tuned.interval();
+        let median = tuned.scale().get_median_weight(samples, interval);
+        let restored = tuned.restore();
+        let median = median?;
+        restored?;
+        Ok(TunedMedian {
+            median,
+            data_interval,
+        })
+    }
+
+    /// Like [`get_median_weight`](Self::get_median_weight), but gives up with
+    /// `ScaleError::Cancelled` as soon as `cancel` is set.
+    pub fn get_median_weight_cancellable(
+        &self,
+        samples: usize,
+        interval: Duration,
+        cancel: &CancelFlag,
+    ) -> Result<MedianGrams, ScaleError> {
+        check_interval(interval, self.max_duration)?;
+        collect_median_on(&*self.clock, samples, interval, cancel, || {
+            self.get_weight()
+        })
     }
 
     fn get_input_reading(&self, input: usize) -> Result<f64, ScaleError> {
-        self.vins[input]
-            .voltage_ratio()
-            .map_err(|e| ScaleError::phidget_error(e, input))
+        let reading = match self.vins[input].ratio() {
+            Ok(reading) if reading.is_finite() => Ok(reading),
+            Ok(_) => Err(ScaleError::NonFinite {
+                channel: Some(input),
+            }),
+            Err(e) => Err(ScaleError::phidget_error(e, input)),
+        };
+        if let Err(error) = &reading {
+            *self.last_error.borrow_mut() = Some(ScaleErrorInfo::from(error));
+        }
+        reading
+    }
+
+    /// The median of each load cell over `samples` readings of every load
+    /// cell at once, `sample_period` apart, which is checked as the interval
+    /// of [`get_median_weight`](Self::get_median_weight) is.
+    ///
+    /// Each reading is taken as [`get_raw_readings`](Self::get_raw_readings)
+    /// takes it, from cached change events while they are fresh, so the
+    /// load cells' samples line up: the `n`th of each is from the same
+    /// reading. Readings are at least the data interval apart, as last set
+    /// or asked of the scale, however short `sample_period`, so that each is
+    /// a new reading of the bridge rather than the last one again. The
+    /// medians are then of as many independent samples as asked for, and
+    /// take at least as many data intervals; calibrating with a short
+    /// period weighs a longer stretch than it did before, noise and all.
+    pub fn get_load_cell_medians(
+        &self,
+        samples: usize,
+        sample_period: Duration,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], ScaleError> {
+        self.get_load_cell_medians_cancellable(samples, sample_period, &CancelFlag::new())
     }
 
-    pub fn get_load_cell_medians(&self, samples: usize, sample_period: Duration) -> Result<[f64; NUMBER_OF_INPUTS], ScaleError> {
-        let mut medians: [Vec<f64>; NUMBER_OF_INPUTS] =
-            array::from_fn(|_| Vec::with_capacity(samples));
-        for _ in 0..samples {
-            for (i, vin_medians) in medians.iter_mut().enumerate().take(NUMBER_OF_INPUTS) {
-                vin_medians.push(self.get_input_reading(i)?);
+    /// Like [`get_load_cell_medians`](Self::get_load_cell_medians), but gives
+    /// up with `ScaleError::Cancelled` as soon as `cancel` is set.
+    pub fn get_load_cell_medians_cancellable(
+        &self,
+        samples: usize,
+        sample_period: Duration,
+        cancel: &CancelFlag,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], ScaleError> {
+        check_samples(samples)?;
+        check_interval(sample_period, self.max_duration)?;
+        let period = self
+            .known_data_interval
+            .get()
+            .map_or(sample_period, |data_interval| {
+                sample_period.max(data_interval)
+            });
+        let mut medians = self.cell_samples.borrow_mut();
+        for vin_medians in medians.iter_mut() {
+            vin_medians.clear();
+            vin_medians.reserve(samples);
+        }
+        for collected in 0..samples {
+            if cancel.is_cancelled() {
+                return Err(ScaleError::Cancelled { collected });
+            }
+            let readings = RawScale::get_raw_readings(self)?;
+            for (vin_medians, reading) in medians.iter_mut().zip(readings) {
+                vin_medians.push(reading);
+            }
+            if !cancel.sleep_on(&*self.clock, period) {
+                return Err(ScaleError::Cancelled {
+                    collected: collected + 1,
+                });
             }
-            std::thread::sleep(sample_period);
         }
         Ok(array::from_fn(|vin| {
-            medians.sort_by(|a, b| a.partial_cmp(b).unwrap());
-            medians[vin][samples / 2]
+            let (_, median, _) = medians[vin].select_nth_unstable_by(samples / 2, f64::total_cmp);
+            *median
         }))
     }
 
     pub fn get_phidget_id(&self) -> i32 {
         self.phidget_id
     }
+
+    /// Closes every load cell channel, attempting all of them even if one
+    /// fails, and releases the claim on the phidget. The first failure is
+    /// returned.
+    pub fn close(&mut self) -> Result<(), ScaleError> {
+        let mut result = Ok(());
+        for (i, vin) in self.vins.iter_mut().enumerate() {
+            if let Err(return_code) = vin.close() {
+                result = result.and(Err(ScaleError::phidget_error(return_code, i)));
+            }
+        }
+        self.claim = None;
+        result
+    }
+}
+
+/// A median taken with the data interval tuned for it, from
+/// [`ConnectedScale::get_median_weight_tuned`].
+#[cfg(feature = "hardware")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TunedMedian {
+    pub median: MedianGrams,
+    /// The data interval the bridge read at while the median was taken.
+    pub data_interval: Duration,
+}
+
+/// A scale with its data interval tuned for a sampling rate, from
+/// [`ConnectedScale::tune_data_interval_for`]. Dropping it puts back each
+/// channel's data interval from before, and gives up quietly on any channel
+/// that does not take it; [`restore`](Self::restore) says whether they all
+/// did.
+#[cfg(feature = "hardware")]
+pub struct TunedDataInterval<'a, V: VoltageSource> {
+    scale: &'a mut ConnectedScale<V>,
+    /// Each channel's data interval from before, in channel order.
+    previous: Vec<Duration>,
+    interval: Duration,
+    restored: bool,
+}
+
+#[cfg(feature = "hardware")]
+impl<V: VoltageSource> TunedDataInterval<'_, V> {
+    /// The data interval the scale reads at until this is dropped.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// The longest data interval from before, as
+    /// [`ConnectedScale::data_interval`] reported it.
+    pub fn previous(&self) -> Duration {
+        self.previous.iter().copied().max().unwrap_or_default()
+    }
+
+    pub fn scale(&self) -> &ConnectedScale<V> {
+        self.scale
+    }
+
+    pub fn scale_mut(&mut self) -> &mut ConnectedScale<V> {
+        self.scale
+    }
+
+    /// Puts back each channel's data interval from before, failing with the
+    /// first channel that does not take it.
+    pub fn restore(mut self) -> Result<(), ScaleError> {
+        self.restored = true;
+        self.scale.restore_data_intervals(&self.previous)
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl<V: VoltageSource> Drop for TunedDataInterval<'_, V> {
+    fn drop(&mut self) {
+        if !self.restored {
+            let _ = self.scale.restore_data_intervals(&self.previous);
+        }
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl<V: VoltageSource> RawScale for ConnectedScale<V> {
+    fn get_raw_readings(&self) -> Result<[f64; NUMBER_OF_INPUTS], ScaleError> {
+        if let Some(ratios) = self.snapshot.as_ref().and_then(RatioSnapshot::fresh) {
+            self.reads.count_cached();
+            return Ok(ratios);
+        }
+        self.reads.count_direct();
+        all_channels(self.get_raw_readings_all())
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl<V: VoltageSource> Scale for ConnectedScale<V> {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ConnectedScale::get_weight(self)?)
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ConnectedScale::get_median_weight(
+            self,
+            self.sampling.median_samples,
+            self.sampling.sample_interval(),
+        )?)
+    }
+
+    fn get_median_weight_cancellable(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ConnectedScale::get_median_weight_cancellable(
+            self,
+            samples,
+            self.sampling.sample_interval(),
+            cancel,
+        )?)
+    }
+
+    fn get_median_weight_yielding(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+        between_samples: &mut dyn FnMut(),
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        let median: Result<_, ScaleError> = collect_median_on(
+            &*self.clock,
+            samples,
+            self.sampling.sample_interval(),
+            cancel,
+            || {
+                between_samples();
+                ConnectedScale::get_weight(self)
+            },
+        );
+        Ok(median?)
+    }
+
+    fn get_weight_observed(
+        &self,
+        observe: &mut dyn FnMut(&[f64; NUMBER_OF_INPUTS]) -> Result<(), ScaleError>,
+    ) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.check_calibration()?;
+        let readings = RawScale::get_raw_readings(self)?;
+        observe(&readings)?;
+        Ok(self.weigh(&readings)?)
+    }
+
+    fn tare(&mut self, samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ConnectedScale::tare(
+            self,
+            samples,
+            self.sampling.sample_interval(),
+        )?)
+    }
+
+    fn zero(
+        &mut self,
+        samples: usize,
+    ) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ConnectedScale::zero(
+            self,
+            samples,
+            self.sampling.sample_interval(),
+        )?)
+    }
+
+    fn get_raw_readings(
+        &self,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        Ok(RawScale::get_raw_readings(self)?)
+    }
+
+    fn get_raw_medians(
+        &self,
+        samples: usize,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.get_load_cell_medians(samples, self.sampling.sample_interval())?)
+    }
+
+    fn calibration(&self) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ConnectedScale::calibration(self))
+    }
+
+    fn set_calibration(
+        &mut self,
+        calibration: Calibration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        calibration.validate()?;
+        ConnectedScale::set_calibration(self, calibration);
+        Ok(())
+    }
+
+    fn status(&mut self) -> Result<ScaleStatus, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ConnectedScale::status(self)?)
+    }
+
+    fn data_interval(&mut self) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ConnectedScale::data_interval(self)?)
+    }
+
+    fn set_data_interval(
+        &mut self,
+        interval: Duration,
+    ) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ConnectedScale::set_data_interval(self, interval)?)
+    }
+
+    fn clock(&self) -> Arc<dyn Clock> {
+        Arc::clone(&self.clock)
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ConnectedScale::close(self)?)
+    }
 }
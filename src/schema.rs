@@ -0,0 +1,38 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use schemars::{schema_for, Schema};
+
+use crate::calibration::Calibration;
+use crate::{Reply, Request, ScaleCmd, ScaleErrorInfo, ScaleResponse, ScaleStatus, StampedWeight};
+
+/// The JSON Schema of each message of the command protocol and of the types
+/// they carry, by the file name [`export_schemas`] writes it to.
+pub fn schemas() -> Vec<(&'static str, Schema)> {
+    vec![
+        ("ScaleCmd.json", schema_for!(ScaleCmd)),
+        ("ScaleResponse.json", schema_for!(ScaleResponse)),
+        ("Request.json", schema_for!(Request)),
+        ("Reply.json", schema_for!(Reply)),
+        ("Calibration.json", schema_for!(Calibration)),
+        ("StampedWeight.json", schema_for!(StampedWeight)),
+        ("ScaleErrorInfo.json", schema_for!(ScaleErrorInfo)),
+        ("ScaleStatus.json", schema_for!(ScaleStatus)),
+    ]
+}
+
+/// Writes every one of [`schemas`] to `dir`, creating it if need be, and
+/// returns the paths written.
+pub fn export_schemas(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+    schemas()
+        .into_iter()
+        .map(|(name, schema)| {
+            let path = dir.join(name);
+            let mut text = serde_json::to_string_pretty(&schema).map_err(io::Error::other)?;
+            text.push('\n');
+            std::fs::write(&path, text)?;
+            Ok(path)
+        })
+        .collect()
+}
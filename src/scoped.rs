@@ -0,0 +1,191 @@
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{Scope, ScopedJoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(feature = "hardware")]
+use crate::calibration::RawScale;
+use crate::cancel::CancelFlag;
+#[cfg(feature = "hardware")]
+use crate::scale::ConnectedScale;
+use crate::scale::{ScaleError, NUMBER_OF_INPUTS};
+#[cfg(feature = "hardware")]
+use crate::source::VoltageSource;
+use crate::{Grams, StampedReadings, StampedWeight};
+
+/// Samples the weight on a scoped thread, for programs without an async
+/// runtime.
+///
+/// Because the thread lives in a [`std::thread::scope`], the scale is
+/// borrowed rather than moved and is available again once the scope ends.
+/// Readings arrive on the returned [`Receiver`] as `Ok` samples or in-band
+/// `Err`s, the same way the tokio weight stream yields them. The channel is unbounded, so a consumer that stops reading should
+/// stop the sampler too.
+///
+/// Sampling ends when [`stop`](Self::stop) is called, when the sampler is
+/// dropped, or when the receiver is dropped. Either way the thread exits
+/// within one interval, so the scope can end.
+pub struct ScopedSampler<'scope> {
+    stop: CancelFlag,
+    thread: Option<ScopedJoinHandle<'scope, ()>>,
+    /// What the data interval was tuned to, if it was.
+    data_interval: Option<Duration>,
+}
+
+impl<'scope> ScopedSampler<'scope> {
+    /// Starts reading `scale` every `interval` on a thread in `scope`.
+    ///
+    /// The scale is borrowed mutably because `ConnectedScale` is not `Sync`:
+    /// the sampling thread needs it to itself.
+    #[cfg(feature = "hardware")]
+    pub fn run<'env, V: VoltageSource + Send>(
+        scale: &'scope mut ConnectedScale<V>,
+        interval: Duration,
+        scope: &'scope Scope<'scope, 'env>,
+    ) -> (Self, Receiver<Result<StampedWeight, ScaleError>>) {
+        Self::run_with(move || scale.get_weight(), interval, scope)
+    }
+
+    /// Like [`run`](Self::run), with the scale's data interval tuned for
+    /// samples `interval` apart, as
+    /// [`ConnectedScale::tune_data_interval_for`] does, until the thread
+    /// exits, when it is put back. Fails without sampling if the scale does
+    /// not take the tuned interval.
+    #[cfg(feature = "hardware")]
+    pub fn run_tuned<'env, V: VoltageSource + Send>(
+        scale: &'scope mut ConnectedScale<V>,
+        interval: Duration,
+        scope: &'scope Scope<'scope, 'env>,
+    ) -> Result<(Self, Receiver<Result<StampedWeight, ScaleError>>), ScaleError> {
+        let tuned = scale.tune_data_interval_for(interval)?;
+        let data_interval = tuned.interval();
+        let (mut sampler, rx) = Self::run_with(move || tuned.scale().get_weight(), interval, scope);
+        sampler.data_interval = Some(data_interval);
+        Ok((sampler, rx))
+    }
+
+    /// Like [`run`](Self::run), sampling any `read` function.
+    pub fn run_with<'env, F>(
+        mut read: F,
+        interval: Duration,
+        scope: &'scope Scope<'scope, 'env>,
+    ) -> (Self, Receiver<Result<StampedWeight, ScaleError>>)
+    where
+        F: FnMut() -> Result<Grams, ScaleError> + Send + 'scope,
+    {
+        Self::spawn(
+            move |sequence| {
+                read().map(|weight| StampedWeight {
+                    weight,
+                    sequence,
+                    timestamp: SystemTime::now(),
+                })
+            },
+            interval,
+            scope,
+        )
+    }
+
+    /// Like [`run`](Self::run), sampling the voltage ratio of each load cell
+    /// instead of the weight.
+    #[cfg(feature = "hardware")]
+    pub fn run_raw<'env, V: VoltageSource + Send>(
+        scale: &'scope mut ConnectedScale<V>,
+        interval: Duration,
+        scope: &'scope Scope<'scope, 'env>,
+    ) -> (Self, Receiver<Result<StampedReadings, ScaleError>>) {
+        Self::run_raw_with(move || RawScale::get_raw_readings(scale), interval, scope)
+    }
+
+    /// Like [`run_raw`](Self::run_raw), sampling any `read` function.
+    pub fn run_raw_with<'env, F>(
+        mut read: F,
+        interval: Duration,
+        scope: &'scope Scope<'scope, 'env>,
+    ) -> (Self, Receiver<Result<StampedReadings, ScaleError>>)
+    where
+        F: FnMut() -> Result<[f64; NUMBER_OF_INPUTS], ScaleError> + Send + 'scope,
+    {
+        Self::spawn(
+            move |sequence| {
+                read().map(|ratios| StampedReadings {
+                    ratios,
+                    sequence,
+                    timestamp: SystemTime::now(),
+                })
+            },
+            interval,
+            scope,
+        )
+    }
+
+    /// Calls `read` with the sequence number of its next sample, which only
+    /// moves on after a successful read.
+    fn spawn<'env, T, F>(
+        mut read: F,
+        interval: Duration,
+        scope: &'scope Scope<'scope, 'env>,
+    ) -> (Self, Receiver<Result<T, ScaleError>>)
+    where
+        T: Send + 'scope,
+        F: FnMut(u64) -> Result<T, ScaleError> + Send + 'scope,
+    {
+        let (tx, rx) = mpsc::channel();
+        let stop = CancelFlag::new();
+        let thread = scope.spawn({
+            let stop = stop.clone();
+            move || {
+                let mut sequence = 0;
+                let mut next = Instant::now();
+                while !stop.is_cancelled() {
+                    let item = read(sequence);
+                    if item.is_ok() {
+                        sequence += 1;
+                    }
+                    if tx.send(item).is_err() {
+                        return;
+                    }
+                    next += interval;
+                    stop.sleep(next.saturating_duration_since(Instant::now()));
+                }
+            }
+        });
+        let sampler = Self {
+            stop,
+            thread: Some(thread),
+            data_interval: None,
+        };
+        (sampler, rx)
+    }
+
+    /// The data interval the scale reads at while sampled, if the sampler
+    /// tuned it; see [`run_tuned`](Self::run_tuned).
+    pub fn data_interval(&self) -> Option<Duration> {
+        self.data_interval
+    }
+
+    pub fn stop(&self) {
+        self.stop.cancel();
+    }
+
+    /// Whether the sampling thread has exited.
+    pub fn is_finished(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_none_or(|thread| thread.is_finished())
+    }
+
+    /// Stops sampling and waits for the thread to exit. A panic in the read
+    /// function is passed on to the caller.
+    pub fn join(mut self) {
+        self.stop();
+        if let Some(Err(panic)) = self.thread.take().map(ScopedJoinHandle::join) {
+            std::panic::resume_unwind(panic);
+        }
+    }
+}
+
+impl Drop for ScopedSampler<'_> {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,288 @@
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+#[cfg(feature = "serial")]
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::cancel::CancelFlag;
+use crate::command::execute;
+use crate::{Scale, ScaleCmd, ScaleErrorInfo, ScaleErrorKind, ScaleResponse};
+
+/// First byte of every frame (ASCII STX).
+pub const FRAME_START: u8 = 0x02;
+/// Last byte of every frame (ASCII ETX).
+pub const FRAME_END: u8 = 0x03;
+/// Largest payload a frame can carry.
+pub const MAX_PAYLOAD_LEN: usize = u16::MAX as usize;
+/// Largest payload [`serve_frames`] accepts. Commands are small, and a low
+/// limit lets the decoder give up quickly on a start byte found in noise.
+pub const MAX_COMMAND_LEN: usize = 256;
+
+/// Start, two length bytes, two CRC bytes and end around the payload.
+const FRAME_OVERHEAD: usize = 6;
+
+/// Why [`FrameDecoder`] discarded a frame.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    #[error("Frame CRC {received:#06x} does not match the computed {computed:#06x}")]
+    Crc { received: u16, computed: u16 },
+
+    #[error("Frame payload of {len} bytes exceeds the limit of {max}")]
+    TooLong { len: usize, max: usize },
+
+    #[error("Frame is not closed by the end byte")]
+    MissingEnd,
+
+    #[error("Frame payload could not be decoded: {0}")]
+    Payload(String),
+}
+
+/// CRC-16/MODBUS of `bytes`: polynomial 0x8005 reflected, initial value
+/// 0xFFFF.
+pub fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0xFFFF, |crc, &byte| {
+        (0..8).fold(crc ^ u16::from(byte), |crc, _| {
+            if crc & 1 == 0 {
+                crc >> 1
+            } else {
+                (crc >> 1) ^ 0xA001
+            }
+        })
+    })
+}
+
+/// Frames `cmd` for a serial line.
+///
+/// A frame is the start byte, the payload length as a big-endian `u16`, the
+/// payload, the [`crc16`] of the length and payload bytes (low byte first, as
+/// in Modbus), and the end byte. The payload is the message in bincode's
+/// standard configuration.
+///
+/// Panics if `cmd` does not fit in a frame. Only a string of tens of
+/// kilobytes, such as an absurd `ScaleCmd::Auth` token, can make it that
+/// long, and that is a bug in the caller rather than something the line can
+/// cause.
+pub fn encode_frame(cmd: &ScaleCmd) -> Vec<u8> {
+    encode_message(cmd).expect("every command fits in a frame")
+}
+
+/// Frames `response` the same way [`encode_frame`] frames a command. Fails
+/// with `FrameError::TooLong` if the response does not fit in a frame.
+pub fn encode_response_frame(response: &ScaleResponse) -> Result<Vec<u8>, FrameError> {
+    encode_message(response)
+}
+
+fn encode_message<T: Serialize>(message: &T) -> Result<Vec<u8>, FrameError> {
+    let payload = bincode::serde::encode_to_vec(message, bincode::config::standard())
+        .map_err(|e| FrameError::Payload(e.to_string()))?;
+    let len = u16::try_from(payload.len()).map_err(|_| FrameError::TooLong {
+        len: payload.len(),
+        max: MAX_PAYLOAD_LEN,
+    })?;
+    let mut frame = Vec::with_capacity(payload.len() + FRAME_OVERHEAD);
+    frame.push(FRAME_START);
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(&payload);
+    frame.extend_from_slice(&crc16(&frame[1..]).to_le_bytes());
+    frame.push(FRAME_END);
+    Ok(frame)
+}
+
+/// Reassembles frames from a byte stream that may arrive in pieces and may
+/// carry noise between frames.
+///
+/// Bytes before a start byte are skipped. When a candidate frame turns out
+/// to be bad, only its start byte is dropped and the search resumes from the
+/// next byte, so a real frame hidden behind a false start is still found.
+pub struct FrameDecoder<T> {
+    buffer: Vec<u8>,
+    max_payload: usize,
+    message: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> FrameDecoder<T> {
+    pub fn new() -> Self {
+        Self::with_max_payload(MAX_PAYLOAD_LEN)
+    }
+
+    /// A decoder that rejects frames claiming more than `max_payload` bytes
+    /// without waiting for them to arrive.
+    pub fn with_max_payload(max_payload: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_payload: max_payload.min(MAX_PAYLOAD_LEN),
+            message: PhantomData,
+        }
+    }
+
+    /// Adds bytes read from the line.
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// The next complete frame, `Some(Err(_))` for a frame that was
+    /// discarded, or `None` if more bytes are needed.
+    pub fn next_frame(&mut self) -> Option<Result<T, FrameError>> {
+        match self.buffer.iter().position(|&byte| byte == FRAME_START) {
+            Some(start) => {
+                self.buffer.drain(..start);
+            }
+            None => {
+                self.buffer.clear();
+                return None;
+            }
+        }
+        let header = self.buffer.get(1..3)?;
+        let len = usize::from(u16::from_be_bytes([header[0], header[1]]));
+        if len > self.max_payload {
+            self.buffer.remove(0);
+            return Some(Err(FrameError::TooLong {
+                len,
+                max: self.max_payload,
+            }));
+        }
+        let frame = self.buffer.get(..len + FRAME_OVERHEAD)?;
+        let (body, trailer) = frame[1..].split_at(2 + len);
+        let result = if trailer[2] != FRAME_END {
+            Err(FrameError::MissingEnd)
+        } else {
+            let received = u16::from_le_bytes([trailer[0], trailer[1]]);
+            let computed = crc16(body);
+            if received == computed {
+                Ok(decode_payload(&body[2..]))
+            } else {
+                Err(FrameError::Crc { received, computed })
+            }
+        };
+        match result {
+            Ok(message) => {
+                self.buffer.drain(..len + FRAME_OVERHEAD);
+                Some(message)
+            }
+            Err(e) => {
+                self.buffer.remove(0);
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Default for FrameDecoder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn decode_payload<T: DeserializeOwned>(payload: &[u8]) -> Result<T, FrameError> {
+    match bincode::serde::decode_from_slice(payload, bincode::config::standard()) {
+        Ok((message, read)) if read == payload.len() => Ok(message),
+        Ok(_) => Err(FrameError::Payload(
+            "trailing bytes after the message".into(),
+        )),
+        Err(e) => Err(FrameError::Payload(e.to_string())),
+    }
+}
+
+/// Answers framed commands read from `port` by running them on `scale`,
+/// writing one response frame per command, until `ScaleCmd::Shutdown` is
+/// answered or `port` reaches end of file.
+///
+/// Frames damaged on the line are dropped without a reply, as a Modbus
+/// device would, so the sender's retry logic applies. A frame that arrives
+/// intact but holds an unknown command is answered with a
+/// `ScaleResponse::Error` of kind `InvalidCommand`. Read timeouts are
+/// ignored.
+pub fn serve_frames<S, P>(scale: &mut S, port: &mut P) -> io::Result<()>
+where
+    S: Scale,
+    P: Read + Write,
+{
+    let mut decoder = FrameDecoder::<ScaleCmd>::with_max_payload(MAX_COMMAND_LEN);
+    let mut buffer = [0; 256];
+    loop {
+        let read = match port.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(read) => read,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::TimedOut
+                        | io::ErrorKind::WouldBlock
+                        | io::ErrorKind::Interrupted
+                ) =>
+            {
+                continue
+            }
+            Err(e) => return Err(e),
+        };
+        decoder.extend(&buffer[..read]);
+        while let Some(frame) = decoder.next_frame() {
+            let response = match frame {
+                Ok(ScaleCmd::Shutdown) => {
+                    port.write_all(&encode_response_frame(&ScaleResponse::ShutdownAck)?)?;
+                    return port.flush();
+                }
+                Ok(cmd) => execute(scale, cmd, &CancelFlag::new(), &mut |_| {}),
+                Err(FrameError::Payload(e)) => ScaleResponse::Error(ScaleErrorInfo::new(
+                    ScaleErrorKind::InvalidCommand,
+                    format!("Invalid command: {e}"),
+                )),
+                Err(_) => continue,
+            };
+            let frame = encode_response_frame(&response)
+                .or_else(|e| encode_response_frame(&ScaleResponse::InternalError(e.to_string())))?;
+            port.write_all(&frame)?;
+            port.flush()?;
+        }
+    }
+}
+
+impl From<FrameError> for io::Error {
+    fn from(error: FrameError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+/// How to open the serial port for [`serve_serial`]. Frames are binary, so
+/// the port always uses eight data bits.
+#[cfg(feature = "serial")]
+#[derive(Clone, Debug)]
+pub struct PortSettings {
+    /// Such as `/dev/ttyUSB0` or `COM3`.
+    pub path: String,
+    pub baud_rate: u32,
+    pub parity: serialport::Parity,
+    pub stop_bits: serialport::StopBits,
+    /// How long a read waits for bytes before trying again.
+    pub timeout: Duration,
+}
+
+#[cfg(feature = "serial")]
+impl PortSettings {
+    /// `path` at `baud_rate`, no parity and one stop bit.
+    pub fn new(path: impl Into<String>, baud_rate: u32) -> Self {
+        Self {
+            path: path.into(),
+            baud_rate,
+            parity: serialport::Parity::None,
+            stop_bits: serialport::StopBits::One,
+            timeout: Duration::from_millis(100),
+        }
+    }
+}
+
+/// Opens the serial port described by `settings` and runs [`serve_frames`]
+/// on it.
+#[cfg(feature = "serial")]
+pub fn serve_serial<S: Scale>(scale: &mut S, settings: &PortSettings) -> io::Result<()> {
+    let mut port = serialport::new(&settings.path, settings.baud_rate)
+        .data_bits(serialport::DataBits::Eight)
+        .parity(settings.parity)
+        .stop_bits(settings.stop_bits)
+        .timeout(settings.timeout)
+        .open()?;
+    serve_frames(scale, &mut port)
+}
@@ -0,0 +1,330 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, TryLockError};
+use std::thread::{self, ThreadId};
+use std::time::Duration;
+
+use crate::calibration::Calibration;
+use crate::cancel::CancelFlag;
+use crate::clock::Clock;
+use crate::scale::{ScaleError, NUMBER_OF_INPUTS};
+use crate::watchdog::StaleChannel;
+#[cfg(feature = "tokio")]
+use crate::{
+    sampling::{check_samples, finite, sample_failed, MedianCollector},
+    AsyncScale, AsyncScaleError,
+};
+use crate::{Grams, MedianGrams, Scale, ScaleStatus};
+#[cfg(feature = "tokio")]
+use tokio_util::sync::CancellationToken;
+
+/// A cloneable handle that lets several threads use one scale.
+///
+/// `ConnectedScale` is `Send` but not `Sync`: the phidget channel handles may
+/// be moved to another thread, but the library gives no guarantee about using
+/// the same handle from two threads at once. `SharedScale` serializes access
+/// behind a mutex so the handle is only ever touched by one thread at a time.
+///
+/// A panic while the lock is held poisons the mutex. The scale itself holds no
+/// invariants a panic could break halfway, so every method here recovers the
+/// guard and carries on instead of propagating the panic.
+///
+/// A yielding median holds the lock for its whole run. Reads made from its
+/// `between_samples` callback, on the thread holding the lock, go straight to
+/// the scale instead of waiting on that lock.
+pub struct SharedScale<S> {
+    inner: Arc<Shared<S>>,
+}
+
+struct Shared<S> {
+    scale: Mutex<S>,
+    yielding: Mutex<Option<Yielding<S>>>,
+}
+
+/// The thread running a yielding median, and the scale it has locked.
+struct Yielding<S> {
+    thread: ThreadId,
+    scale: *const S,
+}
+
+// SAFETY: the pointer is only dereferenced on `thread`, which created it.
+unsafe impl<S: Send> Send for Yielding<S> {}
+
+/// Records a yielding median for as long as it runs.
+struct YieldingGuard<'a, S> {
+    yielding: &'a Mutex<Option<Yielding<S>>>,
+}
+
+impl<'a, S> YieldingGuard<'a, S> {
+    fn new(yielding: &'a Mutex<Option<Yielding<S>>>, scale: &S) -> Self {
+        *yielding.lock().unwrap_or_else(PoisonError::into_inner) = Some(Yielding {
+            thread: thread::current().id(),
+            scale,
+        });
+        Self { yielding }
+    }
+}
+
+impl<S> Drop for YieldingGuard<'_, S> {
+    fn drop(&mut self) {
+        *self.yielding.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+impl<S> SharedScale<S> {
+    pub fn new(scale: S) -> Self {
+        Self {
+            inner: Arc::new(Shared {
+                scale: Mutex::new(scale),
+                yielding: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Locks the scale for exclusive use, blocking until it is available.
+    pub fn lock(&self) -> MutexGuard<'_, S> {
+        self.inner
+            .scale
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the scale if no other thread currently holds it.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, S>> {
+        match self.inner.scale.try_lock() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    /// Returns the scale if this is the last handle to it.
+    pub fn into_inner(self) -> Result<S, Self> {
+        match Arc::try_unwrap(self.inner) {
+            Ok(shared) => Ok(shared
+                .scale
+                .into_inner()
+                .unwrap_or_else(PoisonError::into_inner)),
+            Err(inner) => Err(Self { inner }),
+        }
+    }
+
+    /// The scale locked by a yielding median running on this thread.
+    fn yielding(&self) -> Option<*const S> {
+        let yielding = self
+            .inner
+            .yielding
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        yielding
+            .as_ref()
+            .filter(|yielding| yielding.thread == thread::current().id())
+            .map(|yielding| yielding.scale)
+    }
+
+    /// Runs `read` on the scale, locking it unless this thread already holds
+    /// it for a yielding median.
+    fn read<T>(&self, read: impl FnOnce(&S) -> T) -> T {
+        match self.yielding() {
+            // SAFETY: the median that recorded this pointer is further up
+            // this thread's stack, holding the lock and a shared borrow of
+            // the scale until it clears the record.
+            Some(scale) => read(unsafe { &*scale }),
+            None => read(&self.lock()),
+        }
+    }
+}
+
+impl<S: Scale> SharedScale<S> {
+    /// Reads the weight without waiting on the lock, failing with
+    /// `ScaleError::Busy` if another thread is using the scale.
+    pub fn try_get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        match self.try_lock() {
+            Some(scale) => scale.get_weight(),
+            None => Err(Box::new(ScaleError::Busy)),
+        }
+    }
+}
+
+impl<S> Clone for SharedScale<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<S: Scale> Scale for SharedScale<S> {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.read(|scale| scale.get_weight())
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.read(|scale| scale.get_median_weight())
+    }
+
+    fn get_median_weight_of(
+        &self,
+        samples: usize,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.read(|scale| scale.get_median_weight_of(samples))
+    }
+
+    fn get_median_weight_cancellable(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.read(|scale| scale.get_median_weight_cancellable(samples, cancel))
+    }
+
+    fn get_median_weight_yielding(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+        between_samples: &mut dyn FnMut(),
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        if self.yielding().is_some() {
+            return self
+                .read(|scale| scale.get_median_weight_yielding(samples, cancel, between_samples));
+        }
+        let scale = self.lock();
+        let _yielding = YieldingGuard::new(&self.inner.yielding, &*scale);
+        scale.get_median_weight_yielding(samples, cancel, between_samples)
+    }
+
+    fn get_weight_observed(
+        &self,
+        observe: &mut dyn FnMut(&[f64; NUMBER_OF_INPUTS]) -> Result<(), ScaleError>,
+    ) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.read(|scale| scale.get_weight_observed(observe))
+    }
+
+    fn tare(&mut self, samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.lock().tare(samples)
+    }
+
+    fn zero(
+        &mut self,
+        samples: usize,
+    ) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        self.lock().zero(samples)
+    }
+
+    fn get_raw_readings(
+        &self,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        self.read(|scale| scale.get_raw_readings())
+    }
+
+    fn get_raw_medians(
+        &self,
+        samples: usize,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        self.read(|scale| scale.get_raw_medians(samples))
+    }
+
+    fn calibration(&self) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        self.read(|scale| scale.calibration())
+    }
+
+    fn set_calibration(
+        &mut self,
+        calibration: Calibration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.lock().set_calibration(calibration)
+    }
+
+    fn status(&mut self) -> Result<ScaleStatus, Box<dyn std::error::Error + Send + Sync>> {
+        self.lock().status()
+    }
+
+    fn data_interval(&mut self) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+        self.lock().data_interval()
+    }
+
+    fn set_data_interval(
+        &mut self,
+        interval: Duration,
+    ) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+        self.lock().set_data_interval(interval)
+    }
+
+    fn stale_channel(&self) -> Option<StaleChannel> {
+        self.read(|scale| scale.stale_channel())
+    }
+
+    fn clock(&self) -> Arc<dyn Clock> {
+        self.read(|scale| scale.clock())
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.lock().close()
+    }
+}
+
+/// Each read runs on tokio's blocking pool and the waits between samples are
+/// `tokio::time::sleep`s, so a median read never occupies a runtime worker.
+/// Dropping one of these futures abandons it at the next await point; at most
+/// the single read already handed to the blocking pool runs to completion.
+#[cfg(feature = "tokio")]
+impl<S: Scale + Send + 'static> AsyncScale for SharedScale<S> {
+    async fn get_weight(&self) -> Result<Grams, AsyncScaleError> {
+        self.read_blocking().await
+    }
+
+    async fn get_median_weight(&self, samples: usize) -> Result<MedianGrams, AsyncScaleError> {
+        self.get_median_weight_with_token(samples, &CancellationToken::new())
+            .await
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<S: Scale + Send + 'static> SharedScale<S> {
+    /// Async median read that returns `ScaleError::Cancelled` as soon as
+    /// `token` is cancelled, whether it is waiting between samples or for a
+    /// read to come back.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "get_median_weight",
+            target = "libra",
+            level = "debug",
+            skip_all,
+            fields(
+                samples,
+                interval_ms = crate::scale::DEFAULT_SAMPLE_INTERVAL.as_millis() as u64
+            )
+        )
+    )]
+    pub async fn get_median_weight_with_token(
+        &self,
+        samples: usize,
+        token: &CancellationToken,
+    ) -> Result<MedianGrams, AsyncScaleError> {
+        check_samples(samples)?;
+        let mut collector = MedianCollector::new(samples, crate::scale::DEFAULT_SAMPLE_INTERVAL);
+        while let Some(delay) = collector.next_delay() {
+            let weight = tokio::select! {
+                _ = token.cancelled() => None,
+                weight = async {
+                    tokio::time::sleep(delay).await;
+                    self.read_blocking().await
+                } => Some(weight.inspect_err(|e| sample_failed(collector.collected(), e))?),
+            };
+            match weight {
+                Some(weight) => collector.push(weight),
+                None => {
+                    return Err(Box::new(ScaleError::Cancelled {
+                        collected: collector.collected(),
+                    }))
+                }
+            }
+        }
+        Ok(collector.finish())
+    }
+
+    async fn read_blocking(&self) -> Result<Grams, AsyncScaleError> {
+        let shared = self.clone();
+        let weight = tokio::task::spawn_blocking(move || Scale::get_weight(&shared)).await??;
+        Ok(finite(weight)?)
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::clock::Clock;
+use crate::scale::NUMBER_OF_INPUTS;
+
+/// Each load cell's latest ratio and when it came, once it has reported one.
+type Latest = [Option<(f64, Instant)>; NUMBER_OF_INPUTS];
+
+/// The latest voltage ratio of each load cell, as its change events report
+/// it, and when it came by the clock the snapshot was made with.
+pub(crate) struct RatioSnapshot {
+    ratios: Arc<Mutex<Latest>>,
+    clock: Arc<dyn Clock>,
+    max_age: Duration,
+}
+
+impl RatioSnapshot {
+    pub(crate) fn new(clock: Arc<dyn Clock>, max_age: Duration) -> Self {
+        Self {
+            ratios: Arc::default(),
+            clock,
+            max_age,
+        }
+    }
+
+    /// What a change event of `channel` calls with each new ratio.
+    pub(crate) fn recorder(&self, channel: usize) -> Box<dyn Fn(f64) + Send + Sync> {
+        let ratios = Arc::clone(&self.ratios);
+        let clock = Arc::clone(&self.clock);
+        Box::new(move |ratio| {
+            let now = clock.now();
+            lock(&ratios)[channel] = Some((ratio, now));
+        })
+    }
+
+    /// Every channel's ratio, if each has reported a finite one no more than
+    /// the snapshot's age ago.
+    pub(crate) fn fresh(&self) -> Option<[f64; NUMBER_OF_INPUTS]> {
+        let now = self.clock.now();
+        let ratios = lock(&self.ratios);
+        let mut fresh = [0.; NUMBER_OF_INPUTS];
+        for (fresh, latest) in fresh.iter_mut().zip(ratios.iter()) {
+            let (ratio, at) = (*latest)?;
+            if !ratio.is_finite() || now.saturating_duration_since(at) > self.max_age {
+                return None;
+            }
+            *fresh = ratio;
+        }
+        Some(fresh)
+    }
+
+    pub(crate) fn max_age(&self) -> Duration {
+        self.max_age
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// How a scale's weights were read: from the ratios its change events
+/// reported, or by asking each load cell. Clones share the same counters.
+#[derive(Clone, Debug, Default)]
+pub struct ReadCounters {
+    counts: Arc<Counts>,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    cached: AtomicU64,
+    direct: AtomicU64,
+}
+
+impl ReadCounters {
+    /// Readings taken from the ratios change events reported.
+    pub fn cached(&self) -> u64 {
+        self.counts.cached.load(Ordering::Relaxed)
+    }
+
+    /// Readings taken by asking each load cell, because change events were
+    /// not cached or the ratios they reported were too old.
+    pub fn direct(&self) -> u64 {
+        self.counts.direct.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn count_cached(&self) {
+        self.counts.cached.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn count_direct(&self) {
+        self.counts.direct.fetch_add(1, Ordering::Relaxed);
+    }
+}
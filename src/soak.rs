@@ -0,0 +1,264 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::clock::{Clock, SystemClock};
+use crate::sampling::finite;
+use crate::scale::{check_interval, ScaleError, DEFAULT_MAX_DURATION, DEFAULT_SAMPLE_INTERVAL};
+use crate::stability::{StabilityDetector, DEFAULT_STABLE_TOLERANCE, DEFAULT_STABLE_WINDOW};
+use crate::{median, Grams, Scale, ScaleErrorInfo, ScaleErrorKind};
+
+/// How long a [`SoakConfig::default`] runs for: overnight.
+pub const DEFAULT_SOAK_DURATION: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// How long a [`soak`] runs and how it reads the scale.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SoakConfig {
+    pub duration: Duration,
+    /// Time from the start of one read to the start of the next.
+    pub sample_interval: Duration,
+    /// Readings the stability detector looks back over, and the spread in
+    /// grams within which it calls them stable. The drift is taken between
+    /// the medians of the first and last `stable_window` readings.
+    pub stable_window: usize,
+    pub stable_tolerance: f64,
+}
+
+impl Default for SoakConfig {
+    fn default() -> Self {
+        Self {
+            duration: DEFAULT_SOAK_DURATION,
+            sample_interval: DEFAULT_SAMPLE_INTERVAL,
+            stable_window: DEFAULT_STABLE_WINDOW,
+            stable_tolerance: DEFAULT_STABLE_TOLERANCE,
+        }
+    }
+}
+
+/// Percentiles of the noise on the readings of a soak, in grams.
+///
+/// The noise of a reading is estimated from its difference with the one
+/// before, divided by the square root of 2: the standard deviation of white
+/// noise, unaffected by drift much slower than the sample interval.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct NoisePercentiles {
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+/// How many reads of a soak failed with errors of one kind.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct ErrorCount {
+    pub kind: ScaleErrorKind,
+    pub count: u64,
+}
+
+/// What a [`soak`] saw of a scale over its run.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SoakReport {
+    /// Time from the first read to the end of the last.
+    pub duration: Duration,
+    /// Reads taken, and how many of them failed.
+    pub reads: u64,
+    pub failed_reads: u64,
+    /// The share of the successful readings at which the stability detector
+    /// called the scale stable.
+    pub stable_fraction: f64,
+    /// How far, in grams, the median of the last readings is from that of
+    /// the first. `None` until there are two windows of readings.
+    pub drift: Option<f64>,
+    /// `None` until two readings in a row have succeeded.
+    pub noise: Option<NoisePercentiles>,
+    /// Failed reads by kind of error, in the order each kind first appeared.
+    pub errors: Vec<ErrorCount>,
+    /// Times a read succeeded after reads had failed because the scale was
+    /// disconnected.
+    pub reconnects: u64,
+    /// Times the scale's watchdog caught a load cell frozen, counting each
+    /// episode once.
+    pub watchdog_trips: u64,
+    /// The latest a read started after it was due, because the reads before
+    /// it took longer than the sample interval.
+    pub worst_overrun: Duration,
+    pub last_error: Option<ScaleErrorInfo>,
+}
+
+impl fmt::Display for SoakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Duration: {:?}", self.duration)?;
+        writeln!(
+            f,
+            "Reads: {} of {} succeeded",
+            self.reads - self.failed_reads,
+            self.reads
+        )?;
+        writeln!(f, "Stable: {:.1}% of readings", self.stable_fraction * 100.)?;
+        match self.drift {
+            Some(drift) => writeln!(f, "Drift: {drift:+.3} g")?,
+            None => writeln!(f, "Drift: unknown")?,
+        }
+        match self.noise {
+            Some(noise) => writeln!(
+                f,
+                "Noise: p50 {:.3} g, p90 {:.3} g, p99 {:.3} g, max {:.3} g",
+                noise.p50, noise.p90, noise.p99, noise.max
+            )?,
+            None => writeln!(f, "Noise: unknown")?,
+        }
+        if self.errors.is_empty() {
+            writeln!(f, "Errors: none")?;
+        } else {
+            writeln!(f, "Errors:")?;
+            for ErrorCount { kind, count } in &self.errors {
+                writeln!(f, "  - {kind:?}: {count}")?;
+            }
+        }
+        writeln!(f, "Reconnects: {}", self.reconnects)?;
+        writeln!(f, "Watchdog trips: {}", self.watchdog_trips)?;
+        writeln!(f, "Worst overrun: {:?}", self.worst_overrun)?;
+        match &self.last_error {
+            Some(error) => write!(f, "Last error: {error}"),
+            None => write!(f, "Last error: none"),
+        }
+    }
+}
+
+/// Reads `scale` every `config.sample_interval` for `config.duration`, and
+/// reports on how it held up: for a run against real hardware before a
+/// release, or against a [`SimulatedScale`](crate::testing::SimulatedScale)
+/// playing a scenario.
+///
+/// Every reading feeds a stability detector, and the scale's own watchdog,
+/// if it has one, is checked after every read. A failed read does not end
+/// the run. Blocks the thread for the whole run. Fails only for a sample
+/// interval that is zero or over [`DEFAULT_MAX_DURATION`].
+pub fn soak<S: Scale + ?Sized>(scale: &S, config: &SoakConfig) -> Result<SoakReport, ScaleError> {
+    soak_on(&SystemClock, scale, config)
+}
+
+/// Like [`soak`], keeping time and waiting by `clock`.
+pub fn soak_on<S: Scale + ?Sized>(
+    clock: &dyn Clock,
+    scale: &S,
+    config: &SoakConfig,
+) -> Result<SoakReport, ScaleError> {
+    if config.sample_interval.is_zero() {
+        return Err(ScaleError::InvalidArgument(
+            "sample interval must not be zero".into(),
+        ));
+    }
+    check_interval(config.sample_interval, DEFAULT_MAX_DURATION)?;
+    let window = config.stable_window.max(1);
+    let mut detector = StabilityDetector::new(window, config.stable_tolerance);
+    let mut first: Vec<Grams> = Vec::with_capacity(window);
+    let mut last: VecDeque<Grams> = VecDeque::with_capacity(window);
+    let mut noise = Vec::new();
+    let mut previous: Option<Grams> = None;
+    let mut stable = 0;
+    let mut disconnected = false;
+    let mut frozen = false;
+    let mut report = SoakReport {
+        duration: Duration::ZERO,
+        reads: 0,
+        failed_reads: 0,
+        stable_fraction: 0.,
+        drift: None,
+        noise: None,
+        errors: Vec::new(),
+        reconnects: 0,
+        watchdog_trips: 0,
+        worst_overrun: Duration::ZERO,
+        last_error: None,
+    };
+
+    let started = clock.now();
+    let mut due = started;
+    while due.duration_since(started) < config.duration {
+        let now = clock.now();
+        if now < due {
+            clock.sleep(due - now);
+        }
+        let start = clock.now();
+        report.worst_overrun = report.worst_overrun.max(start.duration_since(due));
+        due = start + config.sample_interval;
+
+        report.reads += 1;
+        match scale.get_weight().and_then(|weight| Ok(finite(weight)?)) {
+            Ok(weight) => {
+                if std::mem::take(&mut disconnected) {
+                    report.reconnects += 1;
+                }
+                if let Some(previous) = previous {
+                    noise.push((weight.0 - previous.0).abs() / std::f64::consts::SQRT_2);
+                }
+                previous = Some(weight);
+                if detector.push(weight.0) {
+                    stable += 1;
+                }
+                if first.len() < window {
+                    first.push(weight);
+                }
+                if last.len() == window {
+                    last.pop_front();
+                }
+                last.push_back(weight);
+            }
+            Err(error) => {
+                let info = ScaleErrorInfo::from_dyn(&*error);
+                report.failed_reads += 1;
+                match report
+                    .errors
+                    .iter_mut()
+                    .find(|count| count.kind == info.kind)
+                {
+                    Some(count) => count.count += 1,
+                    None => report.errors.push(ErrorCount {
+                        kind: info.kind,
+                        count: 1,
+                    }),
+                }
+                disconnected |= ScaleError::from(info.clone()).is_disconnection();
+                previous = None;
+                report.last_error = Some(info);
+            }
+        }
+        let stale = scale.stale_channel().is_some();
+        if stale && !frozen {
+            report.watchdog_trips += 1;
+        }
+        frozen = stale;
+    }
+    report.duration = clock.now().duration_since(started);
+
+    let succeeded = report.reads - report.failed_reads;
+    if succeeded > 0 {
+        report.stable_fraction = stable as f64 / succeeded as f64;
+    }
+    if succeeded >= 2 * window as u64 {
+        let mut last = Vec::from(last);
+        report.drift = Some(median(&mut last).get() - median(&mut first).get());
+    }
+    if !noise.is_empty() {
+        let rank = |p: f64| ((noise.len() - 1) as f64 * p).round() as usize;
+        let ranks = [noise.len() - 1, rank(0.99), rank(0.9), rank(0.5)];
+        let [max, p99, p90, p50] = ranked(&mut noise, ranks);
+        report.noise = Some(NoisePercentiles { p50, p90, p99, max });
+    }
+    Ok(report)
+}
+
+/// The values that would be at each of `ranks`, highest first, were `values`
+/// sorted. Each is found among those up to the last, so only as many are
+/// moved as it takes to find them.
+fn ranked<const N: usize>(values: &mut [f64], ranks: [usize; N]) -> [f64; N] {
+    let mut end = values.len();
+    ranks.map(|rank| {
+        let value = *values[..end].select_nth_unstable_by(rank, f64::total_cmp).1;
+        end = rank + 1;
+        value
+    })
+}
@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+use phidget::devices::VoltageRatioInput;
+use phidget::{ffi, Phidget, ReturnCode};
+
+use crate::config::BridgeGain;
+
+/// One load cell channel, as a [`ConnectedScale`](crate::scale::ConnectedScale)
+/// reads it: a phidget `VoltageRatioInput` when connected to hardware, or a
+/// stand-in such as [`FakeVoltageSource`](crate::testing::FakeVoltageSource).
+///
+/// Failures are phidget return codes, so that a stand-in fails the way the
+/// hardware would.
+pub trait VoltageSource {
+    /// The latest voltage ratio of the load cell.
+    fn ratio(&self) -> phidget::Result<f64>;
+
+    fn set_data_interval(&mut self, interval: Duration) -> phidget::Result<()>;
+
+    fn data_interval(&mut self) -> phidget::Result<Duration>;
+
+    /// The shortest data interval the channel takes. A source that cannot
+    /// say, as by default, fails with `ReturnCode::Unsupported`.
+    fn min_data_interval(&mut self) -> phidget::Result<Duration> {
+        Err(ReturnCode::Unsupported)
+    }
+
+    /// The longest data interval the channel takes, failing as
+    /// [`min_data_interval`](Self::min_data_interval) does.
+    fn max_data_interval(&mut self) -> phidget::Result<Duration> {
+        Err(ReturnCode::Unsupported)
+    }
+
+    /// Sets the gain of the channel. A source without one, as by default,
+    /// fails with `ReturnCode::Unsupported`.
+    fn set_bridge_gain(&mut self, gain: BridgeGain) -> phidget::Result<()> {
+        let _ = gain;
+        Err(ReturnCode::Unsupported)
+    }
+
+    /// The gain of the channel, failing as
+    /// [`set_bridge_gain`](Self::set_bridge_gain) does.
+    fn bridge_gain(&mut self) -> phidget::Result<BridgeGain> {
+        Err(ReturnCode::Unsupported)
+    }
+
+    /// Serial number of the bridge the channel is on.
+    fn serial_number(&mut self) -> phidget::Result<i32>;
+
+    fn is_attached(&mut self) -> phidget::Result<bool>;
+
+    fn close(&mut self) -> phidget::Result<()>;
+
+    /// Calls `callback` with every new voltage ratio, replacing any callback
+    /// set before.
+    fn on_ratio_change(&mut self, callback: Box<dyn Fn(f64) + Send + Sync>) -> phidget::Result<()>;
+}
+
+impl VoltageSource for VoltageRatioInput {
+    fn ratio(&self) -> phidget::Result<f64> {
+        self.voltage_ratio()
+    }
+
+    fn set_data_interval(&mut self, interval: Duration) -> phidget::Result<()> {
+        Phidget::set_data_interval(self, interval)
+    }
+
+    fn data_interval(&mut self) -> phidget::Result<Duration> {
+        Phidget::data_interval(self)
+    }
+
+    fn min_data_interval(&mut self) -> phidget::Result<Duration> {
+        Phidget::min_data_interval(self)
+    }
+
+    fn max_data_interval(&mut self) -> phidget::Result<Duration> {
+        Phidget::max_data_interval(self)
+    }
+
+    // The phidget crate has no methods for the gain, so these call the
+    // library itself.
+    fn set_bridge_gain(&mut self, gain: BridgeGain) -> phidget::Result<()> {
+        // The library numbers the gains from 1, in the order of the enum.
+        let code = gain as u32 + 1;
+        ReturnCode::result(unsafe {
+            ffi::PhidgetVoltageRatioInput_setBridgeGain(*self.as_channel(), code)
+        })
+    }
+
+    fn bridge_gain(&mut self) -> phidget::Result<BridgeGain> {
+        let mut code = 0;
+        ReturnCode::result(unsafe {
+            ffi::PhidgetVoltageRatioInput_getBridgeGain(*self.as_channel(), &mut code)
+        })?;
+        code.checked_sub(1)
+            .and_then(|index| BridgeGain::ALL.get(index as usize).copied())
+            .ok_or(ReturnCode::Unexpected)
+    }
+
+    fn serial_number(&mut self) -> phidget::Result<i32> {
+        Phidget::serial_number(self)
+    }
+
+    fn is_attached(&mut self) -> phidget::Result<bool> {
+        Phidget::is_attached(self)
+    }
+
+    fn close(&mut self) -> phidget::Result<()> {
+        Phidget::close(self)
+    }
+
+    fn on_ratio_change(&mut self, callback: Box<dyn Fn(f64) + Send + Sync>) -> phidget::Result<()> {
+        self.set_on_voltage_ratio_change_handler(move |_, ratio| callback(ratio))
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::VecDeque;
+
+/// Readings a [`StabilityDetector::default`] looks back over.
+pub const DEFAULT_STABLE_WINDOW: usize = 5;
+/// Spread, in grams, within which a [`StabilityDetector::default`] calls its
+/// window stable.
+pub const DEFAULT_STABLE_TOLERANCE: f64 = 1.;
+
+/// Tells when a stream of readings has stopped moving: once the last
+/// `window` readings lie within `tolerance` of each other.
+#[derive(Clone, Debug)]
+pub struct StabilityDetector {
+    window: usize,
+    tolerance: f64,
+    recent: VecDeque<f64>,
+}
+
+impl StabilityDetector {
+    pub fn new(window: usize, tolerance: f64) -> Self {
+        let window = window.max(1);
+        Self {
+            window,
+            tolerance,
+            recent: VecDeque::with_capacity(window),
+        }
+    }
+
+    /// Adds a reading, dropping the oldest once the window is full, and
+    /// returns whether the readings are now stable.
+    pub fn push(&mut self, reading: f64) -> bool {
+        if self.recent.len() == self.window {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(reading);
+        self.is_stable()
+    }
+
+    /// Whether the window is full and its spread is within the tolerance.
+    pub fn is_stable(&self) -> bool {
+        self.recent.len() == self.window && self.spread() <= self.tolerance
+    }
+
+    /// Difference between the highest and lowest readings in the window.
+    pub fn spread(&self) -> f64 {
+        let (low, high) = self.recent.iter().fold(
+            (f64::INFINITY, f64::NEG_INFINITY),
+            |(low, high), &reading| (low.min(reading), high.max(reading)),
+        );
+        (high - low).max(0.)
+    }
+
+    /// Forgets every reading, so the detector starts over as unstable.
+    pub fn reset(&mut self) {
+        self.recent.clear();
+    }
+}
+
+impl Default for StabilityDetector {
+    fn default() -> Self {
+        Self::new(DEFAULT_STABLE_WINDOW, DEFAULT_STABLE_TOLERANCE)
+    }
+}
@@ -0,0 +1,769 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::calibration::{crc32, Calibration, CalibrationRecord};
+#[cfg(feature = "hardware")]
+use crate::scale::ConnectedScale;
+#[cfg(feature = "hardware")]
+use crate::source::VoltageSource;
+
+/// How many earlier calibrations a [`CalibrationStore::new`] keeps.
+pub const DEFAULT_BACKUPS: usize = 5;
+
+#[derive(Error, Debug)]
+pub enum CalibrationError {
+    #[error("Cannot {action} {}: {source}", .path.display())]
+    Io {
+        action: &'static str,
+        path: PathBuf,
+        source: io::Error,
+    },
+    #[error("{} is not a calibration: {message}", .path.display())]
+    Malformed { path: PathBuf, message: String },
+    #[error("There is no earlier calibration of {} to roll back to", .path.display())]
+    NoBackup { path: PathBuf },
+    /// The calibration in the file is not the one saved: the checksum saved
+    /// with it, `expected`, is not that of what the file now holds,
+    /// `found`. [`CalibrationStore::load_unchecked`] still reads it.
+    #[error(
+        "{} has been changed since it was saved: its checksum is {found}, not {expected}",
+        .path.display()
+    )]
+    IntegrityFailure {
+        path: PathBuf,
+        expected: String,
+        found: String,
+    },
+    /// The calibration was saved through the bridge `stored`, not the
+    /// `live` one connected, and the store rejects it until the new bridge
+    /// is adopted with [`CalibrationStore::adopt_new_bridge`].
+    #[error(
+        "{} was saved through bridge {stored} of platform {platform_id:?}, not bridge {live}; \
+         adopt the new bridge to keep the calibration",
+        .path.display()
+    )]
+    BridgeMismatch {
+        path: PathBuf,
+        platform_id: String,
+        stored: i32,
+        live: i32,
+    },
+    /// The calibration is of the platform `stored`, not `expected`. Its load
+    /// cells are not the ones calibrated, so the platform needs a
+    /// calibration of its own.
+    #[error(
+        "{} is the calibration of platform {stored:?}, not {expected:?}; calibrate the new platform",
+        .path.display()
+    )]
+    PlatformMismatch {
+        path: PathBuf,
+        stored: String,
+        expected: String,
+    },
+    #[error("{} was saved without a scale identity", .path.display())]
+    NoIdentity { path: PathBuf },
+}
+
+#[derive(Error, Debug)]
+pub enum AssignmentError {
+    #[error("Cannot {action} {}: {source}", .path.display())]
+    Io {
+        action: &'static str,
+        path: PathBuf,
+        source: io::Error,
+    },
+    #[error("{} is not a list of scale assignments: {message}", .path.display())]
+    Malformed { path: PathBuf, message: String },
+}
+
+/// What a [`CalibrationStore`] does with a calibration saved through
+/// another bridge than the one connected, until the new bridge is adopted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BridgeMismatchAction {
+    /// The calibration is loaded, and a `bridge_mismatch` event is emitted.
+    #[default]
+    Warn,
+    /// Loading fails with [`CalibrationError::BridgeMismatch`].
+    Reject,
+}
+
+/// How the identity saved with a calibration compares with the scale it is
+/// loaded into; see [`CalibrationStore::check_identity`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IdentityCheck {
+    /// The calibration was saved through the connected bridge.
+    Matched,
+    /// The calibration was saved without an identity, so there is nothing
+    /// to compare.
+    Unrecorded,
+    /// The calibration was saved through the bridge `stored`, not the
+    /// `live` one connected, and the store only warns of it.
+    BridgeSwapped { stored: i32, live: i32 },
+}
+
+/// How far a calibration read by [`CalibrationStore::load_checked`] could be
+/// trusted to be the one saved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Integrity {
+    /// Its checksum matched.
+    Verified,
+    /// The file has no checksum, as saved by a version from before they
+    /// were written, so it could not be checked.
+    Unchecked,
+    /// The file is a bare `[offset, c0, c1, c2, c3]` array, as written by
+    /// the tooling from before this crate, and was converted; see
+    /// [`Calibration::from_legacy`].
+    Legacy,
+}
+
+/// A calibration read from a [`CalibrationStore`], and whether it could be
+/// checked.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadedCalibration {
+    pub record: CalibrationRecord,
+    pub integrity: Integrity,
+}
+
+/// A calibration file: the record, and the checksum of its canonical form.
+#[derive(Serialize, Deserialize)]
+struct StoredRecord {
+    #[serde(flatten)]
+    record: CalibrationRecord,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    checksum: Option<String>,
+}
+
+/// An earlier calibration kept by a [`CalibrationStore`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CalibrationBackup {
+    pub path: PathBuf,
+    /// When it was replaced, to the millisecond.
+    pub saved_at: SystemTime,
+}
+
+/// A calibration file that is never left half written, and the calibrations
+/// it held before.
+///
+/// [`save`](Self::save) writes the new calibration to a temporary file next
+/// to the real one, syncs it to disk and renames it over the real one, so
+/// that a crash or power cut at any point leaves either the old calibration
+/// or the new one. The calibration replaced is kept beside it as
+/// `<file>.<milliseconds since the epoch>.bak`, the newest
+/// [`DEFAULT_BACKUPS`] of them by default, for [`rollback`](Self::rollback).
+///
+/// The file is JSON, as written by `serde_json`, with a `checksum` of the
+/// calibration: the CRC-32 of its compact JSON, as `crc32:<8 hex digits>`.
+/// A calibration that no longer matches its checksum, from a half-written
+/// or hand-edited file, fails to load; fields a calibration does not have
+/// are ignored, and not checked. One process at a time should save to a
+/// store.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CalibrationStore {
+    path: PathBuf,
+    backups: usize,
+    bridge_mismatch: BridgeMismatchAction,
+}
+
+/// A calibration written and synced to the temporary file of a
+/// [`CalibrationStore`], waiting to be renamed into place by
+/// [`commit`](Self::commit). Dropped instead, the temporary file is removed
+/// and the store is as it was.
+#[must_use = "the calibration is not saved until committed"]
+#[derive(Debug)]
+pub struct PreparedSave<'a> {
+    store: &'a CalibrationStore,
+    temporary: PathBuf,
+    committed: bool,
+}
+
+impl CalibrationStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            backups: DEFAULT_BACKUPS,
+            bridge_mismatch: BridgeMismatchAction::default(),
+        }
+    }
+
+    /// Keeps the newest `backups` earlier calibrations. Zero keeps none.
+    pub fn with_backups(self, backups: usize) -> Self {
+        Self { backups, ..self }
+    }
+
+    /// What [`check_identity`](Self::check_identity) does with a
+    /// calibration saved through another bridge. Warns by default.
+    pub fn with_bridge_mismatch(self, bridge_mismatch: BridgeMismatchAction) -> Self {
+        Self {
+            bridge_mismatch,
+            ..self
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the calibration in the store.
+    pub fn load(&self) -> Result<Calibration, CalibrationError> {
+        Ok(self.load_record()?.calibration)
+    }
+
+    /// Reads the calibration in the store with whatever it has of its
+    /// metadata; none, for a file of a bare calibration.
+    pub fn load_record(&self) -> Result<CalibrationRecord, CalibrationError> {
+        Ok(self.load_checked()?.record)
+    }
+
+    /// Like [`load_record`](Self::load_record), saying whether the file had
+    /// a checksum to check it by. Fails with
+    /// [`CalibrationError::IntegrityFailure`] if it did not match.
+    pub fn load_checked(&self) -> Result<LoadedCalibration, CalibrationError> {
+        read(&self.path, true)
+    }
+
+    /// Reads the calibration in the store without checking it against its
+    /// checksum, to recover one that fails the check. It must still be a
+    /// usable calibration.
+    pub fn load_unchecked(&self) -> Result<CalibrationRecord, CalibrationError> {
+        Ok(read(&self.path, false)?.record)
+    }
+
+    /// Replaces the calibration in the store with `calibration`, keeping
+    /// the one it had as a backup.
+    pub fn save(&self, calibration: &Calibration) -> Result<(), CalibrationError> {
+        self.prepare(calibration)?.commit()
+    }
+
+    /// Like [`save`](Self::save), with the metadata of `record`.
+    pub fn save_record(&self, record: &CalibrationRecord) -> Result<(), CalibrationError> {
+        self.prepare_record(record)?.commit()
+    }
+
+    /// The first half of [`save`](Self::save): writes `calibration` to the
+    /// temporary file and syncs it, without touching the calibration in the
+    /// store. A temporary file left by an earlier save that never finished
+    /// is overwritten.
+    pub fn prepare(&self, calibration: &Calibration) -> Result<PreparedSave<'_>, CalibrationError> {
+        self.prepare_record(&CalibrationRecord::from(*calibration))
+    }
+
+    /// Like [`prepare`](Self::prepare), with the metadata of `record`.
+    pub fn prepare_record(
+        &self,
+        record: &CalibrationRecord,
+    ) -> Result<PreparedSave<'_>, CalibrationError> {
+        record
+            .calibration
+            .validate()
+            .map_err(|error| CalibrationError::Malformed {
+                path: self.path.clone(),
+                message: error.to_string(),
+            })?;
+        let temporary = self.sibling("tmp");
+        let encode_error = |error| io_error("write", &temporary, io::Error::other(error));
+        let stored = StoredRecord {
+            record: record.clone(),
+            checksum: Some(checksum(record).map_err(encode_error)?),
+        };
+        let mut text = serde_json::to_string_pretty(&stored).map_err(encode_error)?;
+        text.push('\n');
+        let prepared = PreparedSave {
+            store: self,
+            temporary,
+            committed: false,
+        };
+        File::create(&prepared.temporary)
+            .and_then(|mut file| {
+                file.write_all(text.as_bytes())?;
+                file.sync_all()
+            })
+            .map_err(|source| io_error("write", &prepared.temporary, source))?;
+        Ok(prepared)
+    }
+
+    /// Compares the identity saved with `record` with the scale it is to be
+    /// loaded into: that of the platform `platform_id`, when the scale
+    /// knows it, read through the bridge with serial number
+    /// `bridge_serial`.
+    ///
+    /// Fails with [`CalibrationError::PlatformMismatch`] for the
+    /// calibration of another platform, and, if the store rejects them,
+    /// with [`CalibrationError::BridgeMismatch`] for one saved through
+    /// another bridge.
+    pub fn check_identity(
+        &self,
+        record: &CalibrationRecord,
+        platform_id: Option<&str>,
+        bridge_serial: i32,
+    ) -> Result<IdentityCheck, CalibrationError> {
+        let Some(identity) = &record.identity else {
+            return Ok(IdentityCheck::Unrecorded);
+        };
+        if let Some(expected) = platform_id.filter(|&expected| expected != identity.platform_id) {
+            return Err(CalibrationError::PlatformMismatch {
+                path: self.path.clone(),
+                stored: identity.platform_id.clone(),
+                expected: expected.into(),
+            });
+        }
+        if identity.bridge_serial == bridge_serial {
+            return Ok(IdentityCheck::Matched);
+        }
+        match self.bridge_mismatch {
+            BridgeMismatchAction::Warn => {
+                bridge_mismatch(&identity.platform_id, identity.bridge_serial, bridge_serial);
+                Ok(IdentityCheck::BridgeSwapped {
+                    stored: identity.bridge_serial,
+                    live: bridge_serial,
+                })
+            }
+            BridgeMismatchAction::Reject => Err(CalibrationError::BridgeMismatch {
+                path: self.path.clone(),
+                platform_id: identity.platform_id.clone(),
+                stored: identity.bridge_serial,
+                live: bridge_serial,
+            }),
+        }
+    }
+
+    /// Records that the platform of the calibration in the store is now
+    /// read through the bridge with serial number `serial`, keeping the
+    /// calibration, and notes the swap in the record, which is returned.
+    /// The record it replaces is kept as a backup, as by any save.
+    ///
+    /// Fails with [`CalibrationError::NoIdentity`] for a calibration saved
+    /// without an identity, which has no bridge to replace.
+    pub fn adopt_new_bridge(&self, serial: i32) -> Result<CalibrationRecord, CalibrationError> {
+        let mut record = self.load_record()?;
+        let identity = record
+            .identity
+            .as_mut()
+            .ok_or_else(|| CalibrationError::NoIdentity {
+                path: self.path.clone(),
+            })?;
+        if identity.bridge_serial == serial {
+            return Ok(record);
+        }
+        let note = format!(
+            "Bridge {} replaced by {serial}, calibration kept",
+            identity.bridge_serial
+        );
+        identity.bridge_serial = serial;
+        record.notes = Some(match record.notes.take() {
+            Some(notes) => format!("{notes}; {note}"),
+            None => note,
+        });
+        self.save_record(&record)?;
+        Ok(record)
+    }
+
+    /// Rewrites a legacy `[offset, c0, c1, c2, c3]` file in the store in the
+    /// current format, keeping the original beside it as
+    /// `<file>.legacy.bak`. Returns whether there was one to rewrite; a
+    /// file already in the current format is left as it is.
+    pub fn migrate_legacy(&self) -> Result<bool, CalibrationError> {
+        let loaded = self.load_checked()?;
+        if loaded.integrity != Integrity::Legacy {
+            return Ok(false);
+        }
+        let original = self.sibling("legacy.bak");
+        fs::copy(&self.path, &original)
+            .map_err(|source| io_error("back up", &self.path, source))?;
+        self.save_record(&loaded.record)?;
+        Ok(true)
+    }
+
+    /// The earlier calibrations kept, newest first.
+    pub fn history(&self) -> Result<Vec<CalibrationBackup>, CalibrationError> {
+        let directory = self.directory();
+        let entries = match fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(io_error("list", directory, error)),
+        };
+        let prefix = format!("{}.", self.file_name());
+        let mut history = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|error| io_error("list", directory, error))?;
+            let name = entry.file_name();
+            let Some(millis) = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(&prefix)?.strip_suffix(".bak"))
+                .and_then(|millis| millis.parse().ok())
+            else {
+                continue;
+            };
+            history.push(CalibrationBackup {
+                path: entry.path(),
+                saved_at: UNIX_EPOCH + Duration::from_millis(millis),
+            });
+        }
+        history.sort_by_key(|backup| std::cmp::Reverse(backup.saved_at));
+        Ok(history)
+    }
+
+    /// Puts the newest earlier calibration back in place of the one in the
+    /// store, which is discarded, and returns it. Rolling back again goes
+    /// back one more.
+    ///
+    /// Fails with [`CalibrationError::NoBackup`] if there is none, and with
+    /// [`CalibrationError::Malformed`], leaving the store as it is, if the
+    /// backup cannot be read.
+    pub fn rollback(&self) -> Result<Calibration, CalibrationError> {
+        let backup =
+            self.history()?
+                .into_iter()
+                .next()
+                .ok_or_else(|| CalibrationError::NoBackup {
+                    path: self.path.clone(),
+                })?;
+        let calibration = read(&backup.path, true)?.record.calibration;
+        fs::rename(&backup.path, &self.path)
+            .map_err(|source| io_error("restore", &backup.path, source))?;
+        self.sync_directory()?;
+        Ok(calibration)
+    }
+
+    fn directory(&self) -> &Path {
+        match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        }
+    }
+
+    fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// `<file>.<extension>`, next to the file.
+    fn sibling(&self, extension: &str) -> PathBuf {
+        self.directory()
+            .join(format!("{}.{extension}", self.file_name()))
+    }
+
+    /// Keeps the calibration in the store as the newest backup, under a
+    /// second link to the file so that it stays in place too.
+    fn back_up(&self) -> Result<(), CalibrationError> {
+        if !self.path.exists() || self.backups == 0 {
+            return Ok(());
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        // Saves within a millisecond of each other still keep their order.
+        let newest = self
+            .history()?
+            .first()
+            .map(|backup| {
+                backup
+                    .saved_at
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis()
+            })
+            .unwrap_or_default();
+        let backup = self.sibling(&format!("{}.bak", now.max(newest + 1)));
+        fs::hard_link(&self.path, &backup)
+            .or_else(|_| fs::copy(&self.path, &backup).map(drop))
+            .map_err(|source| io_error("back up", &self.path, source))
+    }
+
+    /// Removes all but the newest backups kept.
+    fn prune(&self) -> Result<(), CalibrationError> {
+        for backup in self.history()?.into_iter().skip(self.backups) {
+            fs::remove_file(&backup.path)
+                .map_err(|source| io_error("remove", &backup.path, source))?;
+        }
+        Ok(())
+    }
+
+    /// Makes the renames in the directory durable, where the platform can.
+    fn sync_directory(&self) -> Result<(), CalibrationError> {
+        #[cfg(unix)]
+        File::open(self.directory())
+            .and_then(|directory| directory.sync_all())
+            .map_err(|source| io_error("sync", self.directory(), source))?;
+        Ok(())
+    }
+}
+
+impl PreparedSave<'_> {
+    /// The second half of [`CalibrationStore::save`]: backs up the
+    /// calibration in the store, renames the temporary file into its place
+    /// and removes the backups no longer kept.
+    pub fn commit(mut self) -> Result<(), CalibrationError> {
+        let store = self.store;
+        store.back_up()?;
+        fs::rename(&self.temporary, &store.path)
+            .map_err(|source| io_error("write", &store.path, source))?;
+        self.committed = true;
+        store.sync_directory()?;
+        store.prune()
+    }
+}
+
+impl Drop for PreparedSave<'_> {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = fs::remove_file(&self.temporary);
+        }
+    }
+}
+
+/// The bridges scales were assigned with `ScaleManager::assign`, kept so
+/// that they are assigned again after a restart.
+///
+/// The file is a JSON object of the serial number of each scale's bridge by
+/// its name, such as `{"flour": 716000}`. [`save`](Self::save) writes it as
+/// [`CalibrationStore::save`] does, to a temporary file synced and renamed
+/// into place, without keeping backups.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssignmentStore {
+    path: PathBuf,
+}
+
+impl AssignmentStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the assignments in the store; none if the file does not exist
+    /// yet.
+    pub fn load(&self) -> Result<BTreeMap<String, i32>, AssignmentError> {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(source) => return Err(assignment_io_error("read", &self.path, source)),
+        };
+        serde_json::from_str(&text).map_err(|error| AssignmentError::Malformed {
+            path: self.path.clone(),
+            message: error.to_string(),
+        })
+    }
+
+    /// Replaces the assignments in the store with `assignments`.
+    pub fn save(&self, assignments: &BTreeMap<String, i32>) -> Result<(), AssignmentError> {
+        let temporary = PathBuf::from(format!("{}.tmp", self.path.display()));
+        let mut text = serde_json::to_string_pretty(assignments)
+            .map_err(|error| assignment_io_error("write", &temporary, io::Error::other(error)))?;
+        text.push('\n');
+        File::create(&temporary)
+            .and_then(|mut file| {
+                file.write_all(text.as_bytes())?;
+                file.sync_all()
+            })
+            .map_err(|source| assignment_io_error("write", &temporary, source))?;
+        fs::rename(&temporary, &self.path).map_err(|source| {
+            let _ = fs::remove_file(&temporary);
+            assignment_io_error("write", &self.path, source)
+        })
+    }
+}
+
+/// Reads the calibration file at `path`, checking it against its checksum,
+/// if it has one, when `check` is set.
+fn read(path: &Path, check: bool) -> Result<LoadedCalibration, CalibrationError> {
+    let text = fs::read_to_string(path).map_err(|source| io_error("read", path, source))?;
+    let malformed = |message: String| CalibrationError::Malformed {
+        path: path.into(),
+        message,
+    };
+    if text.trim_start().starts_with('[') {
+        let record = legacy(&text).map_err(malformed)?;
+        return Ok(LoadedCalibration {
+            record,
+            integrity: Integrity::Legacy,
+        });
+    }
+    let stored: StoredRecord =
+        serde_json::from_str(&text).map_err(|error| malformed(error.to_string()))?;
+    let integrity = match stored.checksum {
+        None => Integrity::Unchecked,
+        Some(_) if !check => Integrity::Unchecked,
+        Some(expected) => {
+            if !expected.starts_with("crc32:") {
+                return Err(malformed(format!("{expected:?} is not a known checksum")));
+            }
+            let found = checksum(&stored.record).map_err(|error| malformed(error.to_string()))?;
+            if found != expected {
+                return Err(CalibrationError::IntegrityFailure {
+                    path: path.into(),
+                    expected,
+                    found,
+                });
+            }
+            Integrity::Verified
+        }
+    };
+    stored
+        .record
+        .calibration
+        .validate()
+        .map_err(|error| malformed(error.to_string()))?;
+    Ok(LoadedCalibration {
+        record: stored.record,
+        integrity,
+    })
+}
+
+/// The note a calibration converted from a legacy file carries.
+const LEGACY_NOTE: &str = "Migrated from a legacy [offset, c0, c1, c2, c3] file";
+
+impl Calibration {
+    /// Reads a calibration file written by the tooling from before this
+    /// crate: a bare JSON array of the offset then the coefficient of each
+    /// load cell, `[offset, c0, c1, c2, c3]`. The record returned notes that
+    /// it was migrated. [`CalibrationStore::load`] reads these files too.
+    ///
+    /// Fails with [`CalibrationError::Malformed`], describing what the file
+    /// holds instead, for anything else, such as an array of four numbers,
+    /// which may be coefficients with no offset or an offset with one
+    /// coefficient missing.
+    pub fn from_legacy(path: impl AsRef<Path>) -> Result<CalibrationRecord, CalibrationError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|source| io_error("read", path, source))?;
+        legacy(&text).map_err(|message| CalibrationError::Malformed {
+            path: path.into(),
+            message,
+        })
+    }
+}
+
+/// The calibration of a legacy file's `text`, or what is wrong with it.
+fn legacy(text: &str) -> Result<CalibrationRecord, String> {
+    let value: serde_json::Value = serde_json::from_str(text).map_err(|error| error.to_string())?;
+    let numbers: Option<Vec<f64>> = value
+        .as_array()
+        .and_then(|items| items.iter().map(serde_json::Value::as_f64).collect());
+    let calibration = match numbers.as_deref() {
+        Some(&[offset, c0, c1, c2, c3]) => Calibration {
+            offset,
+            coefficients: [c0, c1, c2, c3],
+        },
+        _ => {
+            return Err(format!(
+                "found {}, where a legacy calibration is [offset, c0, c1, c2, c3]",
+                shape(&value)
+            ))
+        }
+    };
+    calibration.validate().map_err(|error| error.to_string())?;
+    Ok(CalibrationRecord::from(calibration).with_notes(LEGACY_NOTE))
+}
+
+/// What `value` is, for an error.
+fn shape(value: &serde_json::Value) -> String {
+    use serde_json::Value;
+    match value {
+        Value::Null => "null".into(),
+        Value::Bool(_) => "a boolean".into(),
+        Value::Number(_) => "a number".into(),
+        Value::String(_) => "a string".into(),
+        Value::Object(_) => "an object".into(),
+        Value::Array(items) if items.is_empty() => "an empty array".into(),
+        Value::Array(items) if items.iter().all(Value::is_number) => match items.len() {
+            1 => "an array of 1 number".into(),
+            n => format!("an array of {n} numbers"),
+        },
+        Value::Array(items) => {
+            let kinds: Vec<_> = items.iter().map(shape).collect();
+            format!("an array of {}", kinds.join(", "))
+        }
+    }
+}
+
+/// The checksum saved with `record`: the CRC-32 of its compact JSON, which
+/// does not depend on how the file is laid out.
+///
+/// It is taken over the record as read back, not the bytes of the file, so
+/// it covers the fields a record has and nothing else: a field the record
+/// does not know is ignored on loading and does not fail the check. CRC-32
+/// catches files changed by accident, a half write or a slip while editing,
+/// not ones forged to match.
+fn checksum(record: &CalibrationRecord) -> Result<String, serde_json::Error> {
+    let canonical = serde_json::to_string(record)?;
+    Ok(format!("crc32:{:08x}", crc32(canonical.as_bytes())))
+}
+
+/// Reports a calibration of `platform_id` saved through the bridge
+/// `stored` and loaded through `live` to `tracing`, if the feature is
+/// enabled.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+fn bridge_mismatch(platform_id: &str, stored: i32, live: i32) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        name: "bridge_mismatch",
+        target: "libra",
+        platform_id,
+        stored,
+        live
+    );
+}
+
+fn io_error(action: &'static str, path: &Path, source: io::Error) -> CalibrationError {
+    CalibrationError::Io {
+        action,
+        path: path.into(),
+        source,
+    }
+}
+
+fn assignment_io_error(action: &'static str, path: &Path, source: io::Error) -> AssignmentError {
+    AssignmentError::Io {
+        action,
+        path: path.into(),
+        source,
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl<V: VoltageSource> ConnectedScale<V> {
+    /// Saves the calibration in use to `store`, with
+    /// [`calibrated_at`](Self::calibrated_at) as when it was performed and
+    /// the scale's [`identity`](Self::identity), if it has one. See
+    /// [`CalibrationStore::save`].
+    pub fn save_calibration(&self, store: &CalibrationStore) -> Result<(), CalibrationError> {
+        store.save_record(&CalibrationRecord {
+            performed_at: self.calibrated_at(),
+            identity: self.identity(),
+            ..self.calibration().into()
+        })
+    }
+
+    /// Replaces the calibration in use with the one in `store`, and takes
+    /// when it was performed, or failing that the time the file was last
+    /// written, as when it was made. The tare is kept.
+    ///
+    /// The identity saved with the calibration is first checked against
+    /// the scale's platform and bridge, as by
+    /// [`CalibrationStore::check_identity`], and the calibration in use is
+    /// kept if the check fails.
+    pub fn load_latest(
+        &mut self,
+        store: &CalibrationStore,
+    ) -> Result<Calibration, CalibrationError> {
+        let record = store.load_record()?;
+        store.check_identity(&record, self.platform_id(), self.get_phidget_id())?;
+        self.set_calibration(record.calibration);
+        self.set_calibrated_at(record.performed_at.or_else(|| {
+            fs::metadata(store.path())
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        }));
+        Ok(record.calibration)
+    }
+}
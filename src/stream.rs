@@ -0,0 +1,273 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use futures_core::Stream;
+
+use crate::overflow::{OverflowPolicy, OverflowStats};
+#[cfg(feature = "hardware")]
+use crate::scale::ConnectedScale;
+use crate::scale::ScaleError;
+#[cfg(feature = "hardware")]
+use crate::source::VoltageSource;
+use crate::{Grams, StampedWeight};
+
+/// Samples buffered between the sampling thread and the consumer.
+pub const DEFAULT_STREAM_CAPACITY: usize = 64;
+
+struct Buffer {
+    items: VecDeque<Result<StampedWeight, ScaleError>>,
+    waker: Option<Waker>,
+    finished: bool,
+}
+
+struct Shared {
+    buffer: Mutex<Buffer>,
+    /// Signalled whenever the consumer takes an item or the stream stops.
+    space: Condvar,
+    capacity: usize,
+    policy: OverflowPolicy,
+    interval: Duration,
+    stop: AtomicBool,
+    dropped: AtomicU64,
+}
+
+impl Shared {
+    fn buffer(&self) -> MutexGuard<'_, Buffer> {
+        self.buffer.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.space.notify_all();
+    }
+
+    /// Counts a lost sample, and reports it to `tracing` if the feature is
+    /// enabled.
+    fn overrun(&self) {
+        let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            name: "overrun",
+            target: "libra",
+            capacity = self.capacity,
+            dropped
+        );
+        #[cfg(not(feature = "tracing"))]
+        let _ = dropped;
+    }
+
+    /// Buffers `item` according to the overflow policy. Returns `false` once
+    /// sampling should end.
+    fn push(&self, item: Result<StampedWeight, ScaleError>) -> bool {
+        let mut buffer = self.buffer();
+        if buffer.items.len() >= self.capacity {
+            match self.policy {
+                OverflowPolicy::DropOldest => {
+                    buffer.items.pop_front();
+                    self.overrun();
+                }
+                OverflowPolicy::DropNewest => {
+                    self.overrun();
+                    return true;
+                }
+                OverflowPolicy::Block { timeout } => {
+                    // Never hold the sampling thread past its next read.
+                    let wait = timeout.min(self.interval);
+                    buffer = self
+                        .space
+                        .wait_timeout_while(buffer, wait, |buffer| {
+                            buffer.items.len() >= self.capacity
+                                && !self.stop.load(Ordering::Relaxed)
+                        })
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                    if buffer.items.len() >= self.capacity {
+                        self.overrun();
+                        return true;
+                    }
+                }
+                OverflowPolicy::Error => {
+                    self.overrun();
+                    buffer.items.push_back(Err(ScaleError::Overflow {
+                        capacity: self.capacity,
+                    }));
+                    if let Some(waker) = buffer.waker.take() {
+                        waker.wake();
+                    }
+                    return false;
+                }
+            }
+        }
+        buffer.items.push_back(item);
+        if let Some(waker) = buffer.waker.take() {
+            waker.wake();
+        }
+        true
+    }
+
+    fn finish(&self) {
+        let mut buffer = self.buffer();
+        buffer.finished = true;
+        if let Some(waker) = buffer.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Marks the stream finished even if the read function panics.
+struct FinishOnDrop(Arc<Shared>);
+
+impl Drop for FinishOnDrop {
+    fn drop(&mut self) {
+        self.0.finish();
+    }
+}
+
+/// A stream of weight samples taken on a dedicated thread.
+///
+/// Samples are buffered up to a fixed capacity. What happens when the
+/// consumer falls behind is set by an [`OverflowPolicy`]; by default the
+/// oldest buffered sample is discarded to make room for the new one, so the
+/// stream always catches up to the current weight. Gaps in
+/// `StampedWeight::sequence` and [`WeightStream::dropped`] show how many were
+/// lost. With [`OverflowPolicy::Error`] the first overflow is yielded as a
+/// final `ScaleError::Overflow` item (one slot over capacity) and sampling
+/// stops. Read errors are yielded as `Err` items and sampling carries on.
+///
+/// The stream ends after [`WeightStopper::stop`] is called and the buffered
+/// samples have been consumed. Dropping the stream also stops the sampler.
+pub struct WeightStream {
+    shared: Arc<Shared>,
+}
+
+/// Stops the sampling thread behind a [`WeightStream`].
+#[derive(Clone)]
+pub struct WeightStopper {
+    shared: Arc<Shared>,
+}
+
+impl WeightStopper {
+    pub fn stop(&self) {
+        self.shared.stop();
+    }
+}
+
+impl WeightStream {
+    /// Starts sampling `read` every `interval`, buffering up to `capacity`
+    /// samples and dropping the oldest when full.
+    pub fn spawn<F>(read: F, interval: Duration, capacity: usize) -> Self
+    where
+        F: FnMut() -> Result<Grams, ScaleError> + Send + 'static,
+    {
+        Self::spawn_with_policy(read, interval, capacity, OverflowPolicy::DropOldest)
+    }
+
+    /// Like [`spawn`](Self::spawn), handling a full buffer with `policy`.
+    ///
+    /// [`OverflowPolicy::Block`] waits at most one `interval` for room, however
+    /// long its timeout, so a stalled consumer cannot stall the hardware reads.
+    pub fn spawn_with_policy<F>(
+        mut read: F,
+        interval: Duration,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> Self
+    where
+        F: FnMut() -> Result<Grams, ScaleError> + Send + 'static,
+    {
+        let shared = Arc::new(Shared {
+            buffer: Mutex::new(Buffer {
+                items: VecDeque::with_capacity(capacity),
+                waker: None,
+                finished: false,
+            }),
+            space: Condvar::new(),
+            capacity: capacity.max(1),
+            policy,
+            interval,
+            stop: AtomicBool::new(false),
+            dropped: AtomicU64::new(0),
+        });
+
+        let sampler = FinishOnDrop(Arc::clone(&shared));
+        thread::spawn(move || {
+            let shared = &sampler.0;
+            let mut sequence = 0;
+            let mut next = Instant::now();
+            while !shared.stop.load(Ordering::Relaxed) {
+                let item = read().map(|weight| {
+                    sequence += 1;
+                    StampedWeight {
+                        weight,
+                        sequence: sequence - 1,
+                        timestamp: SystemTime::now(),
+                    }
+                });
+                if !shared.push(item) {
+                    break;
+                }
+                next += interval;
+                thread::sleep(next.saturating_duration_since(Instant::now()));
+            }
+        });
+
+        Self { shared }
+    }
+
+    pub fn stopper(&self) -> WeightStopper {
+        WeightStopper {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+
+    /// Samples discarded so far because the buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    pub fn stats(&self) -> OverflowStats {
+        OverflowStats {
+            queued: self.shared.buffer().items.len(),
+            dropped: self.dropped(),
+        }
+    }
+}
+
+impl Stream for WeightStream {
+    type Item = Result<StampedWeight, ScaleError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut buffer = self.shared.buffer();
+        if let Some(item) = buffer.items.pop_front() {
+            self.shared.space.notify_one();
+            return Poll::Ready(Some(item));
+        }
+        if buffer.finished {
+            return Poll::Ready(None);
+        }
+        buffer.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for WeightStream {
+    fn drop(&mut self) {
+        self.shared.stop();
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl<V: VoltageSource + Send + 'static> ConnectedScale<V> {
+    /// Moves the scale onto a sampling thread that reads the weight every
+    /// `interval` and yields the readings as a stream.
+    ///
+    /// See [`WeightStream`] for the buffering and termination behavior.
+    pub fn into_weight_stream(self, interval: Duration) -> WeightStream {
+        WeightStream::spawn(move || self.get_weight(), interval, DEFAULT_STREAM_CAPACITY)
+    }
+}
@@ -0,0 +1,2091 @@
+use std::collections::VecDeque;
+use std::ops::Range;
+#[cfg(feature = "hardware")]
+use std::ops::{Bound, RangeBounds};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+#[cfg(feature = "hardware")]
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "net")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, DuplexStream};
+#[cfg(feature = "net")]
+use tokio_util::sync::CancellationToken;
+
+#[cfg(feature = "hardware")]
+use phidget::ReturnCode;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use thiserror::Error;
+use toml::Spanned;
+
+#[cfg(feature = "hardware")]
+use crate::calibration::Calibration;
+
+#[cfg(feature = "net")]
+use crate::actor::ScaleHandle;
+use crate::cancel::CancelFlag;
+#[cfg(feature = "net")]
+use crate::client::{ClientConfig, ScaleClient};
+use crate::clock::{Clock, SystemClock};
+use crate::config::line_column;
+#[cfg(feature = "hardware")]
+use crate::config::BridgeGain;
+#[cfg(feature = "net")]
+use crate::net::{serve_connection, Fleet, ServerConfig};
+use crate::sampling::collect_median_on;
+#[cfg(feature = "tokio")]
+use crate::sampling::{check_samples, finite, MedianCollector};
+#[cfg(feature = "hardware")]
+use crate::scale::NUMBER_OF_INPUTS;
+use crate::scale::{ScaleError, DEFAULT_MEDIAN_SAMPLES};
+#[cfg(feature = "hardware")]
+use crate::source::VoltageSource;
+#[cfg(feature = "hardware")]
+use crate::watchdog::StaleChannel;
+#[cfg(feature = "tokio")]
+use crate::BoxFuture;
+#[cfg(feature = "hardware")]
+use crate::ScaleStatus;
+#[cfg(feature = "tokio")]
+use crate::{AsyncScale, AsyncScaleError};
+use crate::{Grams, MedianGrams, Scale};
+
+#[cfg(feature = "hardware")]
+type RatioCallback = Arc<dyn Fn(f64) + Send + Sync>;
+
+#[cfg(feature = "hardware")]
+struct FakeState {
+    serial_number: i32,
+    ratio: f64,
+    queued: VecDeque<f64>,
+    failure: Option<ReturnCode>,
+    attached: bool,
+    closed: bool,
+    data_interval: Duration,
+    /// The shortest and longest data intervals taken, if limited.
+    data_interval_bounds: Option<(Duration, Duration)>,
+    bridge_gain: BridgeGain,
+    reads: usize,
+    callback: Option<RatioCallback>,
+    latencies: Latencies,
+    clock: Arc<dyn Clock>,
+    last_read_at: Option<Instant>,
+}
+
+/// An in-memory [`VoltageSource`], for testing a
+/// [`ConnectedScale`](crate::scale::ConnectedScale) without hardware.
+///
+/// Clones share one channel, so a test can keep a clone to steer the
+/// readings and inspect what the scale did after moving the source into it.
+/// Reads return the queued ratios in order, then the current ratio.
+#[cfg(feature = "hardware")]
+#[derive(Clone)]
+pub struct FakeVoltageSource {
+    state: Arc<Mutex<FakeState>>,
+}
+
+#[cfg(feature = "hardware")]
+impl FakeVoltageSource {
+    /// An attached channel on the bridge with `serial_number`, reading 0.
+    pub fn new(serial_number: i32) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(FakeState {
+                serial_number,
+                ratio: 0.,
+                queued: VecDeque::new(),
+                failure: None,
+                attached: true,
+                closed: false,
+                data_interval: Duration::from_millis(8),
+                data_interval_bounds: None,
+                bridge_gain: BridgeGain::Gain128,
+                reads: 0,
+                callback: None,
+                latencies: Latencies::new(Latency::default()),
+                clock: Arc::new(SystemClock),
+                last_read_at: None,
+            })),
+        }
+    }
+
+    /// A source for every load cell of the bridge with `serial_number`.
+    pub fn bridge(serial_number: i32) -> [Self; NUMBER_OF_INPUTS] {
+        std::array::from_fn(|_| Self::new(serial_number))
+    }
+
+    /// Sets the ratio read once the queue is empty, and passes it to the
+    /// change callback, if any.
+    pub fn set_ratio(&self, ratio: f64) {
+        let callback = {
+            let mut state = self.state();
+            state.ratio = ratio;
+            state.callback.clone()
+        };
+        if let Some(callback) = callback {
+            callback(ratio);
+        }
+    }
+
+    /// Queues ratios for the next reads, one each.
+    pub fn queue(&self, ratios: impl IntoIterator<Item = f64>) {
+        self.state().queued.extend(ratios);
+    }
+
+    /// Makes every read fail with `failure`, or succeed again for `None`.
+    pub fn fail_with(&self, failure: Option<ReturnCode>) {
+        self.state().failure = failure;
+    }
+
+    /// A detached channel reports so, and fails every read with
+    /// `ReturnCode::NotAttached`.
+    pub fn set_attached(&self, attached: bool) {
+        self.state().attached = attached;
+    }
+
+    /// Makes reads take `latency`, a [`Latency`] or a fixed `Duration`,
+    /// slept on the source's clock.
+    pub fn set_latency(&self, latency: impl Into<Latency>) {
+        self.state().latencies = Latencies::new(latency.into());
+    }
+
+    /// Limits the data intervals taken to those from `min` to `max`, which
+    /// the source then reports, as a phidget does. Other intervals fail
+    /// with `ReturnCode::InvalidArg`. Without limits, any interval but zero
+    /// is taken and none are reported.
+    pub fn set_data_interval_bounds(&self, min: Duration, max: Duration) {
+        self.state().data_interval_bounds = Some((min, max));
+    }
+
+    /// Spends the latency on `clock` rather than the real clock.
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        self.state().clock = clock;
+    }
+
+    /// Reads attempted so far, failed or not.
+    pub fn reads(&self) -> usize {
+        self.state().reads
+    }
+
+    /// When, by the source's clock, the last read finished.
+    pub fn last_read_at(&self) -> Option<Instant> {
+        self.state().last_read_at
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state().closed
+    }
+
+    fn state(&self) -> MutexGuard<'_, FakeState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl VoltageSource for FakeVoltageSource {
+    fn ratio(&self) -> phidget::Result<f64> {
+        let (latency, clock) = {
+            let mut state = self.state();
+            (state.latencies.next(), Arc::clone(&state.clock))
+        };
+        // Slept without the lock, so clones can be steered meanwhile.
+        clock.sleep(latency);
+        let mut state = self.state();
+        state.reads += 1;
+        state.last_read_at = Some(clock.now());
+        if state.closed || !state.attached {
+            return Err(ReturnCode::NotAttached);
+        }
+        if let Some(failure) = state.failure {
+            return Err(failure);
+        }
+        let ratio = state.ratio;
+        Ok(state.queued.pop_front().unwrap_or(ratio))
+    }
+
+    fn set_data_interval(&mut self, interval: Duration) -> phidget::Result<()> {
+        let mut state = self.state();
+        let taken = match state.data_interval_bounds {
+            Some((min, max)) => (min..=max).contains(&interval),
+            None => !interval.is_zero(),
+        };
+        if !taken {
+            return Err(ReturnCode::InvalidArg);
+        }
+        state.data_interval = interval;
+        Ok(())
+    }
+
+    fn data_interval(&mut self) -> phidget::Result<Duration> {
+        Ok(self.state().data_interval)
+    }
+
+    fn min_data_interval(&mut self) -> phidget::Result<Duration> {
+        let bounds = self.state().data_interval_bounds;
+        bounds.map(|(min, _)| min).ok_or(ReturnCode::Unsupported)
+    }
+
+    fn max_data_interval(&mut self) -> phidget::Result<Duration> {
+        let bounds = self.state().data_interval_bounds;
+        bounds.map(|(_, max)| max).ok_or(ReturnCode::Unsupported)
+    }
+
+    fn set_bridge_gain(&mut self, gain: BridgeGain) -> phidget::Result<()> {
+        self.state().bridge_gain = gain;
+        Ok(())
+    }
+
+    fn bridge_gain(&mut self) -> phidget::Result<BridgeGain> {
+        Ok(self.state().bridge_gain)
+    }
+
+    fn serial_number(&mut self) -> phidget::Result<i32> {
+        Ok(self.state().serial_number)
+    }
+
+    fn is_attached(&mut self) -> phidget::Result<bool> {
+        let state = self.state();
+        Ok(state.attached && !state.closed)
+    }
+
+    fn close(&mut self) -> phidget::Result<()> {
+        self.state().closed = true;
+        Ok(())
+    }
+
+    fn on_ratio_change(&mut self, callback: Box<dyn Fn(f64) + Send + Sync>) -> phidget::Result<()> {
+        self.state().callback = Some(Arc::from(callback));
+        Ok(())
+    }
+}
+
+/// A [`Clock`] that only moves when told to, so that code which waits can be
+/// tested in no time, and the same way on every run.
+///
+/// A sleep returns at once, having moved the clock on by its duration, as
+/// though the sleeper were the only thing running; [`advance`](Self::advance)
+/// moves it on for anything else, such as a read that takes time. Clones
+/// share one clock.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    now: Arc<Mutex<Instant>>,
+    started: Instant,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            now: Arc::new(Mutex::new(now)),
+            started: now,
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) += by;
+    }
+
+    /// How far the clock has moved since it was made.
+    pub fn elapsed(&self) -> Duration {
+        self.now() - self.started
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+
+    /// Moves the clock on, then gives other tasks a turn.
+    #[cfg(feature = "tokio")]
+    fn sleep_async(&self, duration: Duration) -> BoxFuture<'_, ()> {
+        self.advance(duration);
+        Box::pin(tokio::task::yield_now())
+    }
+}
+
+/// How far a [`TokioClock`] moves tokio's time at once: the resolution of
+/// tokio's timers.
+#[cfg(feature = "tokio")]
+const TOKIO_TICK: Duration = Duration::from_millis(1);
+
+/// Tokio's clock, for tests run in paused time, as with
+/// `#[tokio::test(start_paused = true)]`.
+///
+/// Tokio only moves paused time on while nothing is running, which a
+/// blocking read on `spawn_blocking` always is. A sleep on this clock moves
+/// tokio's time on itself, a millisecond at a time, letting the runtime run
+/// whatever comes due at each, as though the sleep had taken that long.
+/// Sleeping blocks, so it must not be done on one of the runtime's threads.
+#[cfg(feature = "tokio")]
+#[derive(Clone, Debug)]
+pub struct TokioClock {
+    runtime: tokio::runtime::Handle,
+}
+
+#[cfg(feature = "tokio")]
+impl TokioClock {
+    /// The clock of the runtime this is called from.
+    pub fn new() -> Self {
+        Self {
+            runtime: tokio::runtime::Handle::current(),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Default for TokioClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        let _runtime = self.runtime.enter();
+        tokio::time::Instant::now().into_std()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        let advance = self.runtime.spawn(async move {
+            let mut left = duration;
+            while !left.is_zero() {
+                let tick = left.min(TOKIO_TICK);
+                tokio::time::advance(tick).await;
+                tokio::task::yield_now().await;
+                left -= tick;
+            }
+        });
+        // Cancelled only as the runtime shuts down, when time no longer matters.
+        if let Err(error) = self.runtime.block_on(advance) {
+            if let Ok(panic) = error.try_into_panic() {
+                std::panic::resume_unwind(panic);
+            }
+        }
+    }
+}
+
+/// How a [`SimulatedScale`] behaves.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulationConfig {
+    /// What is on the platform to begin with.
+    pub load: Grams,
+    /// Standard deviation of the Gaussian noise on each reading, in grams.
+    pub noise: f64,
+    /// How fast the reading creeps away from the load, in grams per second,
+    /// as a load cell warming up does.
+    pub drift: f64,
+    /// Time constant of the reading's approach to a new load: after a
+    /// [`set_load`](SimulatedScale::set_load), about 63% of the change shows
+    /// after one `settling`, and over 99% after five.
+    pub settling: Duration,
+    /// How often the bridge has a new reading. Reads in between see the same
+    /// one again, as they would from a phidget.
+    pub data_interval: Duration,
+    /// Seed of the noise, so a run can be repeated.
+    pub seed: u64,
+    /// Temperature and creep effects, none by default.
+    pub scenario: Scenario,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            load: Grams(0.),
+            noise: 0.5,
+            drift: 0.,
+            settling: Duration::from_millis(200),
+            data_interval: Duration::from_millis(8),
+            seed: 0x11b7a,
+            scenario: Scenario::default(),
+        }
+    }
+}
+
+/// Environmental effects a [`SimulatedScale`] plays out on top of its load,
+/// noise and drift: a temperature that shifts the zero and span, and creep
+/// under load.
+///
+/// At `d` °C from the reference temperature, a reading `w`, creep included,
+/// becomes `w * (1 + span_per_celsius * d) + zero_per_celsius * d`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scenario {
+    /// The temperature, in °C, at times since the scale was made, in time
+    /// order. It goes in a straight line between points and holds before the
+    /// first and after the last. With no points it stays at the reference.
+    pub temperature: Vec<(Duration, f64)>,
+    /// The temperature at which the scale reads true.
+    pub reference_celsius: f64,
+    /// How far the zero moves, in grams per °C away from the reference.
+    pub zero_per_celsius: f64,
+    /// How much the span grows, as a fraction of the reading per °C away
+    /// from the reference.
+    pub span_per_celsius: f64,
+    pub creep: Option<Creep>,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            temperature: Vec::new(),
+            reference_celsius: 20.,
+            zero_per_celsius: 0.,
+            span_per_celsius: 0.,
+            creep: None,
+        }
+    }
+}
+
+/// How a load cell creeps: after the load changes, the reading goes on
+/// moving by `magnitude` times the change, approaching it exponentially.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Creep {
+    /// The creep once it has run its course, as a fraction of the load.
+    pub magnitude: f64,
+    /// About 63% of the creep shows after one `time_constant`.
+    pub time_constant: Duration,
+}
+
+/// Line `line` of a scenario's TOML could not be read.
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("Line {line} of the scenario is invalid: {message}")]
+pub struct ScenarioError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Scenario {
+    /// The temperature `elapsed` after the scale was made.
+    pub fn temperature_at(&self, elapsed: Duration) -> f64 {
+        let after = self.temperature.partition_point(|(at, _)| *at <= elapsed);
+        let before = after.checked_sub(1).map(|before| self.temperature[before]);
+        match (before, self.temperature.get(after)) {
+            (None, None) => self.reference_celsius,
+            (Some((_, celsius)), None) | (None, Some(&(_, celsius))) => celsius,
+            (Some((from_at, from)), Some(&(to_at, to))) => {
+                let along = (elapsed - from_at).as_secs_f64() / (to_at - from_at).as_secs_f64();
+                from + (to - from) * along
+            }
+        }
+    }
+
+    /// Reads a scenario from TOML: `reference_celsius`, `zero_g_per_celsius`
+    /// and `span_per_celsius` at the top, a `[[temperature]]` table with
+    /// `at_s` and `celsius` for each point of the profile, and optionally a
+    /// `[creep]` table with `magnitude` and `time_constant_s`. Numbers may be
+    /// written as integers or floats.
+    ///
+    /// ```toml
+    /// # Warms up by 10 °C over ten minutes, then holds.
+    /// zero_g_per_celsius = 0.05
+    /// span_per_celsius = 0.0002
+    ///
+    /// [[temperature]]
+    /// at_s = 0
+    /// celsius = 20
+    ///
+    /// [[temperature]]
+    /// at_s = 600
+    /// celsius = 30
+    ///
+    /// [creep]
+    /// magnitude = 0.001
+    /// time_constant_s = 120
+    /// ```
+    ///
+    /// Other keys and tables are refused.
+    pub fn from_toml(toml: &str) -> Result<Self, ScenarioError> {
+        let invalid = |(line, message)| ScenarioError { line, message };
+        let file: ScenarioFile = read_toml(toml).map_err(invalid)?;
+        let seconds = |seconds: &Spanned<f64>| {
+            Duration::try_from_secs_f64(*seconds.get_ref()).map_err(|_| ScenarioError {
+                line: toml_line(toml, seconds.span()),
+                message: format!("{} s is not a time", seconds.get_ref()),
+            })
+        };
+
+        let mut scenario = Self::default();
+        scenario.reference_celsius = file.reference_celsius.unwrap_or(scenario.reference_celsius);
+        scenario.zero_per_celsius = file.zero_g_per_celsius.unwrap_or(0.);
+        scenario.span_per_celsius = file.span_per_celsius.unwrap_or(0.);
+        for point in &file.temperature {
+            let at = seconds(&point.get_ref().at_s)?;
+            if scenario
+                .temperature
+                .last()
+                .is_some_and(|(last, _)| *last > at)
+            {
+                return Err(ScenarioError {
+                    line: toml_line(toml, point.span()),
+                    message: "temperature points must be in time order".into(),
+                });
+            }
+            scenario.temperature.push((at, point.get_ref().celsius));
+        }
+        scenario.creep = file
+            .creep
+            .map(|creep| {
+                Ok::<_, ScenarioError>(Creep {
+                    magnitude: creep.magnitude,
+                    time_constant: seconds(&creep.time_constant_s)?,
+                })
+            })
+            .transpose()?;
+        Ok(scenario)
+    }
+}
+
+impl FromStr for Scenario {
+    type Err = ScenarioError;
+
+    fn from_str(toml: &str) -> Result<Self, Self::Err> {
+        Self::from_toml(toml)
+    }
+}
+
+/// A scenario as written in TOML.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioFile {
+    reference_celsius: Option<f64>,
+    zero_g_per_celsius: Option<f64>,
+    span_per_celsius: Option<f64>,
+    #[serde(default)]
+    temperature: Vec<Spanned<TemperaturePoint>>,
+    creep: Option<CreepTable>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TemperaturePoint {
+    at_s: Spanned<f64>,
+    celsius: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CreepTable {
+    magnitude: f64,
+    time_constant_s: Spanned<f64>,
+}
+
+/// Reads `toml` as a `T`, or the line of the mistake and what it is.
+fn read_toml<T: DeserializeOwned>(toml: &str) -> Result<T, (usize, String)> {
+    toml::from_str(toml).map_err(|error: toml::de::Error| {
+        let line = error.span().map_or(1, |span| toml_line(toml, span));
+        (line, error.message().to_string())
+    })
+}
+
+/// The line, from 1, that `span` of `toml` starts on.
+fn toml_line(toml: &str, span: Range<usize>) -> usize {
+    line_column(toml, span.start).0
+}
+
+struct Simulation {
+    rng: u64,
+    /// The load being settled towards, the reading it started from, and when.
+    load: f64,
+    from: f64,
+    changed_at: Instant,
+    /// The creep when the load last changed; it heads for the scenario's
+    /// share of the load from there.
+    creep_from: f64,
+    /// The data interval the last reading came from, and the reading.
+    last: Option<(u128, f64)>,
+    tare: f64,
+}
+
+/// A scale that behaves like a real one without hardware, for demos and
+/// tests: noisy, slowly drifting readings that settle on a new load over
+/// time and only change once per data interval.
+///
+/// Medians honor the data interval too: each sample is taken one interval
+/// after the last, so that every sample is a fresh reading.
+pub struct SimulatedScale {
+    config: SimulationConfig,
+    clock: Arc<dyn Clock>,
+    started: Instant,
+    simulation: Mutex<Simulation>,
+}
+
+impl SimulatedScale {
+    pub fn new(config: SimulationConfig) -> Self {
+        Self::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// A scale whose readings, and the waits between the samples of its
+    /// medians, go by `clock`: with a [`ManualClock`], it only settles and
+    /// drifts as the clock is moved on.
+    pub fn with_clock(config: SimulationConfig, clock: Arc<dyn Clock>) -> Self {
+        let now = clock.now();
+        Self {
+            simulation: Mutex::new(Simulation {
+                rng: config.seed,
+                load: config.load.0,
+                from: config.load.0,
+                changed_at: now,
+                creep_from: config
+                    .scenario
+                    .creep
+                    .map_or(0., |creep| creep.magnitude * config.load.0),
+                last: None,
+                tare: 0.,
+            }),
+            started: now,
+            clock,
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &SimulationConfig {
+        &self.config
+    }
+
+    /// Places or removes things, so that `load` is on the platform from now
+    /// on. The reading settles on it over [`SimulationConfig::settling`].
+    pub fn set_load(&self, load: Grams) {
+        let now = self.clock.now();
+        let mut simulation = self.simulation();
+        simulation.from = self.settled(&simulation, now);
+        simulation.creep_from = self.creep(&simulation, now);
+        simulation.load = load.0;
+        simulation.changed_at = now;
+    }
+
+    /// What is on the platform, as last set.
+    pub fn load(&self) -> Grams {
+        Grams(self.simulation().load)
+    }
+
+    fn read(&self) -> Grams {
+        let now = self.clock.now();
+        let interval = self.config.data_interval.as_nanos().max(1);
+        let tick = now.duration_since(self.started).as_nanos() / interval;
+        let mut simulation = self.simulation();
+        let reading = match simulation.last {
+            Some((last, reading)) if last == tick => reading,
+            _ => {
+                let noise = self.config.noise * gaussian(&mut simulation.rng);
+                let reading = self.environment(&simulation, now) + noise;
+                simulation.last = Some((tick, reading));
+                reading
+            }
+        };
+        Grams(reading - simulation.tare)
+    }
+
+    /// The reading at `now` without noise or tare.
+    fn settled(&self, simulation: &Simulation, now: Instant) -> f64 {
+        let since = now.duration_since(simulation.changed_at).as_secs_f64();
+        let settling = self.config.settling.as_secs_f64();
+        let remaining = if settling > 0. {
+            (-since / settling).exp()
+        } else {
+            0.
+        };
+        let drift = self.config.drift * now.duration_since(self.started).as_secs_f64();
+        simulation.load + (simulation.from - simulation.load) * remaining + drift
+    }
+
+    /// The creep at `now`.
+    fn creep(&self, simulation: &Simulation, now: Instant) -> f64 {
+        let Some(creep) = self.config.scenario.creep else {
+            return 0.;
+        };
+        let since = now.duration_since(simulation.changed_at).as_secs_f64();
+        let time_constant = creep.time_constant.as_secs_f64();
+        let remaining = if time_constant > 0. {
+            (-since / time_constant).exp()
+        } else {
+            0.
+        };
+        let to = creep.magnitude * simulation.load;
+        to + (simulation.creep_from - to) * remaining
+    }
+
+    /// The reading at `now` without noise or tare, with the scenario's creep
+    /// and temperature effects.
+    fn environment(&self, simulation: &Simulation, now: Instant) -> f64 {
+        let scenario = &self.config.scenario;
+        let celsius = scenario.temperature_at(now.duration_since(self.started));
+        let away = celsius - scenario.reference_celsius;
+        let reading = self.settled(simulation, now) + self.creep(simulation, now);
+        reading * (1. + scenario.span_per_celsius * away) + scenario.zero_per_celsius * away
+    }
+
+    fn simulation(&self) -> MutexGuard<'_, Simulation> {
+        self.simulation
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The next number of a splitmix64 stream.
+fn splitmix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// A standard normal sample, by Box-Muller over a splitmix64 stream.
+fn gaussian(state: &mut u64) -> f64 {
+    // In (0, 1], so the logarithm is finite.
+    let mut uniform = || ((splitmix(state) >> 11) + 1) as f64 / (1u64 << 53) as f64;
+    let (u, v) = (uniform(), uniform());
+    (-2. * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()
+}
+
+impl Scale for SimulatedScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.read())
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_median_weight_of(DEFAULT_MEDIAN_SAMPLES)
+    }
+
+    fn get_median_weight_cancellable(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        collect_median_on(
+            &*self.clock,
+            samples,
+            self.config.data_interval,
+            cancel,
+            || Scale::get_weight(self),
+        )
+    }
+
+    fn get_median_weight_yielding(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+        between_samples: &mut dyn FnMut(),
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        collect_median_on(
+            &*self.clock,
+            samples,
+            self.config.data_interval,
+            cancel,
+            || {
+                between_samples();
+                Scale::get_weight(self)
+            },
+        )
+    }
+
+    fn tare(&mut self, samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        let gross = self.get_median_weight_of(samples)?.get() + self.simulation().tare;
+        self.simulation().tare = gross;
+        Ok(Grams(gross))
+    }
+
+    fn clock(&self) -> Arc<dyn Clock> {
+        Arc::clone(&self.clock)
+    }
+}
+
+/// Reads take no time, and the waits between the samples of a median are
+/// [`Clock::sleep_async`]s of one data interval: `tokio::time::sleep`s, on
+/// the real clock.
+#[cfg(feature = "tokio")]
+impl AsyncScale for SimulatedScale {
+    async fn get_weight(&self) -> Result<Grams, AsyncScaleError> {
+        Ok(self.read())
+    }
+
+    async fn get_median_weight(&self, samples: usize) -> Result<MedianGrams, AsyncScaleError> {
+        check_samples(samples)?;
+        let mut collector =
+            MedianCollector::with_clock(samples, self.config.data_interval, &*self.clock);
+        while let Some(delay) = collector.next_delay() {
+            self.clock.sleep_async(delay).await;
+            collector.push(self.read());
+        }
+        Ok(collector.finish())
+    }
+}
+
+/// How long each read of a [`MockScale`] or a `FakeVoltageSource` takes.
+///
+/// The time is spent sleeping on the reader's [`Clock`]: with a
+/// [`ManualClock`], a slow read moves the clock on and returns at once, so
+/// that timing can be tested fast and the same way on every run. A
+/// `TokioClock` does the same for reads made through the scale actor, in
+/// tokio's paused time.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Latency {
+    /// Every read takes this long.
+    Fixed(Duration),
+    /// Each read takes a time drawn evenly from `min` to `max`, from a
+    /// stream seeded with `seed`, so a run can be repeated.
+    Uniform {
+        min: Duration,
+        max: Duration,
+        seed: u64,
+    },
+    /// Read `n`, counting from 0, takes the `n`th duration; reads past the
+    /// end take none.
+    Scripted(Vec<Duration>),
+}
+
+impl Default for Latency {
+    fn default() -> Self {
+        Latency::Fixed(Duration::ZERO)
+    }
+}
+
+impl From<Duration> for Latency {
+    fn from(latency: Duration) -> Self {
+        Latency::Fixed(latency)
+    }
+}
+
+/// The durations of successive reads with a [`Latency`].
+struct Latencies {
+    latency: Latency,
+    rng: u64,
+    calls: usize,
+}
+
+impl Latencies {
+    fn new(latency: Latency) -> Self {
+        let rng = match latency {
+            Latency::Uniform { seed, .. } => seed,
+            _ => 0,
+        };
+        Self {
+            latency,
+            rng,
+            calls: 0,
+        }
+    }
+
+    fn next(&mut self) -> Duration {
+        let call = self.calls;
+        self.calls += 1;
+        match &self.latency {
+            Latency::Fixed(latency) => *latency,
+            Latency::Uniform { min, max, .. } => {
+                let along = (splitmix(&mut self.rng) >> 11) as f64 / (1u64 << 53) as f64;
+                *min + max.saturating_sub(*min).mul_f64(along)
+            }
+            Latency::Scripted(latencies) => latencies.get(call).copied().unwrap_or_default(),
+        }
+    }
+}
+
+/// A scale that answers from a script, for unit tests of code that is
+/// generic over [`Scale`] or [`AsyncScale`].
+///
+/// Each read takes the next response from the script, then keeps returning
+/// the steady weight once the script runs out. Medians read their samples
+/// the same way, so an error in the script fails the median that reaches
+/// it. The scale counts its calls, so tests can check what the code under
+/// test asked for.
+///
+/// A downstream unit test might look like this:
+///
+/// ```
+/// use libra::scale::ScaleError;
+/// use libra::testing::MockScale;
+/// use libra::{Grams, Scale};
+///
+/// /// Code under test: the first reading over `limit`, if any.
+/// fn first_over(scale: &impl Scale, limit: Grams, tries: usize) -> Option<Grams> {
+///     (0..tries)
+///         .filter_map(|_| scale.get_weight().ok())
+///         .find(|weight| weight.0 > limit.0)
+/// }
+///
+/// let scale = MockScale::new(Grams(0.)).returns(vec![
+///     Ok(Grams(12.)),
+///     Err(ScaleError::Busy),
+///     Ok(Grams(250.)),
+/// ]);
+/// assert_eq!(first_over(&scale, Grams(100.), 5), Some(Grams(250.)));
+/// assert_eq!(scale.reads(), 3);
+/// ```
+pub struct MockScale {
+    weight: Grams,
+    script: Mutex<VecDeque<Result<Grams, ScaleError>>>,
+    latencies: Mutex<Latencies>,
+    clock: Arc<dyn Clock>,
+    reads: AtomicUsize,
+    medians: AtomicUsize,
+}
+
+impl MockScale {
+    /// A scale that reads `weight` every time.
+    pub fn new(weight: Grams) -> Self {
+        Self {
+            weight,
+            script: Mutex::new(VecDeque::new()),
+            latencies: Mutex::new(Latencies::new(Latency::default())),
+            clock: Arc::new(SystemClock),
+            reads: AtomicUsize::new(0),
+            medians: AtomicUsize::new(0),
+        }
+    }
+
+    /// Answers the next reads with `responses`, in order, after any still
+    /// left from before.
+    pub fn returns(self, responses: impl IntoIterator<Item = Result<Grams, ScaleError>>) -> Self {
+        self.script().extend(responses);
+        self
+    }
+
+    /// Makes reads take `latency`, a [`Latency`] or a fixed `Duration`. It is
+    /// slept on the clock: a [`Clock::sleep`] through [`Scale`], and a
+    /// [`Clock::sleep_async`] through [`AsyncScale`].
+    pub fn with_latency(self, latency: impl Into<Latency>) -> Self {
+        *self.latencies() = Latencies::new(latency.into());
+        self
+    }
+
+    /// Spends the latency, and paces medians, on `clock` rather than the
+    /// real clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Reads so far, counting each sample of a median.
+    pub fn reads(&self) -> usize {
+        self.reads.load(Ordering::Relaxed)
+    }
+
+    /// Medians asked for so far.
+    pub fn medians(&self) -> usize {
+        self.medians.load(Ordering::Relaxed)
+    }
+
+    /// Responses left in the script.
+    pub fn remaining(&self) -> usize {
+        self.script().len()
+    }
+
+    fn next(&self) -> Result<Grams, ScaleError> {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        self.script().pop_front().unwrap_or(Ok(self.weight))
+    }
+
+    fn script(&self) -> MutexGuard<'_, VecDeque<Result<Grams, ScaleError>>> {
+        self.script.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn latencies(&self) -> MutexGuard<'_, Latencies> {
+        self.latencies
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        let latency = self.latencies().next();
+        self.clock.sleep(latency);
+        Ok(self.next()?)
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_median_weight_of(DEFAULT_MEDIAN_SAMPLES)
+    }
+
+    fn get_median_weight_cancellable(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.medians.fetch_add(1, Ordering::Relaxed);
+        collect_median_on(&*self.clock, samples, Duration::ZERO, cancel, || {
+            Scale::get_weight(self)
+        })
+    }
+    fn clock(&self) -> Arc<dyn Clock> {
+        Arc::clone(&self.clock)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncScale for MockScale {
+    async fn get_weight(&self) -> Result<Grams, AsyncScaleError> {
+        let latency = self.latencies().next();
+        self.clock.sleep_async(latency).await;
+        Ok(self.next()?)
+    }
+
+    async fn get_median_weight(&self, samples: usize) -> Result<MedianGrams, AsyncScaleError> {
+        self.medians.fetch_add(1, Ordering::Relaxed);
+        check_samples(samples)?;
+        let mut collector = MedianCollector::with_clock(samples, Duration::ZERO, &*self.clock);
+        while collector.next_delay().is_some() {
+            let weight = AsyncScale::get_weight(self).await?;
+            collector.push(finite(weight)?);
+        }
+        Ok(collector.finish())
+    }
+}
+
+/// Readings a conformance check takes in a row, and the sample counts of the
+/// medians it asks for: odd and even, since the median of an even number is
+/// the upper of the middle two.
+const CONFORMANCE_READS: usize = 20;
+const CONFORMANCE_SAMPLES: [usize; 4] = [1, 2, 3, 5];
+
+/// Checks that a [`Scale`] behaves the way the rest of the crate expects,
+/// panicking with the name of the first check it fails. Meant to be called
+/// from a test of each implementor, in this crate or downstream.
+///
+/// `make` builds a fresh scale for each check, already connected, with a
+/// constant load on it and no noise, so that every reading is the same. The
+/// suite checks that:
+///
+/// - readings are finite and repeated reads do not fail;
+/// - medians, of any number of samples, equal the load;
+/// - a median of no samples fails with `ScaleError::InvalidArgument`, and
+///   one cancelled before it starts with `ScaleError::Cancelled`;
+/// - a yielding median calls back at most once a sample;
+/// - a tare, if the scale supports it, returns the load and zeroes the
+///   readings, and otherwise fails with `ScaleError::Unsupported`;
+/// - a fresh scale has no stale load cell, and closes cleanly.
+///
+/// A wrapper may wrap the `ScaleError`s it passes on, as long as they stay
+/// in the [`source`](std::error::Error::source) chain.
+///
+/// ```
+/// use libra::testing::{scale_conformance, MockScale};
+/// use libra::Grams;
+///
+/// scale_conformance(|| MockScale::new(Grams(250.)));
+/// ```
+pub fn scale_conformance<S: Scale>(make: impl Fn() -> S) {
+    let load = match make().get_weight() {
+        Ok(load) => load,
+        Err(e) => panic!("reading the load failed: {e}"),
+    };
+    assert!(load.0.is_finite(), "the load read as {load:?}");
+
+    let scale = make();
+    for read in 0..CONFORMANCE_READS {
+        match scale.get_weight() {
+            Ok(weight) => assert_weight("repeated reads", read, weight, load),
+            Err(e) => panic!("repeated reads: read {read} failed: {e}"),
+        }
+    }
+
+    for samples in CONFORMANCE_SAMPLES {
+        match make().get_median_weight_of(samples) {
+            Ok(median) => assert_weight("medians", samples, Grams(median.get()), load),
+            Err(e) => panic!("medians: median of {samples} failed: {e}"),
+        }
+    }
+    match make().get_median_weight() {
+        Ok(median) => assert_weight("medians", DEFAULT_MEDIAN_SAMPLES, Grams(median.get()), load),
+        Err(e) => panic!("medians: default median failed: {e}"),
+    }
+
+    let scale = make();
+    let result = scale.get_median_weight_of(0);
+    assert_error("empty medians", result, |e| {
+        matches!(e, ScaleError::InvalidArgument(_))
+    });
+    let cancel = CancelFlag::new();
+    cancel.cancel();
+    let result = scale.get_median_weight_cancellable(3, &cancel);
+    assert_error("cancelled medians", result, |e| {
+        matches!(e, ScaleError::Cancelled { .. })
+    });
+
+    let mut calls = 0;
+    let result = make().get_median_weight_yielding(3, &CancelFlag::new(), &mut || calls += 1);
+    match result {
+        Ok(median) => assert_weight("yielding medians", 3, Grams(median.get()), load),
+        Err(e) => panic!("yielding medians: median of 3 failed: {e}"),
+    }
+    assert!(
+        calls <= 3,
+        "yielding medians: called back {calls} times for 3 samples"
+    );
+
+    let mut scale = make();
+    match scale.tare(3) {
+        Ok(tare) => {
+            assert_weight("taring", 3, tare, load);
+            match scale.get_weight() {
+                Ok(weight) => assert_weight("taring", 0, weight, Grams(0.)),
+                Err(e) => panic!("taring: reading after the tare failed: {e}"),
+            }
+        }
+        result => assert_error("taring", result, |e| {
+            matches!(e, ScaleError::Unsupported(_))
+        }),
+    }
+
+    let mut scale = make();
+    scale.get_weight().ok();
+    assert_eq!(
+        scale.stale_channel(),
+        None,
+        "stale channels: a fresh scale has a stale load cell"
+    );
+    if let Err(e) = scale.close() {
+        panic!("closing failed: {e}");
+    }
+}
+
+/// The async twin of [`scale_conformance`], for an [`AsyncScale`]: finite,
+/// repeated readings, medians equal to the load, a median of no samples
+/// failing with `ScaleError::InvalidArgument`, and a median abandoned after
+/// its first poll leaving the scale usable. `make` builds a fresh scale
+/// with a constant load on it for each check.
+///
+/// It needs whatever runtime the scale does; on a tokio runtime with time
+/// paused, medians that wait between samples take no real time.
+#[cfg(feature = "tokio")]
+pub async fn async_scale_conformance<S: AsyncScale>(make: impl Fn() -> S) {
+    let load = match make().get_weight().await {
+        Ok(load) => load,
+        Err(e) => panic!("reading the load failed: {e}"),
+    };
+    assert!(load.0.is_finite(), "the load read as {load:?}");
+
+    let scale = make();
+    for read in 0..CONFORMANCE_READS {
+        match scale.get_weight().await {
+            Ok(weight) => assert_weight("repeated reads", read, weight, load),
+            Err(e) => panic!("repeated reads: read {read} failed: {e}"),
+        }
+    }
+
+    for samples in CONFORMANCE_SAMPLES {
+        match make().get_median_weight(samples).await {
+            Ok(median) => assert_weight("medians", samples, Grams(median.get()), load),
+            Err(e) => panic!("medians: median of {samples} failed: {e}"),
+        }
+    }
+
+    let result = make().get_median_weight(0).await;
+    assert_error("empty medians", result, |e| {
+        matches!(e, ScaleError::InvalidArgument(_))
+    });
+
+    let scale = make();
+    {
+        let mut median = std::pin::pin!(scale.get_median_weight(3));
+        std::future::poll_fn(|cx| {
+            let _ = std::future::Future::poll(median.as_mut(), cx);
+            std::task::Poll::Ready(())
+        })
+        .await;
+    }
+    match scale.get_weight().await {
+        Ok(weight) => assert_weight("abandoned medians", 0, weight, load),
+        Err(e) => panic!("abandoned medians: reading afterwards failed: {e}"),
+    }
+}
+
+/// Panics unless `weight`, from read or median `n` of `check`, is `expected`
+/// to within rounding.
+fn assert_weight(check: &str, n: usize, weight: Grams, expected: Grams) {
+    let tolerance = 1e-9 * expected.0.abs().max(1.);
+    assert!(
+        (weight.0 - expected.0).abs() <= tolerance,
+        "{check}: {n} gave {weight:?}, not {expected:?}"
+    );
+}
+
+/// Panics unless `result` failed with a `ScaleError` somewhere in its source
+/// chain that is `expected`, so that an implementor cannot pick an error of
+/// its own for a case the crate defines one for.
+fn assert_error<T: std::fmt::Debug>(
+    check: &str,
+    result: Result<T, Box<dyn std::error::Error + Send + Sync>>,
+    expected: impl Fn(&ScaleError) -> bool,
+) {
+    let e = match result {
+        Ok(value) => panic!("{check}: succeeded with {value:?}"),
+        Err(e) => e,
+    };
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(&*e);
+    while let Some(error) = source {
+        if error.downcast_ref().is_some_and(&expected) {
+            return;
+        }
+        source = error.source();
+    }
+    panic!("{check}: failed with the wrong error: {e}");
+}
+
+/// A call a [`FaultRule`] can inject a fault into.
+#[cfg(feature = "hardware")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FaultMethod {
+    /// `Scale::get_weight`, and so every sample of a median.
+    GetWeight,
+    GetRawReadings,
+    GetRawMedians,
+    Tare,
+    Zero,
+    Calibration,
+    SetCalibration,
+    Status,
+    /// `Scale::close` and `VoltageSource::close`.
+    Close,
+    /// `VoltageSource::ratio`.
+    Ratio,
+    SetDataInterval,
+    DataInterval,
+    SerialNumber,
+    IsAttached,
+}
+
+#[cfg(feature = "hardware")]
+impl FaultMethod {
+    pub const ALL: [FaultMethod; 14] = [
+        FaultMethod::GetWeight,
+        FaultMethod::GetRawReadings,
+        FaultMethod::GetRawMedians,
+        FaultMethod::Tare,
+        FaultMethod::Zero,
+        FaultMethod::Calibration,
+        FaultMethod::SetCalibration,
+        FaultMethod::Status,
+        FaultMethod::Close,
+        FaultMethod::Ratio,
+        FaultMethod::SetDataInterval,
+        FaultMethod::DataInterval,
+        FaultMethod::SerialNumber,
+        FaultMethod::IsAttached,
+    ];
+
+    /// The name of the method, as in a schedule's TOML.
+    pub fn name(self) -> &'static str {
+        match self {
+            FaultMethod::GetWeight => "get_weight",
+            FaultMethod::GetRawReadings => "get_raw_readings",
+            FaultMethod::GetRawMedians => "get_raw_medians",
+            FaultMethod::Tare => "tare",
+            FaultMethod::Zero => "zero",
+            FaultMethod::Calibration => "calibration",
+            FaultMethod::SetCalibration => "set_calibration",
+            FaultMethod::Status => "status",
+            FaultMethod::Close => "close",
+            FaultMethod::Ratio => "ratio",
+            FaultMethod::SetDataInterval => "set_data_interval",
+            FaultMethod::DataInterval => "data_interval",
+            FaultMethod::SerialNumber => "serial_number",
+            FaultMethod::IsAttached => "is_attached",
+        }
+    }
+}
+
+/// What a [`FaultRule`] does to a call.
+#[cfg(feature = "hardware")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    /// Fails the call with the return code: as is from a source, and as a
+    /// `ScaleError::PhidgetError` of the rule's channel, or Load Cell 0 for
+    /// a rule of every channel, from a scale.
+    Error(ReturnCode),
+    /// Waits this long, then makes the call.
+    Latency(Duration),
+    /// Reads NaN: as the weight, as the rule's channel of the raw readings
+    /// (every channel for a rule of every channel), or as the ratio. Calls
+    /// that read neither are made as usual.
+    NaN,
+}
+
+/// One fault of a [`FaultSchedule`]: which calls it hits, and what it does
+/// to them.
+///
+/// A rule counts the calls it matches, of its method on its channel, from 1,
+/// and only hits those numbered within its [`calls`](Self::calls); the rest
+/// go through, so a rule of calls 3 to 5 fails three calls and then lets
+/// every call succeed again.
+#[cfg(feature = "hardware")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaultRule {
+    pub fault: Fault,
+    /// `None` for every method.
+    pub method: Option<FaultMethod>,
+    /// `None` for every channel.
+    pub channel: Option<usize>,
+    /// The first and last matching calls hit.
+    pub first: u64,
+    pub last: u64,
+}
+
+#[cfg(feature = "hardware")]
+impl FaultRule {
+    /// A rule hitting every call with `fault`.
+    pub fn new(fault: Fault) -> Self {
+        Self {
+            fault,
+            method: None,
+            channel: None,
+            first: 1,
+            last: u64::MAX,
+        }
+    }
+
+    pub fn error(code: ReturnCode) -> Self {
+        Self::new(Fault::Error(code))
+    }
+
+    pub fn latency(latency: Duration) -> Self {
+        Self::new(Fault::Latency(latency))
+    }
+
+    pub fn nan() -> Self {
+        Self::new(Fault::NaN)
+    }
+
+    /// Hits only calls of `method`.
+    pub fn on(self, method: FaultMethod) -> Self {
+        Self {
+            method: Some(method),
+            ..self
+        }
+    }
+
+    /// Hits only calls on `channel`.
+    pub fn on_channel(self, channel: usize) -> Self {
+        Self {
+            channel: Some(channel),
+            ..self
+        }
+    }
+
+    /// Hits only the matching calls numbered within `calls`, counting from 1.
+    pub fn calls(self, calls: impl RangeBounds<u64>) -> Self {
+        let first = match calls.start_bound() {
+            Bound::Included(&first) => first,
+            Bound::Excluded(&first) => first.saturating_add(1),
+            Bound::Unbounded => 1,
+        };
+        let last = match calls.end_bound() {
+            Bound::Included(&last) => last,
+            Bound::Excluded(&last) => last.saturating_sub(1),
+            Bound::Unbounded => u64::MAX,
+        };
+        Self {
+            first,
+            last,
+            ..self
+        }
+    }
+
+    /// Whether the rule applies to a call of `method` on `channel`, `None`
+    /// being every channel.
+    fn matches(&self, method: FaultMethod, channel: Option<usize>) -> bool {
+        self.method.is_none_or(|m| m == method)
+            && match (self.channel, channel) {
+                (Some(rule), Some(channel)) => rule == channel,
+                _ => true,
+            }
+    }
+}
+
+/// Line `line` of a fault schedule's TOML could not be read.
+#[cfg(feature = "hardware")]
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("Line {line} of the fault schedule is invalid: {message}")]
+pub struct FaultScheduleError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// The faults a [`FaultyScale`] injects, in order.
+///
+/// Every rule matching a call counts it. Of the rules that hit it, every
+/// latency is waited, and then the first error or NaN is injected.
+#[cfg(feature = "hardware")]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FaultSchedule {
+    pub rules: Vec<FaultRule>,
+}
+
+#[cfg(feature = "hardware")]
+impl FaultSchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_rule(mut self, rule: FaultRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Reads a schedule from TOML: a `[[fault]]` table for each rule, with
+    ///
+    /// - one of `error`, a return code by name such as `"NotAttached"` or by
+    ///   number, `latency_ms`, or `nan = true`, for the [`Fault`];
+    /// - optionally `method`, by [`FaultMethod::name`], `channel`, and
+    ///   `first` and `last`, the calls hit.
+    ///
+    /// ```toml
+    /// # Fail calls 3 to 5 on channel 2 with NotAttached, then succeed.
+    /// [[fault]]
+    /// method = "ratio"
+    /// channel = 2
+    /// first = 3
+    /// last = 5
+    /// error = "NotAttached"
+    /// ```
+    ///
+    /// Other keys and tables are refused.
+    pub fn from_toml(toml: &str) -> Result<Self, FaultScheduleError> {
+        let file: FaultFile =
+            read_toml(toml).map_err(|(line, message)| FaultScheduleError { line, message })?;
+        let rules = file
+            .fault
+            .iter()
+            .map(|table| rule_from_toml(toml, table))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { rules })
+    }
+}
+
+#[cfg(feature = "hardware")]
+impl FromStr for FaultSchedule {
+    type Err = FaultScheduleError;
+
+    fn from_str(toml: &str) -> Result<Self, Self::Err> {
+        Self::from_toml(toml)
+    }
+}
+
+/// A fault schedule as written in TOML.
+#[cfg(feature = "hardware")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FaultFile {
+    #[serde(default)]
+    fault: Vec<Spanned<FaultTable>>,
+}
+
+#[cfg(feature = "hardware")]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FaultTable {
+    /// By name or by number.
+    error: Option<Spanned<toml::Value>>,
+    latency_ms: Option<Spanned<f64>>,
+    nan: Option<Spanned<bool>>,
+    method: Option<Spanned<String>>,
+    channel: Option<Spanned<u64>>,
+    first: Option<u64>,
+    last: Option<u64>,
+}
+
+/// The rule of a `[[fault]]` table of `toml`.
+#[cfg(feature = "hardware")]
+fn rule_from_toml(
+    toml: &str,
+    table: &Spanned<FaultTable>,
+) -> Result<FaultRule, FaultScheduleError> {
+    let invalid = |span: Range<usize>, message: String| FaultScheduleError {
+        line: toml_line(toml, span),
+        message,
+    };
+    let keys = table.get_ref();
+    let mut faults = Vec::new();
+    if let Some(error) = &keys.error {
+        let code = match error.get_ref() {
+            toml::Value::String(name) => return_code(name)
+                .ok_or_else(|| invalid(error.span(), format!("unknown return code {name:?}")))?,
+            toml::Value::Integer(code) => u32::try_from(*code)
+                .ok()
+                .filter(|code| (1..=61).contains(code))
+                .map(ReturnCode::from)
+                .ok_or_else(|| invalid(error.span(), format!("unknown return code {code}")))?,
+            other => {
+                return Err(invalid(
+                    error.span(),
+                    format!("`error` must be a return code, not {}", other.type_str()),
+                ))
+            }
+        };
+        faults.push((error.span(), Fault::Error(code)));
+    }
+    if let Some(ms) = &keys.latency_ms {
+        let latency = Duration::try_from_secs_f64(ms.get_ref() / 1000.)
+            .map_err(|_| invalid(ms.span(), format!("{} ms is not a latency", ms.get_ref())))?;
+        faults.push((ms.span(), Fault::Latency(latency)));
+    }
+    if let Some(nan) = &keys.nan {
+        if !nan.get_ref() {
+            return Err(invalid(nan.span(), "`nan` can only be true".into()));
+        }
+        faults.push((nan.span(), Fault::NaN));
+    }
+    // The second fault given is the one too many.
+    faults.sort_by_key(|(span, _)| span.start);
+    let mut faults = faults.into_iter();
+    let Some((_, fault)) = faults.next() else {
+        return Err(invalid(
+            table.span(),
+            "the fault has none of `error`, `latency_ms` or `nan`".into(),
+        ));
+    };
+    if let Some((span, _)) = faults.next() {
+        return Err(invalid(
+            span,
+            "a fault has one of `error`, `latency_ms` or `nan`".into(),
+        ));
+    }
+
+    let mut rule = FaultRule::new(fault);
+    if let Some(name) = &keys.method {
+        rule.method = Some(
+            FaultMethod::ALL
+                .into_iter()
+                .find(|method| method.name() == name.get_ref())
+                .ok_or_else(|| {
+                    invalid(name.span(), format!("unknown method {:?}", name.get_ref()))
+                })?,
+        );
+    }
+    if let Some(channel) = &keys.channel {
+        rule.channel = Some(
+            usize::try_from(*channel.get_ref())
+                .ok()
+                .filter(|channel| *channel < NUMBER_OF_INPUTS)
+                .ok_or_else(|| {
+                    invalid(
+                        channel.span(),
+                        format!("there is no channel {}", channel.get_ref()),
+                    )
+                })?,
+        );
+    }
+    rule.first = keys.first.unwrap_or(rule.first);
+    rule.last = keys.last.unwrap_or(rule.last);
+    Ok(rule)
+}
+
+/// The return code named `name`, as in `ReturnCode`'s variants.
+#[cfg(feature = "hardware")]
+fn return_code(name: &str) -> Option<ReturnCode> {
+    (1..=61)
+        .map(ReturnCode::from)
+        .find(|code| format!("{code:?}") == name)
+}
+
+/// How often each rule of a [`FaultSchedule`] matched a call, and hit one.
+#[cfg(feature = "hardware")]
+struct FaultCounts {
+    matched: Vec<u64>,
+    fired: Vec<u64>,
+}
+
+/// The counters of a [`FaultyScale`] and of the others sharing its schedule,
+/// by rule in schedule order. Clones share them.
+#[cfg(feature = "hardware")]
+#[derive(Clone)]
+pub struct FaultLog {
+    counts: Arc<Mutex<FaultCounts>>,
+}
+
+#[cfg(feature = "hardware")]
+impl FaultLog {
+    fn new(rules: usize) -> Self {
+        Self {
+            counts: Arc::new(Mutex::new(FaultCounts {
+                matched: vec![0; rules],
+                fired: vec![0; rules],
+            })),
+        }
+    }
+
+    /// Calls the rule numbered `rule` matched so far.
+    pub fn matched(&self, rule: usize) -> u64 {
+        self.counts().matched.get(rule).copied().unwrap_or(0)
+    }
+
+    /// Times the fault of the rule numbered `rule` was injected so far.
+    pub fn fired(&self, rule: usize) -> u64 {
+        self.counts().fired.get(rule).copied().unwrap_or(0)
+    }
+
+    /// Faults injected so far, of every rule.
+    pub fn total_fired(&self) -> u64 {
+        self.counts().fired.iter().sum()
+    }
+
+    fn counts(&self) -> MutexGuard<'_, FaultCounts> {
+        self.counts.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// What a [`FaultyScale`] does with a call after any latency.
+#[cfg(feature = "hardware")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Injection {
+    Pass,
+    Fail(ReturnCode, Option<usize>),
+    NaN(Option<usize>),
+}
+
+/// A [`Scale`] or a [`VoltageSource`] with faults injected into its calls
+/// at the points a [`FaultSchedule`] scripts, for testing what is built on
+/// it: retries, circuit breakers and the like.
+///
+/// Wrapping a scale, rules of any channel match: a scale's calls are on
+/// every channel. Wrapping the sources of a bridge with
+/// [`bridge`](Self::bridge), each source only matches rules of its own
+/// channel or of every channel, and the sources count calls together.
+#[cfg(feature = "hardware")]
+pub struct FaultyScale<S> {
+    inner: S,
+    channel: Option<usize>,
+    rules: Arc<[FaultRule]>,
+    log: FaultLog,
+}
+
+#[cfg(feature = "hardware")]
+impl<S> FaultyScale<S> {
+    pub fn new(inner: S, schedule: FaultSchedule) -> Self {
+        Self {
+            inner,
+            channel: None,
+            log: FaultLog::new(schedule.rules.len()),
+            rules: schedule.rules.into(),
+        }
+    }
+
+    /// Wraps the source of every load cell of a bridge, sharing `schedule`.
+    pub fn bridge(
+        sources: [S; NUMBER_OF_INPUTS],
+        schedule: FaultSchedule,
+    ) -> [Self; NUMBER_OF_INPUTS] {
+        let log = FaultLog::new(schedule.rules.len());
+        let rules: Arc<[FaultRule]> = schedule.rules.into();
+        let mut channel = 0;
+        sources.map(|inner| {
+            channel += 1;
+            Self {
+                inner,
+                channel: Some(channel - 1),
+                rules: rules.clone(),
+                log: log.clone(),
+            }
+        })
+    }
+
+    /// The counters, to keep hold of after moving the scale away.
+    pub fn log(&self) -> FaultLog {
+        self.log.clone()
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Counts a call of `method` against the schedule, waits out any latency,
+    /// and says what to do with it.
+    fn inject(&self, method: FaultMethod) -> Injection {
+        let mut latency = Duration::ZERO;
+        let mut injection = Injection::Pass;
+        {
+            let mut counts = self.log.counts();
+            for (index, rule) in self.rules.iter().enumerate() {
+                if !rule.matches(method, self.channel) {
+                    continue;
+                }
+                counts.matched[index] += 1;
+                let call = counts.matched[index];
+                if call < rule.first || call > rule.last {
+                    continue;
+                }
+                let channel = rule.channel.or(self.channel);
+                match rule.fault {
+                    Fault::Latency(wait) => latency += wait,
+                    Fault::Error(code) if injection == Injection::Pass => {
+                        injection = Injection::Fail(code, channel)
+                    }
+                    Fault::NaN
+                        if injection == Injection::Pass
+                            && matches!(
+                                method,
+                                FaultMethod::GetWeight
+                                    | FaultMethod::GetRawReadings
+                                    | FaultMethod::GetRawMedians
+                                    | FaultMethod::Ratio
+                            ) =>
+                    {
+                        injection = Injection::NaN(channel)
+                    }
+                    _ => continue,
+                }
+                counts.fired[index] += 1;
+            }
+        }
+        if !latency.is_zero() {
+            thread::sleep(latency);
+        }
+        injection
+    }
+
+    /// Injects into a scale call, failing it with a [`ScaleError`].
+    fn inject_scale(&self, method: FaultMethod) -> Result<Injection, ScaleError> {
+        match self.inject(method) {
+            Injection::Fail(code, channel) => {
+                Err(ScaleError::phidget_error(code, channel.unwrap_or(0)))
+            }
+            injection => Ok(injection),
+        }
+    }
+
+    /// Injects into a source call that reads nothing.
+    fn inject_source(&self, method: FaultMethod) -> phidget::Result<()> {
+        match self.inject(method) {
+            Injection::Fail(code, _) => Err(code),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// `ratios` with NaN on `channel`, or on every channel for `None`.
+#[cfg(feature = "hardware")]
+fn with_nan(
+    mut ratios: [f64; NUMBER_OF_INPUTS],
+    channel: Option<usize>,
+) -> [f64; NUMBER_OF_INPUTS] {
+    for (n, ratio) in ratios.iter_mut().enumerate() {
+        if channel.is_none_or(|channel| channel == n) {
+            *ratio = f64::NAN;
+        }
+    }
+    ratios
+}
+
+/// Medians are taken from [`get_weight`](Scale::get_weight)s of this scale,
+/// so that faults hit each sample.
+#[cfg(feature = "hardware")]
+impl<S: Scale> Scale for FaultyScale<S> {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        match self.inject_scale(FaultMethod::GetWeight)? {
+            Injection::NaN(_) => Ok(Grams(f64::NAN)),
+            _ => self.inner.get_weight(),
+        }
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_median_weight_of(DEFAULT_MEDIAN_SAMPLES)
+    }
+
+    fn tare(&mut self, samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.inject_scale(FaultMethod::Tare)?;
+        self.inner.tare(samples)
+    }
+
+    fn zero(
+        &mut self,
+        samples: usize,
+    ) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        self.inject_scale(FaultMethod::Zero)?;
+        self.inner.zero(samples)
+    }
+
+    fn get_raw_readings(
+        &self,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        let injection = self.inject_scale(FaultMethod::GetRawReadings)?;
+        let ratios = self.inner.get_raw_readings()?;
+        match injection {
+            Injection::NaN(channel) => Ok(with_nan(ratios, channel)),
+            _ => Ok(ratios),
+        }
+    }
+
+    fn get_raw_medians(
+        &self,
+        samples: usize,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        let injection = self.inject_scale(FaultMethod::GetRawMedians)?;
+        let ratios = self.inner.get_raw_medians(samples)?;
+        match injection {
+            Injection::NaN(channel) => Ok(with_nan(ratios, channel)),
+            _ => Ok(ratios),
+        }
+    }
+
+    fn calibration(&self) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        self.inject_scale(FaultMethod::Calibration)?;
+        self.inner.calibration()
+    }
+
+    fn set_calibration(
+        &mut self,
+        calibration: Calibration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.inject_scale(FaultMethod::SetCalibration)?;
+        self.inner.set_calibration(calibration)
+    }
+
+    fn status(&mut self) -> Result<ScaleStatus, Box<dyn std::error::Error + Send + Sync>> {
+        self.inject_scale(FaultMethod::Status)?;
+        self.inner.status()
+    }
+
+    fn data_interval(&mut self) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+        self.inner.data_interval()
+    }
+
+    fn set_data_interval(
+        &mut self,
+        interval: Duration,
+    ) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+        self.inner.set_data_interval(interval)
+    }
+
+    fn stale_channel(&self) -> Option<StaleChannel> {
+        self.inner.stale_channel()
+    }
+
+    fn clock(&self) -> Arc<dyn Clock> {
+        self.inner.clock()
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.inject_scale(FaultMethod::Close)?;
+        Scale::close(&mut self.inner)
+    }
+}
+
+/// Ratio changes are passed on to the callback untouched.
+#[cfg(feature = "hardware")]
+impl<V: VoltageSource> VoltageSource for FaultyScale<V> {
+    fn ratio(&self) -> phidget::Result<f64> {
+        match self.inject(FaultMethod::Ratio) {
+            Injection::Fail(code, _) => Err(code),
+            Injection::NaN(_) => self.inner.ratio().map(|_| f64::NAN),
+            Injection::Pass => self.inner.ratio(),
+        }
+    }
+
+    fn set_data_interval(&mut self, interval: Duration) -> phidget::Result<()> {
+        self.inject_source(FaultMethod::SetDataInterval)?;
+        self.inner.set_data_interval(interval)
+    }
+
+    fn data_interval(&mut self) -> phidget::Result<Duration> {
+        self.inject_source(FaultMethod::DataInterval)?;
+        self.inner.data_interval()
+    }
+
+    fn min_data_interval(&mut self) -> phidget::Result<Duration> {
+        self.inner.min_data_interval()
+    }
+
+    fn max_data_interval(&mut self) -> phidget::Result<Duration> {
+        self.inner.max_data_interval()
+    }
+
+    fn set_bridge_gain(&mut self, gain: BridgeGain) -> phidget::Result<()> {
+        self.inner.set_bridge_gain(gain)
+    }
+
+    fn bridge_gain(&mut self) -> phidget::Result<BridgeGain> {
+        self.inner.bridge_gain()
+    }
+
+    fn serial_number(&mut self) -> phidget::Result<i32> {
+        self.inject_source(FaultMethod::SerialNumber)?;
+        self.inner.serial_number()
+    }
+
+    fn is_attached(&mut self) -> phidget::Result<bool> {
+        self.inject_source(FaultMethod::IsAttached)?;
+        self.inner.is_attached()
+    }
+
+    fn close(&mut self) -> phidget::Result<()> {
+        self.inject_source(FaultMethod::Close)?;
+        VoltageSource::close(&mut self.inner)
+    }
+
+    fn on_ratio_change(&mut self, callback: Box<dyn Fn(f64) + Send + Sync>) -> phidget::Result<()> {
+        self.inner.on_ratio_change(callback)
+    }
+}
+
+/// Options for a [`Loopback`].
+#[cfg(feature = "net")]
+#[derive(Clone, Debug)]
+pub struct LoopbackConfig {
+    pub server: ServerConfig,
+    /// How long each piece of a write takes to arrive, in either direction.
+    pub latency: Duration,
+    /// Largest piece a write arrives in, so that readers see messages in
+    /// fragments. `None` passes on whatever was read at once.
+    pub fragment: Option<usize>,
+    /// Bytes each direction buffers before a writer has to wait.
+    pub capacity: usize,
+}
+
+#[cfg(feature = "net")]
+impl Default for LoopbackConfig {
+    fn default() -> Self {
+        Self {
+            server: ServerConfig::default(),
+            latency: Duration::ZERO,
+            fragment: None,
+            capacity: 64 * 1024,
+        }
+    }
+}
+
+/// A server for the JSON-lines protocol of
+/// [`serve_tcp`](crate::net::serve_tcp), reached over in-memory streams
+/// instead of sockets, for testing clients against a real server without the
+/// network.
+///
+/// Each [`connect`](Self::connect) opens a connection of its own, served as
+/// a TCP connection would be. Bytes pass between the two ends through a
+/// relay that delays and fragments them as configured, and that
+/// [`disconnect`](Self::disconnect) cuts, as a pulled cable would.
+///
+/// Dropping the loopback cuts every connection.
+#[cfg(feature = "net")]
+pub struct Loopback {
+    handle: ScaleHandle,
+    config: LoopbackConfig,
+    /// Cancelled to cut the connections open now.
+    cut: Arc<Mutex<CancellationToken>>,
+}
+
+/// A [`Loopback`] server answering with the scale behind `handle`. Must be
+/// called within a tokio runtime.
+#[cfg(feature = "net")]
+pub fn loopback(handle: ScaleHandle, config: LoopbackConfig) -> Loopback {
+    Loopback {
+        handle,
+        config,
+        cut: Arc::new(Mutex::new(CancellationToken::new())),
+    }
+}
+
+#[cfg(feature = "net")]
+impl Loopback {
+    /// Opens a connection, returning the client's end of it.
+    pub fn connect(&self) -> DuplexStream {
+        open(&self.handle, &self.config, &self.cut)
+    }
+
+    /// A [`ScaleClient`] that connects through the loopback, and connects
+    /// again whenever its connection is cut.
+    pub fn client(&self, config: ClientConfig) -> ScaleClient {
+        let handle = self.handle.clone();
+        let loopback = self.config.clone();
+        let cut = self.cut.clone();
+        ScaleClient::new(
+            move || std::future::ready(Ok(open(&handle, &loopback, &cut))),
+            config,
+        )
+    }
+
+    /// Cuts every connection open now: reads from the client's end see the
+    /// connection close, writes to it fail, and whatever was on its way is
+    /// lost. Connections opened afterwards are not affected.
+    pub fn disconnect(&self) {
+        let mut cut = self.cut.lock().unwrap_or_else(PoisonError::into_inner);
+        cut.cancel();
+        *cut = CancellationToken::new();
+    }
+}
+
+#[cfg(feature = "net")]
+impl Drop for Loopback {
+    fn drop(&mut self) {
+        self.cut
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .cancel();
+    }
+}
+
+/// Serves a new connection and relays between it and the returned end.
+#[cfg(feature = "net")]
+fn open(
+    handle: &ScaleHandle,
+    config: &LoopbackConfig,
+    cut: &Mutex<CancellationToken>,
+) -> DuplexStream {
+    let cut = cut
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .child_token();
+    let (client, relay_client) = tokio::io::duplex(config.capacity);
+    let (relay_server, server) = tokio::io::duplex(config.capacity);
+    tokio::spawn(serve_connection(
+        Fleet::One(handle.clone()),
+        server,
+        config.server.clone(),
+        cut.clone(),
+    ));
+    let (latency, fragment) = (config.latency, config.fragment);
+    tokio::spawn(async move {
+        let (client_read, client_write) = tokio::io::split(relay_client);
+        let (server_read, server_write) = tokio::io::split(relay_server);
+        let relay = async {
+            tokio::join!(
+                relay(client_read, server_write, latency, fragment),
+                relay(server_read, client_write, latency, fragment),
+            )
+        };
+        // Dropping the relay's ends closes the connection on both sides.
+        tokio::select! {
+            _ = relay => {}
+            () = cut.cancelled() => {}
+        }
+    });
+    client
+}
+
+/// Copies `from` to `to`, `fragment` bytes at most at a time, each `latency`
+/// after the last, until `from` closes.
+#[cfg(feature = "net")]
+async fn relay(
+    mut from: impl AsyncRead + Unpin,
+    mut to: impl AsyncWrite + Unpin,
+    latency: Duration,
+    fragment: Option<usize>,
+) {
+    let mut buffer = vec![0; 8 * 1024];
+    while let Ok(read @ 1..) = from.read(&mut buffer).await {
+        for piece in buffer[..read].chunks(fragment.unwrap_or(read).max(1)) {
+            if !latency.is_zero() {
+                tokio::time::sleep(latency).await;
+            }
+            if to.write_all(piece).await.is_err() {
+                return;
+            }
+        }
+    }
+    let _ = to.shutdown().await;
+}
@@ -0,0 +1,89 @@
+use std::future::Future;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::{AsyncScale, AsyncScaleError, Grams, MedianGrams};
+
+/// Timeout used by [`TimeoutScale::new`].
+pub const DEFAULT_SCALE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Error, Debug)]
+pub enum TimeoutScaleError {
+    #[error("Scale did not respond within {0:?}")]
+    Elapsed(Duration),
+
+    #[error("{0}")]
+    Scale(#[source] AsyncScaleError),
+}
+
+/// Puts a deadline on every call to an [`AsyncScale`].
+///
+/// The `_timeout` methods take the deadline per call; the `AsyncScale` impl
+/// uses the default set once at construction. A call that runs out of time
+/// fails with [`TimeoutScaleError::Elapsed`], which through the `AsyncScale`
+/// impl arrives boxed and can be recovered with
+/// `err.downcast_ref::<TimeoutScaleError>()`.
+pub struct TimeoutScale<S> {
+    scale: S,
+    timeout: Duration,
+}
+
+impl<S: AsyncScale> TimeoutScale<S> {
+    pub fn new(scale: S) -> Self {
+        Self {
+            scale,
+            timeout: DEFAULT_SCALE_TIMEOUT,
+        }
+    }
+
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self { timeout, ..self }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.scale
+    }
+
+    pub fn into_inner(self) -> S {
+        self.scale
+    }
+
+    pub async fn get_weight_timeout(&self, timeout: Duration) -> Result<Grams, TimeoutScaleError> {
+        within(timeout, self.scale.get_weight()).await
+    }
+
+    pub async fn get_median_weight_timeout(
+        &self,
+        samples: usize,
+        timeout: Duration,
+    ) -> Result<MedianGrams, TimeoutScaleError> {
+        within(timeout, self.scale.get_median_weight(samples)).await
+    }
+}
+
+async fn within<T>(
+    timeout: Duration,
+    future: impl Future<Output = Result<T, AsyncScaleError>>,
+) -> Result<T, TimeoutScaleError> {
+    tokio::time::timeout(timeout, future)
+        .await
+        .map_err(|_| TimeoutScaleError::Elapsed(timeout))?
+        .map_err(TimeoutScaleError::Scale)
+}
+
+impl<S: AsyncScale + Sync> AsyncScale for TimeoutScale<S> {
+    async fn get_weight(&self) -> Result<Grams, AsyncScaleError> {
+        Ok(self.get_weight_timeout(self.timeout).await?)
+    }
+
+    async fn get_median_weight(&self, samples: usize) -> Result<MedianGrams, AsyncScaleError> {
+        Ok(self
+            .get_median_weight_timeout(samples, self.timeout)
+            .await?)
+    }
+}
@@ -0,0 +1,27 @@
+/// The `tracing` target of every span and event the crate emits.
+///
+/// Span and event names, and their fields, are stable: they may gain fields,
+/// but none below will be renamed or removed without a major release.
+/// Durations are whole milliseconds.
+///
+/// | Span | Level | Fields | Around |
+/// |------|-------|--------|--------|
+/// | `connect` | info | `phidget_id` (when given), `timeout_ms` | Opening a phidget's channels. An error closes it with an `error` event. |
+/// | `get_weight` | debug | | One weight read from a `ConnectedScale`. |
+/// | `get_median_weight` | debug | `samples`, `interval_ms` | A blocking or async median read, whatever the scale. |
+/// | `tare`, `zero` | info | `samples`, `interval_ms` | Taring or zeroing a `ConnectedScale`. |
+/// | `calibrate` | info | `reference_masses` | A guided calibration run. |
+/// | `command` | debug | `command`, `correlation_id` (when known), `lane` | The scale actor running one command. `command` is its `ScaleCmd::name`; `lane` is `normal` or `priority`. |
+/// | `reconnect` | info | `attempt` | One attempt by a `ScaleClient` to connect, counting failures in a row from 1. |
+///
+/// | Event | Level | Fields | When |
+/// |-------|-------|--------|------|
+/// | `sample_failed` | warn | `sample`, `error` | A sample of a median or batch failed, ending the read. `sample` counts from 0. |
+/// | `overrun` | warn | `capacity`, `dropped` | A weight stream's buffer was full and a sample was lost. `dropped` is the total so far. |
+/// | `watchdog_tripped` | warn | `channel`, `age_ms` | The actor's periodic sampling found a load cell frozen. |
+/// | `overload` | warn | `outcome`, `queue_depth` | The actor's queue was full: `outcome` is `refused` for the new command or `evicted` for the oldest one. |
+/// | `calibration_expired` | warn | `age_ms`, `action` | A `ConnectedScale` read a weight with a calibration older than its policy allows, the first time since the policy or calibration time was set. `action` is `Warn` or `Block`. |
+/// | `calibration_future_dated` | warn | `ahead_ms` | Likewise, with a calibration dated `ahead_ms` after the system clock. |
+/// | `setting_mismatch` | warn | `key`, `channel`, `requested`, `applied` | `ScaleConfig::connect` read back a setting a channel did not take as asked, such as a data interval clamped by the bridge. |
+/// | `reconnect_failed` | warn | `attempt`, `error`, `delay_ms` | A connection attempt by a `ScaleClient` or the MQTT publisher failed, and the next is due after `delay_ms`. |
+pub const TARGET: &str = "libra";
@@ -0,0 +1,487 @@
+use std::future::Future;
+use std::io::{self, BufRead, Read, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::UnixStream as StdUnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio_util::sync::CancellationToken;
+
+use crate::actor::ScaleHandle;
+use crate::calibration::Calibration;
+use crate::cancel::CancelFlag;
+use crate::manager::ScaleManager;
+use crate::net::{end_line, read_line, serve_connection, Fleet, Line, ServerConfig};
+use crate::scale::{ScaleError, DEFAULT_MEDIAN_SAMPLES, NUMBER_OF_INPUTS};
+use crate::{
+    AsyncScale, AsyncScaleError, Grams, MedianGrams, Scale, ScaleCmd, ScaleErrorInfo,
+    ScaleErrorKind, ScaleResponse, ScaleStatus, StampedWeight, PROTOCOL_VERSION,
+};
+
+/// Permissions of the socket file: read and write for the owner and group.
+pub const DEFAULT_SOCKET_MODE: u32 = 0o660;
+/// The longest line a [`UnixScaleClient`] reads from the server, in bytes.
+const MAX_RESPONSE_LENGTH: usize = 1024 * 1024;
+
+/// Serves the scale command protocol on a Unix domain socket at `path`, the
+/// same way [`serve_tcp`](crate::net::serve_tcp) does over TCP.
+///
+/// A socket file left behind by a server that is no longer running is
+/// removed first; if another server is still listening on it, this fails
+/// with `AddrInUse`, and a file that is not a socket is never touched. The
+/// socket file is removed again when the server stops, which it does once the
+/// actor shuts down (for example on `ScaleCmd::Shutdown`) or when its future
+/// is dropped.
+pub fn serve_unix(
+    handle: ScaleHandle,
+    path: impl AsRef<Path>,
+) -> impl Future<Output = io::Result<()>> {
+    serve_unix_with_config(handle, path, DEFAULT_SOCKET_MODE, ServerConfig::default())
+}
+
+/// Like [`serve_unix`], creating the socket with permissions `mode` and
+/// serving with the options in `config`.
+pub async fn serve_unix_with_config(
+    handle: ScaleHandle,
+    path: impl AsRef<Path>,
+    mode: u32,
+    config: ServerConfig,
+) -> io::Result<()> {
+    serve_unix_fleet(Fleet::One(handle), path.as_ref().to_owned(), mode, config).await
+}
+
+/// Like [`serve_unix_with_config`], answering for every scale of `manager`
+/// the way [`serve_fleet`](crate::net::serve_fleet) does, until the future
+/// is dropped.
+pub fn serve_fleet_unix<S: Scale + Send + 'static>(
+    manager: &ScaleManager<S>,
+    path: impl AsRef<Path>,
+    mode: u32,
+    config: ServerConfig,
+) -> impl Future<Output = io::Result<()>> + Send + 'static {
+    serve_unix_fleet(
+        Fleet::Many(manager.directory()),
+        path.as_ref().to_owned(),
+        mode,
+        config,
+    )
+}
+
+async fn serve_unix_fleet(
+    fleet: Fleet,
+    path: PathBuf,
+    mode: u32,
+    config: ServerConfig,
+) -> io::Result<()> {
+    let path = path.as_path();
+    remove_stale_socket(path)?;
+    let listener = UnixListener::bind(path)?;
+    let _remove_socket = RemoveOnDrop(path.to_owned());
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+
+    let stop = CancellationToken::new();
+    let _close_connections = stop.clone().drop_guard();
+    loop {
+        tokio::select! {
+            () = fleet.stopped() => return Ok(()),
+            accepted = listener.accept() => {
+                // A failed accept only loses that one connection.
+                if let Ok((stream, _)) = accepted {
+                    tokio::spawn(serve_connection(
+                        fleet.clone(),
+                        stream,
+                        config.clone(),
+                        stop.clone(),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+        Ok(metadata) if !metadata.file_type().is_socket() => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ));
+        }
+        Ok(_) => {}
+    }
+    match StdUnixStream::connect(path) {
+        Ok(_) => Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("A server is already listening on {}", path.display()),
+        )),
+        Err(_) => std::fs::remove_file(path),
+    }
+}
+
+struct RemoveOnDrop(PathBuf);
+
+impl Drop for RemoveOnDrop {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// A scale served by [`serve_unix`] in another process, usable anywhere a
+/// local [`Scale`] or [`AsyncScale`] is.
+///
+/// Connects on first use and reconnects as needed, so the client outlives
+/// server restarts. A command that fails on a connection that had already
+/// been used is sent once more on a fresh one; this means a command can run
+/// twice if the server dies while running it.
+///
+/// The blocking `Scale` methods and the async `AsyncScale` methods use
+/// separate connections.
+///
+/// Heartbeats from the server are skipped.
+///
+/// Servers older than the client are handled where possible: see
+/// [`hello`](Self::hello) and [`get_weight_batch`](Self::get_weight_batch).
+pub struct UnixScaleClient {
+    path: PathBuf,
+    blocking: Mutex<Option<io::BufReader<StdUnixStream>>>,
+    connection: tokio::sync::Mutex<Option<BufReader<UnixStream>>>,
+    /// Set once the server has turned down `ScaleCmd::GetWeightBatch`.
+    batch_unsupported: AtomicBool,
+}
+
+/// What a server said about itself in answer to `ScaleCmd::Hello`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerInfo {
+    /// The server's `PROTOCOL_VERSION`, or 0 for a server that predates the
+    /// handshake.
+    pub version: u32,
+    /// Names of the commands the server understands. Empty for a server that
+    /// predates the handshake, which had no way to say.
+    pub supported_commands: Vec<String>,
+}
+
+impl UnixScaleClient {
+    /// A client for the server at `path`. Nothing is connected until the
+    /// first command.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            blocking: Mutex::new(None),
+            connection: tokio::sync::Mutex::new(None),
+            batch_unsupported: AtomicBool::new(false),
+        }
+    }
+
+    /// Exchanges protocol versions with the server.
+    pub async fn hello(&self) -> Result<ServerInfo, AsyncScaleError> {
+        let hello = ScaleCmd::Hello {
+            client_version: PROTOCOL_VERSION,
+        };
+        match self.request(&hello).await? {
+            ScaleResponse::HelloAck {
+                server_version,
+                supported_commands,
+            } => Ok(ServerInfo {
+                version: server_version,
+                supported_commands,
+            }),
+            response if is_unknown_command(&response) => Ok(ServerInfo {
+                version: 0,
+                supported_commands: Vec::new(),
+            }),
+            response => Err(failure(response)),
+        }
+    }
+
+    /// `count` readings taken `interval` apart, as `ScaleCmd::GetWeightBatch`
+    /// would return them.
+    ///
+    /// A server too old to know `GetWeightBatch` is sent `count` single reads
+    /// instead, paced and stamped here, so the readings arrive less evenly
+    /// spaced. The client remembers and goes straight to single reads from
+    /// then on.
+    pub async fn get_weight_batch(
+        &self,
+        count: usize,
+        interval: Duration,
+    ) -> Result<Vec<StampedWeight>, AsyncScaleError> {
+        if !self.batch_unsupported.load(Ordering::Relaxed) {
+            let batch = ScaleCmd::GetWeightBatch {
+                count,
+                interval_ms: interval.as_millis().try_into().unwrap_or(u64::MAX),
+            };
+            match self.request(&batch).await? {
+                ScaleResponse::WeightBatch(batch) => return Ok(batch),
+                response if is_unknown_command(&response) => {
+                    self.batch_unsupported.store(true, Ordering::Relaxed);
+                }
+                response => return Err(failure(response)),
+            }
+        }
+        let mut batch = Vec::with_capacity(count);
+        for sequence in 0..count as u64 {
+            if sequence > 0 {
+                tokio::time::sleep(interval).await;
+            }
+            batch.push(StampedWeight {
+                weight: AsyncScale::get_weight(self).await?,
+                sequence,
+                timestamp: SystemTime::now(),
+            });
+        }
+        Ok(batch)
+    }
+
+    /// Sends `cmd` and waits for the server's response. Cancel safe: a
+    /// request dropped before its response arrives closes its connection, so
+    /// the response is never taken for that of the next request.
+    pub async fn request(&self, cmd: &ScaleCmd) -> io::Result<ScaleResponse> {
+        let mut connection = self.connection.lock().await;
+        // Out of the slot until answered, so that a request dropped midway
+        // leaves nothing behind.
+        let stream = connection.take();
+        let reused = stream.is_some();
+        let (response, stream) = match self.exchange(stream, cmd).await {
+            Err(_) if reused => self.exchange(None, cmd).await,
+            result => result,
+        }?;
+        *connection = Some(stream);
+        Ok(response)
+    }
+
+    /// Sends `cmd` on `stream`, or on a new connection, and returns the
+    /// response with the connection to use next.
+    async fn exchange(
+        &self,
+        stream: Option<BufReader<UnixStream>>,
+        cmd: &ScaleCmd,
+    ) -> io::Result<(ScaleResponse, BufReader<UnixStream>)> {
+        let mut stream = match stream {
+            Some(stream) => stream,
+            None => BufReader::new(UnixStream::connect(&self.path).await?),
+        };
+        stream.get_mut().write_all(&encode(cmd)?).await?;
+        loop {
+            match decode(read_line(&mut stream, MAX_RESPONSE_LENGTH).await?)? {
+                ScaleResponse::Heartbeat { .. } => continue,
+                response => return Ok((response, stream)),
+            }
+        }
+    }
+
+    /// Blocking form of [`request`](Self::request).
+    pub fn request_blocking(&self, cmd: &ScaleCmd) -> io::Result<ScaleResponse> {
+        let mut connection = self.blocking.lock().unwrap_or_else(PoisonError::into_inner);
+        let reused = connection.is_some();
+        match self.exchange_blocking(&mut connection, cmd) {
+            Err(_) if reused => self.exchange_blocking(&mut connection, cmd),
+            result => result,
+        }
+    }
+
+    fn exchange_blocking(
+        &self,
+        connection: &mut Option<io::BufReader<StdUnixStream>>,
+        cmd: &ScaleCmd,
+    ) -> io::Result<ScaleResponse> {
+        let stream = match connection {
+            Some(stream) => stream,
+            None => connection.insert(io::BufReader::new(StdUnixStream::connect(&self.path)?)),
+        };
+        let result = (|| {
+            stream.get_mut().write_all(&encode(cmd)?)?;
+            loop {
+                let mut line = Vec::new();
+                let limit = MAX_RESPONSE_LENGTH as u64 + 1;
+                let line = match stream.by_ref().take(limit).read_until(b'\n', &mut line)? {
+                    0 => Line::Closed,
+                    _ => end_line(line, MAX_RESPONSE_LENGTH)?,
+                };
+                match decode(line)? {
+                    ScaleResponse::Heartbeat { .. } => continue,
+                    response => return Ok(response),
+                }
+            }
+        })();
+        if result.is_err() {
+            *connection = None;
+        }
+        result
+    }
+}
+
+fn encode(cmd: &ScaleCmd) -> io::Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(cmd).map_err(io::Error::other)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+fn decode(line: Line) -> io::Result<ScaleResponse> {
+    match line {
+        Line::Read(line) => {
+            serde_json::from_str(&line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        }
+        Line::TooLong => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Response is over {MAX_RESPONSE_LENGTH} bytes"),
+        )),
+        Line::Closed => Err(io::ErrorKind::UnexpectedEof.into()),
+    }
+}
+
+/// Whether the server could not make sense of the command because it is
+/// older than the client. Servers from before `ScaleResponse::Unsupported`
+/// answered unknown commands as undecodable.
+fn is_unknown_command(response: &ScaleResponse) -> bool {
+    match response {
+        ScaleResponse::Unsupported { .. } => true,
+        ScaleResponse::Error(info) => info.kind == ScaleErrorKind::InvalidCommand,
+        _ => false,
+    }
+}
+
+/// The error for a response that does not answer the command with a result,
+/// as a `ScaleError` so that it can be handled like a local scale's.
+fn failure(response: ScaleResponse) -> AsyncScaleError {
+    let info = match response {
+        ScaleResponse::Error(info) => info,
+        ScaleResponse::InternalError(message) => {
+            ScaleErrorInfo::new(ScaleErrorKind::Other, message)
+        }
+        ScaleResponse::ShuttingDown => {
+            ScaleErrorInfo::new(ScaleErrorKind::Stopped, "Scale is shutting down")
+        }
+        ScaleResponse::Unsupported { command } => ScaleErrorInfo::new(
+            ScaleErrorKind::Unsupported,
+            format!("{command} is not supported by the server"),
+        ),
+        ScaleResponse::UnknownScale { name, available } => ScaleErrorInfo::new(
+            ScaleErrorKind::InvalidArgument,
+            format!("The server has no scale {name:?}, only {available:?}"),
+        ),
+        response => ScaleErrorInfo::new(
+            ScaleErrorKind::Other,
+            format!("Unexpected response {response:?}"),
+        ),
+    };
+    Box::new(ScaleError::from(info))
+}
+
+impl Scale for UnixScaleClient {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        match self.request_blocking(&ScaleCmd::GetWeight)? {
+            ScaleResponse::Weight(weight) => Ok(weight),
+            response => Err(failure(response)),
+        }
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_median_weight_of(DEFAULT_MEDIAN_SAMPLES)
+    }
+
+    /// Taken by the server in one command rather than sample by sample.
+    fn get_median_weight_of(
+        &self,
+        samples: usize,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        match self.request_blocking(&ScaleCmd::GetMedianWeight { samples })? {
+            ScaleResponse::MedianWeight(median) => Ok(median),
+            response => Err(failure(response)),
+        }
+    }
+
+    /// Checks `cancel` only before sending: the server takes the median in
+    /// one command, which cannot be stopped once sent.
+    fn get_median_weight_cancellable(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        if cancel.is_cancelled() {
+            return Err(Box::new(ScaleError::Cancelled { collected: 0 }));
+        }
+        self.get_median_weight_of(samples)
+    }
+
+    fn tare(&mut self, samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        match self.request_blocking(&ScaleCmd::Tare { samples })? {
+            ScaleResponse::Tared(tare) => Ok(tare),
+            response => Err(failure(response)),
+        }
+    }
+
+    fn zero(
+        &mut self,
+        samples: usize,
+    ) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        match self.request_blocking(&ScaleCmd::Zero { samples })? {
+            ScaleResponse::Zeroed(calibration) => Ok(calibration),
+            response => Err(failure(response)),
+        }
+    }
+
+    fn get_raw_readings(
+        &self,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        match self.request_blocking(&ScaleCmd::GetRawReadings)? {
+            ScaleResponse::RawReadings(readings) => Ok(readings),
+            response => Err(failure(response)),
+        }
+    }
+
+    fn get_raw_medians(
+        &self,
+        samples: usize,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        match self.request_blocking(&ScaleCmd::GetRawMedians { samples })? {
+            ScaleResponse::RawMedians(medians) => Ok(medians),
+            response => Err(failure(response)),
+        }
+    }
+
+    fn calibration(&self) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        match self.request_blocking(&ScaleCmd::GetCalibration)? {
+            ScaleResponse::Calibration(calibration) => Ok(calibration),
+            response => Err(failure(response)),
+        }
+    }
+
+    fn set_calibration(
+        &mut self,
+        calibration: Calibration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match self.request_blocking(&ScaleCmd::SetCalibration(calibration))? {
+            ScaleResponse::CalibrationSet => Ok(()),
+            response => Err(failure(response)),
+        }
+    }
+
+    fn status(&mut self) -> Result<ScaleStatus, Box<dyn std::error::Error + Send + Sync>> {
+        match self.request_blocking(&ScaleCmd::GetStatus)? {
+            ScaleResponse::Status(status) => Ok(status),
+            response => Err(failure(response)),
+        }
+    }
+}
+
+impl AsyncScale for UnixScaleClient {
+    async fn get_weight(&self) -> Result<Grams, AsyncScaleError> {
+        match self.request(&ScaleCmd::GetWeight).await? {
+            ScaleResponse::Weight(weight) => Ok(weight),
+            response => Err(failure(response)),
+        }
+    }
+
+    async fn get_median_weight(&self, samples: usize) -> Result<MedianGrams, AsyncScaleError> {
+        match self.request(&ScaleCmd::GetMedianWeight { samples }).await? {
+            ScaleResponse::MedianWeight(median) => Ok(median),
+            response => Err(failure(response)),
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::calibration::Calibration;
+use crate::cancel::CancelFlag;
+use crate::clock::{Clock, SystemClock};
+use crate::sampling::collect_median;
+use crate::scale::{ScaleError, DEFAULT_MEDIAN_SAMPLES, DEFAULT_SAMPLE_INTERVAL, NUMBER_OF_INPUTS};
+use crate::{Grams, MedianGrams, Scale, ScaleStatus};
+
+/// How long a [`Watchdog::default`] lets a load cell go without changing.
+pub const DEFAULT_WATCHDOG_WINDOW: Duration = Duration::from_secs(60);
+/// Change in voltage ratio a [`Watchdog::default`] counts as movement. Well
+/// below the noise of a live bridge, so only a stream that repeats itself
+/// exactly looks frozen.
+pub const DEFAULT_WATCHDOG_EPSILON: f64 = 1e-9;
+
+/// A load cell whose readings have not changed within the watchdog's window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct StaleChannel {
+    pub channel: usize,
+    /// How long ago its reading last changed.
+    pub age: Duration,
+}
+
+/// Tells when a load cell's readings have frozen, as they do when the
+/// bridge's firmware locks up and keeps returning its last ratio.
+///
+/// A frozen stream looks perfectly settled, so nothing downstream notices on
+/// its own. The watchdog remembers, per load cell, the reading at its last
+/// change and when that was; a reading that moves more than `epsilon` from it
+/// is a change. A load cell that has not changed within `window` is stale.
+#[derive(Clone)]
+pub struct Watchdog {
+    window: Duration,
+    epsilon: f64,
+    strict: bool,
+    changes: [Option<(f64, Instant)>; NUMBER_OF_INPUTS],
+    clock: Arc<dyn Clock>,
+}
+
+impl Watchdog {
+    pub fn new(window: Duration, epsilon: f64) -> Self {
+        Self {
+            window,
+            epsilon,
+            strict: false,
+            changes: [None; NUMBER_OF_INPUTS],
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Tells the time of changes by `clock` rather than the real clock.
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Makes [`check`](Self::check), and so every read through a
+    /// [`WatchdogScale`], fail with `ScaleError::StaleData` while a load
+    /// cell is stale.
+    pub fn strict(self) -> Self {
+        Self {
+            strict: true,
+            ..self
+        }
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Notes the voltage ratio of each load cell at the time of calling.
+    pub fn observe(&mut self, readings: &[f64; NUMBER_OF_INPUTS]) {
+        let now = self.clock.now();
+        for (change, &reading) in self.changes.iter_mut().zip(readings) {
+            match change {
+                Some((last, _)) if (reading - *last).abs() <= self.epsilon => {}
+                _ => *change = Some((reading, now)),
+            }
+        }
+    }
+
+    /// How long ago the reading of `channel` last changed, or `None` before
+    /// anything has been observed.
+    pub fn time_since_last_change(&self, channel: usize) -> Option<Duration> {
+        let (_, changed) = (*self.changes.get(channel)?)?;
+        Some(self.clock.now().saturating_duration_since(changed))
+    }
+
+    /// The first load cell that has gone longer than the window without
+    /// changing.
+    pub fn stale_channel(&self) -> Option<StaleChannel> {
+        (0..NUMBER_OF_INPUTS).find_map(|channel| {
+            let age = self.time_since_last_change(channel)?;
+            (age > self.window).then_some(StaleChannel { channel, age })
+        })
+    }
+
+    /// In strict mode, fails with `ScaleError::StaleData` for the first
+    /// stale load cell. Always succeeds otherwise.
+    pub fn check(&self) -> Result<(), ScaleError> {
+        match self.stale_channel() {
+            Some(StaleChannel { channel, age }) if self.strict => {
+                Err(ScaleError::StaleData { channel, age })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Forgets every observation, as after reconnecting.
+    pub fn reset(&mut self) {
+        self.changes = [None; NUMBER_OF_INPUTS];
+    }
+}
+
+impl fmt::Debug for Watchdog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watchdog")
+            .field("window", &self.window)
+            .field("epsilon", &self.epsilon)
+            .field("strict", &self.strict)
+            .field("changes", &self.changes)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new(DEFAULT_WATCHDOG_WINDOW, DEFAULT_WATCHDOG_EPSILON)
+    }
+}
+
+/// Runs a [`Watchdog`] over every weight read from a scale.
+///
+/// Each weight read hands the watchdog the raw readings it was weighed from,
+/// through `Scale::get_weight_observed`, so the scale must support raw
+/// readings. Medians are taken
+/// here, one watched weight read per sample, `sample_interval` apart. The
+/// scale actor's periodic sampling reports a stale load cell through
+/// `Scale::stale_channel`, strict or not.
+pub struct WatchdogScale<S> {
+    scale: S,
+    watchdog: Mutex<Watchdog>,
+    sample_interval: Duration,
+}
+
+impl<S: Scale> WatchdogScale<S> {
+    pub fn new(scale: S, watchdog: Watchdog) -> Self {
+        Self {
+            scale,
+            watchdog: Mutex::new(watchdog),
+            sample_interval: DEFAULT_SAMPLE_INTERVAL,
+        }
+    }
+
+    /// Spacing between the samples of a median.
+    pub fn with_sample_interval(self, sample_interval: Duration) -> Self {
+        Self {
+            sample_interval,
+            ..self
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.scale
+    }
+
+    pub fn into_inner(self) -> S {
+        self.scale
+    }
+
+    pub fn watchdog(&self) -> MutexGuard<'_, Watchdog> {
+        self.watchdog.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// How long ago the reading of `channel` last changed. See
+    /// [`Watchdog::time_since_last_change`].
+    pub fn time_since_last_change(&self, channel: usize) -> Option<Duration> {
+        self.watchdog().time_since_last_change(channel)
+    }
+
+    fn watch(&self, readings: &[f64; NUMBER_OF_INPUTS]) -> Result<(), ScaleError> {
+        let mut watchdog = self.watchdog();
+        watchdog.observe(readings);
+        watchdog.check()
+    }
+}
+
+impl<S: Scale> Scale for WatchdogScale<S> {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.scale
+            .get_weight_observed(&mut |readings| self.watch(readings))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_median_weight_of(DEFAULT_MEDIAN_SAMPLES)
+    }
+
+    fn get_median_weight_cancellable(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        collect_median(samples, self.sample_interval, cancel, || self.get_weight())
+    }
+
+    fn get_median_weight_yielding(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+        between_samples: &mut dyn FnMut(),
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        collect_median(samples, self.sample_interval, cancel, || {
+            between_samples();
+            self.get_weight()
+        })
+    }
+
+    fn tare(&mut self, samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.scale.tare(samples)
+    }
+
+    fn zero(
+        &mut self,
+        samples: usize,
+    ) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        self.scale.zero(samples)
+    }
+
+    fn get_raw_readings(
+        &self,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        let readings = self.scale.get_raw_readings()?;
+        self.watch(&readings)?;
+        Ok(readings)
+    }
+
+    fn get_raw_medians(
+        &self,
+        samples: usize,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        self.scale.get_raw_medians(samples)
+    }
+
+    fn calibration(&self) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        self.scale.calibration()
+    }
+
+    fn set_calibration(
+        &mut self,
+        calibration: Calibration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.scale.set_calibration(calibration)
+    }
+
+    fn status(&mut self) -> Result<ScaleStatus, Box<dyn std::error::Error + Send + Sync>> {
+        self.scale.status()
+    }
+
+    fn data_interval(&mut self) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+        self.scale.data_interval()
+    }
+
+    fn set_data_interval(
+        &mut self,
+        interval: Duration,
+    ) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+        self.scale.set_data_interval(interval)
+    }
+
+    fn stale_channel(&self) -> Option<StaleChannel> {
+        self.watchdog().stale_channel()
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.scale.close()
+    }
+}
@@ -0,0 +1,108 @@
+#![cfg(feature = "tokio")]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use libra::actor::spawn_scale_actor;
+use libra::{Grams, MedianGrams, Scale, ScaleCmd, ScaleResponse};
+
+/// Returns increasing weights and records how many reads it served.
+#[derive(Default)]
+struct MockScale {
+    reads: Arc<AtomicUsize>,
+    delay: Duration,
+}
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        std::thread::sleep(self.delay);
+        Ok(Grams(self.reads.fetch_add(1, Ordering::SeqCst) as f64))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_median_weight_of(5)
+    }
+}
+
+#[tokio::test]
+async fn get_weight_and_median() {
+    let (handle, task) = spawn_scale_actor(MockScale::default());
+
+    match handle.send(ScaleCmd::GetWeight).await {
+        ScaleResponse::Weight(w) => assert_eq!(w, Grams(0.)),
+        other => panic!("unexpected response {other:?}"),
+    }
+    match handle.send(ScaleCmd::GetMedianWeight { samples: 5 }).await {
+        // Reads 1..=5, median is the third.
+        ScaleResponse::MedianWeight(w) => assert_eq!(w, MedianGrams(3.)),
+        other => panic!("unexpected response {other:?}"),
+    }
+
+    assert!(matches!(
+        handle.send(ScaleCmd::Shutdown).await,
+        ScaleResponse::ShutdownAck
+    ));
+    let scale = task.await.unwrap().expect("actor returns the scale");
+    assert_eq!(scale.reads.load(Ordering::SeqCst), 6);
+}
+
+#[tokio::test]
+async fn concurrent_senders() {
+    let reads = Arc::new(AtomicUsize::new(0));
+    let (handle, task) = spawn_scale_actor(MockScale {
+        reads: Arc::clone(&reads),
+        delay: Duration::from_millis(1),
+    });
+
+    let senders: Vec<_> = (0..10)
+        .map(|_| {
+            let handle = handle.clone();
+            tokio::spawn(async move {
+                for _ in 0..10 {
+                    assert!(matches!(
+                        handle.send(ScaleCmd::GetWeight).await,
+                        ScaleResponse::Weight(_)
+                    ));
+                }
+            })
+        })
+        .collect();
+    for sender in senders {
+        sender.await.unwrap();
+    }
+    // Back-to-back GetWeights share a read, so there may be fewer than 100.
+    let reads = reads.load(Ordering::SeqCst);
+    assert!((1..=100).contains(&reads));
+
+    handle.send(ScaleCmd::Shutdown).await;
+    task.await.unwrap();
+}
+
+#[tokio::test]
+async fn send_after_shutdown_is_refused() {
+    let (handle, task) = spawn_scale_actor(MockScale::default());
+    assert!(matches!(
+        handle.send(ScaleCmd::Shutdown).await,
+        ScaleResponse::ShutdownAck
+    ));
+    task.await.unwrap().unwrap();
+    assert!(matches!(
+        handle.send(ScaleCmd::GetWeight).await,
+        ScaleResponse::Error(_)
+    ));
+}
+
+#[tokio::test]
+async fn dropping_every_handle_stops_the_actor() {
+    let (handle, task) = spawn_scale_actor(MockScale::default());
+    let other = handle.clone();
+    drop(handle);
+    other.send(ScaleCmd::GetWeight).await;
+    drop(other);
+    let scale = tokio::time::timeout(Duration::from_secs(1), task)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(scale.is_some());
+}
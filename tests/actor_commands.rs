@@ -0,0 +1,202 @@
+#![cfg(feature = "tokio")]
+
+use std::sync::{Arc, Mutex};
+
+use libra::actor::spawn_scale_actor;
+use libra::calibration::Calibration;
+use libra::scale::NUMBER_OF_INPUTS;
+use libra::{Grams, MedianGrams, Scale, ScaleCmd, ScaleErrorKind, ScaleResponse, ScaleStatus};
+
+/// Four load cells sharing `load`, weighed the way `ConnectedScale` does.
+struct MockScale {
+    load: Arc<Mutex<f64>>,
+    calibration: Calibration,
+    tare: f64,
+}
+
+impl MockScale {
+    fn new(load: f64) -> Self {
+        Self {
+            load: Arc::new(Mutex::new(load)),
+            calibration: Calibration {
+                offset: 0.,
+                coefficients: [1.; NUMBER_OF_INPUTS],
+            },
+            tare: 0.,
+        }
+    }
+
+    fn cells(&self) -> [f64; NUMBER_OF_INPUTS] {
+        [*self.load.lock().unwrap() / 4.; NUMBER_OF_INPUTS]
+    }
+
+    fn gross(&self) -> f64 {
+        let sum: f64 = self
+            .cells()
+            .iter()
+            .zip(self.calibration.coefficients)
+            .map(|(r, c)| r * c)
+            .sum();
+        sum - self.calibration.offset
+    }
+}
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(self.gross() - self.tare))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+
+    fn tare(&mut self, _samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.tare = self.gross();
+        Ok(Grams(self.tare))
+    }
+
+    fn zero(
+        &mut self,
+        _samples: usize,
+    ) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        self.calibration.offset += self.gross();
+        self.tare = 0.;
+        Ok(self.calibration)
+    }
+
+    fn get_raw_readings(
+        &self,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.cells())
+    }
+
+    fn get_raw_medians(
+        &self,
+        _samples: usize,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.cells())
+    }
+
+    fn calibration(&self) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.calibration)
+    }
+
+    fn set_calibration(
+        &mut self,
+        calibration: Calibration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.calibration = calibration;
+        Ok(())
+    }
+
+    fn status(&mut self) -> Result<ScaleStatus, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ScaleStatus {
+            phidget_id: 1,
+            attached: true,
+            calibration: self.calibration,
+            tare: Grams(self.tare),
+            calibration_expires_in_days: None,
+        })
+    }
+}
+
+#[tokio::test]
+async fn tare_subtracts_the_container() {
+    let scale = MockScale::new(200.);
+    let load = Arc::clone(&scale.load);
+    let (handle, _task) = spawn_scale_actor(scale);
+
+    let response = handle.send(ScaleCmd::Tare { samples: 5 }).await;
+    assert_eq!(response, ScaleResponse::Tared(Grams(200.)));
+    *load.lock().unwrap() = 300.;
+    assert_eq!(
+        handle.send(ScaleCmd::GetWeight).await,
+        ScaleResponse::Weight(Grams(100.))
+    );
+
+    let ScaleResponse::Status(status) = handle.send(ScaleCmd::GetStatus).await else {
+        panic!("expected a status");
+    };
+    assert_eq!(status.tare, Grams(200.));
+    assert_eq!(status.calibration.offset, 0.);
+}
+
+#[tokio::test]
+async fn zero_corrects_the_offset_and_clears_the_tare() {
+    let scale = MockScale::new(40.);
+    let load = Arc::clone(&scale.load);
+    let (handle, _task) = spawn_scale_actor(scale);
+
+    handle.send(ScaleCmd::Tare { samples: 5 }).await;
+    let ScaleResponse::Zeroed(calibration) = handle.send(ScaleCmd::Zero { samples: 5 }).await
+    else {
+        panic!("expected the new calibration");
+    };
+    assert_eq!(calibration.offset, 40.);
+    *load.lock().unwrap() = 140.;
+    assert_eq!(
+        handle.send(ScaleCmd::GetWeight).await,
+        ScaleResponse::Weight(Grams(100.))
+    );
+}
+
+#[tokio::test]
+async fn calibration_round_trips_through_the_actor() {
+    let (handle, _task) = spawn_scale_actor(MockScale::new(100.));
+    let calibration = Calibration {
+        offset: 5.,
+        coefficients: [2.; NUMBER_OF_INPUTS],
+    };
+
+    assert_eq!(
+        handle.send(ScaleCmd::SetCalibration(calibration)).await,
+        ScaleResponse::CalibrationSet
+    );
+    assert_eq!(
+        handle.send(ScaleCmd::GetCalibration).await,
+        ScaleResponse::Calibration(calibration)
+    );
+    assert_eq!(
+        handle.send(ScaleCmd::GetWeight).await,
+        ScaleResponse::Weight(Grams(195.))
+    );
+    assert_eq!(
+        handle.send(ScaleCmd::GetRawReadings).await,
+        ScaleResponse::RawReadings([25.; NUMBER_OF_INPUTS])
+    );
+    assert_eq!(
+        handle.send(ScaleCmd::GetRawMedians { samples: 3 }).await,
+        ScaleResponse::RawMedians([25.; NUMBER_OF_INPUTS])
+    );
+}
+
+#[tokio::test]
+async fn scales_without_the_feature_say_so() {
+    struct ReadOnly;
+    impl Scale for ReadOnly {
+        fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+            Ok(Grams(1.))
+        }
+
+        fn get_median_weight(
+            &self,
+        ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+            Ok(MedianGrams(1.))
+        }
+    }
+
+    let (handle, _task) = spawn_scale_actor(ReadOnly);
+    for cmd in [
+        ScaleCmd::Tare { samples: 1 },
+        ScaleCmd::Zero { samples: 1 },
+        ScaleCmd::GetRawReadings,
+        ScaleCmd::GetCalibration,
+        ScaleCmd::GetStatus,
+    ] {
+        let response = handle.send(cmd.clone()).await;
+        let ScaleResponse::Error(error) = response else {
+            panic!("{cmd:?} answered with {response:?}");
+        };
+        assert_eq!(error.kind, ScaleErrorKind::Unsupported);
+    }
+}
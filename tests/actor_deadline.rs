@@ -0,0 +1,142 @@
+#![cfg(feature = "tokio")]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use libra::actor::{spawn_scale_actor, ScaleHandle};
+use libra::cancel::CancelFlag;
+use libra::testing::{MockScale, TokioClock};
+use libra::{Grams, MedianGrams, Scale, ScaleCmd, ScaleResponse};
+use tokio::time::Instant;
+
+const READ_TIME: Duration = Duration::from_millis(50);
+
+/// Each read takes `READ_TIME` of tokio's paused time; medians take one read
+/// per sample. Weights count the reads before them.
+struct SlowScale {
+    reads: Arc<AtomicUsize>,
+    mock: MockScale,
+}
+
+impl SlowScale {
+    fn new(reads: &Arc<AtomicUsize>) -> Self {
+        Self {
+            reads: Arc::clone(reads),
+            mock: MockScale::new(Grams(0.))
+                .with_latency(READ_TIME)
+                .with_clock(Arc::new(TokioClock::new())),
+        }
+    }
+}
+
+impl Scale for SlowScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.mock.get_weight()?;
+        Ok(Grams(self.reads.fetch_add(1, Ordering::SeqCst) as f64))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+
+    fn get_median_weight_cancellable(
+        &self,
+        samples: usize,
+        cancel: &CancelFlag,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.mock.get_median_weight_cancellable(samples, cancel)
+    }
+}
+
+fn spawn_send(handle: &ScaleHandle, cmd: ScaleCmd) -> tokio::task::JoinHandle<ScaleResponse> {
+    let handle = handle.clone();
+    tokio::spawn(async move { handle.send(cmd).await })
+}
+
+#[tokio::test(start_paused = true)]
+async fn stale_commands_expire() {
+    let reads = Arc::new(AtomicUsize::new(0));
+    let (handle, _task) = spawn_scale_actor(SlowScale::new(&reads));
+    let median = spawn_send(&handle, ScaleCmd::GetMedianWeight { samples: 4 });
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    // Stale long before the median finishes.
+    let deadline = Instant::now() + READ_TIME;
+    let stale = handle.send_with_deadline(ScaleCmd::GetWeight, deadline);
+    let fresh = handle.send_with_deadline(
+        ScaleCmd::GetWeightBatch {
+            count: 1,
+            interval_ms: 0,
+        },
+        Instant::now() + READ_TIME * 10,
+    );
+    let (stale, fresh) = tokio::join!(stale, fresh);
+
+    assert!(matches!(stale, ScaleResponse::Expired));
+    assert!(matches!(fresh, ScaleResponse::WeightBatch(_)));
+    assert!(matches!(
+        median.await.unwrap(),
+        ScaleResponse::MedianWeight(_)
+    ));
+    assert_eq!(reads.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn queued_duplicates_share_one_read() {
+    let reads = Arc::new(AtomicUsize::new(0));
+    let (handle, _task) = spawn_scale_actor(SlowScale::new(&reads));
+    let median = spawn_send(&handle, ScaleCmd::GetMedianWeight { samples: 2 });
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    let weights: Vec<_> = (0..5)
+        .map(|_| spawn_send(&handle, ScaleCmd::GetWeight))
+        .collect();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert_eq!(handle.stats().queued, 5);
+
+    for weight in weights {
+        assert!(matches!(
+            weight.await.unwrap(),
+            ScaleResponse::Weight(Grams(0.))
+        ));
+    }
+    median.await.unwrap();
+    assert_eq!(reads.load(Ordering::SeqCst), 1);
+    assert_eq!(handle.stats().queued, 0);
+}
+
+#[tokio::test(start_paused = true)]
+async fn different_commands_are_not_collapsed() {
+    let reads = Arc::new(AtomicUsize::new(0));
+    let (handle, _task) = spawn_scale_actor(SlowScale::new(&reads));
+    let median = spawn_send(&handle, ScaleCmd::GetMedianWeight { samples: 2 });
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    let first = spawn_send(&handle, ScaleCmd::GetWeight);
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    let batch = spawn_send(
+        &handle,
+        ScaleCmd::GetWeightBatch {
+            count: 1,
+            interval_ms: 0,
+        },
+    );
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    let second = spawn_send(&handle, ScaleCmd::GetWeight);
+
+    assert!(matches!(
+        first.await.unwrap(),
+        ScaleResponse::Weight(Grams(0.))
+    ));
+    assert!(matches!(
+        batch.await.unwrap(),
+        ScaleResponse::WeightBatch(_)
+    ));
+    assert!(matches!(
+        second.await.unwrap(),
+        ScaleResponse::Weight(Grams(2.))
+    ));
+    median.await.unwrap();
+    assert_eq!(reads.load(Ordering::SeqCst), 3);
+}
@@ -0,0 +1,165 @@
+#![cfg(feature = "tokio")]
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use libra::actor::{spawn_scale_actor, ScaleHandle};
+use libra::cancel::CancelFlag;
+use libra::shared::SharedScale;
+use libra::testing::{MockScale, TokioClock};
+use libra::{Grams, MedianGrams, Scale, ScaleCmd, ScaleResponse};
+use tokio::time::Instant;
+
+const SAMPLE_TIME: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Call {
+    Weight,
+    Median(usize),
+}
+
+/// Every read, and every sample of a median, takes `SAMPLE_TIME` of tokio's
+/// paused time.
+struct SlowScale {
+    calls: Arc<Mutex<Vec<Call>>>,
+    mock: MockScale,
+}
+
+impl SlowScale {
+    fn new(calls: &Arc<Mutex<Vec<Call>>>) -> Self {
+        Self {
+            calls: Arc::clone(calls),
+            mock: MockScale::new(Grams(1.))
+                .with_latency(SAMPLE_TIME)
+                .with_clock(Arc::new(TokioClock::new())),
+        }
+    }
+}
+
+impl Scale for SlowScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.calls.lock().unwrap().push(Call::Weight);
+        self.mock.get_weight()
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+
+    fn get_median_weight_yielding(
+        &self,
+        samples: usize,
+        _cancel: &CancelFlag,
+        between_samples: &mut dyn FnMut(),
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.calls.lock().unwrap().push(Call::Median(samples));
+        for _ in 0..samples {
+            between_samples();
+            self.mock.get_weight()?;
+        }
+        Ok(MedianGrams(samples as f64))
+    }
+}
+
+fn spawn_send(handle: &ScaleHandle, cmd: ScaleCmd) -> tokio::task::JoinHandle<ScaleResponse> {
+    let handle = handle.clone();
+    tokio::spawn(async move { handle.send(cmd).await })
+}
+
+fn spawn_send_priority(
+    handle: &ScaleHandle,
+    cmd: ScaleCmd,
+) -> tokio::task::JoinHandle<ScaleResponse> {
+    let handle = handle.clone();
+    tokio::spawn(async move { handle.send_priority(cmd).await })
+}
+
+#[tokio::test(start_paused = true)]
+async fn priority_read_interrupts_long_median() {
+    let (handle, _task) = spawn_scale_actor(SlowScale::new(&Arc::default()));
+    let median = spawn_send(&handle, ScaleCmd::GetMedianWeight { samples: 30 });
+    tokio::time::sleep(SAMPLE_TIME * 2).await;
+
+    let start = Instant::now();
+    let response = handle.send_priority(ScaleCmd::GetWeight).await;
+    let waited = start.elapsed();
+    assert!(matches!(response, ScaleResponse::Weight(_)));
+    // At most one sample to finish, then the read itself.
+    assert!(waited <= SAMPLE_TIME * 2, "priority read took {waited:?}");
+    assert!(!median.is_finished());
+
+    // The median resumes and completes with all its samples.
+    assert!(matches!(
+        median.await.unwrap(),
+        ScaleResponse::MedianWeight(MedianGrams(30.))
+    ));
+}
+
+#[tokio::test(start_paused = true)]
+async fn priority_read_interrupts_median_over_shared_scale() {
+    let calls = Arc::default();
+    let (handle, _task) = spawn_scale_actor(SharedScale::new(SlowScale::new(&calls)));
+    let median = spawn_send(&handle, ScaleCmd::GetMedianWeight { samples: 30 });
+    tokio::time::sleep(SAMPLE_TIME * 2).await;
+
+    let start = Instant::now();
+    let response = handle.send_priority(ScaleCmd::GetWeight).await;
+    let waited = start.elapsed();
+    assert!(matches!(response, ScaleResponse::Weight(_)));
+    assert!(waited <= SAMPLE_TIME * 2, "priority read took {waited:?}");
+    assert!(!median.is_finished());
+    assert!(matches!(
+        median.await.unwrap(),
+        ScaleResponse::MedianWeight(MedianGrams(30.))
+    ));
+    assert_eq!(*calls.lock().unwrap(), [Call::Median(30), Call::Weight]);
+}
+
+#[tokio::test(start_paused = true)]
+async fn priority_jumps_the_queue() {
+    let calls = Arc::default();
+    let (handle, _task) = spawn_scale_actor(SlowScale::new(&calls));
+    let busy = spawn_send(&handle, ScaleCmd::GetWeight);
+    tokio::time::sleep(SAMPLE_TIME / 5).await;
+    let normal = spawn_send(&handle, ScaleCmd::GetMedianWeight { samples: 1 });
+    tokio::time::sleep(SAMPLE_TIME / 10).await;
+    let priority = spawn_send_priority(&handle, ScaleCmd::GetMedianWeight { samples: 2 });
+
+    for task in [busy, normal, priority] {
+        task.await.unwrap();
+    }
+    assert_eq!(
+        *calls.lock().unwrap(),
+        [Call::Weight, Call::Median(2), Call::Median(1)]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn normal_lane_is_not_starved() {
+    let calls = Arc::default();
+    let (handle, _task) = spawn_scale_actor(SlowScale::new(&calls));
+    let busy = spawn_send(&handle, ScaleCmd::GetWeight);
+    tokio::time::sleep(SAMPLE_TIME / 5).await;
+    let normal = spawn_send(&handle, ScaleCmd::GetMedianWeight { samples: 20 });
+    tokio::time::sleep(SAMPLE_TIME / 10).await;
+    let priority: Vec<_> = (1..=6)
+        .map(|samples| spawn_send_priority(&handle, ScaleCmd::GetMedianWeight { samples }))
+        .collect();
+    tokio::time::sleep(SAMPLE_TIME / 10).await;
+    busy.await.unwrap();
+    for task in priority {
+        task.await.unwrap();
+    }
+    normal.abort();
+
+    let calls = calls.lock().unwrap();
+    let normal_at = calls
+        .iter()
+        .position(|call| *call == Call::Median(20))
+        .expect("normal command ran");
+    // The busy read, then a bounded streak of priority commands.
+    assert!(
+        normal_at <= 5,
+        "normal command ran at {normal_at}: {calls:?}"
+    );
+}
@@ -0,0 +1,109 @@
+#![cfg(feature = "tokio")]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use libra::actor::{spawn_scale_actor_with_config, ActorConfig, ScaleHandle, ShutdownPolicy};
+use libra::{Grams, MedianGrams, Scale, ScaleCmd, ScaleResponse};
+use tokio::task::JoinHandle;
+
+const READ_TIME: Duration = Duration::from_millis(10);
+
+#[derive(Default)]
+struct Counters {
+    reads: AtomicUsize,
+    closes: AtomicUsize,
+}
+
+struct MockScale(Arc<Counters>);
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        std::thread::sleep(READ_TIME);
+        Ok(Grams(self.0.reads.fetch_add(1, Ordering::SeqCst) as f64))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.0.closes.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+fn actor(policy: ShutdownPolicy) -> (ScaleHandle, JoinHandle<Option<MockScale>>, Arc<Counters>) {
+    let counters = Arc::new(Counters::default());
+    let (handle, task) = spawn_scale_actor_with_config(
+        MockScale(Arc::clone(&counters)),
+        ActorConfig {
+            shutdown_policy: policy,
+            ..Default::default()
+        },
+    );
+    (handle, task, counters)
+}
+
+/// Starts a 50-sample median, queues a weight read behind it, then shuts
+/// down once the median is a few samples in.
+async fn shutdown_during_long_median(
+    policy: ShutdownPolicy,
+) -> (ScaleResponse, ScaleResponse, Arc<Counters>) {
+    let (handle, task, counters) = actor(policy);
+    let median = tokio::spawn({
+        let handle = handle.clone();
+        async move { handle.send(ScaleCmd::GetMedianWeight { samples: 50 }).await }
+    });
+    tokio::time::sleep(READ_TIME * 5).await;
+    let queued = tokio::spawn({
+        let handle = handle.clone();
+        async move { handle.send(ScaleCmd::GetWeight).await }
+    });
+    tokio::time::sleep(READ_TIME).await;
+
+    tokio::time::timeout(Duration::from_secs(5), handle.shutdown())
+        .await
+        .expect("shutdown completes");
+    assert!(task.await.unwrap().is_some());
+    (median.await.unwrap(), queued.await.unwrap(), counters)
+}
+
+#[tokio::test]
+async fn finish_policy_completes_in_flight_median() {
+    let (median, queued, counters) =
+        shutdown_during_long_median(ShutdownPolicy::FinishInFlight).await;
+    assert!(matches!(median, ScaleResponse::MedianWeight(_)));
+    assert!(matches!(queued, ScaleResponse::ShuttingDown));
+    assert_eq!(counters.reads.load(Ordering::SeqCst), 50);
+    assert_eq!(counters.closes.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn cancel_policy_abandons_in_flight_median() {
+    let (median, queued, counters) =
+        shutdown_during_long_median(ShutdownPolicy::CancelInFlight).await;
+    assert!(matches!(median, ScaleResponse::ShuttingDown));
+    assert!(matches!(queued, ScaleResponse::ShuttingDown));
+    assert!(counters.reads.load(Ordering::SeqCst) < 50);
+    assert_eq!(counters.closes.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn shutdown_is_idempotent() {
+    let (handle, task, counters) = actor(ShutdownPolicy::default());
+    let (first, second) = tokio::join!(
+        handle.send(ScaleCmd::Shutdown),
+        handle.send(ScaleCmd::Shutdown)
+    );
+    assert!(matches!(first, ScaleResponse::ShutdownAck));
+    assert!(matches!(second, ScaleResponse::ShutdownAck));
+    handle.shutdown().await;
+    assert!(matches!(
+        handle.send(ScaleCmd::Shutdown).await,
+        ScaleResponse::ShutdownAck
+    ));
+    task.await.unwrap();
+    assert_eq!(counters.closes.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,97 @@
+#![cfg(feature = "tokio")]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use libra::actor::{spawn_scale_actor, spawn_scale_actor_with_config, ActorConfig};
+use libra::cancel::CancelFlag;
+use libra::{Grams, MedianGrams, Scale, ScaleCmd, ScaleResponse};
+
+/// Panics on a median of 13 samples, and on every third periodic read.
+#[derive(Default)]
+struct PanickyScale {
+    reads: Arc<AtomicUsize>,
+}
+
+impl Scale for PanickyScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        let n = self.reads.fetch_add(1, Ordering::SeqCst);
+        if n % 3 == 2 {
+            panic!("callback fired on a closed channel");
+        }
+        Ok(Grams(n as f64))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+
+    fn get_median_weight_cancellable(
+        &self,
+        samples: usize,
+        _cancel: &CancelFlag,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        if samples == 13 {
+            panic!("unlucky median");
+        }
+        Ok(MedianGrams(samples as f64))
+    }
+}
+
+#[tokio::test]
+async fn panicking_command_is_answered_and_actor_survives() {
+    let (handle, task) = spawn_scale_actor(PanickyScale::default());
+
+    let response = handle.send(ScaleCmd::GetMedianWeight { samples: 13 }).await;
+    let ScaleResponse::InternalError(message) = response else {
+        panic!("unexpected response {response:?}");
+    };
+    assert!(message.contains("unlucky median"));
+
+    assert!(matches!(
+        handle.send(ScaleCmd::GetMedianWeight { samples: 5 }).await,
+        ScaleResponse::MedianWeight(MedianGrams(5.))
+    ));
+    assert!(matches!(
+        handle.send(ScaleCmd::GetWeight).await,
+        ScaleResponse::Weight(Grams(0.))
+    ));
+
+    handle.shutdown().await;
+    assert!(task.await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn panicking_periodic_read_does_not_stop_sampling() {
+    let (handle, _task) = spawn_scale_actor_with_config(
+        PanickyScale::default(),
+        ActorConfig {
+            sample_interval: Some(Duration::from_millis(5)),
+            ..Default::default()
+        },
+    );
+    let mut rx = handle.watch_weight();
+    // Every third read panics and is skipped, so once a few samples are in
+    // the read count runs ahead of the published sequence.
+    rx.wait_for(|latest| latest.is_some_and(|s| s.sequence >= 3))
+        .await
+        .unwrap();
+    let latest = rx.borrow().unwrap();
+    assert!(latest.weight.get() > latest.sequence as f64);
+}
+
+#[tokio::test]
+async fn send_to_dead_actor_fails_immediately() {
+    let (handle, task) = spawn_scale_actor(PanickyScale::default());
+    task.abort();
+    let _ = task.await;
+
+    let response = tokio::time::timeout(Duration::from_secs(1), handle.send(ScaleCmd::GetWeight))
+        .await
+        .expect("send to a dead actor must not hang");
+    assert!(matches!(response, ScaleResponse::Error(_)));
+    tokio::time::timeout(Duration::from_secs(1), handle.stopped())
+        .await
+        .unwrap();
+}
@@ -0,0 +1,119 @@
+#![cfg(feature = "tokio")]
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use libra::actor::{spawn_scale_actor, spawn_scale_actor_with_config, ActorConfig};
+use libra::cancel::CancelFlag;
+use libra::{Grams, MedianGrams, Scale, ScaleCmd, ScaleResponse};
+use tokio::time::Instant;
+
+#[derive(Debug, PartialEq)]
+enum Call {
+    Weight,
+    Median,
+}
+
+/// Logs whether each call came from periodic sampling or a median command.
+#[derive(Default)]
+struct MockScale {
+    calls: Arc<Mutex<Vec<Call>>>,
+}
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        let mut calls = self.calls.lock().unwrap();
+        calls.push(Call::Weight);
+        Ok(Grams(calls.len() as f64))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+
+    fn get_median_weight_cancellable(
+        &self,
+        _samples: usize,
+        _cancel: &CancelFlag,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.calls.lock().unwrap().push(Call::Median);
+        Ok(MedianGrams(0.))
+    }
+}
+
+const INTERVAL: Duration = Duration::from_millis(100);
+
+fn sampling_actor(
+    calls: &Arc<Mutex<Vec<Call>>>,
+) -> (
+    libra::actor::ScaleHandle,
+    tokio::task::JoinHandle<Option<MockScale>>,
+) {
+    spawn_scale_actor_with_config(
+        MockScale {
+            calls: Arc::clone(calls),
+        },
+        ActorConfig {
+            sample_interval: Some(INTERVAL),
+            ..Default::default()
+        },
+    )
+}
+
+#[tokio::test(start_paused = true)]
+async fn publishes_at_sample_interval() {
+    let calls = Arc::default();
+    let (handle, _task) = sampling_actor(&calls);
+    let mut rx = handle.watch_weight();
+    let mut other = handle.watch_weight();
+
+    rx.changed().await.unwrap();
+    let first = Instant::now();
+    for i in 1..=5 {
+        rx.changed().await.unwrap();
+        assert_eq!(Instant::now() - first, INTERVAL * i);
+    }
+    let latest = rx.borrow().unwrap();
+    assert_eq!(latest.sequence, 5);
+    assert_eq!(latest.weight, Grams(6.));
+    // Every receiver sees the same latest value.
+    assert_eq!(other.borrow_and_update().unwrap().sequence, 5);
+    let lockfree = handle.latest_lockfree();
+    assert_eq!(lockfree.get(), Some(latest));
+    assert_eq!(lockfree.updates(), 6);
+}
+
+#[tokio::test(start_paused = true)]
+async fn commands_run_before_periodic_reads() {
+    let calls = Arc::default();
+    let (handle, _task) = sampling_actor(&calls);
+    handle.watch_weight().changed().await.unwrap();
+
+    // Distinct sample counts so the actor cannot collapse them into one run.
+    let commands = (1..=20).map(|samples| handle.send(ScaleCmd::GetMedianWeight { samples }));
+    for response in futures::future::join_all(commands).await {
+        assert!(matches!(response, ScaleResponse::MedianWeight(_)));
+    }
+    tokio::time::sleep(INTERVAL * 2).await;
+
+    let calls = calls.lock().unwrap();
+    let first_median = calls.iter().position(|c| *c == Call::Median).unwrap();
+    // All twenty medians ran back to back, with no periodic read in between.
+    assert!(calls[first_median..first_median + 20]
+        .iter()
+        .all(|c| *c == Call::Median));
+    assert_eq!(calls.last(), Some(&Call::Weight));
+}
+
+#[tokio::test(start_paused = true)]
+async fn no_publishing_without_sample_interval() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let (handle, _task) = spawn_scale_actor(MockScale {
+        calls: Arc::clone(&calls),
+    });
+    let rx = handle.watch_weight();
+    tokio::time::sleep(INTERVAL * 10).await;
+    assert!(rx.borrow().is_none());
+    assert!(handle.latest_lockfree().get().is_none());
+    assert!(calls.lock().unwrap().is_empty());
+}
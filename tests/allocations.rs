@@ -0,0 +1,110 @@
+#![cfg(feature = "hardware")]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::time::Duration;
+
+use libra::calibration::Calibration;
+use libra::cancel::CancelFlag;
+use libra::sampler::MedianSampler;
+use libra::scale::{ConnectedScale, NUMBER_OF_INPUTS};
+use libra::testing::FakeVoltageSource;
+use libra::Scale;
+
+/// The system allocator, counting the allocations made on each thread, so
+/// that tests running at the same time do not count each other's.
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// The allocations `op` makes on this thread.
+fn allocations<T>(op: impl FnOnce() -> T) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    std::hint::black_box(op());
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+fn scale() -> ConnectedScale<FakeVoltageSource> {
+    let sources = FakeVoltageSource::bridge(716_000);
+    for (source, ratio) in sources.iter().zip([1e-4, 2e-4, 3e-4, 4e-4]) {
+        source.set_ratio(ratio);
+    }
+    let calibration = Calibration {
+        offset: -10.,
+        coefficients: [1e6; NUMBER_OF_INPUTS],
+    };
+    ConnectedScale::from_sources(716_000, calibration, sources)
+}
+
+#[test]
+fn a_weight_is_read_without_allocating() {
+    let scale = scale();
+    assert_eq!(allocations(|| scale.get_weight().unwrap()), 0);
+    assert_eq!(allocations(|| Scale::get_weight(&scale).unwrap()), 0);
+    assert_eq!(allocations(|| scale.get_raw_readings().unwrap()), 0);
+}
+
+#[test]
+fn a_weight_is_read_from_change_events_without_allocating() {
+    let sources = FakeVoltageSource::bridge(716_000);
+    let calibration = Calibration {
+        offset: -10.,
+        coefficients: [1e6; NUMBER_OF_INPUTS],
+    };
+    let mut scale = ConnectedScale::from_sources(716_000, calibration, sources.clone());
+    scale.cache_change_events(Duration::from_secs(60)).unwrap();
+    for source in &sources {
+        source.set_ratio(1e-4);
+    }
+    assert_eq!(allocations(|| scale.get_weight().unwrap()), 0);
+    assert_eq!(scale.read_counters().cached(), 1);
+}
+
+#[test]
+fn load_cell_medians_reuse_their_buffers() {
+    let scale = scale();
+    let cancel = CancelFlag::new();
+    let medians = |samples| {
+        scale
+            .get_load_cell_medians_cancellable(samples, Duration::ZERO, &cancel)
+            .unwrap()
+    };
+    let first = medians(21);
+    assert_eq!(allocations(|| medians(21)), 0);
+    assert_eq!(allocations(|| medians(5)), 0);
+    assert_eq!(medians(5), first);
+}
+
+#[test]
+fn a_sampler_reuses_its_buffer() {
+    let scale = scale();
+    let mut sampler = MedianSampler::new(15, Duration::ZERO).unwrap();
+    let first = sampler.measure(&scale).unwrap();
+    assert_eq!(allocations(|| sampler.measure(&scale).unwrap()), 0);
+    assert_eq!(
+        sampler.measure(&scale).unwrap(),
+        scale.get_median_weight(15, Duration::ZERO).unwrap()
+    );
+    assert_eq!(sampler.measure(&scale).unwrap(), first);
+}
@@ -0,0 +1,69 @@
+#![cfg(feature = "tokio")]
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use libra::scale::DEFAULT_SAMPLE_INTERVAL;
+use libra::shared::SharedScale;
+use libra::{AsyncScale, Grams, MedianGrams, Scale};
+use tokio::time::Instant;
+
+/// Records the (virtual) time of every read.
+#[derive(Default)]
+struct MockScale {
+    reads: Arc<Mutex<Vec<Instant>>>,
+}
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        let mut reads = self.reads.lock().unwrap();
+        reads.push(Instant::now());
+        Ok(Grams(reads.len() as f64))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn median_samples_at_interval_cadence() {
+    let reads = Arc::new(Mutex::new(Vec::new()));
+    let scale = SharedScale::new(MockScale {
+        reads: Arc::clone(&reads),
+    });
+
+    let start = Instant::now();
+    let median = AsyncScale::get_median_weight(&scale, 20).await.unwrap();
+    assert_eq!(median, MedianGrams(11.));
+    assert_eq!(start.elapsed(), DEFAULT_SAMPLE_INTERVAL * 20);
+
+    let reads = reads.lock().unwrap();
+    assert_eq!(reads.len(), 20);
+    for (i, read) in reads.iter().enumerate() {
+        assert_eq!(*read - start, DEFAULT_SAMPLE_INTERVAL * (i as u32 + 1));
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn dropping_the_future_stops_sampling() {
+    let reads = Arc::new(Mutex::new(Vec::new()));
+    let scale = SharedScale::new(MockScale {
+        reads: Arc::clone(&reads),
+    });
+
+    let cut_off = DEFAULT_SAMPLE_INTERVAL * 5 + DEFAULT_SAMPLE_INTERVAL / 2;
+    let result = tokio::time::timeout(cut_off, AsyncScale::get_median_weight(&scale, 20)).await;
+    assert!(result.is_err());
+    let taken = reads.lock().unwrap().len();
+    assert_eq!(taken, 5);
+
+    tokio::time::sleep(Duration::from_secs(5)).await;
+    assert_eq!(reads.lock().unwrap().len(), taken);
+}
+
+#[tokio::test(start_paused = true)]
+async fn single_read_goes_through_blocking_pool() {
+    let scale = SharedScale::new(MockScale::default());
+    assert_eq!(AsyncScale::get_weight(&scale).await.unwrap(), Grams(1.));
+}
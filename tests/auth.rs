@@ -0,0 +1,192 @@
+#![cfg(all(feature = "net", feature = "http"))]
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use libra::actor::{spawn_scale_actor, ScaleHandle};
+use libra::auth::AuthToken;
+use libra::http::{serve_http_listener, HttpConfig};
+use libra::net::{serve_listener, ServerConfig};
+use libra::{Grams, MedianGrams, Scale, ScaleErrorKind, ScaleResponse};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+const TOKEN: &str = "correct horse battery staple";
+const DELAY: Duration = Duration::from_millis(100);
+
+struct MockScale;
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(10.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(MedianGrams(10.))
+    }
+}
+
+#[test]
+fn tokens_compare_as_they_match() {
+    let token = AuthToken::new(TOKEN);
+    assert!(token.matches(TOKEN));
+    assert_eq!(token, AuthToken::new(TOKEN));
+    assert_ne!(token, AuthToken::new("correct horse battery stapl"));
+    assert_ne!(token, AuthToken::new("correct horse battery staple!"));
+    assert_ne!(token, AuthToken::new("Correct horse battery staple"));
+    assert_eq!(format!("{token:?}"), "AuthToken(..)");
+}
+
+async fn listener() -> (TcpListener, SocketAddr) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    (listener, addr)
+}
+
+async fn start_tcp() -> (ScaleHandle, SocketAddr) {
+    let (handle, _task) = spawn_scale_actor(MockScale);
+    let (listener, addr) = listener().await;
+    let config = ServerConfig {
+        auth: Some(AuthToken::new(TOKEN)),
+        auth_failure_delay: DELAY,
+        ..ServerConfig::default()
+    };
+    tokio::spawn(serve_listener(handle.clone(), listener, config));
+    (handle, addr)
+}
+
+async fn connect(addr: SocketAddr) -> (Lines<BufReader<OwnedReadHalf>>, OwnedWriteHalf) {
+    let (read, write) = TcpStream::connect(addr).await.unwrap().into_split();
+    (BufReader::new(read).lines(), write)
+}
+
+async fn exchange(
+    (lines, write): &mut (Lines<BufReader<OwnedReadHalf>>, OwnedWriteHalf),
+    line: &str,
+) -> Value {
+    write
+        .write_all(format!("{line}\n").as_bytes())
+        .await
+        .unwrap();
+    let line = lines.next_line().await.unwrap().expect("connection closed");
+    serde_json::from_str(&line).unwrap()
+}
+
+fn unauthorized(answer: &Value) -> bool {
+    let response: ScaleResponse = serde_json::from_value(answer.clone()).unwrap();
+    matches!(response, ScaleResponse::Error(info) if info.kind == ScaleErrorKind::Unauthorized)
+}
+
+#[tokio::test]
+async fn tcp_commands_run_after_auth() {
+    let (_handle, addr) = start_tcp().await;
+    let mut connection = connect(addr).await;
+    let hello = exchange(&mut connection, r#"{"Hello":{"client_version":1}}"#).await;
+    assert!(hello.get("HelloAck").is_some(), "{hello}");
+    let auth = json!({"Auth": {"token": TOKEN}}).to_string();
+    assert_eq!(
+        exchange(&mut connection, &auth).await,
+        json!("Authenticated")
+    );
+    assert_eq!(
+        exchange(&mut connection, r#""GetWeight""#).await,
+        json!({"Weight": 10.})
+    );
+}
+
+#[tokio::test]
+async fn tcp_enveloped_auth_keeps_its_id() {
+    let (_handle, addr) = start_tcp().await;
+    let mut connection = connect(addr).await;
+    let auth = json!({"id": 7, "cmd": {"Auth": {"token": TOKEN}}}).to_string();
+    assert_eq!(
+        exchange(&mut connection, &auth).await,
+        json!({"id": 7, "response": "Authenticated"})
+    );
+}
+
+#[tokio::test]
+async fn tcp_wrong_token_is_refused_and_closed() {
+    let (_handle, addr) = start_tcp().await;
+    let mut connection = connect(addr).await;
+    let started = Instant::now();
+    let answer = exchange(&mut connection, r#"{"Auth":{"token":"tr0ub4dor"}}"#).await;
+    assert!(unauthorized(&answer), "{answer}");
+    assert!(started.elapsed() >= DELAY);
+    assert_eq!(connection.0.next_line().await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn tcp_commands_before_auth_are_refused_and_closed() {
+    let (_handle, addr) = start_tcp().await;
+    let mut connection = connect(addr).await;
+    let answer = exchange(&mut connection, r#""GetWeight""#).await;
+    assert!(unauthorized(&answer), "{answer}");
+    assert_eq!(connection.0.next_line().await.unwrap(), None);
+}
+
+async fn start_http() -> SocketAddr {
+    let (handle, _task) = spawn_scale_actor(MockScale);
+    let (listener, addr) = listener().await;
+    let config = HttpConfig {
+        auth: Some(AuthToken::new(TOKEN)),
+        auth_failure_delay: DELAY,
+        ..HttpConfig::default()
+    };
+    tokio::spawn(serve_http_listener(handle, listener, config));
+    addr
+}
+
+/// Sends `GET /weight/median` with `headers` and reads until the server
+/// closes the connection, which it must do even though the client asks to
+/// keep it alive. Returns the status and the lowercased response.
+async fn get_median(addr: SocketAddr, headers: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!("GET /weight/median HTTP/1.1\r\nHost: scale\r\n{headers}\r\n");
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let status = response[9..12].parse().unwrap();
+    (status, response.to_ascii_lowercase())
+}
+
+#[tokio::test]
+async fn http_requests_need_the_token() {
+    let addr = start_http().await;
+
+    let started = Instant::now();
+    let (status, response) = get_median(addr, "").await;
+    assert_eq!(status, 401);
+    assert!(started.elapsed() >= DELAY);
+    assert!(response.contains("www-authenticate: bearer"), "{response}");
+    assert!(response.contains("\"kind\":\"unauthorized\""), "{response}");
+
+    let (status, _) = get_median(addr, "Authorization: Bearer hunter2\r\n").await;
+    assert_eq!(status, 401);
+
+    let headers = format!("Authorization: Bearer {TOKEN}\r\nConnection: close\r\n");
+    let (status, response) = get_median(addr, &headers).await;
+    assert_eq!(status, 200, "{response}");
+}
+
+#[tokio::test]
+async fn websocket_upgrade_needs_the_token() {
+    let addr = start_http().await;
+    let url = format!("ws://{addr}/weight/stream");
+
+    match tokio_tungstenite::connect_async(&url).await {
+        Err(tokio_tungstenite::tungstenite::Error::Http(response)) => {
+            assert_eq!(response.status(), 401)
+        }
+        other => panic!("expected a 401, got {other:?}"),
+    }
+
+    let mut request = url.into_client_request().unwrap();
+    request
+        .headers_mut()
+        .insert("Authorization", format!("Bearer {TOKEN}").parse().unwrap());
+    assert!(tokio_tungstenite::connect_async(request).await.is_ok());
+}
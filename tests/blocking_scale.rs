@@ -0,0 +1,74 @@
+#![cfg(feature = "tokio")]
+
+use std::time::Duration;
+
+use libra::blocking::{BlockingScale, BlockingScaleError};
+use libra::{AsyncScale, AsyncScaleError, Grams, MedianGrams, Scale};
+
+struct MockScale {
+    hang: bool,
+}
+
+impl AsyncScale for MockScale {
+    async fn get_weight(&self) -> Result<Grams, AsyncScaleError> {
+        if self.hang {
+            std::future::pending::<()>().await;
+        }
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        Ok(Grams(5.))
+    }
+
+    async fn get_median_weight(&self, samples: usize) -> Result<MedianGrams, AsyncScaleError> {
+        if samples == 0 {
+            return Err("no samples".into());
+        }
+        Ok(MedianGrams(samples as f64))
+    }
+}
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_time()
+        .build()
+        .unwrap()
+}
+
+fn blocking_error(error: Box<dyn std::error::Error + Send + Sync>) -> BlockingScaleError {
+    *error.downcast::<BlockingScaleError>().unwrap()
+}
+
+#[test]
+fn works_from_a_plain_thread() {
+    let runtime = runtime();
+    let scale = BlockingScale::new(MockScale { hang: false }, runtime.handle().clone());
+    let result = std::thread::spawn(move || {
+        (
+            scale.get_weight().unwrap(),
+            scale.get_median_weight_of(7).unwrap(),
+            scale.get_median_weight_of(0).map_err(blocking_error),
+        )
+    })
+    .join()
+    .unwrap();
+    assert_eq!(result.0, Grams(5.));
+    assert_eq!(result.1, MedianGrams(7.));
+    assert!(matches!(result.2, Err(BlockingScaleError::Scale(_))));
+}
+
+#[test]
+fn times_out_instead_of_hanging() {
+    let runtime = runtime();
+    let scale = BlockingScale::new(MockScale { hang: true }, runtime.handle().clone())
+        .with_timeout(Duration::from_millis(50));
+    let error = blocking_error(scale.get_weight().unwrap_err());
+    assert!(matches!(error, BlockingScaleError::Timeout(t) if t == Duration::from_millis(50)));
+}
+
+#[tokio::test]
+async fn refuses_to_block_inside_a_runtime() {
+    let scale = BlockingScale::new(MockScale { hang: false }, tokio::runtime::Handle::current());
+    let error = blocking_error(scale.get_weight().unwrap_err());
+    assert!(matches!(error, BlockingScaleError::InsideRuntime));
+    assert!(error.to_string().contains("inside a tokio runtime"));
+}
@@ -0,0 +1,177 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libra::breaker::{BreakerConfig, BreakerState, CircuitBreaker, CircuitBreakerScale};
+use libra::scale::ScaleError;
+use libra::{Grams, MedianGrams, Scale, ScaleErrorInfo, ScaleErrorKind};
+
+const COOLDOWN: Duration = Duration::from_millis(50);
+/// How long a read of the dead bridge takes to time out.
+const READ_TIMEOUT: Duration = Duration::from_millis(20);
+
+/// A bridge that times out every read until it is `healthy` again.
+#[derive(Clone, Default)]
+struct FlakyScale {
+    healthy: Arc<AtomicBool>,
+    reads: Arc<AtomicUsize>,
+}
+
+impl Scale for FlakyScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        if self.healthy.load(Ordering::Relaxed) {
+            return Ok(Grams(250.));
+        }
+        thread::sleep(READ_TIMEOUT);
+        Err(Box::new(ScaleError::Timeout {
+            elapsed: READ_TIMEOUT,
+            collected: 0,
+        }))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        Err(Box::new(ScaleError::InvalidArgument("samples".into())))
+    }
+}
+
+type Transitions = Arc<Mutex<Vec<(BreakerState, BreakerState)>>>;
+
+fn breaker_scale(bridge: &FlakyScale) -> (CircuitBreakerScale<FlakyScale>, Transitions) {
+    let transitions = Transitions::default();
+    let seen = Arc::clone(&transitions);
+    let breaker = CircuitBreaker::new(BreakerConfig {
+        failure_threshold: 3,
+        cooldown: COOLDOWN,
+    })
+    .on_transition(move |from, to| seen.lock().unwrap().push((from, to)));
+    (
+        CircuitBreakerScale::new(bridge.clone(), breaker),
+        transitions,
+    )
+}
+
+#[test]
+fn reads_fail_fast_while_open_and_recover_after_the_probe() {
+    let bridge = FlakyScale::default();
+    let (scale, transitions) = breaker_scale(&bridge);
+
+    for failures in 1..=3 {
+        assert_eq!(scale.state(), BreakerState::Closed);
+        let error = scale.get_weight().unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ScaleError>(),
+            Some(ScaleError::Timeout { .. })
+        ));
+        assert_eq!(scale.breaker().consecutive_failures(), failures);
+    }
+    assert_eq!(scale.state(), BreakerState::Open);
+    assert_eq!(bridge.reads.load(Ordering::Relaxed), 3);
+
+    // Open: nothing reaches the bridge, and nothing waits for it.
+    let started = Instant::now();
+    for _ in 0..10 {
+        let error = scale.get_weight().unwrap_err();
+        let Some(ScaleError::CircuitOpen { last_error, .. }) = error.downcast_ref::<ScaleError>()
+        else {
+            panic!("{error} is not an open circuit");
+        };
+        assert_eq!(last_error.kind, ScaleErrorKind::Timeout);
+        let info = ScaleErrorInfo::from_dyn(&*error);
+        assert_eq!(info.kind, ScaleErrorKind::CircuitOpen);
+    }
+    assert!(started.elapsed() < READ_TIMEOUT, "{:?}", started.elapsed());
+    assert_eq!(bridge.reads.load(Ordering::Relaxed), 3);
+
+    bridge.healthy.store(true, Ordering::Relaxed);
+    thread::sleep(COOLDOWN);
+    assert_eq!(scale.get_weight().unwrap(), Grams(250.));
+    assert_eq!(scale.state(), BreakerState::Closed);
+    assert_eq!(scale.breaker().consecutive_failures(), 0);
+    assert_eq!(bridge.reads.load(Ordering::Relaxed), 4);
+
+    use BreakerState::*;
+    assert_eq!(
+        *transitions.lock().unwrap(),
+        [(Closed, Open), (Open, HalfOpen), (HalfOpen, Closed)]
+    );
+}
+
+#[test]
+fn a_failed_probe_reopens_the_circuit() {
+    let bridge = FlakyScale::default();
+    let (scale, transitions) = breaker_scale(&bridge);
+    for _ in 0..3 {
+        let _ = scale.get_weight();
+    }
+    thread::sleep(COOLDOWN);
+    assert!(scale.get_weight().is_err());
+    assert_eq!(scale.state(), BreakerState::Open);
+    assert_eq!(bridge.reads.load(Ordering::Relaxed), 4);
+
+    // A fresh cooldown started with the failed probe.
+    assert!(matches!(
+        scale.get_weight().unwrap_err().downcast_ref::<ScaleError>(),
+        Some(ScaleError::CircuitOpen { .. })
+    ));
+    assert_eq!(bridge.reads.load(Ordering::Relaxed), 4);
+
+    use BreakerState::*;
+    assert_eq!(
+        *transitions.lock().unwrap(),
+        [(Closed, Open), (Open, HalfOpen), (HalfOpen, Open)]
+    );
+}
+
+#[test]
+fn a_success_resets_the_count() {
+    let bridge = FlakyScale::default();
+    let (scale, _) = breaker_scale(&bridge);
+    for _ in 0..2 {
+        let _ = scale.get_weight();
+    }
+    bridge.healthy.store(true, Ordering::Relaxed);
+    scale.get_weight().unwrap();
+    bridge.healthy.store(false, Ordering::Relaxed);
+    for _ in 0..2 {
+        let _ = scale.get_weight();
+    }
+    assert_eq!(scale.state(), BreakerState::Closed);
+    assert_eq!(scale.breaker().consecutive_failures(), 2);
+}
+
+#[test]
+fn permanent_errors_are_not_failures() {
+    let bridge = FlakyScale::default();
+    let (scale, _) = breaker_scale(&bridge);
+    for _ in 0..2 {
+        let _ = scale.get_weight();
+    }
+    // The scale answered, if not with a weight.
+    for _ in 0..5 {
+        assert!(scale.get_median_weight().is_err());
+    }
+    assert_eq!(scale.state(), BreakerState::Closed);
+    assert_eq!(scale.breaker().consecutive_failures(), 0);
+}
+
+#[test]
+fn reset_closes_the_circuit() {
+    let bridge = FlakyScale::default();
+    let (scale, transitions) = breaker_scale(&bridge);
+    for _ in 0..3 {
+        let _ = scale.get_weight();
+    }
+    assert_eq!(scale.state(), BreakerState::Open);
+    scale.breaker().reset();
+    assert_eq!(scale.state(), BreakerState::Closed);
+    assert_eq!(
+        transitions.lock().unwrap().last(),
+        Some(&(BreakerState::Open, BreakerState::Closed))
+    );
+    assert_eq!(
+        scale.breaker().last_error().unwrap().kind,
+        ScaleErrorKind::Timeout
+    );
+}
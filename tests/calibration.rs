@@ -0,0 +1,278 @@
+#![cfg(feature = "tokio")]
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use libra::calibration::{
+    calibrate, calibrate_with_clock, Calibration, CalibrationPlan, CalibrationProgress,
+    CalibrationStep, RawScale,
+};
+use libra::scale::{ScaleError, NUMBER_OF_INPUTS};
+use libra::shared::SharedScale;
+use libra::testing::ManualClock;
+use libra::{Grams, MedianGrams, Scale};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// Each cell reads 0.1 plus its quarter of the load, at 0.001 per gram.
+#[derive(Default)]
+struct MockCells {
+    load: Arc<Mutex<f64>>,
+}
+
+impl RawScale for MockCells {
+    fn get_raw_readings(&self) -> Result<[f64; NUMBER_OF_INPUTS], ScaleError> {
+        let load = *self.load.lock().unwrap();
+        Ok([0.1 + load * 0.001 / 4.; NUMBER_OF_INPUTS])
+    }
+}
+
+fn plan() -> CalibrationPlan {
+    CalibrationPlan {
+        zero_samples: 5,
+        reference_masses: vec![Grams(500.), Grams(1000.)],
+        span_samples: 7,
+        sample_interval: Duration::from_millis(1),
+        settle_window: 3,
+        settle_tolerance: 1e-9,
+        settle_timeout: Duration::from_secs(1),
+    }
+}
+
+fn weigh(calibration: &Calibration, cells: [f64; NUMBER_OF_INPUTS]) -> f64 {
+    let sum: f64 = cells
+        .iter()
+        .zip(calibration.coefficients)
+        .map(|(r, c)| r * c)
+        .sum();
+    sum - calibration.offset
+}
+
+/// Plays the operator: loads the platform as asked and confirms each step.
+/// Returns every event description it saw.
+fn operator(
+    load: Arc<Mutex<f64>>,
+    mut progress: mpsc::Receiver<CalibrationProgress>,
+    cancel_at: Option<(CalibrationStep, usize, CancellationToken)>,
+) -> tokio::task::JoinHandle<Vec<String>> {
+    tokio::spawn(async move {
+        let mut seen = Vec::new();
+        while let Some(event) = progress.recv().await {
+            match event {
+                CalibrationProgress::AwaitingOperator { step, ready } => {
+                    *load.lock().unwrap() = match step {
+                        CalibrationStep::ClearPlatform => 0.,
+                        CalibrationStep::PlaceMass(mass) => mass.get(),
+                    };
+                    seen.push(format!("operator {step:?}"));
+                    ready.confirm();
+                }
+                CalibrationProgress::Settling { step } => seen.push(format!("settle {step:?}")),
+                CalibrationProgress::Measuring {
+                    step,
+                    sample,
+                    samples,
+                } => {
+                    seen.push(format!("measure {step:?} {sample}/{samples}"));
+                    if let Some((at, n, token)) = &cancel_at {
+                        if *at == step && *n == sample {
+                            token.cancel();
+                        }
+                    }
+                }
+            }
+        }
+        seen
+    })
+}
+
+#[tokio::test]
+async fn full_calibration_flow() {
+    let cells = MockCells::default();
+    let load = Arc::clone(&cells.load);
+    let scale = SharedScale::new(cells);
+    let (tx, rx) = mpsc::channel(4);
+    let operator = operator(load, rx, None);
+
+    let record = calibrate(&scale, plan(), tx, &CancellationToken::new())
+        .await
+        .unwrap();
+    let seen = operator.await.unwrap();
+
+    let calibration = record.calibration;
+    for mass in [0., 250., 500., 1000.] {
+        let cells = [0.1 + mass * 0.001 / 4.; NUMBER_OF_INPUTS];
+        assert!((weigh(&calibration, cells) - mass).abs() < 1e-6);
+    }
+    assert!(record.performed_at.is_some());
+    assert_eq!(record.reference_masses(), [Grams(500.), Grams(1000.)]);
+    assert_eq!(record.reference_points.len(), 3);
+    for point in &record.reference_points {
+        assert_eq!(
+            point.readings,
+            [0.1 + point.mass.get() * 0.001 / 4.; NUMBER_OF_INPUTS]
+        );
+        assert!(point.residual_after.unwrap().abs() < 1e-6);
+        assert_eq!(point.residual_before, None);
+    }
+    assert_eq!(seen[0], "operator ClearPlatform");
+    assert_eq!(seen[1], "settle ClearPlatform");
+    assert_eq!(seen[2], "measure ClearPlatform 1/5");
+    assert_eq!(seen[6], "measure ClearPlatform 5/5");
+    assert_eq!(seen[7], "operator PlaceMass(Grams(500.0))");
+    assert_eq!(seen.last().unwrap(), "measure PlaceMass(Grams(1000.0)) 7/7");
+    assert_eq!(seen.len(), 3 * 2 + 5 + 7 * 2);
+}
+
+#[tokio::test]
+async fn cancelled_mid_span() {
+    let cells = MockCells::default();
+    let load = Arc::clone(&cells.load);
+    let scale = SharedScale::new(cells);
+    let (tx, rx) = mpsc::channel(4);
+    let token = CancellationToken::new();
+    let operator = operator(
+        load,
+        rx,
+        Some((CalibrationStep::PlaceMass(Grams(500.)), 3, token.clone())),
+    );
+
+    let result = calibrate(&scale, plan(), tx, &token).await;
+    assert!(matches!(result, Err(ScaleError::Cancelled { .. })));
+    let seen = operator.await.unwrap();
+    assert!(!seen.iter().any(|e| e.contains("1000")));
+}
+
+#[tokio::test]
+async fn dropping_operator_ready_cancels() {
+    let scale = SharedScale::new(MockCells::default());
+    let (tx, mut rx) = mpsc::channel(4);
+    let operator = tokio::spawn(async move {
+        // Walk away without confirming.
+        drop(rx.recv().await);
+    });
+    let result = calibrate(&scale, plan(), tx, &CancellationToken::new()).await;
+    assert!(matches!(result, Err(ScaleError::Cancelled { .. })));
+    operator.await.unwrap();
+}
+
+#[tokio::test]
+async fn unsettled_platform_times_out() {
+    /// Reads a little higher every time.
+    struct Drifting(Mutex<f64>);
+    impl RawScale for Drifting {
+        fn get_raw_readings(&self) -> Result<[f64; NUMBER_OF_INPUTS], ScaleError> {
+            let mut drift = self.0.lock().unwrap();
+            *drift += 0.01;
+            Ok([*drift; NUMBER_OF_INPUTS])
+        }
+    }
+    let scale = SharedScale::new(Drifting(Mutex::new(0.)));
+    let (tx, rx) = mpsc::channel(4);
+    let _operator = operator(Arc::default(), rx, None);
+    let plan = CalibrationPlan {
+        sample_interval: Duration::from_millis(100),
+        settle_timeout: Duration::from_secs(60),
+        ..plan()
+    };
+    let clock = ManualClock::new();
+    let result = calibrate_with_clock(&scale, plan, tx, &CancellationToken::new(), &clock).await;
+    assert!(matches!(
+        result,
+        Err(ScaleError::NotSettled(timeout)) if timeout == Duration::from_secs(60)
+    ));
+    // One read every 100 ms of the clock, until a minute of it has passed.
+    assert_eq!(clock.elapsed(), Duration::from_secs(60));
+    let reads = *scale.lock().0.lock().unwrap() / 0.01;
+    assert!((reads - 600.).abs() < 1e-6, "{reads}");
+}
+
+/// A platform of the same build as [`MockCells`], its cells `sensitivity`
+/// times as sensitive, under a calibration of its own.
+struct Sibling {
+    sensitivity: f64,
+    load: f64,
+    calibration: Calibration,
+    unreadable: bool,
+}
+
+impl Sibling {
+    fn new(sensitivity: f64) -> Self {
+        Self {
+            sensitivity,
+            load: 500.,
+            calibration: Calibration {
+                offset: 0.,
+                coefficients: [1.; NUMBER_OF_INPUTS],
+            },
+            unreadable: false,
+        }
+    }
+}
+
+impl Scale for Sibling {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        if self.unreadable {
+            return Err(Box::new(ScaleError::Busy));
+        }
+        let cell = 0.1 + self.load * self.sensitivity * 0.001 / 4.;
+        Ok(self.calibration.weigh(&[cell; NUMBER_OF_INPUTS]))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_median_weight_of(3)
+    }
+
+    fn calibration(&self) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.calibration)
+    }
+
+    fn set_calibration(
+        &mut self,
+        calibration: Calibration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.calibration = calibration;
+        Ok(())
+    }
+}
+
+/// The calibration of a [`MockCells`] platform.
+fn master() -> Calibration {
+    Calibration {
+        offset: 400.,
+        coefficients: [1000.; NUMBER_OF_INPUTS],
+    }
+}
+
+#[test]
+fn a_transferred_calibration_is_kept_only_if_it_weighs_the_check_mass() {
+    let mut twin = Sibling::new(1.);
+    let result = master()
+        .transfer_check(&mut twin, Grams(500.), Grams(0.5), 3)
+        .unwrap();
+    assert!(result.passed, "{result:?}");
+    assert!(result.error.get().abs() < 1e-9, "{result:?}");
+    assert_eq!(twin.calibration, master());
+
+    // Cells 1% more sensitive read 5 g over.
+    let mut stiff = Sibling::new(1.01);
+    let before = stiff.calibration;
+    let result = master()
+        .transfer_check(&mut stiff, Grams(500.), Grams(0.5), 3)
+        .unwrap();
+    assert!(!result.passed);
+    assert!((result.error.get() - 5.).abs() < 1e-9, "{result:?}");
+    assert!((result.measured.get() - 505.).abs() < 1e-9, "{result:?}");
+    assert_eq!(stiff.calibration, before);
+
+    let mut unreadable = Sibling::new(1.);
+    unreadable.unreadable = true;
+    let error = master()
+        .transfer_check(&mut unreadable, Grams(500.), Grams(0.5), 3)
+        .unwrap_err();
+    assert!(
+        matches!(error.downcast_ref(), Some(ScaleError::Busy)),
+        "{error:?}"
+    );
+    assert_eq!(unreadable.calibration, before);
+}
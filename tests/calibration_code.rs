@@ -0,0 +1,121 @@
+use libra::calibration::{Calibration, CompactCodeError};
+
+const CALIBRATION: Calibration = Calibration {
+    offset: 412.5,
+    coefficients: [1000.25, 1001., 998.75, -1e-7],
+};
+
+#[test]
+fn codes_read_back_exactly() {
+    let code = CALIBRATION.to_compact_string();
+    assert_eq!(code.len(), 83);
+    assert!(code.split('-').all(|group| group.len() == 6), "{code}");
+    assert!(
+        code.chars()
+            .all(|c| c == '-' || c.is_ascii_digit() || c.is_ascii_uppercase()),
+        "{code}"
+    );
+    assert_eq!(Calibration::from_compact_string(&code), Ok(CALIBRATION));
+
+    // As a technician might type it.
+    let typed = code.replace('-', " ").to_lowercase();
+    assert_eq!(Calibration::from_compact_string(&typed), Ok(CALIBRATION));
+    let misread = code.replace('0', "O").replace('1', "l");
+    assert_eq!(Calibration::from_compact_string(&misread), Ok(CALIBRATION));
+
+    let zero = Calibration {
+        offset: 0.,
+        coefficients: [0.; 4],
+    };
+    assert_eq!(
+        Calibration::from_compact_string(&zero.to_compact_string()),
+        Ok(zero)
+    );
+}
+
+#[test]
+fn cut_short_or_padded_codes_are_refused() {
+    let code = CALIBRATION.to_compact_string();
+    let error = Calibration::from_compact_string(&code[..80]).unwrap_err();
+    assert_eq!(
+        error,
+        CompactCodeError::WrongLength {
+            found: 69,
+            expected: 72
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "The calibration code has 69 characters, not 72; is part of it missing?"
+    );
+    assert_eq!(
+        Calibration::from_compact_string(&format!("{code}-00"))
+            .unwrap_err()
+            .to_string(),
+        "The calibration code has 74 characters, not 72; was something added?"
+    );
+    assert!(matches!(
+        Calibration::from_compact_string(""),
+        Err(CompactCodeError::WrongLength { found: 0, .. })
+    ));
+}
+
+#[test]
+fn corrupted_codes_are_refused() {
+    let code = CALIBRATION.to_compact_string();
+    // Every character but those of the version, changed in turn, fails the
+    // checksum.
+    for (at, c) in code.char_indices().filter(|(at, c)| *c != '-' && *at > 1) {
+        let digit = if c == 'Z' { 'Y' } else { 'Z' };
+        let mut changed = code.clone();
+        changed.replace_range(at..=at, &digit.to_string());
+        let error = Calibration::from_compact_string(&changed).unwrap_err();
+        assert!(
+            matches!(error, CompactCodeError::ChecksumMismatch { expected, found }
+                if expected != found),
+            "{at}: {error:?}"
+        );
+    }
+    let error = Calibration::from_compact_string(&code.replacen('-', "", 1)[..71]).unwrap_err();
+    assert!(matches!(error, CompactCodeError::WrongLength { .. }));
+    let mut changed = code.clone();
+    changed.replace_range(40..41, if &code[40..41] == "0" { "1" } else { "0" });
+    assert!(Calibration::from_compact_string(&changed)
+        .unwrap_err()
+        .to_string()
+        .ends_with("; a character was read or typed wrong"));
+
+    let error = Calibration::from_compact_string(&code.replacen('-', "U", 1)).unwrap_err();
+    assert_eq!(
+        error,
+        CompactCodeError::InvalidCharacter {
+            character: 'U',
+            position: 7
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "'U' at position 7 is not a character of a calibration code"
+    );
+
+    // A newer version starts with other digits.
+    let error = Calibration::from_compact_string(&code.replacen("04", "0C", 1)).unwrap_err();
+    assert_eq!(error, CompactCodeError::UnknownVersion(3));
+    assert_eq!(
+        error.to_string(),
+        "The calibration code is of version 3, and this version of libra reads version 1"
+    );
+}
+
+#[test]
+fn codes_of_calibrations_that_are_not_finite_are_refused() {
+    let code = Calibration {
+        offset: f64::NAN,
+        ..CALIBRATION
+    }
+    .to_compact_string();
+    assert_eq!(
+        Calibration::from_compact_string(&code),
+        Err(CompactCodeError::NotFinite)
+    );
+}
@@ -0,0 +1,101 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use libra::calibration::{Calibration, CalibrationRecord, ReferencePoint};
+use libra::Grams;
+
+const CALIBRATION: Calibration = Calibration {
+    offset: 400.,
+    coefficients: [1000.; 4],
+};
+
+/// A record of a calibration against 500 g, with the readings of cells
+/// reading 0.1 each empty and 0.001 per gram between them.
+fn record() -> CalibrationRecord {
+    let points = [0., 500.].map(|mass| {
+        let readings = [0.1 + mass * 0.001 / 4.; 4];
+        ReferencePoint::new(Grams(mass), readings, &CALIBRATION)
+    });
+    CalibrationRecord {
+        performed_at: Some(UNIX_EPOCH + Duration::from_secs(1_760_000_000)),
+        reference_points: points.into(),
+        ..CALIBRATION.into()
+    }
+    .with_operator("tech-17")
+    .with_notes("21 °C, doors closed")
+}
+
+#[test]
+fn a_bare_calibration_reads_as_a_record_without_metadata() {
+    let record: CalibrationRecord = serde_json::from_str(
+        r#"{"offset": 400.0, "coefficients": [1000.0, 1000.0, 1000.0, 1000.0]}"#,
+    )
+    .unwrap();
+    assert_eq!(record, CalibrationRecord::from(CALIBRATION));
+    assert_eq!(
+        serde_json::to_value(&record).unwrap(),
+        serde_json::to_value(CALIBRATION).unwrap()
+    );
+}
+
+#[test]
+fn records_round_trip() {
+    let record = record().with_previous(&Calibration {
+        offset: 390.,
+        ..CALIBRATION
+    });
+    let json = serde_json::to_string(&record).unwrap();
+    assert_eq!(
+        serde_json::from_str::<CalibrationRecord>(&json).unwrap(),
+        record
+    );
+    // Read by an older version, the metadata is ignored.
+    assert_eq!(
+        serde_json::from_str::<Calibration>(&json).unwrap(),
+        CALIBRATION
+    );
+}
+
+#[test]
+fn residuals_are_the_error_under_each_calibration() {
+    let record = record().with_previous(&Calibration {
+        offset: 390.,
+        ..CALIBRATION
+    });
+    let residuals: Vec<_> = record
+        .reference_points
+        .iter()
+        .map(|point| (point.residual_before, point.residual_after))
+        .collect();
+    assert_eq!(residuals, [(Some(10.), Some(0.)), (Some(10.), Some(0.))]);
+    assert_eq!(record.reference_masses(), [Grams(500.)]);
+}
+
+#[test]
+fn the_summary_reads_as_a_certificate() {
+    let record = record().with_previous(&Calibration {
+        offset: 402.5,
+        ..CALIBRATION
+    });
+    assert_eq!(
+        record.summary().to_string(),
+        "Calibration certificate\n\
+         Performed: 2025-10-09 08:53:20 UTC\n\
+         Operator: tech-17\n\
+         Notes: 21 °C, doors closed\n\
+         Offset: 400\n\
+         Coefficients: [1000.0, 1000.0, 1000.0, 1000.0]\n   \
+         Reference        Before         After\n       \
+         0.0 g      -2.500 g      +0.000 g\n     \
+         500.0 g      -2.500 g      +0.000 g"
+    );
+    assert_eq!(
+        CalibrationRecord::from(CALIBRATION).summary().to_string(),
+        "Calibration certificate\n\
+         Performed: not recorded\n\
+         Operator: not recorded\n\
+         Notes: not recorded\n\
+         Offset: 400\n\
+         Coefficients: [1000.0, 1000.0, 1000.0, 1000.0]\n\
+         Reference points: not recorded"
+    );
+}
@@ -0,0 +1,453 @@
+#![cfg(feature = "store")]
+
+use std::path::PathBuf;
+
+use libra::calibration::{Calibration, CalibrationRecord, ScaleIdentity};
+use libra::store::{
+    AssignmentError, AssignmentStore, BridgeMismatchAction, CalibrationError, CalibrationStore,
+    IdentityCheck, Integrity,
+};
+
+/// An empty directory of its own for the test `name`.
+fn directory(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("libra-store-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn calibration(offset: f64) -> Calibration {
+    Calibration {
+        offset,
+        coefficients: [1000., 1001., 999., 1000.5],
+    }
+}
+
+#[test]
+fn saves_keep_the_calibrations_they_replace() {
+    let dir = directory("history");
+    let store = CalibrationStore::new(dir.join("calibration.json")).with_backups(2);
+    assert!(store.history().unwrap().is_empty());
+
+    for offset in 1..=4 {
+        store.save(&calibration(offset as f64)).unwrap();
+    }
+    assert_eq!(store.load().unwrap(), calibration(4.));
+    let history = store.history().unwrap();
+    assert_eq!(history.len(), 2);
+    assert!(history[0].saved_at > history[1].saved_at);
+    let kept: Vec<_> = history
+        .iter()
+        .map(|backup| CalibrationStore::new(&backup.path).load().unwrap().offset)
+        .collect();
+    assert_eq!(kept, [3., 2.]);
+    assert!(!dir.join("calibration.json.tmp").exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn records_keep_their_metadata() {
+    let dir = directory("record");
+    let store = CalibrationStore::new(dir.join("calibration.json"));
+    let record = CalibrationRecord::from(calibration(1.))
+        .with_operator("tech-17")
+        .with_notes("21 °C");
+    store.save_record(&record).unwrap();
+    assert_eq!(store.load_record().unwrap(), record);
+    assert_eq!(store.load().unwrap(), calibration(1.));
+
+    // A file of a bare calibration, as saved before records.
+    store.save(&calibration(2.)).unwrap();
+    assert_eq!(
+        store.load_record().unwrap(),
+        CalibrationRecord::from(calibration(2.))
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn rollback_restores_the_newest_backup() {
+    let dir = directory("rollback");
+    let store = CalibrationStore::new(dir.join("calibration.json"));
+    store.save(&calibration(1.)).unwrap();
+    assert!(matches!(
+        store.rollback(),
+        Err(CalibrationError::NoBackup { .. })
+    ));
+
+    store.save(&calibration(2.)).unwrap();
+    store.save(&calibration(3.)).unwrap();
+    assert_eq!(store.rollback().unwrap(), calibration(2.));
+    assert_eq!(store.load().unwrap(), calibration(2.));
+    assert_eq!(store.rollback().unwrap(), calibration(1.));
+    assert!(store.history().unwrap().is_empty());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn a_save_interrupted_before_the_rename_leaves_the_old_file() {
+    let dir = directory("crash");
+    let store = CalibrationStore::new(dir.join("calibration.json"));
+    store.save(&calibration(1.)).unwrap();
+
+    // As if the process died with the new calibration only in the
+    // temporary file.
+    std::mem::forget(store.prepare(&calibration(2.)).unwrap());
+    assert!(dir.join("calibration.json.tmp").exists());
+    assert_eq!(store.load().unwrap(), calibration(1.));
+    assert!(store.history().unwrap().is_empty());
+
+    store.save(&calibration(3.)).unwrap();
+    assert_eq!(store.load().unwrap(), calibration(3.));
+    assert!(!dir.join("calibration.json.tmp").exists());
+
+    // Given up on, a prepared save tidies up after itself.
+    drop(store.prepare(&calibration(4.)).unwrap());
+    assert!(!dir.join("calibration.json.tmp").exists());
+    assert_eq!(store.load().unwrap(), calibration(3.));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn unreadable_calibrations_are_refused() {
+    let dir = directory("malformed");
+    let path = dir.join("calibration.json");
+    let store = CalibrationStore::new(&path);
+    assert!(matches!(
+        store.load(),
+        Err(CalibrationError::Io { action: "read", .. })
+    ));
+    assert!(matches!(
+        store.save(&calibration(f64::NAN)),
+        Err(CalibrationError::Malformed { .. })
+    ));
+    assert!(!path.exists());
+
+    std::fs::write(&path, r#"{"offset": 1.0, "coefficients": [1.0, 2.0]}"#).unwrap();
+    let error = store.load().unwrap_err();
+    assert!(
+        matches!(error, CalibrationError::Malformed { .. }),
+        "{error:?}"
+    );
+    assert!(error
+        .to_string()
+        .starts_with(&format!("{} is not a calibration: ", path.display())));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn a_changed_file_fails_its_integrity_check() {
+    let dir = directory("tampered");
+    let path = dir.join("calibration.json");
+    let store = CalibrationStore::new(&path);
+    store.save(&calibration(1.)).unwrap();
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(text.contains("\"checksum\": \"crc32:"), "{text}");
+    assert_eq!(store.load_checked().unwrap().integrity, Integrity::Verified);
+
+    // Laid out differently, it is the same calibration.
+    let compact: serde_json::Value = serde_json::from_str(&text).unwrap();
+    std::fs::write(&path, compact.to_string()).unwrap();
+    assert_eq!(store.load().unwrap(), calibration(1.));
+
+    // A field no calibration has is ignored, and not checked.
+    let extra = text.replacen('{', "{\n  \"station\": \"fryer\",", 1);
+    std::fs::write(&path, extra).unwrap();
+    assert_eq!(store.load_checked().unwrap().integrity, Integrity::Verified);
+
+    // One digit of a coefficient changed.
+    std::fs::write(&path, text.replacen("1001.0", "1007.0", 1)).unwrap();
+    let error = store.load().unwrap_err();
+    assert!(
+        matches!(&error, CalibrationError::IntegrityFailure { expected, found, .. }
+            if expected != found),
+        "{error:?}"
+    );
+    assert!(error.to_string().starts_with(&format!(
+        "{} has been changed since it was saved",
+        path.display()
+    )));
+    let recovered = store.load_unchecked().unwrap();
+    assert_eq!(recovered.calibration.coefficients[1], 1007.);
+
+    std::fs::write(&path, text.replace("crc32:", "md5:")).unwrap();
+    assert!(matches!(
+        store.load(),
+        Err(CalibrationError::Malformed { .. })
+    ));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn a_file_without_a_checksum_loads_unchecked() {
+    let dir = directory("legacy");
+    let path = dir.join("calibration.json");
+    std::fs::write(
+        &path,
+        r#"{"offset": 1.0, "coefficients": [1000.0, 1001.0, 999.0, 1000.5]}"#,
+    )
+    .unwrap();
+    let store = CalibrationStore::new(&path);
+    let loaded = store.load_checked().unwrap();
+    assert_eq!(loaded.integrity, Integrity::Unchecked);
+    assert_eq!(loaded.record.calibration, calibration(1.));
+
+    // Saved again, it gets one.
+    store.save(&loaded.record.calibration).unwrap();
+    assert_eq!(store.load_checked().unwrap().integrity, Integrity::Verified);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// The path of the fixture `name`.
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+#[test]
+fn legacy_arrays_are_read_and_migrated() {
+    let legacy = Calibration {
+        offset: 412.5,
+        coefficients: [1000.25, 1001., 998.75, 1000.5],
+    };
+    let record = Calibration::from_legacy(fixture("legacy_calibration.json")).unwrap();
+    assert_eq!(record.calibration, legacy);
+    assert_eq!(
+        record.notes.as_deref(),
+        Some("Migrated from a legacy [offset, c0, c1, c2, c3] file")
+    );
+
+    let dir = directory("legacy-array");
+    let path = dir.join("calibration.json");
+    std::fs::copy(fixture("legacy_calibration.json"), &path).unwrap();
+    let store = CalibrationStore::new(&path);
+    let loaded = store.load_checked().unwrap();
+    assert_eq!(loaded.integrity, Integrity::Legacy);
+    assert_eq!(loaded.record, record);
+
+    assert!(store.migrate_legacy().unwrap());
+    assert_eq!(store.load_checked().unwrap().integrity, Integrity::Verified);
+    assert_eq!(store.load_record().unwrap(), record);
+    assert_eq!(
+        std::fs::read(dir.join("calibration.json.legacy.bak")).unwrap(),
+        std::fs::read(fixture("legacy_calibration.json")).unwrap()
+    );
+    assert!(!store.migrate_legacy().unwrap());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn ambiguous_legacy_arrays_are_refused() {
+    let error = Calibration::from_legacy(fixture("legacy_calibration_four.json")).unwrap_err();
+    let CalibrationError::Malformed { message, .. } = &error else {
+        panic!("{error:?}");
+    };
+    assert_eq!(
+        message,
+        "found an array of 4 numbers, where a legacy calibration is [offset, c0, c1, c2, c3]"
+    );
+
+    let dir = directory("legacy-shapes");
+    let path = dir.join("calibration.json");
+    let store = CalibrationStore::new(&path);
+    for (text, shape) in [
+        ("[]", "an empty array"),
+        (
+            "[1.0, \"2\", 3, 4, 5]",
+            "an array of a number, a string, a number, a number, a number",
+        ),
+        ("[[1, 2, 3, 4, 5]]", "an array of an array of 5 numbers"),
+    ] {
+        std::fs::write(&path, text).unwrap();
+        let error = store.load().unwrap_err();
+        assert!(
+            matches!(&error, CalibrationError::Malformed { message, .. }
+                if message.starts_with(&format!("found {shape}, "))),
+            "{error:?}"
+        );
+        assert!(matches!(
+            store.migrate_legacy(),
+            Err(CalibrationError::Malformed { .. })
+        ));
+    }
+    assert!(!dir.join("calibration.json.legacy.bak").exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn a_connected_scale_saves_and_loads_its_calibration() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use libra::scale::ConnectedScale;
+    use libra::testing::FakeVoltageSource;
+
+    let dir = directory("scale");
+    let store = CalibrationStore::new(dir.join("calibration.json"));
+    let mut scale =
+        ConnectedScale::from_sources(716_000, calibration(5.), FakeVoltageSource::bridge(716_000));
+    let calibrated_at = UNIX_EPOCH + Duration::from_secs(1_760_000_000);
+    scale.set_calibrated_at(Some(calibrated_at));
+    scale.save_calibration(&store).unwrap();
+
+    let mut other =
+        ConnectedScale::from_sources(716_001, calibration(0.), FakeVoltageSource::bridge(716_001));
+    assert_eq!(other.load_latest(&store).unwrap(), calibration(5.));
+    assert_eq!(other.calibration(), calibration(5.));
+    assert_eq!(other.calibrated_at(), Some(calibrated_at));
+
+    // Once named, the platform is saved with the bridge it is read through.
+    scale.set_platform_id(Some("hopper-3".into()));
+    scale.save_calibration(&store).unwrap();
+    let strict = store
+        .clone()
+        .with_bridge_mismatch(BridgeMismatchAction::Reject);
+    other.set_calibration(calibration(0.));
+    assert!(matches!(
+        other.load_latest(&strict),
+        Err(CalibrationError::BridgeMismatch { .. })
+    ));
+    other.set_platform_id(Some("hopper-4".into()));
+    assert!(matches!(
+        other.load_latest(&store),
+        Err(CalibrationError::PlatformMismatch { .. })
+    ));
+    assert_eq!(other.calibration(), calibration(0.));
+    other.set_platform_id(Some("hopper-3".into()));
+    strict.adopt_new_bridge(716_001).unwrap();
+    assert_eq!(other.load_latest(&strict).unwrap(), calibration(5.));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+fn identified(offset: f64, platform_id: &str, bridge_serial: i32) -> CalibrationRecord {
+    CalibrationRecord::from(calibration(offset)).with_identity(ScaleIdentity {
+        platform_id: platform_id.into(),
+        bridge_serial,
+    })
+}
+
+#[test]
+fn a_calibration_is_checked_against_the_scale_it_is_loaded_into() {
+    let dir = directory("identity");
+    let store = CalibrationStore::new(dir.join("calibration.json"));
+    store
+        .save_record(&identified(5., "hopper-3", 716_000))
+        .unwrap();
+    let record = store.load_record().unwrap();
+    assert_eq!(record.identity.as_ref().unwrap().platform_id, "hopper-3");
+
+    let check = |platform_id, serial| store.check_identity(&record, platform_id, serial);
+    assert_eq!(
+        check(Some("hopper-3"), 716_000).unwrap(),
+        IdentityCheck::Matched
+    );
+    assert_eq!(check(None, 716_000).unwrap(), IdentityCheck::Matched);
+    // A new bridge is only warned of by default.
+    assert_eq!(
+        check(Some("hopper-3"), 716_001).unwrap(),
+        IdentityCheck::BridgeSwapped {
+            stored: 716_000,
+            live: 716_001
+        }
+    );
+    // A new platform needs calibrating, whatever the bridge.
+    let error = check(Some("hopper-4"), 716_000).unwrap_err();
+    assert!(
+        matches!(&error, CalibrationError::PlatformMismatch { stored, expected, .. }
+            if stored == "hopper-3" && expected == "hopper-4"),
+        "{error:?}"
+    );
+    assert_eq!(
+        store
+            .check_identity(
+                &CalibrationRecord::from(calibration(5.)),
+                Some("hopper-3"),
+                1
+            )
+            .unwrap(),
+        IdentityCheck::Unrecorded
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn a_strict_store_rejects_a_new_bridge_until_it_is_adopted() {
+    let dir = directory("adopt");
+    let store = CalibrationStore::new(dir.join("calibration.json"))
+        .with_bridge_mismatch(BridgeMismatchAction::Reject);
+    store
+        .save_record(&identified(5., "hopper-3", 716_000).with_notes("21 °C"))
+        .unwrap();
+
+    let record = store.load_record().unwrap();
+    let error = store
+        .check_identity(&record, Some("hopper-3"), 716_001)
+        .unwrap_err();
+    assert!(
+        matches!(
+            error,
+            CalibrationError::BridgeMismatch {
+                stored: 716_000,
+                live: 716_001,
+                ..
+            }
+        ),
+        "{error:?}"
+    );
+    assert!(
+        error.to_string().contains("adopt the new bridge"),
+        "{error}"
+    );
+
+    let adopted = store.adopt_new_bridge(716_001).unwrap();
+    assert_eq!(adopted.calibration, calibration(5.));
+    assert_eq!(adopted.identity.as_ref().unwrap().bridge_serial, 716_001);
+    assert_eq!(
+        adopted.notes.as_deref(),
+        Some("21 °C; Bridge 716000 replaced by 716001, calibration kept")
+    );
+    assert_eq!(store.load_record().unwrap(), adopted);
+    assert_eq!(
+        store
+            .check_identity(&adopted, Some("hopper-3"), 716_001)
+            .unwrap(),
+        IdentityCheck::Matched
+    );
+    // The record from before the swap is kept.
+    assert_eq!(store.history().unwrap().len(), 1);
+
+    store.save(&calibration(6.)).unwrap();
+    assert!(matches!(
+        store.adopt_new_bridge(716_002),
+        Err(CalibrationError::NoIdentity { .. })
+    ));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn assignments_are_a_json_object_of_serial_numbers() {
+    let dir = directory("assignments");
+    let store = AssignmentStore::new(dir.join("assignments.json"));
+    assert!(store.load().unwrap().is_empty());
+
+    let assignments = [
+        ("flour".to_string(), 716_000),
+        ("rice".to_string(), 716_001),
+    ]
+    .into();
+    store.save(&assignments).unwrap();
+    assert_eq!(store.load().unwrap(), assignments);
+    let text = std::fs::read_to_string(store.path()).unwrap();
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&text).unwrap(),
+        serde_json::json!({"flour": 716000, "rice": 716001})
+    );
+    assert!(!dir.join("assignments.json.tmp").exists());
+
+    std::fs::write(store.path(), "[716000]").unwrap();
+    assert!(matches!(
+        store.load(),
+        Err(AssignmentError::Malformed { .. })
+    ));
+}
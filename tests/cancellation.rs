@@ -0,0 +1,114 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libra::cancel::CancelFlag;
+use libra::scale::ScaleError;
+use libra::{Grams, MedianGrams, Scale};
+
+struct SlowScale {
+    reads: Arc<AtomicUsize>,
+    read_time: Duration,
+}
+
+impl Scale for SlowScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        thread::sleep(self.read_time);
+        Ok(Grams(self.reads.fetch_add(1, Ordering::SeqCst) as f64))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_median_weight_of(30)
+    }
+}
+
+fn cancelled_count(error: Box<dyn std::error::Error + Send + Sync>) -> usize {
+    match error.downcast_ref::<ScaleError>() {
+        Some(ScaleError::Cancelled { collected }) => *collected,
+        _ => panic!("expected a cancellation, got {error}"),
+    }
+}
+
+#[test]
+fn cancel_mid_collection_reports_progress() {
+    let reads = Arc::new(AtomicUsize::new(0));
+    let scale = SlowScale {
+        reads: Arc::clone(&reads),
+        read_time: Duration::from_millis(10),
+    };
+    let cancel = CancelFlag::new();
+    let canceller = cancel.clone();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(55));
+        canceller.cancel();
+    });
+
+    let start = Instant::now();
+    let error = scale
+        .get_median_weight_cancellable(1000, &cancel)
+        .unwrap_err();
+    assert!(start.elapsed() < Duration::from_millis(200));
+    let collected = cancelled_count(error);
+    assert!(collected > 0 && collected < 1000);
+    assert_eq!(collected, reads.load(Ordering::SeqCst));
+}
+
+#[test]
+fn already_cancelled_takes_no_samples() {
+    let reads = Arc::new(AtomicUsize::new(0));
+    let scale = SlowScale {
+        reads: Arc::clone(&reads),
+        read_time: Duration::ZERO,
+    };
+    let cancel = CancelFlag::new();
+    cancel.cancel();
+    let error = scale
+        .get_median_weight_cancellable(10, &cancel)
+        .unwrap_err();
+    assert_eq!(cancelled_count(error), 0);
+    assert_eq!(reads.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn uncancelled_flag_completes() {
+    let scale = SlowScale {
+        reads: Arc::new(AtomicUsize::new(0)),
+        read_time: Duration::ZERO,
+    };
+    let median = scale
+        .get_median_weight_cancellable(5, &CancelFlag::new())
+        .unwrap();
+    assert_eq!(median, MedianGrams(2.));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(start_paused = true)]
+async fn token_cancels_async_median_between_samples() {
+    use libra::scale::DEFAULT_SAMPLE_INTERVAL;
+    use libra::shared::SharedScale;
+    use tokio_util::sync::CancellationToken;
+
+    let reads = Arc::new(AtomicUsize::new(0));
+    let scale = SharedScale::new(SlowScale {
+        reads: Arc::clone(&reads),
+        read_time: Duration::ZERO,
+    });
+    let token = CancellationToken::new();
+    let canceller = token.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(DEFAULT_SAMPLE_INTERVAL * 7 + DEFAULT_SAMPLE_INTERVAL / 2).await;
+        canceller.cancel();
+    });
+
+    let start = tokio::time::Instant::now();
+    let error = scale
+        .get_median_weight_with_token(30, &token)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        start.elapsed(),
+        DEFAULT_SAMPLE_INTERVAL * 7 + DEFAULT_SAMPLE_INTERVAL / 2
+    );
+    assert_eq!(cancelled_count(error), 7);
+}
@@ -0,0 +1,618 @@
+#![cfg(feature = "cli")]
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+use clap::Parser;
+use libra::calibration::Calibration;
+use libra::cancel::CancelFlag;
+use libra::cli::{
+    parse_mass, run, transfer, watch, CalibrationAction, Cli, CliConfig, CliError, Command, Report,
+    WatchSample, WatchStyle, WatchSummary, WatchView, EXIT_SCALE, EXIT_USAGE,
+};
+use libra::discovery::DiscoveredScale;
+use libra::scale::ScaleError;
+use libra::{Grams, MedianGrams, Scale, ScaleStatus};
+use phidget::ReturnCode;
+
+/// Reads 250 g; its load cells read 0.25 empty and 0.5 once `loaded`.
+#[derive(Default)]
+struct MockScale {
+    loaded: Rc<Cell<bool>>,
+    calibration: Option<Calibration>,
+    fail: Option<fn() -> ScaleError>,
+}
+
+const CALIBRATION: Calibration = Calibration {
+    offset: 12.5,
+    coefficients: [1000.; 4],
+};
+
+impl MockScale {
+    fn ratios(&self) -> [f64; 4] {
+        if self.loaded.get() {
+            [0.5; 4]
+        } else {
+            [0.25; 4]
+        }
+    }
+
+    fn check(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match self.fail {
+            Some(error) => Err(error().into()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.check()?;
+        Ok(Grams(250.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+
+    fn tare(&mut self, _samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(250.))
+    }
+
+    fn zero(
+        &mut self,
+        _samples: usize,
+    ) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(CALIBRATION)
+    }
+
+    fn get_raw_readings(&self) -> Result<[f64; 4], Box<dyn std::error::Error + Send + Sync>> {
+        Ok([0.001, -0.002, 0.003, 0.0045])
+    }
+
+    fn get_raw_medians(
+        &self,
+        _samples: usize,
+    ) -> Result<[f64; 4], Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.ratios())
+    }
+
+    fn set_calibration(
+        &mut self,
+        calibration: Calibration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.calibration = Some(calibration);
+        Ok(())
+    }
+
+    fn status(&mut self) -> Result<ScaleStatus, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ScaleStatus {
+            phidget_id: 716_000,
+            attached: true,
+            calibration: CALIBRATION,
+            tare: Grams(0.),
+            calibration_expires_in_days: None,
+        })
+    }
+}
+
+/// A scale with nothing but weights.
+struct WeightOnly;
+
+impl Scale for WeightOnly {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(1.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(MedianGrams(1.))
+    }
+}
+
+/// Reads `weights` in turn and sets `stop` after the last one.
+struct Scripted {
+    weights: RefCell<VecDeque<f64>>,
+    stop: CancelFlag,
+}
+
+impl Scripted {
+    fn new(weights: &[f64]) -> Self {
+        Self {
+            weights: RefCell::new(weights.iter().copied().collect()),
+            stop: CancelFlag::new(),
+        }
+    }
+}
+
+impl Scale for Scripted {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        let mut weights = self.weights.borrow_mut();
+        let weight = weights.pop_front().ok_or(ScaleError::Busy)?;
+        if weights.is_empty() {
+            self.stop.cancel();
+        }
+        Ok(Grams(weight))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+
+    fn get_raw_readings(&self) -> Result<[f64; 4], Box<dyn std::error::Error + Send + Sync>> {
+        Ok([0.001, -0.002, 0.003, 0.0045])
+    }
+}
+
+/// A load going on and settling, one reading every 100 ms.
+const SETTLING: [f64; 7] = [100., 250.4, 250., 250.2, 250.1, 250.3, 250.2];
+
+fn settling(raw: bool) -> impl Iterator<Item = WatchSample> {
+    SETTLING
+        .into_iter()
+        .enumerate()
+        .map(move |(at, weight)| WatchSample {
+            elapsed: Duration::from_millis(100 * at as u64),
+            weight: Grams(weight),
+            ratios: raw.then_some([0.001, -0.002, 0.003, 0.0045]),
+        })
+}
+
+fn parse(args: &[&str]) -> Cli {
+    Cli::try_parse_from(std::iter::once("libra").chain(args.iter().copied())).unwrap()
+}
+
+fn no_operator(instruction: &str) -> std::io::Result<()> {
+    panic!("unexpected prompt {instruction:?}")
+}
+
+/// Runs `args` against a fresh mock scale.
+fn report(args: &[&str]) -> Report {
+    let cli = parse(args);
+    run(&mut MockScale::default(), &cli.command, &mut no_operator).unwrap()
+}
+
+/// Compares `json` with `tests/snapshots/cli/<name>.json`, or writes it
+/// there when `UPDATE_SNAPSHOTS` is set.
+fn assert_snapshot(name: &str, json: serde_json::Value) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots/cli")
+        .join(format!("{name}.json"));
+    let actual = serde_json::to_string_pretty(&json).unwrap() + "\n";
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, &actual).unwrap();
+    }
+    let expected =
+        std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+    assert_eq!(actual, expected, "{name}");
+}
+
+#[test]
+fn arguments_are_parsed() {
+    let cli = parse(&["median", "--samples", "5", "--interval", "0", "--json"]);
+    assert!(cli.json);
+    assert_eq!(
+        cli.command,
+        Command::Median {
+            samples: 5,
+            interval: 0
+        }
+    );
+    let cli = parse(&["--serial", "716000", "--config", "scale.json", "weight"]);
+    assert_eq!(cli.serial, Some(716_000));
+    assert_eq!(cli.config, Some(PathBuf::from("scale.json")));
+    assert_eq!(cli.command, Command::Weight);
+
+    assert_eq!(parse_mass("500g"), Ok(Grams(500.)));
+    assert_eq!(parse_mass("2kg"), Ok(Grams(2000.)));
+    assert_eq!(parse_mass("750"), Ok(Grams(750.)));
+    for bad in ["", "g", "-5g", "heavy", "NaNg", "1e308kg"] {
+        assert!(parse_mass(bad).is_err(), "{bad:?}");
+    }
+}
+
+#[test]
+fn bad_arguments_are_usage_errors() {
+    for args in [
+        &["calibrate", "--mass", "heavy"][..],
+        &["median", "--samples", "ten"],
+        &["weigh"],
+        &[],
+    ] {
+        let args = std::iter::once("libra").chain(args.iter().copied());
+        let error = Cli::try_parse_from(args).unwrap_err();
+        assert_eq!(error.exit_code(), EXIT_USAGE, "{error}");
+    }
+    let cli = parse(&["tare", "--samples", "0"]);
+    let error = run(&mut MockScale::default(), &cli.command, &mut no_operator).unwrap_err();
+    assert!(matches!(error, CliError::Usage(_)));
+    assert_eq!(error.exit_code(), EXIT_USAGE);
+}
+
+#[test]
+fn reports_match_their_snapshots() {
+    for (name, args) in [
+        ("weight", &["weight"][..]),
+        ("median", &["median", "--samples", "3", "--interval", "0"]),
+        ("tare", &["tare"]),
+        ("zero", &["zero", "--samples", "5"]),
+        ("raw", &["raw"]),
+        ("info", &["info"]),
+    ] {
+        assert_snapshot(name, report(args).to_json());
+    }
+}
+
+#[test]
+fn reports_read_well_as_text() {
+    assert_eq!(report(&["weight"]).to_string(), "250.0 g");
+    assert_eq!(
+        report(&["median", "--samples", "3", "--interval", "0"]).to_string(),
+        "250.0 g (median of 3)"
+    );
+    assert_eq!(report(&["tare"]).to_string(), "Tared 250.0 g");
+    assert_eq!(
+        report(&["raw"]).to_string(),
+        "Load cell 0: 0.001000000\nLoad cell 1: -0.002000000\n\
+         Load cell 2: 0.003000000\nLoad cell 3: 0.004500000"
+    );
+    assert_eq!(
+        report(&["info"]).to_string(),
+        "Phidget: 716000\nAttached: yes\nTare: 0.0 g\nOffset: 12.5\n\
+         Coefficients: [1000.0, 1000.0, 1000.0, 1000.0]"
+    );
+}
+
+#[test]
+fn calibration_walks_the_operator_through_both_points() {
+    let mut scale = MockScale::default();
+    let loaded = Rc::clone(&scale.loaded);
+    let mut prompts = Vec::new();
+    let cli = parse(&["calibrate", "--mass", "500g", "--samples", "3"]);
+    let report = run(&mut scale, &cli.command, &mut |instruction| {
+        prompts.push(instruction.to_owned());
+        loaded.set(prompts.len() == 2);
+        Ok(())
+    })
+    .unwrap();
+
+    assert_eq!(
+        prompts,
+        [
+            "Clear the platform, then press Enter.",
+            "Place 500 g on the platform, then press Enter.",
+        ]
+    );
+    let calibration = Calibration {
+        offset: 500.,
+        coefficients: [500.; 4],
+    };
+    assert_eq!(report, Report::Calibrated(calibration));
+    assert_eq!(report.new_calibration(), Some(calibration));
+    assert_eq!(scale.calibration, Some(calibration));
+    assert_snapshot("calibrate", report.to_json());
+
+    // An operator who walks away stops the calibration.
+    let mut scale = MockScale::default();
+    let error = run(&mut scale, &cli.command, &mut |_| {
+        Err(std::io::ErrorKind::UnexpectedEof.into())
+    })
+    .unwrap_err();
+    assert!(matches!(error, CliError::Io(_)));
+    assert_eq!(scale.calibration, None);
+}
+
+#[test]
+fn scale_errors_have_their_own_exit_code() {
+    let mut scale = MockScale {
+        fail: Some(|| ScaleError::Busy),
+        ..Default::default()
+    };
+    let error = run(&mut scale, &Command::Weight, &mut no_operator).unwrap_err();
+    assert_eq!(error.exit_code(), EXIT_SCALE);
+    assert_snapshot("busy", error.to_json());
+
+    let mut scale = MockScale {
+        fail: Some(|| ScaleError::phidget_error(ReturnCode::NotAttached, 1)),
+        ..Default::default()
+    };
+    let error = run(&mut scale, &Command::Weight, &mut no_operator).unwrap_err();
+    assert_eq!(error.exit_code(), EXIT_SCALE);
+    let json = error.to_json();
+    assert_eq!(json["error"]["kind"], "Phidget");
+    assert_eq!(json["error"]["load_cell"], 1);
+    assert_eq!(json["error"]["return_code"], ReturnCode::NotAttached as i32);
+
+    // Unsupported operations are the scale's limitation, not the caller's.
+    let error = run(&mut WeightOnly, &Command::Raw, &mut no_operator).unwrap_err();
+    assert_eq!(error.exit_code(), EXIT_SCALE);
+    assert_eq!(error.to_json()["error"]["kind"], "Unsupported");
+}
+
+#[test]
+fn configs_round_trip_and_bad_ones_are_usage_errors() {
+    let dir = std::env::temp_dir().join(format!("libra-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("scale.json");
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(CliConfig::load(&path).unwrap(), CliConfig::default());
+    let config = CliConfig {
+        serial: Some(716_000),
+        calibration: Some(CALIBRATION),
+    };
+    config.save(&path).unwrap();
+    assert_eq!(CliConfig::load(&path).unwrap(), config);
+
+    std::fs::write(&path, "{\"serial\": \"seven\"}").unwrap();
+    let error = CliConfig::load(&path).unwrap_err();
+    assert_eq!(error.exit_code(), EXIT_USAGE);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn calibrations_are_exported_and_imported_as_codes() {
+    let config = CliConfig {
+        serial: Some(716_000),
+        calibration: Some(CALIBRATION),
+    };
+    let cli = parse(&["calibration", "export"]);
+    let Command::Calibration { action } = &cli.command else {
+        panic!("{:?}", cli.command);
+    };
+    let report = transfer(action, &config).unwrap();
+    let Report::CalibrationCode(code) = &report else {
+        panic!("{report:?}");
+    };
+    assert_eq!(report.to_string(), *code);
+    assert_eq!(report.new_calibration(), None);
+    assert_snapshot("export", report.to_json());
+
+    let cli = parse(&["calibration", "import", code]);
+    assert_eq!(
+        cli.command,
+        Command::Calibration {
+            action: CalibrationAction::Import { code: code.clone() }
+        }
+    );
+    let Command::Calibration { action } = &cli.command else {
+        unreachable!();
+    };
+    let report = transfer(action, &CliConfig::default()).unwrap();
+    assert_eq!(report, Report::Imported(CALIBRATION));
+    assert_eq!(report.new_calibration(), Some(CALIBRATION));
+    assert_eq!(
+        report.to_string(),
+        "Offset: 12.5
+Coefficients: [1000.0, 1000.0, 1000.0, 1000.0]"
+    );
+
+    let error = transfer(&CalibrationAction::Export, &CliConfig::default()).unwrap_err();
+    assert_eq!(error.exit_code(), EXIT_USAGE);
+    let error = transfer(
+        &CalibrationAction::Import {
+            code: code[..20].into(),
+        },
+        &config,
+    )
+    .unwrap_err();
+    assert_eq!(error.exit_code(), EXIT_USAGE);
+    assert!(
+        error.to_string().contains("is part of it missing?"),
+        "{error}"
+    );
+    let error = run(&mut MockScale::default(), &cli.command, &mut no_operator).unwrap_err();
+    assert!(matches!(error, CliError::Usage(_)));
+}
+
+#[test]
+fn discovered_scales_are_listed_as_a_table() {
+    let cli = parse(&["discover", "--timeout", "250"]);
+    assert_eq!(cli.command, Command::Discover { timeout: 250 });
+    let error = run(&mut MockScale::default(), &cli.command, &mut no_operator).unwrap_err();
+    assert!(matches!(error, CliError::Usage(_)));
+
+    let report = Report::Discovered(vec![
+        DiscoveredScale {
+            serial: 716_000,
+            label: Some("Pass 1".into()),
+            hub_port: None,
+            channels: vec![0, 1, 2, 3],
+        },
+        DiscoveredScale {
+            serial: 716_001,
+            label: None,
+            hub_port: Some(3),
+            channels: vec![0, 1, 2, 3],
+        },
+    ]);
+    assert_eq!(
+        report.to_string(),
+        "Serial    Hub port  Channels  Label\n\
+         716000    -         4         Pass 1\n\
+         716001    3         4         -"
+    );
+    assert_snapshot("discover", report.to_json());
+    assert_eq!(
+        Report::Discovered(Vec::new()).to_string(),
+        "No scales found"
+    );
+}
+
+#[test]
+fn watch_is_parsed_but_not_run() {
+    let cli = parse(&["watch", "--raw", "--log", "weights.csv", "--interval", "50"]);
+    assert_eq!(
+        cli.command,
+        Command::Watch {
+            raw: true,
+            log: Some(PathBuf::from("weights.csv")),
+            interval: 50,
+        }
+    );
+    let error = run(&mut MockScale::default(), &cli.command, &mut no_operator).unwrap_err();
+    assert!(matches!(error, CliError::Usage(_)));
+}
+
+#[test]
+fn plain_output_has_a_line_per_reading() {
+    let mut view = WatchView::new(WatchStyle::Lines, false);
+    let output: String = settling(false).map(|sample| view.push(sample)).collect();
+    assert_eq!(
+        output,
+        concat!(
+            "   0.000 s       100.0 g  moving  min 100.0 g  max 100.0 g\n",
+            "   0.100 s       250.4 g  moving  min 100.0 g  max 250.4 g\n",
+            "   0.200 s       250.0 g  moving  min 100.0 g  max 250.4 g\n",
+            "   0.300 s       250.2 g  moving  min 100.0 g  max 250.4 g\n",
+            "   0.400 s       250.1 g  moving  min 100.0 g  max 250.4 g\n",
+            "   0.500 s       250.3 g  stable  min 100.0 g  max 250.4 g\n",
+            "   0.600 s       250.2 g  stable  min 100.0 g  max 250.4 g\n",
+        )
+    );
+    assert_eq!(view.finish(), "");
+    assert_eq!(
+        view.summary(),
+        WatchSummary {
+            samples: 7,
+            min: Some(Grams(100.)),
+            max: Some(Grams(250.4)),
+            last: Some(Grams(250.2)),
+        }
+    );
+}
+
+#[test]
+fn terminal_output_rewrites_one_line() {
+    let mut view = WatchView::new(WatchStyle::Terminal, true);
+    let lines: Vec<_> = settling(true).map(|sample| view.push(sample)).collect();
+    assert!(lines.iter().all(|line| line.starts_with("\r\x1b[K")));
+    assert!(lines.iter().all(|line| !line.contains('\n')));
+    assert_eq!(
+        lines[6],
+        concat!(
+            "\r\x1b[K     250.2 g  stable  min 100.0 g  max 250.4 g",
+            "    0.001000000   -0.002000000    0.003000000    0.004500000",
+        )
+    );
+    assert_eq!(view.finish(), "\n");
+    assert_eq!(WatchView::new(WatchStyle::Terminal, false).finish(), "");
+}
+
+#[test]
+fn json_output_has_an_object_per_reading() {
+    let mut view = WatchView::new(WatchStyle::Json, true);
+    let lines: Vec<serde_json::Value> = settling(true)
+        .map(|sample| serde_json::from_str(&view.push(sample)).unwrap())
+        .collect();
+    assert_eq!(
+        lines[5],
+        serde_json::json!({
+            "elapsed": 0.5,
+            "weight": 250.3,
+            "stable": true,
+            "min": 100.,
+            "max": 250.4,
+            "ratios": [0.001, -0.002, 0.003, 0.0045],
+        })
+    );
+    assert_eq!(lines[0]["stable"], false);
+}
+
+#[test]
+fn readings_are_logged_as_csv() {
+    let mut view = WatchView::new(WatchStyle::Lines, true);
+    assert_eq!(
+        view.csv_header(),
+        "elapsed_s,weight_g,stable,ratio_0,ratio_1,ratio_2,ratio_3"
+    );
+    assert_eq!(view.csv_row(), None);
+    let rows: Vec<_> = settling(true)
+        .map(|sample| {
+            view.push(sample);
+            view.csv_row().unwrap()
+        })
+        .collect();
+    assert_eq!(rows[0], "0.000,100,false,0.001,-0.002,0.003,0.0045");
+    assert_eq!(rows[6], "0.600,250.2,true,0.001,-0.002,0.003,0.0045");
+    assert_eq!(
+        WatchView::new(WatchStyle::Lines, false).csv_header(),
+        "elapsed_s,weight_g,stable"
+    );
+}
+
+#[test]
+fn watching_runs_until_stopped_and_summarises() {
+    let scale = Scripted::new(&SETTLING);
+    let mut view = WatchView::new(WatchStyle::Terminal, false);
+    let (mut out, mut log) = (Vec::new(), Vec::new());
+    let report = watch(
+        &scale,
+        &mut view,
+        Duration::ZERO,
+        &mut out,
+        Some(&mut log),
+        &scale.stop,
+    )
+    .unwrap();
+
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out.matches("\r\x1b[K").count(), SETTLING.len());
+    assert!(out.ends_with("250.2 g  stable  min 100.0 g  max 250.4 g\n"));
+    assert_eq!(
+        String::from_utf8(log).unwrap().lines().count(),
+        SETTLING.len()
+    );
+    assert_eq!(
+        report.to_string(),
+        "7 samples, last 250.2 g, min 100.0 g, max 250.4 g"
+    );
+    assert_eq!(
+        report.to_json(),
+        serde_json::json!({"samples": 7, "last": 250.2, "min": 100., "max": 250.4})
+    );
+
+    // Stopped before the first reading.
+    let stop = CancelFlag::new();
+    stop.cancel();
+    let mut view = WatchView::new(WatchStyle::Terminal, false);
+    let mut out = Vec::new();
+    let report = watch(&scale, &mut view, Duration::ZERO, &mut out, None, &stop).unwrap();
+    assert!(out.is_empty());
+    assert_eq!(report.to_string(), "No samples");
+}
+
+#[test]
+fn a_failed_reading_ends_the_watch() {
+    let scale = Scripted::new(&[]);
+    let mut view = WatchView::new(WatchStyle::Lines, false);
+    let error = watch(
+        &scale,
+        &mut view,
+        Duration::ZERO,
+        &mut Vec::new(),
+        None,
+        &CancelFlag::new(),
+    )
+    .unwrap_err();
+    assert_eq!(error.exit_code(), EXIT_SCALE);
+}
+
+#[cfg(feature = "schema")]
+#[test]
+fn schemas_need_no_scale() {
+    let dir = std::env::temp_dir().join(format!("libra-cli-schema-{}", std::process::id()));
+    let cli = parse(&["schema", dir.to_str().unwrap()]);
+    let report = run(&mut WeightOnly, &cli.command, &mut no_operator).unwrap();
+    let Report::Exported(paths) = &report else {
+        panic!("{report:?}");
+    };
+    assert!(paths.iter().all(|path| path.exists()));
+    assert_eq!(
+        report.to_json()["files"].as_array().unwrap().len(),
+        paths.len()
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,183 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use libra::composite::CompositeScale;
+use libra::scale::ScaleError;
+use libra::{Grams, MedianGrams, Scale, ScaleErrorKind};
+
+const READ_TIME: Duration = Duration::from_millis(100);
+
+#[derive(Default)]
+struct Log {
+    /// When each read started.
+    reads: Mutex<Vec<Instant>>,
+    tares: AtomicUsize,
+    closes: AtomicUsize,
+}
+
+/// Weighs its scripted readings in turn, then `weight`, each read taking
+/// `delay`.
+struct Platform {
+    weight: f64,
+    scripted: Mutex<VecDeque<Result<f64, ScaleError>>>,
+    delay: Duration,
+    log: Arc<Log>,
+}
+
+impl Platform {
+    fn new(weight: f64) -> Self {
+        Self {
+            weight,
+            scripted: Mutex::default(),
+            delay: Duration::ZERO,
+            log: Arc::default(),
+        }
+    }
+
+    fn scripted(self, readings: impl IntoIterator<Item = Result<f64, ScaleError>>) -> Self {
+        *self.scripted.lock().unwrap() = readings.into_iter().collect();
+        self
+    }
+
+    fn slow(self) -> Self {
+        Self {
+            delay: READ_TIME,
+            ..self
+        }
+    }
+}
+
+impl Scale for Platform {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.log.reads.lock().unwrap().push(Instant::now());
+        std::thread::sleep(self.delay);
+        match self.scripted.lock().unwrap().pop_front() {
+            Some(reading) => Ok(Grams(reading?)),
+            None => Ok(Grams(self.weight)),
+        }
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_median_weight_of(3)
+    }
+
+    fn tare(&mut self, _samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.log.tares.fetch_add(1, Ordering::SeqCst);
+        Ok(Grams(self.weight))
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.log.closes.fetch_add(1, Ordering::SeqCst);
+        Err(Box::new(ScaleError::Busy))
+    }
+}
+
+fn scale_error(error: Box<dyn std::error::Error + Send + Sync>) -> ScaleError {
+    *error.downcast::<ScaleError>().unwrap()
+}
+
+#[test]
+fn a_sum_reads_every_member_at_once() {
+    let conveyor = CompositeScale::sum(vec![
+        Platform::new(1200.).slow(),
+        Platform::new(800.).slow(),
+    ])
+    .unwrap();
+    assert_eq!(conveyor.member_count(), 2);
+    let start = Instant::now();
+    assert_eq!(conveyor.get_weight().unwrap(), Grams(2000.));
+    let elapsed = start.elapsed();
+    assert!(elapsed < READ_TIME * 2, "took {elapsed:?}");
+
+    let error = CompositeScale::<Platform>::sum(Vec::new()).err().unwrap();
+    assert!(matches!(error, ScaleError::InvalidArgument(_)), "{error:?}");
+}
+
+#[test]
+fn a_difference_takes_the_second_from_the_first() {
+    let scale = CompositeScale::difference(Platform::new(1200.), Platform::new(800.));
+    assert_eq!(scale.get_weight().unwrap(), Grams(400.));
+}
+
+#[test]
+fn a_failing_member_is_named() {
+    let conveyor = CompositeScale::sum(vec![
+        Platform::new(1200.),
+        Platform::new(800.).scripted([Err(ScaleError::Disconnected { channel: Some(2) })]),
+    ])
+    .unwrap();
+    let error = scale_error(conveyor.get_weight().unwrap_err());
+    assert!(
+        matches!(&error, ScaleError::MemberFailed { member: 1, error }
+            if matches!(**error, ScaleError::Disconnected { channel: Some(2) })),
+        "{error:?}"
+    );
+    assert_eq!(error.kind(), ScaleErrorKind::Disconnected);
+    assert!(error.is_disconnection());
+    assert_eq!(
+        error.to_string(),
+        "Member 1 of the composite scale failed: Scale is disconnected at Load Cell 2"
+    );
+
+    // The member recovers, and so does the composite.
+    assert_eq!(conveyor.get_weight().unwrap(), Grams(2000.));
+}
+
+#[test]
+fn medians_are_of_samples_taken_together() {
+    let first = Platform::new(0.).scripted([Ok(1.), Ok(100.), Ok(3.)]);
+    let second = Platform::new(0.).scripted([Ok(10.), Ok(10.), Ok(-90.)]);
+    let logs = [Arc::clone(&first.log), Arc::clone(&second.log)];
+    let conveyor = CompositeScale::sum(vec![first, second])
+        .unwrap()
+        .with_sample_interval(Duration::from_millis(20));
+
+    // The sums are 11, 110 and -87; the medians of the members apart would
+    // have made 3 + 10.
+    assert_eq!(conveyor.get_median_weight_of(3).unwrap(), MedianGrams(11.));
+    let [first, second] = logs.map(|log| log.reads.lock().unwrap().clone());
+    assert_eq!((first.len(), second.len()), (3, 3));
+    for (a, b) in first.iter().zip(&second) {
+        let apart = a.max(b).duration_since(*a.min(b));
+        assert!(apart < Duration::from_millis(10), "{apart:?} apart");
+    }
+}
+
+#[test]
+fn the_tare_is_held_by_the_composite() {
+    let first = Platform::new(1200.);
+    let second = Platform::new(800.);
+    let logs = [Arc::clone(&first.log), Arc::clone(&second.log)];
+    let mut conveyor = CompositeScale::sum(vec![first, second])
+        .unwrap()
+        .with_sample_interval(Duration::ZERO);
+
+    assert_eq!(Scale::tare(&mut conveyor, 3).unwrap(), Grams(2000.));
+    assert_eq!(conveyor.tare_weight(), Grams(2000.));
+    assert_eq!(conveyor.get_weight().unwrap(), Grams(0.));
+    for log in &logs {
+        assert_eq!(log.tares.load(Ordering::SeqCst), 0);
+    }
+
+    // A second tare is of the gross weight, not of what is left.
+    assert_eq!(Scale::tare(&mut conveyor, 3).unwrap(), Grams(2000.));
+    conveyor.clear_tare();
+    assert_eq!(conveyor.get_weight().unwrap(), Grams(2000.));
+
+    let mut scale = CompositeScale::difference(Platform::new(1200.), Platform::new(800.));
+    assert_eq!(Scale::tare(&mut scale, 1).unwrap(), Grams(400.));
+    assert_eq!(scale.get_weight().unwrap(), Grams(0.));
+
+    // Each member is closed, though the first fails to close.
+    let error = scale_error(conveyor.close().unwrap_err());
+    assert!(
+        matches!(error, ScaleError::MemberFailed { member: 0, .. }),
+        "{error:?}"
+    );
+    for log in &logs {
+        assert_eq!(log.closes.load(Ordering::SeqCst), 1);
+    }
+    assert_eq!(conveyor.into_members().len(), 2);
+}
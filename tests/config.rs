@@ -0,0 +1,1031 @@
+use std::path::PathBuf;
+
+use libra::calibration::Calibration;
+use libra::config::{
+    BridgeGain, ConfigFileError, ConfigProblem, ConfigWarning, ConfigWatcher, FleetConfig,
+    SamplingDefaults, ScaleConfig, ScaleConfigDefaults,
+};
+
+#[test]
+fn the_default_config_is_valid() {
+    assert_eq!(ScaleConfig::default().validate(), Ok(()));
+}
+
+#[test]
+fn every_problem_is_listed() {
+    let config = ScaleConfig {
+        serial: Some(-3),
+        label: Some("  ".into()),
+        hub_port: Some(-1),
+        calibration: Calibration {
+            offset: f64::NAN,
+            coefficients: [1., f64::INFINITY, 1., f64::NAN],
+        },
+        data_interval_ms: 0,
+        bridge_gain: None,
+        connect_timeout_ms: 5 * 60 * 60 * 1000,
+        capacity_g: Some(0.),
+        sampling: SamplingDefaults {
+            median_samples: 0,
+            sample_interval_ms: 100,
+        },
+    };
+    let error = config.validate().unwrap_err();
+    let keys: Vec<_> = error
+        .problems
+        .iter()
+        .map(|problem| problem.key.as_str())
+        .collect();
+    assert_eq!(
+        keys,
+        [
+            "serial",
+            "label",
+            "hub_port",
+            "calibration.offset",
+            "calibration.coefficients[1]",
+            "calibration.coefficients[3]",
+            "data_interval_ms",
+            "connect_timeout_ms",
+            "capacity_g",
+            "sampling.median_samples",
+        ]
+    );
+    assert_eq!(
+        error.problems[7],
+        ConfigProblem {
+            key: "connect_timeout_ms".into(),
+            message: "18000000 ms is over the limit of 3600s; is it in the wrong unit?".into(),
+        }
+    );
+    assert!(error
+        .to_string()
+        .starts_with("Invalid scale config: serial: -3 is not a serial number; label: "));
+}
+
+#[test]
+fn missing_fields_take_their_defaults() {
+    let config: ScaleConfig = serde_json::from_str(
+        r#"{"serial": 716000, "capacity_g": 5000, "sampling": {"median_samples": 5}}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        config,
+        ScaleConfig {
+            serial: Some(716_000),
+            capacity_g: Some(5000.),
+            sampling: SamplingDefaults {
+                median_samples: 5,
+                ..SamplingDefaults::default()
+            },
+            ..ScaleConfig::default()
+        }
+    );
+    let json = serde_json::to_string(&config).unwrap();
+    assert_eq!(serde_json::from_str::<ScaleConfig>(&json).unwrap(), config);
+}
+
+#[test]
+fn unknown_fields_are_ignored() {
+    let config = serde_json::from_str::<ScaleConfig>(r#"{"serial_number": 716000}"#).unwrap();
+    assert_eq!(config, ScaleConfig::default());
+}
+
+/// Writes `toml` to a file of its own for the test `name`.
+fn config_file(name: &str, toml: &str) -> PathBuf {
+    let path =
+        std::env::temp_dir().join(format!("libra-config-{}-{name}.toml", std::process::id()));
+    std::fs::write(&path, toml).unwrap();
+    path
+}
+
+#[test]
+fn the_example_reads_as_the_default() {
+    let (config, warnings) = ScaleConfig::from_toml(&ScaleConfig::example_toml()).unwrap();
+    assert_eq!(config, ScaleConfig::default());
+    assert!(warnings.is_empty(), "{warnings:?}");
+}
+
+#[test]
+fn bridge_gains_are_read_for_every_channel_or_each() {
+    let (config, _) = ScaleConfig::from_toml("bridge_gain = 64\n").unwrap();
+    assert_eq!(config.bridge_gain, Some([BridgeGain::Gain64; 4]));
+    let (config, _) = ScaleConfig::from_toml("bridge_gain = [128, 128, 8, 1]\n").unwrap();
+    assert_eq!(
+        config.bridge_gain,
+        Some([
+            BridgeGain::Gain128,
+            BridgeGain::Gain128,
+            BridgeGain::Gain8,
+            BridgeGain::Gain1
+        ])
+    );
+    let config = config
+        .with_vars("LIBRA", [("LIBRA_BRIDGE_GAIN", "16, 16, 32, 32")])
+        .unwrap();
+    assert_eq!(
+        config
+            .bridge_gain
+            .map(|gains| gains.map(BridgeGain::factor)),
+        Some([16, 16, 32, 32])
+    );
+    assert_eq!(
+        config
+            .with_vars("LIBRA", [("LIBRA_BRIDGE_GAIN", "")])
+            .unwrap()
+            .bridge_gain,
+        None
+    );
+
+    for (toml, message) in [
+        (
+            "bridge_gain = 100\n",
+            "100 is not a bridge gain, which is 1, 2, 4, 8, 16, 32, 64 or 128",
+        ),
+        (
+            "bridge_gain = [128, 128]\n",
+            "invalid length 2, expected an array of length 4",
+        ),
+    ] {
+        let error = ScaleConfig::from_toml(toml).unwrap_err();
+        assert!(
+            matches!(&error, ConfigFileError::Parse { line: 1, column: 15, message: found, .. }
+                if found == message),
+            "{error:?}"
+        );
+    }
+    assert!(serde_json::from_str::<ScaleConfig>(r#"{"bridge_gain": [1, 2, 3, 4]}"#).is_err());
+}
+
+#[test]
+fn a_config_written_out_reads_back_the_same() {
+    let config = ScaleConfig {
+        serial: Some(716_000),
+        label: Some("Pass 1".into()),
+        hub_port: Some(2),
+        calibration: Calibration {
+            offset: -12.5,
+            coefficients: [1000., 1000.5, 999., 1e-7],
+        },
+        data_interval_ms: 16,
+        bridge_gain: Some([
+            BridgeGain::Gain128,
+            BridgeGain::Gain64,
+            BridgeGain::Gain128,
+            BridgeGain::Gain128,
+        ]),
+        connect_timeout_ms: 2500,
+        capacity_g: Some(5000.),
+        sampling: SamplingDefaults {
+            median_samples: 7,
+            sample_interval_ms: 10,
+        },
+    };
+    let toml = config.to_toml();
+    assert!(
+        toml.contains("bridge_gain = [128, 64, 128, 128]\n"),
+        "{toml}"
+    );
+    let (read, warnings) = ScaleConfig::from_toml(&toml).unwrap();
+    assert_eq!(read, config);
+    assert!(warnings.is_empty(), "{warnings:?}");
+
+    let default = ScaleConfig::default().to_toml();
+    assert!(!default.contains("serial") && !default.contains("bridge_gain"));
+    assert_eq!(
+        ScaleConfig::from_toml(&default).unwrap().0,
+        ScaleConfig::default()
+    );
+
+    for label in [
+        "Pass \"1\"",
+        "C:\\scales\\1",
+        "by the\nfryer",
+        "'''\"\"\"\\\n",
+        "# not a comment",
+    ] {
+        let config = ScaleConfig {
+            label: Some(label.into()),
+            ..config.clone()
+        };
+        let (read, _) = ScaleConfig::from_toml(&config.to_toml()).unwrap();
+        assert_eq!(read, config, "{label:?}");
+    }
+}
+
+#[test]
+fn the_environment_overrides_the_file() {
+    let path = config_file(
+        "precedence",
+        "serial = 716_000\n\
+         label = \"Pass 1\" # by the fryer\n\
+         hub_port = 2\n\
+         data_interval_ms = 16\n\
+         \n\
+         [calibration]\n\
+         offset = 12.5\n\
+         coefficients = [1000, 1000.5, 999, 1000]\n\
+         \n\
+         [sampling]\n\
+         median_samples = 7\n",
+    );
+    let (file, warnings) = ScaleConfig::from_toml_file(&path).unwrap();
+    assert!(warnings.is_empty());
+    let config = file
+        .with_vars(
+            "LIBRA",
+            [
+                ("LIBRA_SERIAL", "716001"),
+                ("LIBRA_HUB_PORT", ""),
+                ("LIBRA_LABEL", " Pass 2 "),
+                ("LIBRA_CALIBRATION_COEFFICIENTS", "2000, 2000, 2000, 2000"),
+                ("LIBRA_CAPACITY_G", "5000"),
+                ("OTHER_SERIAL", "1"),
+            ],
+        )
+        .unwrap();
+    assert_eq!(
+        config,
+        ScaleConfig {
+            serial: Some(716_001),
+            label: Some("Pass 2".into()),
+            hub_port: None,
+            calibration: Calibration {
+                offset: 12.5,
+                coefficients: [2000.; 4],
+            },
+            data_interval_ms: 16,
+            capacity_g: Some(5000.),
+            sampling: SamplingDefaults {
+                median_samples: 7,
+                ..SamplingDefaults::default()
+            },
+            ..ScaleConfig::default()
+        }
+    );
+
+    std::env::set_var("LIBRA_CONFIG_TEST_SAMPLING_MEDIAN_SAMPLES", "9");
+    let config = ScaleConfig::from_env("LIBRA_CONFIG_TEST").unwrap();
+    assert_eq!(config.sampling.median_samples, 9);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn a_malformed_file_names_the_line_and_column() {
+    let path = config_file(
+        "malformed",
+        "serial = 716000\n\
+         \n  data_interval_ms = ten\n",
+    );
+    let error = ScaleConfig::from_toml_file(&path).unwrap_err();
+    assert!(
+        matches!(
+            error,
+            ConfigFileError::Parse {
+                line: 3,
+                column: 22,
+                ..
+            }
+        ),
+        "{error:?}"
+    );
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "{}: line 3, column 22: invalid boolean, expected `true`",
+            path.display()
+        )
+    );
+    std::fs::remove_file(&path).unwrap();
+
+    let error = ScaleConfig::from_toml_file(&path).unwrap_err();
+    assert!(matches!(error, ConfigFileError::Read { .. }), "{error:?}");
+    assert!(error.to_string().contains(&*path.to_string_lossy()));
+
+    for (toml, message) in [
+        (
+            "serial = \"one\"",
+            "line 1, column 10: invalid type: string \"one\", expected i32",
+        ),
+        (
+            "hub_port = 1\nhub_port = 2",
+            "line 2, column 1: duplicate key",
+        ),
+        (
+            "[calibration]\ncoefficients = [1, 2]",
+            "line 2, column 16: invalid length 2, expected an array of length 4",
+        ),
+        (
+            "data_interval_ms = -8",
+            "line 1, column 20: invalid value: integer `-8`, expected u64",
+        ),
+        (
+            "[sampling",
+            "line 1, column 10: unclosed table, expected `]`",
+        ),
+    ] {
+        assert_eq!(
+            ScaleConfig::from_toml(toml).unwrap_err().to_string(),
+            message
+        );
+    }
+}
+
+#[test]
+fn unknown_keys_warn_rather_than_fail() {
+    let (config, warnings) = ScaleConfig::from_toml(
+        "serial_number = 716000\n\
+         serial = 716000\n\
+         [display]\n\
+         units = \"g\"\n\
+         [sampling]\n\
+         median_samples = 3\n\
+         median = 5\n",
+    )
+    .unwrap();
+    assert_eq!(config.serial, Some(716_000));
+    assert_eq!(config.sampling.median_samples, 3);
+    assert_eq!(
+        warnings,
+        [
+            ConfigWarning {
+                line: 1,
+                key: "serial_number".into(),
+            },
+            ConfigWarning {
+                line: 3,
+                key: "display".into(),
+            },
+            ConfigWarning {
+                line: 7,
+                key: "sampling.median".into(),
+            },
+        ]
+    );
+    assert_eq!(
+        warnings[0].to_string(),
+        "line 1: unknown key `serial_number` ignored"
+    );
+}
+
+#[test]
+fn any_toml_is_read() {
+    let (config, warnings) = ScaleConfig::from_toml(
+        "label = \"Pass \\\"1\\\"\\tleft\\\\right\" # a comment\n\
+         tags = ['raw \\n', \"#1\"]\n\
+         [calibration]\n\
+         offset = 0\n\
+         coefficients = [\n\
+         \x20   1000, # the first\n\
+         \x20   1000.5,\n\
+         \x20   999,\n\
+         \x20   1e-7,\n\
+         ]\n",
+    )
+    .unwrap();
+    assert_eq!(config.label.as_deref(), Some("Pass \"1\"\tleft\\right"));
+    assert_eq!(config.calibration.coefficients, [1000., 1000.5, 999., 1e-7]);
+    assert_eq!(
+        warnings,
+        [ConfigWarning {
+            line: 2,
+            key: "tags".into(),
+        }]
+    );
+
+    let (config, _) = ScaleConfig::from_toml("label = 'C:\\scales\\\"1\"'\n").unwrap();
+    assert_eq!(config.label.as_deref(), Some("C:\\scales\\\"1\""));
+}
+
+#[test]
+fn problems_say_where_their_value_was_set() {
+    let error = ScaleConfig::from_toml("label = \"Pass 1\"\nserial = -3\n").unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Invalid scale config: serial: -3 is not a serial number (line 2)"
+    );
+
+    let error = ScaleConfig::default()
+        .with_vars("LIBRA", [("LIBRA_SAMPLING_MEDIAN_SAMPLES", "0")])
+        .unwrap_err();
+    let ConfigFileError::Invalid { error, .. } = error else {
+        panic!("{error:?}");
+    };
+    assert_eq!(
+        error.problems,
+        [ConfigProblem {
+            key: "sampling.median_samples".into(),
+            message: "must be at least 1 (from LIBRA_SAMPLING_MEDIAN_SAMPLES)".into(),
+        }]
+    );
+
+    let error = ScaleConfig::default()
+        .with_vars("LIBRA", [("LIBRA_DATA_INTERVAL_MS", "")])
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "LIBRA_DATA_INTERVAL_MS: `data_interval_ms` must be set, and cannot be empty"
+    );
+}
+
+#[test]
+fn a_watcher_reads_each_new_version_once() {
+    let path = config_file("watch", "serial = 716000\n");
+    let mut watcher = ConfigWatcher::new(&path);
+    let (config, _) = watcher.poll().unwrap().unwrap();
+    assert_eq!(config.serial, Some(716_000));
+    assert!(watcher.poll().unwrap().is_none());
+
+    // Written again, a second later as far as the file system knows.
+    let later = |path: &PathBuf| {
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        let modified = file.metadata().unwrap().modified().unwrap();
+        file.set_modified(modified + std::time::Duration::from_secs(1))
+            .unwrap();
+    };
+    std::fs::write(&path, "serial = 716001\n").unwrap();
+    later(&path);
+    let (config, _) = watcher.poll().unwrap().unwrap();
+    assert_eq!(config.serial, Some(716_001));
+
+    std::fs::write(&path, "serial = \n").unwrap();
+    later(&path);
+    assert!(matches!(
+        watcher.poll(),
+        Err(ConfigFileError::Parse { line: 1, .. })
+    ));
+    assert!(watcher.poll().unwrap().is_none());
+
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(watcher.poll(), Err(ConfigFileError::Read { .. })));
+}
+
+const FLEET: &str = r#"
+[defaults]
+data_interval_ms = 16
+capacity_g = 5000.0
+
+[defaults.sampling]
+median_samples = 5
+
+[scales.hopper_a]
+serial = 716000
+label = "Hopper A"
+
+[scales.hopper_b]
+serial = 716001
+label = "Hopper B"
+capacity_g = 2000.0
+
+[scales.hopper_b.sampling]
+sample_interval_ms = 10
+
+[scales.catch_weigher]
+serial = 716002
+data_interval_ms = 8
+
+[scales.catch_weigher.calibration]
+offset = 1.5
+coefficients = [1.0, 1.0, 1.0, 1.0]
+"#;
+
+#[test]
+fn scales_take_the_defaults_they_do_not_override() {
+    let (fleet, warnings) = FleetConfig::from_toml(FLEET).unwrap();
+    assert!(warnings.is_empty(), "{warnings:?}");
+    let names: Vec<_> = fleet.scales.keys().map(String::as_str).collect();
+    assert_eq!(names, ["catch_weigher", "hopper_a", "hopper_b"]);
+
+    let defaults = fleet.defaults.clone().unwrap();
+    assert_eq!(
+        defaults,
+        ScaleConfigDefaults {
+            data_interval_ms: Some(16),
+            capacity_g: Some(5000.),
+            sampling: Some(SamplingDefaults {
+                median_samples: 5,
+                ..SamplingDefaults::default()
+            }),
+            ..ScaleConfigDefaults::default()
+        }
+    );
+    assert_eq!(
+        fleet.scales["hopper_a"],
+        ScaleConfig {
+            serial: Some(716_000),
+            label: Some("Hopper A".into()),
+            ..defaults.config()
+        }
+    );
+    let hopper_b = &fleet.scales["hopper_b"];
+    assert_eq!(hopper_b.capacity_g, Some(2000.));
+    assert_eq!(
+        hopper_b.sampling,
+        SamplingDefaults {
+            median_samples: 5,
+            sample_interval_ms: 10,
+        }
+    );
+    let catch_weigher = &fleet.scales["catch_weigher"];
+    assert_eq!(catch_weigher.data_interval_ms, 8);
+    assert_eq!(catch_weigher.capacity_g, Some(5000.));
+    assert_eq!(catch_weigher.calibration.offset, 1.5);
+    assert_eq!(fleet.scales["hopper_a"].calibration.offset, 0.);
+
+    // The defaults may come after the scales that take them.
+    let (fleet, _) =
+        FleetConfig::from_toml("[scales.a]\nserial = 1\n[defaults]\nconnect_timeout_ms = 250\n")
+            .unwrap();
+    assert_eq!(fleet.scales["a"].connect_timeout_ms, 250);
+}
+
+#[test]
+fn scales_may_not_share_a_serial_or_label() {
+    let toml = "[defaults]\ncapacity_g = -1.0\n\n\
+                [scales.a]\nserial = 716000\nlabel = \"Hopper\"\n\n\
+                [scales.b]\nserial = 716000\nlabel = \"Hopper\"\ncapacity_g = 100.0\n";
+    let error = FleetConfig::from_toml(toml).unwrap_err();
+    let ConfigFileError::Invalid { error, .. } = error else {
+        panic!("{error:?}");
+    };
+    let problems: Vec<_> = error
+        .problems
+        .iter()
+        .map(ConfigProblem::to_string)
+        .collect();
+    assert_eq!(
+        problems,
+        [
+            "scales.a.capacity_g: -1 is not a positive number of grams (line 2)",
+            "scales.b.serial: 716000 is also the serial of `a` (line 9)",
+            "scales.b.label: \"Hopper\" is also the label of `a` (line 10)",
+        ]
+    );
+
+    let error = FleetConfig::from_toml("[defaults]\nserial = 716000\n").unwrap_err();
+    assert!(
+        matches!(&error, ConfigFileError::Parse { line: 2, column: 1, message, .. }
+            if message == "`serial` cannot be a default; give it to each scale"),
+        "{error:?}"
+    );
+}
+
+#[test]
+fn unknown_fleet_keys_warn() {
+    let toml = "slack = 1\n[scales.a]\nserail = 716000\n[scales.a.tuning]\ngain = 2\n";
+    let (fleet, warnings) = FleetConfig::from_toml(toml).unwrap();
+    assert_eq!(fleet.scales["a"], ScaleConfig::default());
+    assert_eq!(fleet.defaults, None);
+    let warnings: Vec<_> = warnings.iter().map(ConfigWarning::to_string).collect();
+    assert_eq!(
+        warnings,
+        [
+            "line 1: unknown key `slack` ignored",
+            "line 3: unknown key `scales.a.serail` ignored",
+            "line 4: unknown key `scales.a.tuning` ignored",
+        ]
+    );
+    let error = FleetConfig::from_toml("[scales.\"a b\"]\n").unwrap_err();
+    assert!(
+        matches!(error, ConfigFileError::Parse { line: 1, .. }),
+        "{error:?}"
+    );
+}
+
+#[cfg(feature = "hardware")]
+mod connect {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use libra::calibration::Calibration;
+    use libra::config::{BridgeGain, FleetConfig, SamplingDefaults, ScaleConfig, SettingMismatch};
+    use libra::scale::{ScaleError, NUMBER_OF_INPUTS};
+    use libra::source::VoltageSource;
+    use libra::Grams;
+    use phidget::{ChannelClass, Phidget, ReturnCode};
+
+    /// What the mock phidget library does, and what was asked of it.
+    #[derive(Default)]
+    struct Device {
+        serial_number: i32,
+        /// Fails `set_data_interval` when set.
+        rejects_intervals: bool,
+        /// Serial numbers of bridges that never answer.
+        missing: Vec<i32>,
+        /// Serial numbers, hub ports and channels of the handles opened.
+        opened: Vec<(Option<i32>, Option<i32>, i32)>,
+        data_intervals: Vec<Duration>,
+        /// The shortest data interval taken; shorter ones are clamped to it.
+        min_interval: Option<Duration>,
+        /// The gain of each channel, if set.
+        gains: [Option<BridgeGain>; NUMBER_OF_INPUTS],
+        closed: usize,
+    }
+
+    /// A `VoltageRatioInput` stand-in that never reaches the phidget library,
+    /// reading a ratio of 0.1 on every channel.
+    struct MockInput {
+        device: Arc<Mutex<Device>>,
+        serial_number: Option<i32>,
+        hub_port: Option<i32>,
+        channel: i32,
+    }
+
+    impl Phidget for MockInput {
+        fn as_handle(&mut self) -> phidget::ffi::PhidgetHandle {
+            std::ptr::null_mut()
+        }
+
+        fn set_serial_number(&mut self, sn: i32) -> phidget::Result<()> {
+            self.serial_number = Some(sn);
+            Ok(())
+        }
+
+        fn serial_number(&mut self) -> phidget::Result<i32> {
+            Ok(self.device.lock().unwrap().serial_number)
+        }
+
+        fn set_hub_port(&mut self, port: i32) -> phidget::Result<()> {
+            self.hub_port = Some(port);
+            Ok(())
+        }
+
+        fn set_channel(&mut self, chan: i32) -> phidget::Result<()> {
+            self.channel = chan;
+            Ok(())
+        }
+
+        fn open_wait(&mut self, _to: Duration) -> phidget::Result<()> {
+            let device = self.device.lock().unwrap();
+            if self
+                .serial_number
+                .is_some_and(|serial| device.missing.contains(&serial))
+            {
+                return Err(ReturnCode::Timeout);
+            }
+            drop(device);
+            let opened = (self.serial_number, self.hub_port, self.channel);
+            self.device.lock().unwrap().opened.push(opened);
+            Ok(())
+        }
+
+        fn close(&mut self) -> phidget::Result<()> {
+            self.device.lock().unwrap().closed += 1;
+            Ok(())
+        }
+
+        fn channel_class(&mut self) -> phidget::Result<ChannelClass> {
+            Ok(ChannelClass::VoltageRatioInput)
+        }
+
+        fn device_channel_count(&mut self, _cls: ChannelClass) -> phidget::Result<u32> {
+            Ok(NUMBER_OF_INPUTS as u32)
+        }
+    }
+
+    impl VoltageSource for MockInput {
+        fn ratio(&self) -> phidget::Result<f64> {
+            Ok(0.1)
+        }
+
+        fn set_data_interval(&mut self, interval: Duration) -> phidget::Result<()> {
+            let mut device = self.device.lock().unwrap();
+            if device.rejects_intervals {
+                return Err(ReturnCode::InvalidArg);
+            }
+            let interval = device
+                .min_interval
+                .map_or(interval, |min| interval.max(min));
+            device.data_intervals.push(interval);
+            Ok(())
+        }
+
+        fn data_interval(&mut self) -> phidget::Result<Duration> {
+            Ok(*self.device.lock().unwrap().data_intervals.last().unwrap())
+        }
+
+        fn set_bridge_gain(&mut self, gain: BridgeGain) -> phidget::Result<()> {
+            self.device.lock().unwrap().gains[self.channel as usize] = Some(gain);
+            Ok(())
+        }
+
+        fn bridge_gain(&mut self) -> phidget::Result<BridgeGain> {
+            let gain = self.device.lock().unwrap().gains[self.channel as usize];
+            Ok(gain.unwrap_or(BridgeGain::Gain128))
+        }
+
+        fn serial_number(&mut self) -> phidget::Result<i32> {
+            Phidget::serial_number(self)
+        }
+
+        fn is_attached(&mut self) -> phidget::Result<bool> {
+            Ok(true)
+        }
+
+        fn close(&mut self) -> phidget::Result<()> {
+            Phidget::close(self)
+        }
+
+        fn on_ratio_change(
+            &mut self,
+            _callback: Box<dyn Fn(f64) + Send + Sync>,
+        ) -> phidget::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn device(serial_number: i32) -> Arc<Mutex<Device>> {
+        Arc::new(Mutex::new(Device {
+            serial_number,
+            ..Device::default()
+        }))
+    }
+
+    fn input(device: &Arc<Mutex<Device>>) -> impl FnMut() -> MockInput + '_ {
+        || MockInput {
+            device: device.clone(),
+            serial_number: None,
+            hub_port: None,
+            channel: -1,
+        }
+    }
+
+    fn config(serial: Option<i32>) -> ScaleConfig {
+        ScaleConfig {
+            serial,
+            hub_port: Some(2),
+            calibration: Calibration {
+                offset: 5.,
+                coefficients: [1000.; NUMBER_OF_INPUTS],
+            },
+            data_interval_ms: 16,
+            capacity_g: Some(1000.),
+            sampling: SamplingDefaults {
+                median_samples: 3,
+                sample_interval_ms: 0,
+            },
+            ..ScaleConfig::default()
+        }
+    }
+
+    #[test]
+    fn connecting_sets_up_the_whole_scale() {
+        let device = device(0);
+        let mut scale = config(Some(717_001)).connect_with(input(&device)).unwrap();
+        {
+            let device = device.lock().unwrap();
+            assert_eq!(
+                device.opened,
+                [0, 1, 2, 3].map(|channel| (Some(717_001), Some(2), channel))
+            );
+            assert_eq!(device.data_intervals, [Duration::from_millis(16); 4]);
+            assert_eq!(device.gains, [None; NUMBER_OF_INPUTS]);
+        }
+        assert!(scale.connect_mismatches().is_empty());
+        assert_eq!(scale.get_phidget_id(), 717_001);
+        // 1000 · 4 · 0.1 - 5
+        assert_eq!(scale.get_weight().unwrap(), Grams(395.));
+        assert_eq!(scale.capacity(), Some(Grams(1000.)));
+        assert_eq!(scale.sampling().median_samples, 3);
+
+        let error = config(Some(717_001))
+            .connect_with(input(&device))
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error,
+            ScaleError::AlreadyConnected { serial: 717_001 }
+        ));
+        scale.close().unwrap();
+        config(Some(717_001))
+            .connect_with(input(&device))
+            .unwrap()
+            .close()
+            .unwrap();
+    }
+
+    #[test]
+    fn without_a_serial_the_one_found_is_claimed() {
+        let device = device(717_002);
+        let scale = config(None).connect_with(input(&device)).unwrap();
+        assert_eq!(scale.get_phidget_id(), 717_002);
+        assert!(device
+            .lock()
+            .unwrap()
+            .opened
+            .iter()
+            .all(|(serial, ..)| serial.is_none()));
+        let error = config(Some(717_002))
+            .connect_with(input(&device))
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error,
+            ScaleError::AlreadyConnected { serial: 717_002 }
+        ));
+    }
+
+    #[test]
+    fn gains_and_intervals_are_set_and_read_back() {
+        let device = device(0);
+        device.lock().unwrap().min_interval = Some(Duration::from_millis(20));
+        let gains = [
+            BridgeGain::Gain128,
+            BridgeGain::Gain128,
+            BridgeGain::Gain64,
+            BridgeGain::Gain64,
+        ];
+        let config = ScaleConfig {
+            bridge_gain: Some(gains),
+            ..config(Some(717_008))
+        };
+        let mut scale = config.connect_with(input(&device)).unwrap();
+        assert_eq!(device.lock().unwrap().gains, gains.map(Some));
+        let mismatches = scale.connect_mismatches();
+        assert_eq!(mismatches.len(), NUMBER_OF_INPUTS);
+        assert_eq!(
+            mismatches[2],
+            SettingMismatch {
+                key: "data_interval_ms",
+                channel: 2,
+                requested: "16".into(),
+                applied: "20".into(),
+            }
+        );
+        assert_eq!(
+            mismatches[0].to_string(),
+            "data_interval_ms of Load Cell 0: asked for 16, set to 20"
+        );
+
+        let current = scale.current_config().unwrap();
+        assert_eq!(
+            current,
+            ScaleConfig {
+                data_interval_ms: 20,
+                ..config.clone()
+            }
+        );
+        assert_eq!(
+            ScaleConfig::from_toml(&current.to_toml()).unwrap().0,
+            current
+        );
+
+        let update = ScaleConfig {
+            bridge_gain: Some([BridgeGain::Gain32; NUMBER_OF_INPUTS]),
+            data_interval_ms: 20,
+            ..config
+        };
+        assert_eq!(
+            scale.apply_config_update(&update).unwrap().to_string(),
+            "bridge_gain: [128, 128, 64, 64] -> [32, 32, 32, 32]"
+        );
+        assert_eq!(
+            scale.current_config().unwrap().bridge_gain,
+            update.bridge_gain
+        );
+    }
+
+    #[test]
+    fn an_invalid_config_opens_nothing() {
+        let device = device(0);
+        let config = ScaleConfig {
+            data_interval_ms: 0,
+            capacity_g: Some(-1.),
+            ..config(Some(717_003))
+        };
+        let error = config.connect_with(input(&device)).err().unwrap();
+        let ScaleError::InvalidArgument(message) = error else {
+            panic!("{error:?}");
+        };
+        assert!(message.contains("data_interval_ms") && message.contains("capacity_g"));
+        assert!(device.lock().unwrap().opened.is_empty());
+    }
+
+    #[test]
+    fn updates_are_applied_to_the_running_scale() {
+        let device = device(0);
+        let mut scale = config(Some(717_005)).connect_with(input(&device)).unwrap();
+        let unchanged = scale.apply_config_update(&config(None)).unwrap();
+        assert!(unchanged.is_empty());
+        assert_eq!(unchanged.to_string(), "no changes");
+
+        let update = ScaleConfig {
+            calibration: Calibration {
+                offset: 6.,
+                ..config(None).calibration
+            },
+            data_interval_ms: 32,
+            capacity_g: None,
+            sampling: SamplingDefaults {
+                median_samples: 5,
+                sample_interval_ms: 0,
+            },
+            connect_timeout_ms: 5000,
+            ..config(Some(717_005))
+        };
+        let applied = scale.apply_config_update(&update).unwrap();
+        let keys: Vec<_> = applied.changes.iter().map(|change| change.key).collect();
+        assert_eq!(
+            keys,
+            [
+                "data_interval_ms",
+                "calibration.offset",
+                "capacity_g",
+                "sampling.median_samples"
+            ]
+        );
+        assert_eq!(
+            applied.to_string(),
+            "data_interval_ms: 16 -> 32; calibration.offset: 5 -> 6; \
+             capacity_g: 1000 -> none; sampling.median_samples: 3 -> 5"
+        );
+        assert!(applied.changed("capacity_g") && !applied.changed("calibration.coefficients"));
+        assert_eq!(
+            device.lock().unwrap().data_intervals[NUMBER_OF_INPUTS..],
+            [Duration::from_millis(32); NUMBER_OF_INPUTS]
+        );
+        assert_eq!(scale.get_weight().unwrap(), Grams(394.));
+        assert_eq!(scale.capacity(), None);
+        assert_eq!(scale.sampling().median_samples, 5);
+        assert!(scale.apply_config_update(&update).unwrap().is_empty());
+    }
+
+    #[test]
+    fn updates_that_need_a_reconnect_are_refused() {
+        let device = device(0);
+        let mut scale = config(Some(717_006)).connect_with(input(&device)).unwrap();
+        let update = ScaleConfig {
+            hub_port: Some(3),
+            capacity_g: None,
+            ..config(Some(717_007))
+        };
+        let error = scale.apply_config_update(&update).err().unwrap();
+        let ScaleError::InvalidArgument(message) = error else {
+            panic!("{error:?}");
+        };
+        assert_eq!(
+            message,
+            "Cannot change serial (717006 to 717007), hub_port (2 to 3) \
+             without reconnecting the scale"
+        );
+        assert_eq!(scale.capacity(), Some(Grams(1000.)));
+
+        let invalid = ScaleConfig {
+            capacity_g: Some(-1.),
+            ..config(None)
+        };
+        let error = scale.apply_config_update(&invalid).err().unwrap();
+        assert!(matches!(error, ScaleError::InvalidArgument(_)), "{error:?}");
+        assert_eq!(scale.capacity(), Some(Grams(1000.)));
+    }
+
+    #[test]
+    fn a_fleet_connects_the_scales_it_can() {
+        let device = device(0);
+        device.lock().unwrap().missing.push(717_022);
+        let mut fleet = FleetConfig::default();
+        for (name, serial) in [("a", 717_020), ("b", 717_021), ("c", 717_022)] {
+            fleet.scales.insert(name.into(), config(Some(serial)));
+        }
+        fleet.scales.insert(
+            "d".into(),
+            ScaleConfig {
+                capacity_g: Some(-1.),
+                ..config(Some(717_023))
+            },
+        );
+        let connection = fleet.connect_all_with(input(&device));
+        assert!(!connection.is_complete());
+        let connected: Vec<_> = connection.scales.keys().map(String::as_str).collect();
+        assert_eq!(connected, ["a", "b"]);
+        assert_eq!(connection.scales["b"].get_phidget_id(), 717_021);
+        assert_eq!(connection.scales["b"].capacity(), Some(Grams(1000.)));
+        let failed: Vec<_> = connection.failures.keys().map(String::as_str).collect();
+        assert_eq!(failed, ["c", "d"]);
+        assert!(matches!(
+            connection.failures["d"],
+            ScaleError::InvalidArgument(_)
+        ));
+        assert!(
+            connection.failures["c"].is_transient(),
+            "{:?}",
+            connection.failures["c"]
+        );
+    }
+
+    #[test]
+    fn a_failed_set_up_closes_the_channels_again() {
+        let device = device(0);
+        device.lock().unwrap().rejects_intervals = true;
+        let error = config(Some(717_004))
+            .connect_with(input(&device))
+            .err()
+            .unwrap();
+        assert!(matches!(error, ScaleError::PhidgetError(_)), "{error:?}");
+        assert_eq!(device.lock().unwrap().closed, NUMBER_OF_INPUTS);
+
+        // The claim went with the channels.
+        device.lock().unwrap().rejects_intervals = false;
+        config(Some(717_004)).connect_with(input(&device)).unwrap();
+    }
+}
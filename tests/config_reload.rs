@@ -0,0 +1,116 @@
+#![cfg(feature = "watch")]
+
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use libra::calibration::Calibration;
+use libra::config::{ConfigReloader, ReloadError};
+use libra::scale::{ConnectedScale, ScaleError, NUMBER_OF_INPUTS};
+use libra::shared::SharedScale;
+use libra::testing::FakeVoltageSource;
+
+const SERIAL: i32 = 716_000;
+const WAIT: Duration = Duration::from_secs(5);
+
+fn config_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("libra-reload-{}-{name}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn config(offset: f64) -> String {
+    format!(
+        "serial = {SERIAL}\n\n[calibration]\noffset = {offset:?}\ncoefficients = [1000.0, 1000.0, 1000.0, 1000.0]\n"
+    )
+}
+
+fn scale() -> SharedScale<ConnectedScale<FakeVoltageSource>> {
+    let calibration = Calibration {
+        offset: 0.,
+        coefficients: [1000.; NUMBER_OF_INPUTS],
+    };
+    SharedScale::new(ConnectedScale::from_sources(
+        SERIAL,
+        calibration,
+        FakeVoltageSource::bridge(SERIAL),
+    ))
+}
+
+#[test]
+fn each_write_is_applied_to_the_scale() {
+    let path = config_dir("write").join("scale.toml");
+    std::fs::write(&path, config(0.)).unwrap();
+    let scale = scale();
+    let (tx, rx) = mpsc::channel();
+    let _reloader = ConfigReloader::new(&path, scale.clone(), move |reload| {
+        let _ = tx.send(reload);
+    })
+    .unwrap();
+
+    std::fs::write(&path, config(2.5)).unwrap();
+    let (changes, warnings) = rx.recv_timeout(WAIT).unwrap().unwrap();
+    assert!(changes.changed("calibration.offset"), "{changes}");
+    assert!(warnings.is_empty(), "{warnings:?}");
+    assert_eq!(scale.lock().calibration().offset, 2.5);
+}
+
+#[test]
+fn a_file_renamed_over_the_config_is_applied() {
+    let dir = config_dir("rename");
+    let path = dir.join("scale.toml");
+    std::fs::write(&path, config(0.)).unwrap();
+    let scale = scale();
+    let (tx, rx) = mpsc::channel();
+    let _reloader = ConfigReloader::new(&path, scale.clone(), move |reload| {
+        let _ = tx.send(reload);
+    })
+    .unwrap();
+
+    let saved = dir.join("scale.toml.new");
+    std::fs::write(&saved, config(-1.)).unwrap();
+    std::fs::rename(&saved, &path).unwrap();
+    rx.recv_timeout(WAIT).unwrap().unwrap();
+    assert_eq!(scale.lock().calibration().offset, -1.);
+}
+
+#[test]
+fn a_config_for_another_scale_is_refused() {
+    let path = config_dir("refused").join("scale.toml");
+    std::fs::write(&path, config(0.)).unwrap();
+    let scale = scale();
+    let (tx, rx) = mpsc::channel();
+    let _reloader = ConfigReloader::new(&path, scale.clone(), move |reload| {
+        let _ = tx.send(reload);
+    })
+    .unwrap();
+
+    std::fs::write(&path, config(3.).replace("716000", "716001")).unwrap();
+    let error = rx.recv_timeout(WAIT).unwrap().unwrap_err();
+    assert!(
+        matches!(error, ReloadError::Apply(ScaleError::InvalidArgument(_))),
+        "{error}"
+    );
+    assert_eq!(scale.lock().calibration().offset, 0.);
+}
+
+#[test]
+fn an_unchanged_version_is_not_applied_again() {
+    let dir = config_dir("unchanged");
+    let path = dir.join("scale.toml");
+    std::fs::write(&path, config(0.)).unwrap();
+    let scale = scale();
+    let (tx, rx) = mpsc::channel();
+    let _reloader = ConfigReloader::new(&path, scale.clone(), move |reload| {
+        let _ = tx.send(reload);
+    })
+    .unwrap();
+
+    std::fs::write(&path, config(0.)).unwrap();
+    let saved = dir.join("scale.toml.new");
+    std::fs::write(&saved, config(1.)).unwrap();
+    std::fs::rename(&saved, &path).unwrap();
+    let (changes, _) = rx.recv_timeout(WAIT).unwrap().unwrap();
+    assert!(changes.changed("calibration.offset"), "{changes}");
+    assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+}
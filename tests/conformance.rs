@@ -0,0 +1,137 @@
+use std::sync::Arc;
+
+use libra::breaker::{CircuitBreaker, CircuitBreakerScale};
+use libra::shared::SharedScale;
+use libra::testing::{scale_conformance, ManualClock, MockScale, SimulatedScale, SimulationConfig};
+use libra::Grams;
+
+const LOAD: Grams = Grams(250.);
+
+/// A noiseless simulator with its own clock, so that medians take no time.
+fn simulated() -> SimulatedScale {
+    let config = SimulationConfig {
+        load: LOAD,
+        noise: 0.,
+        ..SimulationConfig::default()
+    };
+    SimulatedScale::with_clock(config, Arc::new(ManualClock::new()))
+}
+
+#[test]
+fn the_mock_conforms() {
+    scale_conformance(|| MockScale::new(LOAD));
+}
+
+#[test]
+fn the_simulator_conforms() {
+    scale_conformance(simulated);
+}
+
+#[test]
+fn a_shared_scale_conforms() {
+    scale_conformance(|| SharedScale::new(simulated()));
+}
+
+#[test]
+fn a_circuit_breaker_conforms() {
+    scale_conformance(|| CircuitBreakerScale::new(MockScale::new(LOAD), CircuitBreaker::default()));
+}
+
+#[test]
+#[should_panic(expected = "empty medians")]
+fn a_scale_with_its_own_empty_median_error_does_not() {
+    use libra::{MedianGrams, Scale};
+
+    struct Lenient;
+
+    impl Scale for Lenient {
+        fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+            Ok(LOAD)
+        }
+
+        fn get_median_weight(
+            &self,
+        ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+            Ok(MedianGrams(LOAD.0))
+        }
+
+        fn get_median_weight_of(
+            &self,
+            samples: usize,
+        ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+            if samples == 0 {
+                return Err("no samples".into());
+            }
+            Ok(MedianGrams(LOAD.0))
+        }
+    }
+
+    scale_conformance(|| Lenient);
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn a_connected_scale_conforms() {
+    use libra::calibration::Calibration;
+    use libra::scale::{ConnectedScale, NUMBER_OF_INPUTS};
+    use libra::testing::FakeVoltageSource;
+
+    scale_conformance(|| {
+        let sources = FakeVoltageSource::bridge(716_000);
+        for source in &sources {
+            source.set_ratio(0.0625);
+        }
+        let calibration = Calibration {
+            offset: 0.,
+            coefficients: [1000.; NUMBER_OF_INPUTS],
+        };
+        let mut scale = ConnectedScale::from_sources(716_000, calibration, sources);
+        scale.set_clock(Arc::new(ManualClock::new()));
+        scale
+    });
+}
+
+#[cfg(feature = "tokio")]
+mod async_scale {
+    use std::sync::Arc;
+
+    use libra::blocking::BlockingScale;
+    use libra::shared::SharedScale;
+    use libra::testing::{async_scale_conformance, scale_conformance, ManualClock, MockScale};
+    use libra::timeout::TimeoutScale;
+
+    use super::{simulated, LOAD};
+
+    #[tokio::test(start_paused = true)]
+    async fn the_mock_conforms() {
+        async_scale_conformance(|| MockScale::new(LOAD)).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_simulator_conforms() {
+        async_scale_conformance(simulated).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_shared_scale_conforms() {
+        async_scale_conformance(|| SharedScale::new(MockScale::new(LOAD))).await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn a_timeout_scale_conforms() {
+        async_scale_conformance(|| {
+            TimeoutScale::new(MockScale::new(LOAD).with_clock(Arc::new(ManualClock::new())))
+        })
+        .await;
+    }
+
+    #[test]
+    fn a_blocking_scale_conforms() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_time()
+            .build()
+            .unwrap();
+        scale_conformance(|| BlockingScale::new(simulated(), runtime.handle().clone()));
+    }
+}
@@ -0,0 +1,492 @@
+#![cfg(feature = "hardware")]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use libra::calibration::Calibration;
+use libra::cancel::CancelFlag;
+use libra::config::SamplingDefaults;
+use libra::health::{ExpiryAction, Status};
+use libra::scale::{
+    data_interval_for, ConnectedScale, ScaleError, DEFAULT_SAMPLE_INTERVAL, NUMBER_OF_INPUTS,
+};
+use libra::source::VoltageSource;
+use libra::testing::{FakeVoltageSource, ManualClock};
+use libra::{Grams, MedianGrams, Scale, ScaleErrorKind};
+use phidget::ReturnCode;
+
+const SERIAL: i32 = 716_000;
+const CALIBRATION: Calibration = Calibration {
+    offset: 2.,
+    coefficients: [1000.; NUMBER_OF_INPUTS],
+};
+
+fn scale() -> (
+    ConnectedScale<FakeVoltageSource>,
+    [FakeVoltageSource; NUMBER_OF_INPUTS],
+) {
+    let sources = FakeVoltageSource::bridge(SERIAL);
+    let scale = ConnectedScale::from_sources(SERIAL, CALIBRATION, sources.clone());
+    (scale, sources)
+}
+
+/// Queues `ratios` on every load cell.
+fn queue_all(sources: &[FakeVoltageSource], ratios: &[f64]) {
+    for source in sources {
+        source.queue(ratios.iter().copied());
+    }
+}
+
+#[test]
+fn the_weight_is_the_calibrated_sum_less_the_tare() {
+    let (mut scale, sources) = scale();
+    for (channel, source) in sources.iter().enumerate() {
+        source.set_ratio(0.001 * (channel + 1) as f64);
+    }
+    // 1000 · (0.001 + 0.002 + 0.003 + 0.004) - 2
+    assert_eq!(scale.get_weight().unwrap(), Grams(8.));
+    assert_eq!(
+        scale.get_raw_readings().unwrap(),
+        [0.001, 0.002, 0.003, 0.004]
+    );
+
+    scale.tare(3, Duration::ZERO).unwrap();
+    assert_eq!(scale.tare_weight(), Grams(8.));
+    assert_eq!(scale.get_weight().unwrap(), Grams(0.));
+    scale.clear_tare();
+    assert_eq!(Scale::get_weight(&scale).unwrap(), Grams(8.));
+}
+
+#[test]
+fn medians_ignore_outliers() {
+    let (scale, sources) = scale();
+    // Weights of 2, 1000 and 2: the spike is thrown out.
+    queue_all(&sources, &[0.001, 0.2505, 0.001]);
+    let median = scale.get_median_weight(3, Duration::ZERO).unwrap();
+    assert_eq!(median, MedianGrams(2.));
+    assert!(sources.iter().all(|source| source.reads() == 3));
+
+    queue_all(&sources, &[0.001, 0.003, 0.002, 0.004, 0.005]);
+    assert_eq!(
+        scale.get_load_cell_medians(5, Duration::ZERO).unwrap(),
+        [0.003; NUMBER_OF_INPUTS]
+    );
+}
+
+#[test]
+fn medians_are_paced_by_the_scale_clock() {
+    let (mut scale, _sources) = scale();
+    let clock = ManualClock::new();
+    scale.set_clock(Arc::new(clock.clone()));
+
+    // Every sample, the first included, waits out one interval.
+    scale.get_median_weight(5, Duration::from_secs(1)).unwrap();
+    assert_eq!(clock.elapsed(), Duration::from_secs(5));
+    Scale::get_median_weight_of(&scale, 3).unwrap();
+    assert_eq!(
+        clock.elapsed(),
+        Duration::from_secs(5) + DEFAULT_SAMPLE_INTERVAL * 3
+    );
+
+    let clock = ManualClock::new();
+    scale.set_clock(Arc::new(clock.clone()));
+    scale
+        .get_load_cell_medians(4, Duration::from_millis(250))
+        .unwrap();
+    assert_eq!(clock.elapsed(), Duration::from_secs(1));
+}
+
+#[test]
+fn trait_medians_take_the_sampling_defaults() {
+    let (mut scale, sources) = scale();
+    let clock = ManualClock::new();
+    scale.set_clock(Arc::new(clock.clone()));
+    scale.set_sampling(SamplingDefaults {
+        median_samples: 3,
+        sample_interval_ms: 20,
+    });
+    Scale::get_median_weight(&scale).unwrap();
+    assert_eq!(clock.elapsed(), Duration::from_millis(60));
+    assert!(sources.iter().all(|source| source.reads() == 3));
+    Scale::tare(&mut scale, 2).unwrap();
+    assert_eq!(clock.elapsed(), Duration::from_millis(100));
+}
+
+#[test]
+fn an_expired_calibration_warns_or_blocks() {
+    let (mut scale, sources) = scale();
+    for source in &sources {
+        source.set_ratio(0.2);
+    }
+    let days_ago = |days: u64| Some(SystemTime::now() - Duration::from_secs(days * 86_400));
+    scale.set_calibrated_at(days_ago(40));
+    // Without a policy, any age is fine.
+    assert_eq!(scale.get_weight().unwrap(), Grams(798.));
+    assert_eq!(scale.status().unwrap().calibration_expires_in_days, None);
+
+    scale.set_calibration_policy(Duration::from_secs(30 * 86_400), ExpiryAction::Warn);
+    assert_eq!(scale.get_weight().unwrap(), Grams(798.));
+    assert_eq!(
+        scale.status().unwrap().calibration_expires_in_days,
+        Some(-11)
+    );
+    assert_eq!(scale.health(3).unwrap().status, Status::Degraded);
+
+    scale.set_calibration_policy(Duration::from_secs(30 * 86_400), ExpiryAction::Block);
+    let error = scale.get_weight().unwrap_err();
+    assert!(
+        matches!(error, ScaleError::CalibrationExpired { age }
+            if age >= Duration::from_secs(40 * 86_400)),
+        "{error:?}"
+    );
+    assert_eq!(error.kind(), ScaleErrorKind::CalibrationExpired);
+    assert!(!error.is_transient());
+    assert!(scale.get_median_weight(3, Duration::ZERO).is_err());
+    assert_eq!(scale.health(3).unwrap().status, Status::Unusable);
+    // The load cells can still be read, to recalibrate.
+    assert_eq!(scale.get_raw_readings().unwrap(), [0.2; NUMBER_OF_INPUTS]);
+
+    scale.set_calibrated_at(days_ago(2));
+    assert_eq!(scale.get_weight().unwrap(), Grams(798.));
+    assert_eq!(
+        scale.status().unwrap().calibration_expires_in_days,
+        Some(27)
+    );
+}
+
+#[test]
+fn a_future_dated_calibration_is_not_trusted_or_blocked() {
+    let (mut scale, sources) = scale();
+    for source in &sources {
+        source.set_ratio(0.2);
+    }
+    scale.set_calibration_policy(Duration::from_secs(30 * 86_400), ExpiryAction::Block);
+    scale.set_calibrated_at(Some(SystemTime::now() + Duration::from_secs(86_400)));
+    assert_eq!(scale.get_weight().unwrap(), Grams(798.));
+    assert_eq!(scale.status().unwrap().calibration_expires_in_days, None);
+    let report = scale.health(3).unwrap();
+    assert_eq!(report.status, Status::Degraded, "{report}");
+    assert!(report.calibration_future_dated);
+}
+
+#[test]
+fn loads_over_the_capacity_are_refused() {
+    let (mut scale, sources) = scale();
+    assert_eq!(scale.capacity(), None);
+    scale.set_capacity(Some(Grams(1000.)));
+    for source in &sources {
+        source.set_ratio(0.2);
+    }
+    assert_eq!(scale.get_weight().unwrap(), Grams(798.));
+    scale.tare(1, Duration::ZERO).unwrap();
+
+    // 1198 g on the platform reads as 400 g net, but the container counts.
+    for source in &sources {
+        source.set_ratio(0.3);
+    }
+    let error = scale.get_weight().unwrap_err();
+    assert!(
+        matches!(error, ScaleError::OverCapacity { weight, capacity }
+            if (weight - 1198.).abs() < 1e-9 && capacity == 1000.),
+        "{error:?}"
+    );
+    assert!(error.is_transient());
+    assert_eq!(error.kind(), ScaleErrorKind::OverCapacity);
+
+    scale.set_capacity(None);
+    assert!((scale.get_weight().unwrap().0 - 400.).abs() < 1e-9);
+}
+
+#[test]
+fn zeroing_moves_the_offset() {
+    let (mut scale, sources) = scale();
+    for source in &sources {
+        source.set_ratio(0.0005);
+    }
+    let calibration = scale.zero(3, Duration::ZERO).unwrap();
+    assert_eq!(calibration.offset, 2.);
+    assert_eq!(scale.get_weight().unwrap(), Grams(0.));
+
+    for source in &sources {
+        source.set_ratio(0.001);
+    }
+    assert_eq!(scale.get_weight().unwrap(), Grams(2.));
+}
+
+#[test]
+fn a_cancelled_median_stops_reading() {
+    let (scale, sources) = scale();
+    let cancel = CancelFlag::new();
+    cancel.cancel();
+    let error = scale
+        .get_median_weight_cancellable(5, Duration::ZERO, &cancel)
+        .unwrap_err();
+    assert!(matches!(error, ScaleError::Cancelled { collected: 0 }));
+    assert_eq!(sources[0].reads(), 0);
+}
+
+#[test]
+fn failing_load_cells_are_all_reported() {
+    let (scale, sources) = scale();
+    sources[1].set_attached(false);
+    sources[3].fail_with(Some(ReturnCode::Timeout));
+    let error = scale.get_weight().unwrap_err();
+    let ScaleError::MultipleChannels(failures) = &error else {
+        panic!("{error}");
+    };
+    let channels: Vec<usize> = failures.iter().map(|(channel, _)| *channel).collect();
+    assert_eq!(channels, [1, 3]);
+    assert!(error.is_disconnection());
+    assert_eq!(scale.last_error().unwrap().load_cell, Some(3));
+
+    sources[1].set_attached(true);
+    let error = scale.get_median_weight(3, Duration::ZERO).unwrap_err();
+    assert!(error.is_transient(), "{error}");
+
+    sources[3].fail_with(None);
+    sources[2].queue([f64::NAN]);
+    assert!(matches!(
+        scale.get_weight(),
+        Err(ScaleError::NonFinite { channel: Some(2) })
+    ));
+    assert!(scale.get_weight().is_ok());
+}
+
+#[test]
+fn status_and_close_reach_every_channel() {
+    let (mut scale, sources) = scale();
+    let status = scale.status().unwrap();
+    assert!(status.attached);
+    assert_eq!(status.phidget_id, SERIAL);
+    assert_eq!(status.calibration, CALIBRATION);
+
+    sources[2].set_attached(false);
+    assert!(!scale.status().unwrap().attached);
+
+    scale.set_data_intervals(Duration::from_millis(16)).unwrap();
+    assert_eq!(
+        scale.get_data_intervals().unwrap(),
+        [Duration::from_millis(16); NUMBER_OF_INPUTS]
+    );
+    let error = scale.set_data_intervals(Duration::ZERO).unwrap_err();
+    assert_eq!(error.kind(), ScaleErrorKind::Phidget);
+
+    scale.close().unwrap();
+    assert!(sources.iter().all(FakeVoltageSource::is_closed));
+    assert!(scale.get_weight().unwrap_err().is_disconnection());
+}
+
+#[test]
+fn fakes_report_ratio_changes() {
+    let mut source = FakeVoltageSource::new(SERIAL);
+    let changes = Arc::new(AtomicUsize::new(0));
+    let seen = Arc::clone(&changes);
+    source
+        .on_ratio_change(Box::new(move |_| {
+            seen.fetch_add(1, Ordering::Relaxed);
+        }))
+        .unwrap();
+    source.set_ratio(0.1);
+    source.set_ratio(0.2);
+    assert_eq!(changes.load(Ordering::Relaxed), 2);
+    assert_eq!(source.ratio().unwrap(), 0.2);
+    assert_eq!(source.serial_number().unwrap(), SERIAL);
+}
+
+#[test]
+fn a_fake_scale_passes_a_health_check() {
+    let (scale, sources) = scale();
+    queue_all(&sources, &[0.001, 0.0010001, 0.001, 0.0009999]);
+    let config = libra::health::HealthConfig {
+        sample_interval: Duration::ZERO,
+        ..Default::default()
+    };
+    let report = scale.health_with(4, &config).unwrap();
+    assert_eq!(report.status, libra::health::Status::Ok, "{report}");
+}
+
+/// Sets each load cell of `sources` to a different ratio.
+fn set_ratios(sources: &[FakeVoltageSource]) {
+    for (channel, source) in sources.iter().enumerate() {
+        source.set_ratio(0.001 * (channel + 1) as f64);
+    }
+}
+
+#[test]
+fn a_fresh_snapshot_weighs_what_the_load_cells_read() {
+    let clock = ManualClock::new();
+    let (mut cached, sources) = scale();
+    cached.set_clock(Arc::new(clock.clone()));
+    cached
+        .cache_change_events(Duration::from_millis(50))
+        .unwrap();
+    assert_eq!(
+        cached.change_event_max_age(),
+        Some(Duration::from_millis(50))
+    );
+    set_ratios(&sources);
+    let (direct, direct_sources) = scale();
+    set_ratios(&direct_sources);
+
+    clock.advance(Duration::from_millis(50));
+    assert_eq!(cached.get_weight().unwrap(), direct.get_weight().unwrap());
+    assert_eq!(
+        cached.get_raw_readings().unwrap(),
+        direct.get_raw_readings().unwrap()
+    );
+    assert_eq!(
+        sources.iter().map(FakeVoltageSource::reads).sum::<usize>(),
+        0
+    );
+    let counters = cached.read_counters();
+    assert_eq!((counters.cached(), counters.direct()), (2, 0));
+    let counters = direct.read_counters();
+    assert_eq!((counters.cached(), counters.direct()), (0, 2));
+}
+
+#[test]
+fn a_stale_snapshot_asks_the_load_cells() {
+    let clock = ManualClock::new();
+    let (mut scale, sources) = scale();
+    scale.set_clock(Arc::new(clock.clone()));
+    scale
+        .cache_change_events(Duration::from_millis(50))
+        .unwrap();
+    let counters = scale.read_counters();
+    // Nothing reported yet.
+    scale.get_weight().unwrap();
+    set_ratios(&sources[..3]);
+    scale.get_weight().unwrap();
+    assert_eq!((counters.cached(), counters.direct()), (0, 2));
+
+    sources[3].set_ratio(0.004);
+    assert_eq!(scale.get_weight().unwrap(), Grams(8.));
+    assert_eq!(counters.cached(), 1);
+
+    // The change events stop while the load cells move on.
+    clock.advance(Duration::from_millis(51));
+    queue_all(&sources, &[0.002]);
+    assert_eq!(scale.get_weight().unwrap(), Grams(6.));
+    assert_eq!((counters.cached(), counters.direct()), (1, 3));
+
+    sources[0].set_ratio(f64::NAN);
+    set_ratios(&sources[1..]);
+    assert!(matches!(
+        scale.get_weight(),
+        Err(ScaleError::NonFinite { channel: Some(0) })
+    ));
+    assert_eq!((counters.cached(), counters.direct()), (1, 4));
+}
+
+#[test]
+fn change_events_need_an_age() {
+    let (mut scale, sources) = scale();
+    assert_eq!(scale.change_event_max_age(), None);
+    assert!(matches!(
+        scale.cache_change_events(Duration::ZERO),
+        Err(ScaleError::InvalidArgument(_))
+    ));
+    assert_eq!(scale.change_event_max_age(), None);
+    set_ratios(&sources);
+    scale.get_weight().unwrap();
+    assert_eq!(scale.read_counters().direct(), 1);
+}
+
+#[test]
+fn the_data_interval_is_the_longest_at_or_below_the_sampling_interval() {
+    let ms = Duration::from_millis;
+    let cases = [
+        // Sampling, shortest and longest data interval, data interval.
+        (ms(10), None, None, ms(10)),
+        (Duration::from_micros(10_700), None, None, ms(10)),
+        (Duration::ZERO, None, None, ms(1)),
+        (Duration::from_micros(300), None, None, ms(1)),
+        (ms(10), Some(ms(8)), Some(ms(1000)), ms(10)),
+        (ms(1), Some(ms(8)), Some(ms(1000)), ms(8)),
+        (Duration::ZERO, Some(ms(8)), Some(ms(1000)), ms(8)),
+        (
+            Duration::from_secs(2),
+            Some(ms(8)),
+            Some(ms(1000)),
+            ms(1000),
+        ),
+        (
+            Duration::from_secs(2),
+            None,
+            Some(ms(60_000)),
+            Duration::from_secs(2),
+        ),
+        (ms(5), Some(ms(20)), None, ms(20)),
+    ];
+    for (sampling, min, max, expected) in cases {
+        assert_eq!(
+            data_interval_for(sampling, min, max),
+            expected,
+            "{sampling:?} within {min:?}..{max:?}"
+        );
+    }
+}
+
+#[test]
+fn a_tuned_data_interval_is_put_back_when_dropped() {
+    let ms = Duration::from_millis;
+    let (mut scale, sources) = scale();
+    for source in &sources {
+        source.set_data_interval_bounds(ms(8), ms(1000));
+    }
+    // The narrowest bounds of any channel hold for all of them.
+    sources[3].set_data_interval_bounds(ms(16), ms(500));
+    assert_eq!(
+        scale.data_interval_bounds().unwrap(),
+        (Some(ms(16)), Some(ms(500)))
+    );
+    scale.set_data_intervals(ms(250)).unwrap();
+    let mut channel = sources[1].clone();
+    channel.set_data_interval(ms(100)).unwrap();
+    let before = scale.get_data_intervals().unwrap();
+
+    let tuned = scale.tune_data_interval_for(ms(10)).unwrap();
+    assert_eq!((tuned.interval(), tuned.previous()), (ms(16), ms(250)));
+    assert_eq!(tuned.scale().get_weight().unwrap(), Grams(-2.));
+    drop(tuned);
+    assert_eq!(scale.get_data_intervals().unwrap(), before);
+
+    let tuned = scale
+        .tune_data_interval_for(Duration::from_secs(5))
+        .unwrap();
+    assert_eq!(tuned.interval(), ms(500));
+    tuned.restore().unwrap();
+    assert_eq!(scale.get_data_intervals().unwrap(), before);
+
+    // Without bounds, only the unit of the interval is changed.
+    let (mut scale, _) = self::scale();
+    assert_eq!(scale.data_interval_bounds().unwrap(), (None, None));
+    let tuned = scale.tune_data_interval_for(ms(1500)).unwrap();
+    assert_eq!((tuned.interval(), tuned.previous()), (ms(1500), ms(8)));
+}
+
+#[test]
+fn a_tuned_median_reports_its_data_interval() {
+    let ms = Duration::from_millis;
+    let clock = ManualClock::new();
+    let (mut scale, sources) = scale();
+    scale.set_clock(Arc::new(clock.clone()));
+    for source in &sources {
+        source.set_data_interval_bounds(ms(8), ms(1000));
+    }
+    set_ratios(&sources);
+    let tuned = scale.get_median_weight_tuned(5, ms(50)).unwrap();
+    assert_eq!(tuned.median, MedianGrams(8.));
+    assert_eq!(tuned.data_interval, ms(50));
+    assert_eq!(clock.elapsed(), ms(250));
+    assert_eq!(scale.data_interval().unwrap(), ms(8));
+
+    sources[2].fail_with(Some(ReturnCode::Timeout));
+    assert!(scale.get_median_weight_tuned(5, ms(50)).is_err());
+    assert_eq!(scale.data_interval().unwrap(), ms(8));
+    assert!(matches!(
+        scale.get_median_weight_tuned(0, ms(50)),
+        Err(ScaleError::InvalidArgument(_))
+    ));
+}
@@ -0,0 +1,186 @@
+#![cfg(feature = "tokio")]
+
+use std::time::Duration;
+
+use futures::future::join_all;
+use libra::correlation::{CorrelationError, Correlator, PendingReply};
+use libra::{Grams, MedianGrams, Reply, Request, ScaleCmd, ScaleResponse};
+use tokio::sync::mpsc;
+
+/// A transport that delivers requests to the test and replies in whatever
+/// order the test sends them.
+struct MockTransport {
+    correlator: Correlator,
+    sent: mpsc::UnboundedSender<Request>,
+}
+
+impl MockTransport {
+    fn new(correlator: Correlator) -> (Self, mpsc::UnboundedReceiver<Request>) {
+        let (sent, received) = mpsc::unbounded_channel();
+        (Self { correlator, sent }, received)
+    }
+
+    fn send(&self, cmd: ScaleCmd) -> PendingReply {
+        let (request, reply) = self.correlator.start(cmd);
+        self.sent.send(request).unwrap();
+        reply
+    }
+}
+
+/// What a scale would answer to `cmd`, so replies can be told apart.
+fn answer(cmd: &ScaleCmd) -> ScaleResponse {
+    match cmd {
+        ScaleCmd::GetWeight => ScaleResponse::Weight(Grams(1.)),
+        ScaleCmd::GetMedianWeight { samples } => {
+            ScaleResponse::MedianWeight(MedianGrams(*samples as f64))
+        }
+        ScaleCmd::Tare { samples } => ScaleResponse::Tared(Grams(*samples as f64)),
+        _ => ScaleResponse::Expired,
+    }
+}
+
+#[tokio::test]
+async fn out_of_order_replies_reach_their_requests() {
+    let correlator = Correlator::new();
+    let (transport, mut received) = MockTransport::new(correlator.clone());
+    let cmds = [
+        ScaleCmd::GetWeight,
+        ScaleCmd::GetMedianWeight { samples: 3 },
+        ScaleCmd::Tare { samples: 5 },
+        ScaleCmd::GetMedianWeight { samples: 7 },
+    ];
+    let replies: Vec<_> = cmds.iter().map(|cmd| transport.send(cmd.clone())).collect();
+    assert_eq!(correlator.pending(), 4);
+
+    let mut requests = Vec::new();
+    while let Ok(request) = received.try_recv() {
+        requests.push(request);
+    }
+    // Answer in the order 2, 0, 3, 1.
+    for at in [2, 0, 3, 1] {
+        let Request { id, cmd, .. } = requests[at].clone();
+        correlator
+            .resolve(Reply {
+                id,
+                response: answer(&cmd),
+            })
+            .unwrap();
+    }
+
+    for (cmd, reply) in cmds.iter().zip(replies) {
+        assert_eq!(reply.await, Ok(answer(cmd)));
+    }
+    assert_eq!(correlator.pending(), 0);
+    assert_eq!(correlator.stray_replies(), 0);
+}
+
+#[tokio::test]
+async fn concurrent_requests_resolve_as_replies_arrive() {
+    let correlator = Correlator::new();
+    let (transport, mut received) = MockTransport::new(correlator.clone());
+
+    // Answers each pair of requests the wrong way round.
+    let server = correlator.clone();
+    tokio::spawn(async move {
+        while let Some(first) = received.recv().await {
+            let second = received.recv().await.unwrap();
+            for Request { id, cmd, .. } in [second, first] {
+                tokio::task::yield_now().await;
+                server
+                    .resolve(Reply {
+                        id,
+                        response: answer(&cmd),
+                    })
+                    .unwrap();
+            }
+        }
+    });
+
+    let waits = (1..=20).map(|samples| {
+        let reply = transport.send(ScaleCmd::GetMedianWeight { samples });
+        async move { (samples, reply.await) }
+    });
+    for (samples, reply) in join_all(waits).await {
+        assert_eq!(
+            reply,
+            Ok(ScaleResponse::MedianWeight(MedianGrams(samples as f64)))
+        );
+    }
+}
+
+#[tokio::test]
+async fn unknown_and_duplicate_replies_are_dropped() {
+    let correlator = Correlator::new();
+    let (request, reply) = correlator.start(ScaleCmd::GetWeight);
+    let answer = Reply {
+        id: request.id,
+        response: ScaleResponse::Weight(Grams(2.)),
+    };
+
+    let unknown = Reply {
+        id: request.id + 100,
+        response: ScaleResponse::Weight(Grams(3.)),
+    };
+    assert_eq!(correlator.resolve(unknown.clone()), Err(unknown));
+    assert_eq!(correlator.resolve(answer.clone()), Ok(()));
+    assert_eq!(correlator.resolve(answer.clone()), Err(answer));
+    assert_eq!(reply.await, Ok(ScaleResponse::Weight(Grams(2.))));
+    assert_eq!(correlator.stray_replies(), 2);
+}
+
+#[tokio::test]
+async fn ids_are_not_reused_while_pending() {
+    let correlator = Correlator::new();
+    let mut ids: Vec<_> = (0..100)
+        .map(|_| correlator.start(ScaleCmd::GetWeight))
+        .map(|(request, reply)| {
+            drop(reply);
+            request.id
+        })
+        .collect();
+    let pending: Vec<_> = (0..100)
+        .map(|_| correlator.start(ScaleCmd::GetWeight))
+        .collect();
+    ids.extend(pending.iter().map(|(request, _)| request.id));
+    ids.sort_unstable();
+    ids.dedup();
+    assert_eq!(ids.len(), 200);
+    assert_eq!(correlator.pending(), 100);
+}
+
+#[tokio::test(start_paused = true)]
+async fn unanswered_requests_time_out() {
+    let correlator = Correlator::with_timeout(Duration::from_secs(2));
+    let (request, reply) = correlator.start(ScaleCmd::GetWeight);
+    assert_eq!(
+        reply.await,
+        Err(CorrelationError::TimedOut {
+            id: request.id,
+            timeout: Duration::from_secs(2),
+        })
+    );
+    assert_eq!(correlator.pending(), 0);
+
+    // The reply turns up too late.
+    let late = Reply {
+        id: request.id,
+        response: ScaleResponse::Weight(Grams(1.)),
+    };
+    assert!(correlator.resolve(late).is_err());
+    assert_eq!(correlator.stray_replies(), 1);
+}
+
+#[tokio::test]
+async fn dropped_and_abandoned_requests_leave_the_map() {
+    let correlator = Correlator::new();
+    let (_, dropped) = correlator.start(ScaleCmd::GetWeight);
+    drop(dropped);
+    assert_eq!(correlator.pending(), 0);
+
+    let (request, abandoned) = correlator.start(ScaleCmd::GetWeight);
+    correlator.abandon_all();
+    assert_eq!(
+        abandoned.await,
+        Err(CorrelationError::Abandoned { id: request.id })
+    );
+}
@@ -0,0 +1,179 @@
+#![cfg(feature = "hardware")]
+
+use std::time::Duration;
+
+use libra::discovery::{
+    discover_scales, discover_scales_with, group_channels, AttachedChannel, ChannelManager,
+    DeviceChange, DeviceEvent, DeviceTracker, DiscoveredScale,
+};
+use libra::scale::ScaleError;
+use phidget::{ChannelClass, ReturnCode};
+
+/// The channels of a bridge on USB, or on `hub_port` of a VINT hub.
+fn bridge(serial: i32, hub_port: Option<i32>, label: Option<&str>) -> Vec<AttachedChannel> {
+    (0..4)
+        .map(|channel| AttachedChannel {
+            serial,
+            label: label.map(Into::into),
+            hub_port,
+            is_hub_port_device: false,
+            class: ChannelClass::VoltageRatioInput,
+            channel,
+        })
+        .collect()
+}
+
+/// Reports `channels`, or fails with `failure`, and remembers the timeout.
+struct MockManager {
+    channels: Vec<AttachedChannel>,
+    failure: Option<ReturnCode>,
+    asked: Option<Duration>,
+}
+
+impl ChannelManager for MockManager {
+    fn attached_channels(&mut self, timeout: Duration) -> Result<Vec<AttachedChannel>, ScaleError> {
+        self.asked = Some(timeout);
+        match self.failure {
+            Some(code) => Err(ScaleError::phidget_error(code, 0)),
+            None => Ok(self.channels.clone()),
+        }
+    }
+}
+
+#[test]
+fn channels_are_grouped_by_device() {
+    let mut channels = Vec::new();
+    channels.extend(bridge(716_002, None, None));
+    channels.extend(bridge(716_000, Some(3), Some("Pass 1")));
+    // A second bridge on another port of the same hub.
+    channels.extend(bridge(716_000, Some(1), None));
+    // Reported again, as after a reattach.
+    channels.extend(bridge(716_002, None, None).into_iter().rev());
+    // Channels of other classes on the device are left out.
+    channels.push(AttachedChannel {
+        class: ChannelClass::DigitalInput,
+        ..bridge(716_002, None, None)[0].clone()
+    });
+    // A hub port in voltage ratio mode is one load cell, not a scale.
+    channels.push(AttachedChannel {
+        is_hub_port_device: true,
+        ..bridge(716_000, Some(0), None)[0].clone()
+    });
+    // Nor is a device with too few channels.
+    channels.extend(bridge(716_005, None, None).into_iter().take(2));
+
+    let scales = group_channels(channels);
+    let found: Vec<_> = scales
+        .iter()
+        .map(|scale| (scale.serial, scale.hub_port, scale.channel_count()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (716_000, Some(1), 4),
+            (716_000, Some(3), 4),
+            (716_002, None, 4)
+        ]
+    );
+    assert_eq!(scales[1].label.as_deref(), Some("Pass 1"));
+    assert_eq!(scales[2].channels, [0, 1, 2, 3]);
+}
+
+#[test]
+fn a_discovered_scale_connects_where_it_was_found() {
+    let scale = DiscoveredScale {
+        serial: 716_000,
+        label: None,
+        hub_port: Some(3),
+        channels: vec![0, 1, 2, 3],
+    };
+    let disconnected = scale.clone().into_disconnected();
+    assert_eq!(disconnected.phidget_id(), 716_000);
+    assert_eq!(disconnected.hub_port(), Some(3));
+    let on_usb = DiscoveredScale {
+        hub_port: None,
+        ..scale
+    };
+    assert_eq!(on_usb.into_disconnected().hub_port(), None);
+}
+
+#[test]
+fn discovery_asks_the_manager() {
+    let mut manager = MockManager {
+        channels: bridge(716_000, None, None),
+        failure: None,
+        asked: None,
+    };
+    let scales = discover_scales_with(&mut manager, Duration::from_millis(250)).unwrap();
+    assert_eq!(scales.len(), 1);
+    assert_eq!(manager.asked, Some(Duration::from_millis(250)));
+
+    manager.asked = None;
+    assert!(matches!(
+        discover_scales_with(&mut manager, Duration::ZERO),
+        Err(ScaleError::InvalidArgument(_))
+    ));
+    assert_eq!(manager.asked, None);
+
+    manager.failure = Some(ReturnCode::Unsupported);
+    assert!(matches!(
+        discover_scales_with(&mut manager, Duration::from_millis(250)),
+        Err(ScaleError::PhidgetError(_))
+    ));
+}
+
+#[test]
+fn bridges_are_tracked_as_their_channels_come_and_go() {
+    let mut tracker = DeviceTracker::new();
+    let mut channels = bridge(716_000, Some(2), Some("flour"));
+    let last = channels.pop().unwrap();
+    for channel in channels.iter().cloned() {
+        assert_eq!(tracker.apply(DeviceEvent::Attached(channel)), None);
+    }
+    // A hub port in voltage ratio mode is no part of the bridge.
+    let mut hub_port = last.clone();
+    hub_port.is_hub_port_device = true;
+    assert_eq!(tracker.apply(DeviceEvent::Attached(hub_port)), None);
+
+    assert_eq!(
+        tracker.apply(DeviceEvent::Attached(last.clone())),
+        Some(DeviceChange::Available(DiscoveredScale {
+            serial: 716_000,
+            label: Some("flour".into()),
+            hub_port: Some(2),
+            channels: vec![0, 1, 2, 3],
+        }))
+    );
+    assert_eq!(tracker.apply(DeviceEvent::Attached(last.clone())), None);
+
+    let lost = Some(DeviceChange::Lost {
+        serial: 716_000,
+        hub_port: Some(2),
+    });
+    assert_eq!(tracker.apply(DeviceEvent::Detached(last.clone())), lost);
+    assert_eq!(tracker.apply(DeviceEvent::Detached(last.clone())), None);
+    for channel in channels.iter().cloned() {
+        assert_eq!(tracker.apply(DeviceEvent::Detached(channel)), None);
+    }
+
+    // Plugged back in, the bridge is available again.
+    let changes: Vec<_> = bridge(716_000, Some(2), None)
+        .into_iter()
+        .filter_map(|channel| tracker.apply(DeviceEvent::Attached(channel)))
+        .collect();
+    assert!(
+        matches!(&changes[..], [DeviceChange::Available(scale)] if scale.label.is_none()),
+        "{changes:?}"
+    );
+}
+
+/// Lists the bridges attached to this machine. Run with
+/// `cargo test --test discovery -- --ignored` with at least one attached.
+#[test]
+#[ignore]
+fn attached_bridges_are_discovered() {
+    let scales = discover_scales(Duration::from_secs(1)).unwrap();
+    println!("{scales:#?}");
+    assert!(!scales.is_empty());
+    assert!(scales.iter().all(|scale| scale.serial > 0));
+}
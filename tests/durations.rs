@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+use libra::health::{check, HealthConfig};
+use libra::scale::{
+    check_interval, check_timeout, ScaleError, DEFAULT_MAX_DURATION, NUMBER_OF_INPUTS,
+};
+#[cfg(feature = "hardware")]
+use libra::scale::{ConnectedScale, DisconnectedScale};
+use libra::{Grams, MedianGrams, Scale};
+
+const NANO: Duration = Duration::from_nanos(1);
+
+fn invalid<T>(result: Result<T, ScaleError>) -> bool {
+    matches!(result, Err(ScaleError::InvalidArgument(_)))
+}
+
+#[test]
+fn timeouts_must_be_positive_and_under_the_cap() {
+    let max = DEFAULT_MAX_DURATION;
+    assert!(invalid(check_timeout(Duration::ZERO, max)));
+    assert!(check_timeout(NANO, max).is_ok());
+    assert!(check_timeout(max, max).is_ok());
+    assert!(invalid(check_timeout(max + NANO, max)));
+
+    let max = Duration::from_secs(5);
+    assert!(check_timeout(max, max).is_ok());
+    assert!(invalid(check_timeout(max + NANO, max)));
+}
+
+#[test]
+fn intervals_may_be_zero_but_not_over_the_cap() {
+    let max = DEFAULT_MAX_DURATION;
+    assert!(check_interval(Duration::ZERO, max).is_ok());
+    assert!(check_interval(max, max).is_ok());
+    assert!(invalid(check_interval(max + NANO, max)));
+    assert!(invalid(check_interval(
+        Duration::from_secs(2),
+        Duration::from_secs(1)
+    )));
+}
+
+#[test]
+fn the_cap_suggests_a_unit_mistake() {
+    // 500 "milliseconds" passed as seconds.
+    let error = check_timeout(Duration::from_secs(500_000), DEFAULT_MAX_DURATION).unwrap_err();
+    assert!(error.to_string().contains("wrong unit"), "{error}");
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn connecting_checks_the_timeout_before_opening_anything() {
+    let coefficients = [1.; NUMBER_OF_INPUTS];
+    for timeout in [Duration::ZERO, DEFAULT_MAX_DURATION + NANO] {
+        assert!(invalid(DisconnectedScale::new(716_000).connect(
+            0.,
+            coefficients,
+            timeout
+        )));
+        assert!(invalid(ConnectedScale::without_id(timeout)));
+    }
+    let capped = DisconnectedScale::new(716_000).with_max_duration(Duration::from_secs(1));
+    assert!(invalid(capped.connect(
+        0.,
+        coefficients,
+        Duration::from_secs(2)
+    )));
+}
+
+struct SteadyScale;
+
+impl Scale for SteadyScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(1.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(MedianGrams(1.))
+    }
+
+    fn get_raw_readings(
+        &self,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        Ok([1e-4; NUMBER_OF_INPUTS])
+    }
+}
+
+#[test]
+fn health_checks_refuse_an_absurd_interval() {
+    let config = |sample_interval| HealthConfig {
+        sample_interval,
+        ..HealthConfig::default()
+    };
+    assert!(check(&SteadyScale, 2, None, None, &config(Duration::ZERO)).is_ok());
+    let absurd = config(DEFAULT_MAX_DURATION + NANO);
+    assert!(invalid(check(&SteadyScale, 2, None, None, &absurd)));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn batches_refuse_an_absurd_interval() {
+    use libra::actor::spawn_scale_actor;
+    use libra::{ScaleCmd, ScaleErrorKind, ScaleResponse};
+
+    let (handle, _task) = spawn_scale_actor(SteadyScale);
+    let max_ms = DEFAULT_MAX_DURATION.as_millis() as u64;
+    let response = handle
+        .send(ScaleCmd::GetWeightBatch {
+            count: 2,
+            interval_ms: max_ms + 1,
+        })
+        .await;
+    assert!(
+        matches!(&response, ScaleResponse::Error(info) if info.kind == ScaleErrorKind::InvalidArgument),
+        "{response:?}"
+    );
+    let response = handle
+        .send(ScaleCmd::GetWeightBatch {
+            count: 2,
+            interval_ms: 0,
+        })
+        .await;
+    assert!(matches!(response, ScaleResponse::WeightBatch(batch) if batch.len() == 2));
+}
@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use libra::{AsyncScale, AsyncScaleError, DynAsyncScale, Grams, MedianGrams};
+
+struct FixedScale(f64);
+
+impl AsyncScale for FixedScale {
+    async fn get_weight(&self) -> Result<Grams, AsyncScaleError> {
+        Ok(Grams(self.0))
+    }
+
+    async fn get_median_weight(&self, _samples: usize) -> Result<MedianGrams, AsyncScaleError> {
+        Ok(MedianGrams(self.0))
+    }
+}
+
+/// Takes time per read and fails on request, unlike `FixedScale`.
+struct SlowScale {
+    delay: Duration,
+}
+
+impl AsyncScale for SlowScale {
+    async fn get_weight(&self) -> Result<Grams, AsyncScaleError> {
+        tokio::time::sleep(self.delay).await;
+        Ok(Grams(2.))
+    }
+
+    async fn get_median_weight(&self, samples: usize) -> Result<MedianGrams, AsyncScaleError> {
+        if samples == 0 {
+            return Err("no samples requested".into());
+        }
+        tokio::time::sleep(self.delay * samples as u32).await;
+        Ok(MedianGrams(2.))
+    }
+}
+
+#[tokio::test]
+async fn heterogeneous_scales_in_one_registry() {
+    let registry: Vec<Box<dyn DynAsyncScale + Send + Sync>> = vec![
+        Box::new(FixedScale(1.)),
+        Box::new(SlowScale {
+            delay: Duration::from_millis(1),
+        }),
+    ];
+
+    let mut weights = Vec::new();
+    for scale in &registry {
+        weights.push(scale.get_weight().await.unwrap());
+    }
+    assert_eq!(weights, vec![Grams(1.), Grams(2.)]);
+
+    let medians = futures::future::join_all(registry.iter().map(|s| s.get_median_weight(3))).await;
+    let medians: Vec<_> = medians.into_iter().map(Result::unwrap).collect();
+    assert_eq!(medians, vec![MedianGrams(1.), MedianGrams(2.)]);
+    assert!(registry[1].get_median_weight(0).await.is_err());
+}
+
+#[tokio::test]
+async fn futures_and_errors_cross_tasks() {
+    let scale: std::sync::Arc<dyn DynAsyncScale + Send + Sync> = std::sync::Arc::new(SlowScale {
+        delay: Duration::from_millis(1),
+    });
+    let error = tokio::spawn(async move { scale.get_median_weight(0).await })
+        .await
+        .unwrap()
+        .unwrap_err();
+    assert_eq!(error.to_string(), "no samples requested");
+}
@@ -0,0 +1,329 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use libra::failover::{FailoverPolicy, FailoverRole, FailoverScale, SwitchReason, Switchover};
+use libra::scale::ScaleError;
+use libra::watchdog::StaleChannel;
+use libra::{Grams, MedianGrams, Scale, ScaleErrorInfo, ScaleErrorKind};
+
+/// What a [`Platform`] is made to do, shared with the test after the
+/// platform is moved into a failover.
+#[derive(Default)]
+struct Faults {
+    /// Fails every call with the error this makes.
+    failing: Mutex<Option<fn() -> ScaleError>>,
+    stale: Mutex<Option<StaleChannel>>,
+    reads: AtomicUsize,
+    tares: AtomicUsize,
+}
+
+impl Faults {
+    fn fail(&self, error: fn() -> ScaleError) {
+        *self.failing.lock().unwrap() = Some(error);
+    }
+
+    fn recover(&self) {
+        *self.failing.lock().unwrap() = None;
+    }
+
+    fn check(&self) -> Result<(), ScaleError> {
+        match *self.failing.lock().unwrap() {
+            Some(error) => Err(error()),
+            None => Ok(()),
+        }
+    }
+}
+
+struct Platform {
+    weight: f64,
+    faults: Arc<Faults>,
+}
+
+impl Scale for Platform {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.faults.reads.fetch_add(1, Ordering::SeqCst);
+        self.faults.check()?;
+        Ok(Grams(self.weight))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_median_weight_of(3)
+    }
+
+    fn tare(&mut self, _samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.faults.check()?;
+        self.faults.tares.fetch_add(1, Ordering::SeqCst);
+        Ok(Grams(self.weight))
+    }
+
+    fn stale_channel(&self) -> Option<StaleChannel> {
+        *self.faults.stale.lock().unwrap()
+    }
+}
+
+fn timeout() -> ScaleError {
+    ScaleError::Timeout {
+        elapsed: Duration::from_millis(500),
+        collected: 0,
+    }
+}
+
+fn detached() -> ScaleError {
+    ScaleError::Disconnected { channel: None }
+}
+
+/// A failover from a primary weighing 100 g to a backup weighing 101 g,
+/// with the faults of each and the switches it has made.
+#[allow(clippy::type_complexity)]
+fn station(
+    policy: FailoverPolicy,
+) -> (
+    FailoverScale<Platform>,
+    [Arc<Faults>; 2],
+    Arc<Mutex<Vec<Switchover>>>,
+) {
+    let faults = [Arc::<Faults>::default(), Arc::default()];
+    let switches = Arc::new(Mutex::new(Vec::new()));
+    let scale = FailoverScale::new(
+        Platform {
+            weight: 100.,
+            faults: Arc::clone(&faults[0]),
+        },
+        Platform {
+            weight: 101.,
+            faults: Arc::clone(&faults[1]),
+        },
+        policy,
+    )
+    .on_switch({
+        let switches = Arc::clone(&switches);
+        move |switch| switches.lock().unwrap().push(switch.clone())
+    });
+    (scale, faults, switches)
+}
+
+fn scale_error(error: Box<dyn std::error::Error + Send + Sync>) -> ScaleError {
+    *error.downcast::<ScaleError>().unwrap()
+}
+
+#[test]
+fn the_primary_fails_over_after_failures_in_a_row() {
+    let (scale, [primary, backup], switches) = station(FailoverPolicy {
+        failure_threshold: 2,
+        ..FailoverPolicy::default()
+    });
+    assert_eq!(scale.get_weight().unwrap(), Grams(100.));
+
+    primary.fail(timeout);
+    // The first failure is returned as it is.
+    let error = scale_error(scale.get_weight().unwrap_err());
+    assert!(matches!(error, ScaleError::Timeout { .. }), "{error:?}");
+    assert_eq!(scale.active(), FailoverRole::Primary);
+    // The second fails over, and the read is made again on the backup.
+    assert_eq!(scale.get_weight().unwrap(), Grams(101.));
+    assert_eq!(scale.active(), FailoverRole::Backup);
+    assert_eq!(scale.get_median_weight_of(3).unwrap(), MedianGrams(101.));
+    assert_eq!(primary.reads.load(Ordering::SeqCst), 3);
+    assert_eq!(backup.reads.load(Ordering::SeqCst), 4);
+
+    let switches = switches.lock().unwrap();
+    assert_eq!(switches.len(), 1);
+    let Switchover { from, to, reason } = &switches[0];
+    assert_eq!((*from, *to), (FailoverRole::Primary, FailoverRole::Backup));
+    assert!(
+        matches!(reason, SwitchReason::Failures { failures: 2, last_error }
+            if last_error.kind == ScaleErrorKind::Timeout),
+        "{reason:?}"
+    );
+}
+
+#[test]
+fn a_success_or_a_permanent_error_resets_the_count() {
+    let (scale, [primary, _], _) = station(FailoverPolicy {
+        failure_threshold: 2,
+        ..FailoverPolicy::default()
+    });
+    for _ in 0..3 {
+        primary.fail(timeout);
+        assert!(scale.get_weight().is_err());
+        primary.fail(|| ScaleError::InvalidArgument("no".into()));
+        assert!(scale.get_weight().is_err());
+    }
+    primary.fail(detached);
+    assert!(scale.get_weight().is_err());
+    primary.recover();
+    assert_eq!(scale.get_weight().unwrap(), Grams(100.));
+    assert_eq!(scale.active(), FailoverRole::Primary);
+}
+
+#[test]
+fn an_open_circuit_or_a_frozen_load_cell_fails_over_at_once() {
+    let (scale, [primary, _], switches) = station(FailoverPolicy::default());
+    primary.fail(|| ScaleError::CircuitOpen {
+        since: SystemTime::now(),
+        last_error: ScaleErrorInfo::new(ScaleErrorKind::Timeout, "Timed out"),
+    });
+    assert_eq!(scale.get_weight().unwrap(), Grams(101.));
+    assert_eq!(
+        switches.lock().unwrap()[0].reason,
+        SwitchReason::CircuitOpen
+    );
+
+    let (scale, [primary, _], switches) = station(FailoverPolicy::default());
+    let stale = StaleChannel {
+        channel: 2,
+        age: Duration::from_secs(90),
+    };
+    *primary.stale.lock().unwrap() = Some(stale);
+    // The frozen primary reads fine, but the weight comes from the backup.
+    assert_eq!(scale.get_weight().unwrap(), Grams(101.));
+    assert_eq!(
+        switches.lock().unwrap()[0].reason,
+        SwitchReason::Stale(stale)
+    );
+    assert_eq!(scale.stale_channel(), None);
+
+    // Not so when the policy leaves them to the failure count.
+    let (scale, [primary, _], _) = station(FailoverPolicy {
+        on_circuit_open: false,
+        on_stale: false,
+        ..FailoverPolicy::default()
+    });
+    *primary.stale.lock().unwrap() = Some(stale);
+    assert_eq!(scale.get_weight().unwrap(), Grams(100.));
+    assert_eq!(scale.stale_channel(), Some(stale));
+}
+
+#[test]
+fn the_primary_is_probed_and_failed_back_to_once_it_recovers() {
+    let (scale, [primary, _], switches) = station(FailoverPolicy {
+        failure_threshold: 1,
+        probe_interval: Duration::from_millis(50),
+        ..FailoverPolicy::default()
+    });
+    primary.fail(detached);
+    assert_eq!(scale.get_weight().unwrap(), Grams(101.));
+    let probed = primary.reads.load(Ordering::SeqCst);
+
+    // A probe while the primary is down puts the next one off.
+    assert!(!scale.probe());
+    assert_eq!(primary.reads.load(Ordering::SeqCst), probed + 1);
+    primary.recover();
+    // Until the interval is up, reads stay on the backup without probing.
+    assert_eq!(scale.get_weight().unwrap(), Grams(101.));
+    assert_eq!(primary.reads.load(Ordering::SeqCst), probed + 1);
+
+    std::thread::sleep(Duration::from_millis(60));
+    assert_eq!(scale.get_weight().unwrap(), Grams(100.));
+    assert_eq!(scale.active(), FailoverRole::Primary);
+    let switches = switches.lock().unwrap();
+    assert_eq!(switches.len(), 2);
+    assert_eq!(
+        switches[1],
+        Switchover {
+            from: FailoverRole::Backup,
+            to: FailoverRole::Primary,
+            reason: SwitchReason::Recovered,
+        }
+    );
+}
+
+#[test]
+fn a_read_fails_only_when_both_scales_do() {
+    let (scale, [primary, backup], switches) = station(FailoverPolicy {
+        failure_threshold: 1,
+        probe_interval: Duration::from_secs(60),
+        ..FailoverPolicy::default()
+    });
+    primary.fail(detached);
+    backup.fail(timeout);
+    let error = scale_error(scale.get_weight().unwrap_err());
+    assert!(
+        matches!(&error, ScaleError::BothScalesFailed { primary, backup }
+            if matches!(**primary, ScaleError::Disconnected { .. })
+                && matches!(**backup, ScaleError::Timeout { .. })),
+        "{error:?}"
+    );
+    // The harder of the two to recover from.
+    assert_eq!(error.kind(), ScaleErrorKind::Disconnected);
+    assert!(error.is_disconnection());
+    assert_eq!(scale.active(), FailoverRole::Backup);
+
+    // With the backup still down, the primary is tried at once, and taken
+    // back to as soon as it answers.
+    primary.recover();
+    assert_eq!(scale.get_weight().unwrap(), Grams(100.));
+    assert_eq!(scale.active(), FailoverRole::Primary);
+    assert_eq!(switches.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn taring_is_done_to_both_scales_or_fails() {
+    let (mut scale, [primary, backup], _) = station(FailoverPolicy::default());
+    assert_eq!(scale.tare(5).unwrap(), Grams(100.));
+    assert_eq!(primary.tares.load(Ordering::SeqCst), 1);
+    assert_eq!(backup.tares.load(Ordering::SeqCst), 1);
+
+    backup.fail(detached);
+    let error = scale_error(scale.tare(5).unwrap_err());
+    assert!(
+        matches!(&error, ScaleError::FailoverMemberFailed { role: FailoverRole::Backup, error }
+            if matches!(**error, ScaleError::Disconnected { .. })),
+        "{error:?}"
+    );
+    assert_eq!(
+        error.to_string(),
+        "The backup scale of the failover failed: Scale is disconnected"
+    );
+    assert_eq!(primary.tares.load(Ordering::SeqCst), 2);
+
+    // One calibration cannot be right for both platforms.
+    let calibration = libra::calibration::Calibration {
+        offset: 0.,
+        coefficients: [1.; libra::scale::NUMBER_OF_INPUTS],
+    };
+    let error = scale_error(scale.set_calibration(calibration).unwrap_err());
+    assert_eq!(error.kind(), ScaleErrorKind::Unsupported);
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn injected_faults_fail_over_and_back() {
+    use libra::testing::{FaultMethod, FaultRule, FaultSchedule, FaultyScale};
+    use phidget::ReturnCode;
+
+    let platform = |weight| Platform {
+        weight,
+        faults: Arc::default(),
+    };
+    // The primary's bridge times out on reads 2 to 4.
+    let primary = FaultyScale::new(
+        platform(100.),
+        FaultSchedule::new().with_rule(
+            FaultRule::error(ReturnCode::Timeout)
+                .on(FaultMethod::GetWeight)
+                .calls(2..=4),
+        ),
+    );
+    let backup = FaultyScale::new(platform(101.), FaultSchedule::new());
+    let scale = FailoverScale::new(
+        primary,
+        backup,
+        FailoverPolicy {
+            failure_threshold: 2,
+            probe_interval: Duration::ZERO,
+            ..FailoverPolicy::default()
+        },
+    );
+    let weights: Vec<_> = (0..5)
+        .map(|_| scale.get_weight().map(|weight| weight.0).ok())
+        .collect();
+    // Read 3 fails over; the probe before the next read is the primary's
+    // fourth call, which fails, and the one after succeeds.
+    assert_eq!(
+        weights,
+        [Some(100.), None, Some(101.), Some(101.), Some(100.)]
+    );
+    assert_eq!(scale.active(), FailoverRole::Primary);
+}
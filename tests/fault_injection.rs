@@ -0,0 +1,340 @@
+#[cfg(feature = "hardware")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "hardware")]
+use libra::breaker::{BreakerConfig, BreakerState, CircuitBreaker, CircuitBreakerScale};
+#[cfg(feature = "hardware")]
+use libra::calibration::Calibration;
+#[cfg(feature = "hardware")]
+use libra::retry::RetryPolicy;
+#[cfg(feature = "hardware")]
+use libra::scale::{ConnectedScale, ScaleError, NUMBER_OF_INPUTS};
+#[cfg(feature = "hardware")]
+use libra::source::VoltageSource;
+#[cfg(feature = "hardware")]
+use libra::testing::{
+    FakeVoltageSource, Fault, FaultMethod, FaultRule, FaultSchedule, FaultyScale,
+};
+#[cfg(feature = "hardware")]
+use libra::{Grams, MedianGrams, Scale, ScaleErrorKind};
+#[cfg(feature = "hardware")]
+use phidget::ReturnCode;
+
+#[cfg(feature = "hardware")]
+const SERIAL: i32 = 159_000;
+#[cfg(feature = "hardware")]
+const CALIBRATION: Calibration = Calibration {
+    offset: 0.,
+    coefficients: [1000.; NUMBER_OF_INPUTS],
+};
+
+/// Reads 100 g, and 0.25 from every load cell.
+#[cfg(feature = "hardware")]
+struct Constant;
+
+#[cfg(feature = "hardware")]
+impl Scale for Constant {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(100.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(MedianGrams(100.))
+    }
+
+    fn get_raw_readings(
+        &self,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        Ok([0.25; NUMBER_OF_INPUTS])
+    }
+}
+
+/// The load cell and return code of a phidget error.
+#[cfg(feature = "hardware")]
+fn phidget(error: Box<dyn std::error::Error + Send + Sync>) -> (usize, ReturnCode) {
+    match error.downcast::<ScaleError>().map(|error| *error) {
+        Ok(ScaleError::PhidgetError(error)) => (error.load_cell(), error.return_code()),
+        other => panic!("not a phidget error: {other:?}"),
+    }
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn errors_hit_exactly_the_scheduled_calls() {
+    let schedule = FaultSchedule::new().with_rule(
+        FaultRule::error(ReturnCode::Timeout)
+            .on(FaultMethod::GetWeight)
+            .calls(3..=5),
+    );
+    let scale = FaultyScale::new(Constant, schedule);
+    let log = scale.log();
+    let results: Vec<bool> = (0..8).map(|_| scale.get_weight().is_ok()).collect();
+    assert_eq!(results, [true, true, false, false, false, true, true, true]);
+    assert_eq!(log.matched(0), 8);
+    assert_eq!(log.fired(0), 3);
+    assert_eq!(log.total_fired(), 3);
+
+    // Other methods are not counted against the rule.
+    scale.get_raw_readings().unwrap();
+    assert_eq!(log.matched(0), 8);
+    assert_eq!(
+        phidget(
+            FaultyScale::new(
+                Constant,
+                FaultSchedule::new().with_rule(FaultRule::error(ReturnCode::Busy)),
+            )
+            .get_weight()
+            .unwrap_err()
+        ),
+        (0, ReturnCode::Busy)
+    );
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn ranges_of_calls_are_inclusive_exclusive_or_open() {
+    let fired = |rule: FaultRule| {
+        let scale = FaultyScale::new(Constant, FaultSchedule::new().with_rule(rule));
+        (1..=6).filter(|_| scale.get_weight().is_err()).count()
+    };
+    let error = || FaultRule::error(ReturnCode::Timeout);
+    assert_eq!(fired(error()), 6);
+    assert_eq!(fired(error().calls(2..4)), 2);
+    assert_eq!(fired(error().calls(5..)), 2);
+    assert_eq!(fired(error().calls(..=1)), 1);
+    assert_eq!(fired(error().calls(4..4)), 0);
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn channel_faults_hit_only_their_load_cell() {
+    let schedule = FaultSchedule::new()
+        .with_rule(
+            FaultRule::error(ReturnCode::NotAttached)
+                .on(FaultMethod::Ratio)
+                .on_channel(2)
+                .calls(3..=5),
+        )
+        .with_rule(
+            FaultRule::nan()
+                .on(FaultMethod::Ratio)
+                .on_channel(1)
+                .calls(1..=1),
+        );
+    let fakes = FakeVoltageSource::bridge(SERIAL);
+    for fake in &fakes {
+        fake.set_ratio(0.1);
+    }
+    let sources = FaultyScale::bridge(fakes.clone(), schedule);
+    let log = sources[0].log();
+
+    assert!(sources[1].ratio().unwrap().is_nan());
+    assert_eq!(sources[1].ratio().unwrap(), 0.1);
+    for _ in 0..2 {
+        assert_eq!(sources[2].ratio(), Ok(0.1));
+    }
+    for _ in 0..3 {
+        assert_eq!(sources[2].ratio(), Err(ReturnCode::NotAttached));
+        assert_eq!(sources[3].ratio(), Ok(0.1));
+    }
+    assert_eq!(sources[2].ratio(), Ok(0.1));
+    assert_eq!((log.matched(0), log.fired(0)), (6, 3));
+    assert_eq!((log.matched(1), log.fired(1)), (2, 1));
+    // Faulted reads still reach the source, except for errors.
+    assert_eq!(fakes[1].reads(), 2);
+    assert_eq!(fakes[2].reads(), 3);
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn a_connected_scale_sees_the_faults_of_its_sources() {
+    let schedule = FaultSchedule::new().with_rule(
+        FaultRule::error(ReturnCode::NotAttached)
+            .on(FaultMethod::Ratio)
+            .on_channel(2)
+            .calls(3..=5),
+    );
+    let fakes = FakeVoltageSource::bridge(SERIAL + 1);
+    for fake in &fakes {
+        fake.set_ratio(0.001);
+    }
+    let sources = FaultyScale::bridge(fakes, schedule);
+    let log = sources[0].log();
+    let scale = ConnectedScale::from_sources(SERIAL + 1, CALIBRATION, sources);
+
+    for _ in 0..2 {
+        assert_eq!(scale.get_weight().unwrap(), Grams(4.));
+    }
+    for _ in 0..3 {
+        let error = scale.get_weight().unwrap_err();
+        assert_eq!(phidget(error.into()), (2, ReturnCode::NotAttached));
+    }
+    assert_eq!(scale.get_weight().unwrap(), Grams(4.));
+    assert_eq!(log.fired(0), 3);
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn latency_delays_the_call_and_nan_replaces_the_reading() {
+    let schedule = FaultSchedule::new()
+        .with_rule(FaultRule::latency(Duration::from_millis(30)).calls(1..=1))
+        .with_rule(
+            FaultRule::nan()
+                .on(FaultMethod::GetRawReadings)
+                .on_channel(3),
+        )
+        .with_rule(FaultRule::nan().on(FaultMethod::GetWeight).calls(2..=2));
+    let scale = FaultyScale::new(Constant, schedule);
+    let log = scale.log();
+
+    let start = Instant::now();
+    assert_eq!(scale.get_weight().unwrap(), Grams(100.));
+    assert!(start.elapsed() >= Duration::from_millis(30));
+    let start = Instant::now();
+    assert!(scale.get_weight().unwrap().get().is_nan());
+    assert!(start.elapsed() < Duration::from_millis(30));
+
+    let ratios = scale.get_raw_readings().unwrap();
+    assert_eq!(ratios[..3], [0.25; 3]);
+    assert!(ratios[3].is_nan());
+    assert_eq!((log.fired(0), log.fired(1), log.fired(2)), (1, 1, 1));
+
+    // NaN in a median is an error, as from a failing bridge.
+    let scale = FaultyScale::new(
+        Constant,
+        FaultSchedule::new().with_rule(FaultRule::nan().calls(2..=2)),
+    );
+    let error = scale.get_median_weight_of(3).unwrap_err();
+    assert_eq!(
+        libra::ScaleErrorInfo::from_dyn(&*error).kind,
+        ScaleErrorKind::NonFinite
+    );
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn the_first_error_or_nan_wins_and_the_rest_go_uncounted() {
+    let schedule = FaultSchedule::new()
+        .with_rule(FaultRule::error(ReturnCode::Busy).calls(1..=1))
+        .with_rule(FaultRule::error(ReturnCode::Timeout))
+        .with_rule(FaultRule::nan().on(FaultMethod::Status));
+    let mut scale = FaultyScale::new(Constant, schedule);
+    let log = scale.log();
+    assert_eq!(phidget(scale.get_weight().unwrap_err()).1, ReturnCode::Busy);
+    assert_eq!(
+        phidget(scale.get_weight().unwrap_err()).1,
+        ReturnCode::Timeout
+    );
+    assert_eq!(phidget(scale.status().unwrap_err()).1, ReturnCode::Timeout);
+    assert_eq!((log.fired(0), log.fired(1), log.fired(2)), (1, 2, 0));
+    assert_eq!(log.matched(2), 1);
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn retries_and_breakers_can_be_driven_by_a_schedule() {
+    let schedule = FaultSchedule::new().with_rule(
+        FaultRule::error(ReturnCode::Timeout)
+            .on(FaultMethod::GetWeight)
+            .calls(1..=2),
+    );
+    let scale = FaultyScale::new(Constant, schedule.clone());
+    let policy = RetryPolicy::fixed(3, Duration::ZERO);
+    let weight = policy.execute(|_| scale.get_weight());
+    assert_eq!(weight.unwrap(), Grams(100.));
+    assert_eq!(scale.log().matched(0), 3);
+
+    let breaker = CircuitBreaker::new(BreakerConfig {
+        failure_threshold: 2,
+        cooldown: Duration::from_secs(60),
+    });
+    let scale = CircuitBreakerScale::new(FaultyScale::new(Constant, schedule), breaker);
+    assert!(scale.get_weight().is_err());
+    assert!(scale.get_weight().is_err());
+    assert_eq!(scale.state(), BreakerState::Open);
+    assert!(scale.get_weight().is_err());
+    assert_eq!(scale.get_ref().log().matched(0), 2);
+}
+
+#[cfg(feature = "hardware")]
+const TOML: &str = r#"
+# Fail calls 3 to 5 on channel 2 with NotAttached, then succeed.
+[[fault]]
+method = "ratio"
+channel = 2
+first = 3
+last = 5
+error = "NotAttached"
+
+[[fault]]   # Slow down everything a little.
+latency_ms = 1.5
+
+[[fault]]
+method = "get_raw_readings"
+nan = true
+last = 1
+
+[[fault]]
+error = 3
+"#;
+
+#[cfg(feature = "hardware")]
+#[test]
+fn schedules_load_from_toml() {
+    let schedule = FaultSchedule::from_toml(TOML).unwrap();
+    assert_eq!(
+        schedule,
+        FaultSchedule::new()
+            .with_rule(
+                FaultRule::error(ReturnCode::NotAttached)
+                    .on(FaultMethod::Ratio)
+                    .on_channel(2)
+                    .calls(3..=5)
+            )
+            .with_rule(FaultRule::latency(Duration::from_micros(1500)))
+            .with_rule(FaultRule::nan().on(FaultMethod::GetRawReadings).calls(..=1))
+            .with_rule(FaultRule::error(ReturnCode::Timeout))
+    );
+    assert_eq!(TOML.parse::<FaultSchedule>().unwrap(), schedule);
+    assert_eq!(
+        FaultSchedule::from_toml("# nothing\n").unwrap(),
+        FaultSchedule::new()
+    );
+    for method in FaultMethod::ALL {
+        let toml = format!("[[fault]]\nmethod = \"{}\"\nnan = true\n", method.name());
+        let rule = &FaultSchedule::from_toml(&toml).unwrap().rules[0];
+        assert_eq!(rule.method, Some(method));
+        assert_eq!(rule.fault, Fault::NaN);
+    }
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn bad_toml_is_reported_by_line() {
+    for (toml, line) in [
+        ("error = \"Busy\"\n", 1),
+        ("[[fault]]\n", 1),
+        (
+            "[[fault]]\nerror = \"Busy\"\n\n[[fault]]\nmethod = \"ratio\"\n",
+            4,
+        ),
+        ("[[fault]]\nerror = \"Bogus\"\n", 2),
+        ("[[fault]]\nerror = 999\n", 2),
+        ("[[fault]]\nerror = true\n", 2),
+        ("[[fault]]\nerror = \"Busy\"\nnan = true\n", 3),
+        ("[[fault]]\nnan = false\n", 2),
+        ("[[fault]]\nnan = true\nmethod = \"recalibrate\"\n", 3),
+        ("[[fault]]\nnan = true\nchannel = 4\n", 3),
+        ("[[fault]]\nnan = true\nfirst = -1\n", 3),
+        ("[[fault]]\nnan = true\nfirst = 1\nfirst = 2\n", 4),
+        ("[[fault]]\nnan = true\ncolor = \"red\"\n", 3),
+        ("[[fault]]\nnan = true\nlast\n", 3),
+        ("[[fault]]\nnan = true\nlast = [1, 2]\n", 3),
+        ("[[fault]]\nlatency_ms = -5\n", 2),
+        ("[faults]\n", 1),
+    ] {
+        let error = FaultSchedule::from_toml(toml).unwrap_err();
+        assert_eq!(error.line, line, "{toml:?}: {error}");
+        assert!(error.to_string().starts_with(&format!("Line {line} ")));
+    }
+}
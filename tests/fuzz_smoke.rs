@@ -0,0 +1,222 @@
+use std::path::PathBuf;
+
+use libra::serial::{encode_frame, encode_response_frame};
+use libra::{ScaleCmd, ScaleResponse};
+
+#[path = "../fuzz/src/lib.rs"]
+mod checks;
+
+/// Inputs each check gets beyond its seeds. Enough to catch a decoder that
+/// panics on the first bad length or tag it sees, and quick in a debug
+/// build; the fuzz targets are for searching further.
+const ITERATIONS: usize = 4000;
+
+const JSON_VECTORS: &str = include_str!("vectors/json.txt");
+const POSTCARD_VECTORS: &str = include_str!("vectors/postcard.txt");
+
+/// Masses written the ways the command line accepts, and a few it rejects.
+const MASSES: [(&str, &str); 6] = [
+    ("grams", "500g"),
+    ("kilograms", "1.5kg"),
+    ("bare", "750"),
+    ("spaced", " 2.5 kg "),
+    ("exponent", "1e3g"),
+    ("negative", "-5g"),
+];
+
+/// SplitMix64, so that every run tries the same inputs.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// A byte, often one that means something to a decoder.
+    fn byte(&mut self) -> u8 {
+        const INTERESTING: [u8; 10] = [0x00, 0x01, 0x02, 0x03, 0x7f, 0x80, 0xff, b'"', b'{', b'9'];
+        match self.below(2) {
+            0 => INTERESTING[self.below(INTERESTING.len())],
+            _ => self.next() as u8,
+        }
+    }
+}
+
+/// A seed with a few bytes flipped, replaced, inserted, removed or spliced
+/// in from another seed, or now and then bytes made up from nothing.
+fn mutate(rng: &mut Rng, seeds: &[(String, Vec<u8>)]) -> Vec<u8> {
+    if rng.below(8) == 0 {
+        return (0..rng.below(64)).map(|_| rng.byte()).collect();
+    }
+    let mut input = seeds[rng.below(seeds.len())].1.clone();
+    for _ in 0..=rng.below(4) {
+        let at = rng.below(input.len() + 1);
+        match rng.below(6) {
+            0 if at < input.len() => input[at] ^= 1 << rng.below(8),
+            1 if at < input.len() => input[at] = rng.byte(),
+            2 => input.insert(at, rng.byte()),
+            3 if at < input.len() => {
+                input.remove(at);
+            }
+            4 => input.truncate(at),
+            _ => {
+                let other = &seeds[rng.below(seeds.len())].1;
+                let from = rng.below(other.len() + 1);
+                input.splice(at..at, other[from..].iter().copied());
+            }
+        }
+    }
+    input
+}
+
+/// Runs `check` on every seed and then on `ITERATIONS` mutations of them.
+fn smoke(check: fn(&[u8]), seeds: &[(String, Vec<u8>)]) {
+    assert!(!seeds.is_empty());
+    for (_, seed) in seeds {
+        check(seed);
+    }
+    let mut rng = Rng(0x5eed);
+    for _ in 0..ITERATIONS {
+        check(&mutate(&mut rng, seeds));
+    }
+}
+
+/// The vectors of type `kind` in a `<type> <name> <value>` file.
+fn vectors<'a>(file: &'a str, kind: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+    file.lines()
+        .filter(|line| !line.starts_with('#') && !line.is_empty())
+        .filter_map(move |line| {
+            let mut fields = line.splitn(3, ' ');
+            (fields.next() == Some(kind)).then(|| (fields.next().unwrap(), fields.next().unwrap()))
+        })
+}
+
+fn unhex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+fn json_commands() -> Vec<(&'static str, ScaleCmd)> {
+    vectors(JSON_VECTORS, "cmd")
+        .map(|(name, json)| (name, serde_json::from_str(json).unwrap()))
+        .collect()
+}
+
+fn frame_seeds() -> Vec<(String, Vec<u8>)> {
+    let mut seeds: Vec<_> = json_commands()
+        .into_iter()
+        .map(|(name, cmd)| (format!("cmd-{name}"), encode_frame(&cmd)))
+        .collect();
+    seeds.extend(vectors(JSON_VECTORS, "response").map(|(name, json)| {
+        let response: ScaleResponse = serde_json::from_str(json).unwrap();
+        (
+            format!("response-{name}"),
+            encode_response_frame(&response).unwrap(),
+        )
+    }));
+    let stream = seeds.iter().flat_map(|(_, frame)| frame.clone()).collect();
+    seeds.push(("stream".into(), stream));
+    seeds
+}
+
+fn json_seeds() -> Vec<(String, Vec<u8>)> {
+    vectors(JSON_VECTORS, "cmd")
+        .flat_map(|(name, json)| {
+            [
+                (format!("cmd-{name}"), json.as_bytes().to_vec()),
+                (
+                    format!("request-{name}"),
+                    format!(r#"{{"id":7,"cmd":{json}}}"#).into_bytes(),
+                ),
+            ]
+        })
+        .collect()
+}
+
+fn postcard_seeds() -> Vec<(String, Vec<u8>)> {
+    ["cmd", "response"]
+        .into_iter()
+        .flat_map(|kind| {
+            vectors(POSTCARD_VECTORS, kind)
+                .map(move |(name, hex)| (format!("{kind}-{name}"), unhex(hex)))
+        })
+        .collect()
+}
+
+fn mass_seeds() -> Vec<(String, Vec<u8>)> {
+    MASSES
+        .iter()
+        .map(|(name, mass)| (name.to_string(), mass.as_bytes().to_vec()))
+        .collect()
+}
+
+#[test]
+fn the_frame_decoder_only_errs() {
+    smoke(checks::frame_decoder, &frame_seeds());
+}
+
+#[test]
+fn json_command_decoding_only_errs() {
+    smoke(checks::json_cmd, &json_seeds());
+}
+
+#[cfg(feature = "binary-proto")]
+#[test]
+fn postcard_decoding_only_errs() {
+    smoke(checks::postcard, &postcard_seeds());
+}
+
+#[cfg(feature = "cli")]
+#[test]
+fn mass_parsing_only_errs() {
+    smoke(checks::parse_mass, &mass_seeds());
+}
+
+/// Checks that fuzz/seeds holds exactly the seeds above, one file per seed
+/// in a directory per target. With `WRITE_FUZZ_SEEDS` set, rewrites it.
+#[test]
+fn the_fuzz_seeds_match_the_vectors() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fuzz/seeds");
+    let targets = [
+        ("frame_decoder", frame_seeds()),
+        ("json_cmd", json_seeds()),
+        ("postcard", postcard_seeds()),
+        ("parse_mass", mass_seeds()),
+    ];
+    for (target, seeds) in targets {
+        let dir = root.join(target);
+        if std::env::var_os("WRITE_FUZZ_SEEDS").is_some() {
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            for (name, seed) in &seeds {
+                std::fs::write(dir.join(name), seed).unwrap();
+            }
+            continue;
+        }
+        let mut files: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap_or_else(|e| panic!("{}: {e}", dir.display()))
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        let mut names: Vec<_> = seeds.iter().map(|(name, _)| name.clone()).collect();
+        names.sort();
+        assert_eq!(files, names, "{target}");
+        for (name, seed) in &seeds {
+            assert_eq!(
+                &std::fs::read(dir.join(name)).unwrap(),
+                seed,
+                "{target} {name}"
+            );
+        }
+    }
+}
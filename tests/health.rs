@@ -0,0 +1,271 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime};
+
+use libra::health::{
+    check, CalibrationExpiry, CalibrationPolicy, ExpiryAction, HealthConfig, Status,
+};
+use libra::scale::{all_channels, PhidgetError, ScaleError, NUMBER_OF_INPUTS};
+use libra::watchdog::StaleChannel;
+use libra::{Grams, MedianGrams, Scale, ScaleErrorInfo, ScaleErrorKind};
+
+/// A bridge whose load cells read 1e-4 V/V each, wobbling by `noise`, with
+/// the load cells in `detached` failing every read and `failing_reads`
+/// failing outright.
+#[derive(Default)]
+struct MockScale {
+    noise: [f64; NUMBER_OF_INPUTS],
+    detached: Vec<usize>,
+    failing_reads: Vec<u64>,
+    stale: Option<StaleChannel>,
+    reads: AtomicU64,
+}
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+
+    fn get_raw_readings(
+        &self,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        let read = self.reads.fetch_add(1, Ordering::Relaxed);
+        if self.failing_reads.contains(&read) {
+            return Err(ScaleError::Busy.into());
+        }
+        let sign = if read.is_multiple_of(2) { 1. } else { -1. };
+        Ok(all_channels(std::array::from_fn(|channel| {
+            if self.detached.contains(&channel) {
+                // Not attached, as numbered by phidget22.
+                Err(ScaleError::PhidgetError(PhidgetError::from_code(
+                    52, channel,
+                )))
+            } else {
+                Ok(1e-4 + sign * self.noise[channel])
+            }
+        }))?)
+    }
+
+    fn stale_channel(&self) -> Option<StaleChannel> {
+        self.stale
+    }
+}
+
+fn config() -> HealthConfig {
+    HealthConfig {
+        sample_interval: Duration::ZERO,
+        noise_degraded: 1e-6,
+        noise_unusable: 1e-5,
+        calibration_degraded: Some(Duration::from_secs(3600)),
+        calibration_unusable: Some(Duration::from_secs(7200)),
+        calibration_policy: None,
+    }
+}
+
+fn quiet() -> MockScale {
+    MockScale {
+        noise: [1e-8; NUMBER_OF_INPUTS],
+        ..MockScale::default()
+    }
+}
+
+fn ago(secs: u64) -> Option<SystemTime> {
+    Some(SystemTime::now() - Duration::from_secs(secs))
+}
+
+#[test]
+fn a_quiet_attached_scale_is_ok() {
+    let report = check(&quiet(), 10, ago(60), None, &config()).unwrap();
+    assert_eq!(report.status, Status::Ok, "{report}");
+    assert!(report.reasons.is_empty());
+    assert_eq!((report.reads, report.failed_reads), (10, 0));
+    for (channel, health) in report.channels.iter().enumerate() {
+        assert_eq!(health.channel, channel);
+        assert!(health.attached);
+        let noise = health.noise.unwrap();
+        assert!(noise > 0. && noise < 1e-6, "{noise}");
+    }
+    let age = report.calibration_age.unwrap();
+    assert!(age >= Duration::from_secs(60) && age < Duration::from_secs(70));
+}
+
+#[test]
+fn noise_and_old_calibrations_degrade() {
+    let mut scale = quiet();
+    scale.noise[2] = 3e-6;
+    let report = check(&scale, 10, None, None, &config()).unwrap();
+    assert_eq!(report.status, Status::Degraded, "{report}");
+    assert_eq!(report.reasons.len(), 1);
+    assert!(report.reasons[0].starts_with("Load Cell 2 is noisy"));
+
+    let report = check(&quiet(), 10, ago(5000), None, &config()).unwrap();
+    assert_eq!(report.status, Status::Degraded, "{report}");
+    assert!(report.reasons[0].starts_with("Calibration is"));
+}
+
+#[test]
+fn a_few_failed_reads_degrade() {
+    let scale = MockScale {
+        failing_reads: vec![3],
+        ..quiet()
+    };
+    let report = check(&scale, 10, None, None, &config()).unwrap();
+    assert_eq!(report.status, Status::Degraded, "{report}");
+    assert_eq!(report.failed_reads, 1);
+    assert_eq!(report.reasons, ["1 of 10 raw reads failed"]);
+    assert_eq!(report.last_error.unwrap().kind, ScaleErrorKind::Busy);
+    assert!(report.channels.iter().all(|channel| channel.attached));
+}
+
+#[test]
+fn detached_load_cells_are_unusable() {
+    let scale = MockScale {
+        detached: vec![1, 3],
+        ..quiet()
+    };
+    let report = check(&scale, 5, None, None, &config()).unwrap();
+    assert_eq!(report.status, Status::Unusable, "{report}");
+    let attached: Vec<bool> = report.channels.iter().map(|c| c.attached).collect();
+    assert_eq!(attached, [true, false, true, false]);
+    assert!(report
+        .channels
+        .iter()
+        .all(|channel| channel.noise.is_none()));
+    assert!(report
+        .reasons
+        .contains(&"Load Cell 3 is detached".to_string()));
+    assert_eq!(report.last_error.unwrap().kind, ScaleErrorKind::Phidget);
+}
+
+#[test]
+fn noise_staleness_and_ancient_calibrations_are_unusable() {
+    let mut scale = quiet();
+    scale.noise[0] = 2e-5;
+    assert_eq!(
+        check(&scale, 10, None, None, &config()).unwrap().status,
+        Status::Unusable
+    );
+
+    let scale = MockScale {
+        stale: Some(StaleChannel {
+            channel: 2,
+            age: Duration::from_secs(90),
+        }),
+        ..quiet()
+    };
+    let report = check(&scale, 10, None, None, &config()).unwrap();
+    assert_eq!(report.status, Status::Unusable);
+    assert_eq!(report.reasons, ["Load Cell 2 has not changed in 90s"]);
+
+    let report = check(&quiet(), 10, ago(10_000), None, &config()).unwrap();
+    assert_eq!(report.status, Status::Unusable);
+}
+
+#[test]
+fn the_last_error_before_the_check_is_kept() {
+    let before = ScaleErrorInfo::new(ScaleErrorKind::Timeout, "Timed out");
+    let report = check(&quiet(), 3, None, Some(before.clone()), &config()).unwrap();
+    assert_eq!(report.status, Status::Ok);
+    assert_eq!(report.last_error, Some(before));
+}
+
+#[test]
+fn noise_needs_two_samples() {
+    let error = check(&quiet(), 1, None, None, &config()).unwrap_err();
+    assert!(matches!(error, ScaleError::InvalidArgument(_)));
+}
+
+#[test]
+fn reports_read_and_serialize() {
+    let scale = MockScale {
+        detached: vec![1],
+        ..quiet()
+    };
+    let report = check(&scale, 3, ago(120), None, &config()).unwrap();
+    let text = report.to_string();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[0], "Status: unusable");
+    assert!(lines.contains(&"Load Cell 1: detached"), "{text}");
+    assert!(lines.contains(&"Stale data: none"), "{text}");
+    assert!(lines.contains(&"Reads: 0 of 3 succeeded"), "{text}");
+    assert!(lines
+        .last()
+        .unwrap()
+        .starts_with("Last error: Phidget error at Load Cell 1"));
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["status"], "Unusable");
+    assert_eq!(json["channels"][1]["attached"], false);
+    assert_eq!(json["channels"][0]["attached"], true);
+    assert!(json["stale"].is_null());
+}
+
+fn policy(action: ExpiryAction) -> HealthConfig {
+    HealthConfig {
+        calibration_policy: Some(CalibrationPolicy::new(Duration::from_secs(600), action)),
+        ..config()
+    }
+}
+
+#[test]
+fn expiry_counts_whole_days_either_side() {
+    let day = Duration::from_secs(86_400);
+    let made = SystemTime::UNIX_EPOCH + 1000 * day;
+    let policy = CalibrationPolicy::new(30 * day, ExpiryAction::Warn);
+    let at = |offset: Duration| policy.expiry(made, made + offset);
+    assert_eq!(at(Duration::ZERO).days_left(), Some(30));
+    assert_eq!(at(day / 2).days_left(), Some(29));
+    assert_eq!(
+        at(30 * day),
+        CalibrationExpiry::Valid {
+            remaining: Duration::ZERO
+        }
+    );
+    assert_eq!(at(30 * day + day / 2).days_left(), Some(-1));
+    assert_eq!(at(32 * day).days_left(), Some(-2));
+    assert_eq!(
+        policy.expiry(made, made - day),
+        CalibrationExpiry::FutureDated { ahead: day }
+    );
+    assert_eq!(policy.expiry(made, made - day).days_left(), None);
+}
+
+#[test]
+fn an_expired_calibration_degrades_or_blocks() {
+    let report = check(&quiet(), 3, ago(60), None, &policy(ExpiryAction::Block)).unwrap();
+    assert_eq!(report.status, Status::Ok, "{report}");
+    assert_eq!(report.calibration_expires_in_days, Some(0));
+
+    let report = check(&quiet(), 3, ago(1200), None, &policy(ExpiryAction::Warn)).unwrap();
+    assert_eq!(report.status, Status::Degraded, "{report}");
+    assert!(report.reasons[0].starts_with("Calibration expired"));
+    assert_eq!(report.calibration_expires_in_days, Some(-1));
+    assert!(matches!(
+        report.calibration_expiry,
+        Some(CalibrationExpiry::Expired { .. })
+    ));
+    assert!(report
+        .to_string()
+        .contains("Calibration expired: 1 days ago"));
+
+    let report = check(&quiet(), 3, ago(1200), None, &policy(ExpiryAction::Block)).unwrap();
+    assert_eq!(report.status, Status::Unusable, "{report}");
+}
+
+#[test]
+fn a_future_dated_calibration_degrades() {
+    let tomorrow = Some(SystemTime::now() + Duration::from_secs(86_400));
+    for config in [config(), policy(ExpiryAction::Block)] {
+        let report = check(&quiet(), 3, tomorrow, None, &config).unwrap();
+        assert_eq!(report.status, Status::Degraded, "{report}");
+        assert!(report.calibration_future_dated);
+        assert_eq!(
+            report.reasons,
+            ["Calibration is dated in the future; check the clock"]
+        );
+        assert_eq!(report.calibration_expires_in_days, None);
+    }
+}
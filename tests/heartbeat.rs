@@ -0,0 +1,252 @@
+#![cfg(feature = "net")]
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use libra::actor::{spawn_scale_actor, spawn_scale_actor_with_config, ActorConfig};
+use libra::cancel::CancelFlag;
+use libra::client::{ClientConfig, ScaleClient};
+use libra::net::{serve_listener, ServerConfig};
+use libra::retry::RetryPolicy;
+use libra::{Grams, MedianGrams, Scale, ScaleCmd, ScaleResponse};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream, Lines};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+const HEARTBEAT: &str = r#"{"Heartbeat":{"uptime":{"secs":1,"nanos":0},"last_reading_age":null}}"#;
+
+/// Reads 10 g; a median takes 150 ms.
+struct SlowScale;
+
+impl Scale for SlowScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(10.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+
+    fn get_median_weight_cancellable(
+        &self,
+        _samples: usize,
+        _cancel: &CancelFlag,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        std::thread::sleep(Duration::from_millis(150));
+        Ok(MedianGrams(10.))
+    }
+}
+
+async fn start(actor: ActorConfig, heartbeat_interval: Duration) -> SocketAddr {
+    let (handle, _task) = spawn_scale_actor_with_config(SlowScale, actor);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let config = ServerConfig {
+        heartbeat_interval: Some(heartbeat_interval),
+        ..ServerConfig::default()
+    };
+    tokio::spawn(serve_listener(handle, listener, config));
+    addr
+}
+
+async fn response(lines: &mut Lines<BufReader<OwnedReadHalf>>) -> ScaleResponse {
+    let line = lines.next_line().await.unwrap().expect("connection closed");
+    serde_json::from_str(&line).unwrap()
+}
+
+#[tokio::test]
+async fn idle_connections_get_heartbeats() {
+    let actor = ActorConfig {
+        sample_interval: Some(Duration::from_millis(10)),
+        ..ActorConfig::default()
+    };
+    let addr = start(actor, Duration::from_millis(50)).await;
+    let (read, _write) = TcpStream::connect(addr).await.unwrap().into_split();
+    let mut lines = BufReader::new(read).lines();
+    for _ in 0..2 {
+        match response(&mut lines).await {
+            ScaleResponse::Heartbeat {
+                uptime,
+                last_reading_age,
+            } => {
+                assert!(uptime >= Duration::from_millis(50));
+                assert!(last_reading_age.unwrap() < Duration::from_millis(50));
+            }
+            other => panic!("expected a heartbeat, got {other:?}"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn heartbeats_fall_between_whole_answers() {
+    let addr = start(ActorConfig::default(), Duration::from_millis(40)).await;
+    let (read, mut write) = TcpStream::connect(addr).await.unwrap().into_split();
+    let mut lines = BufReader::new(read).lines();
+    write
+        .write_all(b"{\"GetMedianWeight\":{\"samples\":3}}\n\"GetWeight\"\n")
+        .await
+        .unwrap();
+    let mut answers = Vec::new();
+    let mut heartbeats = 0;
+    while answers.len() < 2 {
+        match response(&mut lines).await {
+            ScaleResponse::Heartbeat {
+                last_reading_age, ..
+            } => {
+                assert_eq!(last_reading_age, None);
+                heartbeats += 1;
+            }
+            answer => answers.push(answer),
+        }
+    }
+    assert!(heartbeats >= 2, "{heartbeats} heartbeats");
+    assert_eq!(
+        answers,
+        [
+            ScaleResponse::MedianWeight(MedianGrams(10.)),
+            ScaleResponse::Weight(Grams(10.)),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn the_client_skips_heartbeats() {
+    let addr = start(ActorConfig::default(), Duration::from_millis(20)).await;
+    let client = ScaleClient::tcp(
+        addr,
+        ClientConfig {
+            stale_after: Some(Duration::from_millis(100)),
+            ..ClientConfig::default()
+        },
+    );
+    let median = client
+        .request(&ScaleCmd::GetMedianWeight { samples: 3 })
+        .await
+        .unwrap();
+    assert_eq!(median, ScaleResponse::MedianWeight(MedianGrams(10.)));
+    let weight = client.request(&ScaleCmd::GetWeight).await.unwrap();
+    assert_eq!(weight, ScaleResponse::Weight(Grams(10.)));
+    assert!(client.last_heard().unwrap().elapsed() < Duration::from_millis(100));
+}
+
+/// A client whose connections are in-memory pipes. The server ends come out
+/// of the receiver, one per connection.
+fn mock_client(stale_after: Duration) -> (ScaleClient, mpsc::UnboundedReceiver<DuplexStream>) {
+    let (servers, accepted) = mpsc::unbounded_channel();
+    let connect = move || {
+        let (client, server) = tokio::io::duplex(1024);
+        let _ = servers.send(server);
+        async move { Ok::<_, io::Error>(client) }
+    };
+    let config = ClientConfig {
+        stale_after: Some(stale_after),
+        reconnect: RetryPolicy::fixed(1, Duration::from_millis(10)),
+    };
+    (ScaleClient::new(connect, config), accepted)
+}
+
+#[tokio::test]
+async fn a_silent_server_goes_stale_and_is_replaced() {
+    let (client, mut accepted) = mock_client(Duration::from_millis(100));
+    let stale = Arc::new(AtomicUsize::new(0));
+    let counter = stale.clone();
+    client.on_stale(move || {
+        counter.fetch_add(1, Ordering::Relaxed);
+    });
+
+    let mut first = accepted.recv().await.unwrap();
+    for _ in 0..5 {
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        first
+            .write_all(format!("{HEARTBEAT}\n").as_bytes())
+            .await
+            .unwrap();
+    }
+    assert_eq!(stale.load(Ordering::Relaxed), 0);
+    assert!(accepted.try_recv().is_err());
+
+    // Heartbeats stop, but the connection stays open.
+    let second = tokio::time::timeout(Duration::from_secs(1), accepted.recv())
+        .await
+        .expect("no reconnection")
+        .unwrap();
+    assert_eq!(stale.load(Ordering::Relaxed), 1);
+
+    let mut lines = BufReader::new(second);
+    let request = tokio::spawn(async move { client.request(&ScaleCmd::GetWeight).await });
+    let mut line = String::new();
+    lines.read_line(&mut line).await.unwrap();
+    assert_eq!(line, "\"GetWeight\"\n");
+    lines
+        .get_mut()
+        .write_all(format!("{HEARTBEAT}\n{{\"Weight\":5.0}}\n").as_bytes())
+        .await
+        .unwrap();
+    let response = request.await.unwrap().unwrap();
+    assert_eq!(response, ScaleResponse::Weight(Grams(5.)));
+    drop(first);
+}
+
+#[tokio::test]
+async fn commands_on_a_stale_connection_time_out() {
+    let (client, mut accepted) = mock_client(Duration::from_millis(50));
+    let _server = accepted.recv().await.unwrap();
+    let error = client.request(&ScaleCmd::GetWeight).await.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+}
+
+#[tokio::test]
+async fn heartbeats_are_off_by_default() {
+    let (handle, _task) = spawn_scale_actor(SlowScale);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve_listener(handle, listener, ServerConfig::default()));
+    let (read, _write) = TcpStream::connect(addr).await.unwrap().into_split();
+    let mut lines = BufReader::new(read).lines();
+    let line = tokio::time::timeout(Duration::from_millis(200), lines.next_line()).await;
+    assert!(line.is_err(), "{line:?}");
+}
+
+#[tokio::test]
+async fn commands_wait_out_the_reconnect_policy() {
+    let (servers, mut accepted) = mpsc::unbounded_channel();
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let counter = attempts.clone();
+    let connect = move || {
+        let attempt = counter.fetch_add(1, Ordering::Relaxed);
+        let (client, server) = tokio::io::duplex(1024);
+        if attempt >= 2 {
+            let _ = servers.send(server);
+        }
+        async move {
+            if attempt < 2 {
+                Err(io::Error::from(io::ErrorKind::ConnectionRefused))
+            } else {
+                Ok(client)
+            }
+        }
+    };
+    let config = ClientConfig {
+        stale_after: None,
+        reconnect: RetryPolicy::fixed(3, Duration::from_millis(20)),
+    };
+    let client = ScaleClient::new(connect, config);
+    let request = tokio::spawn(async move { client.request(&ScaleCmd::GetWeight).await });
+
+    let mut server = BufReader::new(accepted.recv().await.unwrap());
+    let mut line = String::new();
+    server.read_line(&mut line).await.unwrap();
+    assert_eq!(line, "\"GetWeight\"\n");
+    server
+        .get_mut()
+        .write_all(b"{\"Weight\":5.0}\n")
+        .await
+        .unwrap();
+    let response = request.await.unwrap().unwrap();
+    assert_eq!(response, ScaleResponse::Weight(Grams(5.)));
+    assert_eq!(attempts.load(Ordering::Relaxed), 3);
+}
@@ -0,0 +1,369 @@
+#![cfg(feature = "http")]
+
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+
+use libra::actor::{spawn_scale_actor, spawn_scale_actor_with_config, ActorConfig, ScaleHandle};
+use libra::calibration::Calibration;
+use libra::http::{serve_http_fleet, serve_http_listener, HttpConfig};
+use libra::manager::ScaleManager;
+use libra::scale::ScaleError;
+use libra::{Grams, MedianGrams, Scale, ScaleStatus, StampedWeight};
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+
+struct MockScale {
+    attached: bool,
+}
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(250.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(MedianGrams(250.))
+    }
+
+    fn tare(&mut self, _samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(10.))
+    }
+
+    fn get_raw_readings(&self) -> Result<[f64; 4], Box<dyn std::error::Error + Send + Sync>> {
+        if !self.attached {
+            return Err(Box::new(ScaleError::Disconnected { channel: None }));
+        }
+        Ok([0.1; 4])
+    }
+
+    fn status(&mut self) -> Result<ScaleStatus, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ScaleStatus {
+            phidget_id: 716_000,
+            attached: self.attached,
+            calibration: Calibration {
+                offset: 0.,
+                coefficients: [1.; 4],
+            },
+            tare: Grams(0.),
+            calibration_expires_in_days: None,
+        })
+    }
+}
+
+async fn start(handle: &ScaleHandle) -> (SocketAddr, JoinHandle<std::io::Result<()>>) {
+    start_with_config(handle, HttpConfig::default()).await
+}
+
+async fn start_with_config(
+    handle: &ScaleHandle,
+    config: HttpConfig,
+) -> (SocketAddr, JoinHandle<std::io::Result<()>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(serve_http_listener(handle.clone(), listener, config));
+    (addr, server)
+}
+
+/// Sends one request and returns the status code and JSON body.
+async fn request(addr: SocketAddr, method: &str, path: &str) -> (u16, Value) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!(
+        "{method} {path} HTTP/1.1\r\nHost: scale\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let status = response[9..12].parse().unwrap();
+    let (_, body) = response.split_once("\r\n\r\n").unwrap();
+    let body = if body.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_str(body).unwrap()
+    };
+    (status, body)
+}
+
+fn sampling_actor(attached: bool) -> ScaleHandle {
+    let (handle, _task) = spawn_scale_actor_with_config(
+        MockScale { attached },
+        ActorConfig {
+            sample_interval: Some(Duration::from_millis(10)),
+            ..Default::default()
+        },
+    );
+    handle
+}
+
+#[tokio::test]
+async fn serves_every_route() {
+    let handle = sampling_actor(true);
+    let (addr, _server) = start(&handle).await;
+
+    let (status, body) = loop {
+        let (status, body) = request(addr, "GET", "/weight").await;
+        if status != 503 {
+            break (status, body);
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    };
+    assert_eq!(status, 200);
+    assert_eq!(body["weight"], 250.);
+    assert!(body["sequence"].is_u64());
+
+    assert_eq!(
+        request(addr, "GET", "/weight/median?samples=5").await,
+        (200, json!({"weight": 250., "samples": 5}))
+    );
+    assert_eq!(
+        request(addr, "GET", "/weight/median").await,
+        (200, json!({"weight": 250., "samples": 10}))
+    );
+    assert_eq!(
+        request(addr, "POST", "/tare?samples=3").await,
+        (200, json!({"tare": 10.}))
+    );
+    assert_eq!(
+        request(addr, "GET", "/health").await,
+        (200, json!({"status": "ok", "attached": true}))
+    );
+}
+
+#[tokio::test]
+async fn bad_parameters_are_rejected() {
+    let handle = sampling_actor(true);
+    let (addr, _server) = start(&handle).await;
+
+    for path in [
+        "/weight/median?samples=ten",
+        "/weight/median?samples=0",
+        "/weight/median?samples=100000",
+    ] {
+        let (status, body) = request(addr, "GET", path).await;
+        assert_eq!(status, 400, "{path}");
+        assert_eq!(body["kind"], "InvalidCommand", "{path}");
+        assert!(body["message"].is_string());
+    }
+    assert_eq!(request(addr, "GET", "/tare").await.0, 405);
+    assert_eq!(request(addr, "GET", "/nowhere").await.0, 404);
+}
+
+#[tokio::test]
+async fn unavailable_scales_answer_503() {
+    let handle = sampling_actor(false);
+    let (addr, _server) = start(&handle).await;
+    assert_eq!(
+        request(addr, "GET", "/health").await,
+        (503, json!({"status": "disconnected", "attached": false}))
+    );
+
+    // Never samples, so there is no weight to report.
+    let (handle, _task) = spawn_scale_actor(MockScale { attached: true });
+    let (addr, _server) = start(&handle).await;
+    let (status, body) = request(addr, "GET", "/weight").await;
+    assert_eq!(status, 503);
+    assert_eq!(body["kind"], "Other");
+}
+
+/// A scale whose tare takes longer than a request may wait.
+struct SlowScale(MockScale);
+
+impl Scale for SlowScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.0.get_weight()
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.0.get_median_weight()
+    }
+
+    fn tare(&mut self, samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        std::thread::sleep(Duration::from_millis(300));
+        self.0.tare(samples)
+    }
+
+    fn get_raw_readings(&self) -> Result<[f64; 4], Box<dyn std::error::Error + Send + Sync>> {
+        self.0.get_raw_readings()
+    }
+
+    fn status(&mut self) -> Result<ScaleStatus, Box<dyn std::error::Error + Send + Sync>> {
+        self.0.status()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn slow_scales_answer_504() {
+    let (handle, _task) = spawn_scale_actor(SlowScale(MockScale { attached: true }));
+    let config = HttpConfig {
+        request_timeout: Duration::from_millis(50),
+        ..Default::default()
+    };
+    let (addr, _server) = start_with_config(&handle, config).await;
+    let (status, body) = request(addr, "POST", "/tare").await;
+    assert_eq!(status, 504);
+    assert_eq!(body["kind"], "Timeout");
+}
+
+#[tokio::test]
+async fn the_fleet_health_covers_every_scale() {
+    let manager = ScaleManager::default();
+    manager.add("flour", MockScale { attached: true }).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve_http_fleet(&manager, listener, HttpConfig::default()));
+
+    let (status, body) = request(addr, "GET", "/health").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "Ok");
+    assert_eq!(body["scales"][0]["name"], "flour");
+    assert_eq!(body["scales"][0]["health"]["Ok"]["reads"], 5);
+
+    manager.add("rice", MockScale { attached: false }).unwrap();
+    let (status, body) = request(addr, "GET", "/health").await;
+    assert_eq!(status, 503);
+    assert_eq!(body["status"], "Unusable");
+    let rice = &body["scales"][1]["health"]["Ok"];
+    assert_eq!(rice["status"], "Unusable");
+    assert_eq!(rice["channels"][0]["attached"], false);
+    assert_eq!(request(addr, "GET", "/weight").await.0, 404);
+}
+
+#[tokio::test]
+async fn server_stops_with_the_actor() {
+    let handle = sampling_actor(true);
+    let (addr, server) = start(&handle).await;
+    assert_eq!(request(addr, "GET", "/health").await.0, 200);
+
+    handle.shutdown().await;
+    tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert!(TcpStream::connect(addr).await.is_err());
+}
+
+type WebSocket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<TcpStream>>;
+
+async fn stream(addr: SocketAddr, query: &str) -> WebSocket {
+    let url = format!("ws://{addr}/weight/stream{query}");
+    tokio_tungstenite::connect_async(url).await.unwrap().0
+}
+
+/// The next weight on `socket`, skipping control messages.
+async fn next_weight(socket: &mut WebSocket) -> StampedWeight {
+    loop {
+        match socket.next().await.unwrap().unwrap() {
+            Message::Text(text) => return serde_json::from_str(&text).unwrap(),
+            Message::Ping(_) | Message::Pong(_) => {}
+            message => panic!("expected a weight, got {message:?}"),
+        }
+    }
+}
+
+/// How long `socket` takes to deliver `count` more weights, which must each
+/// be newer than the last.
+async fn time_weights(socket: &mut WebSocket, count: usize) -> Duration {
+    let mut last = next_weight(socket).await;
+    let started = Instant::now();
+    for _ in 0..count {
+        let weight = next_weight(socket).await;
+        assert!(weight.sequence > last.sequence);
+        assert_eq!(weight.weight, Grams(250.));
+        last = weight;
+    }
+    started.elapsed()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn weights_are_streamed_at_the_requested_rate() {
+    let (handle, _task) = spawn_scale_actor_with_config(
+        MockScale { attached: true },
+        ActorConfig {
+            sample_interval: Some(Duration::from_millis(2)),
+            ..Default::default()
+        },
+    );
+    let config = HttpConfig {
+        max_stream_rate: 40,
+        ..Default::default()
+    };
+    let (addr, _server) = start_with_config(&handle, config).await;
+
+    let mut socket = stream(addr, "?rate=10").await;
+    let elapsed = time_weights(&mut socket, 5).await;
+    assert!(elapsed >= Duration::from_millis(450), "{elapsed:?}");
+    assert!(elapsed < Duration::from_millis(1500), "{elapsed:?}");
+
+    // Capped at 40 a second.
+    let mut socket = stream(addr, "?rate=100000").await;
+    let elapsed = time_weights(&mut socket, 10).await;
+    assert!(elapsed >= Duration::from_millis(200), "{elapsed:?}");
+    assert!(elapsed < Duration::from_millis(1000), "{elapsed:?}");
+}
+
+#[tokio::test]
+async fn streams_close_when_the_actor_stops() {
+    let handle = sampling_actor(true);
+    let (addr, _server) = start(&handle).await;
+    let mut socket = stream(addr, "?rate=50").await;
+    next_weight(&mut socket).await;
+
+    handle.shutdown().await;
+    let close = loop {
+        match socket.next().await {
+            Some(Ok(Message::Close(frame))) => break frame.unwrap(),
+            Some(Ok(_)) => {}
+            other => panic!("expected a close frame, got {other:?}"),
+        }
+    };
+    assert_eq!(u16::from(close.code), 1001);
+}
+
+#[tokio::test]
+async fn zero_rates_are_rejected() {
+    let handle = sampling_actor(true);
+    let (addr, _server) = start(&handle).await;
+    let url = format!("ws://{addr}/weight/stream?rate=0");
+    match tokio_tungstenite::connect_async(url).await {
+        Err(tokio_tungstenite::tungstenite::Error::Http(response)) => {
+            assert_eq!(response.status(), 400)
+        }
+        other => panic!("expected a 400, got {other:?}"),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clients_that_stop_reading_are_disconnected() {
+    let handle = sampling_actor(true);
+    let config = HttpConfig {
+        stream_keepalive: Duration::from_millis(100),
+        ..Default::default()
+    };
+    let (addr, _server) = start_with_config(&handle, config).await;
+
+    // Reading answers pings, so a client that keeps up stays connected.
+    let mut reader = stream(addr, "?rate=50").await;
+    let mut idle = stream(addr, "?rate=50").await;
+    let started = Instant::now();
+    while started.elapsed() < Duration::from_millis(500) {
+        next_weight(&mut reader).await;
+    }
+
+    // Everything the idle client was sent before it was dropped is still
+    // there to read, then the stream ends.
+    let mut buffered = 0;
+    loop {
+        match idle.next().await {
+            Some(Ok(Message::Text(_))) => buffered += 1,
+            Some(Ok(_)) => {}
+            Some(Err(_)) | None => break,
+        }
+    }
+    assert!(buffered < 25, "{buffered} weights were buffered");
+    next_weight(&mut reader).await;
+}
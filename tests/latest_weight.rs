@@ -0,0 +1,123 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use libra::latest::LatestWeight;
+use libra::{Grams, StampedWeight};
+
+/// Update `n` of writer `writer`, every word of which can be told from
+/// `n` and `writer`, so that a mix of two updates shows.
+fn update(writer: u64, n: u64) -> StampedWeight {
+    StampedWeight {
+        weight: Grams(n as f64 + writer as f64 / 8.),
+        sequence: writer << 48 | n,
+        timestamp: SystemTime::UNIX_EPOCH + Duration::from_nanos(n * 1_000 + writer),
+    }
+}
+
+/// The writer and number of `stamped`, after checking that it is one whole
+/// update.
+fn whole(stamped: StampedWeight) -> (u64, u64) {
+    let (writer, n) = (stamped.sequence >> 48, stamped.sequence & 0xffff_ffff_ffff);
+    assert_eq!(stamped, update(writer, n), "torn read");
+    (writer, n)
+}
+
+#[test]
+fn a_cell_holds_the_last_weight_published() {
+    let latest = LatestWeight::new();
+    assert_eq!(latest.get(), None);
+    assert_eq!(latest.updates(), 0);
+
+    let stamped = StampedWeight {
+        weight: Grams(-12.5),
+        sequence: 7,
+        timestamp: SystemTime::now(),
+    };
+    latest.clone().publish(stamped);
+    assert_eq!(latest.get(), Some(stamped));
+    latest.publish(update(0, 8));
+    assert_eq!(latest.get(), Some(update(0, 8)));
+    assert_eq!(latest.updates(), 2);
+
+    // Nothing before the epoch to count from.
+    latest.publish(StampedWeight {
+        timestamp: SystemTime::UNIX_EPOCH - Duration::from_secs(1),
+        ..stamped
+    });
+    assert_eq!(latest.get().unwrap().timestamp, SystemTime::UNIX_EPOCH);
+}
+
+#[test]
+fn readers_never_see_a_torn_or_older_update() {
+    const UPDATES: u64 = 200_000;
+    let latest = LatestWeight::new();
+    let done = Arc::new(AtomicBool::new(false));
+    let readers: Vec<_> = (0..8)
+        .map(|_| {
+            let latest = latest.clone();
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                let (mut last, mut reads) = (0, 0u64);
+                while !done.load(Ordering::Relaxed) {
+                    if let Some(stamped) = latest.get() {
+                        let (_, n) = whole(stamped);
+                        assert!(n >= last, "{n} after {last}");
+                        last = n;
+                        reads += 1;
+                    }
+                }
+                reads
+            })
+        })
+        .collect();
+    for n in 1..=UPDATES {
+        latest.publish(update(0, n));
+    }
+    done.store(true, Ordering::Relaxed);
+    for reader in readers {
+        assert!(reader.join().unwrap() > 0);
+    }
+    assert_eq!(latest.get(), Some(update(0, UPDATES)));
+    assert_eq!(latest.updates(), UPDATES);
+}
+
+#[test]
+fn writers_take_turns() {
+    const UPDATES: u64 = 50_000;
+    let latest = LatestWeight::new();
+    let done = Arc::new(AtomicBool::new(false));
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let latest = latest.clone();
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    if let Some(stamped) = latest.get() {
+                        whole(stamped);
+                    }
+                }
+            })
+        })
+        .collect();
+    let writers: Vec<_> = (1..=4)
+        .map(|writer| {
+            let latest = latest.clone();
+            thread::spawn(move || {
+                for n in 1..=UPDATES {
+                    latest.publish(update(writer, n));
+                }
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    done.store(true, Ordering::Relaxed);
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    assert_eq!(whole(latest.get().unwrap()).1, UPDATES);
+    assert_eq!(latest.updates(), 4 * UPDATES);
+}
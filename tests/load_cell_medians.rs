@@ -0,0 +1,230 @@
+#![cfg(feature = "hardware")]
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use libra::calibration::Calibration;
+use libra::clock::Clock;
+use libra::scale::{ConnectedScale, NUMBER_OF_INPUTS};
+use libra::source::VoltageSource;
+use libra::testing::{FakeVoltageSource, ManualClock};
+
+const SERIAL: i32 = 716_000;
+const DATA_INTERVAL: Duration = Duration::from_millis(8);
+/// Each load cell's ratio, less the noise they share.
+const BASES: [f64; NUMBER_OF_INPUTS] = [0.1, 0.2, 0.3, 0.4];
+
+type Callback = Arc<dyn Fn(f64) + Send + Sync>;
+
+/// A bridge that takes a new reading of every load cell each data interval,
+/// as time goes by on its clock, and reports it to the change events. The
+/// load cells share their noise, as they do from vibration, so samples of
+/// different readings show up in their medians.
+#[derive(Clone)]
+struct Bridge {
+    state: Arc<Mutex<BridgeState>>,
+    clock: ManualClock,
+}
+
+struct BridgeState {
+    started: Instant,
+    callbacks: [Option<Callback>; NUMBER_OF_INPUTS],
+    /// How long each direct read of a load cell takes.
+    latency: Duration,
+    reads: usize,
+}
+
+/// The noise of the `tick`th reading, the same on every run.
+fn noise(tick: u64) -> f64 {
+    let mut z = tick.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (z >> 11) as f64 / (1u64 << 53) as f64 * 0.01
+}
+
+/// The median of the noise of readings `ticks`, as a scale takes it.
+fn median_noise(ticks: impl Iterator<Item = u64>) -> f64 {
+    let mut noise: Vec<f64> = ticks.map(noise).collect();
+    noise.sort_by(f64::total_cmp);
+    noise[noise.len() / 2]
+}
+
+impl Bridge {
+    fn new() -> Self {
+        let clock = ManualClock::new();
+        let state = BridgeState {
+            started: clock.now(),
+            callbacks: Default::default(),
+            latency: Duration::ZERO,
+            reads: 0,
+        };
+        Self {
+            state: Arc::new(Mutex::new(state)),
+            clock,
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, BridgeState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn tick(&self) -> u64 {
+        let elapsed = self.clock.now() - self.state().started;
+        (elapsed.as_nanos() / DATA_INTERVAL.as_nanos()) as u64
+    }
+
+    /// Reports the current reading to every change event.
+    fn publish(&self) {
+        let noise = noise(self.tick());
+        let callbacks = self.state().callbacks.clone();
+        for (callback, base) in callbacks.iter().zip(BASES) {
+            if let Some(callback) = callback {
+                callback(base + noise);
+            }
+        }
+    }
+
+    fn scale(&self) -> ConnectedScale<Channel> {
+        let channels = std::array::from_fn(|channel| Channel {
+            bridge: self.clone(),
+            channel,
+        });
+        let calibration = Calibration {
+            offset: 0.,
+            coefficients: [1.; NUMBER_OF_INPUTS],
+        };
+        let mut scale = ConnectedScale::from_sources(SERIAL, calibration, channels);
+        scale.set_clock(Arc::new(self.clone()));
+        scale.set_data_intervals(DATA_INTERVAL).unwrap();
+        scale
+    }
+}
+
+/// Waits on the bridge's clock, reporting each reading taken meanwhile.
+impl Clock for Bridge {
+    fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        let before = self.tick();
+        self.clock.advance(duration);
+        if self.tick() != before {
+            self.publish();
+        }
+    }
+}
+
+struct Channel {
+    bridge: Bridge,
+    channel: usize,
+}
+
+impl VoltageSource for Channel {
+    fn ratio(&self) -> phidget::Result<f64> {
+        let latency = {
+            let mut state = self.bridge.state();
+            state.reads += 1;
+            state.latency
+        };
+        self.bridge.clock.advance(latency);
+        Ok(BASES[self.channel] + noise(self.bridge.tick()))
+    }
+
+    fn set_data_interval(&mut self, interval: Duration) -> phidget::Result<()> {
+        assert_eq!(interval, DATA_INTERVAL);
+        Ok(())
+    }
+
+    fn data_interval(&mut self) -> phidget::Result<Duration> {
+        Ok(DATA_INTERVAL)
+    }
+
+    fn serial_number(&mut self) -> phidget::Result<i32> {
+        Ok(SERIAL)
+    }
+
+    fn is_attached(&mut self) -> phidget::Result<bool> {
+        Ok(true)
+    }
+
+    fn close(&mut self) -> phidget::Result<()> {
+        Ok(())
+    }
+
+    fn on_ratio_change(&mut self, callback: Box<dyn Fn(f64) + Send + Sync>) -> phidget::Result<()> {
+        self.bridge.state().callbacks[self.channel] = Some(Arc::from(callback));
+        Ok(())
+    }
+}
+
+#[test]
+fn medians_from_change_events_are_of_aligned_readings() {
+    let bridge = Bridge::new();
+    // Slow enough for a direct read of every load cell to span readings.
+    bridge.state().latency = Duration::from_millis(3);
+    let mut scale = bridge.scale();
+    scale.cache_change_events(DATA_INTERVAL * 2).unwrap();
+    bridge.publish();
+
+    let medians = scale.get_load_cell_medians(21, Duration::ZERO).unwrap();
+    let expected = median_noise(0..21);
+    for (median, base) in medians.into_iter().zip(BASES) {
+        assert_eq!(median, base + expected);
+    }
+    assert_eq!(bridge.state().reads, 0);
+    assert_eq!(scale.read_counters().cached(), 21);
+    assert_eq!(bridge.clock.elapsed(), DATA_INTERVAL * 21);
+
+    // Read directly, the later load cells of a sample are of later readings.
+    let bridge = Bridge::new();
+    bridge.state().latency = Duration::from_millis(3);
+    let medians = bridge
+        .scale()
+        .get_load_cell_medians(21, Duration::ZERO)
+        .unwrap();
+    let noise: Vec<f64> = medians.iter().zip(BASES).map(|(m, b)| m - b).collect();
+    assert!(noise.iter().any(|n| *n != noise[0]), "{noise:?}");
+}
+
+#[test]
+fn direct_reads_are_paced_by_the_data_interval() {
+    let bridge = Bridge::new();
+    let scale = bridge.scale();
+    let medians = scale.get_load_cell_medians(15, Duration::ZERO).unwrap();
+    // One new reading per sample, rather than the first one 15 times.
+    let expected = median_noise(0..15);
+    assert_ne!(expected, noise(0));
+    for (median, base) in medians.into_iter().zip(BASES) {
+        assert_eq!(median, base + expected);
+    }
+    assert_eq!(bridge.state().reads, 15 * NUMBER_OF_INPUTS);
+    assert_eq!(scale.read_counters().direct(), 15);
+    assert_eq!(bridge.clock.elapsed(), DATA_INTERVAL * 15);
+
+    // A period longer than the data interval is kept.
+    let started = bridge.clock.elapsed();
+    scale
+        .get_load_cell_medians(4, Duration::from_millis(20))
+        .unwrap();
+    assert_eq!(bridge.clock.elapsed() - started, Duration::from_millis(80));
+}
+
+#[test]
+fn medians_are_not_paced_by_an_unknown_data_interval() {
+    let clock = ManualClock::new();
+    let sources = FakeVoltageSource::bridge(SERIAL);
+    let calibration = Calibration {
+        offset: 0.,
+        coefficients: [1.; NUMBER_OF_INPUTS],
+    };
+    let mut scale = ConnectedScale::from_sources(SERIAL, calibration, sources);
+    scale.set_clock(Arc::new(clock.clone()));
+    scale.get_load_cell_medians(5, Duration::ZERO).unwrap();
+    assert_eq!(clock.elapsed(), Duration::ZERO);
+
+    // The fakes start at 8 ms.
+    assert_eq!(scale.data_interval().unwrap(), DATA_INTERVAL);
+    scale.get_load_cell_medians(5, Duration::ZERO).unwrap();
+    assert_eq!(clock.elapsed(), DATA_INTERVAL * 5);
+}
@@ -0,0 +1,249 @@
+#![cfg(feature = "logger")]
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use libra::logger::{LogRecord, Rotation, WeightLogger};
+use libra::scale::ScaleError;
+use libra::{Grams, StampedWeight};
+use serde_json::Value;
+
+/// 2023-11-14T22:13:20Z.
+const START: u64 = 1_700_000_000;
+
+/// A fresh, empty directory for one test.
+fn log_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("libra-logger-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Sample `sequence`, taken `step` apart from [`START`].
+fn sample(sequence: u64, step: Duration) -> StampedWeight {
+    StampedWeight {
+        weight: Grams(sequence as f64 / 4.),
+        sequence,
+        timestamp: UNIX_EPOCH + Duration::from_secs(START) + step * sequence as u32,
+    }
+}
+
+/// The files in `dir`, oldest first: by name, then by rotation suffix.
+fn files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    files.sort_by_key(|path| {
+        let name = path.file_name().unwrap().to_str().unwrap().to_owned();
+        match name.rsplit_once('.') {
+            Some((stem, index)) if index.parse::<u32>().is_ok() => {
+                (stem.to_owned(), index.parse().unwrap())
+            }
+            _ => (name, 0),
+        }
+    });
+    files
+}
+
+fn read(path: &Path) -> String {
+    std::fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn csv_rotates_by_size_without_losing_samples() {
+    let dir = log_dir("csv-size");
+    let mut logger = WeightLogger::csv(dir.join("weights.csv")).with_rotation(Rotation::Size(2048));
+    for sequence in 0..300_u64 {
+        let ratios = sequence
+            .is_multiple_of(3)
+            .then_some([0.5, -0.25, 0.125, 1e-9]);
+        let record = LogRecord {
+            sample: sample(sequence, Duration::from_millis(10)),
+            ratios,
+        };
+        logger.log(&record).unwrap();
+    }
+    logger.finish().unwrap();
+
+    let files = files(&dir);
+    assert!(files.len() > 3, "{files:?}");
+    assert_eq!(files[0], dir.join("weights.csv"));
+    assert_eq!(files[1], dir.join("weights.csv.1"));
+    let mut sequences = Vec::new();
+    for path in &files {
+        let text = read(path);
+        assert!(text.len() <= 2048, "{path:?}");
+        let mut lines = text.lines();
+        assert_eq!(
+            lines.next(),
+            Some("timestamp,sequence,grams,ratio_0,ratio_1,ratio_2,ratio_3")
+        );
+        for line in lines {
+            let fields: Vec<_> = line.split(',').collect();
+            assert_eq!(fields.len(), 7, "{line}");
+            let sequence: u64 = fields[1].parse().unwrap();
+            let expected = sample(sequence, Duration::from_millis(10));
+            let since = expected.timestamp.duration_since(UNIX_EPOCH).unwrap();
+            assert_eq!(
+                fields[0],
+                format!("{}.{:09}", since.as_secs(), since.subsec_nanos())
+            );
+            assert_eq!(fields[2].parse::<f64>().unwrap(), expected.weight.get());
+            if sequence.is_multiple_of(3) {
+                assert_eq!(fields[3..], ["0.5", "-0.25", "0.125", "0.000000001"]);
+            } else {
+                assert_eq!(fields[3..], ["", "", "", ""]);
+            }
+            sequences.push(sequence);
+        }
+    }
+    assert_eq!(sequences, (0..300).collect::<Vec<_>>());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn jsonl_rotates_by_day() {
+    let dir = log_dir("jsonl-daily");
+    let mut logger =
+        WeightLogger::jsonl(dir.join("weights-{date}.jsonl")).with_rotation(Rotation::Daily);
+    // Every 800 s from late on the 14th into the 17th.
+    for sequence in 0..300 {
+        let sample = sample(sequence, Duration::from_secs(800));
+        logger.log(&sample.into()).unwrap();
+    }
+    assert_eq!(
+        logger.current_path(),
+        Some(dir.join("weights-2023-11-17.jsonl").as_path())
+    );
+    logger.finish().unwrap();
+
+    let names: Vec<_> = files(&dir)
+        .iter()
+        .map(|path| path.file_name().unwrap().to_str().unwrap().to_owned())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "weights-2023-11-14.jsonl",
+            "weights-2023-11-15.jsonl",
+            "weights-2023-11-16.jsonl",
+            "weights-2023-11-17.jsonl",
+        ]
+    );
+    let mut next = 0;
+    for (day, path) in files(&dir).iter().enumerate() {
+        for line in read(path).lines() {
+            let record: Value = serde_json::from_str(line).unwrap();
+            assert_eq!(record["sequence"], next);
+            assert_eq!(record["grams"], next as f64 / 4.);
+            assert!(record.get("ratios").is_none());
+            let timestamp = record["timestamp"].as_f64().unwrap() as u64;
+            assert_eq!(timestamp, START + 800 * next);
+            assert_eq!((timestamp - START + 80_000) / 86_400, day as u64);
+            next += 1;
+        }
+    }
+    assert_eq!(next, 300);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn indexed_patterns_and_restarts() {
+    let dir = log_dir("indexed");
+    let pattern = dir.join("weights-{index}.csv");
+    let mut logger = WeightLogger::csv(&pattern).with_rotation(Rotation::Size(256));
+    for sequence in 0..10 {
+        logger
+            .log(&sample(sequence, Duration::ZERO).into())
+            .unwrap();
+    }
+    logger.finish().unwrap();
+    let first = files(&dir);
+    assert_eq!(first[0], dir.join("weights-0.csv"));
+    assert_eq!(first[1], dir.join("weights-1.csv"));
+
+    // A new logger appends to the first file, header and all, then rotates
+    // past the files already there.
+    let mut logger = WeightLogger::csv(&pattern).with_rotation(Rotation::Size(256));
+    logger.log(&sample(10, Duration::ZERO).into()).unwrap();
+    assert_eq!(
+        logger.current_path(),
+        Some(dir.join("weights-0.csv").as_path())
+    );
+    logger.log(&sample(11, Duration::ZERO).into()).unwrap();
+    assert_eq!(
+        logger.current_path(),
+        Some(dir.join(format!("weights-{}.csv", first.len())).as_path())
+    );
+    logger.finish().unwrap();
+    let headers = read(&dir.join("weights-0.csv"))
+        .lines()
+        .filter(|line| line.starts_with("timestamp"))
+        .count();
+    assert_eq!(headers, 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn attached_loggers_flush_while_idle_and_on_shutdown() {
+    let dir = log_dir("attached");
+    let path = dir.join("weights.jsonl");
+    let (samples, receiver) = mpsc::channel::<Result<StampedWeight, ScaleError>>();
+    let handle = WeightLogger::jsonl(&path)
+        .with_flush_interval(Duration::from_millis(10))
+        .attach(receiver);
+
+    for sequence in 0..5 {
+        samples.send(Ok(sample(sequence, Duration::ZERO))).unwrap();
+    }
+    // Read errors are not logged.
+    samples
+        .send(Err(ScaleError::IoError(io::ErrorKind::TimedOut.into())))
+        .unwrap();
+    let start = Instant::now();
+    while std::fs::read_to_string(&path).map_or(0, |text| text.lines().count()) < 5 {
+        assert!(start.elapsed() < Duration::from_secs(1), "never flushed");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+
+    for sequence in 5..400 {
+        samples.send(Ok(sample(sequence, Duration::ZERO))).unwrap();
+    }
+    assert!(!handle.is_finished());
+    drop(samples);
+    handle.join().unwrap();
+    let sequences: Vec<u64> = read(&path)
+        .lines()
+        .map(|line| {
+            serde_json::from_str::<Value>(line).unwrap()["sequence"]
+                .as_u64()
+                .unwrap()
+        })
+        .collect();
+    assert_eq!(sequences, (0..400).collect::<Vec<_>>());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn write_errors_end_the_thread() {
+    let dir = log_dir("unwritable");
+    let (samples, receiver) = mpsc::channel();
+    let handle = WeightLogger::csv(dir.join("missing/weights.csv")).attach(receiver);
+    samples
+        .send(Ok::<_, ScaleError>(StampedWeight {
+            weight: Grams(1.),
+            sequence: 0,
+            timestamp: SystemTime::now(),
+        }))
+        .unwrap();
+    let error = handle.join().unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    // The receiver went with the thread, so a sampler would stop.
+    assert!(samples
+        .send(Err(ScaleError::IoError(io::ErrorKind::TimedOut.into())))
+        .is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
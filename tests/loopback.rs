@@ -0,0 +1,147 @@
+#![cfg(feature = "net")]
+
+use std::io;
+use std::time::Duration;
+
+use libra::actor::spawn_scale_actor;
+use libra::cancel::CancelFlag;
+use libra::client::ClientConfig;
+use libra::testing::{loopback, Loopback, LoopbackConfig};
+use libra::{Grams, MedianGrams, Reply, Request, Scale, ScaleCmd, ScaleResponse};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+/// Reads 10 g; a median of `n` samples takes `n` times 10 ms and reads
+/// `n` g.
+struct MockScale;
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(10.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+
+    fn get_median_weight_cancellable(
+        &self,
+        samples: usize,
+        _cancel: &CancelFlag,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        std::thread::sleep(Duration::from_millis(10) * samples as u32);
+        Ok(MedianGrams(samples as f64))
+    }
+}
+
+fn start(config: LoopbackConfig) -> Loopback {
+    let (handle, _task) = spawn_scale_actor(MockScale);
+    loopback(handle, config)
+}
+
+fn batch(count: usize) -> ScaleCmd {
+    ScaleCmd::GetWeightBatch {
+        count,
+        interval_ms: 0,
+    }
+}
+
+fn batch_len(response: ScaleResponse) -> usize {
+    match response {
+        ScaleResponse::WeightBatch(batch) => batch.len(),
+        other => panic!("expected a batch, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn pipelined_requests_are_answered_in_order() {
+    let loopback = start(LoopbackConfig {
+        latency: Duration::from_millis(1),
+        fragment: Some(64),
+        ..LoopbackConfig::default()
+    });
+    let client = loopback.client(ClientConfig::default());
+    let commands: Vec<_> = (1..=8).map(batch).collect();
+    let responses = futures::future::join_all(commands.iter().map(|cmd| client.request(cmd))).await;
+    for (count, response) in (1..=8).zip(responses) {
+        assert_eq!(batch_len(response.unwrap()), count);
+    }
+}
+
+#[tokio::test]
+async fn fragmented_requests_are_answered_under_their_ids() {
+    let loopback = start(LoopbackConfig {
+        fragment: Some(1),
+        ..LoopbackConfig::default()
+    });
+    let (read, mut write) = tokio::io::split(loopback.connect());
+    let mut lines = BufReader::new(read).lines();
+    let mut sent = Vec::new();
+    for (id, samples) in [(1, 3), (2, 1), (3, 2)] {
+        let request = Request {
+            id,
+            scale: None,
+            cmd: ScaleCmd::GetMedianWeight { samples },
+        };
+        sent.extend(serde_json::to_vec(&request).unwrap());
+        sent.push(b'\n');
+    }
+    write.write_all(&sent).await.unwrap();
+    for (id, samples) in [(1, 3.), (2, 1.), (3, 2.)] {
+        let line = lines.next_line().await.unwrap().expect("connection closed");
+        let reply: Reply = serde_json::from_str(&line).unwrap();
+        assert_eq!(reply.id, id);
+        assert_eq!(
+            reply.response,
+            ScaleResponse::MedianWeight(MedianGrams(samples))
+        );
+    }
+}
+
+#[tokio::test]
+async fn a_disconnect_fails_what_is_outstanding() {
+    let loopback = start(LoopbackConfig::default());
+    let client = loopback.client(ClientConfig::default());
+    let slow = client.request(&ScaleCmd::GetMedianWeight { samples: 20 });
+    let cut = async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        loopback.disconnect();
+    };
+    let (response, ()) = tokio::join!(slow, cut);
+    assert_eq!(
+        response.unwrap_err().kind(),
+        io::ErrorKind::ConnectionAborted
+    );
+    let response = client.request(&ScaleCmd::GetWeight).await.unwrap();
+    assert_eq!(response, ScaleResponse::Weight(Grams(10.)));
+}
+
+#[tokio::test]
+async fn a_disconnect_closes_the_stream() {
+    let loopback = start(LoopbackConfig::default());
+    let (read, mut write) = tokio::io::split(loopback.connect());
+    let mut lines = BufReader::new(read).lines();
+    write
+        .write_all(b"{\"GetMedianWeight\":{\"samples\":20}}\n")
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    loopback.disconnect();
+    assert!(lines.next_line().await.unwrap().is_none());
+    assert!(write.write_all(b"\"GetWeight\"\n").await.is_err());
+}
+
+#[tokio::test]
+async fn responses_larger_than_a_read_buffer_arrive_whole() {
+    let loopback = start(LoopbackConfig {
+        fragment: Some(1000),
+        capacity: 4096,
+        ..LoopbackConfig::default()
+    });
+    let client = loopback.client(ClientConfig::default());
+    let response = client
+        .request(&batch(libra::MAX_BATCH_COUNT))
+        .await
+        .unwrap();
+    assert!(serde_json::to_vec(&response).unwrap().len() > 64 * 1024);
+    assert_eq!(batch_len(response), libra::MAX_BATCH_COUNT);
+}
@@ -0,0 +1,958 @@
+#![cfg(feature = "tokio")]
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use libra::actor::ActorConfig;
+use libra::calibration::{Calibration, TransferCheck};
+use libra::health::{HealthConfig, Status};
+use libra::manager::{
+    FleetEvent, FleetStatusConfig, ManagerError, NamedEvent, ScaleEvent, ScaleManager,
+};
+use libra::scale::{ScaleError, NUMBER_OF_INPUTS};
+use libra::{
+    Grams, MedianGrams, Scale, ScaleCmd, ScaleErrorKind, ScaleResponse, ScaleState, ScaleStatus,
+};
+use tokio::sync::broadcast::error::RecvError;
+
+const READ_TIME: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy)]
+enum Behaviour {
+    Weighs(f64),
+    Fails,
+    /// Weighs this, but panics when tared.
+    PanicsOnTare(f64),
+}
+
+/// Takes `READ_TIME` per read, and counts how many times it is closed.
+struct Platform {
+    behaviour: Behaviour,
+    closes: Arc<AtomicUsize>,
+}
+
+impl Scale for Platform {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        std::thread::sleep(READ_TIME);
+        match self.behaviour {
+            Behaviour::Weighs(weight) => Ok(Grams(weight)),
+            Behaviour::Fails => Err(Box::new(ScaleError::IoError(
+                std::io::ErrorKind::TimedOut.into(),
+            ))),
+            Behaviour::PanicsOnTare(weight) => Ok(Grams(weight)),
+        }
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_median_weight_of(3)
+    }
+
+    fn tare(&mut self, _samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        if let Behaviour::PanicsOnTare(_) = self.behaviour {
+            panic!("load cell fell off");
+        }
+        self.get_weight()
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.closes.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+fn platform(behaviour: Behaviour) -> (Platform, Arc<AtomicUsize>) {
+    let closes = Arc::new(AtomicUsize::new(0));
+    let platform = Platform {
+        behaviour,
+        closes: Arc::clone(&closes),
+    };
+    (platform, closes)
+}
+
+/// A manager of "flour", "rice", which fails, and "salt", which panics
+/// when tared, with their close counts.
+fn pantry(
+    config: ActorConfig,
+) -> (
+    ScaleManager<Platform>,
+    BTreeMap<&'static str, Arc<AtomicUsize>>,
+) {
+    let manager = ScaleManager::new(config);
+    let mut closes = BTreeMap::new();
+    for (name, behaviour) in [
+        ("flour", Behaviour::Weighs(1000.)),
+        ("rice", Behaviour::Fails),
+        ("salt", Behaviour::PanicsOnTare(50.)),
+    ] {
+        let (platform, count) = platform(behaviour);
+        manager.add(name, platform).unwrap();
+        closes.insert(name, count);
+    }
+    (manager, closes)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reads_run_in_parallel_and_failures_stay_with_their_scale() {
+    let (manager, _) = pantry(ActorConfig::default());
+    for _ in 0..2 {
+        let start = Instant::now();
+        let weights = manager.read_all().await;
+        let elapsed = start.elapsed();
+        assert!(elapsed < READ_TIME * 2, "took {elapsed:?}");
+
+        assert_eq!(weights.len(), 3);
+        assert_eq!(weights["flour"], Ok(Grams(1000.)));
+        assert_eq!(
+            weights["rice"].as_ref().unwrap_err().kind,
+            ScaleErrorKind::Io
+        );
+        assert_eq!(weights["salt"], Ok(Grams(50.)));
+    }
+
+    let tares = manager.tare_all(3).await;
+    assert_eq!(tares["flour"], Ok(Grams(1000.)));
+    assert!(tares["rice"].is_err());
+    let panicked = tares["salt"].as_ref().unwrap_err();
+    assert_eq!(panicked.kind, ScaleErrorKind::Other);
+    assert!(
+        panicked.message.contains("load cell fell off"),
+        "{panicked:?}"
+    );
+    // A panic leaves the scale's actor running.
+    assert_eq!(manager.read_all().await["salt"], Ok(Grams(50.)));
+    manager.shutdown().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn scales_are_added_and_removed_by_name() {
+    let manager = ScaleManager::default();
+    let (flour, flour_closes) = platform(Behaviour::Weighs(1000.));
+    let (sugar, _) = platform(Behaviour::Weighs(250.));
+    manager.add("flour", flour).unwrap();
+    assert_eq!(
+        manager.add("flour", sugar).err(),
+        Some(ManagerError::NameTaken {
+            name: "flour".into()
+        })
+    );
+    let handle = manager.get("flour").unwrap();
+    assert_eq!(
+        handle.send(ScaleCmd::GetWeight).await,
+        ScaleResponse::Weight(Grams(1000.))
+    );
+
+    let removed = manager.remove("flour").await.unwrap().unwrap();
+    assert_eq!(flour_closes.load(Ordering::SeqCst), 1);
+    assert_eq!(removed.closes.load(Ordering::SeqCst), 1);
+    assert!(matches!(
+        handle.send(ScaleCmd::GetWeight).await,
+        ScaleResponse::Error(info) if info.kind == ScaleErrorKind::Stopped
+    ));
+    let unknown = ManagerError::UnknownScale {
+        name: "flour".into(),
+    };
+    assert_eq!(manager.get("flour").err(), Some(unknown.clone()));
+    assert_eq!(manager.remove("flour").await.err(), Some(unknown));
+    assert!(manager.names().is_empty());
+    assert!(manager.read_all().await.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn events_are_tagged_with_the_scale_they_came_from() {
+    let manager = ScaleManager::new(ActorConfig {
+        sample_interval: Some(Duration::from_millis(10)),
+        ..Default::default()
+    });
+    let mut events = manager.events();
+    for (name, weight) in [("flour", 1000.), ("sugar", 250.)] {
+        manager
+            .add(name, platform(Behaviour::Weighs(weight)).0)
+            .unwrap();
+    }
+
+    let mut seen = BTreeMap::new();
+    while seen.len() < 2 {
+        let FleetEvent::Scale(NamedEvent { name, event }) = events.recv().await.unwrap() else {
+            continue;
+        };
+        if let ScaleEvent::Weight(stamped) = event {
+            seen.insert(name, stamped.weight);
+        }
+    }
+    assert_eq!(
+        seen,
+        BTreeMap::from([
+            ("flour".into(), Grams(1000.)),
+            ("sugar".into(), Grams(250.))
+        ])
+    );
+    manager.shutdown().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn shutdown_finishes_in_flight_commands_then_closes_every_scale() {
+    let (manager, closes) = pantry(ActorConfig::default());
+    let mut events = manager.events();
+    let in_flight = tokio::spawn({
+        let handle = manager.get("flour").unwrap();
+        async move { handle.send(ScaleCmd::GetWeight).await }
+    });
+    tokio::time::sleep(READ_TIME / 4).await;
+
+    let scales = manager.shutdown().await;
+    assert_eq!(
+        in_flight.await.unwrap(),
+        ScaleResponse::Weight(Grams(1000.))
+    );
+    assert_eq!(scales.len(), 3);
+    assert!(scales.values().all(Option::is_some));
+    for count in closes.values() {
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    // Each scale reports stopping once, after it was closed, and the
+    // receiver is closed after the last.
+    let mut stopped = Vec::new();
+    loop {
+        match events.recv().await {
+            Ok(FleetEvent::Scale(NamedEvent {
+                name,
+                event: ScaleEvent::Stopped,
+            })) => stopped.push(name),
+            Ok(_) => {}
+            Err(RecvError::Closed) => break,
+            Err(error) => panic!("{error:?}"),
+        }
+    }
+    stopped.sort();
+    assert_eq!(stopped, ["flour", "rice", "salt"]);
+}
+
+/// Takes a reading every `interval`, and takes `status_time` to report
+/// its status.
+struct Ticker {
+    interval: Duration,
+    status_time: Duration,
+}
+
+impl Ticker {
+    fn new(interval_ms: u64) -> Self {
+        Self {
+            interval: Duration::from_millis(interval_ms),
+            status_time: Duration::ZERO,
+        }
+    }
+
+    fn slow(self) -> Self {
+        Self {
+            status_time: Duration::from_secs(1),
+            ..self
+        }
+    }
+}
+
+impl Scale for Ticker {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(0.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(MedianGrams(0.))
+    }
+
+    fn tare(&mut self, _samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(0.))
+    }
+
+    fn status(&mut self) -> Result<ScaleStatus, Box<dyn std::error::Error + Send + Sync>> {
+        std::thread::sleep(self.status_time);
+        Ok(ScaleStatus {
+            phidget_id: 716_000,
+            attached: true,
+            calibration: Calibration {
+                offset: 0.,
+                coefficients: [1.; NUMBER_OF_INPUTS],
+            },
+            tare: Grams(0.),
+            calibration_expires_in_days: None,
+        })
+    }
+
+    fn data_interval(&mut self) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.interval)
+    }
+
+    fn set_data_interval(
+        &mut self,
+        interval: Duration,
+    ) -> Result<Duration, Box<dyn std::error::Error + Send + Sync>> {
+        self.interval = interval;
+        Ok(interval)
+    }
+}
+
+fn intervals<S: Scale + Send + 'static>(manager: &ScaleManager<S>) -> Vec<Option<u64>> {
+    manager
+        .list()
+        .into_iter()
+        .map(|scale| scale.data_interval_ms)
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn each_scale_keeps_its_own_data_interval() {
+    let manager = ScaleManager::default();
+    manager.add("flour", Ticker::new(8)).unwrap();
+    manager.add("rice", Ticker::new(100)).unwrap();
+    // Each actor reads its scale's interval before its first command.
+    assert!(manager
+        .status_all(Duration::from_secs(1))
+        .await
+        .values()
+        .all(Result::is_ok));
+    assert_eq!(intervals(&manager), [Some(8), Some(100)]);
+
+    let interval = manager
+        .set_data_interval("flour", Duration::from_millis(20))
+        .await;
+    assert_eq!(interval, Ok(Duration::from_millis(20)));
+    assert_eq!(intervals(&manager), [Some(20), Some(100)]);
+    assert_eq!(
+        manager
+            .set_data_interval("sugar", Duration::from_millis(20))
+            .await,
+        Err(ManagerError::UnknownScale {
+            name: "sugar".into()
+        })
+    );
+    manager.shutdown().await;
+
+    // A scale without intervals lists none, and fails to be given one.
+    let manager = ScaleManager::default();
+    manager
+        .add("salt", platform(Behaviour::Weighs(50.)).0)
+        .unwrap();
+    assert!(manager.read_all().await["salt"].is_ok());
+    assert_eq!(intervals(&manager), [None]);
+    let error = manager
+        .set_data_interval("salt", Duration::from_millis(20))
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&error, ManagerError::ScaleFailed { name, error }
+            if name == "salt" && error.kind == ScaleErrorKind::Unsupported),
+        "{error:?}"
+    );
+    manager.shutdown().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_slow_scale_does_not_hold_up_the_statuses_of_the_others() {
+    let manager = ScaleManager::default();
+    manager.add("flour", Ticker::new(8)).unwrap();
+    manager.add("rice", Ticker::new(100).slow()).unwrap();
+
+    let start = Instant::now();
+    let statuses = manager.status_all(Duration::from_millis(200)).await;
+    let elapsed = start.elapsed();
+    assert!(elapsed < Duration::from_millis(500), "took {elapsed:?}");
+    assert_eq!(statuses["flour"].as_ref().unwrap().phidget_id, 716_000);
+    assert_eq!(
+        statuses["rice"].as_ref().unwrap_err().kind,
+        ScaleErrorKind::Timeout
+    );
+}
+
+#[cfg(feature = "hardware")]
+#[tokio::test(flavor = "multi_thread")]
+async fn fleets_are_managed_by_their_names() {
+    use libra::config::FleetConnection;
+    use libra::scale::ConnectedScale;
+    use libra::testing::FakeVoltageSource;
+
+    let calibration = Calibration {
+        offset: 0.,
+        coefficients: [1000.; 4],
+    };
+    let connection = FleetConnection {
+        scales: BTreeMap::from([(
+            "flour".to_string(),
+            ConnectedScale::from_sources(716_000, calibration, FakeVoltageSource::bridge(716_000)),
+        )]),
+        failures: BTreeMap::from([(
+            "rice".to_string(),
+            ScaleError::AlreadyConnected { serial: 716_001 },
+        )]),
+    };
+    let (manager, failures) = ScaleManager::from_fleet(connection, ActorConfig::default());
+    assert_eq!(manager.names(), ["flour"]);
+    assert!(matches!(
+        failures["rice"],
+        ScaleError::AlreadyConnected { serial: 716_001 }
+    ));
+    assert!(manager.read_all().await["flour"].is_ok());
+    assert!(manager.shutdown().await["flour"].is_some());
+}
+
+#[cfg(feature = "hardware")]
+#[tokio::test(flavor = "multi_thread")]
+async fn a_fleet_keeps_the_data_interval_each_scale_was_set_up_with() {
+    use libra::config::FleetConnection;
+    use libra::scale::ConnectedScale;
+    use libra::testing::FakeVoltageSource;
+
+    let scales = [("catch", 716_000, 8), ("hopper", 716_001, 100)]
+        .into_iter()
+        .map(|(name, serial, interval_ms)| {
+            let calibration = Calibration {
+                offset: 0.,
+                coefficients: [1000.; NUMBER_OF_INPUTS],
+            };
+            let mut scale = ConnectedScale::from_sources(
+                serial,
+                calibration,
+                FakeVoltageSource::bridge(serial),
+            );
+            scale
+                .set_data_intervals(Duration::from_millis(interval_ms))
+                .unwrap();
+            (name.to_string(), scale)
+        })
+        .collect();
+    let connection = FleetConnection {
+        scales,
+        failures: BTreeMap::new(),
+    };
+    let (manager, _) = ScaleManager::from_fleet(connection, ActorConfig::default());
+    assert!(manager.read_all().await.values().all(Result::is_ok));
+    assert_eq!(intervals(&manager), [Some(8), Some(100)]);
+
+    let interval = manager
+        .set_data_interval("hopper", Duration::from_millis(50))
+        .await;
+    assert_eq!(interval, Ok(Duration::from_millis(50)));
+    assert_eq!(intervals(&manager), [Some(8), Some(50)]);
+    manager.shutdown().await;
+}
+
+#[cfg(feature = "hardware")]
+mod hot_plug {
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    use libra::actor::ActorConfig;
+    use libra::calibration::Calibration;
+    use libra::config::{FleetConfig, ScaleConfig};
+    use libra::discovery::{AttachedChannel, DeviceEvent};
+    use libra::manager::{AutoConnect, FleetEvent, ScaleManager};
+    use libra::scale::{ConnectedScale, ScaleError};
+    use libra::testing::FakeVoltageSource;
+    use libra::{ScaleCmd, ScaleErrorKind, ScaleResponse, ScaleState};
+    use phidget::ChannelClass;
+    use tokio::sync::broadcast;
+    use tokio::sync::mpsc::{self, UnboundedSender};
+
+    const CALIBRATION: Calibration = Calibration {
+        offset: 12.,
+        coefficients: [1000., 1001., 999., 1000.5],
+    };
+
+    fn channel(serial: i32, channel: i32) -> AttachedChannel {
+        AttachedChannel {
+            serial,
+            label: None,
+            hub_port: None,
+            is_hub_port_device: false,
+            class: ChannelClass::VoltageRatioInput,
+            channel,
+        }
+    }
+
+    fn attach(devices: &UnboundedSender<DeviceEvent>, serial: i32) {
+        for number in 0..4 {
+            devices
+                .send(DeviceEvent::Attached(channel(serial, number)))
+                .unwrap();
+        }
+    }
+
+    /// The next event that is not of a scale's actor.
+    async fn next(events: &mut broadcast::Receiver<FleetEvent>) -> FleetEvent {
+        loop {
+            let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+                .await
+                .expect("no event within 5s")
+                .unwrap();
+            if !matches!(event, FleetEvent::Scale(_)) {
+                return event;
+            }
+        }
+    }
+
+    fn fleet() -> FleetConfig {
+        let config = |serial| ScaleConfig {
+            serial: Some(serial),
+            calibration: CALIBRATION,
+            ..Default::default()
+        };
+        FleetConfig {
+            scales: BTreeMap::from([
+                ("flour".to_string(), config(716_000)),
+                ("rice".to_string(), config(716_001)),
+            ]),
+            defaults: None,
+        }
+    }
+
+    /// Connects to a fake bridge, except for 716001, which is in use.
+    fn connect(config: &ScaleConfig) -> Result<ConnectedScale<FakeVoltageSource>, ScaleError> {
+        match config.serial.unwrap() {
+            716_001 => Err(ScaleError::AlreadyConnected { serial: 716_001 }),
+            serial => Ok(ConnectedScale::from_sources(
+                serial,
+                config.calibration,
+                FakeVoltageSource::bridge(serial),
+            )),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn scales_of_the_fleet_connect_as_their_bridges_are_attached() {
+        let manager = ScaleManager::new(ActorConfig::default());
+        let mut events = manager.events();
+        let (devices, receiver) = mpsc::unbounded_channel();
+        let watcher = manager.watch_devices_with(receiver, AutoConnect::Fleet(fleet()), connect);
+
+        attach(&devices, 716_000);
+        assert_eq!(next(&mut events).await, FleetEvent::ScaleAvailable(716_000));
+        assert_eq!(
+            next(&mut events).await,
+            FleetEvent::ScaleConnected("flour".into())
+        );
+        let flour = manager.get("flour").unwrap();
+        assert_eq!(
+            flour.send(ScaleCmd::GetCalibration).await,
+            ScaleResponse::Calibration(CALIBRATION)
+        );
+
+        // A bridge outside the fleet waits for a name.
+        attach(&devices, 716_002);
+        assert_eq!(next(&mut events).await, FleetEvent::ScaleAvailable(716_002));
+        assert_eq!(
+            next(&mut events).await,
+            FleetEvent::ScaleUnassigned {
+                serial: 716_002,
+                hub_port: None
+            }
+        );
+
+        attach(&devices, 716_001);
+        assert_eq!(next(&mut events).await, FleetEvent::ScaleAvailable(716_001));
+        let FleetEvent::ConnectFailed { name, error } = next(&mut events).await else {
+            panic!("rice connected");
+        };
+        assert_eq!(name, "rice");
+        assert_eq!(error.kind, ScaleErrorKind::AlreadyConnected);
+        assert_eq!(manager.names(), ["flour"]);
+
+        // Losing one channel loses the scale; losing the rest changes
+        // nothing more.
+        for number in [2, 0, 1, 3] {
+            devices
+                .send(DeviceEvent::Detached(channel(716_000, number)))
+                .unwrap();
+        }
+        assert_eq!(
+            next(&mut events).await,
+            FleetEvent::ScaleLost("flour".into())
+        );
+        assert_eq!(manager.state("flour"), Ok(ScaleState::Disconnected));
+
+        // Plugged back in, it is the same scale on the same actor.
+        attach(&devices, 716_000);
+        assert_eq!(next(&mut events).await, FleetEvent::ScaleAvailable(716_000));
+        assert_eq!(
+            next(&mut events).await,
+            FleetEvent::ScaleConnected("flour".into())
+        );
+        assert_eq!(manager.state("flour"), Ok(ScaleState::Connected));
+        assert!(matches!(
+            flour.send(ScaleCmd::GetWeight).await,
+            ScaleResponse::Weight(_)
+        ));
+
+        drop(watcher);
+        assert_eq!(manager.shutdown().await.len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn without_auto_connect_bridges_are_only_reported() {
+        let manager: ScaleManager<ConnectedScale<FakeVoltageSource>> =
+            ScaleManager::new(ActorConfig::default());
+        let mut events = manager.events();
+        let (devices, receiver) = mpsc::unbounded_channel();
+        let _watcher = manager.watch_devices_with(receiver, AutoConnect::Off, |_| {
+            panic!("connected with auto-connect off")
+        });
+
+        attach(&devices, 716_000);
+        assert_eq!(next(&mut events).await, FleetEvent::ScaleAvailable(716_000));
+        devices
+            .send(DeviceEvent::Detached(channel(716_000, 0)))
+            .unwrap();
+        attach(&devices, 716_003);
+        assert_eq!(next(&mut events).await, FleetEvent::ScaleAvailable(716_003));
+        assert!(manager.names().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn an_unknown_bridge_waits_until_it_is_assigned_a_name() {
+        let mut fleet = fleet();
+        fleet.scales.insert(
+            "salt".into(),
+            ScaleConfig {
+                label: Some("salt".into()),
+                ..Default::default()
+            },
+        );
+        let manager = ScaleManager::new(ActorConfig::default());
+        let mut events = manager.events();
+        let (devices, receiver) = mpsc::unbounded_channel();
+        let _watcher = manager.watch_devices_with(receiver, AutoConnect::Fleet(fleet), connect);
+
+        attach(&devices, 716_002);
+        assert_eq!(next(&mut events).await, FleetEvent::ScaleAvailable(716_002));
+        assert_eq!(
+            next(&mut events).await,
+            FleetEvent::ScaleUnassigned {
+                serial: 716_002,
+                hub_port: None
+            }
+        );
+        let waiting: Vec<_> = manager.unassigned().iter().map(|d| d.serial).collect();
+        assert_eq!(waiting, [716_002]);
+        assert!(manager.names().is_empty());
+
+        manager.assign("sugar", 716_002).unwrap();
+        assert_eq!(
+            next(&mut events).await,
+            FleetEvent::ScaleConnected("sugar".into())
+        );
+        assert!(manager.unassigned().is_empty());
+        assert_eq!(
+            manager.assignments(),
+            BTreeMap::from([("sugar".into(), 716_002)])
+        );
+
+        // A scale of the fleet without a serial number binds by the label
+        // written to its bridge.
+        for number in 0..4 {
+            let channel = AttachedChannel {
+                label: Some("salt".into()),
+                ..channel(716_004, number)
+            };
+            devices.send(DeviceEvent::Attached(channel)).unwrap();
+        }
+        assert_eq!(next(&mut events).await, FleetEvent::ScaleAvailable(716_004));
+        assert_eq!(
+            next(&mut events).await,
+            FleetEvent::ScaleConnected("salt".into())
+        );
+        assert_eq!(manager.names(), ["salt", "sugar"]);
+        manager.shutdown().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn a_bridge_bound_to_a_name_already_connected_is_a_conflict() {
+        let manager = ScaleManager::new(ActorConfig::default());
+        let mut events = manager.events();
+        let (devices, receiver) = mpsc::unbounded_channel();
+        let _watcher = manager.watch_devices_with(receiver, AutoConnect::Fleet(fleet()), connect);
+
+        attach(&devices, 716_000);
+        assert_eq!(next(&mut events).await, FleetEvent::ScaleAvailable(716_000));
+        assert_eq!(
+            next(&mut events).await,
+            FleetEvent::ScaleConnected("flour".into())
+        );
+
+        // Flour is given a new bridge while its old one is still connected.
+        manager.assign("flour", 716_003).unwrap();
+        attach(&devices, 716_003);
+        assert_eq!(next(&mut events).await, FleetEvent::ScaleAvailable(716_003));
+        assert_eq!(
+            next(&mut events).await,
+            FleetEvent::BindingConflict {
+                name: "flour".into(),
+                serial: 716_003
+            }
+        );
+        assert_eq!(manager.names(), ["flour"]);
+        let waiting: Vec<_> = manager.unassigned().iter().map(|d| d.serial).collect();
+        assert_eq!(waiting, [716_003]);
+
+        // Assigned a name of its own, it connects.
+        manager.assign("sugar", 716_003).unwrap();
+        assert_eq!(
+            next(&mut events).await,
+            FleetEvent::ScaleConnected("sugar".into())
+        );
+        assert_eq!(manager.names(), ["flour", "sugar"]);
+        manager.shutdown().await;
+    }
+
+    #[cfg(feature = "store")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn assignments_outlast_the_manager() {
+        use libra::store::AssignmentStore;
+
+        let dir = std::env::temp_dir().join(format!("libra-assignments-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = AssignmentStore::new(dir.join("assignments.json"));
+
+        let manager: ScaleManager<ConnectedScale<FakeVoltageSource>> =
+            ScaleManager::new(ActorConfig::default());
+        manager.persist_assignments(store.clone()).unwrap();
+        manager.assign("sugar", 716_002).unwrap();
+        manager.shutdown().await;
+        assert_eq!(
+            store.load().unwrap(),
+            BTreeMap::from([("sugar".into(), 716_002)])
+        );
+
+        // After a restart, the bridge is connected under its name at once.
+        let manager = ScaleManager::new(ActorConfig::default());
+        manager.persist_assignments(store).unwrap();
+        let mut events = manager.events();
+        let (devices, receiver) = mpsc::unbounded_channel();
+        let _watcher = manager.watch_devices_with(receiver, AutoConnect::Fleet(fleet()), connect);
+        attach(&devices, 716_002);
+        assert_eq!(next(&mut events).await, FleetEvent::ScaleAvailable(716_002));
+        assert_eq!(
+            next(&mut events).await,
+            FleetEvent::ScaleConnected("sugar".into())
+        );
+        manager.shutdown().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+/// A platform of a commissioned line, whose cells are `sensitivity` times
+/// as sensitive as the one the master calibration was made on, with a
+/// 500 g check mass on it. Its own calibration reads 3 g light.
+struct Line {
+    sensitivity: f64,
+    calibration: Calibration,
+}
+
+impl Line {
+    fn new(sensitivity: f64) -> Self {
+        Self {
+            sensitivity,
+            calibration: Calibration {
+                offset: 3.,
+                coefficients: [1.; NUMBER_OF_INPUTS],
+            },
+        }
+    }
+}
+
+impl Scale for Line {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        let cell = 500. * self.sensitivity / 4.;
+        Ok(self.calibration.weigh(&[cell; NUMBER_OF_INPUTS]))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_median_weight_of(3)
+    }
+
+    fn calibration(&self) -> Result<Calibration, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.calibration)
+    }
+
+    fn set_calibration(
+        &mut self,
+        calibration: Calibration,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.calibration = calibration;
+        Ok(())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_calibration_is_copied_to_the_scales_that_weigh_the_check_mass() {
+    let manager = ScaleManager::default();
+    for (name, sensitivity) in [("line-1", 1.), ("line-2", 1.02), ("line-3", 0.999)] {
+        manager.add(name, Line::new(sensitivity)).unwrap();
+    }
+    let master = Calibration {
+        offset: 0.,
+        coefficients: [1.; NUMBER_OF_INPUTS],
+    };
+    let check = TransferCheck {
+        check_mass: Grams(500.),
+        tolerance: Grams(1.),
+        samples: 3,
+    };
+    let results = manager
+        .apply_calibration_to(["line-1", "line-2", "line-3", "line-9"], master, check)
+        .await;
+
+    assert_eq!(results.len(), 4);
+    assert_eq!(results["line-1"], Ok(check.judge(Grams(500.))));
+    let line_2 = results["line-2"].as_ref().unwrap();
+    assert!(!line_2.passed);
+    assert!((line_2.error.get() - 10.).abs() < 1e-9, "{line_2:?}");
+    assert!(results["line-3"].as_ref().unwrap().passed);
+    assert_eq!(
+        results["line-9"],
+        Err(ManagerError::UnknownScale {
+            name: "line-9".into()
+        })
+    );
+
+    let calibration = |name| {
+        let handle = manager.get(name).unwrap();
+        async move {
+            match handle.send(ScaleCmd::GetCalibration).await {
+                ScaleResponse::Calibration(calibration) => calibration,
+                response => panic!("{response:?}"),
+            }
+        }
+    };
+    assert_eq!(calibration("line-1").await, master);
+    assert_eq!(calibration("line-3").await, master);
+    // The scale that failed has its own back.
+    assert_eq!(calibration("line-2").await, Line::new(1.).calibration);
+    manager.shutdown().await;
+}
+
+/// Load cells reading 250 g, whose raw readings swing by `wobble` either
+/// way from one read to the next, and that hang for a second on each raw
+/// read once `wedged`.
+struct Cells {
+    wobble: f64,
+    wedged: bool,
+    reads: AtomicUsize,
+}
+
+impl Cells {
+    fn new(wobble: f64) -> Self {
+        Self {
+            wobble,
+            wedged: false,
+            reads: AtomicUsize::new(0),
+        }
+    }
+
+    fn wedged(self) -> Self {
+        Self {
+            wedged: true,
+            ..self
+        }
+    }
+}
+
+impl Scale for Cells {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(250.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(MedianGrams(250.))
+    }
+
+    fn get_raw_readings(
+        &self,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        if self.wedged {
+            std::thread::sleep(Duration::from_secs(1));
+        }
+        let sign = match self.reads.fetch_add(1, Ordering::SeqCst) % 2 {
+            0 => 1.,
+            _ => -1.,
+        };
+        Ok([0.1 + sign * self.wobble; NUMBER_OF_INPUTS])
+    }
+
+    fn status(&mut self) -> Result<ScaleStatus, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ScaleStatus {
+            phidget_id: 716_000,
+            attached: true,
+            calibration: Calibration {
+                offset: 0.,
+                coefficients: [1.; NUMBER_OF_INPUTS],
+            },
+            tare: Grams(0.),
+            calibration_expires_in_days: Some(30),
+        })
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_wedged_scale_times_out_of_the_fleet_status_alone() {
+    let manager = ScaleManager::new(ActorConfig {
+        sample_interval: Some(Duration::from_millis(10)),
+        ..ActorConfig::default()
+    });
+    manager.add("flour", Cells::new(0.)).unwrap();
+    manager.add("rice", Cells::new(1e-6)).unwrap();
+    manager.add("salt", Cells::new(0.).wedged()).unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let config = FleetStatusConfig {
+        deadline: Duration::from_millis(300),
+        samples: 4,
+        health: HealthConfig {
+            sample_interval: Duration::from_millis(10),
+            ..HealthConfig::default()
+        },
+    };
+    let start = Instant::now();
+    let fleet = manager.fleet_status_with(&config).await;
+    let elapsed = start.elapsed();
+    assert!(elapsed < Duration::from_millis(600), "took {elapsed:?}");
+
+    let names: Vec<_> = fleet.scales.iter().map(|scale| &scale.name[..]).collect();
+    assert_eq!(names, ["flour", "rice", "salt"]);
+    let [flour, rice, salt] = &fleet.scales[..] else {
+        unreachable!()
+    };
+    let report = flour.health.as_ref().unwrap();
+    assert_eq!((report.status, report.reads), (Status::Ok, 4));
+    assert_eq!(flour.last_weight.unwrap().weight, Grams(250.));
+    assert!(flour.last_weight_age.unwrap() < Duration::from_millis(100));
+    assert_eq!(flour.calibration_expires_in_days, Some(30));
+    assert_eq!(flour.reconnects, 0);
+    assert_eq!(rice.status(), Status::Degraded);
+    assert!(
+        rice.health.as_ref().unwrap().reasons[0].contains("noisy"),
+        "{rice:?}"
+    );
+    // The wedged scale is reported with what is known of it without asking.
+    assert_eq!(
+        salt.health.as_ref().unwrap_err().kind,
+        ScaleErrorKind::Timeout
+    );
+    assert_eq!(salt.state, ScaleState::Connected);
+    assert!(salt.last_weight.is_some());
+    assert_eq!(fleet.status, Status::Unusable);
+
+    let table = fleet.to_string();
+    let lines: Vec<_> = table.lines().collect();
+    assert_eq!(lines.len(), 5, "{table}");
+    assert_eq!(lines[0], "Fleet: unusable (3 scales)");
+    assert!(lines[1].starts_with("SCALE"), "{table}");
+    assert!(
+        lines[2].starts_with("flour  connected")
+            && lines[2].contains("ok")
+            && lines[2].ends_with("expires in 30 days"),
+        "{table}"
+    );
+    assert!(lines[3].contains("degraded"), "{table}");
+    assert!(lines[4].contains("timeout"), "{table}");
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use libra::cancel::CancelFlag;
+use libra::sampler::MedianSampler;
+use libra::scale::ScaleError;
+use libra::testing::{ManualClock, MockScale};
+use libra::{Grams, Scale};
+
+/// `n` weights in no particular order, with repeats, the same on every run.
+fn weights(n: usize) -> impl Iterator<Item = Result<Grams, ScaleError>> {
+    let mut state = 0x5eed_u64;
+    (0..n).map(move |_| {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1);
+        Ok(Grams((state >> 40) as f64 % 997. / 4.))
+    })
+}
+
+#[test]
+fn a_sampler_takes_the_medians_a_scale_does() {
+    for samples in [1, 2, 15, 101] {
+        let scale = MockScale::new(Grams(0.)).returns(weights(samples * 4));
+        let sampled = MockScale::new(Grams(0.)).returns(weights(samples * 4));
+        let mut sampler = MedianSampler::new(samples, Duration::ZERO).unwrap();
+        assert_eq!(sampler.samples(), samples);
+        for _ in 0..4 {
+            assert_eq!(
+                sampler.measure(&sampled).unwrap(),
+                scale.get_median_weight_of(samples).unwrap(),
+                "{samples} samples"
+            );
+        }
+        assert_eq!(sampled.remaining(), 0);
+    }
+}
+
+#[test]
+fn a_failed_read_leaves_nothing_for_the_next_median() {
+    let scale = MockScale::new(Grams(100.)).returns([
+        Ok(Grams(1.)),
+        Err(ScaleError::Busy),
+        Ok(Grams(f64::NAN)),
+    ]);
+    let mut sampler = MedianSampler::new(3, Duration::ZERO).unwrap();
+    assert!(matches!(sampler.measure(&scale), Err(ScaleError::Busy)));
+    assert!(matches!(
+        sampler.measure(&scale),
+        Err(ScaleError::NonFinite { channel: None })
+    ));
+    // Not the 1 g read before the failure.
+    assert_eq!(sampler.measure(&scale).unwrap().get(), 100.);
+}
+
+#[test]
+fn samples_are_paced_by_the_sampler_clock() {
+    let clock = ManualClock::new();
+    let scale = MockScale::new(Grams(5.));
+    let mut sampler = MedianSampler::new(15, Duration::from_millis(10))
+        .unwrap()
+        .with_clock(Arc::new(clock.clone()));
+    assert_eq!(sampler.measure(&scale).unwrap().get(), 5.);
+    assert_eq!(clock.elapsed(), Duration::from_millis(150));
+    assert_eq!(scale.reads(), 15);
+
+    let cancel = CancelFlag::new();
+    cancel.cancel();
+    assert!(matches!(
+        sampler.measure_cancellable(&scale, &cancel),
+        Err(ScaleError::Cancelled { collected: 0 })
+    ));
+}
+
+#[test]
+fn a_sampler_needs_a_sample_and_a_sane_interval() {
+    assert!(matches!(
+        MedianSampler::new(0, Duration::ZERO),
+        Err(ScaleError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        MedianSampler::new(5, Duration::from_secs(24 * 60 * 60)),
+        Err(ScaleError::InvalidArgument(_))
+    ));
+}
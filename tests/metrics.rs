@@ -0,0 +1,134 @@
+#![cfg(feature = "metrics")]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use libra::actor::{spawn_scale_actor_with_config, ActorConfig};
+use libra::metrics::{serve_metrics_listener, ScaleMetrics};
+use libra::scale::ScaleError;
+use libra::{Grams, MedianGrams, Scale, ScaleCmd};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Every third read reports the scale busy; the others weigh 250 g.
+#[derive(Default)]
+struct MockScale {
+    reads: AtomicUsize,
+}
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        if self.reads.fetch_add(1, Ordering::Relaxed) % 3 == 2 {
+            return Err(ScaleError::Busy.into());
+        }
+        Ok(Grams(250.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+
+    fn get_raw_readings(&self) -> Result<[f64; 4], Box<dyn std::error::Error + Send + Sync>> {
+        panic!("raw readings are broken")
+    }
+}
+
+fn lines(exposition: &str) -> Vec<&str> {
+    exposition
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect()
+}
+
+#[tokio::test]
+async fn commands_are_recorded() {
+    let metrics = ScaleMetrics::new();
+    let (handle, _task) = spawn_scale_actor_with_config(
+        MockScale::default(),
+        ActorConfig {
+            metrics: Some(metrics.clone()),
+            ..Default::default()
+        },
+    );
+    let exposition = metrics.exposition();
+    assert!(!lines(&exposition)
+        .iter()
+        .any(|line| line.starts_with("libra_weight_grams")));
+
+    for _ in 0..6 {
+        handle.send(ScaleCmd::GetWeight).await;
+    }
+    handle.send(ScaleCmd::GetRawReadings).await;
+    handle.send(ScaleCmd::Tare { samples: 3 }).await;
+    metrics.record_reconnect();
+
+    let exposition = metrics.exposition();
+    let samples = lines(&exposition);
+    for expected in [
+        "libra_weight_grams 250",
+        "libra_queue_depth 0",
+        "libra_reads_total 4",
+        "libra_errors_total{kind=\"busy\"} 2",
+        "libra_errors_total{kind=\"internal\"} 1",
+        "libra_errors_total{kind=\"unsupported\"} 1",
+        "libra_reconnects_total 1",
+        "libra_read_duration_seconds_bucket{le=\"+Inf\"} 4",
+        "libra_read_duration_seconds_count 4",
+    ] {
+        assert!(samples.contains(&expected), "{expected} in\n{exposition}");
+    }
+    assert!(exposition.contains("# TYPE libra_read_duration_seconds histogram"));
+    assert!(samples.contains(&"libra_read_duration_seconds_bucket{le=\"10\"} 4"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn periodic_samples_are_recorded() {
+    let metrics = ScaleMetrics::new();
+    let (_handle, _task) = spawn_scale_actor_with_config(
+        MockScale::default(),
+        ActorConfig {
+            sample_interval: Some(Duration::from_millis(100)),
+            metrics: Some(metrics.clone()),
+            ..Default::default()
+        },
+    );
+    // Samples at 0, 100, ..., 500 ms: two of the six are busy.
+    tokio::time::sleep(Duration::from_millis(550)).await;
+
+    let exposition = metrics.exposition();
+    let samples = lines(&exposition);
+    assert!(samples.contains(&"libra_reads_total 4"), "{exposition}");
+    assert!(
+        samples.contains(&"libra_errors_total{kind=\"busy\"} 2"),
+        "{exposition}"
+    );
+}
+
+#[tokio::test]
+async fn metrics_are_served_over_http() {
+    let metrics = ScaleMetrics::new();
+    metrics.record_reconnect();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve_metrics_listener(metrics, listener));
+
+    let get = |path: &'static str| async move {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {path} HTTP/1.1\r\nHost: scale\r\n\r\n");
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    };
+
+    let response = get("/metrics").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{response}");
+    assert!(response.contains("Content-Type: text/plain; version=0.0.4\r\n"));
+    assert!(response.contains("\nlibra_reconnects_total 1\n"));
+
+    let response = get("/").await;
+    assert!(
+        response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+        "{response}"
+    );
+}
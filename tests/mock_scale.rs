@@ -0,0 +1,121 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use libra::scale::ScaleError;
+use libra::testing::{Latency, ManualClock, MockScale};
+use libra::{Grams, MedianGrams, Scale};
+
+fn scale_error<'a>(e: &'a (dyn std::error::Error + Send + Sync + 'static)) -> &'a ScaleError {
+    e.downcast_ref().expect("a ScaleError")
+}
+
+#[test]
+fn the_script_is_read_in_order_then_the_steady_weight() {
+    let scale = MockScale::new(Grams(5.)).returns(vec![
+        Ok(Grams(12.)),
+        Err(ScaleError::Busy),
+        Ok(Grams(13.)),
+    ]);
+    assert_eq!(scale.remaining(), 3);
+    assert_eq!(scale.get_weight().unwrap(), Grams(12.));
+    let e = scale.get_weight().unwrap_err();
+    assert!(matches!(scale_error(&*e), ScaleError::Busy));
+    assert_eq!(scale.get_weight().unwrap(), Grams(13.));
+    assert_eq!(scale.remaining(), 0);
+    for _ in 0..3 {
+        assert_eq!(scale.get_weight().unwrap(), Grams(5.));
+    }
+    assert_eq!(scale.reads(), 6);
+    assert_eq!(scale.medians(), 0);
+}
+
+#[test]
+fn medians_take_their_samples_from_the_script() {
+    let scale = MockScale::new(Grams(0.)).returns([3., 1., 2.].map(|weight| Ok(Grams(weight))));
+    assert_eq!(scale.get_median_weight_of(3).unwrap(), MedianGrams(2.));
+    assert_eq!(scale.reads(), 3);
+    assert_eq!(scale.medians(), 1);
+
+    let scale = MockScale::new(Grams(0.)).returns(vec![
+        Ok(Grams(1.)),
+        Err(ScaleError::Disconnected { channel: Some(2) }),
+    ]);
+    let e = scale.get_median_weight_of(5).unwrap_err();
+    assert!(matches!(
+        scale_error(&*e),
+        ScaleError::Disconnected { channel: Some(2) }
+    ));
+    assert_eq!(scale.reads(), 2);
+}
+
+#[test]
+fn reads_take_the_latency() {
+    let scale = MockScale::new(Grams(1.)).with_latency(Duration::from_millis(20));
+    let started = Instant::now();
+    scale.get_weight().unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(20));
+}
+
+#[test]
+fn scripted_latencies_are_spent_on_its_clock() {
+    let clock = ManualClock::new();
+    let scale = MockScale::new(Grams(1.))
+        .with_latency(Latency::Scripted(vec![
+            Duration::from_millis(5),
+            Duration::from_millis(20),
+        ]))
+        .with_clock(Arc::new(clock.clone()));
+    assert_eq!(scale.get_median_weight_of(3).unwrap(), MedianGrams(1.));
+    assert_eq!(clock.elapsed(), Duration::from_millis(25));
+}
+
+#[test]
+fn it_can_be_shared_between_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<MockScale>();
+}
+
+#[cfg(feature = "tokio")]
+mod async_scale {
+    use std::time::Duration;
+
+    use libra::actor::spawn_scale_actor;
+    use libra::scale::ScaleError;
+    use libra::testing::MockScale;
+    use libra::{AsyncScale, Grams, MedianGrams, ScaleCmd, ScaleErrorKind, ScaleResponse};
+    use tokio::time::Instant;
+
+    #[tokio::test(start_paused = true)]
+    async fn async_reads_share_the_script_and_sleep_the_latency() {
+        let scale = MockScale::new(Grams(4.))
+            .returns([9., 7., 8.].map(|weight| Ok(Grams(weight))))
+            .with_latency(Duration::from_millis(100));
+        let started = Instant::now();
+        assert_eq!(
+            AsyncScale::get_median_weight(&scale, 3).await.unwrap(),
+            MedianGrams(8.)
+        );
+        assert_eq!(AsyncScale::get_weight(&scale).await.unwrap(), Grams(4.));
+        assert_eq!(started.elapsed(), Duration::from_millis(400));
+        assert_eq!(scale.reads(), 4);
+        assert_eq!(scale.medians(), 1);
+        assert!(AsyncScale::get_median_weight(&scale, 0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn it_runs_behind_the_actor() {
+        let scale = MockScale::new(Grams(10.)).returns(vec![Err(ScaleError::Busy)]);
+        let (handle, task) = spawn_scale_actor(scale);
+        match handle.send(ScaleCmd::GetWeight).await {
+            ScaleResponse::Error(info) => assert_eq!(info.kind, ScaleErrorKind::Busy),
+            other => panic!("unexpected response {other:?}"),
+        }
+        assert_eq!(
+            handle.send(ScaleCmd::GetWeight).await,
+            ScaleResponse::Weight(Grams(10.))
+        );
+        handle.send(ScaleCmd::Shutdown).await;
+        let scale = task.await.unwrap().unwrap();
+        assert_eq!(scale.reads(), 2);
+    }
+}
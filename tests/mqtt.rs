@@ -0,0 +1,198 @@
+#![cfg(feature = "mqtt")]
+
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use libra::actor::{spawn_scale_actor_with_config, ActorConfig};
+use libra::mqtt::{
+    publish_weights, publish_weights_with, MqttConfig, MqttError, MqttPublisher, Qos,
+    STATUS_OFFLINE,
+};
+use libra::{Grams, MedianGrams, Scale, ScaleCmd, ScaleResponse, StampedWeight};
+use tokio::sync::watch;
+
+#[derive(Debug, PartialEq)]
+struct Message {
+    topic: String,
+    qos: Qos,
+    retain: bool,
+    payload: Vec<u8>,
+}
+
+/// Records every message, or refuses them all when `offline`.
+#[derive(Default)]
+struct MockPublisher {
+    messages: Mutex<Vec<Message>>,
+    offline: bool,
+}
+
+impl MqttPublisher for MockPublisher {
+    fn publish(
+        &self,
+        topic: &str,
+        qos: Qos,
+        retain: bool,
+        payload: Vec<u8>,
+    ) -> Result<(), MqttError> {
+        if self.offline {
+            return Err(MqttError {
+                topic: topic.to_owned(),
+            });
+        }
+        self.messages.lock().unwrap().push(Message {
+            topic: topic.to_owned(),
+            qos,
+            retain,
+            payload,
+        });
+        Ok(())
+    }
+}
+
+fn config() -> MqttConfig {
+    let mut config = MqttConfig::new("localhost", 1883, "libra-test", "kitchen/scale-1");
+    config.publish_interval = Duration::from_millis(100);
+    config.qos = Qos::AtLeastOnce;
+    config
+}
+
+fn stamped(sequence: u64) -> StampedWeight {
+    StampedWeight {
+        weight: Grams(sequence as f64),
+        sequence,
+        timestamp: SystemTime::now(),
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn publishes_weights_then_offline_status() {
+    let (sender, receiver) = watch::channel(None);
+    let publisher = MockPublisher::default();
+    let config = config();
+    let publishing = publish_weights_with(receiver, &publisher, &config);
+    let feeding = async move {
+        sender.send(Some(stamped(1))).unwrap();
+        tokio::time::sleep(Duration::from_millis(150)).await;
+    };
+    tokio::join!(publishing, feeding);
+
+    let messages = publisher.messages.into_inner().unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].topic, "kitchen/scale-1/weight");
+    assert_eq!(messages[0].qos, Qos::AtLeastOnce);
+    assert!(!messages[0].retain);
+    let weight: StampedWeight = serde_json::from_slice(&messages[0].payload).unwrap();
+    assert_eq!(weight.sequence, 1);
+    assert_eq!(weight.weight, Grams(1.));
+    assert_eq!(
+        messages[1],
+        Message {
+            topic: "kitchen/scale-1/status".into(),
+            qos: Qos::AtLeastOnce,
+            retain: true,
+            payload: STATUS_OFFLINE.into(),
+        }
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn fast_readings_are_downsampled_to_the_newest() {
+    let (sender, receiver) = watch::channel(None);
+    let publisher = MockPublisher::default();
+    let config = config();
+    let publishing = publish_weights_with(receiver, &publisher, &config);
+    let feeding = async move {
+        for sequence in 1..=100 {
+            sender.send(Some(stamped(sequence))).unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    };
+    tokio::join!(publishing, feeding);
+
+    let messages = publisher.messages.into_inner().unwrap();
+    let sequences: Vec<u64> = messages
+        .iter()
+        .filter(|message| message.topic.ends_with("/weight"))
+        .map(|message| {
+            serde_json::from_slice::<StampedWeight>(&message.payload)
+                .unwrap()
+                .sequence
+        })
+        .collect();
+    assert!((9..=11).contains(&sequences.len()), "{sequences:?}");
+    assert_eq!(sequences[0], 1);
+    assert!(sequences.windows(2).all(|pair| pair[1] >= pair[0] + 9));
+}
+
+#[tokio::test(start_paused = true)]
+async fn dropped_messages_do_not_stop_publishing() {
+    let (sender, receiver) = watch::channel(None);
+    let publisher = MockPublisher {
+        offline: true,
+        ..Default::default()
+    };
+    let config = config();
+    let publishing = publish_weights_with(receiver, &publisher, &config);
+    let feeding = async move {
+        for sequence in 1..=5 {
+            sender.send(Some(stamped(sequence))).unwrap();
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(5), async {
+        tokio::join!(publishing, feeding)
+    })
+    .await
+    .unwrap();
+}
+
+#[test]
+fn last_will_marks_the_scale_offline() {
+    let will = config().mqtt_options().last_will().unwrap();
+    assert_eq!(will.topic, "kitchen/scale-1/status");
+    assert_eq!(&will.message[..], STATUS_OFFLINE.as_bytes());
+    assert_eq!(will.qos, rumqttc::QoS::AtLeastOnce);
+    assert!(will.retain);
+}
+
+struct MockScale;
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(5.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(MedianGrams(5.))
+    }
+}
+
+#[tokio::test]
+async fn unreachable_broker_does_not_hold_up_the_scale() {
+    let (handle, _task) = spawn_scale_actor_with_config(
+        MockScale,
+        ActorConfig {
+            sample_interval: Some(Duration::from_millis(10)),
+            ..Default::default()
+        },
+    );
+    // Nothing listens on port 1, so every connection attempt fails.
+    let mut config = config();
+    config.host = "127.0.0.1".into();
+    config.port = 1;
+    let publisher = publish_weights(&handle, config);
+
+    for _ in 0..20 {
+        let response =
+            tokio::time::timeout(Duration::from_millis(500), handle.send(ScaleCmd::GetWeight))
+                .await
+                .unwrap();
+        assert_eq!(response, ScaleResponse::Weight(Grams(5.)));
+    }
+
+    handle.shutdown().await;
+    tokio::time::timeout(Duration::from_secs(5), publisher)
+        .await
+        .unwrap()
+        .unwrap();
+}
@@ -0,0 +1,100 @@
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libra::multi::run_concurrently;
+use libra::scale::ScaleError;
+use libra::{Grams, MedianGrams, Scale};
+
+const READ_TIME: Duration = Duration::from_millis(200);
+
+/// Takes `READ_TIME` per read and optionally fails.
+struct SlowScale {
+    weight: f64,
+    fail: bool,
+}
+
+impl Scale for SlowScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        thread::sleep(READ_TIME);
+        if self.fail {
+            return Err(Box::new(ScaleError::IoError(
+                io::ErrorKind::TimedOut.into(),
+            )));
+        }
+        Ok(Grams(self.weight))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_median_weight_of(3)
+    }
+}
+
+fn platforms() -> Vec<SlowScale> {
+    vec![
+        SlowScale {
+            weight: 1.,
+            fail: false,
+        },
+        SlowScale {
+            weight: 2.,
+            fail: true,
+        },
+        SlowScale {
+            weight: 3.,
+            fail: false,
+        },
+    ]
+}
+
+#[test]
+fn reads_run_in_parallel_and_keep_order() {
+    let mut scales = platforms();
+    let start = Instant::now();
+    let results = run_concurrently(&mut scales, |scale| {
+        scale.get_weight().map_err(|e| e.to_string())
+    });
+    let elapsed = start.elapsed();
+
+    assert!(elapsed < READ_TIME * 2, "took {elapsed:?}");
+    assert_eq!(results[0], Ok(Grams(1.)));
+    assert!(results[1].is_err());
+    assert_eq!(results[2], Ok(Grams(3.)));
+}
+
+#[test]
+fn median_windows_overlap() {
+    let mut scales = platforms();
+    let start = Instant::now();
+    let results = run_concurrently(&mut scales, |scale| {
+        scale.get_median_weight().map_err(|e| e.to_string())
+    });
+    let elapsed = start.elapsed();
+
+    // Three samples each; serial would be nine read times.
+    assert!(elapsed < READ_TIME * 5, "took {elapsed:?}");
+    assert_eq!(results[0], Ok(MedianGrams(1.)));
+    assert!(results[1].is_err());
+    assert_eq!(results[2], Ok(MedianGrams(3.)));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(flavor = "multi_thread")]
+async fn async_reads_run_in_parallel_and_keep_order() {
+    use libra::multi::{read_all_async, read_all_medians_async};
+    use libra::shared::SharedScale;
+
+    let scales: Vec<_> = platforms().into_iter().map(SharedScale::new).collect();
+
+    let start = Instant::now();
+    let results = read_all_async(&scales).await;
+    assert!(start.elapsed() < READ_TIME * 2);
+    assert_eq!(results[0].as_ref().unwrap(), &Grams(1.));
+    assert!(results[1].is_err());
+    assert_eq!(results[2].as_ref().unwrap(), &Grams(3.));
+
+    let results = read_all_medians_async(&scales, 2).await;
+    assert_eq!(results[0].as_ref().unwrap(), &MedianGrams(1.));
+    assert!(results[1].is_err());
+    assert_eq!(results[2].as_ref().unwrap(), &MedianGrams(3.));
+}
@@ -0,0 +1,230 @@
+#![cfg(feature = "hardware")]
+
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use libra::scale::{open_channels, ScaleError, NUMBER_OF_INPUTS};
+use libra::{ScaleErrorInfo, ScaleErrorKind};
+use phidget::{ChannelClass, Phidget, ReturnCode};
+
+/// What the mock phidget library does, and what was asked of it.
+#[derive(Default)]
+struct Device {
+    /// Channel numbers `set_channel` turns down.
+    rejected: Vec<i32>,
+    /// The channel `open_wait` times out on.
+    timing_out: Option<i32>,
+    /// How many voltage ratio channels the device reports.
+    channels: u32,
+    /// Serial numbers and channels of the handles that were opened.
+    opened: Vec<(Option<i32>, i32)>,
+    /// Channels of the handles that were closed, in order.
+    closed: Vec<i32>,
+}
+
+/// A stand-in for a `VoltageRatioInput` that never reaches the phidget
+/// library.
+struct MockInput {
+    device: Arc<Mutex<Device>>,
+    serial_number: Option<i32>,
+    channel: i32,
+}
+
+impl Phidget for MockInput {
+    fn as_handle(&mut self) -> phidget::ffi::PhidgetHandle {
+        std::ptr::null_mut()
+    }
+
+    fn set_serial_number(&mut self, sn: i32) -> phidget::Result<()> {
+        self.serial_number = Some(sn);
+        Ok(())
+    }
+
+    fn set_channel(&mut self, chan: i32) -> phidget::Result<()> {
+        if self.device.lock().unwrap().rejected.contains(&chan) {
+            return Err(ReturnCode::InvalidArg);
+        }
+        self.channel = chan;
+        Ok(())
+    }
+
+    fn open_wait(&mut self, _to: Duration) -> phidget::Result<()> {
+        let mut device = self.device.lock().unwrap();
+        if device.timing_out == Some(self.channel) {
+            return Err(ReturnCode::Timeout);
+        }
+        device.opened.push((self.serial_number, self.channel));
+        Ok(())
+    }
+
+    fn close(&mut self) -> phidget::Result<()> {
+        self.device.lock().unwrap().closed.push(self.channel);
+        Ok(())
+    }
+
+    fn channel_class(&mut self) -> phidget::Result<ChannelClass> {
+        Ok(ChannelClass::VoltageRatioInput)
+    }
+
+    fn device_channel_count(&mut self, cls: ChannelClass) -> phidget::Result<u32> {
+        assert_eq!(cls, ChannelClass::VoltageRatioInput);
+        Ok(self.device.lock().unwrap().channels)
+    }
+}
+
+fn device(channels: u32, rejected: Vec<i32>) -> Arc<Mutex<Device>> {
+    Arc::new(Mutex::new(Device {
+        rejected,
+        channels,
+        ..Device::default()
+    }))
+}
+
+fn open(
+    device: &Arc<Mutex<Device>>,
+    phidget_id: Option<i32>,
+) -> Result<[MockInput; NUMBER_OF_INPUTS], ScaleError> {
+    open_channels(phidget_id, Duration::from_millis(10), || MockInput {
+        device: device.clone(),
+        serial_number: None,
+        channel: -1,
+    })
+}
+
+#[test]
+fn every_channel_is_opened_in_order() {
+    let device = device(4, Vec::new());
+    let vins = open(&device, Some(716_709)).unwrap();
+    let channels: Vec<_> = vins.iter().map(|vin| vin.channel).collect();
+    assert_eq!(channels, [0, 1, 2, 3]);
+    let opened = &device.lock().unwrap().opened;
+    assert_eq!(
+        *opened,
+        [0, 1, 2, 3].map(|channel| (Some(716_709), channel))
+    );
+}
+
+#[test]
+fn any_phidget_will_do_without_an_id() {
+    let device = device(8, Vec::new());
+    open(&device, None).unwrap();
+    assert!(device
+        .lock()
+        .unwrap()
+        .opened
+        .iter()
+        .all(|(serial_number, _)| serial_number.is_none()));
+}
+
+#[test]
+fn a_rejected_channel_is_a_wrong_device() {
+    let device = device(4, vec![2]);
+    let error = open(&device, Some(716_709)).err().unwrap();
+    let ScaleError::OpenRolledBack { error, .. } = error else {
+        panic!("{error:?} was not rolled back");
+    };
+    assert!(
+        matches!(
+            *error,
+            ScaleError::WrongDevice {
+                channel: 2,
+                channels: None
+            }
+        ),
+        "{error:?}"
+    );
+    assert_eq!(error.kind(), ScaleErrorKind::WrongDevice);
+    assert_eq!(
+        error.to_string(),
+        "Phidget has no Load Cell 2; check that it is a 4-channel bridge"
+    );
+    assert_eq!(device.lock().unwrap().opened.len(), 2);
+}
+
+#[test]
+fn a_two_channel_bridge_is_caught_after_the_first_open() {
+    let device = device(2, Vec::new());
+    let error = open(&device, None).err().unwrap();
+    assert!(!error.is_transient());
+    assert!(!error.is_disconnection());
+    assert_eq!(error.kind(), ScaleErrorKind::WrongDevice);
+    let ScaleError::OpenRolledBack { closed: 1, error } = error else {
+        panic!("{error:?} was not rolled back");
+    };
+    assert!(
+        matches!(
+            *error,
+            ScaleError::WrongDevice {
+                channel: 2,
+                channels: Some(2)
+            }
+        ),
+        "{error:?}"
+    );
+    assert_eq!(
+        error.to_string(),
+        "Phidget has no Load Cell 2 (it reports 2 channels); check that it is a 4-channel bridge"
+    );
+    let device = device.lock().unwrap();
+    assert_eq!(device.opened.len(), 1);
+    assert_eq!(device.closed, [0]);
+}
+
+#[test]
+fn a_failed_open_closes_the_channels_before_it() {
+    for failing in 0..NUMBER_OF_INPUTS as i32 {
+        let device = Arc::new(Mutex::new(Device {
+            timing_out: Some(failing),
+            channels: 4,
+            ..Device::default()
+        }));
+        let error = open(&device, Some(716_709)).err().unwrap();
+        assert_eq!(error.kind(), ScaleErrorKind::Phidget);
+        assert!(error.is_transient(), "{error:?}");
+
+        let closed = device.lock().unwrap().closed.clone();
+        assert_eq!(closed, (0..failing).collect::<Vec<_>>());
+        let inner = match error {
+            ScaleError::OpenRolledBack { closed, error } => {
+                assert_eq!(closed, failing as usize);
+                assert!(error
+                    .to_string()
+                    .starts_with(&format!("Phidget error at Load Cell {failing}")));
+                *error
+            }
+            error => {
+                // Nothing had opened, so there was nothing to close.
+                assert_eq!(failing, 0);
+                error
+            }
+        };
+        let ScaleError::PhidgetError(inner) = inner else {
+            panic!("{inner:?} is not a phidget error");
+        };
+        assert_eq!(inner.return_code(), ReturnCode::Timeout);
+        assert_eq!(inner.load_cell(), failing as usize);
+    }
+}
+
+#[test]
+fn a_rolled_back_error_notes_the_cleanup() {
+    let error = ScaleError::OpenRolledBack {
+        closed: 2,
+        error: Box::new(ScaleError::phidget_error(ReturnCode::Timeout, 2)),
+    };
+    assert!(error
+        .to_string()
+        .ends_with("; closed the 2 channels opened before it"));
+    let source = error.source().unwrap().to_string();
+    assert!(
+        source.starts_with("Phidget error at Load Cell 2"),
+        "{source}"
+    );
+
+    let info = ScaleErrorInfo::from(&error);
+    assert_eq!(info.kind, ScaleErrorKind::Phidget);
+    assert_eq!(info.load_cell, Some(2));
+    assert_eq!(info.return_code, Some(ReturnCode::Timeout as i32));
+    assert_eq!(info.message, error.to_string());
+}
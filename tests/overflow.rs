@@ -0,0 +1,183 @@
+#![cfg(feature = "tokio")]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+use libra::actor::{spawn_scale_actor_with_config, ActorConfig, ScaleHandle};
+use libra::overflow::OverflowPolicy;
+use libra::scale::ScaleError;
+use libra::stream::WeightStream;
+use libra::{Grams, MedianGrams, Scale, ScaleCmd, ScaleResponse};
+
+const INTERVAL: Duration = Duration::from_millis(5);
+
+fn counting_reader(reads: &Arc<AtomicUsize>) -> impl FnMut() -> Result<Grams, ScaleError> + Send {
+    let reads = Arc::clone(reads);
+    move || Ok(Grams(reads.fetch_add(1, Ordering::SeqCst) as f64))
+}
+
+/// Lets the sampler overrun a buffer of two, then drains what survived.
+async fn overrun(policy: OverflowPolicy) -> (Vec<Result<u64, ScaleError>>, WeightStream) {
+    let reads = Arc::default();
+    let mut stream = WeightStream::spawn_with_policy(counting_reader(&reads), INTERVAL, 2, policy);
+    tokio::time::sleep(INTERVAL * 20).await;
+    stream.stopper().stop();
+    let items = (&mut stream)
+        .map(|item| item.map(|sample| sample.sequence))
+        .collect()
+        .await;
+    (items, stream)
+}
+
+#[tokio::test]
+async fn stream_drop_oldest_keeps_latest_samples() {
+    let (items, stream) = overrun(OverflowPolicy::DropOldest).await;
+    assert!(stream.dropped() > 0);
+    let first = *items[0].as_ref().unwrap();
+    assert!(first > 0);
+}
+
+#[tokio::test]
+async fn stream_drop_newest_keeps_earliest_samples() {
+    let (items, stream) = overrun(OverflowPolicy::DropNewest).await;
+    assert!(stream.dropped() > 0);
+    assert_eq!(*items[0].as_ref().unwrap(), 0);
+    assert_eq!(*items[1].as_ref().unwrap(), 1);
+}
+
+#[tokio::test]
+async fn stream_error_policy_ends_with_overflow() {
+    let (items, stream) = overrun(OverflowPolicy::Error).await;
+    assert_eq!(items.len(), 3);
+    assert_eq!(*items[0].as_ref().unwrap(), 0);
+    assert_eq!(*items[1].as_ref().unwrap(), 1);
+    assert!(matches!(
+        items[2],
+        Err(ScaleError::Overflow { capacity: 2 })
+    ));
+    assert_eq!(stream.dropped(), 1);
+}
+
+#[tokio::test]
+async fn stream_block_never_stalls_sampling() {
+    let reads = Arc::new(AtomicUsize::new(0));
+    let mut stream = WeightStream::spawn_with_policy(
+        counting_reader(&reads),
+        INTERVAL,
+        1,
+        OverflowPolicy::Block {
+            timeout: Duration::from_secs(3600),
+        },
+    );
+    // A consumer that takes one sample every ten intervals.
+    let start = Instant::now();
+    for _ in 0..3 {
+        stream.next().await.unwrap().unwrap();
+        tokio::time::sleep(INTERVAL * 10).await;
+    }
+    let elapsed = start.elapsed();
+    // Each blocked push waits one interval at most, so reads carry on at
+    // roughly half speed instead of stopping for the hour-long timeout.
+    let expected = (elapsed.as_millis() / INTERVAL.as_millis()) as usize;
+    assert!(reads.load(Ordering::SeqCst) >= expected / 4);
+    assert!(stream.dropped() > 0);
+    assert_eq!(stream.stats().queued, 1);
+}
+
+/// Takes long enough per read for the command queue to back up.
+struct SlowScale;
+
+impl Scale for SlowScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        std::thread::sleep(Duration::from_millis(100));
+        Ok(Grams(1.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+}
+
+fn slow_actor(policy: OverflowPolicy) -> ScaleHandle {
+    let (handle, _task) = spawn_scale_actor_with_config(
+        SlowScale,
+        ActorConfig {
+            queue_depth: 1,
+            overflow_policy: policy,
+            ..Default::default()
+        },
+    );
+    handle
+}
+
+/// Sends three reads to an actor that only has room for one in flight and
+/// one queued, so the third overflows.
+async fn overfill(handle: &ScaleHandle) -> [ScaleResponse; 3] {
+    let in_flight = tokio::spawn({
+        let handle = handle.clone();
+        async move { handle.send(ScaleCmd::GetWeight).await }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let queued = tokio::spawn({
+        let handle = handle.clone();
+        async move { handle.send(ScaleCmd::GetWeight).await }
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(handle.stats().queued, 1);
+    let third = handle.send(ScaleCmd::GetWeight).await;
+    [in_flight.await.unwrap(), queued.await.unwrap(), third]
+}
+
+#[tokio::test]
+async fn actor_drop_newest_refuses_new_command() {
+    let handle = slow_actor(OverflowPolicy::DropNewest);
+    let [first, second, third] = overfill(&handle).await;
+    assert!(matches!(first, ScaleResponse::Weight(_)));
+    assert!(matches!(second, ScaleResponse::Weight(_)));
+    assert!(matches!(third, ScaleResponse::Error(_)));
+    assert_eq!(handle.stats().dropped, 1);
+}
+
+#[tokio::test]
+async fn actor_error_policy_refuses_new_command() {
+    let handle = slow_actor(OverflowPolicy::Error);
+    let [_, second, third] = overfill(&handle).await;
+    assert!(matches!(second, ScaleResponse::Weight(_)));
+    assert!(matches!(third, ScaleResponse::Error(_)));
+    assert_eq!(handle.stats().dropped, 1);
+}
+
+#[tokio::test]
+async fn actor_drop_oldest_evicts_queued_command() {
+    let handle = slow_actor(OverflowPolicy::DropOldest);
+    let [first, second, third] = overfill(&handle).await;
+    assert!(matches!(first, ScaleResponse::Weight(_)));
+    assert!(matches!(second, ScaleResponse::Error(_)));
+    assert!(matches!(third, ScaleResponse::Weight(_)));
+    assert_eq!(handle.stats().dropped, 1);
+}
+
+#[tokio::test]
+async fn actor_block_times_out() {
+    let handle = slow_actor(OverflowPolicy::Block {
+        timeout: Duration::from_millis(10),
+    });
+    let [_, second, third] = overfill(&handle).await;
+    assert!(matches!(second, ScaleResponse::Weight(_)));
+    assert!(matches!(third, ScaleResponse::Error(_)));
+    assert_eq!(handle.stats().dropped, 1);
+}
+
+#[tokio::test]
+async fn actor_block_waits_for_room() {
+    let handle = slow_actor(OverflowPolicy::Block {
+        timeout: Duration::from_secs(5),
+    });
+    let responses = overfill(&handle).await;
+    assert!(responses
+        .iter()
+        .all(|response| matches!(response, ScaleResponse::Weight(_))));
+    assert_eq!(handle.stats().dropped, 0);
+}
@@ -0,0 +1,318 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use libra::breaker::{CircuitBreaker, CircuitBreakerScale};
+use libra::calibration::{Calibration, RawScale};
+use libra::cancel::CancelFlag;
+use libra::health::{check, HealthConfig, Status};
+use libra::scale::{ScaleError, NUMBER_OF_INPUTS};
+use libra::shared::SharedScale;
+use libra::watchdog::{Watchdog, WatchdogScale};
+use libra::{median, Grams, MedianGrams, Scale, ScaleErrorKind};
+
+const BAD_VALUES: [f64; 3] = [f64::NAN, f64::INFINITY, f64::NEG_INFINITY];
+
+/// Reads `value` from every load cell and as the weight, failing every call
+/// numbered a multiple of `fail_every`, counting from 1.
+struct ChaosScale {
+    value: f64,
+    fail_every: Option<u64>,
+    calls: AtomicU64,
+}
+
+impl ChaosScale {
+    fn new(value: f64) -> Self {
+        Self {
+            value,
+            fail_every: None,
+            calls: AtomicU64::new(0),
+        }
+    }
+
+    fn failing_every(fail_every: u64) -> Self {
+        Self {
+            fail_every: Some(fail_every),
+            ..Self::new(10.)
+        }
+    }
+
+    fn call(&self) -> Result<(), ScaleError> {
+        let call = self.calls.fetch_add(1, Ordering::Relaxed) + 1;
+        match self.fail_every {
+            Some(every) if call.is_multiple_of(every) => Err(ScaleError::Busy),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Scale for ChaosScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.call()?;
+        Ok(Grams(self.value))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_median_weight_of(5)
+    }
+
+    fn get_raw_readings(
+        &self,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        self.call()?;
+        Ok([self.value; NUMBER_OF_INPUTS])
+    }
+}
+
+impl RawScale for ChaosScale {
+    fn get_raw_readings(&self) -> Result<[f64; NUMBER_OF_INPUTS], ScaleError> {
+        self.call()?;
+        Ok([self.value; NUMBER_OF_INPUTS])
+    }
+}
+
+/// Runs `op`, failing the test if it panics.
+fn no_panic<T>(what: &str, op: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(op)).unwrap_or_else(|_| panic!("{what} panicked"))
+}
+
+type MedianResult = Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Every median of `scale`, through the trait and each wrapper, for
+/// `samples` samples.
+fn medians(
+    scale: impl Scale + Send + 'static,
+    samples: usize,
+) -> Vec<(&'static str, MedianResult)> {
+    let shared = SharedScale::new(scale);
+    let cancel = CancelFlag::new();
+    let mut results = vec![
+        ("get_median_weight_of", shared.get_median_weight_of(samples)),
+        (
+            "get_median_weight_cancellable",
+            shared.get_median_weight_cancellable(samples, &cancel),
+        ),
+        (
+            "get_median_weight_yielding",
+            shared.get_median_weight_yielding(samples, &cancel, &mut || {}),
+        ),
+    ];
+    let watched = WatchdogScale::new(shared.clone(), Watchdog::default())
+        .with_sample_interval(Duration::ZERO);
+    results.push(("WatchdogScale", watched.get_median_weight_of(samples)));
+    let breaker = CircuitBreakerScale::new(shared, CircuitBreaker::default());
+    results.push(("CircuitBreakerScale", breaker.get_median_weight_of(samples)));
+    results
+}
+
+#[test]
+fn non_finite_readings_fail_every_median() {
+    for value in BAD_VALUES {
+        for (what, result) in no_panic("a median", || medians(ChaosScale::new(value), 3)) {
+            let error = result.expect_err(what);
+            let kind = libra::ScaleErrorInfo::from_dyn(&*error).kind;
+            assert_eq!(
+                kind,
+                ScaleErrorKind::NonFinite,
+                "{what} of {value}: {error}"
+            );
+        }
+    }
+}
+
+#[test]
+fn no_samples_is_an_error() {
+    for (what, result) in no_panic("a median of nothing", || medians(ChaosScale::new(10.), 0)) {
+        let error = result.expect_err(what);
+        assert!(
+            matches!(
+                error.downcast_ref::<ScaleError>(),
+                Some(ScaleError::InvalidArgument(_))
+            ),
+            "{what}: {error}"
+        );
+    }
+}
+
+#[test]
+fn failures_on_any_call_are_errors() {
+    for fail_every in 1..=5 {
+        for (what, result) in no_panic("a failing median", || {
+            medians(ChaosScale::failing_every(fail_every), 5)
+        }) {
+            assert!(result.is_err(), "{what} failing every {fail_every}");
+        }
+    }
+}
+
+#[test]
+fn median_is_total() {
+    assert!(no_panic("median of nothing", || median(&mut [])).0.is_nan());
+    let mut weights = [Grams(f64::NAN), Grams(2.), Grams(1.)];
+    let middle = no_panic("median of NaNs", || median(&mut weights));
+    assert_eq!(middle, MedianGrams(2.));
+}
+
+#[test]
+fn non_finite_raw_readings_make_a_scale_unusable() {
+    let config = HealthConfig {
+        sample_interval: Duration::ZERO,
+        ..HealthConfig::default()
+    };
+    for value in BAD_VALUES {
+        let report = no_panic("a health check", || {
+            check(&ChaosScale::new(value), 4, None, None, &config)
+        })
+        .unwrap();
+        assert_eq!(report.status, Status::Unusable, "{value}: {report}");
+        assert_eq!(report.failed_reads, 4);
+        assert_eq!(report.last_error.unwrap().kind, ScaleErrorKind::NonFinite);
+    }
+    for samples in [0, 1] {
+        assert!(check(&ChaosScale::new(10.), samples, None, None, &config).is_err());
+    }
+}
+
+#[test]
+fn bad_calibrations_are_refused() {
+    for value in BAD_VALUES {
+        let bad = [
+            Calibration {
+                offset: value,
+                coefficients: [1.; NUMBER_OF_INPUTS],
+            },
+            Calibration {
+                offset: 0.,
+                coefficients: [1., value, 1., 1.],
+            },
+        ];
+        for calibration in bad {
+            assert!(matches!(
+                calibration.validate(),
+                Err(ScaleError::InvalidCoefficients)
+            ));
+        }
+    }
+    for coefficients in ["[1, 2, 3]", "[1, 2, 3, 4, 5]", "[]"] {
+        let json = format!(r#"{{"offset": 0, "coefficients": {coefficients}}}"#);
+        assert!(
+            serde_json::from_str::<Calibration>(&json).is_err(),
+            "{json}"
+        );
+    }
+}
+
+#[cfg(feature = "tokio")]
+mod actor {
+    use super::*;
+    use libra::actor::spawn_scale_actor;
+    use libra::{ScaleCmd, ScaleResponse};
+
+    /// Every command that reads the scale or changes its calibration.
+    fn commands() -> Vec<ScaleCmd> {
+        let nan = Calibration {
+            offset: f64::NAN,
+            coefficients: [1.; NUMBER_OF_INPUTS],
+        };
+        vec![
+            ScaleCmd::GetWeight,
+            ScaleCmd::GetMedianWeight { samples: 0 },
+            ScaleCmd::GetMedianWeight { samples: 3 },
+            ScaleCmd::GetWeightBatch {
+                count: 3,
+                interval_ms: 0,
+            },
+            ScaleCmd::GetRawReadings,
+            ScaleCmd::GetRawMedians { samples: 0 },
+            ScaleCmd::SetCalibration(nan),
+        ]
+    }
+
+    #[tokio::test]
+    async fn the_actor_answers_bad_readings_with_errors() {
+        for value in BAD_VALUES {
+            let (handle, task) = spawn_scale_actor(ChaosScale::new(value));
+            for cmd in commands() {
+                let response = handle.send(cmd.clone()).await;
+                assert!(
+                    matches!(response, ScaleResponse::Error(_)),
+                    "{cmd:?} of {value}: {response:?}"
+                );
+            }
+            assert!(matches!(
+                handle.send(ScaleCmd::Shutdown).await,
+                ScaleResponse::ShutdownAck
+            ));
+            assert!(task.await.is_ok(), "the actor panicked");
+        }
+    }
+
+    #[tokio::test]
+    async fn the_actor_survives_failures_on_any_call() {
+        for fail_every in 1..=3 {
+            let (handle, task) = spawn_scale_actor(ChaosScale::failing_every(fail_every));
+            for cmd in commands() {
+                let _ = handle.send(cmd).await;
+            }
+            handle.send(ScaleCmd::Shutdown).await;
+            assert!(task.await.is_ok(), "the actor panicked");
+        }
+    }
+
+    #[tokio::test]
+    async fn async_medians_refuse_bad_readings() {
+        use tokio_util::sync::CancellationToken;
+
+        let token = CancellationToken::new();
+        for value in BAD_VALUES {
+            let shared = SharedScale::new(ChaosScale::new(value));
+            let error = shared
+                .get_median_weight_with_token(2, &token)
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<ScaleError>(),
+                Some(ScaleError::NonFinite { channel: None })
+            ));
+        }
+        let shared = SharedScale::new(ChaosScale::new(10.));
+        assert!(shared
+            .get_median_weight_with_token(0, &token)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn calibration_fails_on_non_finite_readings() {
+        use libra::calibration::{calibrate, CalibrationPlan, CalibrationProgress};
+        use tokio::sync::mpsc;
+        use tokio_util::sync::CancellationToken;
+
+        for value in BAD_VALUES {
+            let scale = SharedScale::new(ChaosScale::new(value));
+            let plan = CalibrationPlan {
+                zero_samples: 3,
+                reference_masses: vec![Grams(500.)],
+                span_samples: 3,
+                sample_interval: Duration::from_millis(1),
+                settle_window: 2,
+                settle_tolerance: 1e-6,
+                settle_timeout: Duration::from_secs(1),
+            };
+            let (tx, mut rx) = mpsc::channel(8);
+            let operator = tokio::spawn(async move {
+                while let Some(event) = rx.recv().await {
+                    if let CalibrationProgress::AwaitingOperator { ready, .. } = event {
+                        ready.confirm();
+                    }
+                }
+            });
+            let result = calibrate(&scale, plan, tx, &CancellationToken::new()).await;
+            assert!(
+                matches!(result, Err(ScaleError::NonFinite { channel: Some(0) })),
+                "{value}: {result:?}"
+            );
+            operator.await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,363 @@
+#![cfg(feature = "binary-proto")]
+
+use std::io::{self, Cursor};
+use std::time::{Duration, UNIX_EPOCH};
+
+use libra::binary::{read_frame, write_frame, DecodeError, MAX_MESSAGE_LEN};
+use libra::calibration::Calibration;
+use libra::health::{
+    CalibrationExpiry, ChannelHealth, FleetStatus, HealthReport, ScaleHealth, Status,
+};
+use libra::{
+    Grams, ListedScale, MedianGrams, ScaleCmd, ScaleErrorInfo, ScaleErrorKind, ScaleResponse,
+    ScaleState, ScaleStatus, StampedWeight,
+};
+
+/// Encoded bytes for every variant, one `cmd` or `response` line per value.
+/// Existing lines must keep decoding; add lines for new variants.
+const VECTORS: &str = include_str!("vectors/postcard.txt");
+
+const CALIBRATION: Calibration = Calibration {
+    offset: 1.5,
+    coefficients: [2.; 4],
+};
+
+fn commands() -> Vec<(&'static str, ScaleCmd)> {
+    vec![
+        ("GetWeight", ScaleCmd::GetWeight),
+        ("GetMedianWeight", ScaleCmd::GetMedianWeight { samples: 10 }),
+        (
+            "GetWeightBatch",
+            ScaleCmd::GetWeightBatch {
+                count: 300,
+                interval_ms: 20,
+            },
+        ),
+        ("Shutdown", ScaleCmd::Shutdown),
+        ("Tare", ScaleCmd::Tare { samples: 3 }),
+        ("Zero", ScaleCmd::Zero { samples: 3 }),
+        ("GetRawReadings", ScaleCmd::GetRawReadings),
+        ("GetRawMedians", ScaleCmd::GetRawMedians { samples: 7 }),
+        ("SetCalibration", ScaleCmd::SetCalibration(CALIBRATION)),
+        ("GetCalibration", ScaleCmd::GetCalibration),
+        ("GetStatus", ScaleCmd::GetStatus),
+        ("Hello", ScaleCmd::Hello { client_version: 1 }),
+        (
+            "Auth",
+            ScaleCmd::Auth {
+                token: "secret".into(),
+            },
+        ),
+        ("ListScales", ScaleCmd::ListScales),
+        (
+            "SetDataInterval",
+            ScaleCmd::SetDataInterval { interval_ms: 8 },
+        ),
+    ]
+}
+
+fn responses() -> Vec<(&'static str, ScaleResponse)> {
+    vec![
+        ("Weight", ScaleResponse::Weight(Grams(12.5))),
+        (
+            "MedianWeight",
+            ScaleResponse::MedianWeight(MedianGrams(-3.)),
+        ),
+        (
+            "WeightBatch",
+            ScaleResponse::WeightBatch(vec![StampedWeight {
+                weight: Grams(1.),
+                sequence: 7,
+                timestamp: UNIX_EPOCH + Duration::new(1_700_000_000, 500),
+            }]),
+        ),
+        (
+            "RawReadings",
+            ScaleResponse::RawReadings([0.1, 0.2, 0.3, 0.4]),
+        ),
+        (
+            "Error",
+            ScaleResponse::Error(ScaleErrorInfo::new(ScaleErrorKind::Busy, "Scale is busy")),
+        ),
+        (
+            "PhidgetError",
+            ScaleResponse::Error(ScaleErrorInfo {
+                load_cell: Some(3),
+                return_code: Some(52),
+                ..ScaleErrorInfo::new(ScaleErrorKind::Phidget, "Detached")
+            }),
+        ),
+        (
+            "InternalError",
+            ScaleResponse::InternalError("Scale panicked: oops".into()),
+        ),
+        ("ShutdownAck", ScaleResponse::ShutdownAck),
+        ("ShuttingDown", ScaleResponse::ShuttingDown),
+        ("Expired", ScaleResponse::Expired),
+        ("Tared", ScaleResponse::Tared(Grams(250.))),
+        ("Zeroed", ScaleResponse::Zeroed(CALIBRATION)),
+        ("RawMedians", ScaleResponse::RawMedians([0.5; 4])),
+        ("CalibrationSet", ScaleResponse::CalibrationSet),
+        ("Calibration", ScaleResponse::Calibration(CALIBRATION)),
+        (
+            "Status",
+            ScaleResponse::Status(ScaleStatus {
+                phidget_id: 716_000,
+                attached: true,
+                calibration: CALIBRATION,
+                tare: Grams(0.),
+                calibration_expires_in_days: Some(12),
+            }),
+        ),
+        (
+            "HelloAck",
+            ScaleResponse::HelloAck {
+                server_version: 1,
+                supported_commands: vec!["GetWeight".into(), "Hello".into()],
+            },
+        ),
+        (
+            "Unsupported",
+            ScaleResponse::Unsupported {
+                command: "Frobnicate".into(),
+            },
+        ),
+        ("Authenticated", ScaleResponse::Authenticated),
+        (
+            "Heartbeat",
+            ScaleResponse::Heartbeat {
+                uptime: Duration::new(90, 500),
+                last_reading_age: Some(Duration::from_millis(250)),
+            },
+        ),
+        (
+            "Scales",
+            ScaleResponse::Scales(vec![
+                ListedScale {
+                    name: "flour".into(),
+                    state: ScaleState::Connected,
+                    data_interval_ms: Some(8),
+                },
+                ListedScale {
+                    name: "rice".into(),
+                    state: ScaleState::Disconnected,
+                    data_interval_ms: None,
+                },
+            ]),
+        ),
+        (
+            "UnknownScale",
+            ScaleResponse::UnknownScale {
+                name: Some("sugar".into()),
+                available: vec!["flour".into(), "rice".into()],
+            },
+        ),
+        (
+            "DataIntervalSet",
+            ScaleResponse::DataIntervalSet { interval_ms: 8 },
+        ),
+        ("FleetStatus", ScaleResponse::FleetStatus(fleet_status())),
+    ]
+}
+
+fn unhex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|at| u8::from_str_radix(&hex[at..at + 2], 16).unwrap())
+        .collect()
+}
+
+/// The vectors of type `kind`, by name.
+fn vectors(kind: &str) -> Vec<(&'static str, Vec<u8>)> {
+    VECTORS
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.is_empty())
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+            (fields.next() == Some(kind))
+                .then(|| (fields.next().unwrap(), unhex(fields.next().unwrap())))
+        })
+        .collect()
+}
+
+#[test]
+fn every_command_matches_its_vector() {
+    let vectors = vectors("cmd");
+    for (name, cmd) in commands() {
+        let (_, bytes) = vectors
+            .iter()
+            .find(|(vector, _)| *vector == name)
+            .unwrap_or_else(|| panic!("no vector for {name}"));
+        assert_eq!(ScaleCmd::from_postcard(bytes), Ok(cmd.clone()), "{name}");
+        assert_eq!(&cmd.to_postcard(), bytes, "{name}");
+    }
+    assert_eq!(vectors.len(), commands().len());
+}
+
+#[test]
+fn every_response_matches_its_vector() {
+    let vectors = vectors("response");
+    for (name, response) in responses() {
+        let (_, bytes) = vectors
+            .iter()
+            .find(|(vector, _)| *vector == name)
+            .unwrap_or_else(|| panic!("no vector for {name}"));
+        assert_eq!(
+            ScaleResponse::from_postcard(bytes),
+            Ok(response.clone()),
+            "{name}"
+        );
+        assert_eq!(&response.to_postcard(), bytes, "{name}");
+    }
+    assert_eq!(vectors.len(), responses().len());
+}
+
+#[test]
+fn postcard_is_smaller_than_json() {
+    for (name, cmd) in commands() {
+        let json = serde_json::to_vec(&cmd).unwrap();
+        assert!(cmd.to_postcard().len() < json.len(), "{name}");
+    }
+}
+
+#[test]
+fn trailing_bytes_are_rejected() {
+    let mut bytes = ScaleCmd::GetWeight.to_postcard();
+    bytes.push(0);
+    assert_eq!(
+        ScaleCmd::from_postcard(&bytes),
+        Err(DecodeError::TrailingBytes(1))
+    );
+    assert!(ScaleCmd::from_postcard(&[]).is_err());
+    assert!(ScaleCmd::from_postcard(&[0xFF]).is_err());
+}
+
+#[test]
+fn frames_round_trip_over_a_stream() {
+    let mut stream = Vec::new();
+    for (_, cmd) in commands() {
+        write_frame(&mut stream, &cmd.to_postcard()).unwrap();
+    }
+    let mut reader = Cursor::new(stream);
+    let mut decoded = Vec::new();
+    while let Some(frame) = read_frame(&mut reader).unwrap() {
+        decoded.push(ScaleCmd::from_postcard(&frame).unwrap());
+    }
+    let expected: Vec<_> = commands().into_iter().map(|(_, cmd)| cmd).collect();
+    assert_eq!(decoded, expected);
+}
+
+#[test]
+fn bad_frames_are_errors() {
+    let error = write_frame(&mut Vec::new(), &vec![0; MAX_MESSAGE_LEN + 1]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+    let mut stream = Vec::new();
+    write_frame(&mut stream, &ScaleCmd::GetStatus.to_postcard()).unwrap();
+    stream.extend([0x00, 0x05, 0x01]);
+    let mut reader = Cursor::new(stream);
+    assert!(read_frame(&mut reader).unwrap().is_some());
+    let error = read_frame(&mut reader).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+/// xorshift64, so the noise is the same on every run.
+struct Noise(u64);
+
+impl Noise {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[test]
+fn random_bytes_never_panic() {
+    let mut noise = Noise(0x5eed);
+    for _ in 0..50_000 {
+        let len = (noise.next() % 48) as usize;
+        let bytes: Vec<u8> = (0..len).map(|_| noise.next() as u8).collect();
+        let _ = ScaleCmd::from_postcard(&bytes);
+        let _ = ScaleResponse::from_postcard(&bytes);
+        let _ = read_frame(&mut Cursor::new(bytes));
+    }
+}
+
+#[test]
+fn mutated_vectors_never_panic() {
+    let mut noise = Noise(42);
+    let vectors: Vec<_> = vectors("cmd")
+        .into_iter()
+        .chain(vectors("response"))
+        .map(|(_, bytes)| bytes)
+        .collect();
+    for _ in 0..20_000 {
+        let mut bytes = vectors[(noise.next() % vectors.len() as u64) as usize].clone();
+        for _ in 0..1 + noise.next() % 3 {
+            let at = (noise.next() % bytes.len() as u64) as usize;
+            bytes[at] = noise.next() as u8;
+        }
+        let cut = (noise.next() % (bytes.len() as u64 + 1)) as usize;
+        let _ = ScaleCmd::from_postcard(&bytes[..cut]);
+        let _ = ScaleResponse::from_postcard(&bytes[..cut]);
+    }
+}
+
+/// A fleet of a scale with a noisy load cell, and one that did not answer
+/// in time.
+fn fleet_status() -> FleetStatus {
+    let noise = |channel| {
+        if channel == 2 {
+            0.5f64.powi(20)
+        } else {
+            0.5f64.powi(23)
+        }
+    };
+    FleetStatus::new(vec![
+        ScaleHealth {
+            name: "flour".into(),
+            state: ScaleState::Connected,
+            health: Ok(HealthReport {
+                status: Status::Degraded,
+                reasons: vec!["Load Cell 2 is noisy (9.537e-7)".into()],
+                channels: std::array::from_fn(|channel| ChannelHealth {
+                    channel,
+                    attached: true,
+                    noise: Some(noise(channel)),
+                }),
+                reads: 5,
+                failed_reads: 0,
+                stale: None,
+                calibration_age: Some(Duration::from_secs(3 * 24 * 60 * 60)),
+                calibration_expiry: Some(CalibrationExpiry::Valid {
+                    remaining: Duration::from_secs(12 * 24 * 60 * 60),
+                }),
+                calibration_expires_in_days: Some(12),
+                calibration_future_dated: false,
+                last_error: None,
+            }),
+            last_weight: Some(StampedWeight {
+                weight: Grams(1000.),
+                sequence: 7,
+                timestamp: UNIX_EPOCH + Duration::new(1_700_000_000, 500),
+            }),
+            last_weight_age: Some(Duration::from_millis(250)),
+            reconnects: 1,
+            calibration_expires_in_days: Some(12),
+        },
+        ScaleHealth {
+            name: "rice".into(),
+            state: ScaleState::Disconnected,
+            health: Err(ScaleErrorInfo::new(
+                ScaleErrorKind::Timeout,
+                "No answer within 2s",
+            )),
+            last_weight: None,
+            last_weight_age: None,
+            reconnects: 0,
+            calibration_expires_in_days: None,
+        },
+    ])
+}
@@ -0,0 +1,185 @@
+#![cfg(all(feature = "net", unix))]
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use libra::actor::spawn_scale_actor;
+use libra::net::{serve_listener, ServerConfig};
+use libra::unix::{serve_unix, ServerInfo, UnixScaleClient};
+use libra::{Grams, MedianGrams, Scale, ScaleCmd, PROTOCOL_VERSION};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream, UnixListener};
+
+struct MockScale;
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(10.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(MedianGrams(10.))
+    }
+
+    fn tare(&mut self, _samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(10.))
+    }
+}
+
+fn socket_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("libra-{}-{name}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// Sends each line to a new server as written and returns the raw answers,
+/// the way a client built against an older libra would see them.
+async fn exchange(lines: &[&str]) -> Vec<Value> {
+    let (handle, _task) = spawn_scale_actor(MockScale);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve_listener(handle, listener, ServerConfig::default()));
+
+    let (read, mut write) = TcpStream::connect(addr).await.unwrap().into_split();
+    let mut answers = BufReader::new(read).lines();
+    let mut responses = Vec::new();
+    for line in lines {
+        write
+            .write_all(format!("{line}\n").as_bytes())
+            .await
+            .unwrap();
+        let answer = answers
+            .next_line()
+            .await
+            .unwrap()
+            .expect("connection closed");
+        responses.push(serde_json::from_str(&answer).unwrap());
+    }
+    responses
+}
+
+/// A server from before the handshake: it knows `GetWeight` and nothing
+/// newer, and calls anything else an invalid command.
+fn old_server(path: &PathBuf) {
+    let listener = UnixListener::bind(path).unwrap();
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let (read, mut write) = stream.into_split();
+                let mut lines = BufReader::new(read).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let answer = match line.as_str() {
+                        "\"GetWeight\"" => json!({"Weight": 5.0}),
+                        _ => json!({"Error": {
+                            "kind": "InvalidCommand",
+                            "message": "Invalid command: unknown variant",
+                        }}),
+                    };
+                    let answer = format!("{answer}\n");
+                    if write.write_all(answer.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+}
+
+#[tokio::test]
+async fn old_clients_are_still_understood() {
+    let answers = exchange(&[
+        "\"GetWeight\"",
+        "{\"GetMedianWeight\":{\"samples\":3}}",
+        "{\"Tare\":{\"samples\":3}}",
+    ])
+    .await;
+    assert_eq!(answers[0], json!({"Weight": 10.}));
+    assert_eq!(answers[1], json!({"MedianWeight": 10.}));
+    assert_eq!(answers[2], json!({"Tared": 10.}));
+}
+
+#[tokio::test]
+async fn hello_is_acknowledged_with_the_commands() {
+    let answers = exchange(&["{\"Hello\":{\"client_version\":1}}"]).await;
+    let ack = &answers[0]["HelloAck"];
+    assert_eq!(ack["server_version"], PROTOCOL_VERSION);
+    let commands = ack["supported_commands"].as_array().unwrap();
+    assert!(commands.contains(&json!("GetWeightBatch")));
+    assert!(commands.contains(&json!("Hello")));
+    assert_eq!(commands.len(), ScaleCmd::NAMES.len());
+}
+
+#[tokio::test]
+async fn unknown_commands_are_unsupported() {
+    let answers = exchange(&[
+        "{\"Frobnicate\":{\"speed\":11}}",
+        "\"Frobnicate\"",
+        "{\"Tare\":{\"samples\":\"three\"}}",
+        "not json",
+    ])
+    .await;
+    assert_eq!(
+        answers[0],
+        json!({"Unsupported": {"command": "Frobnicate"}})
+    );
+    assert_eq!(
+        answers[1],
+        json!({"Unsupported": {"command": "Frobnicate"}})
+    );
+    // Known commands with bad payloads are still invalid.
+    assert_eq!(answers[2]["Error"]["kind"], "InvalidCommand");
+    assert_eq!(answers[3]["Error"]["kind"], "InvalidCommand");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn new_clients_negotiate_with_new_servers() {
+    let path = socket_path("hello-new");
+    let (handle, _task) = spawn_scale_actor(MockScale);
+    tokio::spawn(serve_unix(handle, path.clone()));
+    let client = UnixScaleClient::new(&path);
+    let info = loop {
+        match client.hello().await {
+            Ok(info) => break info,
+            Err(_) => tokio::time::sleep(Duration::from_millis(5)).await,
+        }
+    };
+    assert_eq!(info.version, PROTOCOL_VERSION);
+    assert!(info
+        .supported_commands
+        .iter()
+        .any(|c| c == "GetWeightBatch"));
+
+    let batch = client
+        .get_weight_batch(3, Duration::from_millis(1))
+        .await
+        .unwrap();
+    assert_eq!(batch.len(), 3);
+    assert!(batch.iter().all(|stamped| stamped.weight == Grams(10.)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn new_clients_fall_back_with_old_servers() {
+    let path = socket_path("hello-old");
+    old_server(&path);
+    let client = UnixScaleClient::new(&path);
+
+    assert_eq!(
+        client.hello().await.unwrap(),
+        ServerInfo {
+            version: 0,
+            supported_commands: Vec::new(),
+        }
+    );
+    for _ in 0..2 {
+        let batch = client
+            .get_weight_batch(3, Duration::from_millis(1))
+            .await
+            .unwrap();
+        let sequences: Vec<_> = batch.iter().map(|stamped| stamped.sequence).collect();
+        assert_eq!(sequences, [0, 1, 2]);
+        assert!(batch.iter().all(|stamped| stamped.weight == Grams(5.)));
+        assert!(batch.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+    }
+}
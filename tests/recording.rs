@@ -0,0 +1,311 @@
+#![cfg(feature = "recording")]
+
+use std::io;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use libra::calibration::Calibration;
+use libra::recording::{
+    Recorder, Recording, RecordingError, RecordingHeader, ReplayScale, RECORDING_VERSION,
+};
+use libra::scale::ScaleError;
+use libra::scoped::ScopedSampler;
+use libra::{Grams, Scale, StampedReadings};
+
+const CALIBRATION: Calibration = Calibration {
+    offset: 12.5,
+    coefficients: [1000., 1010., 990., 1005.],
+};
+
+fn recording_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("libra-{}-{name}.jsonl", std::process::id()))
+}
+
+/// Load cell ratios that drift with `step`, different on every cell.
+fn ratios(step: u64) -> [f64; 4] {
+    let step = step as f64;
+    [
+        0.1 + step * 1e-4,
+        0.2 - step * 3e-5,
+        0.05 + step * 7e-6,
+        0.15,
+    ]
+}
+
+/// Stands in for the load cells: drifts on every read and fails every
+/// fifth one.
+#[derive(Default)]
+struct MockCells {
+    reads: u64,
+}
+
+impl MockCells {
+    fn read(&mut self) -> Result<[f64; 4], ScaleError> {
+        self.reads += 1;
+        if self.reads.is_multiple_of(5) {
+            return Err(ScaleError::IoError(io::ErrorKind::TimedOut.into()));
+        }
+        Ok(ratios(self.reads))
+    }
+}
+
+fn ended(error: Box<dyn std::error::Error + Send + Sync>) -> usize {
+    match error.downcast::<RecordingError>().map(|error| *error) {
+        Ok(RecordingError::Ended { snapshots }) => snapshots,
+        other => panic!("not the end of the recording: {other:?}"),
+    }
+}
+
+#[test]
+fn recorded_sessions_replay_the_same_weights() {
+    let path = recording_path("session");
+    let header = RecordingHeader {
+        tare: Grams(40.),
+        ..RecordingHeader::new(CALIBRATION)
+    };
+    let mut cells = MockCells::default();
+    thread::scope(|scope| {
+        let (sampler, samples) =
+            ScopedSampler::run_raw_with(|| cells.read(), Duration::from_millis(1), scope);
+        let recorder = Recorder::attach(samples, &path, header).unwrap();
+        thread::sleep(Duration::from_millis(60));
+        sampler.join();
+        recorder.join().unwrap();
+    });
+
+    let recording = Recording::read(&path).unwrap();
+    assert_eq!(recording.header, header);
+    let snapshots = &recording.snapshots;
+    assert!(snapshots.len() >= 10, "{}", snapshots.len());
+    // Every successful read, in order; the failed ones are left out.
+    let expected: Vec<_> = (1..=cells.reads)
+        .filter(|read| !read.is_multiple_of(5))
+        .map(ratios)
+        .collect();
+    let recorded: Vec<_> = snapshots.iter().map(|snapshot| snapshot.ratios).collect();
+    assert_eq!(recorded, expected);
+    assert!(snapshots.windows(2).all(|pair| pair[0].at <= pair[1].at));
+    for snapshot in snapshots {
+        let weight = CALIBRATION.weigh(&snapshot.ratios).get() - 40.;
+        assert_eq!(snapshot.weight, Grams(weight));
+    }
+
+    let replay = ReplayScale::from_recording(&path)
+        .unwrap()
+        .speed(f64::INFINITY);
+    for snapshot in snapshots {
+        assert_eq!(replay.get_weight().unwrap(), snapshot.weight);
+    }
+    assert_eq!(replay.remaining(), 0);
+    assert_eq!(ended(replay.get_weight().unwrap_err()), snapshots.len());
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// Writes a recording of `count` snapshots `step` apart.
+fn spaced_recording(name: &str, count: u64, step: Duration) -> PathBuf {
+    let path = recording_path(name);
+    let header = RecordingHeader {
+        started: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+        ..RecordingHeader::new(CALIBRATION)
+    };
+    let mut recorder = Recorder::create(&path, header).unwrap();
+    for sequence in 0..count {
+        recorder
+            .record(&StampedReadings {
+                ratios: ratios(sequence),
+                sequence,
+                timestamp: header.started + step * sequence as u32,
+            })
+            .unwrap();
+    }
+    recorder.finish().unwrap();
+    path
+}
+
+/// How long it takes to read every snapshot at `speed`.
+fn replay_time(path: &PathBuf, speed: f64) -> Duration {
+    let replay = ReplayScale::from_recording(path).unwrap().speed(speed);
+    let start = Instant::now();
+    while replay.remaining() > 0 {
+        replay.get_weight().unwrap();
+    }
+    start.elapsed()
+}
+
+#[test]
+fn replay_keeps_the_recorded_timing() {
+    let path = spaced_recording("timing", 6, Duration::from_millis(40));
+    let recording = Recording::read(&path).unwrap();
+    assert_eq!(recording.snapshots[5].at, Duration::from_millis(200));
+
+    assert!(replay_time(&path, 1.) >= Duration::from_millis(200));
+    let faster = replay_time(&path, 4.);
+    assert!(faster >= Duration::from_millis(50), "{faster:?}");
+    assert!(faster < Duration::from_millis(200), "{faster:?}");
+    assert!(replay_time(&path, f64::INFINITY) < Duration::from_millis(50));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn replays_behave_like_a_scale() {
+    let path = spaced_recording("scale", 20, Duration::ZERO);
+    let recording = Recording::read(&path).unwrap();
+    let mut replay = ReplayScale::new(recording.clone());
+
+    assert_eq!(replay.get_raw_readings().unwrap(), ratios(0));
+    assert_eq!(replay.calibration().unwrap(), CALIBRATION);
+    let status = replay.status().unwrap();
+    assert!(status.attached);
+    assert_eq!(status.calibration, CALIBRATION);
+
+    // Recalibrating changes the weights worked out from the same ratios.
+    let doubled = Calibration {
+        offset: 25.,
+        coefficients: CALIBRATION.coefficients.map(|c| c * 2.),
+    };
+    replay.set_calibration(doubled).unwrap();
+    let weight = replay.get_weight().unwrap().get();
+    assert_eq!(weight, recording.snapshots[1].weight.get() * 2.);
+
+    let tare = replay.tare(3).unwrap();
+    assert_eq!(
+        tare,
+        Grams(doubled.weigh(&recording.snapshots[3].ratios).get())
+    );
+    assert!(replay.get_weight().unwrap().get() > 0.);
+    assert_eq!(replay.remaining(), 14);
+
+    let median = replay.get_median_weight().unwrap();
+    assert!(median.get() > 0.);
+    assert_eq!(replay.remaining(), 4);
+    assert!(ended(replay.get_median_weight().unwrap_err()) == 20);
+    assert!(!replay.status().unwrap().attached);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn bad_recordings_are_refused() {
+    let path = recording_path("bad");
+    let header = serde_json::to_string(&libra::recording::Entry::Header(RecordingHeader::new(
+        CALIBRATION,
+    )))
+    .unwrap();
+    let future = header.replace(
+        &format!("\"version\":{RECORDING_VERSION}"),
+        "\"version\":99",
+    );
+    let snapshot = "{\"Snapshot\":{\"at\":{\"secs\":0,\"nanos\":0},\
+                    \"ratios\":[0.0,0.0,0.0,0.0],\"weight\":0.0}}";
+
+    for (text, check) in [
+        (
+            String::new(),
+            (|e| matches!(e, RecordingError::MissingHeader)) as fn(&RecordingError) -> bool,
+        ),
+        (format!("{snapshot}\n"), |e| {
+            matches!(e, RecordingError::MissingHeader)
+        }),
+        (format!("{future}\n"), |e| {
+            matches!(e, RecordingError::UnsupportedVersion(99))
+        }),
+        (format!("{header}\n{snapshot}\nnot json\n"), |e| {
+            matches!(e, RecordingError::Invalid { line: 3, .. })
+        }),
+        (format!("{header}\n{header}\n"), |e| {
+            matches!(e, RecordingError::Invalid { line: 2, .. })
+        }),
+    ] {
+        std::fs::write(&path, &text).unwrap();
+        let error = Recording::read(&path).unwrap_err();
+        assert!(check(&error), "{text:?}: {error}");
+    }
+
+    std::fs::write(&path, format!("{header}\n{snapshot}\n")).unwrap();
+    assert_eq!(Recording::read(&path).unwrap().snapshots.len(), 1);
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(Recording::read(&path), Err(RecordingError::Io(_))));
+}
+
+const CSV: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/vectors/replay.csv");
+
+#[test]
+fn csv_logs_replay_their_rows() {
+    let recording = Recording::read_csv(CSV, CALIBRATION).unwrap();
+    assert_eq!(recording.header.calibration, CALIBRATION);
+    assert_eq!(
+        recording.header.started,
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+    );
+    assert_eq!(recording.snapshots.len(), 6);
+    assert_eq!(recording.snapshots[0].at, Duration::ZERO);
+    assert_eq!(
+        recording.snapshots[1].ratios,
+        [0.1001, 0.19997, 0.050007, 0.15]
+    );
+    let at = recording.snapshots[5].at.as_secs_f64();
+    assert!((at - 0.2).abs() < 1e-6, "{at}");
+
+    let replay = ReplayScale::from_csv(CSV, CALIBRATION).unwrap();
+    assert_eq!(replay.get_raw_readings().unwrap(), [0.1, 0.2, 0.05, 0.15]);
+    assert_eq!(
+        replay.get_weight().unwrap(),
+        CALIBRATION.weigh(&recording.snapshots[1].ratios)
+    );
+    assert_eq!(replay.remaining(), 4);
+    while replay.remaining() > 0 {
+        replay.get_raw_readings().unwrap();
+    }
+    assert_eq!(ended(replay.get_raw_readings().unwrap_err()), 6);
+    assert_eq!(ended(replay.get_weight().unwrap_err()), 6);
+}
+
+#[test]
+fn csv_replays_are_paced_unless_asked_not_to() {
+    let time = |replay: ReplayScale| {
+        let start = Instant::now();
+        while replay.remaining() > 0 {
+            replay.get_raw_readings().unwrap();
+        }
+        start.elapsed()
+    };
+    let paced = time(ReplayScale::from_csv(CSV, CALIBRATION).unwrap());
+    assert!(paced >= Duration::from_millis(200), "{paced:?}");
+    let fast = time(
+        ReplayScale::from_csv(CSV, CALIBRATION)
+            .unwrap()
+            .as_fast_as_possible(),
+    );
+    assert!(fast < Duration::from_millis(50), "{fast:?}");
+}
+
+#[test]
+fn malformed_csv_rows_are_reported_by_line() {
+    let path = recording_path("bad.csv");
+    let good = "1.0,0.1,0.2,0.3,0.4";
+    for (text, bad_line) in [
+        (format!("seconds,a,b,c,d\n{good}\nseconds,a,b,c,d\n"), 3),
+        (format!("{good}\n1.5,0.1,0.2,0.3\n"), 2),
+        (format!("{good}\n\n1.5,0.1,0.2,0.3,0.4,0.5\n"), 3),
+        (format!("# a log\n{good}\n1.5,0.1,x,0.3,0.4\n"), 3),
+        (format!("{good}\n0.5,0.1,0.2,0.3,0.4\n"), 2),
+        ("-1,0.1,0.2,0.3,0.4\n".to_string(), 1),
+        ("inf,0.1,0.2,0.3,0.4\n".to_string(), 1),
+    ] {
+        std::fs::write(&path, &text).unwrap();
+        let error = Recording::read_csv(&path, CALIBRATION).unwrap_err();
+        assert!(
+            matches!(error, RecordingError::Invalid { line, .. } if line == bad_line),
+            "{text:?}: {error}"
+        );
+    }
+
+    std::fs::write(&path, "seconds,a,b,c,d\n").unwrap();
+    let replay = ReplayScale::from_csv(&path, CALIBRATION).unwrap();
+    assert_eq!(ended(replay.get_weight().unwrap_err()), 0);
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(
+        Recording::read_csv(&path, CALIBRATION),
+        Err(RecordingError::Io(_))
+    ));
+}
@@ -0,0 +1,96 @@
+#[cfg(feature = "hardware")]
+use std::time::Duration;
+
+use libra::registry::{is_claimed, SerialClaim};
+use libra::scale::ScaleError;
+#[cfg(feature = "hardware")]
+use libra::scale::{DisconnectedScale, NUMBER_OF_INPUTS};
+use libra::ScaleErrorKind;
+
+/// Short enough that opening a phidget that is not there fails quickly.
+#[cfg(feature = "hardware")]
+const TIMEOUT: Duration = Duration::from_millis(10);
+#[cfg(feature = "hardware")]
+const COEFFICIENTS: [f64; NUMBER_OF_INPUTS] = [1.; NUMBER_OF_INPUTS];
+
+// Each test claims its own serial numbers: the registry is shared by the
+// whole test process.
+
+#[test]
+fn a_serial_can_only_be_claimed_once_at_a_time() {
+    let claim = SerialClaim::claim(101).unwrap();
+    assert_eq!(claim.serial(), 101);
+    assert!(is_claimed(101));
+    let error = SerialClaim::claim(101).unwrap_err();
+    assert!(matches!(
+        error,
+        ScaleError::AlreadyConnected { serial: 101 }
+    ));
+    assert_eq!(error.kind(), ScaleErrorKind::AlreadyConnected);
+    assert!(!error.is_transient() && !error.is_disconnection());
+    assert_eq!(
+        error.to_string(),
+        "Phidget 101 is already connected to another scale"
+    );
+
+    // Other serials are unaffected.
+    let other = SerialClaim::claim(102).unwrap();
+
+    drop(claim);
+    assert!(!is_claimed(101));
+    let again = SerialClaim::claim(101).unwrap();
+    assert!(is_claimed(101) && is_claimed(other.serial()));
+    drop(again);
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn connecting_a_claimed_serial_fails_before_opening_anything() {
+    let claim = SerialClaim::claim(201).unwrap();
+    let error = DisconnectedScale::new(201)
+        .connect(0., COEFFICIENTS, TIMEOUT)
+        .err()
+        .unwrap();
+    assert!(matches!(
+        error,
+        ScaleError::AlreadyConnected { serial: 201 }
+    ));
+
+    drop(claim);
+    let error = DisconnectedScale::new(201)
+        .connect(0., COEFFICIENTS, TIMEOUT)
+        .err()
+        .unwrap();
+    assert!(
+        !matches!(error, ScaleError::AlreadyConnected { .. }),
+        "{error}"
+    );
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn a_failed_connect_releases_its_claim() {
+    // There is no phidget 301 to open.
+    assert!(DisconnectedScale::new(301)
+        .connect(0., COEFFICIENTS, TIMEOUT)
+        .is_err());
+    assert!(!is_claimed(301));
+    drop(SerialClaim::claim(301).unwrap());
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn shared_scales_skip_the_registry() {
+    let claim = SerialClaim::claim(401).unwrap();
+    let error = DisconnectedScale::new(401)
+        .shared()
+        .connect(0., COEFFICIENTS, TIMEOUT)
+        .err()
+        .unwrap();
+    assert!(
+        !matches!(error, ScaleError::AlreadyConnected { .. }),
+        "{error}"
+    );
+    assert!(is_claimed(401));
+    drop(claim);
+}
@@ -0,0 +1,213 @@
+use std::io;
+use std::time::Duration;
+
+use libra::retry::{RetryPolicy, Transient};
+use libra::scale::ScaleError;
+use libra::testing::ManualClock;
+use libra::{ScaleErrorInfo, ScaleErrorKind};
+
+fn doubling() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 5,
+        initial_backoff: Duration::from_millis(100),
+        max_backoff: Duration::from_millis(500),
+        multiplier: 2.,
+        jitter: 0.,
+    }
+}
+
+/// Runs `op` under `policy` on a manual clock, returning its result, the
+/// attempts it saw and the waits in between.
+fn run<T>(
+    policy: &RetryPolicy,
+    mut op: impl FnMut(u32) -> Result<T, ScaleError>,
+) -> (Result<T, ScaleError>, Vec<u32>, Vec<Duration>) {
+    let clock = ManualClock::new();
+    let mut attempts = Vec::new();
+    let mut started = Vec::new();
+    let result = policy.execute_with_clock(&clock, |attempt| {
+        attempts.push(attempt);
+        started.push(clock.elapsed());
+        op(attempt)
+    });
+    let sleeps = started.windows(2).map(|w| w[1] - w[0]).collect();
+    (result, attempts, sleeps)
+}
+
+#[test]
+fn sleeping_waits_out_the_backoff() {
+    let policy = RetryPolicy::fixed(3, Duration::from_millis(5));
+    let mut sleeps = Vec::new();
+    let result: Result<(), _> =
+        policy.execute_with_sleep(|wait| sleeps.push(wait), |_| Err(ScaleError::Busy));
+    assert!(result.is_err());
+    assert_eq!(sleeps, [Duration::from_millis(5); 2]);
+
+    let started = std::time::Instant::now();
+    let result: Result<(), _> = policy.execute(|_| Err(ScaleError::Busy));
+    assert!(result.is_err());
+    assert!(started.elapsed() >= Duration::from_millis(10));
+}
+
+#[test]
+fn backoff_grows_up_to_the_limit() {
+    let (result, attempts, sleeps) = run(&doubling(), |attempt| {
+        if attempt < 5 {
+            Err(ScaleError::Busy)
+        } else {
+            Ok(attempt)
+        }
+    });
+    assert_eq!(result.unwrap(), 5);
+    assert_eq!(attempts, [1, 2, 3, 4, 5]);
+    assert_eq!(sleeps, [100, 200, 400, 500].map(Duration::from_millis));
+    assert_eq!(doubling().backoff(1000), Duration::from_millis(500));
+}
+
+#[test]
+fn the_last_error_is_returned_once_attempts_run_out() {
+    let policy = RetryPolicy {
+        max_attempts: 3,
+        ..doubling()
+    };
+    let (result, attempts, sleeps) = run(&policy, |attempt| {
+        Err::<(), _>(ScaleError::Timeout {
+            elapsed: Duration::from_secs(1),
+            collected: attempt as usize,
+        })
+    });
+    assert!(matches!(
+        result,
+        Err(ScaleError::Timeout { collected: 3, .. })
+    ));
+    assert_eq!(attempts, [1, 2, 3]);
+    assert_eq!(sleeps.len(), 2);
+}
+
+#[test]
+fn permanent_errors_abort_at_once() {
+    let (result, attempts, sleeps) = run(&doubling(), |_| {
+        Err::<(), _>(ScaleError::InvalidArgument("samples".into()))
+    });
+    assert!(matches!(result, Err(ScaleError::InvalidArgument(_))));
+    assert_eq!(attempts, [1]);
+    assert!(sleeps.is_empty());
+
+    let (result, attempts, _) = run(&doubling(), |attempt| {
+        if attempt == 1 {
+            Err(ScaleError::Busy)
+        } else {
+            Err::<(), _>(ScaleError::Disconnected { channel: None })
+        }
+    });
+    assert!(matches!(result, Err(ScaleError::Disconnected { .. })));
+    assert_eq!(attempts, [1, 2]);
+}
+
+#[test]
+fn none_tries_once() {
+    let (result, attempts, sleeps) = run(&RetryPolicy::none(), |_| Err::<(), _>(ScaleError::Busy));
+    assert!(result.is_err());
+    assert_eq!(attempts, [1]);
+    assert!(sleeps.is_empty());
+
+    let never = RetryPolicy {
+        max_attempts: 0,
+        ..doubling()
+    };
+    assert_eq!(run(&never, |_| Err::<(), _>(ScaleError::Busy)).1, [1]);
+}
+
+#[test]
+fn default_transient_retries_briefly() {
+    let policy = RetryPolicy::default_transient();
+    let (result, attempts, sleeps) = run(&policy, |_| Err::<(), _>(ScaleError::Busy));
+    assert!(result.is_err());
+    assert_eq!(attempts, [1, 2, 3]);
+    for (sleep, backoff) in sleeps.into_iter().zip([100, 200]) {
+        let backoff = Duration::from_millis(backoff);
+        assert!(
+            sleep <= backoff && sleep >= backoff.mul_f64(0.9),
+            "{sleep:?}"
+        );
+    }
+}
+
+#[test]
+fn jitter_only_shortens_the_wait() {
+    let policy = RetryPolicy {
+        jitter: 0.5,
+        ..doubling()
+    };
+    for retry in 1..100 {
+        let backoff = policy.backoff(retry);
+        let jittered = policy.jittered_backoff(retry);
+        assert!(jittered <= backoff && jittered >= backoff / 2, "{retry}");
+    }
+}
+
+#[test]
+fn boxed_and_io_errors_are_classified() {
+    let boxed = |error: Box<dyn std::error::Error + Send + Sync>| error.is_transient();
+    assert!(boxed(Box::new(ScaleError::Busy)));
+    assert!(!boxed(Box::new(ScaleError::InvalidCoefficients)));
+    assert!(boxed(Box::new(io::Error::from(io::ErrorKind::TimedOut))));
+    assert!(!boxed(Box::new(io::Error::from(io::ErrorKind::NotFound))));
+    assert!(boxed(Box::new(ScaleErrorInfo::new(
+        ScaleErrorKind::QueueFull,
+        "Queue full"
+    ))));
+    assert!(!boxed("not a scale error".into()));
+    assert!(io::Error::from(io::ErrorKind::Interrupted).is_transient());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test(start_paused = true)]
+async fn async_retries_wait_on_the_tokio_clock() {
+    use tokio::time::Instant;
+
+    let started = Instant::now();
+    let mut waits = Vec::new();
+    let result = doubling()
+        .execute_async(|attempt| {
+            waits.push(started.elapsed());
+            async move {
+                if attempt < 4 {
+                    Err(ScaleError::Busy)
+                } else {
+                    Ok(attempt)
+                }
+            }
+        })
+        .await;
+    assert_eq!(result.unwrap(), 4);
+    assert_eq!(waits, [0, 100, 300, 700].map(Duration::from_millis));
+
+    let started = Instant::now();
+    let result: Result<(), _> = doubling()
+        .execute_async(|_| async { Err(ScaleError::Unsupported("Taring")) })
+        .await;
+    assert!(result.is_err());
+    assert_eq!(started.elapsed(), Duration::ZERO);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn async_retries_can_wait_on_a_manual_clock() {
+    let clock = ManualClock::new();
+    let mut waits = Vec::new();
+    let result = doubling()
+        .execute_async_with_clock(&clock, |attempt| {
+            waits.push(clock.elapsed());
+            async move {
+                if attempt < 5 {
+                    Err(ScaleError::Busy)
+                } else {
+                    Ok(attempt)
+                }
+            }
+        })
+        .await;
+    assert_eq!(result.unwrap(), 5);
+    assert_eq!(waits, [0, 100, 300, 700, 1200].map(Duration::from_millis));
+}
@@ -0,0 +1,498 @@
+use std::error::Error;
+use std::fs::File;
+use std::io;
+use std::time::{Duration, SystemTime};
+
+#[cfg(feature = "hardware")]
+use libra::failover::FailoverRole;
+#[cfg(feature = "hardware")]
+use libra::scale::all_channels;
+use libra::scale::{PhidgetError, PhidgetErrorKind, ScaleError};
+use libra::{Grams, MedianGrams, Scale, ScaleErrorInfo, ScaleErrorKind};
+#[cfg(feature = "hardware")]
+use phidget::ReturnCode;
+
+fn missing_file() -> ScaleError {
+    File::open("/nonexistent/libra/calibration.json")
+        .map_err(ScaleError::from)
+        .unwrap_err()
+}
+
+#[test]
+fn io_errors_keep_their_source() {
+    let error = missing_file();
+    let source = error.source().expect("no source");
+    let io = source
+        .downcast_ref::<io::Error>()
+        .expect("not an io::Error");
+    assert_eq!(io.kind(), io::ErrorKind::NotFound);
+    assert_eq!(error.to_string(), format!("IO error: {io}"));
+    assert_eq!(error.kind(), ScaleErrorKind::Io);
+}
+
+#[test]
+fn the_chain_survives_boxing() {
+    let boxed: Box<dyn Error> = Box::new(missing_file());
+    let io = boxed
+        .source()
+        .and_then(|source| source.downcast_ref::<io::Error>())
+        .expect("chain broken");
+    assert_eq!(io.kind(), io::ErrorKind::NotFound);
+    assert_eq!(ScaleErrorInfo::from_dyn(&*boxed).kind, ScaleErrorKind::Io);
+}
+
+#[test]
+fn bare_io_errors_are_described_as_io() {
+    let error = io::Error::new(io::ErrorKind::ConnectionReset, "reset by peer");
+    let info = ScaleErrorInfo::from_dyn(&error);
+    assert_eq!(info.kind, ScaleErrorKind::Io);
+    assert_eq!(info.message, "reset by peer");
+}
+
+#[test]
+fn remote_io_errors_keep_their_message() {
+    let info = ScaleErrorInfo::from(&missing_file());
+    let rebuilt = ScaleError::from(info.clone());
+    assert_eq!(rebuilt.kind(), ScaleErrorKind::Io);
+    assert_eq!(rebuilt.to_string(), info.message);
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn phidget_errors_are_classified() {
+    let cases = [
+        (ReturnCode::Timeout, PhidgetErrorKind::Timeout),
+        (ReturnCode::NotAttached, PhidgetErrorKind::NotAttached),
+        (ReturnCode::NoDev, PhidgetErrorKind::NotAttached),
+        (ReturnCode::InvalidArg, PhidgetErrorKind::InvalidArgument),
+        (ReturnCode::Busy, PhidgetErrorKind::Busy),
+        (ReturnCode::Again, PhidgetErrorKind::Busy),
+        (ReturnCode::UnknownValHigh, PhidgetErrorKind::UnknownValue),
+        (ReturnCode::ConnReset, PhidgetErrorKind::Connection),
+        (ReturnCode::NoMemory, PhidgetErrorKind::Unknown),
+    ];
+    for (return_code, kind) in cases {
+        let error = PhidgetError::new(return_code, 3);
+        assert_eq!(error.kind(), kind, "{return_code:?}");
+        assert_eq!(error.return_code(), return_code);
+        assert_eq!(error.load_cell(), 3);
+    }
+}
+
+#[test]
+fn phidget_errors_are_rebuilt_from_their_code() {
+    let error = ScaleError::PhidgetError(PhidgetError::from_code(99, 2));
+    let info = ScaleErrorInfo::from(&error);
+    assert_eq!(info.return_code, Some(99));
+    let ScaleError::PhidgetError(rebuilt) = ScaleError::from(info.clone()) else {
+        panic!("expected a phidget error");
+    };
+    assert_eq!(rebuilt.code(), 99);
+    assert_eq!(rebuilt.load_cell(), 2);
+    #[cfg(feature = "hardware")]
+    assert_eq!(rebuilt.return_code(), ReturnCode::Unexpected);
+    assert_eq!(rebuilt.kind(), PhidgetErrorKind::Unknown);
+
+    let negative = ScaleErrorInfo {
+        return_code: Some(-1),
+        ..info
+    };
+    let rebuilt = ScaleError::from(negative);
+    assert!(matches!(rebuilt, ScaleError::Remote(_)), "{rebuilt:?}");
+    assert_eq!(rebuilt.kind(), ScaleErrorKind::Phidget);
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn phidget_errors_are_errors() {
+    let error: Box<dyn Error> = Box::new(PhidgetError::new(ReturnCode::Timeout, 1));
+    assert!(error
+        .to_string()
+        .starts_with("Phidget error at Load Cell 1"));
+    let ScaleError::PhidgetError(inner) = ScaleError::phidget_error(ReturnCode::Timeout, 1) else {
+        panic!("expected a phidget error");
+    };
+    assert_eq!(inner.kind(), PhidgetErrorKind::Timeout);
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn every_error_is_classified() {
+    let remote = |kind| ScaleError::Remote(ScaleErrorInfo::new(kind, "remote"));
+    let io = |kind: io::ErrorKind| ScaleError::IoError(kind.into());
+    // (error, transient, disconnection)
+    let cases = [
+        (ScaleError::InvalidCoefficients, false, false),
+        (ScaleError::InvalidPhidgetId, false, false),
+        (
+            ScaleError::WrongDevice {
+                channel: 2,
+                channels: Some(2),
+            },
+            false,
+            false,
+        ),
+        (
+            ScaleError::phidget_error(ReturnCode::Timeout, 0),
+            true,
+            false,
+        ),
+        (
+            ScaleError::phidget_error(ReturnCode::UnknownVal, 0),
+            true,
+            false,
+        ),
+        (
+            ScaleError::phidget_error(ReturnCode::NotAttached, 0),
+            false,
+            true,
+        ),
+        (
+            ScaleError::phidget_error(ReturnCode::ConnReset, 0),
+            false,
+            true,
+        ),
+        (
+            ScaleError::phidget_error(ReturnCode::InvalidArg, 0),
+            false,
+            false,
+        ),
+        (io(io::ErrorKind::TimedOut), true, false),
+        (io(io::ErrorKind::BrokenPipe), false, true),
+        (io(io::ErrorKind::NotFound), false, false),
+        (ScaleError::Busy, true, false),
+        (ScaleError::Cancelled { collected: 2 }, false, false),
+        (ScaleError::BatchTooLarge { count: 2, max: 1 }, false, false),
+        (ScaleError::NotSettled(Duration::from_secs(3)), true, false),
+        (ScaleError::Overflow { capacity: 64 }, true, false),
+        (ScaleError::Unsupported("Taring"), false, false),
+        (
+            ScaleError::Timeout {
+                elapsed: Duration::from_secs(1),
+                collected: 3,
+            },
+            true,
+            false,
+        ),
+        (ScaleError::Disconnected { channel: Some(1) }, false, true),
+        (
+            ScaleError::StaleData {
+                channel: 1,
+                age: Duration::from_secs(60),
+            },
+            false,
+            true,
+        ),
+        (ScaleError::InvalidArgument("samples".into()), false, false),
+        (
+            ScaleError::OpenRolledBack {
+                closed: 3,
+                error: Box::new(ScaleError::phidget_error(ReturnCode::Timeout, 3)),
+            },
+            true,
+            false,
+        ),
+        (
+            ScaleError::MemberFailed {
+                member: 1,
+                error: Box::new(ScaleError::Disconnected { channel: Some(2) }),
+            },
+            false,
+            true,
+        ),
+        (
+            ScaleError::FailoverMemberFailed {
+                role: FailoverRole::Primary,
+                error: Box::new(ScaleError::Busy),
+            },
+            true,
+            false,
+        ),
+        (
+            ScaleError::BothScalesFailed {
+                primary: Box::new(ScaleError::Busy),
+                backup: Box::new(ScaleError::Disconnected { channel: None }),
+            },
+            false,
+            true,
+        ),
+        (
+            ScaleError::MultipleChannels(vec![
+                (0, ScaleError::phidget_error(ReturnCode::Timeout, 0)),
+                (2, ScaleError::phidget_error(ReturnCode::Busy, 2)),
+            ]),
+            true,
+            false,
+        ),
+        (
+            ScaleError::MultipleChannels(vec![
+                (0, ScaleError::phidget_error(ReturnCode::Timeout, 0)),
+                (2, ScaleError::phidget_error(ReturnCode::NotAttached, 2)),
+            ]),
+            false,
+            true,
+        ),
+        (
+            ScaleError::CircuitOpen {
+                since: SystemTime::now(),
+                last_error: ScaleErrorInfo::new(ScaleErrorKind::Disconnected, "gone"),
+            },
+            true,
+            false,
+        ),
+        (ScaleError::NonFinite { channel: Some(2) }, true, false),
+        (
+            ScaleError::AlreadyConnected { serial: 716_000 },
+            false,
+            false,
+        ),
+        (
+            ScaleError::OverCapacity {
+                weight: 5012.5,
+                capacity: 5000.,
+            },
+            true,
+            false,
+        ),
+        (
+            ScaleError::CalibrationExpired {
+                age: Duration::from_secs(45 * 86_400),
+            },
+            false,
+            false,
+        ),
+        (remote(ScaleErrorKind::QueueFull), true, false),
+        (remote(ScaleErrorKind::NonFinite), true, false),
+        (remote(ScaleErrorKind::CircuitOpen), true, false),
+        (remote(ScaleErrorKind::AlreadyConnected), false, false),
+        (remote(ScaleErrorKind::OverCapacity), true, false),
+        (remote(ScaleErrorKind::CalibrationExpired), false, false),
+        (remote(ScaleErrorKind::Io), true, false),
+        (remote(ScaleErrorKind::Stopped), false, true),
+        (remote(ScaleErrorKind::Disconnected), false, true),
+        (remote(ScaleErrorKind::StaleData), false, true),
+        (remote(ScaleErrorKind::InvalidCommand), false, false),
+    ];
+    for (error, transient, disconnection) in cases {
+        assert_eq!(error.is_transient(), transient, "{error:?}");
+        assert_eq!(error.is_disconnection(), disconnection, "{error:?}");
+    }
+}
+
+/// Log-based alerting matches on these, so they must not change.
+#[test]
+fn messages_are_stable() {
+    let cases = [
+        (ScaleError::InvalidCoefficients, "Invalid coefficients"),
+        (ScaleError::InvalidPhidgetId, "Invalid Phidget ID"),
+        (
+            ScaleError::WrongDevice {
+                channel: 3,
+                channels: None,
+            },
+            "Phidget has no Load Cell 3; check that it is a 4-channel bridge",
+        ),
+        (ScaleError::Busy, "Scale is busy"),
+        (
+            ScaleError::Cancelled { collected: 2 },
+            "Cancelled after 2 samples",
+        ),
+        (
+            ScaleError::BatchTooLarge {
+                count: 2000,
+                max: 1000,
+            },
+            "Batch of 2000 readings exceeds the limit of 1000",
+        ),
+        (
+            ScaleError::NotSettled(Duration::from_secs(3)),
+            "Platform did not settle within 3s",
+        ),
+        (
+            ScaleError::Overflow { capacity: 64 },
+            "Buffer of 64 samples overflowed",
+        ),
+        (
+            ScaleError::Unsupported("Taring"),
+            "Taring is not supported by this scale",
+        ),
+        (
+            ScaleError::Timeout {
+                elapsed: Duration::from_millis(1500),
+                collected: 4,
+            },
+            "Timed out after 1.5s with 4 samples",
+        ),
+        (
+            ScaleError::Disconnected { channel: Some(2) },
+            "Scale is disconnected at Load Cell 2",
+        ),
+        (
+            ScaleError::Disconnected { channel: None },
+            "Scale is disconnected",
+        ),
+        (
+            ScaleError::InvalidArgument("samples must be at least 1".into()),
+            "Invalid argument: samples must be at least 1",
+        ),
+        (
+            ScaleError::MultipleChannels(vec![
+                (0, ScaleError::Disconnected { channel: Some(0) }),
+                (1, ScaleError::Busy),
+                (3, ScaleError::Disconnected { channel: Some(3) }),
+            ]),
+            "Load Cells 0, 1 and 3 failed: Scale is disconnected at Load Cell 0; \
+             Scale is busy; Scale is disconnected at Load Cell 3",
+        ),
+        (
+            ScaleError::NonFinite { channel: Some(1) },
+            "Reading is not a finite number at Load Cell 1",
+        ),
+        (
+            ScaleError::NonFinite { channel: None },
+            "Reading is not a finite number",
+        ),
+        (
+            ScaleError::CircuitOpen {
+                since: SystemTime::UNIX_EPOCH,
+                last_error: ScaleErrorInfo::new(
+                    ScaleErrorKind::Timeout,
+                    "Timed out after 1s with 0 samples",
+                ),
+            },
+            "Circuit open after repeated failures: Timed out after 1s with 0 samples",
+        ),
+        (
+            ScaleError::AlreadyConnected { serial: 716_000 },
+            "Phidget 716000 is already connected to another scale",
+        ),
+        (
+            ScaleError::OverCapacity {
+                weight: 5012.54,
+                capacity: 5000.,
+            },
+            "Load of 5012.5 g is over the capacity of 5000 g",
+        ),
+        (
+            ScaleError::CalibrationExpired {
+                age: Duration::from_secs(45 * 86_400 + 3600),
+            },
+            "Calibration expired 45 days after it was made; recalibrate the scale",
+        ),
+        (
+            ScaleError::MemberFailed {
+                member: 1,
+                error: Box::new(ScaleError::Busy),
+            },
+            "Member 1 of the composite scale failed: Scale is busy",
+        ),
+        (
+            ScaleError::BothScalesFailed {
+                primary: Box::new(ScaleError::Busy),
+                backup: Box::new(ScaleError::Disconnected { channel: None }),
+            },
+            "Both scales of the failover failed: primary: Scale is busy; \
+             backup: Scale is disconnected",
+        ),
+    ];
+    for (error, message) in cases {
+        assert_eq!(error.to_string(), message);
+    }
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn every_failing_channel_is_reported() {
+    let error = all_channels([
+        Ok(0.1),
+        Err(ScaleError::phidget_error(ReturnCode::NotAttached, 1)),
+        Ok(0.3),
+        Err(ScaleError::phidget_error(ReturnCode::Timeout, 3)),
+    ])
+    .unwrap_err();
+    let ScaleError::MultipleChannels(failures) = &error else {
+        panic!("{error:?} is not a multi-channel failure");
+    };
+    let channels: Vec<usize> = failures.iter().map(|(channel, _)| *channel).collect();
+    assert_eq!(channels, [1, 3]);
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "Load Cells 1 and 3 failed: {}; {}",
+            PhidgetError::new(ReturnCode::NotAttached, 1),
+            PhidgetError::new(ReturnCode::Timeout, 3)
+        )
+    );
+    // The unplugged cell needs a reconnect, so retrying on the timeout alone
+    // would not help.
+    assert!(error.is_disconnection());
+    assert_eq!(error.kind(), ScaleErrorKind::Phidget);
+
+    let info = ScaleErrorInfo::from(&error);
+    assert_eq!(info.kind, ScaleErrorKind::Phidget);
+    assert_eq!(info.load_cell, None);
+    assert_eq!(info.message, error.to_string());
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn a_single_failing_channel_is_reported_as_it_is() {
+    let error = all_channels([
+        Ok(0.1),
+        Ok(0.2),
+        Err(ScaleError::phidget_error(ReturnCode::Timeout, 2)),
+        Ok(0.4),
+    ])
+    .unwrap_err();
+    assert!(matches!(&error, ScaleError::PhidgetError(e) if e.load_cell() == 2));
+    assert!(error.is_transient());
+
+    assert_eq!(
+        all_channels([Ok(0.1), Ok(0.2), Ok(0.3), Ok(0.4)]).unwrap(),
+        [0.1, 0.2, 0.3, 0.4]
+    );
+}
+
+#[test]
+fn disconnections_keep_their_channel_over_the_wire() {
+    let info = ScaleErrorInfo::from(&ScaleError::Disconnected { channel: Some(3) });
+    assert_eq!(info.load_cell, Some(3));
+    assert!(matches!(
+        ScaleError::from(info),
+        ScaleError::Disconnected { channel: Some(3) }
+    ));
+}
+
+fn assert_send_sync<T: Send + Sync + 'static>() {}
+
+#[test]
+fn errors_cross_threads() {
+    assert_send_sync::<ScaleError>();
+    assert_send_sync::<PhidgetError>();
+    #[cfg(feature = "hardware")]
+    assert_send_sync::<ReturnCode>();
+    assert_send_sync::<ScaleErrorInfo>();
+
+    struct Failing;
+    impl Scale for Failing {
+        fn get_weight(&self) -> Result<Grams, Box<dyn Error + Send + Sync>> {
+            Err(Box::new(ScaleError::Busy))
+        }
+
+        fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn Error + Send + Sync>> {
+            Err(missing_file().into())
+        }
+    }
+
+    let error = std::thread::spawn(|| Failing.get_weight().unwrap_err())
+        .join()
+        .unwrap();
+    assert!(matches!(
+        error.downcast_ref::<ScaleError>(),
+        Some(ScaleError::Busy)
+    ));
+    let error = std::thread::spawn(|| Failing.get_median_weight().unwrap_err())
+        .join()
+        .unwrap();
+    assert_eq!(ScaleErrorInfo::from_dyn(&*error).kind, ScaleErrorKind::Io);
+}
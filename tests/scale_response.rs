@@ -0,0 +1,446 @@
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use libra::calibration::Calibration;
+use libra::failover::FailoverRole;
+use libra::health::{FleetStatus, ScaleHealth};
+use libra::scale::{PhidgetError, ScaleError};
+use libra::{
+    Grams, ListedScale, MedianGrams, ResponseKind, ScaleCmd, ScaleErrorInfo, ScaleErrorKind,
+    ScaleResponse, ScaleState, ScaleStatus, StampedWeight,
+};
+#[cfg(feature = "hardware")]
+use phidget::ReturnCode;
+use serde_json::json;
+
+fn every_response() -> Vec<ScaleResponse> {
+    vec![
+        ScaleResponse::Weight(Grams(12.5)),
+        ScaleResponse::MedianWeight(MedianGrams(-3.)),
+        ScaleResponse::WeightBatch(vec![StampedWeight {
+            weight: Grams(1.),
+            sequence: 7,
+            timestamp: UNIX_EPOCH + Duration::new(1_700_000_000, 500),
+        }]),
+        ScaleResponse::RawReadings([0.1, 0.2, 0.3, 0.4]),
+        ScaleResponse::Error(ScaleErrorInfo::new(ScaleErrorKind::Busy, "Scale is busy")),
+        ScaleResponse::InternalError("Scale panicked: oops".into()),
+        ScaleResponse::ShutdownAck,
+        ScaleResponse::ShuttingDown,
+        ScaleResponse::Expired,
+        ScaleResponse::Tared(Grams(250.)),
+        ScaleResponse::Zeroed(CALIBRATION),
+        ScaleResponse::RawMedians([0.5; 4]),
+        ScaleResponse::CalibrationSet,
+        ScaleResponse::Calibration(CALIBRATION),
+        ScaleResponse::Status(ScaleStatus {
+            phidget_id: 716_000,
+            attached: true,
+            calibration: CALIBRATION,
+            tare: Grams(0.),
+            calibration_expires_in_days: None,
+        }),
+        ScaleResponse::HelloAck {
+            server_version: 1,
+            supported_commands: vec!["GetWeight".into(), "Hello".into()],
+        },
+        ScaleResponse::Unsupported {
+            command: "Frobnicate".into(),
+        },
+        ScaleResponse::Authenticated,
+        ScaleResponse::Heartbeat {
+            uptime: Duration::new(90, 500),
+            last_reading_age: Some(Duration::from_millis(250)),
+        },
+        ScaleResponse::Scales(vec![ListedScale {
+            name: "flour".into(),
+            state: ScaleState::Disconnected,
+            data_interval_ms: Some(100),
+        }]),
+        ScaleResponse::UnknownScale {
+            name: Some("sugar".into()),
+            available: vec!["flour".into()],
+        },
+        ScaleResponse::DataIntervalSet { interval_ms: 8 },
+        ScaleResponse::FleetStatus(FleetStatus::new(vec![ScaleHealth {
+            name: "rice".into(),
+            state: ScaleState::Connected,
+            health: Err(ScaleErrorInfo::new(ScaleErrorKind::Timeout, "Timed out")),
+            last_weight: None,
+            last_weight_age: None,
+            reconnects: 2,
+            calibration_expires_in_days: Some(-1),
+        }])),
+    ]
+}
+
+const CALIBRATION: Calibration = Calibration {
+    offset: 2.,
+    coefficients: [1000.; 4],
+};
+
+#[test]
+fn responses_round_trip() {
+    for response in every_response() {
+        let json = serde_json::to_string(&response).unwrap();
+        let back: ScaleResponse = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, response, "{json}");
+    }
+}
+
+/// Pins the wire format. If this fails, the change breaks every client.
+#[test]
+fn json_shape_is_stable() {
+    let shapes: Vec<_> = every_response()
+        .iter()
+        .map(|response| serde_json::to_value(response).unwrap())
+        .collect();
+    assert_eq!(
+        shapes,
+        [
+            json!({"Weight": 12.5}),
+            json!({"MedianWeight": -3.0}),
+            json!({"WeightBatch": [{
+                "weight": 1.0,
+                "sequence": 7,
+                "timestamp": {"secs_since_epoch": 1_700_000_000, "nanos_since_epoch": 500},
+            }]}),
+            json!({"RawReadings": [0.1, 0.2, 0.3, 0.4]}),
+            json!({"Error": {
+                "kind": "Busy",
+                "load_cell": null,
+                "return_code": null,
+                "message": "Scale is busy",
+            }}),
+            json!({"InternalError": "Scale panicked: oops"}),
+            json!("ShutdownAck"),
+            json!("ShuttingDown"),
+            json!("Expired"),
+            json!({"Tared": 250.0}),
+            json!({"Zeroed": {"offset": 2.0, "coefficients": [1000.0, 1000.0, 1000.0, 1000.0]}}),
+            json!({"RawMedians": [0.5, 0.5, 0.5, 0.5]}),
+            json!("CalibrationSet"),
+            json!({"Calibration": {"offset": 2.0, "coefficients": [1000.0, 1000.0, 1000.0, 1000.0]}}),
+            json!({"Status": {
+                "phidget_id": 716_000,
+                "attached": true,
+                "calibration": {"offset": 2.0, "coefficients": [1000.0, 1000.0, 1000.0, 1000.0]},
+                "tare": 0.0,
+                "calibration_expires_in_days": null,
+            }}),
+            json!({"HelloAck": {"server_version": 1, "supported_commands": ["GetWeight", "Hello"]}}),
+            json!({"Unsupported": {"command": "Frobnicate"}}),
+            json!("Authenticated"),
+            json!({"Heartbeat": {
+                "uptime": {"secs": 90, "nanos": 500},
+                "last_reading_age": {"secs": 0, "nanos": 250_000_000},
+            }}),
+            json!({"Scales": [{"name": "flour", "state": "Disconnected", "data_interval_ms": 100}]}),
+            json!({"UnknownScale": {"name": "sugar", "available": ["flour"]}}),
+            json!({"DataIntervalSet": {"interval_ms": 8}}),
+            json!({"FleetStatus": {
+                "status": "Unusable",
+                "scales": [{
+                    "name": "rice",
+                    "state": "Connected",
+                    "health": {"Err": {
+                        "kind": "Timeout",
+                        "load_cell": null,
+                        "return_code": null,
+                        "message": "Timed out",
+                    }},
+                    "last_weight": null,
+                    "last_weight_age": null,
+                    "reconnects": 2,
+                    "calibration_expires_in_days": -1,
+                }],
+            }}),
+        ]
+    );
+}
+
+#[test]
+fn commands_keep_their_json_shape() {
+    let shapes: Vec<_> = every_command()
+        .iter()
+        .map(|cmd| serde_json::to_value(cmd).unwrap())
+        .collect();
+    assert_eq!(
+        shapes,
+        [
+            json!("GetWeight"),
+            json!({"GetMedianWeight": {"samples": 10}}),
+            json!({"GetWeightBatch": {"count": 5, "interval_ms": 20}}),
+            json!("Shutdown"),
+            json!({"Tare": {"samples": 10}}),
+            json!({"Zero": {"samples": 10}}),
+            json!("GetRawReadings"),
+            json!({"GetRawMedians": {"samples": 10}}),
+            json!({"SetCalibration": {"offset": 2.0, "coefficients": [1000.0, 1000.0, 1000.0, 1000.0]}}),
+            json!("GetCalibration"),
+            json!("GetStatus"),
+            json!({"Hello": {"client_version": 1}}),
+            json!({"Auth": {"token": "secret"}}),
+            json!("ListScales"),
+            json!({"SetDataInterval": {"interval_ms": 8}}),
+        ]
+    );
+}
+
+/// `name` and `NAMES` are what the handshake advertises, so they must match
+/// the wire names.
+#[test]
+fn command_names_match_the_wire() {
+    let names: Vec<_> = every_command().iter().map(ScaleCmd::name).collect();
+    assert_eq!(names, ScaleCmd::NAMES);
+    for cmd in every_command() {
+        let json = serde_json::to_value(&cmd).unwrap();
+        let wire = match &json {
+            serde_json::Value::String(name) => name.clone(),
+            serde_json::Value::Object(fields) => fields.keys().next().unwrap().clone(),
+            _ => panic!("unexpected shape {json}"),
+        };
+        assert_eq!(wire, cmd.name());
+    }
+}
+
+fn every_command() -> Vec<ScaleCmd> {
+    vec![
+        ScaleCmd::GetWeight,
+        ScaleCmd::GetMedianWeight { samples: 10 },
+        ScaleCmd::GetWeightBatch {
+            count: 5,
+            interval_ms: 20,
+        },
+        ScaleCmd::Shutdown,
+        ScaleCmd::Tare { samples: 10 },
+        ScaleCmd::Zero { samples: 10 },
+        ScaleCmd::GetRawReadings,
+        ScaleCmd::GetRawMedians { samples: 10 },
+        ScaleCmd::SetCalibration(CALIBRATION),
+        ScaleCmd::GetCalibration,
+        ScaleCmd::GetStatus,
+        ScaleCmd::Hello { client_version: 1 },
+        ScaleCmd::Auth {
+            token: "secret".into(),
+        },
+        ScaleCmd::ListScales,
+        ScaleCmd::SetDataInterval { interval_ms: 8 },
+    ]
+}
+
+/// Commands written by clients built before the command set grew.
+#[test]
+fn existing_commands_still_decode() {
+    let decoded: Vec<ScaleCmd> = [
+        r#""GetWeight""#,
+        r#"{"GetMedianWeight":{"samples":10}}"#,
+        r#"{"GetWeightBatch":{"count":5,"interval_ms":20}}"#,
+        r#""Shutdown""#,
+    ]
+    .iter()
+    .map(|json| serde_json::from_str(json).unwrap())
+    .collect();
+    assert_eq!(
+        decoded,
+        [
+            ScaleCmd::GetWeight,
+            ScaleCmd::GetMedianWeight { samples: 10 },
+            ScaleCmd::GetWeightBatch {
+                count: 5,
+                interval_ms: 20,
+            },
+            ScaleCmd::Shutdown,
+        ]
+    );
+}
+
+#[test]
+fn expects_pairs_commands_with_responses() {
+    let cmd = ScaleCmd::GetMedianWeight { samples: 3 };
+    assert_eq!(cmd.expects(), ResponseKind::MedianWeight);
+    assert!(ScaleResponse::MedianWeight(MedianGrams(1.)).answers(&cmd));
+    assert!(!ScaleResponse::Weight(Grams(1.)).answers(&cmd));
+    assert!(ScaleResponse::Expired.answers(&cmd));
+    assert_eq!(ScaleCmd::Shutdown.expects(), ResponseKind::ShutdownAck);
+    assert!(ScaleResponse::ShutdownAck.answers(&ScaleCmd::Shutdown));
+    assert!(ScaleResponse::Tared(Grams(1.)).answers(&ScaleCmd::Tare { samples: 3 }));
+    assert!(!ScaleResponse::Tared(Grams(1.)).answers(&ScaleCmd::Zero { samples: 3 }));
+    assert!(ScaleResponse::CalibrationSet.answers(&ScaleCmd::SetCalibration(CALIBRATION)));
+    let hello = ScaleCmd::Hello { client_version: 1 };
+    assert!(ScaleResponse::hello_ack().answers(&hello));
+    assert!(ScaleResponse::Unsupported {
+        command: "Hello".into()
+    }
+    .answers(&hello));
+    let heartbeat = ScaleResponse::Heartbeat {
+        uptime: Duration::from_secs(1),
+        last_reading_age: None,
+    };
+    assert_eq!(heartbeat.kind(), None);
+    assert!(!heartbeat.answers(&hello));
+    let unknown = ScaleResponse::UnknownScale {
+        name: None,
+        available: Vec::new(),
+    };
+    assert_eq!(unknown.kind(), None);
+    assert!(unknown.answers(&ScaleCmd::GetWeight));
+    assert!(ScaleResponse::Scales(Vec::new()).answers(&ScaleCmd::ListScales));
+    let fleet = ScaleResponse::FleetStatus(FleetStatus::new(Vec::new()));
+    assert_eq!(fleet.kind(), Some(ResponseKind::FleetStatus));
+    assert!(fleet.answers(&ScaleCmd::GetStatus));
+    assert!(!fleet.answers(&ScaleCmd::GetWeight));
+}
+
+#[test]
+fn error_info_keeps_the_kind() {
+    let info = ScaleErrorInfo::from(&ScaleError::Cancelled { collected: 2 });
+    assert_eq!(info.kind, ScaleErrorKind::Cancelled);
+    assert_eq!(info.message, "Cancelled after 2 samples");
+
+    let boxed: Box<dyn std::error::Error + Send + Sync> = Box::new(ScaleError::Busy);
+    assert_eq!(ScaleErrorInfo::from_dyn(&*boxed).kind, ScaleErrorKind::Busy);
+    let other: Box<dyn std::error::Error + Send + Sync> = "wobbly".into();
+    assert_eq!(
+        ScaleErrorInfo::from_dyn(&*other).kind,
+        ScaleErrorKind::Other
+    );
+}
+
+#[test]
+fn every_scale_error_survives_the_wire() {
+    let errors = [
+        ScaleError::InvalidCoefficients,
+        ScaleError::InvalidPhidgetId,
+        // Not attached and timed out, as numbered by phidget22.
+        ScaleError::PhidgetError(PhidgetError::from_code(52, 2)),
+        ScaleError::PhidgetError(PhidgetError::from_code(3, 0)),
+        ScaleError::IoError(io::ErrorKind::TimedOut.into()),
+        ScaleError::Busy,
+        ScaleError::Cancelled { collected: 2 },
+        ScaleError::BatchTooLarge {
+            count: 2000,
+            max: 1000,
+        },
+        ScaleError::NotSettled(Duration::from_secs(3)),
+        ScaleError::Overflow { capacity: 64 },
+        ScaleError::Unsupported("Taring"),
+        ScaleError::Timeout {
+            elapsed: Duration::from_millis(1500),
+            collected: 4,
+        },
+        ScaleError::Disconnected { channel: Some(2) },
+        ScaleError::Disconnected { channel: None },
+        ScaleError::InvalidArgument("samples must be at least 1".into()),
+        ScaleError::WrongDevice {
+            channel: 2,
+            channels: Some(2),
+        },
+        ScaleError::StaleData {
+            channel: 1,
+            age: Duration::from_secs(1200),
+        },
+        ScaleError::NonFinite { channel: Some(3) },
+        ScaleError::NonFinite { channel: None },
+        ScaleError::CircuitOpen {
+            since: SystemTime::now(),
+            last_error: ScaleErrorInfo::new(ScaleErrorKind::Busy, "Scale is busy"),
+        },
+        ScaleError::AlreadyConnected { serial: 716_000 },
+        ScaleError::OverCapacity {
+            weight: 5012.5,
+            capacity: 5000.,
+        },
+        ScaleError::CalibrationExpired {
+            age: Duration::from_secs(45 * 86_400),
+        },
+        ScaleError::MemberFailed {
+            member: 0,
+            error: Box::new(ScaleError::Timeout {
+                elapsed: Duration::from_millis(1500),
+                collected: 4,
+            }),
+        },
+        ScaleError::FailoverMemberFailed {
+            role: FailoverRole::Backup,
+            error: Box::new(ScaleError::StaleData {
+                channel: 1,
+                age: Duration::from_secs(90),
+            }),
+        },
+        ScaleError::BothScalesFailed {
+            primary: Box::new(ScaleError::Overflow { capacity: 64 }),
+            backup: Box::new(ScaleError::Timeout {
+                elapsed: Duration::from_millis(1500),
+                collected: 4,
+            }),
+        },
+        ScaleError::Remote(ScaleErrorInfo::new(ScaleErrorKind::QueueFull, "Queue full")),
+    ];
+    for error in errors {
+        let response = ScaleResponse::Error(ScaleErrorInfo::from(&error));
+        let json = serde_json::to_string(&response).unwrap();
+        let ScaleResponse::Error(info) = serde_json::from_str(&json).unwrap() else {
+            panic!("{json} is not an error");
+        };
+        assert_eq!(info.kind, error.kind(), "{json}");
+        assert_eq!(info.message, error.to_string());
+
+        let rebuilt = ScaleError::from(info);
+        assert_eq!(rebuilt.kind(), error.kind(), "{json}");
+        assert_eq!(rebuilt.to_string(), error.to_string());
+    }
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn phidget_errors_keep_their_load_cell_and_code() {
+    let detached = ScaleErrorInfo::from(&ScaleError::phidget_error(ReturnCode::NotAttached, 2));
+    let timed_out = ScaleErrorInfo::from(&ScaleError::phidget_error(ReturnCode::Timeout, 2));
+    assert_eq!(detached.load_cell, Some(2));
+    assert_eq!(detached.return_code, Some(ReturnCode::NotAttached as i32));
+    assert_eq!(timed_out.return_code, Some(ReturnCode::Timeout as i32));
+
+    let ScaleError::PhidgetError(error) = ScaleError::from(detached) else {
+        panic!("expected a phidget error");
+    };
+    assert_eq!(error.return_code(), ReturnCode::NotAttached);
+    assert_eq!(error.load_cell(), 2);
+
+    // Without them there is nothing to rebuild the phidget error from.
+    let bare = ScaleErrorInfo::new(ScaleErrorKind::Phidget, "Phidget error");
+    assert!(matches!(ScaleError::from(bare), ScaleError::Remote(_)));
+}
+
+#[test]
+fn errors_from_older_peers_still_decode() {
+    let info: ScaleErrorInfo =
+        serde_json::from_value(json!({"kind": "Busy", "message": "Scale is busy"})).unwrap();
+    assert_eq!(
+        info,
+        ScaleErrorInfo::new(ScaleErrorKind::Busy, "Scale is busy")
+    );
+}
+
+/// Readings and coefficients are one per load cell, so a peer sending more or
+/// fewer is refused rather than weighed with the extras dropped.
+#[test]
+fn readings_and_coefficients_of_the_wrong_length_do_not_decode() {
+    for values in [json!([0.1, 0.2, 0.3]), json!([0.1, 0.2, 0.3, 0.4, 0.5])] {
+        for response in [
+            json!({ "RawReadings": values }),
+            json!({ "RawMedians": values }),
+            json!({ "Zeroed": {"offset": 2.0, "coefficients": values} }),
+        ] {
+            assert!(
+                serde_json::from_value::<ScaleResponse>(response.clone()).is_err(),
+                "{response}"
+            );
+        }
+        let cmd = json!({ "SetCalibration": {"offset": 2.0, "coefficients": values} });
+        assert!(
+            serde_json::from_value::<ScaleCmd>(cmd.clone()).is_err(),
+            "{cmd}"
+        );
+    }
+}
@@ -0,0 +1,263 @@
+#![cfg(feature = "schema")]
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use libra::calibration::Calibration;
+use libra::health::{
+    CalibrationExpiry, ChannelHealth, FleetStatus, HealthReport, ScaleHealth, Status,
+};
+use libra::schema::{export_schemas, schemas};
+use libra::{
+    Grams, ListedScale, MedianGrams, Reply, Request, ScaleCmd, ScaleErrorInfo, ScaleErrorKind,
+    ScaleResponse, ScaleState, ScaleStatus, StampedWeight,
+};
+use serde_json::{json, Value};
+
+const CALIBRATION: Calibration = Calibration {
+    offset: 1.5,
+    coefficients: [2.; 4],
+};
+
+const STAMPED: StampedWeight = StampedWeight {
+    weight: Grams(1.),
+    sequence: 7,
+    timestamp: UNIX_EPOCH,
+};
+
+fn schema(name: &str) -> jsonschema::Validator {
+    let (_, schema) = schemas()
+        .into_iter()
+        .find(|(file, _)| *file == format!("{name}.json"))
+        .unwrap_or_else(|| panic!("no schema for {name}"));
+    jsonschema::validator_for(&serde_json::to_value(schema).unwrap()).unwrap()
+}
+
+fn commands() -> Vec<ScaleCmd> {
+    vec![
+        ScaleCmd::GetWeight,
+        ScaleCmd::GetMedianWeight { samples: 10 },
+        ScaleCmd::GetWeightBatch {
+            count: 300,
+            interval_ms: 20,
+        },
+        ScaleCmd::Shutdown,
+        ScaleCmd::Tare { samples: 3 },
+        ScaleCmd::Zero { samples: 3 },
+        ScaleCmd::GetRawReadings,
+        ScaleCmd::GetRawMedians { samples: 7 },
+        ScaleCmd::SetCalibration(CALIBRATION),
+        ScaleCmd::GetCalibration,
+        ScaleCmd::GetStatus,
+        ScaleCmd::Hello { client_version: 1 },
+        ScaleCmd::Auth {
+            token: "secret".into(),
+        },
+        ScaleCmd::ListScales,
+        ScaleCmd::SetDataInterval { interval_ms: 8 },
+    ]
+}
+
+fn responses() -> Vec<ScaleResponse> {
+    vec![
+        ScaleResponse::Weight(Grams(12.5)),
+        ScaleResponse::MedianWeight(MedianGrams(-3.)),
+        ScaleResponse::WeightBatch(vec![StampedWeight {
+            timestamp: UNIX_EPOCH + Duration::new(1_700_000_000, 500),
+            ..STAMPED
+        }]),
+        ScaleResponse::RawReadings([0.1, 0.2, 0.3, 0.4]),
+        ScaleResponse::Error(ScaleErrorInfo::new(ScaleErrorKind::Busy, "Scale is busy")),
+        ScaleResponse::Error(ScaleErrorInfo {
+            load_cell: Some(3),
+            return_code: Some(52),
+            ..ScaleErrorInfo::new(ScaleErrorKind::Phidget, "Detached")
+        }),
+        ScaleResponse::InternalError("Scale panicked: oops".into()),
+        ScaleResponse::ShutdownAck,
+        ScaleResponse::ShuttingDown,
+        ScaleResponse::Expired,
+        ScaleResponse::Tared(Grams(250.)),
+        ScaleResponse::Zeroed(CALIBRATION),
+        ScaleResponse::RawMedians([0.5; 4]),
+        ScaleResponse::CalibrationSet,
+        ScaleResponse::Calibration(CALIBRATION),
+        ScaleResponse::Status(ScaleStatus {
+            phidget_id: 716_000,
+            attached: true,
+            calibration: CALIBRATION,
+            tare: Grams(0.),
+            calibration_expires_in_days: None,
+        }),
+        ScaleResponse::hello_ack(),
+        ScaleResponse::Unsupported {
+            command: "Frobnicate".into(),
+        },
+        ScaleResponse::Authenticated,
+        ScaleResponse::Heartbeat {
+            uptime: Duration::new(90, 500),
+            last_reading_age: Some(Duration::from_millis(250)),
+        },
+        ScaleResponse::Scales(vec![ListedScale {
+            name: "flour".into(),
+            state: ScaleState::Disconnected,
+            data_interval_ms: Some(100),
+        }]),
+        ScaleResponse::UnknownScale {
+            name: None,
+            available: vec!["flour".into(), "rice".into()],
+        },
+        ScaleResponse::DataIntervalSet { interval_ms: 8 },
+        ScaleResponse::FleetStatus(fleet_status()),
+    ]
+}
+
+fn assert_valid(validator: &jsonschema::Validator, message: &Value) {
+    let errors: Vec<_> = validator
+        .iter_errors(message)
+        .map(|e| e.to_string())
+        .collect();
+    assert!(errors.is_empty(), "{message}: {errors:?}");
+}
+
+#[test]
+fn every_command_matches_the_schema() {
+    let validator = schema("ScaleCmd");
+    let commands = commands();
+    assert_eq!(commands.len(), ScaleCmd::NAMES.len());
+    for cmd in &commands {
+        assert_valid(&validator, &serde_json::to_value(cmd).unwrap());
+    }
+    let requests = schema("Request");
+    for (id, cmd) in commands.into_iter().enumerate() {
+        let request = Request {
+            id: id as u64,
+            scale: (id % 2 == 1).then(|| "flour".into()),
+            cmd,
+        };
+        assert_valid(&requests, &serde_json::to_value(request).unwrap());
+    }
+}
+
+#[test]
+fn every_response_matches_the_schema() {
+    let validator = schema("ScaleResponse");
+    let replies = schema("Reply");
+    for (id, response) in responses().into_iter().enumerate() {
+        assert_valid(&validator, &serde_json::to_value(&response).unwrap());
+        let reply = Reply {
+            id: id as u64,
+            response,
+        };
+        assert_valid(&replies, &serde_json::to_value(reply).unwrap());
+    }
+}
+
+#[test]
+fn payload_types_match_their_schemas() {
+    assert_valid(
+        &schema("Calibration"),
+        &serde_json::to_value(CALIBRATION).unwrap(),
+    );
+    assert_valid(
+        &schema("StampedWeight"),
+        &serde_json::to_value(STAMPED).unwrap(),
+    );
+    // Older peers leave out the optional fields.
+    assert_valid(
+        &schema("ScaleErrorInfo"),
+        &json!({"kind": "Busy", "message": "Scale is busy"}),
+    );
+}
+
+#[test]
+fn malformed_messages_do_not_match() {
+    let commands = schema("ScaleCmd");
+    for bad in [
+        json!("Teleport"),
+        json!({"Tare": {"samples": "three"}}),
+        json!({"Tare": {"samples": -1}}),
+        json!({"SetCalibration": {"offset": 1.0, "coefficients": [1.0]}}),
+    ] {
+        assert!(!commands.is_valid(&bad), "{bad}");
+    }
+    let responses = schema("ScaleResponse");
+    for bad in [
+        json!({"Weight": "heavy"}),
+        json!({"Error": {"kind": "Gremlins", "message": "?"}}),
+        json!({"RawReadings": [0.1, 0.2]}),
+    ] {
+        assert!(!responses.is_valid(&bad), "{bad}");
+    }
+}
+
+#[test]
+fn schemas_are_exported_as_files() {
+    let dir = std::env::temp_dir().join(format!("libra-schema-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let paths = export_schemas(&dir).unwrap();
+    assert_eq!(paths.len(), schemas().len());
+    assert!(paths.contains(&dir.join("ScaleCmd.json")));
+    for ((name, schema), path) in schemas().into_iter().zip(&paths) {
+        assert_eq!(path.file_name().unwrap(), name);
+        let written: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(written, serde_json::to_value(schema).unwrap(), "{name}");
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A fleet of a scale with a noisy load cell, and one that did not answer
+/// in time.
+fn fleet_status() -> FleetStatus {
+    let noise = |channel| {
+        if channel == 2 {
+            0.5f64.powi(20)
+        } else {
+            0.5f64.powi(23)
+        }
+    };
+    FleetStatus::new(vec![
+        ScaleHealth {
+            name: "flour".into(),
+            state: ScaleState::Connected,
+            health: Ok(HealthReport {
+                status: Status::Degraded,
+                reasons: vec!["Load Cell 2 is noisy (9.537e-7)".into()],
+                channels: std::array::from_fn(|channel| ChannelHealth {
+                    channel,
+                    attached: true,
+                    noise: Some(noise(channel)),
+                }),
+                reads: 5,
+                failed_reads: 0,
+                stale: None,
+                calibration_age: Some(Duration::from_secs(3 * 24 * 60 * 60)),
+                calibration_expiry: Some(CalibrationExpiry::Valid {
+                    remaining: Duration::from_secs(12 * 24 * 60 * 60),
+                }),
+                calibration_expires_in_days: Some(12),
+                calibration_future_dated: false,
+                last_error: None,
+            }),
+            last_weight: Some(StampedWeight {
+                weight: Grams(1000.),
+                sequence: 7,
+                timestamp: UNIX_EPOCH + Duration::new(1_700_000_000, 500),
+            }),
+            last_weight_age: Some(Duration::from_millis(250)),
+            reconnects: 1,
+            calibration_expires_in_days: Some(12),
+        },
+        ScaleHealth {
+            name: "rice".into(),
+            state: ScaleState::Disconnected,
+            health: Err(ScaleErrorInfo::new(
+                ScaleErrorKind::Timeout,
+                "No answer within 2s",
+            )),
+            last_weight: None,
+            last_weight_age: None,
+            reconnects: 0,
+            calibration_expires_in_days: None,
+        },
+    ])
+}
@@ -0,0 +1,153 @@
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use libra::scale::ScaleError;
+use libra::scoped::ScopedSampler;
+use libra::Grams;
+
+const INTERVAL: Duration = Duration::from_millis(2);
+
+/// Stands in for a scale: counts its reads and fails every fourth one.
+#[derive(Default)]
+struct Counter {
+    reads: usize,
+}
+
+impl Counter {
+    fn read(&mut self) -> Result<Grams, ScaleError> {
+        self.reads += 1;
+        if self.reads.is_multiple_of(4) {
+            return Err(ScaleError::IoError(io::ErrorKind::TimedOut.into()));
+        }
+        Ok(Grams(self.reads as f64))
+    }
+}
+
+#[test]
+fn borrows_the_scale_for_the_scope() {
+    let mut counter = Counter::default();
+    let collected = thread::scope(|scope| {
+        let (sampler, rx) = ScopedSampler::run_with(|| counter.read(), INTERVAL, scope);
+        let items: Vec<_> = rx.iter().take(8).collect();
+        sampler.join();
+        items
+    });
+    // The borrow ended with the scope, so the counter is ours again.
+    assert!(counter.reads >= 8);
+
+    let sequences: Vec<_> = collected
+        .iter()
+        .filter_map(|item| item.as_ref().ok())
+        .map(|sample| sample.sequence)
+        .collect();
+    assert_eq!(sequences, [0, 1, 2, 3, 4, 5]);
+    // Errors arrive in-band, in order.
+    assert!(matches!(collected[3], Err(ScaleError::IoError(_))));
+    assert!(matches!(collected[7], Err(ScaleError::IoError(_))));
+}
+
+#[test]
+fn stop_ends_sampling_promptly() {
+    let mut counter = Counter::default();
+    thread::scope(|scope| {
+        let (sampler, rx) =
+            ScopedSampler::run_with(|| counter.read(), Duration::from_secs(3600), scope);
+        rx.recv().unwrap().unwrap();
+        let start = Instant::now();
+        sampler.stop();
+        // The channel closes once the thread has exited.
+        assert!(rx.recv().is_err());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    });
+    assert_eq!(counter.reads, 1);
+}
+
+#[test]
+fn dropping_the_receiver_does_not_wedge_the_sampler() {
+    let mut counter = Counter::default();
+    let start = Instant::now();
+    thread::scope(|scope| {
+        let (sampler, rx) = ScopedSampler::run_with(|| counter.read(), INTERVAL, scope);
+        rx.recv().unwrap().unwrap();
+        drop(rx);
+        while !sampler.is_finished() {
+            assert!(start.elapsed() < Duration::from_secs(1));
+            thread::sleep(INTERVAL);
+        }
+    });
+}
+
+#[test]
+fn dropped_sampler_lets_the_scope_end() {
+    let mut counter = Counter::default();
+    thread::scope(|scope| {
+        let (sampler, rx) = ScopedSampler::run_with(|| counter.read(), INTERVAL, scope);
+        rx.recv().unwrap().unwrap();
+        drop(sampler);
+        // Keep the receiver alive: only dropping the sampler stops it.
+        let _still_listening = rx;
+    });
+    assert!(counter.reads >= 1);
+}
+
+#[test]
+fn raw_sampling_numbers_the_readings() {
+    let mut reads = 0;
+    let collected = thread::scope(|scope| {
+        let (sampler, rx) = ScopedSampler::run_raw_with(
+            || {
+                reads += 1;
+                match reads {
+                    2 => Err(ScaleError::IoError(io::ErrorKind::TimedOut.into())),
+                    _ => Ok([reads as f64; 4]),
+                }
+            },
+            INTERVAL,
+            scope,
+        );
+        let items: Vec<_> = rx.iter().take(4).collect();
+        sampler.join();
+        items
+    });
+    assert!(matches!(collected[1], Err(ScaleError::IoError(_))));
+    let readings: Vec<_> = collected
+        .iter()
+        .filter_map(|item| item.as_ref().ok())
+        .map(|readings| (readings.sequence, readings.ratios[0]))
+        .collect();
+    assert_eq!(readings, [(0, 1.), (1, 3.), (2, 4.)]);
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn a_tuned_sampler_puts_the_data_interval_back() {
+    use libra::calibration::Calibration;
+    use libra::scale::{ConnectedScale, NUMBER_OF_INPUTS};
+    use libra::source::VoltageSource;
+    use libra::testing::FakeVoltageSource;
+
+    let sources = FakeVoltageSource::bridge(716_000);
+    for source in &sources {
+        source.set_data_interval_bounds(Duration::from_millis(8), Duration::from_secs(1));
+    }
+    let calibration = Calibration {
+        offset: 0.,
+        coefficients: [1.; NUMBER_OF_INPUTS],
+    };
+    let mut scale = ConnectedScale::from_sources(716_000, calibration, sources.clone());
+    scale
+        .set_data_intervals(Duration::from_millis(250))
+        .unwrap();
+    thread::scope(|scope| {
+        let (sampler, rx) = ScopedSampler::run_tuned(&mut scale, INTERVAL, scope).unwrap();
+        assert_eq!(sampler.data_interval(), Some(Duration::from_millis(8)));
+        assert!(rx.iter().take(3).all(|item| item.is_ok()));
+        assert_eq!(
+            sources[0].clone().data_interval().unwrap(),
+            Duration::from_millis(8)
+        );
+        sampler.join();
+    });
+    assert_eq!(scale.data_interval().unwrap(), Duration::from_millis(250));
+}
@@ -0,0 +1,296 @@
+use std::io::{self, Cursor, Read, Write};
+use std::time::{Duration, UNIX_EPOCH};
+
+use libra::calibration::Calibration;
+use libra::serial::{
+    crc16, encode_frame, encode_response_frame, serve_frames, FrameDecoder, FrameError, FRAME_END,
+    FRAME_START,
+};
+use libra::{
+    Grams, MedianGrams, Scale, ScaleCmd, ScaleErrorInfo, ScaleErrorKind, ScaleResponse,
+    ScaleStatus, StampedWeight,
+};
+
+const CALIBRATION: Calibration = Calibration {
+    offset: 1.5,
+    coefficients: [2.; 4],
+};
+
+fn every_command() -> Vec<ScaleCmd> {
+    vec![
+        ScaleCmd::GetWeight,
+        ScaleCmd::GetMedianWeight { samples: 10 },
+        ScaleCmd::GetWeightBatch {
+            count: 5,
+            interval_ms: 20,
+        },
+        ScaleCmd::Shutdown,
+        ScaleCmd::Tare { samples: 3 },
+        ScaleCmd::Zero { samples: 3 },
+        ScaleCmd::GetRawReadings,
+        ScaleCmd::GetRawMedians { samples: 7 },
+        ScaleCmd::SetCalibration(CALIBRATION),
+        ScaleCmd::GetCalibration,
+        ScaleCmd::GetStatus,
+    ]
+}
+
+fn every_response() -> Vec<ScaleResponse> {
+    vec![
+        ScaleResponse::Weight(Grams(12.5)),
+        ScaleResponse::MedianWeight(MedianGrams(-3.)),
+        ScaleResponse::WeightBatch(vec![StampedWeight {
+            weight: Grams(1.),
+            sequence: 7,
+            timestamp: UNIX_EPOCH + Duration::new(1_700_000_000, 500),
+        }]),
+        ScaleResponse::RawReadings([0.1, 0.2, 0.3, 0.4]),
+        ScaleResponse::Error(ScaleErrorInfo::new(ScaleErrorKind::Busy, "Scale is busy")),
+        ScaleResponse::InternalError("Scale panicked: oops".into()),
+        ScaleResponse::ShutdownAck,
+        ScaleResponse::ShuttingDown,
+        ScaleResponse::Expired,
+        ScaleResponse::Tared(Grams(250.)),
+        ScaleResponse::Zeroed(CALIBRATION),
+        ScaleResponse::RawMedians([0.5; 4]),
+        ScaleResponse::CalibrationSet,
+        ScaleResponse::Calibration(CALIBRATION),
+        ScaleResponse::Status(ScaleStatus {
+            phidget_id: 716_000,
+            attached: true,
+            calibration: CALIBRATION,
+            tare: Grams(0.),
+            calibration_expires_in_days: None,
+        }),
+    ]
+}
+
+/// xorshift64, so the noise is the same on every run.
+struct Noise(u64);
+
+impl Noise {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+fn drain<T: serde::de::DeserializeOwned>(
+    decoder: &mut FrameDecoder<T>,
+) -> Vec<Result<T, FrameError>> {
+    std::iter::from_fn(|| decoder.next_frame()).collect()
+}
+
+#[test]
+fn crc_matches_the_modbus_check_value() {
+    assert_eq!(crc16(b"123456789"), 0x4B37);
+}
+
+#[test]
+fn every_command_round_trips_byte_by_byte() {
+    let mut decoder = FrameDecoder::<ScaleCmd>::new();
+    let mut decoded = Vec::new();
+    for cmd in every_command() {
+        for byte in encode_frame(&cmd) {
+            decoder.extend(&[byte]);
+            decoded.extend(drain(&mut decoder));
+        }
+    }
+    let decoded: Vec<_> = decoded.into_iter().map(Result::unwrap).collect();
+    assert_eq!(decoded, every_command());
+}
+
+#[test]
+fn every_response_round_trips() {
+    let mut decoder = FrameDecoder::<ScaleResponse>::new();
+    for response in every_response() {
+        decoder.extend(&encode_response_frame(&response).unwrap());
+        assert_eq!(decoder.next_frame(), Some(Ok(response)));
+    }
+    assert_eq!(decoder.next_frame(), None);
+}
+
+#[test]
+fn resynchronizes_after_noise_and_damage() {
+    let mut noise = Noise(7);
+    let mut stream = noise.bytes(40);
+    stream.extend(encode_frame(&ScaleCmd::GetWeight));
+    let mut damaged = encode_frame(&ScaleCmd::Tare { samples: 3 });
+    damaged[4] ^= 0xFF;
+    stream.extend(damaged);
+    stream.extend([FRAME_START, 0x00]);
+    stream.extend(encode_frame(&ScaleCmd::GetStatus));
+
+    let mut decoder = FrameDecoder::<ScaleCmd>::with_max_payload(256);
+    decoder.extend(&stream);
+    let good: Vec<_> = drain(&mut decoder)
+        .into_iter()
+        .filter_map(Result::ok)
+        .collect();
+    assert!(good.contains(&ScaleCmd::GetWeight));
+    assert!(good.contains(&ScaleCmd::GetStatus));
+    assert!(!good.contains(&ScaleCmd::Tare { samples: 3 }));
+}
+
+#[test]
+fn damaged_frames_are_reported() {
+    let mut frame = encode_frame(&ScaleCmd::GetWeight);
+    let crc = frame.len() - 3;
+    frame[crc] ^= 1;
+    let mut decoder = FrameDecoder::<ScaleCmd>::new();
+    decoder.extend(&frame);
+    assert!(matches!(
+        decoder.next_frame(),
+        Some(Err(FrameError::Crc { .. }))
+    ));
+
+    let mut frame = encode_frame(&ScaleCmd::GetWeight);
+    *frame.last_mut().unwrap() = 0;
+    decoder.extend(&frame);
+    assert_eq!(decoder.next_frame(), Some(Err(FrameError::MissingEnd)));
+
+    let mut decoder = FrameDecoder::<ScaleCmd>::with_max_payload(16);
+    decoder.extend(&[FRAME_START, 0x01, 0x00]);
+    assert_eq!(
+        decoder.next_frame(),
+        Some(Err(FrameError::TooLong { len: 256, max: 16 }))
+    );
+}
+
+#[test]
+fn random_bytes_never_panic() {
+    let mut noise = Noise(0x5eed);
+    let mut decoder = FrameDecoder::<ScaleCmd>::with_max_payload(64);
+    for _ in 0..20_000 {
+        let len = (noise.next() % 32) as usize;
+        let mut chunk = noise.bytes(len);
+        // Plenty of start bytes, so the frame parser is exercised too.
+        if noise.next().is_multiple_of(4) {
+            chunk.insert(0, FRAME_START);
+        }
+        decoder.extend(&chunk);
+        drain(&mut decoder);
+    }
+}
+
+#[test]
+fn mutated_frames_never_panic() {
+    let mut noise = Noise(42);
+    let frames: Vec<_> = every_response()
+        .iter()
+        .map(|response| encode_response_frame(response).unwrap())
+        .collect();
+    let mut decoder = FrameDecoder::<ScaleResponse>::new();
+    for _ in 0..5_000 {
+        let mut frame = frames[(noise.next() % frames.len() as u64) as usize].clone();
+        for _ in 0..noise.next() % 4 {
+            let at = (noise.next() % frame.len() as u64) as usize;
+            frame[at] = noise.next() as u8;
+        }
+        let cut = (noise.next() % (frame.len() as u64 + 1)) as usize;
+        decoder.extend(&frame[..cut]);
+        drain(&mut decoder);
+    }
+}
+
+/// Intact frames around random payloads, so payload decoding is exercised.
+#[test]
+fn random_payloads_never_panic() {
+    let mut noise = Noise(99);
+    let mut commands = FrameDecoder::<ScaleCmd>::new();
+    let mut responses = FrameDecoder::<ScaleResponse>::new();
+    for _ in 0..20_000 {
+        let len = (noise.next() % 24) as usize;
+        let mut frame = vec![FRAME_START, 0, len as u8];
+        frame.extend(noise.bytes(len));
+        frame.extend(crc16(&frame[1..]).to_le_bytes());
+        frame.push(FRAME_END);
+        commands.extend(&frame);
+        responses.extend(&frame);
+        drain(&mut commands);
+        drain(&mut responses);
+    }
+}
+
+/// A serial port that reads from `input` and collects what is written.
+struct Loopback {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+impl Read for Loopback {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Deliver a few bytes at a time, as a slow line would.
+        let len = buf.len().min(3);
+        self.input.read(&mut buf[..len])
+    }
+}
+
+impl Write for Loopback {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+struct MockScale;
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(42.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+}
+
+#[test]
+fn serves_commands_until_shutdown() {
+    let mut input = encode_frame(&ScaleCmd::GetWeight);
+    input.extend(b"line noise");
+    let mut damaged = encode_frame(&ScaleCmd::GetWeight);
+    damaged[3] ^= 0x55;
+    input.extend(damaged);
+    input.extend(encode_frame(&ScaleCmd::GetCalibration));
+    // An intact frame holding a command from some future version.
+    let mut unknown = vec![FRAME_START, 0x00, 0x01, 0x7F];
+    unknown.extend(crc16(&unknown[1..]).to_le_bytes());
+    unknown.push(FRAME_END);
+    input.extend(unknown);
+    input.extend(encode_frame(&ScaleCmd::Shutdown));
+    input.extend(encode_frame(&ScaleCmd::GetWeight));
+    let mut port = Loopback {
+        input: Cursor::new(input),
+        output: Vec::new(),
+    };
+
+    serve_frames(&mut MockScale, &mut port).unwrap();
+
+    let mut decoder = FrameDecoder::<ScaleResponse>::new();
+    decoder.extend(&port.output);
+    let responses: Vec<_> = drain(&mut decoder)
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(responses.len(), 4);
+    assert_eq!(responses[0], ScaleResponse::Weight(Grams(42.)));
+    let ScaleResponse::Error(error) = &responses[1] else {
+        panic!("expected an error, got {:?}", responses[1]);
+    };
+    assert_eq!(error.kind, ScaleErrorKind::Unsupported);
+    let ScaleResponse::Error(error) = &responses[2] else {
+        panic!("expected an error, got {:?}", responses[2]);
+    };
+    assert_eq!(error.kind, ScaleErrorKind::InvalidCommand);
+    assert_eq!(responses[3], ScaleResponse::ShutdownAck);
+}
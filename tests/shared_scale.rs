@@ -0,0 +1,137 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::Duration;
+
+#[cfg(feature = "hardware")]
+use libra::scale::ConnectedScale;
+use libra::scale::ScaleError;
+use libra::shared::SharedScale;
+use libra::{Grams, MedianGrams, Scale};
+
+fn assert_send<T: Send>() {}
+fn assert_sync<T: Sync>() {}
+
+#[test]
+fn send_sync_guarantees() {
+    #[cfg(feature = "hardware")]
+    {
+        assert_send::<ConnectedScale>();
+        assert_send::<SharedScale<ConnectedScale>>();
+        assert_sync::<SharedScale<ConnectedScale>>();
+    }
+    assert_send::<ScaleError>();
+    assert_sync::<ScaleError>();
+}
+
+/// Counts reads and detects overlapping access to the scale.
+#[derive(Default)]
+struct MockScale {
+    reads: AtomicUsize,
+    in_use: AtomicBool,
+    panic_next: AtomicBool,
+    hold: Option<Duration>,
+}
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        assert!(
+            !self.in_use.swap(true, Ordering::SeqCst),
+            "overlapping access"
+        );
+        if let Some(hold) = self.hold {
+            thread::sleep(hold);
+        }
+        let n = self.reads.fetch_add(1, Ordering::SeqCst);
+        self.in_use.store(false, Ordering::SeqCst);
+        if self.panic_next.swap(false, Ordering::SeqCst) {
+            panic!("simulated phidget panic");
+        }
+        Ok(Grams(n as f64))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(MedianGrams(self.get_weight()?.get()))
+    }
+}
+
+#[test]
+fn many_threads_serialize_access() {
+    let shared = SharedScale::new(MockScale::default());
+    let threads = 8;
+    let reads_per_thread = 200;
+    let barrier = Arc::new(Barrier::new(threads));
+    let handles: Vec<_> = (0..threads)
+        .map(|_| {
+            let shared = shared.clone();
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                for _ in 0..reads_per_thread {
+                    shared.get_weight().unwrap();
+                    shared.get_median_weight().unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let reads = shared.lock().reads.load(Ordering::SeqCst);
+    assert_eq!(reads, threads * reads_per_thread * 2);
+}
+
+#[test]
+fn try_get_weight_fails_fast_when_locked() {
+    let shared = SharedScale::new(MockScale::default());
+    let guard = shared.lock();
+    let other = shared.clone();
+    let result = thread::spawn(move || other.try_get_weight().map_err(|e| e.to_string()))
+        .join()
+        .unwrap();
+    assert_eq!(result.unwrap_err(), ScaleError::Busy.to_string());
+    drop(guard);
+    assert!(shared.try_get_weight().is_ok());
+}
+
+#[test]
+fn try_get_weight_does_not_wait_for_slow_reader() {
+    let shared = SharedScale::new(MockScale {
+        hold: Some(Duration::from_millis(200)),
+        ..Default::default()
+    });
+    let reader = shared.clone();
+    let slow = thread::spawn(move || reader.get_weight().is_ok());
+    while shared.try_lock().is_some() {
+        thread::yield_now();
+    }
+    let start = std::time::Instant::now();
+    assert!(shared.try_get_weight().is_err());
+    assert!(start.elapsed() < Duration::from_millis(100));
+    assert!(slow.join().unwrap());
+}
+
+#[test]
+fn recovers_from_poisoned_lock() {
+    let shared = SharedScale::new(MockScale::default());
+    shared.lock().panic_next.store(true, Ordering::SeqCst);
+    let other = shared.clone();
+    assert!(thread::spawn(move || other.get_weight().is_ok())
+        .join()
+        .is_err());
+
+    assert!(shared.get_weight().is_ok());
+    assert!(shared.try_get_weight().is_ok());
+    assert_eq!(shared.lock().reads.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn into_inner_requires_last_handle() {
+    let shared = SharedScale::new(MockScale::default());
+    let other = shared.clone();
+    let Err(shared) = shared.into_inner() else {
+        panic!("another handle is still alive");
+    };
+    drop(other);
+    assert!(shared.into_inner().is_ok());
+}
@@ -0,0 +1,298 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use libra::testing::{
+    Creep, ManualClock, Scenario, ScenarioError, SimulatedScale, SimulationConfig,
+};
+use libra::{Grams, Scale};
+
+fn config(load: f64, noise: f64) -> SimulationConfig {
+    SimulationConfig {
+        load: Grams(load),
+        noise,
+        settling: Duration::from_millis(20),
+        data_interval: Duration::from_millis(1),
+        ..SimulationConfig::default()
+    }
+}
+
+/// A scale simulated on a clock of its own, and the clock.
+fn simulated(config: SimulationConfig) -> (SimulatedScale, ManualClock) {
+    let clock = ManualClock::new();
+    let scale = SimulatedScale::with_clock(config, Arc::new(clock.clone()));
+    (scale, clock)
+}
+
+#[test]
+fn medians_converge_on_the_load_within_the_noise() {
+    for (load, noise) in [(500., 1.), (0., 0.5), (2500., 5.)] {
+        let (scale, clock) = simulated(config(load, noise));
+        let median = scale.get_median_weight_of(25).unwrap().get();
+        // The median of 25 samples has a standard error of about σ / 4.
+        assert!(
+            (median - load).abs() < noise,
+            "{median} for {load} ± {noise}"
+        );
+
+        let readings: Vec<f64> = (0..200)
+            .map(|_| {
+                clock.advance(Duration::from_millis(1));
+                scale.get_weight().unwrap().get()
+            })
+            .collect();
+        assert!(readings.iter().any(|reading| *reading != load));
+        assert!(readings
+            .iter()
+            .all(|reading| (reading - load).abs() < 6. * noise));
+    }
+}
+
+#[test]
+fn readings_settle_on_a_new_load() {
+    let (scale, clock) = simulated(SimulationConfig {
+        settling: Duration::from_millis(50),
+        ..config(0., 0.)
+    });
+    scale.set_load(Grams(1000.));
+    assert_eq!(scale.load(), Grams(1000.));
+    assert_eq!(scale.get_weight().unwrap(), Grams(0.));
+
+    // One time constant in, the reading is about 63% of the way there.
+    clock.advance(Duration::from_millis(50));
+    let early = scale.get_weight().unwrap().get();
+    assert!((early - 632.1).abs() < 0.1, "{early}");
+
+    clock.advance(Duration::from_millis(400));
+    let settled = scale.get_median_weight_of(3).unwrap().get();
+    assert!((settled - 1000.).abs() < 1., "{settled}");
+
+    scale.set_load(Grams(0.));
+    clock.advance(Duration::from_millis(400));
+    assert!(scale.get_weight().unwrap().get().abs() < 1.);
+}
+
+#[test]
+fn readings_only_change_once_per_data_interval() {
+    let (scale, clock) = simulated(SimulationConfig {
+        data_interval: Duration::from_secs(60),
+        ..config(100., 2.)
+    });
+    let first = scale.get_weight().unwrap();
+    for _ in 0..10 {
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(scale.get_weight().unwrap(), first);
+    }
+    clock.advance(Duration::from_secs(10));
+    assert_ne!(scale.get_weight().unwrap(), first);
+
+    // A median waits one data interval between samples.
+    let (scale, clock) = simulated(SimulationConfig {
+        data_interval: Duration::from_millis(10),
+        ..config(100., 2.)
+    });
+    scale.get_median_weight_of(5).unwrap();
+    assert_eq!(clock.elapsed(), Duration::from_millis(50));
+}
+
+#[test]
+fn readings_drift() {
+    let (scale, clock) = simulated(SimulationConfig {
+        drift: 100.,
+        ..config(50., 0.)
+    });
+    clock.advance(Duration::from_millis(100));
+    assert!((scale.get_weight().unwrap().get() - 60.).abs() < 1e-9);
+}
+
+#[test]
+fn taring_zeroes_the_reading() {
+    let (mut scale, clock) = simulated(config(300., 0.));
+    assert_eq!(scale.tare(3).unwrap(), Grams(300.));
+    assert_eq!(scale.get_weight().unwrap(), Grams(0.));
+    scale.set_load(Grams(350.));
+    clock.advance(Duration::from_millis(200));
+    assert!((scale.get_weight().unwrap().get() - 50.).abs() < 0.1);
+}
+
+#[test]
+fn the_real_clock_is_the_default() {
+    let scale = SimulatedScale::new(SimulationConfig {
+        data_interval: Duration::from_millis(10),
+        ..config(100., 0.)
+    });
+    let started = std::time::Instant::now();
+    assert_eq!(scale.get_median_weight_of(3).unwrap().get(), 100.);
+    assert!(started.elapsed() >= Duration::from_millis(20));
+}
+
+/// Warms from 20 to 30 °C over ten minutes, then holds.
+fn warming(zero_per_celsius: f64, span_per_celsius: f64) -> Scenario {
+    Scenario {
+        temperature: vec![(Duration::ZERO, 20.), (Duration::from_secs(600), 30.)],
+        zero_per_celsius,
+        span_per_celsius,
+        ..Scenario::default()
+    }
+}
+
+#[test]
+fn the_temperature_follows_its_profile() {
+    let scenario = Scenario {
+        temperature: vec![
+            (Duration::from_secs(10), 15.),
+            (Duration::from_secs(20), 25.),
+            (Duration::from_secs(20), 5.),
+        ],
+        ..Scenario::default()
+    };
+    assert_eq!(scenario.temperature_at(Duration::ZERO), 15.);
+    assert_eq!(scenario.temperature_at(Duration::from_secs(15)), 20.);
+    assert_eq!(scenario.temperature_at(Duration::from_secs(20)), 5.);
+    assert_eq!(scenario.temperature_at(Duration::from_secs(60)), 5.);
+    assert_eq!(Scenario::default().temperature_at(Duration::ZERO), 20.);
+}
+
+#[test]
+fn the_temperature_shifts_the_zero_and_span() {
+    let (scale, clock) = simulated(SimulationConfig {
+        scenario: warming(0.5, 1e-3),
+        ..config(1000., 0.)
+    });
+    assert_eq!(scale.get_weight().unwrap(), Grams(1000.));
+    // 25 °C: 0.5% more span and 2.5 g more zero.
+    clock.advance(Duration::from_secs(300));
+    assert!((scale.get_weight().unwrap().get() - 1007.5).abs() < 1e-9);
+    clock.advance(Duration::from_secs(600));
+    assert!((scale.get_weight().unwrap().get() - 1015.).abs() < 1e-9);
+}
+
+#[test]
+fn loads_creep_after_they_change() {
+    let (scale, clock) = simulated(SimulationConfig {
+        scenario: Scenario {
+            creep: Some(Creep {
+                magnitude: 0.01,
+                time_constant: Duration::from_secs(10),
+            }),
+            ..Scenario::default()
+        },
+        ..config(0., 0.)
+    });
+    scale.set_load(Grams(1000.));
+    // Settled long since, and one time constant of creep in.
+    clock.advance(Duration::from_secs(10));
+    let creep = 10. * (1. - (-1f64).exp());
+    assert!((scale.get_weight().unwrap().get() - 1000. - creep).abs() < 1e-6);
+    clock.advance(Duration::from_secs(60));
+    assert!((scale.get_weight().unwrap().get() - 1010.).abs() < 0.01);
+
+    // Taking the load off, the creep recovers the same way.
+    scale.set_load(Grams(0.));
+    clock.advance(Duration::from_secs(10));
+    let creep = 10. * (-1f64).exp();
+    assert!((scale.get_weight().unwrap().get() - creep).abs() < 0.01);
+}
+
+#[test]
+fn a_scripted_zero_drift_can_be_measured_and_tared_away() {
+    let (mut scale, clock) = simulated(SimulationConfig {
+        scenario: warming(0.2, 0.),
+        ..config(0., 0.5)
+    });
+    // Medians every 30 s through the warm-up, and their least-squares slope.
+    let points: Vec<(f64, f64)> = (0..=20)
+        .map(|_| {
+            let median = scale.get_median_weight_of(25).unwrap().get();
+            let at = clock.elapsed().as_secs_f64();
+            clock.advance(Duration::from_secs(30));
+            (at, median)
+        })
+        .collect();
+    let n = points.len() as f64;
+    let (mean_t, mean_w) = points.iter().fold((0., 0.), |(t, w), (at, median)| {
+        (t + at / n, w + median / n)
+    });
+    let (covariance, variance) = points.iter().fold((0., 0.), |(c, v), (at, median)| {
+        (
+            c + (at - mean_t) * (median - mean_w),
+            v + (at - mean_t).powi(2),
+        )
+    });
+    // 0.2 g/°C at 1 °C a minute.
+    let expected = 0.2 / 60.;
+    let rate = covariance / variance;
+    assert!((rate - expected).abs() < expected / 10., "{rate} g/s");
+
+    // At 30 °C the zero has moved 2 g, which a tare takes out.
+    assert!((scale.get_median_weight_of(25).unwrap().get() - 2.).abs() < 0.5);
+    scale.tare(25).unwrap();
+    clock.advance(Duration::from_secs(60));
+    assert!(scale.get_median_weight_of(25).unwrap().get().abs() < 0.5);
+}
+
+const SCENARIO: &str = r#"
+# Warms up by 10 °C over ten minutes, then holds.
+zero_g_per_celsius = 0.5
+span_per_celsius = 1e-3
+
+[[temperature]]
+at_s = 0
+celsius = 20
+
+[[temperature]]
+at_s = 600
+celsius = 30.0
+
+[creep]
+magnitude = 0.01
+time_constant_s = 2.5
+"#;
+
+#[test]
+fn scenarios_load_from_toml() {
+    let scenario: Scenario = SCENARIO.parse().unwrap();
+    assert_eq!(
+        scenario,
+        Scenario {
+            creep: Some(Creep {
+                magnitude: 0.01,
+                time_constant: Duration::from_millis(2500),
+            }),
+            ..warming(0.5, 1e-3)
+        }
+    );
+    assert_eq!(Scenario::from_toml("").unwrap(), Scenario::default());
+}
+
+#[test]
+fn bad_scenarios_name_the_line() {
+    let error = |toml: &str| Scenario::from_toml(toml).unwrap_err();
+    assert_eq!(
+        error("[[temperature]]\nat_s = 10\ncelsius = 1\n[[temperature]]\nat_s = 5\ncelsius = 2"),
+        ScenarioError {
+            line: 4,
+            message: "temperature points must be in time order".into(),
+        }
+    );
+    assert_eq!(error("[[temperature]]\nat_s = 10").line, 1);
+    assert_eq!(error("reference_celsius = \"warm\"").line, 1);
+    assert_eq!(error("\n[creep]\nmagnitude = 1\nsettling = 2").line, 4);
+    assert_eq!(error("[creep]\n[creep]").line, 2);
+    assert_eq!(error("[[temperature]]\nat_s = -1\ncelsius = 1").line, 2);
+    assert_eq!(error("[drift]").line, 1);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn async_medians_converge_too() {
+    use libra::AsyncScale;
+
+    let (scale, clock) = simulated(config(750., 1.));
+    let median = AsyncScale::get_median_weight(&scale, 25)
+        .await
+        .unwrap()
+        .get();
+    assert!((median - 750.).abs() < 1., "{median}");
+    assert_eq!(clock.elapsed(), Duration::from_millis(25));
+    assert!(AsyncScale::get_median_weight(&scale, 0).await.is_err());
+}
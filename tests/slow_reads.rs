@@ -0,0 +1,134 @@
+#![cfg(feature = "hardware")]
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use libra::calibration::Calibration;
+use libra::scale::{ConnectedScale, NUMBER_OF_INPUTS};
+use libra::source::VoltageSource;
+use libra::testing::{FakeVoltageSource, Latency, ManualClock};
+
+const SERIAL: i32 = 716_000;
+const CALIBRATION: Calibration = Calibration {
+    offset: 0.,
+    coefficients: [1000.; NUMBER_OF_INPUTS],
+};
+
+const MS: Duration = Duration::from_millis(1);
+
+/// A scale whose load cells take the given latencies, all on `clock`.
+fn scale(
+    clock: &ManualClock,
+    latencies: [Duration; NUMBER_OF_INPUTS],
+) -> (
+    ConnectedScale<FakeVoltageSource>,
+    [FakeVoltageSource; NUMBER_OF_INPUTS],
+) {
+    let sources = FakeVoltageSource::bridge(SERIAL);
+    for (source, latency) in sources.iter().zip(latencies) {
+        source.set_clock(Arc::new(clock.clone()));
+        source.set_latency(latency);
+    }
+    let mut scale = ConnectedScale::from_sources(SERIAL, CALIBRATION, sources.clone());
+    scale.set_clock(Arc::new(clock.clone()));
+    (scale, sources)
+}
+
+/// How long each of `reads` reads of `source` took on `clock`.
+fn read_times(source: &FakeVoltageSource, clock: &ManualClock, reads: usize) -> Vec<Duration> {
+    (0..reads)
+        .map(|_| {
+            let before = clock.elapsed();
+            source.ratio().unwrap();
+            clock.elapsed() - before
+        })
+        .collect()
+}
+
+fn source_on(clock: &ManualClock, latency: Latency) -> FakeVoltageSource {
+    let source = FakeVoltageSource::new(SERIAL);
+    source.set_clock(Arc::new(clock.clone()));
+    source.set_latency(latency);
+    source
+}
+
+#[test]
+fn reads_spend_their_latency_on_the_clock() {
+    let clock = ManualClock::new();
+    let (scale, sources) = scale(&clock, [2 * MS; NUMBER_OF_INPUTS]);
+    let started = Instant::now();
+    scale.get_weight().unwrap();
+    assert_eq!(clock.elapsed(), 8 * MS);
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert!(sources.iter().all(|source| source.reads() == 1));
+}
+
+#[test]
+fn uniform_latencies_stay_in_range_and_repeat_by_seed() {
+    let clock = ManualClock::new();
+    let uniform = Latency::Uniform {
+        min: MS,
+        max: 5 * MS,
+        seed: 7,
+    };
+    let times = read_times(&source_on(&clock, uniform.clone()), &clock, 100);
+    assert!(times.iter().all(|time| (MS..=5 * MS).contains(time)));
+    assert!(times.iter().any(|time| *time < 2 * MS));
+    assert!(times.iter().any(|time| *time > 4 * MS));
+    assert_eq!(read_times(&source_on(&clock, uniform), &clock, 100), times);
+}
+
+#[test]
+fn scripted_latencies_go_by_call() {
+    let clock = ManualClock::new();
+    let source = source_on(
+        &clock,
+        Latency::Scripted(vec![3 * MS, Duration::ZERO, 7 * MS]),
+    );
+    assert_eq!(
+        read_times(&source, &clock, 5),
+        [
+            3 * MS,
+            Duration::ZERO,
+            7 * MS,
+            Duration::ZERO,
+            Duration::ZERO
+        ]
+    );
+}
+
+#[test]
+fn slow_reads_push_median_samples_back() {
+    let clock = ManualClock::new();
+    // Each weight takes 20 ms to read, more than the 10 ms between samples.
+    let (scale, sources) = scale(&clock, [5 * MS; NUMBER_OF_INPUTS]);
+    scale.get_median_weight(5, 10 * MS).unwrap();
+    // Every sample waits its full interval after the last read finished,
+    // rather than catching up on the time the reads took.
+    assert_eq!(clock.elapsed(), 5 * (10 * MS + 20 * MS));
+    assert!(sources.iter().all(|source| source.reads() == 5));
+
+    let before = clock.elapsed();
+    scale.get_load_cell_medians(3, 10 * MS).unwrap();
+    assert_eq!(clock.elapsed() - before, 3 * (20 * MS + 10 * MS));
+}
+
+#[test]
+fn a_slow_channel_spreads_out_a_snapshot() {
+    // The load cells are read one after another, so the readings combined
+    // into one weight are as far apart in time as the reads take. One load
+    // cell ten times slower than the rest stretches that from 3 ms to 12 ms.
+    for (slow, spread) in [(MS, 3 * MS), (10 * MS, 12 * MS)] {
+        let clock = ManualClock::new();
+        let (scale, sources) = scale(&clock, [MS, MS, slow, MS]);
+        scale.get_weight().unwrap();
+        let read_at: Vec<Instant> = sources
+            .iter()
+            .map(|source| source.last_read_at().unwrap())
+            .collect();
+        let first = read_at.iter().min().unwrap();
+        let last = read_at.iter().max().unwrap();
+        assert_eq!(*last - *first, spread, "slow channel {slow:?}");
+        assert_eq!(read_at[3], *last);
+    }
+}
@@ -0,0 +1,246 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use libra::scale::ScaleError;
+use libra::soak::{soak, soak_on, ErrorCount, SoakConfig};
+use libra::testing::{Latency, ManualClock, MockScale, Scenario, SimulatedScale, SimulationConfig};
+use libra::watchdog::StaleChannel;
+use libra::{Grams, MedianGrams, Scale, ScaleErrorKind};
+
+fn config(duration: Duration) -> SoakConfig {
+    SoakConfig {
+        duration,
+        sample_interval: Duration::from_millis(100),
+        ..SoakConfig::default()
+    }
+}
+
+/// Warms from 20 to 30 °C over the first ten seconds, moving the zero by
+/// 0.2 g per °C.
+fn warming() -> Scenario {
+    Scenario {
+        temperature: vec![(Duration::ZERO, 20.), (Duration::from_secs(10), 30.)],
+        zero_per_celsius: 0.2,
+        ..Scenario::default()
+    }
+}
+
+fn simulation() -> SimulationConfig {
+    SimulationConfig {
+        load: Grams(500.),
+        noise: 0.5,
+        scenario: warming(),
+        ..SimulationConfig::default()
+    }
+}
+
+/// Reports a frozen load cell while its mock has served between `from` and
+/// `to` reads.
+struct Freezing {
+    scale: MockScale,
+    from: usize,
+    to: usize,
+}
+
+impl Scale for Freezing {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        self.scale.get_weight()
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        self.scale.get_median_weight()
+    }
+
+    fn stale_channel(&self) -> Option<StaleChannel> {
+        (self.from..self.to)
+            .contains(&self.scale.reads())
+            .then_some(StaleChannel {
+                channel: 2,
+                age: Duration::from_secs(60),
+            })
+    }
+}
+
+#[test]
+fn a_simulated_run_measures_drift_and_noise() {
+    let clock = ManualClock::new();
+    let scale = SimulatedScale::with_clock(simulation(), Arc::new(clock.clone()));
+    let config = SoakConfig {
+        stable_tolerance: 2.5,
+        ..config(Duration::from_secs(30))
+    };
+    let report = soak_on(&clock, &scale, &config).unwrap();
+
+    assert_eq!(report.reads, 300);
+    assert_eq!(report.failed_reads, 0);
+    assert_eq!(
+        report.duration,
+        Duration::from_secs(30) - Duration::from_millis(100)
+    );
+    // 10 °C at 0.2 g/°C, within what the noise on two medians of five allows.
+    let drift = report.drift.unwrap();
+    assert!((drift - 2.).abs() < 0.6, "{drift} g");
+    // Half the 0.5 g standard deviation of the noise lies within 0.34 g.
+    let noise = report.noise.unwrap();
+    assert!((0.2..0.5).contains(&noise.p50), "{noise:?}");
+    assert!(noise.p50 <= noise.p90 && noise.p90 <= noise.p99 && noise.p99 <= noise.max);
+    assert!(report.stable_fraction > 0.8, "{}", report.stable_fraction);
+    assert!(report.errors.is_empty());
+    assert_eq!(report.worst_overrun, Duration::ZERO);
+}
+
+#[test]
+fn noise_percentiles_are_those_of_the_sorted_differences() {
+    for reads in [2, 3, 10, 101, 1000] {
+        let clock = ManualClock::new();
+        let mut state = 0x5eed_u64;
+        let weights: Vec<f64> = (0..reads)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1);
+                (state >> 40) as f64 % 97. / 8.
+            })
+            .collect();
+        let scale = MockScale::new(Grams(0.))
+            .returns(weights.iter().map(|&w| Ok(Grams(w))))
+            .with_clock(Arc::new(clock.clone()));
+        let report = soak_on(&clock, &scale, &config(Duration::from_millis(100) * reads)).unwrap();
+        assert_eq!(report.reads, u64::from(reads));
+
+        let mut noise: Vec<f64> = weights
+            .windows(2)
+            .map(|pair| (pair[1] - pair[0]).abs() / std::f64::consts::SQRT_2)
+            .collect();
+        noise.sort_by(f64::total_cmp);
+        let percentile = |p: f64| noise[((noise.len() - 1) as f64 * p).round() as usize];
+        let measured = report.noise.unwrap();
+        assert_eq!(
+            [measured.p50, measured.p90, measured.p99, measured.max],
+            [
+                percentile(0.5),
+                percentile(0.9),
+                percentile(0.99),
+                noise[noise.len() - 1]
+            ],
+            "{reads} reads"
+        );
+    }
+}
+
+#[test]
+fn errors_and_reconnects_are_counted() {
+    let clock = ManualClock::new();
+    let script = [
+        Ok(Grams(1.)),
+        Err(ScaleError::Disconnected { channel: Some(1) }),
+        Err(ScaleError::Disconnected { channel: Some(1) }),
+        Ok(Grams(1.)),
+        Err(ScaleError::Busy),
+        Ok(Grams(1.)),
+        Err(ScaleError::Disconnected { channel: None }),
+    ];
+    let scale = MockScale::new(Grams(1.))
+        .returns(script)
+        .with_clock(Arc::new(clock.clone()));
+    let report = soak_on(&clock, &scale, &config(Duration::from_secs(1))).unwrap();
+
+    assert_eq!(report.reads, 10);
+    assert_eq!(report.failed_reads, 4);
+    assert_eq!(
+        report.errors,
+        [
+            ErrorCount {
+                kind: ScaleErrorKind::Disconnected,
+                count: 3,
+            },
+            ErrorCount {
+                kind: ScaleErrorKind::Busy,
+                count: 1,
+            },
+        ]
+    );
+    // Busy is not a disconnection, so only two recoveries count.
+    assert_eq!(report.reconnects, 2);
+    assert_eq!(
+        report.last_error.unwrap().kind,
+        ScaleErrorKind::Disconnected
+    );
+}
+
+#[test]
+fn the_worst_overrun_is_how_late_a_read_started() {
+    let clock = ManualClock::new();
+    let mut latencies = vec![Duration::from_millis(10); 20];
+    latencies[4] = Duration::from_millis(350);
+    let scale = MockScale::new(Grams(1.))
+        .with_latency(Latency::Scripted(latencies))
+        .with_clock(Arc::new(clock.clone()));
+    let report = soak_on(&clock, &scale, &config(Duration::from_secs(2))).unwrap();
+    assert_eq!(report.worst_overrun, Duration::from_millis(250));
+    assert_eq!(report.drift, Some(0.));
+    assert_eq!(
+        report.stable_fraction,
+        (report.reads - 4) as f64 / report.reads as f64
+    );
+}
+
+#[test]
+fn watchdog_episodes_count_once() {
+    let clock = ManualClock::new();
+    let scale = Freezing {
+        scale: MockScale::new(Grams(1.)),
+        from: 3,
+        to: 6,
+    };
+    let report = soak_on(&clock, &scale, &config(Duration::from_secs(1))).unwrap();
+    assert_eq!(report.watchdog_trips, 1);
+}
+
+#[test]
+fn the_report_reads_as_text() {
+    let clock = ManualClock::new();
+    let scale = MockScale::new(Grams(1.))
+        .returns([Err(ScaleError::Busy)])
+        .with_clock(Arc::new(clock.clone()));
+    let report = soak_on(&clock, &scale, &config(Duration::from_secs(2))).unwrap();
+    assert_eq!(
+        report.to_string(),
+        "Duration: 1.9s\n\
+         Reads: 19 of 20 succeeded\n\
+         Stable: 78.9% of readings\n\
+         Drift: +0.000 g\n\
+         Noise: p50 0.000 g, p90 0.000 g, p99 0.000 g, max 0.000 g\n\
+         Errors:\n  - Busy: 1\n\
+         Reconnects: 0\n\
+         Watchdog trips: 0\n\
+         Worst overrun: 0ns\n\
+         Last error: Scale is busy"
+    );
+}
+
+#[test]
+fn a_zero_interval_is_refused() {
+    let scale = MockScale::new(Grams(1.));
+    let config = SoakConfig {
+        sample_interval: Duration::ZERO,
+        ..config(Duration::from_secs(1))
+    };
+    assert!(matches!(
+        soak(&scale, &config),
+        Err(ScaleError::InvalidArgument(_))
+    ));
+}
+
+/// Thirty seconds of the simulator warming up, on the real clock. Run with
+/// `cargo test --test soak -- --ignored`.
+#[test]
+#[ignore]
+fn thirty_second_simulated_soak() {
+    let scale = SimulatedScale::new(simulation());
+    let report = soak(&scale, &config(Duration::from_secs(30))).unwrap();
+    println!("{report}");
+    assert_eq!(report.failed_reads, 0);
+    assert!((report.drift.unwrap() - 2.).abs() < 1.);
+    assert!(report.worst_overrun < Duration::from_millis(100));
+}
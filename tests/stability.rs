@@ -0,0 +1,30 @@
+use libra::stability::{StabilityDetector, DEFAULT_STABLE_WINDOW};
+
+#[test]
+fn stable_once_a_full_window_agrees() {
+    let mut detector = StabilityDetector::new(3, 0.5);
+    assert!(!detector.push(10.));
+    assert!(!detector.push(10.2));
+    assert!(detector.push(10.4));
+    assert!((detector.spread() - 0.4).abs() < 1e-9);
+
+    // A jump unsettles it until the window has moved past it.
+    assert!(!detector.push(20.));
+    assert!(!detector.push(20.1));
+    assert!(detector.push(20.2));
+
+    detector.reset();
+    assert!(!detector.is_stable());
+    assert_eq!(detector.spread(), 0.);
+}
+
+#[test]
+fn the_default_window_needs_several_readings() {
+    let mut detector = StabilityDetector::default();
+    for _ in 1..DEFAULT_STABLE_WINDOW {
+        assert!(!detector.push(0.));
+    }
+    assert!(detector.push(0.));
+    // Windows of zero still need one reading.
+    assert!(StabilityDetector::new(0, 0.).push(5.));
+}
@@ -0,0 +1,174 @@
+use libra::stability::StabilityDetector;
+use libra::{median, Grams, MedianGrams};
+use proptest::prelude::*;
+use proptest::sample::select;
+
+/// A finite weight of any magnitude, small integers often enough for
+/// duplicates to be common.
+fn value() -> impl Strategy<Value = f64> {
+    prop_oneof![
+        1 => (-2..=2).prop_map(f64::from),
+        1 => select(vec![
+            0.,
+            -0.,
+            f64::MIN_POSITIVE,
+            -f64::MIN_POSITIVE,
+            5e-324
+        ]),
+        1 => select(vec![f64::MAX, f64::MIN, 1e300, -1e300]),
+        2 => any::<f64>().prop_filter("finite", |value| value.is_finite()),
+    ]
+}
+
+/// Finite weights, from 1 to 64 of them: at random, all equal, sorted or
+/// reverse sorted.
+fn weights() -> impl Strategy<Value = Vec<f64>> {
+    let weights = prop_oneof![
+        1 => (value(), 1..=64usize).prop_map(|(value, len)| vec![value; len]),
+        3 => prop::collection::vec(value(), 1..=64),
+    ];
+    (weights, 0..4u8).prop_map(|(mut weights, order)| {
+        match order {
+            0 => weights.sort_by(f64::total_cmp),
+            1 => weights.sort_by(|a, b| b.total_cmp(a)),
+            _ => {}
+        }
+        weights
+    })
+}
+
+/// A NaN or a negative NaN, which order above and below every weight.
+fn nan() -> impl Strategy<Value = f64> {
+    select(vec![f64::NAN, -f64::NAN])
+}
+
+fn median_of(weights: &[f64]) -> MedianGrams {
+    let mut weights: Vec<Grams> = weights.iter().copied().map(Grams).collect();
+    median(&mut weights)
+}
+
+/// The upper median as it used to be taken, sorting every weight, to check
+/// the one that only moves as many as it takes against.
+fn sorted_median(weights: &[f64]) -> f64 {
+    let mut sorted = weights.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    sorted.get(sorted.len() / 2).copied().unwrap_or(f64::NAN)
+}
+
+fn min_max(weights: &[f64]) -> (f64, f64) {
+    weights
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), &w| {
+            (low.min(w), high.max(w))
+        })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(2000))]
+
+    #[test]
+    fn the_median_is_an_element_between_the_extremes(weights in weights()) {
+        let middle = median_of(&weights).get();
+        prop_assert!(weights.iter().any(|w| w.to_bits() == middle.to_bits()));
+        let (low, high) = min_max(&weights);
+        prop_assert!(low <= middle && middle <= high);
+    }
+
+    #[test]
+    fn the_median_splits_the_weights_in_half(weights in weights()) {
+        let middle = median_of(&weights).get();
+        let below = weights.iter().filter(|w| **w < middle).count();
+        let above = weights.iter().filter(|w| **w > middle).count();
+        // The upper median of an even number of weights: half are below it,
+        // counting ties either way.
+        prop_assert!(below <= weights.len() / 2);
+        prop_assert!(above < weights.len().div_ceil(2));
+    }
+
+    #[test]
+    fn the_median_ignores_the_order_of_the_weights(
+        (weights, shuffled) in weights().prop_flat_map(|weights| {
+            (Just(weights.clone()), Just(weights).prop_shuffle())
+        })
+    ) {
+        prop_assert_eq!(
+            median_of(&shuffled).get().to_bits(),
+            median_of(&weights).get().to_bits()
+        );
+    }
+
+    #[test]
+    fn the_median_of_equal_weights_is_that_weight(value in value(), len in 1..=64usize) {
+        prop_assert_eq!(median_of(&vec![value; len]).get().to_bits(), value.to_bits());
+    }
+
+    #[test]
+    fn nans_only_win_the_median_by_outnumbering_the_weights(
+        weights in (weights(), 0..8usize, 0..8usize).prop_flat_map(|(mut weights, above, below)| {
+            // NaN sorts above every weight, and a negative NaN below.
+            weights.extend(std::iter::repeat_n(f64::NAN, above));
+            weights.extend(std::iter::repeat_n(-f64::NAN, below));
+            (Just(weights).prop_shuffle(), Just(above), Just(below))
+        })
+    ) {
+        let (weights, above, below) = weights;
+        let middle = median_of(&weights).get();
+        let len = weights.len();
+        prop_assert_eq!(middle.is_finite(), below <= len / 2 && above < len.div_ceil(2));
+    }
+
+    #[test]
+    fn the_median_is_the_middle_of_the_sorted_weights(
+        weights in (
+            weights(),
+            // Now and then as many as a burst for noise analysis takes.
+            prop_oneof![9 => Just(Vec::new()), 1 => prop::collection::vec(value(), 0..1000)],
+            prop::collection::vec(nan(), 0..4),
+        )
+            .prop_flat_map(|(mut weights, more, nans)| {
+                weights.extend(more);
+                weights.extend(nans);
+                Just(weights).prop_shuffle()
+            })
+    ) {
+        prop_assert_eq!(median_of(&weights).get().to_bits(), sorted_median(&weights).to_bits());
+    }
+
+    #[test]
+    fn the_spread_is_the_range_of_the_window(
+        weights in weights(),
+        window in 1..=8usize,
+        tolerance in (0..4u8).prop_map(f64::from),
+    ) {
+        let weights: Vec<f64> = weights.into_iter().map(|w| w.clamp(-1e150, 1e150)).collect();
+        let mut detector = StabilityDetector::new(window, tolerance);
+        for (pushed, &weight) in weights.iter().enumerate() {
+            let stable = detector.push(weight);
+            let recent = &weights[(pushed + 1).saturating_sub(window)..=pushed];
+            let (low, high) = min_max(recent);
+            prop_assert_eq!(detector.spread(), high - low, "{:?}", recent);
+            prop_assert!(detector.spread() >= 0.);
+            prop_assert_eq!(
+                stable,
+                recent.len() == window && high - low <= tolerance,
+                "{:?} within {}",
+                recent,
+                tolerance
+            );
+        }
+    }
+}
+
+/// The median of an even number of weights is the upper of the middle two,
+/// not their mean, so that it is always a weight that was read.
+#[test]
+fn the_median_of_an_even_number_is_the_upper_middle_weight() {
+    assert_eq!(median_of(&[1., 2.]), MedianGrams(2.));
+    assert_eq!(median_of(&[3., 1., 2., 4.]), MedianGrams(3.));
+}
+
+#[test]
+fn an_empty_or_all_nan_median_is_nan() {
+    assert!(median_of(&[]).get().is_nan() && sorted_median(&[]).is_nan());
+    assert!(median_of(&[f64::NAN]).get().is_nan());
+}
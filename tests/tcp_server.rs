@@ -0,0 +1,396 @@
+#![cfg(feature = "net")]
+
+use std::time::Duration;
+
+use libra::actor::{spawn_scale_actor, ScaleHandle};
+use libra::cancel::CancelFlag;
+use libra::manager::ScaleManager;
+use libra::net::{serve_fleet, serve_listener, ServerConfig, DEFAULT_SCALE_NAME};
+use libra::{Grams, ListedScale, MedianGrams, Scale, ScaleErrorKind, ScaleResponse, ScaleState};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Reads 10 g; a median of `n` samples reads `n` g.
+struct MockScale;
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(10.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+
+    fn get_median_weight_cancellable(
+        &self,
+        samples: usize,
+        _cancel: &CancelFlag,
+    ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        std::thread::sleep(Duration::from_millis(5));
+        Ok(MedianGrams(samples as f64))
+    }
+}
+
+async fn start(config: ServerConfig) -> (ScaleHandle, std::net::SocketAddr) {
+    let (handle, _task) = spawn_scale_actor(MockScale);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve_listener(handle.clone(), listener, config));
+    (handle, addr)
+}
+
+async fn connect(addr: std::net::SocketAddr) -> (Lines<BufReader<OwnedReadHalf>>, OwnedWriteHalf) {
+    let (read, write) = TcpStream::connect(addr).await.unwrap().into_split();
+    (BufReader::new(read).lines(), write)
+}
+
+async fn response(lines: &mut Lines<BufReader<OwnedReadHalf>>) -> ScaleResponse {
+    let line = lines.next_line().await.unwrap().expect("connection closed");
+    serde_json::from_str(&line).unwrap()
+}
+
+async fn raw_response(lines: &mut Lines<BufReader<OwnedReadHalf>>) -> Value {
+    let line = lines.next_line().await.unwrap().expect("connection closed");
+    serde_json::from_str(&line).unwrap()
+}
+
+#[tokio::test]
+async fn answers_commands_in_order() {
+    let (_handle, addr) = start(ServerConfig::default()).await;
+    let (mut lines, mut write) = connect(addr).await;
+
+    write
+        .write_all(
+            b"{\"GetMedianWeight\":{\"samples\":3}}\n\"GetWeight\"\n{\"GetMedianWeight\":{\"samples\":1}}\n",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        response(&mut lines).await,
+        ScaleResponse::MedianWeight(MedianGrams(3.))
+    );
+    assert_eq!(
+        response(&mut lines).await,
+        ScaleResponse::Weight(Grams(10.))
+    );
+    assert_eq!(
+        response(&mut lines).await,
+        ScaleResponse::MedianWeight(MedianGrams(1.))
+    );
+}
+
+#[tokio::test]
+async fn garbage_gets_an_error_and_the_connection_stays_open() {
+    let (_handle, addr) = start(ServerConfig::default()).await;
+    let (mut lines, mut write) = connect(addr).await;
+
+    write.write_all(b"not json at all\n").await.unwrap();
+    let ScaleResponse::Error(error) = response(&mut lines).await else {
+        panic!("expected an error");
+    };
+    assert_eq!(error.kind, ScaleErrorKind::InvalidCommand);
+
+    write.write_all(b"{\"Teleport\":{}}\n\n").await.unwrap();
+    assert_eq!(
+        response(&mut lines).await,
+        ScaleResponse::Unsupported {
+            command: "Teleport".into()
+        }
+    );
+
+    write.write_all(b"\"GetWeight\"\n").await.unwrap();
+    assert_eq!(
+        response(&mut lines).await,
+        ScaleResponse::Weight(Grams(10.))
+    );
+}
+
+#[tokio::test]
+async fn an_overlong_line_gets_an_error_and_the_connection_closes() {
+    let (_handle, addr) = start(ServerConfig {
+        max_line_length: 16,
+        ..Default::default()
+    })
+    .await;
+    let (mut lines, mut write) = connect(addr).await;
+
+    write.write_all(b"\"GetWeight\"     \n").await.unwrap();
+    assert_eq!(
+        response(&mut lines).await,
+        ScaleResponse::Weight(Grams(10.))
+    );
+
+    // Without a line ending, so the server has read all of it when it closes.
+    write.write_all(b"\"GetWeight\"      ").await.unwrap();
+    let ScaleResponse::Error(error) = response(&mut lines).await else {
+        panic!("expected an error");
+    };
+    assert_eq!(error.kind, ScaleErrorKind::InvalidCommand);
+    assert_eq!(error.message, "Lines are limited to 16 bytes");
+    assert!(lines.next_line().await.unwrap().is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn serves_clients_simultaneously() {
+    let (_handle, addr) = start(ServerConfig {
+        max_in_flight: 2,
+        ..Default::default()
+    })
+    .await;
+
+    let clients: Vec<_> = (1..=5)
+        .map(|client| {
+            tokio::spawn(async move {
+                let (mut lines, mut write) = connect(addr).await;
+                let commands: String = (0..10)
+                    .map(|i| {
+                        format!(
+                            "{{\"GetMedianWeight\":{{\"samples\":{}}}}}\n",
+                            client * 100 + i
+                        )
+                    })
+                    .collect();
+                write.write_all(commands.as_bytes()).await.unwrap();
+                for i in 0..10 {
+                    assert_eq!(
+                        response(&mut lines).await,
+                        ScaleResponse::MedianWeight(MedianGrams((client * 100 + i) as f64))
+                    );
+                }
+            })
+        })
+        .collect();
+    for client in clients {
+        client.await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn idle_connections_are_closed() {
+    let (_handle, addr) = start(ServerConfig {
+        idle_timeout: Duration::from_millis(50),
+        ..Default::default()
+    })
+    .await;
+    let (mut lines, _write) = connect(addr).await;
+
+    let closed = tokio::time::timeout(Duration::from_secs(2), lines.next_line()).await;
+    assert!(matches!(closed, Ok(Ok(None))));
+}
+
+#[tokio::test]
+async fn server_stops_with_the_actor() {
+    let (handle, _task) = spawn_scale_actor(MockScale);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let server = tokio::spawn(serve_listener(
+        handle.clone(),
+        listener,
+        ServerConfig::default(),
+    ));
+
+    handle.shutdown().await;
+    tokio::time::timeout(Duration::from_secs(1), server)
+        .await
+        .expect("server kept running")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn requests_are_answered_under_their_ids() {
+    let (_handle, addr) = start(ServerConfig::default()).await;
+    let (mut lines, mut write) = connect(addr).await;
+
+    write
+        .write_all(
+            b"{\"id\":7,\"cmd\":{\"GetMedianWeight\":{\"samples\":3}}}\n\"GetWeight\"\n\
+              {\"id\":8,\"cmd\":\"Frobnicate\"}\n{\"id\":9,\"cmd\":{\"Tare\":{}}}\n",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        raw_response(&mut lines).await,
+        json!({"id": 7, "response": {"MedianWeight": 3.}})
+    );
+    assert_eq!(raw_response(&mut lines).await, json!({"Weight": 10.}));
+    assert_eq!(
+        raw_response(&mut lines).await,
+        json!({"id": 8, "response": {"Unsupported": {"command": "Frobnicate"}}})
+    );
+    let invalid = raw_response(&mut lines).await;
+    assert_eq!(invalid["id"], 9);
+    assert_eq!(invalid["response"]["Error"]["kind"], "InvalidCommand");
+}
+
+/// Reads `weight` g.
+struct Fixed(f64);
+
+impl Scale for Fixed {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(self.0))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(MedianGrams(self.0))
+    }
+}
+
+async fn start_fleet(manager: &ScaleManager<Fixed>) -> std::net::SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(serve_fleet(manager, listener, ServerConfig::default()));
+    addr
+}
+
+#[tokio::test]
+async fn commands_go_to_the_scale_they_name() {
+    let manager = ScaleManager::default();
+    manager.add("flour", Fixed(100.)).unwrap();
+    manager.add("rice", Fixed(200.)).unwrap();
+    let (mut lines, mut write) = connect(start_fleet(&manager).await).await;
+
+    write
+        .write_all(
+            b"{\"id\":1,\"scale\":\"rice\",\"cmd\":\"GetWeight\"}\n\
+              {\"scale\":\"flour\",\"cmd\":\"GetWeight\"}\n\
+              {\"id\":2,\"cmd\":\"GetWeight\"}\n\
+              {\"id\":3,\"scale\":\"sugar\",\"cmd\":{\"Tare\":{\"samples\":3}}}\n",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        raw_response(&mut lines).await,
+        json!({"id": 1, "response": {"Weight": 200.}})
+    );
+    assert_eq!(raw_response(&mut lines).await, json!({"Weight": 100.}));
+    // With two scales, one must be named.
+    assert_eq!(
+        raw_response(&mut lines).await,
+        json!({"id": 2, "response": {"UnknownScale": {
+            "name": null,
+            "available": ["flour", "rice"],
+        }}})
+    );
+    assert_eq!(
+        raw_response(&mut lines).await,
+        json!({"id": 3, "response": {"UnknownScale": {
+            "name": "sugar",
+            "available": ["flour", "rice"],
+        }}})
+    );
+
+    write
+        .write_all(b"{\"scale\":\"sugar\",\"cmd\":\"ListScales\"}\n")
+        .await
+        .unwrap();
+    assert_eq!(
+        response(&mut lines).await,
+        ScaleResponse::Scales(vec![
+            ListedScale {
+                name: "flour".into(),
+                state: ScaleState::Connected,
+                data_interval_ms: None,
+            },
+            ListedScale {
+                name: "rice".into(),
+                state: ScaleState::Connected,
+                data_interval_ms: None,
+            },
+        ])
+    );
+}
+
+#[tokio::test]
+async fn commands_that_name_no_scale_go_to_the_only_one() {
+    let manager = ScaleManager::default();
+    manager.add("flour", Fixed(100.)).unwrap();
+    let (mut lines, mut write) = connect(start_fleet(&manager).await).await;
+
+    write
+        .write_all(b"\"GetWeight\"\n{\"Hello\":{\"client_version\":1}}\n")
+        .await
+        .unwrap();
+    assert_eq!(
+        response(&mut lines).await,
+        ScaleResponse::Weight(Grams(100.))
+    );
+    assert_eq!(response(&mut lines).await, ScaleResponse::hello_ack());
+
+    // Scales added later are served too, and the server stops guessing.
+    manager.add("rice", Fixed(200.)).unwrap();
+    write
+        .write_all(b"\"GetWeight\"\n{\"scale\":\"rice\",\"cmd\":\"GetWeight\"}\n")
+        .await
+        .unwrap();
+    assert!(matches!(
+        response(&mut lines).await,
+        ScaleResponse::UnknownScale { name: None, .. }
+    ));
+    assert_eq!(
+        response(&mut lines).await,
+        ScaleResponse::Weight(Grams(200.))
+    );
+}
+
+#[tokio::test]
+async fn a_status_that_names_no_scale_is_the_fleets() {
+    let manager = ScaleManager::default();
+    manager.add("flour", Fixed(100.)).unwrap();
+    manager.add("rice", Fixed(200.)).unwrap();
+    let (mut lines, mut write) = connect(start_fleet(&manager).await).await;
+
+    write
+        .write_all(
+            b"{\"id\":1,\"cmd\":\"GetStatus\"}\n{\"scale\":\"rice\",\"cmd\":\"GetStatus\"}\n",
+        )
+        .await
+        .unwrap();
+    let fleet = raw_response(&mut lines).await;
+    assert_eq!(fleet["id"], 1);
+    let fleet = &fleet["response"]["FleetStatus"];
+    assert_eq!(fleet["status"], "Unusable");
+    let scales = fleet["scales"].as_array().unwrap();
+    assert_eq!(scales.len(), 2);
+    assert_eq!(scales[0]["name"], "flour");
+    assert_eq!(scales[1]["state"], "Connected");
+    // Fixed has no raw readings to judge its health by.
+    assert_eq!(scales[1]["health"]["Err"]["kind"], "Unsupported");
+    // A named scale answers for itself.
+    let ScaleResponse::Error(error) = response(&mut lines).await else {
+        panic!("expected the scale's own status");
+    };
+    assert_eq!(error.kind, ScaleErrorKind::Unsupported);
+}
+
+#[tokio::test]
+async fn a_single_scale_server_lists_its_scale() {
+    let (_handle, addr) = start(ServerConfig::default()).await;
+    let (mut lines, mut write) = connect(addr).await;
+
+    let request = format!(
+        "\"ListScales\"\n{{\"id\":1,\"scale\":\"{DEFAULT_SCALE_NAME}\",\"cmd\":\"GetWeight\"}}\n\
+         {{\"scale\":\"flour\",\"cmd\":\"GetWeight\"}}\n"
+    );
+    write.write_all(request.as_bytes()).await.unwrap();
+    assert_eq!(
+        response(&mut lines).await,
+        ScaleResponse::Scales(vec![ListedScale {
+            name: DEFAULT_SCALE_NAME.into(),
+            state: ScaleState::Connected,
+            data_interval_ms: None,
+        }])
+    );
+    assert_eq!(
+        raw_response(&mut lines).await,
+        json!({"id": 1, "response": {"Weight": 10.}})
+    );
+    assert_eq!(
+        response(&mut lines).await,
+        ScaleResponse::UnknownScale {
+            name: Some("flour".into()),
+            available: vec![DEFAULT_SCALE_NAME.into()],
+        }
+    );
+}
@@ -0,0 +1,57 @@
+#![cfg(feature = "tokio")]
+
+use std::time::Duration;
+
+use libra::timeout::{TimeoutScale, TimeoutScaleError, DEFAULT_SCALE_TIMEOUT};
+use libra::{AsyncScale, AsyncScaleError, Grams, MedianGrams};
+
+/// Never answers a weight request; medians fail straight away.
+struct HungScale;
+
+impl AsyncScale for HungScale {
+    async fn get_weight(&self) -> Result<Grams, AsyncScaleError> {
+        std::future::pending().await
+    }
+
+    async fn get_median_weight(&self, _samples: usize) -> Result<MedianGrams, AsyncScaleError> {
+        Err("load cell unplugged".into())
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn per_call_timeout_fires() {
+    let scale = TimeoutScale::new(HungScale);
+    let timeout = Duration::from_millis(250);
+    let start = tokio::time::Instant::now();
+
+    let result = scale.get_weight_timeout(timeout).await;
+    assert!(matches!(result, Err(TimeoutScaleError::Elapsed(t)) if t == timeout));
+    assert_eq!(start.elapsed(), timeout);
+}
+
+#[tokio::test(start_paused = true)]
+async fn hardware_errors_are_not_timeouts() {
+    let scale = TimeoutScale::new(HungScale);
+    let result = scale
+        .get_median_weight_timeout(5, Duration::from_secs(1))
+        .await;
+    let Err(TimeoutScaleError::Scale(e)) = result else {
+        panic!("expected a scale error");
+    };
+    assert_eq!(e.to_string(), "load cell unplugged");
+}
+
+#[tokio::test(start_paused = true)]
+async fn trait_calls_use_the_configured_default() {
+    let scale = TimeoutScale::new(HungScale);
+    assert_eq!(scale.timeout(), DEFAULT_SCALE_TIMEOUT);
+    let scale = scale.with_timeout(Duration::from_secs(2));
+    let start = tokio::time::Instant::now();
+
+    let e = AsyncScale::get_weight(&scale).await.unwrap_err();
+    assert_eq!(start.elapsed(), Duration::from_secs(2));
+    assert!(matches!(
+        e.downcast_ref::<TimeoutScaleError>(),
+        Some(TimeoutScaleError::Elapsed(_))
+    ));
+}
@@ -0,0 +1,237 @@
+#![cfg(all(feature = "tracing", feature = "tokio"))]
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use libra::actor::spawn_scale_actor;
+use libra::{Grams, MedianGrams, Scale, ScaleCmd, ScaleResponse};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// A span or event as the subscriber saw it, with its fields formatted.
+#[derive(Clone, Debug)]
+struct Captured {
+    name: &'static str,
+    target: String,
+    fields: HashMap<&'static str, String>,
+    /// The span it was created in, if any.
+    parent: Option<u64>,
+}
+
+impl Captured {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str)
+    }
+}
+
+struct Fields<'a>(&'a mut HashMap<&'static str, String>);
+
+impl Visit for Fields<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.insert(field.name(), format!("{value:?}"));
+    }
+}
+
+thread_local! {
+    static ENTERED: RefCell<Vec<u64>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Keeps every span and event, from every thread.
+#[derive(Default)]
+struct Capture {
+    next_id: AtomicU64,
+    spans: Mutex<HashMap<u64, Captured>>,
+    events: Mutex<Vec<Captured>>,
+}
+
+impl Capture {
+    fn current() -> Option<u64> {
+        ENTERED.with(|entered| entered.borrow().last().copied())
+    }
+}
+
+impl Subscriber for &'static Capture {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let mut fields = HashMap::new();
+        span.record(&mut Fields(&mut fields));
+        let parent = match span.parent() {
+            Some(parent) => Some(parent.into_u64()),
+            None if span.is_contextual() => Capture::current(),
+            None => None,
+        };
+        let captured = Captured {
+            name: span.metadata().name(),
+            target: span.metadata().target().to_string(),
+            fields,
+            parent,
+        };
+        self.spans.lock().unwrap().insert(id, captured);
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        if let Some(captured) = self.spans.lock().unwrap().get_mut(&span.into_u64()) {
+            values.record(&mut Fields(&mut captured.fields));
+        }
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = HashMap::new();
+        event.record(&mut Fields(&mut fields));
+        let parent = match event.parent() {
+            Some(parent) => Some(parent.into_u64()),
+            None if event.is_contextual() => Capture::current(),
+            None => None,
+        };
+        self.events.lock().unwrap().push(Captured {
+            name: event.metadata().name(),
+            target: event.metadata().target().to_string(),
+            fields,
+            parent,
+        });
+    }
+
+    fn enter(&self, span: &Id) {
+        ENTERED.with(|entered| entered.borrow_mut().push(span.into_u64()));
+    }
+
+    fn exit(&self, span: &Id) {
+        ENTERED.with(|entered| {
+            let mut entered = entered.borrow_mut();
+            if let Some(at) = entered.iter().rposition(|&id| id == span.into_u64()) {
+                entered.remove(at);
+            }
+        });
+    }
+}
+
+fn capture() -> &'static Capture {
+    static CAPTURE: OnceLock<&'static Capture> = OnceLock::new();
+    CAPTURE.get_or_init(|| {
+        let capture = Box::leak(Box::default());
+        tracing::subscriber::set_global_default(&*capture).unwrap();
+        capture
+    })
+}
+
+/// The `command` span for `correlation_id`, and the spans and events within
+/// it.
+fn traced(capture: &Capture, correlation_id: u64) -> (Captured, Vec<Captured>, Vec<Captured>) {
+    let spans = capture.spans.lock().unwrap().clone();
+    let (&command_id, command) = spans
+        .iter()
+        .find(|(_, span)| {
+            span.name == "command"
+                && span.field("correlation_id") == Some(&correlation_id.to_string())
+        })
+        .expect("no command span");
+    let within = |mut parent: Option<u64>| {
+        while let Some(id) = parent {
+            if id == command_id {
+                return true;
+            }
+            parent = spans.get(&id).and_then(|span| span.parent);
+        }
+        false
+    };
+    let children = spans
+        .values()
+        .filter(|span| within(span.parent))
+        .cloned()
+        .collect();
+    let events = capture
+        .events
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|event| within(event.parent))
+        .cloned()
+        .collect();
+    (command.clone(), children, events)
+}
+
+/// Reads 10 g, except for read `failing`, counting from 0.
+struct MockScale {
+    failing: Option<u64>,
+    reads: AtomicU64,
+}
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        let read = self.reads.fetch_add(1, Ordering::Relaxed);
+        if Some(read) == self.failing {
+            return Err("bridge fault".into());
+        }
+        Ok(Grams(10.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+}
+
+#[tokio::test]
+async fn a_median_read_is_traced_under_its_command() {
+    let capture = capture();
+    let scale = MockScale {
+        failing: None,
+        reads: AtomicU64::new(0),
+    };
+    let (handle, _task) = spawn_scale_actor(scale);
+    let response = handle
+        .send_correlated(ScaleCmd::GetMedianWeight { samples: 3 }, 42)
+        .await;
+    assert!(matches!(response, ScaleResponse::MedianWeight(MedianGrams(w)) if w == 10.));
+
+    let (command, children, events) = traced(capture, 42);
+    assert_eq!(command.target, libra::trace::TARGET);
+    assert_eq!(command.field("command"), Some("GetMedianWeight"));
+    assert_eq!(command.field("lane"), Some("normal"));
+
+    let median = children
+        .iter()
+        .find(|span| span.name == "get_median_weight")
+        .expect("no get_median_weight span");
+    assert_eq!(median.target, libra::trace::TARGET);
+    assert_eq!(median.field("samples"), Some("3"));
+    assert_eq!(median.field("interval_ms"), Some("0"));
+    assert!(events.iter().all(|event| event.name != "sample_failed"));
+}
+
+#[tokio::test]
+async fn a_failed_sample_is_an_event() {
+    let capture = capture();
+    let scale = MockScale {
+        failing: Some(1),
+        reads: AtomicU64::new(0),
+    };
+    let (handle, _task) = spawn_scale_actor(scale);
+    let response = handle
+        .send_correlated(ScaleCmd::GetMedianWeight { samples: 3 }, 43)
+        .await;
+    assert!(matches!(response, ScaleResponse::Error(_)));
+
+    let (_, _, events) = traced(capture, 43);
+    let failed = events
+        .iter()
+        .find(|event| event.name == "sample_failed")
+        .expect("no sample_failed event");
+    assert_eq!(failed.target, libra::trace::TARGET);
+    assert_eq!(failed.field("sample"), Some("1"));
+    assert_eq!(failed.field("error"), Some("bridge fault"));
+}
@@ -0,0 +1,170 @@
+#![cfg(all(feature = "net", unix))]
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use libra::actor::{spawn_scale_actor, ScaleHandle};
+use libra::net::ServerConfig;
+use libra::unix::{serve_unix, serve_unix_with_config, UnixScaleClient};
+use libra::{AsyncScale, Grams, MedianGrams, Scale, ScaleCmd, ScaleResponse};
+use tokio::task::JoinHandle;
+
+/// Always reads the same weight; tares to whatever it reads.
+struct MockScale(f64);
+
+impl Scale for MockScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(self.0))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(MedianGrams(self.0))
+    }
+
+    fn tare(&mut self, _samples: usize) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(self.0))
+    }
+}
+
+fn socket_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("libra-{}-{name}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+async fn start(weight: f64, path: &Path) -> (ScaleHandle, JoinHandle<std::io::Result<()>>) {
+    let (handle, _task) = spawn_scale_actor(MockScale(weight));
+    let server = tokio::spawn(serve_unix(handle.clone(), path.to_owned()));
+    wait_for_server(path).await;
+    (handle, server)
+}
+
+async fn wait_for_server(path: &Path) {
+    let client = UnixScaleClient::new(path);
+    while client.request(&ScaleCmd::GetWeight).await.is_err() {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_works_as_a_scale() {
+    let path = socket_path("client");
+    let (_handle, _server) = start(7., &path).await;
+    let client = UnixScaleClient::new(&path);
+
+    assert_eq!(AsyncScale::get_weight(&client).await.unwrap(), Grams(7.));
+    assert_eq!(
+        AsyncScale::get_median_weight(&client, 3).await.unwrap(),
+        MedianGrams(7.)
+    );
+    let mut client = tokio::task::spawn_blocking(move || {
+        let mut client = client;
+        assert_eq!(Scale::get_weight(&client).unwrap(), Grams(7.));
+        assert_eq!(client.tare(5).unwrap(), Grams(7.));
+        client
+    })
+    .await
+    .unwrap();
+    let unsupported = tokio::task::spawn_blocking(move || client.status().is_err())
+        .await
+        .unwrap();
+    assert!(unsupported);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_reconnects_after_the_server_restarts() {
+    let path = socket_path("restart");
+    let client = UnixScaleClient::new(&path);
+
+    let (first, server) = start(1., &path).await;
+    assert_eq!(AsyncScale::get_weight(&client).await.unwrap(), Grams(1.));
+    first.shutdown().await;
+    server.await.unwrap().unwrap();
+    assert!(!path.exists());
+
+    let (_second, _server) = start(2., &path).await;
+    assert_eq!(AsyncScale::get_weight(&client).await.unwrap(), Grams(2.));
+}
+
+/// Takes `SLOW_READ` over each weight.
+struct SlowScale;
+
+const SLOW_READ: Duration = Duration::from_millis(200);
+
+impl Scale for SlowScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        std::thread::sleep(SLOW_READ);
+        Ok(Grams(1.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn a_cancelled_request_leaves_no_reply_for_the_next() {
+    let path = socket_path("cancelled");
+    let (handle, _task) = spawn_scale_actor(SlowScale);
+    let _server = tokio::spawn(serve_unix(handle, path.clone()));
+    let client = UnixScaleClient::new(&path);
+    while client.request(&ScaleCmd::ListScales).await.is_err() {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+
+    let cancelled = tokio::time::timeout(SLOW_READ / 4, client.request(&ScaleCmd::GetWeight));
+    assert!(cancelled.await.is_err());
+    tokio::time::sleep(SLOW_READ * 2).await;
+    let response = client.request(&ScaleCmd::ListScales).await.unwrap();
+    assert!(matches!(response, ScaleResponse::Scales(_)), "{response:?}");
+}
+
+#[tokio::test]
+async fn stale_socket_is_replaced() {
+    let path = socket_path("stale");
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+
+    let (_handle, _server) = start(3., &path).await;
+    let client = UnixScaleClient::new(&path);
+    assert_eq!(AsyncScale::get_weight(&client).await.unwrap(), Grams(3.));
+}
+
+#[tokio::test]
+async fn live_socket_and_other_files_are_left_alone() {
+    let path = socket_path("live");
+    let _listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+    let (handle, _task) = spawn_scale_actor(MockScale(0.));
+    let error = serve_unix(handle.clone(), &path).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::AddrInUse);
+
+    let path = socket_path("regular");
+    std::fs::write(&path, "keep me").unwrap();
+    assert!(serve_unix(handle, &path).await.is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep me");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn socket_mode_is_applied() {
+    let path = socket_path("mode");
+    let (handle, _task) = spawn_scale_actor(MockScale(0.));
+    let server = tokio::spawn(serve_unix_with_config(
+        handle.clone(),
+        path.clone(),
+        0o600,
+        ServerConfig::default(),
+    ));
+    wait_for_server(&path).await;
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    let client = UnixScaleClient::new(&path);
+    assert_eq!(
+        client.request(&ScaleCmd::Shutdown).await.unwrap(),
+        ScaleResponse::ShutdownAck
+    );
+    server.await.unwrap().unwrap();
+    assert!(!path.exists());
+}
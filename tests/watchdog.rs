@@ -0,0 +1,208 @@
+#![cfg(feature = "tokio")]
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use libra::scale::{ScaleError, NUMBER_OF_INPUTS};
+use libra::testing::TokioClock;
+use libra::watchdog::{Watchdog, WatchdogScale};
+use libra::{Grams, MedianGrams, Scale, ScaleErrorInfo, ScaleErrorKind};
+
+const WINDOW: Duration = Duration::from_millis(60);
+const EPSILON: f64 = 1e-9;
+
+/// A scale on a quiet platform: every load cell wobbles by a little more than
+/// `EPSILON` around its own ratio, except `frozen`, which repeats itself.
+struct QuietScale {
+    frozen: Option<usize>,
+    reads: AtomicU64,
+}
+
+impl QuietScale {
+    fn new(frozen: Option<usize>) -> Self {
+        Self {
+            frozen,
+            reads: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Scale for QuietScale {
+    fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Grams(10.))
+    }
+
+    fn get_median_weight(&self) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+        unimplemented!()
+    }
+
+    fn get_raw_readings(
+        &self,
+    ) -> Result<[f64; NUMBER_OF_INPUTS], Box<dyn std::error::Error + Send + Sync>> {
+        let read = self.reads.fetch_add(1, Ordering::Relaxed);
+        let wobble = if read.is_multiple_of(2) { 5e-9 } else { -5e-9 };
+        Ok(std::array::from_fn(|channel| {
+            let ratio = 1e-4 * (channel + 1) as f64;
+            if self.frozen == Some(channel) {
+                ratio
+            } else {
+                ratio + wobble
+            }
+        }))
+    }
+}
+
+/// A watchdog on tokio's clock, which the tests below pause.
+fn watchdog() -> Watchdog {
+    Watchdog::new(WINDOW, EPSILON).with_clock(Arc::new(TokioClock::new()))
+}
+
+/// Reads `scale` every 10 ms for `period`.
+async fn sample(scale: &impl Scale, period: Duration) {
+    for _ in 0..period.as_millis() / 10 {
+        let _ = scale.get_weight();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_frozen_channel_is_caught() {
+    let scale = WatchdogScale::new(QuietScale::new(Some(2)), watchdog());
+    sample(&scale, WINDOW * 2).await;
+
+    let frozen = scale.time_since_last_change(2).unwrap();
+    assert!(frozen > WINDOW, "{frozen:?}");
+    for channel in [0, 1, 3] {
+        let age = scale.time_since_last_change(channel).unwrap();
+        assert!(age < WINDOW / 2, "Load Cell {channel}: {age:?}");
+    }
+    let stale = scale.stale_channel().unwrap();
+    assert_eq!(stale.channel, 2);
+    assert!(stale.age > WINDOW);
+    // Not strict, so reads carry on.
+    assert_eq!(scale.get_weight().unwrap(), Grams(10.));
+}
+
+#[tokio::test(start_paused = true)]
+async fn a_quiet_platform_is_not_frozen() {
+    let scale = WatchdogScale::new(QuietScale::new(None), watchdog());
+    sample(&scale, WINDOW * 2).await;
+    assert_eq!(scale.stale_channel(), None);
+}
+
+#[tokio::test(start_paused = true)]
+async fn nothing_is_stale_before_the_first_read() {
+    let scale = WatchdogScale::new(QuietScale::new(Some(0)), watchdog());
+    tokio::time::sleep(WINDOW * 2).await;
+    assert_eq!(scale.time_since_last_change(0), None);
+    assert_eq!(scale.stale_channel(), None);
+    assert_eq!(scale.time_since_last_change(NUMBER_OF_INPUTS), None);
+}
+
+#[tokio::test(start_paused = true)]
+async fn changes_within_epsilon_do_not_count() {
+    let mut watchdog = Watchdog::new(WINDOW, 1e-6).with_clock(Arc::new(TokioClock::new()));
+    watchdog.observe(&[0.5; NUMBER_OF_INPUTS]);
+    // Drifting by less than epsilon a step still adds up to a change.
+    let mut drift = [0.5; NUMBER_OF_INPUTS];
+    for _ in 0..4 {
+        tokio::time::sleep(WINDOW / 4 + Duration::from_millis(1)).await;
+        drift[1] += 4e-7;
+        watchdog.observe(&drift);
+    }
+    assert!(watchdog.time_since_last_change(0).unwrap() > WINDOW);
+    assert!(watchdog.time_since_last_change(1).unwrap() < WINDOW / 2);
+    assert_eq!(watchdog.stale_channel().unwrap().channel, 0);
+
+    watchdog.reset();
+    assert_eq!(watchdog.stale_channel(), None);
+}
+
+#[tokio::test(start_paused = true)]
+async fn strict_reads_fail_on_stale_data() {
+    let scale = WatchdogScale::new(QuietScale::new(Some(3)), watchdog().strict())
+        .with_sample_interval(Duration::from_millis(1));
+    assert_eq!(scale.get_median_weight_of(3).unwrap(), MedianGrams(10.));
+    tokio::time::sleep(WINDOW * 2).await;
+
+    let error = scale.get_weight().unwrap_err();
+    let Some(&ScaleError::StaleData { channel, age }) = error.downcast_ref::<ScaleError>() else {
+        panic!("{error} is not stale data");
+    };
+    assert_eq!(channel, 3);
+    assert_eq!(age, WINDOW * 2);
+    assert_eq!(error.to_string(), "Load Cell 3 has not changed in 120ms");
+    let info = ScaleErrorInfo::from_dyn(&*error);
+    assert_eq!(info.kind, ScaleErrorKind::StaleData);
+    assert_eq!(info.load_cell, Some(3));
+
+    let error = scale.get_median_weight_of(3).unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<ScaleError>(),
+        Some(ScaleError::StaleData { channel: 3, .. })
+    ));
+}
+
+#[test]
+fn stale_data_is_a_disconnection() {
+    let error = ScaleError::StaleData {
+        channel: 1,
+        age: Duration::from_secs(1200),
+    };
+    assert!(error.is_disconnection());
+    assert!(!error.is_transient());
+    assert_eq!(error.to_string(), "Load Cell 1 has not changed in 1200s");
+}
+
+#[tokio::test(start_paused = true)]
+async fn the_background_sampler_warns_when_the_watchdog_trips() {
+    use libra::actor::{spawn_scale_actor_with_config, ActorConfig};
+    use libra::watchdog::StaleChannel;
+
+    let scale = WatchdogScale::new(QuietScale::new(Some(1)), watchdog());
+    let config = ActorConfig {
+        sample_interval: Some(Duration::from_millis(10)),
+        ..ActorConfig::default()
+    };
+    let (handle, _task) = spawn_scale_actor_with_config(scale, config);
+    let mut warnings = handle.watch_watchdog();
+    assert_eq!(*warnings.borrow(), None);
+
+    let warning = tokio::time::timeout(WINDOW * 10, warnings.wait_for(Option::is_some))
+        .await
+        .expect("the watchdog never tripped")
+        .unwrap()
+        .unwrap();
+    let StaleChannel { channel, age } = warning;
+    assert_eq!(channel, 1);
+    assert!(age > WINDOW);
+}
+
+#[cfg(feature = "hardware")]
+#[test]
+fn each_watched_weight_is_one_read() {
+    use libra::calibration::Calibration;
+    use libra::scale::ConnectedScale;
+    use libra::testing::FakeVoltageSource;
+
+    let sources = FakeVoltageSource::bridge(716_000);
+    let calibration = Calibration {
+        offset: 0.,
+        coefficients: [1000.; NUMBER_OF_INPUTS],
+    };
+    let scale = WatchdogScale::new(
+        ConnectedScale::from_sources(716_000, calibration, sources.clone()),
+        Watchdog::new(WINDOW, EPSILON),
+    );
+    for (channel, source) in sources.iter().enumerate() {
+        source.queue([0.001 * (channel + 1) as f64, 1.]);
+    }
+
+    // The weight comes from the same readings the watchdog saw.
+    assert_eq!(scale.get_weight().unwrap(), Grams(10.));
+    for source in &sources {
+        assert_eq!(source.reads(), 1);
+    }
+    assert!(scale.time_since_last_change(0).is_some());
+}
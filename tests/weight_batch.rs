@@ -0,0 +1,161 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use libra::{Grams, ScaleCmd, ScaleResponse, StampedWeight};
+
+fn round_trip<T: serde::Serialize + serde::de::DeserializeOwned>(value: &T) -> T {
+    let config = bincode::config::standard();
+    let bytes = bincode::serde::encode_to_vec(value, config).unwrap();
+    let (decoded, read) = bincode::serde::decode_from_slice(&bytes, config).unwrap();
+    assert_eq!(read, bytes.len());
+    decoded
+}
+
+#[test]
+fn batch_command_round_trips() {
+    let cmd = round_trip(&ScaleCmd::GetWeightBatch {
+        count: 50,
+        interval_ms: 20,
+    });
+    assert!(matches!(
+        cmd,
+        ScaleCmd::GetWeightBatch {
+            count: 50,
+            interval_ms: 20
+        }
+    ));
+}
+
+#[test]
+fn batch_response_round_trips() {
+    let batch: Vec<_> = (0..3)
+        .map(|sequence| StampedWeight {
+            weight: Grams(sequence as f64 * 1.5),
+            sequence,
+            timestamp: UNIX_EPOCH + Duration::from_millis(1_700_000_000_000 + sequence),
+        })
+        .collect();
+    let ScaleResponse::WeightBatch(decoded) =
+        round_trip(&ScaleResponse::WeightBatch(batch.clone()))
+    else {
+        panic!("expected a batch");
+    };
+    assert_eq!(decoded, batch);
+}
+
+#[cfg(feature = "tokio")]
+mod actor {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use libra::actor::{
+        spawn_scale_actor, spawn_scale_actor_with_config, ActorConfig, ShutdownPolicy,
+    };
+    use libra::testing::{ManualClock, MockScale};
+    use libra::{
+        Grams, MedianGrams, Scale, ScaleCmd, ScaleErrorKind, ScaleResponse, MAX_BATCH_COUNT,
+    };
+
+    #[derive(Default)]
+    struct CountingScale {
+        reads: Arc<AtomicUsize>,
+    }
+
+    impl Scale for CountingScale {
+        fn get_weight(&self) -> Result<Grams, Box<dyn std::error::Error + Send + Sync>> {
+            Ok(Grams(self.reads.fetch_add(1, Ordering::SeqCst) as f64))
+        }
+
+        fn get_median_weight(
+            &self,
+        ) -> Result<MedianGrams, Box<dyn std::error::Error + Send + Sync>> {
+            unimplemented!()
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_arrives_in_one_response() {
+        let (handle, _task) = spawn_scale_actor(CountingScale::default());
+        let start = Instant::now();
+        let response = handle
+            .send(ScaleCmd::GetWeightBatch {
+                count: 5,
+                interval_ms: 10,
+            })
+            .await;
+        let ScaleResponse::WeightBatch(batch) = response else {
+            panic!("unexpected response {response:?}");
+        };
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        assert_eq!(batch.len(), 5);
+        for (i, sample) in batch.iter().enumerate() {
+            assert_eq!(sample.sequence, i as u64);
+            assert_eq!(sample.weight, Grams(i as f64));
+        }
+        assert!(batch.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+    }
+
+    #[tokio::test]
+    async fn batch_is_paced_by_the_scales_clock() {
+        let clock = ManualClock::new();
+        let scale = MockScale::new(Grams(5.)).with_clock(Arc::new(clock.clone()));
+        let (handle, _task) = spawn_scale_actor(scale);
+        let start = Instant::now();
+        let response = handle
+            .send(ScaleCmd::GetWeightBatch {
+                count: 4,
+                interval_ms: 60_000,
+            })
+            .await;
+        let ScaleResponse::WeightBatch(batch) = response else {
+            panic!("unexpected response {response:?}");
+        };
+        assert_eq!(batch.len(), 4);
+        assert_eq!(clock.elapsed(), Duration::from_secs(180));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn oversized_batch_is_rejected() {
+        let reads = Arc::new(AtomicUsize::new(0));
+        let (handle, _task) = spawn_scale_actor(CountingScale {
+            reads: Arc::clone(&reads),
+        });
+        let response = handle
+            .send(ScaleCmd::GetWeightBatch {
+                count: MAX_BATCH_COUNT + 1,
+                interval_ms: 0,
+            })
+            .await;
+        let ScaleResponse::Error(error) = response else {
+            panic!("unexpected response {response:?}");
+        };
+        assert_eq!(error.kind, ScaleErrorKind::BatchTooLarge);
+        assert_eq!(reads.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn batch_is_cancelled_by_shutdown() {
+        let (handle, _task) = spawn_scale_actor_with_config(
+            CountingScale::default(),
+            ActorConfig {
+                shutdown_policy: ShutdownPolicy::CancelInFlight,
+                ..Default::default()
+            },
+        );
+        let batch = tokio::spawn({
+            let handle = handle.clone();
+            async move {
+                handle
+                    .send(ScaleCmd::GetWeightBatch {
+                        count: 100,
+                        interval_ms: 50,
+                    })
+                    .await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        handle.shutdown().await;
+        assert!(matches!(batch.await.unwrap(), ScaleResponse::ShuttingDown));
+    }
+}
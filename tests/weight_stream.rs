@@ -0,0 +1,91 @@
+#![cfg(feature = "tokio")]
+
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::StreamExt;
+use libra::scale::ScaleError;
+use libra::stream::WeightStream;
+use libra::Grams;
+
+fn counting_reader() -> impl FnMut() -> Result<Grams, ScaleError> + Send + 'static {
+    let mut n = 0.;
+    move || {
+        n += 1.;
+        Ok(Grams(n))
+    }
+}
+
+#[tokio::test]
+async fn slow_consumer_sees_newest_samples() {
+    let mut stream = WeightStream::spawn(counting_reader(), Duration::from_millis(2), 4);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    stream.stopper().stop();
+
+    let samples: Vec<_> = (&mut stream).map(Result::unwrap).collect().await;
+    assert!(samples.len() <= 4);
+    assert!(stream.dropped() > 0);
+    // The survivors are the most recent readings, still in order.
+    let last = samples.last().unwrap();
+    assert_eq!(last.sequence, stream.dropped() + samples.len() as u64 - 1);
+    assert!(samples
+        .windows(2)
+        .all(|w| w[1].sequence == w[0].sequence + 1));
+    assert!(samples
+        .iter()
+        .all(|s| s.weight.get() == s.sequence as f64 + 1.));
+}
+
+#[tokio::test]
+async fn errors_are_yielded_in_band() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let reader = {
+        let calls = Arc::clone(&calls);
+        move || match calls.fetch_add(1, Ordering::SeqCst) {
+            1 => Err(ScaleError::IoError(io::ErrorKind::TimedOut.into())),
+            n => Ok(Grams(n as f64)),
+        }
+    };
+    let mut stream = WeightStream::spawn(reader, Duration::from_millis(1), 16);
+
+    assert_eq!(stream.next().await.unwrap().unwrap().weight, Grams(0.));
+    assert!(matches!(
+        stream.next().await.unwrap(),
+        Err(ScaleError::IoError(_))
+    ));
+    assert_eq!(stream.next().await.unwrap().unwrap().weight, Grams(2.));
+}
+
+#[tokio::test]
+async fn stream_ends_after_stop() {
+    let stream = WeightStream::spawn(counting_reader(), Duration::from_millis(1), 16);
+    let stopper = stream.stopper();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        stopper.stop();
+    });
+
+    let consumed = tokio::time::timeout(Duration::from_secs(2), stream.count())
+        .await
+        .expect("stream terminates once stopped");
+    assert!(consumed > 0);
+}
+
+#[tokio::test]
+async fn stream_ends_when_reader_panics() {
+    let mut calls = 0;
+    let reader = move || {
+        calls += 1;
+        if calls > 3 {
+            panic!("reader died");
+        }
+        Ok(Grams(calls as f64))
+    };
+    let stream = WeightStream::spawn(reader, Duration::from_millis(1), 16);
+    let consumed = tokio::time::timeout(Duration::from_secs(2), stream.count())
+        .await
+        .unwrap();
+    assert_eq!(consumed, 3);
+}
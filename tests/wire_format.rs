@@ -0,0 +1,400 @@
+use std::fmt::Debug;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+
+use libra::calibration::Calibration;
+use libra::health::{
+    CalibrationExpiry, ChannelHealth, FleetStatus, HealthReport, ScaleHealth, Status,
+};
+use libra::{
+    Grams, ListedScale, MedianGrams, ScaleCmd, ScaleErrorInfo, ScaleErrorKind, ScaleResponse,
+    ScaleState, ScaleStatus, StampedWeight,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+/// The JSON of every value below, one `<type> <name> <json>` line each.
+/// Existing lines must keep decoding; see the file for how to change it.
+const VECTORS: &str = include_str!("vectors/json.txt");
+
+const CALIBRATION: Calibration = Calibration {
+    offset: 1.5,
+    coefficients: [2.; 4],
+};
+
+fn vectors_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/vectors/json.txt")
+}
+
+fn commands() -> Vec<(&'static str, ScaleCmd)> {
+    vec![
+        ("GetWeight", ScaleCmd::GetWeight),
+        ("GetMedianWeight", ScaleCmd::GetMedianWeight { samples: 10 }),
+        (
+            "GetWeightBatch",
+            ScaleCmd::GetWeightBatch {
+                count: 300,
+                interval_ms: 20,
+            },
+        ),
+        ("Shutdown", ScaleCmd::Shutdown),
+        ("Tare", ScaleCmd::Tare { samples: 3 }),
+        ("Zero", ScaleCmd::Zero { samples: 3 }),
+        ("GetRawReadings", ScaleCmd::GetRawReadings),
+        ("GetRawMedians", ScaleCmd::GetRawMedians { samples: 7 }),
+        ("SetCalibration", ScaleCmd::SetCalibration(CALIBRATION)),
+        ("GetCalibration", ScaleCmd::GetCalibration),
+        ("GetStatus", ScaleCmd::GetStatus),
+        ("Hello", ScaleCmd::Hello { client_version: 1 }),
+        (
+            "Auth",
+            ScaleCmd::Auth {
+                token: "secret".into(),
+            },
+        ),
+        ("ListScales", ScaleCmd::ListScales),
+        (
+            "SetDataInterval",
+            ScaleCmd::SetDataInterval { interval_ms: 8 },
+        ),
+    ]
+}
+
+fn stamped_weight() -> StampedWeight {
+    StampedWeight {
+        weight: Grams(1.),
+        sequence: 7,
+        timestamp: UNIX_EPOCH + Duration::new(1_700_000_000, 500),
+    }
+}
+
+fn phidget_error() -> ScaleErrorInfo {
+    ScaleErrorInfo {
+        load_cell: Some(3),
+        return_code: Some(52),
+        ..ScaleErrorInfo::new(ScaleErrorKind::Phidget, "Detached")
+    }
+}
+
+fn responses() -> Vec<(&'static str, ScaleResponse)> {
+    vec![
+        ("Weight", ScaleResponse::Weight(Grams(12.5))),
+        (
+            "MedianWeight",
+            ScaleResponse::MedianWeight(MedianGrams(-3.)),
+        ),
+        (
+            "WeightBatch",
+            ScaleResponse::WeightBatch(vec![stamped_weight()]),
+        ),
+        (
+            "RawReadings",
+            ScaleResponse::RawReadings([0.1, 0.2, 0.3, 0.4]),
+        ),
+        (
+            "Error",
+            ScaleResponse::Error(ScaleErrorInfo::new(ScaleErrorKind::Busy, "Scale is busy")),
+        ),
+        ("PhidgetError", ScaleResponse::Error(phidget_error())),
+        (
+            "InternalError",
+            ScaleResponse::InternalError("Scale panicked: oops".into()),
+        ),
+        ("ShutdownAck", ScaleResponse::ShutdownAck),
+        ("ShuttingDown", ScaleResponse::ShuttingDown),
+        ("Expired", ScaleResponse::Expired),
+        ("Tared", ScaleResponse::Tared(Grams(250.))),
+        ("Zeroed", ScaleResponse::Zeroed(CALIBRATION)),
+        ("RawMedians", ScaleResponse::RawMedians([0.5; 4])),
+        ("CalibrationSet", ScaleResponse::CalibrationSet),
+        ("Calibration", ScaleResponse::Calibration(CALIBRATION)),
+        (
+            "Status",
+            ScaleResponse::Status(ScaleStatus {
+                phidget_id: 716_000,
+                attached: true,
+                calibration: CALIBRATION,
+                tare: Grams(0.),
+                calibration_expires_in_days: Some(12),
+            }),
+        ),
+        (
+            "HelloAck",
+            ScaleResponse::HelloAck {
+                server_version: 1,
+                supported_commands: vec!["GetWeight".into(), "Hello".into()],
+            },
+        ),
+        (
+            "Unsupported",
+            ScaleResponse::Unsupported {
+                command: "Frobnicate".into(),
+            },
+        ),
+        ("Authenticated", ScaleResponse::Authenticated),
+        (
+            "Heartbeat",
+            ScaleResponse::Heartbeat {
+                uptime: Duration::new(90, 500),
+                last_reading_age: Some(Duration::from_millis(250)),
+            },
+        ),
+        (
+            "Scales",
+            ScaleResponse::Scales(vec![
+                ListedScale {
+                    name: "flour".into(),
+                    state: ScaleState::Connected,
+                    data_interval_ms: Some(8),
+                },
+                ListedScale {
+                    name: "rice".into(),
+                    state: ScaleState::Disconnected,
+                    data_interval_ms: None,
+                },
+            ]),
+        ),
+        (
+            "UnknownScale",
+            ScaleResponse::UnknownScale {
+                name: Some("sugar".into()),
+                available: vec!["flour".into(), "rice".into()],
+            },
+        ),
+        (
+            "DataIntervalSet",
+            ScaleResponse::DataIntervalSet { interval_ms: 8 },
+        ),
+        ("FleetStatus", ScaleResponse::FleetStatus(fleet_status())),
+    ]
+}
+
+fn calibrations() -> Vec<(&'static str, Calibration)> {
+    vec![
+        ("Calibration", CALIBRATION),
+        (
+            "Negative",
+            Calibration {
+                offset: -0.25,
+                coefficients: [1e6, -2e6, 3.5e-3, 0.],
+            },
+        ),
+    ]
+}
+
+fn stamped_weights() -> Vec<(&'static str, StampedWeight)> {
+    vec![
+        ("StampedWeight", stamped_weight()),
+        (
+            "Epoch",
+            StampedWeight {
+                weight: Grams(-0.5),
+                sequence: 0,
+                timestamp: UNIX_EPOCH,
+            },
+        ),
+    ]
+}
+
+fn error_infos() -> Vec<(&'static str, ScaleErrorInfo)> {
+    vec![
+        (
+            "Busy",
+            ScaleErrorInfo::new(ScaleErrorKind::Busy, "Scale is busy"),
+        ),
+        ("Phidget", phidget_error()),
+        (
+            "Disconnected",
+            ScaleErrorInfo {
+                load_cell: Some(1),
+                ..ScaleErrorInfo::new(ScaleErrorKind::Disconnected, "Load Cell 1 is detached")
+            },
+        ),
+    ]
+}
+
+/// Older forms that are no longer written but must still be read, with the
+/// value each decodes to.
+fn legacy_error_infos() -> Vec<(&'static str, ScaleErrorInfo)> {
+    vec![(
+        // From before errors named their load cell and return code.
+        "WithoutLoadCell",
+        ScaleErrorInfo::new(ScaleErrorKind::Phidget, "Detached"),
+    )]
+}
+
+/// Older forms of responses that must still be read.
+fn legacy_responses() -> Vec<(&'static str, ScaleResponse)> {
+    vec![(
+        // From before the status carried the calibration's expiry.
+        "StatusWithoutExpiry",
+        ScaleResponse::Status(ScaleStatus {
+            phidget_id: 716_000,
+            attached: true,
+            calibration: CALIBRATION,
+            tare: Grams(0.),
+            calibration_expires_in_days: None,
+        }),
+    )]
+}
+
+/// The vectors of type `kind`, by name.
+fn vectors(kind: &str) -> Vec<(&'static str, &'static str)> {
+    VECTORS
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.is_empty())
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, ' ');
+            (fields.next() == Some(kind)).then(|| (fields.next().unwrap(), fields.next().unwrap()))
+        })
+        .collect()
+}
+
+/// Checks that every value of type `kind` encodes to its vector exactly and
+/// that the vector decodes back to it, and that the file has no other
+/// vectors of that type than `legacy` ones, which only need to decode.
+///
+/// With `UPDATE_SNAPSHOTS` set, values without a vector have one appended.
+/// Existing lines are never rewritten.
+fn assert_vectors<T>(kind: &str, values: Vec<(&str, T)>, legacy: Vec<(&str, T)>)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    let vectors = vectors(kind);
+    let find = |name: &str| vectors.iter().find(|(vector, _)| *vector == name);
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(vectors_path())
+            .unwrap();
+        for (name, value) in values.iter().filter(|(name, _)| find(name).is_none()) {
+            let json = serde_json::to_string(value).unwrap();
+            writeln!(file, "{kind} {name} {json}").unwrap();
+        }
+        return;
+    }
+    for (name, value) in &values {
+        let (_, json) = find(name).unwrap_or_else(|| panic!("no vector for {kind} {name}"));
+        assert_eq!(
+            serde_json::to_string(value).unwrap(),
+            *json,
+            "{kind} {name}"
+        );
+        assert_eq!(
+            &serde_json::from_str::<T>(json).unwrap(),
+            value,
+            "{kind} {name}"
+        );
+    }
+    for (name, value) in &legacy {
+        let (_, json) = find(name).unwrap_or_else(|| panic!("no vector for {kind} {name}"));
+        assert_eq!(
+            &serde_json::from_str::<T>(json).unwrap(),
+            value,
+            "{kind} {name}"
+        );
+    }
+    for (name, _) in &vectors {
+        assert!(
+            values.iter().chain(&legacy).any(|(value, _)| value == name),
+            "{kind} {name} has a vector but no value"
+        );
+    }
+}
+
+#[test]
+fn every_command_matches_its_vector() {
+    let names: Vec<_> = commands().iter().map(|(_, cmd)| cmd.name()).collect();
+    assert_eq!(names, ScaleCmd::NAMES, "a command has no vector");
+    assert_vectors("cmd", commands(), Vec::new());
+}
+
+#[test]
+fn every_response_matches_its_vector() {
+    assert_vectors("response", responses(), legacy_responses());
+}
+
+#[test]
+fn calibrations_match_their_vectors() {
+    assert_vectors("calibration", calibrations(), Vec::new());
+}
+
+#[test]
+fn stamped_weights_match_their_vectors() {
+    assert_vectors("stamped_weight", stamped_weights(), Vec::new());
+}
+
+#[test]
+fn error_infos_match_their_vectors() {
+    assert_vectors("error_info", error_infos(), legacy_error_infos());
+}
+
+#[test]
+fn every_vector_is_valid_json() {
+    for line in VECTORS
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.is_empty())
+    {
+        let json = line.splitn(3, ' ').nth(2).expect(line);
+        assert!(serde_json::from_str::<Value>(json).is_ok(), "{line}");
+    }
+}
+
+/// A fleet of a scale with a noisy load cell, and one that did not answer
+/// in time.
+fn fleet_status() -> FleetStatus {
+    let noise = |channel| {
+        if channel == 2 {
+            0.5f64.powi(20)
+        } else {
+            0.5f64.powi(23)
+        }
+    };
+    FleetStatus::new(vec![
+        ScaleHealth {
+            name: "flour".into(),
+            state: ScaleState::Connected,
+            health: Ok(HealthReport {
+                status: Status::Degraded,
+                reasons: vec!["Load Cell 2 is noisy (9.537e-7)".into()],
+                channels: std::array::from_fn(|channel| ChannelHealth {
+                    channel,
+                    attached: true,
+                    noise: Some(noise(channel)),
+                }),
+                reads: 5,
+                failed_reads: 0,
+                stale: None,
+                calibration_age: Some(Duration::from_secs(3 * 24 * 60 * 60)),
+                calibration_expiry: Some(CalibrationExpiry::Valid {
+                    remaining: Duration::from_secs(12 * 24 * 60 * 60),
+                }),
+                calibration_expires_in_days: Some(12),
+                calibration_future_dated: false,
+                last_error: None,
+            }),
+            last_weight: Some(StampedWeight {
+                weight: Grams(1000.),
+                sequence: 7,
+                timestamp: UNIX_EPOCH + Duration::new(1_700_000_000, 500),
+            }),
+            last_weight_age: Some(Duration::from_millis(250)),
+            reconnects: 1,
+            calibration_expires_in_days: Some(12),
+        },
+        ScaleHealth {
+            name: "rice".into(),
+            state: ScaleState::Disconnected,
+            health: Err(ScaleErrorInfo::new(
+                ScaleErrorKind::Timeout,
+                "No answer within 2s",
+            )),
+            last_weight: None,
+            last_weight_age: None,
+            reconnects: 0,
+            calibration_expires_in_days: None,
+        },
+    ])
+}